@@ -0,0 +1,118 @@
+//! Per-request cost of the inbox duplicate check with and without the
+//! seen-activity filter in front of the database, at 1M remembered ids.
+//!
+//! Run with `cargo bench --bench seen_activities`.
+
+use chrono::Utc;
+use criterion::{criterion_group, criterion_main, Criterion};
+use feder8::database::{Database, DatabaseRef, DbActor, SqliteDatabase};
+use feder8::services::seen_activities::SeenActivities;
+use std::sync::Arc;
+use tempfile::TempDir;
+use tokio::runtime::Runtime;
+
+const REMEMBERED: usize = 1_000_000;
+const ACTOR_ID: &str = "https://example.com/users/alice";
+
+fn activity_id(n: usize) -> String {
+    format!("https://remote.example/activities/{n}")
+}
+
+/// A database holding `REMEMBERED` activities, bulk inserted in one statement
+async fn seeded_database(dir: &TempDir) -> DatabaseRef {
+    let url = format!(
+        "sqlite://{}?mode=rwc",
+        dir.path().join("bench.db").display()
+    );
+    let sqlite = SqliteDatabase::new(&url).await.unwrap();
+    sqlite.run_migrations().await.unwrap();
+    sqlite
+        .create_actor(&DbActor {
+            id: ACTOR_ID.to_string(),
+            username: "alice".to_string(),
+            name: "Alice".to_string(),
+            summary: None,
+            public_key_pem: "bench_key".to_string(),
+            private_key_pem: None,
+            is_admin: false,
+            actor_type: "Person".to_string(),
+            moved_to: None,
+            also_known_as: Vec::new(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        })
+        .await
+        .unwrap();
+
+    let pool = sqlx::SqlitePool::connect(&url).await.unwrap();
+    sqlx::query(
+        "WITH RECURSIVE n(i) AS (SELECT 0 UNION ALL SELECT i + 1 FROM n WHERE i < ? - 1)
+         INSERT INTO activities (id, actor_id, activity_type, object, to_recipients, cc_recipients, published, visibility, state, created_at)
+         SELECT 'https://remote.example/activities/' || i, ?, 'Create', '{}', '[]', '[]', datetime('now'), 'public', 'published', datetime('now') FROM n",
+    )
+    .bind(REMEMBERED as i64)
+    .bind(ACTOR_ID)
+    .execute(&pool)
+    .await
+    .unwrap();
+    pool.close().await;
+
+    Arc::new(sqlite)
+}
+
+/// The inbox's duplicate check: the filter, when present, rules ids out
+/// before the exact database lookup
+async fn is_duplicate(seen: Option<&SeenActivities>, db: &DatabaseRef, id: &str) -> bool {
+    if seen.is_some_and(|seen| !seen.might_contain(id)) {
+        return false;
+    }
+    db.get_activity_by_id(id).await.unwrap().is_some()
+}
+
+fn duplicate_check(c: &mut Criterion) {
+    let runtime = Runtime::new().unwrap();
+    let dir = TempDir::new().unwrap();
+    let db = runtime.block_on(seeded_database(&dir));
+
+    let seen = SeenActivities::new(REMEMBERED);
+    for n in 0..REMEMBERED {
+        seen.insert(&activity_id(n));
+    }
+
+    let mut group = c.benchmark_group("duplicate_check_1m_remembered");
+
+    // Fresh activities are the common case and the one the filter speeds up
+    let mut fresh = REMEMBERED;
+    group.bench_function("new_activity/database_only", |b| {
+        b.iter(|| {
+            fresh += 1;
+            runtime.block_on(is_duplicate(None, &db, &activity_id(fresh)))
+        })
+    });
+    group.bench_function("new_activity/with_filter", |b| {
+        b.iter(|| {
+            fresh += 1;
+            runtime.block_on(is_duplicate(Some(&seen), &db, &activity_id(fresh)))
+        })
+    });
+
+    // Redeliveries pay for the filter on top of the database lookup
+    let mut known = 0;
+    group.bench_function("redelivery/database_only", |b| {
+        b.iter(|| {
+            known = (known + 7919) % REMEMBERED;
+            runtime.block_on(is_duplicate(None, &db, &activity_id(known)))
+        })
+    });
+    group.bench_function("redelivery/with_filter", |b| {
+        b.iter(|| {
+            known = (known + 7919) % REMEMBERED;
+            runtime.block_on(is_duplicate(Some(&seen), &db, &activity_id(known)))
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, duplicate_check);
+criterion_main!(benches);
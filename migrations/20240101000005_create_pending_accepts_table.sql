@@ -0,0 +1,17 @@
+-- Create pending_accepts table for Accept/Undo activities that arrive
+-- before the follow relationship they refer to has been stored
+CREATE TABLE IF NOT EXISTS pending_accepts (
+    id TEXT PRIMARY KEY,
+    activity_type TEXT NOT NULL CHECK (activity_type IN ('Accept', 'Undo')),
+    follower_id TEXT NOT NULL,
+    following_id TEXT NOT NULL,
+    activity TEXT NOT NULL, -- JSON string
+    created_at DATETIME NOT NULL,
+    expires_at DATETIME NOT NULL
+);
+
+-- Create index for matching parked activities to a newly created follow
+CREATE INDEX IF NOT EXISTS idx_pending_accepts_pair ON pending_accepts(follower_id, following_id);
+
+-- Create index for expiry pruning
+CREATE INDEX IF NOT EXISTS idx_pending_accepts_expires_at ON pending_accepts(expires_at);
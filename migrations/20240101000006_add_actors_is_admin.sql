@@ -0,0 +1,2 @@
+-- Flag local actors allowed to use admin-scoped tokens
+ALTER TABLE actors ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,13 @@
+-- Create tokens table for bearer token authentication
+CREATE TABLE IF NOT EXISTS tokens (
+    id TEXT PRIMARY KEY,
+    token_hash TEXT NOT NULL UNIQUE,
+    actor_id TEXT NOT NULL,
+    scopes TEXT NOT NULL, -- JSON array of scope strings
+    created_at DATETIME NOT NULL,
+    expires_at DATETIME,
+    FOREIGN KEY (actor_id) REFERENCES actors(id) ON DELETE CASCADE
+);
+
+-- Create index for listing an actor's tokens
+CREATE INDEX IF NOT EXISTS idx_tokens_actor_id ON tokens(actor_id);
@@ -0,0 +1,13 @@
+-- Create delivery_queue table for outbound activities awaiting delivery
+CREATE TABLE IF NOT EXISTS delivery_queue (
+    id TEXT PRIMARY KEY,
+    inbox_url TEXT NOT NULL,
+    activity TEXT NOT NULL, -- JSON string
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at DATETIME NOT NULL,
+    last_error TEXT,
+    created_at DATETIME NOT NULL
+);
+
+-- Create index for picking up due deliveries
+CREATE INDEX IF NOT EXISTS idx_delivery_queue_next_attempt_at ON delivery_queue(next_attempt_at);
@@ -0,0 +1,2 @@
+-- Add attachments to notes (JSON array of ActivityStreams attachment objects)
+ALTER TABLE notes ADD COLUMN attachments TEXT NOT NULL DEFAULT '[]';
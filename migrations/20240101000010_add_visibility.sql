@@ -0,0 +1,22 @@
+-- Add visibility (public, unlisted, followers, direct) derived from addressing
+ALTER TABLE activities ADD COLUMN visibility TEXT NOT NULL DEFAULT 'public';
+ALTER TABLE notes ADD COLUMN visibility TEXT NOT NULL DEFAULT 'public';
+
+-- Backfill existing rows from their to/cc recipients
+UPDATE activities SET visibility = CASE
+    WHEN EXISTS (SELECT 1 FROM json_each(activities.to_recipients) WHERE value IN ('https://www.w3.org/ns/activitystreams#Public', 'as:Public', 'Public')) THEN 'public'
+    WHEN EXISTS (SELECT 1 FROM json_each(activities.cc_recipients) WHERE value IN ('https://www.w3.org/ns/activitystreams#Public', 'as:Public', 'Public')) THEN 'unlisted'
+    WHEN EXISTS (SELECT 1 FROM json_each(activities.to_recipients) WHERE value LIKE '%/followers')
+        OR EXISTS (SELECT 1 FROM json_each(activities.cc_recipients) WHERE value LIKE '%/followers') THEN 'followers'
+    ELSE 'direct'
+END;
+
+UPDATE notes SET visibility = CASE
+    WHEN EXISTS (SELECT 1 FROM json_each(notes.to_recipients) WHERE value IN ('https://www.w3.org/ns/activitystreams#Public', 'as:Public', 'Public')) THEN 'public'
+    WHEN EXISTS (SELECT 1 FROM json_each(notes.cc_recipients) WHERE value IN ('https://www.w3.org/ns/activitystreams#Public', 'as:Public', 'Public')) THEN 'unlisted'
+    WHEN EXISTS (SELECT 1 FROM json_each(notes.to_recipients) WHERE value LIKE '%/followers')
+        OR EXISTS (SELECT 1 FROM json_each(notes.cc_recipients) WHERE value LIKE '%/followers') THEN 'followers'
+    ELSE 'direct'
+END;
+
+CREATE INDEX IF NOT EXISTS idx_activities_actor_visibility ON activities(actor_id, visibility);
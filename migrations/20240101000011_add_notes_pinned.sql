@@ -0,0 +1,4 @@
+-- Pinned notes make up an actor's featured collection
+ALTER TABLE notes ADD COLUMN pinned BOOLEAN NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS idx_notes_attributed_to_pinned ON notes(attributed_to, pinned);
@@ -0,0 +1,6 @@
+-- Scheduled posts stay out of collections and delivery until they are published
+ALTER TABLE activities ADD COLUMN state TEXT NOT NULL DEFAULT 'published' CHECK (state IN ('published', 'scheduled'));
+ALTER TABLE notes ADD COLUMN state TEXT NOT NULL DEFAULT 'published' CHECK (state IN ('published', 'scheduled'));
+
+-- Create index for picking up scheduled activities that are due
+CREATE INDEX IF NOT EXISTS idx_activities_state_published ON activities(state, published);
@@ -0,0 +1,12 @@
+-- Create likes table for Like activities received on local notes
+CREATE TABLE IF NOT EXISTS likes (
+    id TEXT PRIMARY KEY,
+    actor_id TEXT NOT NULL,
+    object_id TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    FOREIGN KEY (object_id) REFERENCES notes(id) ON DELETE CASCADE,
+    UNIQUE(actor_id, object_id)
+);
+
+-- Create index for counting a note's likes
+CREATE INDEX IF NOT EXISTS idx_likes_object_id ON likes(object_id);
@@ -0,0 +1,29 @@
+-- Cache of remote actor profiles used to render follower and following lists
+CREATE TABLE IF NOT EXISTS remote_actors (
+    id TEXT PRIMARY KEY,
+    username TEXT NOT NULL,
+    name TEXT,
+    avatar_url TEXT,
+    fetched_at DATETIME NOT NULL
+);
+
+-- Either side of a follow can be a remote actor that has no row in actors,
+-- so rebuild follows without the foreign keys to it
+CREATE TABLE follows_new (
+    id TEXT PRIMARY KEY,
+    follower_id TEXT NOT NULL,
+    following_id TEXT NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('pending', 'accepted', 'rejected')),
+    created_at DATETIME NOT NULL,
+    updated_at DATETIME NOT NULL,
+    UNIQUE(follower_id, following_id)
+);
+
+INSERT INTO follows_new SELECT id, follower_id, following_id, status, created_at, updated_at FROM follows;
+DROP TABLE follows;
+ALTER TABLE follows_new RENAME TO follows;
+
+CREATE INDEX IF NOT EXISTS idx_follows_follower_id ON follows(follower_id);
+CREATE INDEX IF NOT EXISTS idx_follows_following_id ON follows(following_id);
+CREATE INDEX IF NOT EXISTS idx_follows_status ON follows(status);
+CREATE INDEX IF NOT EXISTS idx_follows_created_at ON follows(created_at DESC);
@@ -0,0 +1,65 @@
+-- Replies arrive from remote authors and may answer notes we never stored,
+-- so rebuild notes without foreign keys on either. Likes reference notes and
+-- would cascade away with the old table, so they are set aside meanwhile.
+CREATE TABLE likes_backup AS SELECT * FROM likes;
+DROP TABLE likes;
+
+CREATE TABLE notes_new (
+    id TEXT PRIMARY KEY,
+    attributed_to TEXT NOT NULL,
+    content TEXT NOT NULL,
+    to_recipients TEXT NOT NULL, -- JSON array
+    cc_recipients TEXT NOT NULL, -- JSON array
+    published DATETIME NOT NULL,
+    in_reply_to TEXT,
+    tags TEXT NOT NULL, -- JSON array
+    created_at DATETIME NOT NULL,
+    attachments TEXT NOT NULL DEFAULT '[]',
+    visibility TEXT NOT NULL DEFAULT 'public',
+    pinned BOOLEAN NOT NULL DEFAULT 0,
+    state TEXT NOT NULL DEFAULT 'published' CHECK (state IN ('published', 'scheduled')),
+    -- Who a reply answers and how deep in its thread it sits, so clients can
+    -- render "replying to" without fetching the parent. A NULL depth means
+    -- the thread couldn't be traced back to its root.
+    in_reply_to_actor TEXT,
+    thread_depth INTEGER,
+    thread_truncated BOOLEAN NOT NULL DEFAULT 0
+);
+
+INSERT INTO notes_new (id, attributed_to, content, to_recipients, cc_recipients, published, in_reply_to, tags, created_at, attachments, visibility, pinned, state)
+SELECT id, attributed_to, content, to_recipients, cc_recipients, published, in_reply_to, tags, created_at, attachments, visibility, pinned, state FROM notes;
+DROP TABLE notes;
+ALTER TABLE notes_new RENAME TO notes;
+
+CREATE INDEX IF NOT EXISTS idx_notes_attributed_to ON notes(attributed_to);
+CREATE INDEX IF NOT EXISTS idx_notes_published ON notes(published DESC);
+CREATE INDEX IF NOT EXISTS idx_notes_in_reply_to ON notes(in_reply_to);
+CREATE INDEX IF NOT EXISTS idx_notes_to_recipients ON notes(to_recipients);
+CREATE INDEX IF NOT EXISTS idx_notes_cc_recipients ON notes(cc_recipients);
+CREATE INDEX IF NOT EXISTS idx_notes_attributed_to_pinned ON notes(attributed_to, pinned);
+
+CREATE TABLE likes (
+    id TEXT PRIMARY KEY,
+    actor_id TEXT NOT NULL,
+    object_id TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    FOREIGN KEY (object_id) REFERENCES notes(id) ON DELETE CASCADE,
+    UNIQUE(actor_id, object_id)
+);
+INSERT INTO likes SELECT id, actor_id, object_id, created_at FROM likes_backup;
+DROP TABLE likes_backup;
+CREATE INDEX IF NOT EXISTS idx_likes_object_id ON likes(object_id);
+
+UPDATE notes
+SET in_reply_to_actor = (SELECT parent.attributed_to FROM notes parent WHERE parent.id = notes.in_reply_to)
+WHERE in_reply_to IS NOT NULL;
+
+-- Walk down from every root one level at a time; replies whose parent we
+-- never stored stay NULL. The depth cap is a runtime setting, so existing
+-- threads are recorded uncapped.
+WITH RECURSIVE thread(id, depth) AS (
+    SELECT id, 0 FROM notes WHERE in_reply_to IS NULL
+    UNION ALL
+    SELECT reply.id, thread.depth + 1 FROM notes reply JOIN thread ON reply.in_reply_to = thread.id
+)
+UPDATE notes SET thread_depth = (SELECT depth FROM thread WHERE thread.id = notes.id);
@@ -0,0 +1,3 @@
+-- Content warnings: `sensitive` hides media, `summary` is the warning text
+ALTER TABLE notes ADD COLUMN sensitive BOOLEAN NOT NULL DEFAULT 0;
+ALTER TABLE notes ADD COLUMN summary TEXT;
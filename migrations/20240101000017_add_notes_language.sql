@@ -0,0 +1,5 @@
+-- Language of a note's primary content, and every translation it came with
+ALTER TABLE notes ADD COLUMN language TEXT;
+ALTER TABLE notes ADD COLUMN content_map TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_notes_attributed_to_language ON notes(attributed_to, language);
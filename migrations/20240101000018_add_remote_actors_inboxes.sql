@@ -0,0 +1,3 @@
+-- Where to deliver to a cached remote actor, so fan-out needn't refetch them
+ALTER TABLE remote_actors ADD COLUMN inbox TEXT;
+ALTER TABLE remote_actors ADD COLUMN shared_inbox TEXT;
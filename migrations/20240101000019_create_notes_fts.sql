@@ -0,0 +1,26 @@
+-- Full-text index over note content and content warnings, kept in step with
+-- notes by triggers. Rows carry the note id rather than sharing the notes
+-- rowid, which VACUUM is free to renumber.
+CREATE VIRTUAL TABLE IF NOT EXISTS notes_fts USING fts5(
+    note_id UNINDEXED,
+    content,
+    summary
+);
+
+INSERT INTO notes_fts (note_id, content, summary)
+SELECT id, content, COALESCE(summary, '') FROM notes;
+
+CREATE TRIGGER IF NOT EXISTS notes_fts_insert AFTER INSERT ON notes BEGIN
+    INSERT INTO notes_fts (note_id, content, summary)
+    VALUES (new.id, new.content, COALESCE(new.summary, ''));
+END;
+
+CREATE TRIGGER IF NOT EXISTS notes_fts_delete AFTER DELETE ON notes BEGIN
+    DELETE FROM notes_fts WHERE note_id = old.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS notes_fts_update AFTER UPDATE OF id, content, summary ON notes BEGIN
+    DELETE FROM notes_fts WHERE note_id = old.id;
+    INSERT INTO notes_fts (note_id, content, summary)
+    VALUES (new.id, new.content, COALESCE(new.summary, ''));
+END;
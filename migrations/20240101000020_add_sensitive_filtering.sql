@@ -0,0 +1,10 @@
+-- Timelines can leave out sensitive notes, so filter on the flag by index
+CREATE INDEX IF NOT EXISTS idx_notes_sensitive_published ON notes(sensitive, published DESC);
+
+-- Per-actor reading preferences; actors without a row get the defaults
+CREATE TABLE IF NOT EXISTS actor_preferences (
+    actor_id TEXT PRIMARY KEY,
+    -- Show notes behind content warnings expanded rather than collapsed
+    expand_sensitive BOOLEAN NOT NULL DEFAULT 0,
+    FOREIGN KEY (actor_id) REFERENCES actors(id) ON DELETE CASCADE
+);
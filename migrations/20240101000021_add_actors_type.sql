@@ -0,0 +1,2 @@
+-- Local actors may be Groups, which boost what mentions them to their followers
+ALTER TABLE actors ADD COLUMN actor_type TEXT NOT NULL DEFAULT 'Person';
@@ -0,0 +1,32 @@
+-- Inbox activities come from remote actors we have no row for, so rebuild
+-- activities without the foreign key on actor_id. Deleting a local actor
+-- still takes their activities with it, through a trigger instead.
+CREATE TABLE activities_new (
+    id TEXT PRIMARY KEY,
+    actor_id TEXT NOT NULL,
+    activity_type TEXT NOT NULL,
+    object TEXT NOT NULL, -- JSON string
+    to_recipients TEXT NOT NULL, -- JSON array
+    cc_recipients TEXT NOT NULL, -- JSON array
+    published DATETIME NOT NULL,
+    created_at DATETIME NOT NULL,
+    visibility TEXT NOT NULL DEFAULT 'public',
+    state TEXT NOT NULL DEFAULT 'published' CHECK (state IN ('published', 'scheduled'))
+);
+
+INSERT INTO activities_new (id, actor_id, activity_type, object, to_recipients, cc_recipients, published, created_at, visibility, state)
+SELECT id, actor_id, activity_type, object, to_recipients, cc_recipients, published, created_at, visibility, state FROM activities;
+DROP TABLE activities;
+ALTER TABLE activities_new RENAME TO activities;
+
+CREATE INDEX IF NOT EXISTS idx_activities_actor_id ON activities(actor_id);
+CREATE INDEX IF NOT EXISTS idx_activities_published ON activities(published DESC);
+CREATE INDEX IF NOT EXISTS idx_activities_type ON activities(activity_type);
+CREATE INDEX IF NOT EXISTS idx_activities_to_recipients ON activities(to_recipients);
+CREATE INDEX IF NOT EXISTS idx_activities_cc_recipients ON activities(cc_recipients);
+CREATE INDEX IF NOT EXISTS idx_activities_actor_visibility ON activities(actor_id, visibility);
+CREATE INDEX IF NOT EXISTS idx_activities_state_published ON activities(state, published);
+
+CREATE TRIGGER IF NOT EXISTS activities_actor_delete AFTER DELETE ON actors BEGIN
+    DELETE FROM activities WHERE actor_id = old.id;
+END;
@@ -0,0 +1,8 @@
+-- Snapshot of the bytes each remote host sent the inbox, per minute, so
+-- hourly quotas survive a restart
+CREATE TABLE IF NOT EXISTS inbound_usage (
+    host TEXT NOT NULL,
+    minute DATETIME NOT NULL,
+    bytes INTEGER NOT NULL,
+    PRIMARY KEY (host, minute)
+);
@@ -0,0 +1,14 @@
+-- Labels content classifiers gave local notes, kept for audit whether or not
+-- they changed the note
+CREATE TABLE IF NOT EXISTS note_labels (
+    note_id TEXT NOT NULL,
+    label TEXT NOT NULL,
+    -- Whether the label's content warning or sensitive flag was applied
+    applied BOOLEAN NOT NULL,
+    created_at DATETIME NOT NULL,
+    PRIMARY KEY (note_id, label),
+    FOREIGN KEY (note_id) REFERENCES notes(id) ON DELETE CASCADE
+);
+
+-- Actors can opt out of classifiers changing their notes
+ALTER TABLE actor_preferences ADD COLUMN auto_content_warnings BOOLEAN NOT NULL DEFAULT 1;
@@ -0,0 +1,6 @@
+-- Deliveries that hold up a handshake or a conversation go out before
+-- bulk fan-out; anything queued before this was fan-out
+ALTER TABLE delivery_queue ADD COLUMN priority TEXT NOT NULL DEFAULT 'broadcast'
+    CHECK (priority IN ('interactive', 'direct', 'broadcast'));
+
+CREATE INDEX IF NOT EXISTS idx_delivery_queue_priority_next_attempt_at ON delivery_queue(priority, next_attempt_at);
@@ -0,0 +1,14 @@
+-- Remote documents that failed to deserialize, kept for a while so they can
+-- be turned into test fixtures
+CREATE TABLE IF NOT EXISTS parse_failures (
+    id TEXT PRIMARY KEY,
+    kind TEXT NOT NULL,
+    url TEXT NOT NULL,
+    error_path TEXT NOT NULL,
+    error TEXT NOT NULL,
+    document TEXT NOT NULL,
+    truncated BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at DATETIME NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_parse_failures_created_at ON parse_failures(created_at DESC);
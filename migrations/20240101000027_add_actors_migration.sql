@@ -0,0 +1,4 @@
+-- Account migration: where a local actor moved to, and the other ids
+-- (a JSON array) they claim as their own
+ALTER TABLE actors ADD COLUMN moved_to TEXT;
+ALTER TABLE actors ADD COLUMN also_known_as TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1,10 @@
+-- Actors blocked by local actors. The id is that of the Block activity, so an
+-- Undo can refer to it.
+CREATE TABLE IF NOT EXISTS blocks (
+    id TEXT PRIMARY KEY,
+    blocker_id TEXT NOT NULL,
+    blocked_id TEXT NOT NULL,
+    created_at DATETIME NOT NULL,
+    FOREIGN KEY (blocker_id) REFERENCES actors(id) ON DELETE CASCADE,
+    UNIQUE(blocker_id, blocked_id)
+);
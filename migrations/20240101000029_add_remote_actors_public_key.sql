@@ -0,0 +1,3 @@
+-- The signing key a remote actor published, so signatures can be checked
+-- without refetching the actor
+ALTER TABLE remote_actors ADD COLUMN public_key_pem TEXT;
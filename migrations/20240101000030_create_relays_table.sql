@@ -0,0 +1,12 @@
+-- Relays a local actor subscribes to. The subscription is a Follow of Public
+-- sent to the relay's inbox; the relay Accepts it and then Announces the
+-- public posts it receives to that actor. The Follow's id is kept so the
+-- Accept and a later Undo can refer to it.
+CREATE TABLE IF NOT EXISTS relays (
+    id TEXT PRIMARY KEY,
+    inbox_url TEXT NOT NULL UNIQUE,
+    actor_id TEXT NOT NULL,
+    follow_id TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'accepted')),
+    created_at DATETIME NOT NULL
+);
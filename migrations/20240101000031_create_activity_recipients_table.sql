@@ -0,0 +1,22 @@
+-- Who each activity is addressed to, one row per recipient, so the inbox
+-- can find an actor's activities through an index instead of matching
+-- substrings of the JSON recipient lists (which also matched any URL that
+-- merely contained the actor's id, like their followers collection)
+CREATE TABLE IF NOT EXISTS activity_recipients (
+    activity_id TEXT NOT NULL,
+    recipient_url TEXT NOT NULL,
+    recipient_type TEXT NOT NULL CHECK (recipient_type IN ('to', 'cc')),
+    PRIMARY KEY (activity_id, recipient_type, recipient_url),
+    FOREIGN KEY (activity_id) REFERENCES activities(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_activity_recipients_recipient_url ON activity_recipients(recipient_url);
+
+INSERT OR IGNORE INTO activity_recipients (activity_id, recipient_url, recipient_type)
+SELECT activities.id, recipients.value, 'to' FROM activities, json_each(activities.to_recipients) AS recipients;
+INSERT OR IGNORE INTO activity_recipients (activity_id, recipient_url, recipient_type)
+SELECT activities.id, recipients.value, 'cc' FROM activities, json_each(activities.cc_recipients) AS recipients;
+
+-- Nothing looks up the JSON lists as a whole any more
+DROP INDEX IF EXISTS idx_activities_to_recipients;
+DROP INDEX IF EXISTS idx_activities_cc_recipients;
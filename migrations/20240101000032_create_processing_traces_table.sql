@@ -0,0 +1,10 @@
+-- Which inbox processing stages ran for a sampled activity, and how each
+-- went, kept for a while to answer "why didn't this post show up?"
+CREATE TABLE IF NOT EXISTS processing_traces (
+    activity_id TEXT PRIMARY KEY,
+    activity_type TEXT NOT NULL,
+    stages TEXT NOT NULL, -- JSON array of {stage, outcome, detail, duration_us}
+    created_at DATETIME NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_processing_traces_created_at ON processing_traces(created_at);
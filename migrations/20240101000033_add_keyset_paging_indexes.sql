@@ -0,0 +1,6 @@
+-- Listings page by (published, id) rather than by offset, so a page starts
+-- right after the last item of the previous one however many rows arrived
+-- since. These indexes serve both the cursor comparison and the order.
+CREATE INDEX IF NOT EXISTS idx_activities_actor_published_id ON activities(actor_id, published, id);
+CREATE INDEX IF NOT EXISTS idx_activities_published_id ON activities(published, id);
+CREATE INDEX IF NOT EXISTS idx_notes_attributed_to_published_id ON notes(attributed_to, published, id);
@@ -0,0 +1,6 @@
+-- Follows lost their foreign keys to actors when the table was rebuilt to
+-- hold remote actors' follows too, so a deleted local actor's follows in
+-- either direction are cleaned up here instead
+CREATE TRIGGER IF NOT EXISTS follows_actor_delete AFTER DELETE ON actors BEGIN
+    DELETE FROM follows WHERE follower_id = old.id OR following_id = old.id;
+END;
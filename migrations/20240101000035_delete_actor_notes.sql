@@ -0,0 +1,6 @@
+-- Notes lost their foreign key to actors when the table was rebuilt to hold
+-- remote actors' notes too, so a deleted local actor's notes are cleaned up
+-- here instead
+CREATE TRIGGER IF NOT EXISTS notes_actor_delete AFTER DELETE ON actors BEGIN
+    DELETE FROM notes WHERE attributed_to = old.id;
+END;
@@ -0,0 +1,2 @@
+-- Where Move and Add/Remove activities point, so stored copies keep it
+ALTER TABLE activities ADD COLUMN target TEXT;
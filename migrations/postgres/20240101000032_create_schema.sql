@@ -0,0 +1,231 @@
+-- The schema of the SQLite migrations up to 20240101000032, in one go.
+-- Later changes get a migration here with the same version as their SQLite
+-- counterpart, so the two directories stay in step.
+
+CREATE TABLE actors (
+    id TEXT PRIMARY KEY,
+    username TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    summary TEXT,
+    public_key_pem TEXT NOT NULL,
+    private_key_pem TEXT,
+    is_admin BOOLEAN NOT NULL DEFAULT FALSE,
+    actor_type TEXT NOT NULL DEFAULT 'Person',
+    moved_to TEXT,
+    also_known_as JSONB NOT NULL DEFAULT '[]',
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_actors_created_at ON actors(created_at);
+
+CREATE TABLE activities (
+    id TEXT PRIMARY KEY,
+    actor_id TEXT NOT NULL,
+    activity_type TEXT NOT NULL,
+    object JSONB NOT NULL,
+    to_recipients JSONB NOT NULL,
+    cc_recipients JSONB NOT NULL,
+    published TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    visibility TEXT NOT NULL DEFAULT 'public',
+    state TEXT NOT NULL DEFAULT 'published' CHECK (state IN ('published', 'scheduled'))
+);
+
+CREATE INDEX idx_activities_actor_id ON activities(actor_id);
+CREATE INDEX idx_activities_published ON activities(published DESC);
+CREATE INDEX idx_activities_type ON activities(activity_type);
+CREATE INDEX idx_activities_actor_visibility ON activities(actor_id, visibility);
+CREATE INDEX idx_activities_state_published ON activities(state, published);
+
+-- Activities go with the local actor that sent them
+CREATE FUNCTION delete_actor_activities() RETURNS TRIGGER AS $$
+BEGIN
+    DELETE FROM activities WHERE actor_id = OLD.id;
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER activities_actor_delete AFTER DELETE ON actors
+    FOR EACH ROW EXECUTE FUNCTION delete_actor_activities();
+
+CREATE TABLE activity_recipients (
+    activity_id TEXT NOT NULL REFERENCES activities(id) ON DELETE CASCADE,
+    recipient_url TEXT NOT NULL,
+    recipient_type TEXT NOT NULL CHECK (recipient_type IN ('to', 'cc')),
+    PRIMARY KEY (activity_id, recipient_type, recipient_url)
+);
+
+CREATE INDEX idx_activity_recipients_recipient_url ON activity_recipients(recipient_url);
+
+CREATE TABLE notes (
+    id TEXT PRIMARY KEY,
+    attributed_to TEXT NOT NULL,
+    content TEXT NOT NULL,
+    to_recipients JSONB NOT NULL,
+    cc_recipients JSONB NOT NULL,
+    published TIMESTAMPTZ NOT NULL,
+    in_reply_to TEXT,
+    tags JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    attachments JSONB NOT NULL DEFAULT '[]',
+    visibility TEXT NOT NULL DEFAULT 'public',
+    pinned BOOLEAN NOT NULL DEFAULT FALSE,
+    state TEXT NOT NULL DEFAULT 'published' CHECK (state IN ('published', 'scheduled')),
+    in_reply_to_actor TEXT,
+    thread_depth BIGINT,
+    thread_truncated BOOLEAN NOT NULL DEFAULT FALSE,
+    sensitive BOOLEAN NOT NULL DEFAULT FALSE,
+    summary TEXT,
+    language TEXT,
+    content_map JSONB
+);
+
+CREATE INDEX idx_notes_attributed_to ON notes(attributed_to);
+CREATE INDEX idx_notes_published ON notes(published DESC);
+CREATE INDEX idx_notes_in_reply_to ON notes(in_reply_to);
+CREATE INDEX idx_notes_attributed_to_pinned ON notes(attributed_to, pinned);
+CREATE INDEX idx_notes_attributed_to_language ON notes(attributed_to, language);
+CREATE INDEX idx_notes_sensitive_published ON notes(sensitive, published DESC);
+
+-- Full-text search over content and content warning, standing in for the
+-- FTS5 table SQLite uses
+CREATE INDEX idx_notes_search ON notes
+    USING GIN (to_tsvector('simple', content || ' ' || COALESCE(summary, '')));
+
+CREATE TABLE note_labels (
+    note_id TEXT NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+    label TEXT NOT NULL,
+    -- Whether the label's content warning or sensitive flag was applied
+    applied BOOLEAN NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (note_id, label)
+);
+
+CREATE TABLE follows (
+    id TEXT PRIMARY KEY,
+    follower_id TEXT NOT NULL,
+    following_id TEXT NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('pending', 'accepted', 'rejected')),
+    created_at TIMESTAMPTZ NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL,
+    UNIQUE (follower_id, following_id)
+);
+
+CREATE INDEX idx_follows_following_id ON follows(following_id);
+CREATE INDEX idx_follows_status ON follows(status);
+CREATE INDEX idx_follows_created_at ON follows(created_at DESC);
+
+CREATE TABLE likes (
+    id TEXT PRIMARY KEY,
+    actor_id TEXT NOT NULL,
+    object_id TEXT NOT NULL REFERENCES notes(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL,
+    UNIQUE (actor_id, object_id)
+);
+
+CREATE INDEX idx_likes_object_id ON likes(object_id);
+
+CREATE TABLE blocks (
+    id TEXT PRIMARY KEY,
+    blocker_id TEXT NOT NULL REFERENCES actors(id) ON DELETE CASCADE,
+    blocked_id TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    UNIQUE (blocker_id, blocked_id)
+);
+
+CREATE TABLE relays (
+    id TEXT PRIMARY KEY,
+    inbox_url TEXT NOT NULL UNIQUE,
+    actor_id TEXT NOT NULL,
+    follow_id TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'accepted')),
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE TABLE remote_actors (
+    id TEXT PRIMARY KEY,
+    username TEXT NOT NULL,
+    name TEXT,
+    avatar_url TEXT,
+    inbox TEXT,
+    shared_inbox TEXT,
+    public_key_pem TEXT,
+    fetched_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE TABLE parse_failures (
+    id TEXT PRIMARY KEY,
+    kind TEXT NOT NULL,
+    url TEXT NOT NULL,
+    error_path TEXT NOT NULL,
+    error TEXT NOT NULL,
+    document TEXT NOT NULL,
+    truncated BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_parse_failures_created_at ON parse_failures(created_at DESC);
+
+CREATE TABLE processing_traces (
+    activity_id TEXT PRIMARY KEY,
+    activity_type TEXT NOT NULL,
+    -- Array of {stage, outcome, detail, duration_us}
+    stages JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_processing_traces_created_at ON processing_traces(created_at);
+
+CREATE TABLE pending_accepts (
+    id TEXT PRIMARY KEY,
+    activity_type TEXT NOT NULL CHECK (activity_type IN ('Accept', 'Undo')),
+    follower_id TEXT NOT NULL,
+    following_id TEXT NOT NULL,
+    activity JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_pending_accepts_pair ON pending_accepts(follower_id, following_id);
+CREATE INDEX idx_pending_accepts_expires_at ON pending_accepts(expires_at);
+
+CREATE TABLE delivery_queue (
+    id TEXT PRIMARY KEY,
+    inbox_url TEXT NOT NULL,
+    activity JSONB NOT NULL,
+    priority TEXT NOT NULL DEFAULT 'broadcast'
+        CHECK (priority IN ('interactive', 'direct', 'broadcast')),
+    attempts BIGINT NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX idx_delivery_queue_next_attempt_at ON delivery_queue(next_attempt_at);
+CREATE INDEX idx_delivery_queue_priority_next_attempt_at ON delivery_queue(priority, next_attempt_at);
+
+CREATE TABLE inbound_usage (
+    host TEXT NOT NULL,
+    minute TIMESTAMPTZ NOT NULL,
+    bytes BIGINT NOT NULL,
+    PRIMARY KEY (host, minute)
+);
+
+CREATE TABLE tokens (
+    id TEXT PRIMARY KEY,
+    token_hash TEXT NOT NULL UNIQUE,
+    actor_id TEXT NOT NULL REFERENCES actors(id) ON DELETE CASCADE,
+    scopes JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    expires_at TIMESTAMPTZ
+);
+
+CREATE INDEX idx_tokens_actor_id ON tokens(actor_id);
+
+CREATE TABLE actor_preferences (
+    actor_id TEXT PRIMARY KEY REFERENCES actors(id) ON DELETE CASCADE,
+    -- Show notes behind content warnings expanded rather than collapsed
+    expand_sensitive BOOLEAN NOT NULL DEFAULT FALSE,
+    auto_content_warnings BOOLEAN NOT NULL DEFAULT TRUE
+);
@@ -0,0 +1,11 @@
+-- Follows hold remote actors' rows too, so they have no foreign keys to
+-- actors; a deleted local actor's follows in either direction go here instead
+CREATE FUNCTION delete_actor_follows() RETURNS TRIGGER AS $$
+BEGIN
+    DELETE FROM follows WHERE follower_id = OLD.id OR following_id = OLD.id;
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER follows_actor_delete AFTER DELETE ON actors
+    FOR EACH ROW EXECUTE FUNCTION delete_actor_follows();
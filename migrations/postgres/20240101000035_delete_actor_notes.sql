@@ -0,0 +1,11 @@
+-- Notes hold remote actors' rows too, so they have no foreign key to actors;
+-- a deleted local actor's notes go here instead
+CREATE FUNCTION delete_actor_notes() RETURNS TRIGGER AS $$
+BEGIN
+    DELETE FROM notes WHERE attributed_to = OLD.id;
+    RETURN OLD;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER notes_actor_delete AFTER DELETE ON actors
+    FOR EACH ROW EXECUTE FUNCTION delete_actor_notes();
@@ -0,0 +1,328 @@
+use crate::database::DatabaseRef;
+use crate::errors::FederationError;
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Payload, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{self, HeaderMap};
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError};
+use sha2::{Digest, Sha256};
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use tracing::warn;
+
+/// Scope required for the admin API; also requires the actor's `is_admin` flag
+pub const ADMIN_SCOPE: &str = "admin";
+
+/// Scope for posting to an actor's outbox
+pub const WRITE_STATUSES_SCOPE: &str = "write:statuses";
+
+/// Scope for reading an actor's inbox
+pub const READ_STATUSES_SCOPE: &str = "read:statuses";
+
+/// Scope for reading an actor's account settings
+pub const READ_ACCOUNTS_SCOPE: &str = "read:accounts";
+
+/// Scope for changing an actor's account settings
+pub const WRITE_ACCOUNTS_SCOPE: &str = "write:accounts";
+
+/// Hash a plaintext bearer token the way it is stored in the tokens table
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// The authenticated caller of an API request
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuthContext {
+    pub actor_id: String,
+    pub scopes: Vec<String>,
+    pub token_id: String,
+}
+
+impl AuthContext {
+    /// Whether the token grants `required`. A bare scope class such as
+    /// `write` grants every scope in that class (`write:statuses`, ...).
+    pub fn has_scope(&self, required: &str) -> bool {
+        self.scopes.iter().any(|granted| {
+            granted == required
+                || required
+                    .split_once(':')
+                    .is_some_and(|(class, _)| class == granted)
+        })
+    }
+
+    /// Check the token was issued to `actor_id`, the local actor the request
+    /// acts as
+    pub fn ensure_actor(&self, actor_id: &str) -> Result<(), AuthError> {
+        if self.actor_id != actor_id {
+            warn!(
+                "Token {} of {} used for {}",
+                self.token_id, self.actor_id, actor_id
+            );
+            return Err(AuthError::WrongActor);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthError {
+    #[error("Unauthorized")]
+    Unauthorized,
+    #[error("Missing required scope: {0}")]
+    InsufficientScope(String),
+    #[error("Actor is not an administrator")]
+    NotAdmin,
+    #[error("Token does not belong to this actor")]
+    WrongActor,
+    #[error("Internal server error")]
+    Internal,
+}
+
+impl ResponseError for AuthError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            AuthError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AuthError::InsufficientScope(_) | AuthError::NotAdmin | AuthError::WrongActor => {
+                StatusCode::FORBIDDEN
+            }
+            AuthError::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        FederationError::from(self).error_response()
+    }
+}
+
+impl From<&AuthError> for FederationError {
+    fn from(error: &AuthError) -> Self {
+        match error {
+            AuthError::Unauthorized => FederationError::Unauthorized,
+            AuthError::InsufficientScope(scope) => {
+                FederationError::InsufficientScope(scope.clone())
+            }
+            AuthError::NotAdmin | AuthError::WrongActor => {
+                FederationError::Forbidden(error.to_string())
+            }
+            AuthError::Internal => FederationError::InternalError,
+        }
+    }
+}
+
+/// The bearer token in an `Authorization` header, unchecked
+pub fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+}
+
+/// Resolve the request's bearer token to an [`AuthContext`]
+pub async fn authenticate(req: &HttpRequest) -> Result<AuthContext, AuthError> {
+    let token = bearer_token(req.headers()).ok_or(AuthError::Unauthorized)?;
+
+    let db = req
+        .app_data::<web::Data<DatabaseRef>>()
+        .ok_or(AuthError::Internal)?;
+
+    match db.validate_token(token).await {
+        Ok(Some(token)) => Ok(AuthContext {
+            actor_id: token.actor_id,
+            scopes: token.scopes,
+            token_id: token.id,
+        }),
+        Ok(None) => Err(AuthError::Unauthorized),
+        Err(e) => {
+            warn!("Database error while validating token: {}", e);
+            Err(AuthError::Internal)
+        }
+    }
+}
+
+/// Authenticate the request and check it carries `scope`. Admin scopes are
+/// only honoured for actors flagged as admins.
+pub async fn authorize(req: &HttpRequest, scope: &str) -> Result<AuthContext, AuthError> {
+    let context = authenticate(req).await?;
+
+    if !context.has_scope(scope) {
+        warn!("Token {} lacks required scope {}", context.token_id, scope);
+        return Err(AuthError::InsufficientScope(scope.to_string()));
+    }
+
+    if scope == ADMIN_SCOPE || scope.starts_with("admin:") {
+        let db = req
+            .app_data::<web::Data<DatabaseRef>>()
+            .ok_or(AuthError::Internal)?;
+        match db.get_actor_by_id(&context.actor_id).await {
+            Ok(Some(actor)) if actor.is_admin => {}
+            Ok(_) => {
+                warn!("Admin scope used by non-admin actor {}", context.actor_id);
+                return Err(AuthError::NotAdmin);
+            }
+            Err(e) => {
+                warn!("Database error while checking admin flag: {}", e);
+                return Err(AuthError::Internal);
+            }
+        }
+    }
+
+    Ok(context)
+}
+
+/// The reader a request is signed in as, if any, for deciding whether it may
+/// see local-only and private posts. A request without `Authorization` is
+/// anonymous; a bad token is refused rather than quietly made anonymous.
+pub async fn viewer(req: &HttpRequest) -> Result<Option<AuthContext>, AuthError> {
+    if !req.headers().contains_key(header::AUTHORIZATION) {
+        return Ok(None);
+    }
+    authorize(req, READ_STATUSES_SCOPE).await.map(Some)
+}
+
+/// Generate a new plaintext bearer token
+pub fn generate_token() -> String {
+    let bytes: [u8; 32] = rand::random();
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Middleware requiring a bearer token with the given scope, e.g.
+/// `web::resource(...).wrap(RequireScope("write:statuses"))`. Handlers
+/// behind it can extract the [`AuthContext`].
+#[derive(Debug, Clone, Copy)]
+pub struct RequireScope(pub &'static str);
+
+impl<S, B> Transform<S, ServiceRequest> for RequireScope
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = RequireScopeMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequireScopeMiddleware {
+            service: Rc::new(service),
+            scope: self.0,
+        }))
+    }
+}
+
+pub struct RequireScopeMiddleware<S> {
+    service: Rc<S>,
+    scope: &'static str,
+}
+
+type LocalBoxFuture<T> = Pin<Box<dyn Future<Output = T>>>;
+
+impl<S, B> Service<ServiceRequest> for RequireScopeMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let scope = self.scope;
+
+        Box::pin(async move {
+            match authorize(req.request(), scope).await {
+                Ok(context) => {
+                    req.extensions_mut().insert(context);
+                    service.call(req).await.map(|res| res.map_into_left_body())
+                }
+                Err(e) => Ok(req.error_response(e).map_into_right_body()),
+            }
+        })
+    }
+}
+
+impl FromRequest for AuthContext {
+    type Error = AuthError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        // Only present on routes wrapped in RequireScope
+        ready(
+            req.extensions()
+                .get::<AuthContext>()
+                .cloned()
+                .ok_or(AuthError::Unauthorized),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(scopes: &[&str]) -> AuthContext {
+        AuthContext {
+            actor_id: "https://example.com/users/alice".to_string(),
+            scopes: scopes.iter().map(|s| s.to_string()).collect(),
+            token_id: "token-1".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_hash_token_is_stable_hex() {
+        let hash = hash_token("secret");
+
+        assert_eq!(hash.len(), 64);
+        assert_eq!(hash, hash_token("secret"));
+        assert_ne!(hash, hash_token("other"));
+    }
+
+    #[test]
+    fn test_generated_tokens_are_unique() {
+        let token = generate_token();
+
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, generate_token());
+    }
+
+    #[test]
+    fn test_exact_scope() {
+        let context = context(&["read:statuses"]);
+
+        assert!(context.has_scope("read:statuses"));
+        assert!(!context.has_scope("read:follows"));
+        assert!(!context.has_scope("read"));
+        assert!(!context.has_scope("write:statuses"));
+    }
+
+    #[test]
+    fn test_scope_class_grants_sub_scopes() {
+        let context = context(&["write", "follow"]);
+
+        assert!(context.has_scope("write:statuses"));
+        assert!(context.has_scope("write:media"));
+        assert!(context.has_scope("follow"));
+        assert!(!context.has_scope("read:statuses"));
+        assert!(!context.has_scope(ADMIN_SCOPE));
+    }
+
+    #[test]
+    fn test_ensure_actor() {
+        let context = context(&["write"]);
+
+        assert!(context
+            .ensure_actor("https://example.com/users/alice")
+            .is_ok());
+        assert!(matches!(
+            context.ensure_actor("https://example.com/users/bob"),
+            Err(AuthError::WrongActor)
+        ));
+    }
+}
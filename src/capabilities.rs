@@ -0,0 +1,132 @@
+use crate::config::Config;
+use serde::{Deserialize, Serialize};
+
+/// Version of the capabilities document; bump when the shape changes
+pub const CAPABILITIES_API_VERSION: u32 = 1;
+
+/// Optional features advertised to clients so they can adapt their UI
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Capabilities {
+    pub api_version: u32,
+    pub software: SoftwareInfo,
+    pub features: Features,
+    pub limits: Limits,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SoftwareInfo {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Features {
+    pub polls: bool,
+    pub media_uploads: bool,
+    pub markdown: bool,
+    pub streaming: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Limits {
+    pub max_note_length: usize,
+    pub max_media_size: Option<u64>,
+}
+
+impl Capabilities {
+    /// Assemble the capabilities document from configuration and the
+    /// features compiled into this build
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            api_version: CAPABILITIES_API_VERSION,
+            software: SoftwareInfo {
+                name: env!("CARGO_PKG_NAME").to_string(),
+                version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+            features: Features {
+                polls: false,
+                media_uploads: false,
+                markdown: config.markdown_enabled,
+                streaming: false,
+            },
+            limits: Limits {
+                max_note_length: config.max_note_length,
+                max_media_size: None,
+            },
+        }
+    }
+
+    /// Names of the enabled optional features, used for the startup banner
+    pub fn enabled_features(&self) -> Vec<&'static str> {
+        let features = [
+            ("polls", self.features.polls),
+            ("media_uploads", self.features.media_uploads),
+            ("markdown", self.features.markdown),
+            ("streaming", self.features.streaming),
+        ];
+
+        features
+            .into_iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| name)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_test_config() -> Config {
+        Config {
+            server_name: "Test Server".to_string(),
+            server_url: "https://test.example.com".to_string(),
+            port: 8080,
+            actor_name: "testuser".to_string(),
+            private_key_path: None,
+            public_key_path: None,
+            max_note_length: 500,
+            markdown_enabled: false,
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn test_capabilities_from_config() {
+        let capabilities = Capabilities::from_config(&create_test_config());
+
+        assert_eq!(capabilities.api_version, 1);
+        assert_eq!(capabilities.software.name, "feder8");
+        assert!(!capabilities.features.markdown);
+        assert!(!capabilities.features.polls);
+        assert!(!capabilities.features.streaming);
+        assert_eq!(capabilities.limits.max_note_length, 500);
+        assert_eq!(capabilities.limits.max_media_size, None);
+    }
+
+    #[test]
+    fn test_capabilities_follow_config_flags() {
+        let config = Config {
+            max_note_length: 5000,
+            markdown_enabled: true,
+            ..create_test_config()
+        };
+
+        let capabilities = Capabilities::from_config(&config);
+
+        assert!(capabilities.features.markdown);
+        assert_eq!(capabilities.limits.max_note_length, 5000);
+        assert_eq!(capabilities.enabled_features(), vec!["markdown"]);
+    }
+
+    #[test]
+    fn test_capabilities_serialization() {
+        let capabilities = Capabilities::from_config(&create_test_config());
+        let json = serde_json::to_value(&capabilities).unwrap();
+
+        assert_eq!(json["api_version"], 1);
+        assert_eq!(json["features"]["markdown"], false);
+        assert_eq!(json["limits"]["max_note_length"], 500);
+        assert!(json["limits"]["max_media_size"].is_null());
+    }
+}
@@ -0,0 +1,284 @@
+use crate::config::Config;
+use crate::database::{open_database, DatabaseRef, DbActivity};
+use crate::http::ReqwestClient;
+use crate::models::ContextBuilder;
+use crate::services::delivery::DeliveryService;
+use crate::services::keys::KeyManager;
+use anyhow::{anyhow, bail, Result};
+use serde_json::json;
+use std::sync::Arc;
+
+pub const USAGE: &str = "\
+Usage:
+  feder8 [--mock-db]          Run the server; --mock-db keeps everything in memory
+  feder8 keys audit [OPTIONS] Check the actor's keys and stored signatures
+
+Options for `keys audit`:
+  --actor <username>     Actor to audit (defaults to ACTOR_NAME)
+  --resign               Re-sign activities that carry signatures by old keys
+  --deliver-to <host>    Re-deliver re-signed activities to https://<host>/inbox";
+
+/// Page size used when loading an actor's stored activities
+const ACTIVITY_PAGE_SIZE: u32 = 100;
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Serve(ServeOptions),
+    KeysAudit(AuditOptions),
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ServeOptions {
+    /// Use a mock database that forgets everything on restart
+    pub mock_db: bool,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct AuditOptions {
+    pub actor: Option<String>,
+    pub resign: bool,
+    pub deliver_to: Option<String>,
+}
+
+/// Parse command line arguments (without the program name)
+pub fn parse_args<I: IntoIterator<Item = String>>(args: I) -> Result<Command, String> {
+    let mut args = args.into_iter();
+
+    match args.next().as_deref() {
+        None => Ok(Command::Serve(ServeOptions::default())),
+        Some("--mock-db") => match args.next() {
+            None => Ok(Command::Serve(ServeOptions { mock_db: true })),
+            Some(other) => Err(format!("Unknown option: {other}")),
+        },
+        Some("keys") => match args.next().as_deref() {
+            Some("audit") => parse_audit_options(args).map(Command::KeysAudit),
+            Some(other) => Err(format!("Unknown keys command: {other}")),
+            None => Err("Missing keys command".to_string()),
+        },
+        Some(other) => Err(format!("Unknown command: {other}")),
+    }
+}
+
+fn parse_audit_options<I: Iterator<Item = String>>(mut args: I) -> Result<AuditOptions, String> {
+    let mut options = AuditOptions::default();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--actor" => {
+                options.actor = Some(args.next().ok_or("--actor requires a username")?);
+            }
+            "--resign" => options.resign = true,
+            "--deliver-to" => {
+                options.deliver_to = Some(args.next().ok_or("--deliver-to requires a host")?);
+            }
+            other => return Err(format!("Unknown option: {other}")),
+        }
+    }
+
+    if options.deliver_to.is_some() && !options.resign {
+        return Err("--deliver-to can only be used together with --resign".to_string());
+    }
+
+    Ok(options)
+}
+
+/// Audit the actor's keys against the stored activities, optionally
+/// re-signing and re-delivering the ones signed by old keys. Activities are
+/// audited a page at a time, and one that can't be re-signed or delivered is
+/// reported without stopping the rest.
+pub async fn run_keys_audit(config: &Config, options: &AuditOptions) -> Result<()> {
+    let db = open_database(config).await?;
+
+    let username = options.actor.as_deref().unwrap_or(&config.actor_name);
+    let actor = db
+        .get_actor_by_username(username)
+        .await?
+        .ok_or_else(|| anyhow!("Actor not found: {username}"))?;
+
+    let key_manager = KeyManager::new();
+    let key_id = KeyManager::key_id(&actor.id);
+    let keys = key_manager.audit(&actor, &[])?;
+
+    println!("Actor:       {}", actor.id);
+    println!("Key id:      {key_id}");
+    println!("Fingerprint: SHA256:{}", keys.fingerprint);
+    if keys.key_matches {
+        println!("Key pair:    ok");
+    } else {
+        println!("Key pair:    MISMATCH (served publicKey does not match the private key)");
+    }
+
+    // Re-signing with a key that doesn't match what we serve would only make
+    // more signatures that fail to verify
+    let signing_key = match (options.resign, keys.key_matches, &actor.private_key_pem) {
+        (true, true, Some(private_key_pem)) => Some(private_key_pem.as_str()),
+        _ => None,
+    };
+    let delivery = options.deliver_to.as_ref().map(|host| {
+        (
+            format!("https://{host}/inbox"),
+            DeliveryService::new(config.clone(), Arc::new(ReqwestClient::new()), db.clone()),
+        )
+    });
+
+    let mut checked = 0u32;
+    let mut stale = 0usize;
+    let mut failed = 0usize;
+    loop {
+        let page = db
+            .get_activities_by_actor(&actor.id, ACTIVITY_PAGE_SIZE, checked)
+            .await?;
+        let count = page.len() as u32;
+        checked += count;
+
+        let audit = key_manager.audit(&actor, &page)?;
+        stale += audit.stale.len();
+        for signature in &audit.stale {
+            println!(
+                "  {} (signed by {})",
+                signature.activity_id, signature.creator
+            );
+            let Some(private_key_pem) = signing_key else {
+                continue;
+            };
+            let Some(activity) = page.iter().find(|a| a.id == signature.activity_id) else {
+                continue;
+            };
+            if let Err(e) = resign(
+                &db,
+                &key_manager,
+                &key_id,
+                private_key_pem,
+                activity,
+                delivery.as_ref(),
+            )
+            .await
+            {
+                eprintln!("Could not re-sign {}: {e:#}", activity.id);
+                failed += 1;
+            }
+        }
+
+        if count < ACTIVITY_PAGE_SIZE {
+            break;
+        }
+    }
+
+    println!("Checked {checked} stored activities, {stale} signed with old keys");
+    if !options.resign || stale == 0 {
+        return Ok(());
+    }
+    if !keys.key_matches {
+        bail!("Refusing to re-sign while the served public key does not match the private key");
+    }
+    if signing_key.is_none() {
+        bail!("Actor {} has no private key", actor.id);
+    }
+    if failed > 0 {
+        bail!("{failed} of {stale} activities could not be re-signed");
+    }
+
+    Ok(())
+}
+
+/// Re-sign one stored activity with the current key, and re-deliver it
+/// when asked to
+async fn resign(
+    db: &DatabaseRef,
+    key_manager: &KeyManager,
+    key_id: &str,
+    private_key_pem: &str,
+    activity: &DbActivity,
+    delivery: Option<&(String, DeliveryService)>,
+) -> Result<()> {
+    let mut object = activity.object.clone();
+    key_manager.sign_object(&mut object, key_id, private_key_pem)?;
+    db.update_activity_object(&activity.id, &object).await?;
+    println!("Re-signed {}", activity.id);
+
+    if let Some((inbox, service)) = delivery {
+        let resigned = DbActivity {
+            object,
+            ..activity.clone()
+        };
+        service.deliver_activity(inbox, to_json(&resigned)).await?;
+        println!("Re-delivered {} to {inbox}", activity.id);
+    }
+    Ok(())
+}
+
+fn to_json(activity: &DbActivity) -> serde_json::Value {
+    let mut document = json!({
+        "id": activity.id,
+        "type": activity.activity_type,
+        "actor": activity.actor_id,
+        "object": activity.object,
+        "to": activity.to_recipients,
+        "cc": activity.cc_recipients,
+        "published": activity.published
+    });
+    if let Some(target) = &activity.target {
+        document["target"] = json!(target);
+    }
+    document["@context"] = ContextBuilder::for_document(&document).build().into();
+    document
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_no_args_serves() {
+        assert_eq!(
+            parse_args(args(&[])),
+            Ok(Command::Serve(ServeOptions::default()))
+        );
+    }
+
+    #[test]
+    fn test_parse_mock_db() {
+        assert_eq!(
+            parse_args(args(&["--mock-db"])),
+            Ok(Command::Serve(ServeOptions { mock_db: true }))
+        );
+        assert!(parse_args(args(&["--mock-db", "keys"])).is_err());
+    }
+
+    #[test]
+    fn test_parse_keys_audit() {
+        assert_eq!(
+            parse_args(args(&["keys", "audit"])),
+            Ok(Command::KeysAudit(AuditOptions::default()))
+        );
+        assert_eq!(
+            parse_args(args(&[
+                "keys",
+                "audit",
+                "--actor",
+                "bob",
+                "--resign",
+                "--deliver-to",
+                "remote.example"
+            ])),
+            Ok(Command::KeysAudit(AuditOptions {
+                actor: Some("bob".to_string()),
+                resign: true,
+                deliver_to: Some("remote.example".to_string()),
+            }))
+        );
+    }
+
+    #[test]
+    fn test_parse_rejects_invalid_args() {
+        assert!(parse_args(args(&["serve"])).is_err());
+        assert!(parse_args(args(&["keys"])).is_err());
+        assert!(parse_args(args(&["keys", "rotate"])).is_err());
+        assert!(parse_args(args(&["keys", "audit", "--actor"])).is_err());
+        assert!(parse_args(args(&["keys", "audit", "--deliver-to", "remote.example"])).is_err());
+    }
+}
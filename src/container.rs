@@ -1,7 +1,24 @@
+use crate::capabilities::Capabilities;
 use crate::config::Config;
 use crate::database::DatabaseRef;
-use crate::http::{HttpClient, ReqwestClient};
+use crate::health::DatabaseHealth;
+use crate::http::{HttpClient, RecordingHttpClient, ReqwestClient};
+use crate::metered_database::MeteredDatabase;
+use crate::metrics::Metrics;
+use crate::services::actor_fetch::ActorFetchService;
+use crate::services::backpressure::InboxBackpressure;
+use crate::services::classify::{ContentClassifier, KeywordClassifier};
 use crate::services::delivery::DeliveryService;
+use crate::services::document_cache::DocumentCache;
+use crate::services::events::EventBus;
+use crate::services::inbox_stream::InboxStream;
+use crate::services::keys::KeyManager;
+use crate::services::log_dedup::LogDedup;
+use crate::services::parse_failures::ParseFailureRecorder;
+use crate::services::rate_limit::ApiRateLimiter;
+use crate::services::seen_activities::SeenActivities;
+use crate::swappable_database::SwappableDatabase;
+use crate::urls::UrlBuilder;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -11,8 +28,24 @@ use std::time::Duration;
 pub struct Container {
     config: Config,
     database: DatabaseRef,
+    swappable_database: Arc<SwappableDatabase>,
     http_client: Arc<dyn HttpClient>,
     delivery_service: Arc<DeliveryService>,
+    capabilities: Capabilities,
+    key_manager: KeyManager,
+    metrics: Arc<Metrics>,
+    health: Arc<DatabaseHealth>,
+    seen_activities: Arc<SeenActivities>,
+    inbox_backpressure: Arc<InboxBackpressure>,
+    log_dedup: Arc<LogDedup>,
+    event_bus: Arc<EventBus>,
+    document_cache: Arc<DocumentCache>,
+    content_classifier: Arc<dyn ContentClassifier>,
+    inbox_stream: Arc<InboxStream>,
+    api_rate_limiter: Arc<ApiRateLimiter>,
+    actor_fetch: Arc<ActorFetchService>,
+    urls: UrlBuilder,
+    recording_http_client: Option<Arc<RecordingHttpClient>>,
 }
 
 #[allow(dead_code)]
@@ -23,15 +56,7 @@ impl Container {
         let http_client: Arc<dyn HttpClient> =
             Arc::new(ReqwestClient::with_timeout(Duration::from_secs(30)));
 
-        // Create delivery service with injected HTTP client
-        let delivery_service = Arc::new(DeliveryService::new(config.clone(), http_client.clone()));
-
-        Self {
-            config,
-            database,
-            http_client,
-            delivery_service,
-        }
+        Self::with_http_client(config, database, http_client)
     }
 
     /// Create a new container with custom HTTP client
@@ -40,13 +65,100 @@ impl Container {
         database: DatabaseRef,
         http_client: Arc<dyn HttpClient>,
     ) -> Self {
-        let delivery_service = Arc::new(DeliveryService::new(config.clone(), http_client.clone()));
+        // Capabilities are fixed for the lifetime of the process
+        let capabilities = Capabilities::from_config(&config);
+
+        // Time every database call when metrics are enabled, and always track
+        // availability so readiness and 503s reflect database outages. The
+        // swap point sits underneath so a failover keeps both.
+        let swappable_database = Arc::new(SwappableDatabase::new(database));
+        let database: DatabaseRef = swappable_database.clone();
+        let metrics = Arc::new(Metrics::new());
+        let health = Arc::new(DatabaseHealth::new(
+            config.db_failure_rate_threshold,
+            metrics.clone(),
+        ));
+        let threshold = Duration::from_millis(config.slow_query_threshold_ms);
+        let metered = if config.metrics_enabled {
+            MeteredDatabase::new(database, metrics.clone(), threshold)
+        } else {
+            MeteredDatabase::unmetered(database, threshold)
+        };
+        let database: DatabaseRef = Arc::new(metered.with_health(health.clone()));
+
+        // Repeated federation warnings about one peer are collapsed, one
+        // window per warning and host
+        let log_dedup = LogDedup::from_config(&config);
+        let log_dedup = Arc::new(if config.metrics_enabled {
+            log_dedup.with_metrics(metrics.clone())
+        } else {
+            log_dedup
+        });
+
+        // Create delivery service with injected HTTP client, reading followers
+        // through the same metered database as everything else
+        let delivery_service =
+            DeliveryService::new(config.clone(), http_client.clone(), database.clone())
+                .with_log_dedup(log_dedup.clone());
+        let delivery_service = Arc::new(if config.metrics_enabled {
+            delivery_service.with_metrics(metrics.clone())
+        } else {
+            delivery_service
+        });
+
+        let seen_activities = Arc::new(SeenActivities::new(config.seen_activity_capacity));
+        let inbox_backpressure = Arc::new(InboxBackpressure::from_config(&config, metrics.clone()));
+        let urls = UrlBuilder::from_config(&config);
+
+        // Served actor and note documents are dropped from the cache as soon
+        // as anything announces a change to them
+        let document_cache = DocumentCache::from_config(&config);
+        let document_cache = Arc::new(if config.metrics_enabled {
+            document_cache.with_metrics(metrics.clone())
+        } else {
+            document_cache
+        });
+        let event_bus = Arc::new(EventBus::new());
+        event_bus.subscribe(document_cache.clone());
+
+        // Local notes are labelled from the configured keyword rules
+        let content_classifier: Arc<dyn ContentClassifier> =
+            Arc::new(KeywordClassifier::from_config(&config));
+        let inbox_stream = Arc::new(InboxStream::from_config(&config));
+        // Shared by every worker, so each client has one budget per process
+        let api_rate_limiter = Arc::new(ApiRateLimiter::from_config(&config));
+        let parse_failures = ParseFailureRecorder::new(
+            database.clone(),
+            config.metrics_enabled.then(|| metrics.clone()),
+        );
+        let actor_fetch = Arc::new(ActorFetchService::from_config(
+            &config,
+            database.clone(),
+            http_client.clone(),
+            parse_failures,
+        ));
 
         Self {
             config,
             database,
+            swappable_database,
             http_client,
             delivery_service,
+            capabilities,
+            key_manager: KeyManager::new(),
+            metrics,
+            health,
+            seen_activities,
+            inbox_backpressure,
+            log_dedup,
+            event_bus,
+            document_cache,
+            content_classifier,
+            inbox_stream,
+            api_rate_limiter,
+            actor_fetch,
+            urls,
+            recording_http_client: None,
         }
     }
 
@@ -60,6 +172,12 @@ impl Container {
         &self.database
     }
 
+    /// Repoint every database consumer at `database`, returning the previous
+    /// target. Operations already in flight finish against the old one.
+    pub fn swap_database(&self, database: DatabaseRef) -> DatabaseRef {
+        self.swappable_database.swap(database)
+    }
+
     /// Get the HTTP client
     pub fn http_client(&self) -> &Arc<dyn HttpClient> {
         &self.http_client
@@ -69,6 +187,87 @@ impl Container {
     pub fn delivery_service(&self) -> &Arc<DeliveryService> {
         &self.delivery_service
     }
+
+    /// Get the advertised client capabilities
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    /// Get the actor key manager
+    pub fn key_manager(&self) -> &KeyManager {
+        &self.key_manager
+    }
+
+    /// Get the application metrics
+    pub fn metrics(&self) -> &Arc<Metrics> {
+        &self.metrics
+    }
+
+    /// Get the database availability tracker
+    pub fn health(&self) -> &Arc<DatabaseHealth> {
+        &self.health
+    }
+
+    /// Get the recently processed activity ids
+    pub fn seen_activities(&self) -> &Arc<SeenActivities> {
+        &self.seen_activities
+    }
+
+    /// Get the inbox admission control
+    pub fn inbox_backpressure(&self) -> &Arc<InboxBackpressure> {
+        &self.inbox_backpressure
+    }
+
+    /// Get the dedup of repeated federation warnings
+    pub fn log_dedup(&self) -> &Arc<LogDedup> {
+        &self.log_dedup
+    }
+
+    /// Get the bus announcing changes to our documents
+    pub fn event_bus(&self) -> &Arc<EventBus> {
+        &self.event_bus
+    }
+
+    /// Get the cache of serialized actor and note documents
+    pub fn document_cache(&self) -> &Arc<DocumentCache> {
+        &self.document_cache
+    }
+
+    /// Get the classifier labelling local notes
+    pub fn content_classifier(&self) -> &Arc<dyn ContentClassifier> {
+        &self.content_classifier
+    }
+
+    /// Use another classifier for local notes
+    pub fn with_content_classifier(mut self, classifier: Arc<dyn ContentClassifier>) -> Self {
+        self.content_classifier = classifier;
+        self
+    }
+
+    /// Get the stream of stored inbox activities for live clients
+    pub fn inbox_stream(&self) -> &Arc<InboxStream> {
+        &self.inbox_stream
+    }
+
+    /// Get the client API's per-token and per-IP rate limits
+    pub fn api_rate_limiter(&self) -> &Arc<ApiRateLimiter> {
+        &self.api_rate_limiter
+    }
+
+    /// Get the remote actor fetcher
+    pub fn actor_fetch(&self) -> &Arc<ActorFetchService> {
+        &self.actor_fetch
+    }
+
+    /// Get the builder for server-generated URLs
+    pub fn urls(&self) -> &UrlBuilder {
+        &self.urls
+    }
+
+    /// The log of outgoing requests, for containers built in test mode
+    pub fn recording_http_client(&self) -> Option<&Arc<RecordingHttpClient>> {
+        self.recording_http_client.as_ref()
+    }
 }
 
 /// Builder pattern for creating containers with different configurations
@@ -77,6 +276,7 @@ pub struct ContainerBuilder {
     config: Option<Config>,
     database: Option<DatabaseRef>,
     http_client: Option<Arc<dyn HttpClient>>,
+    test_mode: bool,
 }
 
 #[allow(dead_code)]
@@ -86,6 +286,7 @@ impl ContainerBuilder {
             config: None,
             database: None,
             http_client: None,
+            test_mode: false,
         }
     }
 
@@ -104,10 +305,27 @@ impl ContainerBuilder {
         self
     }
 
+    /// Record every outgoing request, readable through
+    /// `Container::recording_http_client`
+    pub fn with_test_mode(mut self) -> Self {
+        self.test_mode = true;
+        self
+    }
+
     pub fn build(self) -> Result<Container, String> {
         let config = self.config.ok_or("Config is required")?;
         let database = self.database.ok_or("Database is required")?;
 
+        if self.test_mode {
+            let inner = self
+                .http_client
+                .unwrap_or_else(|| Arc::new(ReqwestClient::with_timeout(Duration::from_secs(30))));
+            let recording = Arc::new(RecordingHttpClient::new(inner));
+            let mut container = Container::with_http_client(config, database, recording.clone());
+            container.recording_http_client = Some(recording);
+            return Ok(container);
+        }
+
         match self.http_client {
             Some(http_client) => Ok(Container::with_http_client(config, database, http_client)),
             None => Ok(Container::new(config, database)),
@@ -152,6 +370,7 @@ mod tests {
             actor_name: "testuser".to_string(),
             private_key_path: None,
             public_key_path: None,
+            ..Config::default()
         }
     }
 
@@ -167,6 +386,21 @@ mod tests {
         assert_eq!(container.config().actor_name, config.actor_name);
     }
 
+    #[test]
+    fn test_container_capabilities_from_config() {
+        let config = Config {
+            max_note_length: 1000,
+            markdown_enabled: true,
+            ..create_test_config()
+        };
+        let database = Arc::new(create_configured_mock_database());
+        let container = Container::new(config, database);
+
+        assert_eq!(container.capabilities().api_version, 1);
+        assert!(container.capabilities().features.markdown);
+        assert_eq!(container.capabilities().limits.max_note_length, 1000);
+    }
+
     #[test]
     fn test_container_with_custom_http_client() {
         let config = create_test_config();
@@ -199,6 +433,46 @@ mod tests {
         assert_eq!(container.config().actor_name, config.actor_name);
     }
 
+    #[tokio::test]
+    async fn test_container_builder_test_mode_records_requests() {
+        let container = ContainerBuilder::new()
+            .with_config(create_test_config())
+            .with_database(Arc::new(create_configured_mock_database()))
+            .with_http_client(Arc::new(MockHttpClient))
+            .with_test_mode()
+            .build()
+            .unwrap();
+
+        container
+            .delivery_service()
+            .deliver_activity(
+                "https://remote.example/inbox",
+                serde_json::json!({"type": "Create"}),
+            )
+            .await
+            .unwrap();
+
+        let recording = container.recording_http_client().unwrap();
+        assert_eq!(
+            recording
+                .deliveries_to("https://remote.example/inbox")
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_container_builder_without_test_mode_does_not_record() {
+        let container = ContainerBuilder::new()
+            .with_config(create_test_config())
+            .with_database(Arc::new(create_configured_mock_database()))
+            .with_http_client(Arc::new(MockHttpClient))
+            .build()
+            .unwrap();
+
+        assert!(container.recording_http_client().is_none());
+    }
+
     #[test]
     fn test_container_builder_missing_config() {
         let database = Arc::new(create_configured_mock_database());
@@ -219,6 +493,32 @@ mod tests {
         assert_eq!(result.err().unwrap(), "Database is required");
     }
 
+    #[tokio::test]
+    async fn test_container_meters_database_when_enabled() {
+        for metrics_enabled in [true, false] {
+            let config = Config {
+                metrics_enabled,
+                ..create_test_config()
+            };
+            let database = Arc::new(create_configured_mock_database());
+            let container = Container::new(config, database);
+
+            container
+                .database()
+                .get_actor_outbox_count("https://test.example.com/users/testuser")
+                .await
+                .unwrap();
+
+            let recorded = container
+                .metrics()
+                .registry()
+                .gather()
+                .iter()
+                .any(|family| family.name() == "feder8_db_query_duration_seconds");
+            assert_eq!(recorded, metrics_enabled);
+        }
+    }
+
     #[test]
     fn test_container_clone() {
         let config = create_test_config();
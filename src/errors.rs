@@ -0,0 +1,260 @@
+use crate::database::DatabaseError;
+use crate::health::DatabaseUnavailable;
+use actix_web::http::{header, StatusCode};
+use actix_web::{HttpResponse, ResponseError};
+
+/// Errors handlers answer with. Each renders as `{"error", "code"}` JSON with
+/// a matching status; database and internal failures never leak details.
+#[derive(Debug, thiserror::Error)]
+pub enum FederationError {
+    #[error("Actor not found")]
+    ActorNotFound,
+    #[error("Note not found")]
+    NoteNotFound,
+    /// Some other resource, named in the message
+    #[error("{0}")]
+    NotFound(String),
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] DatabaseError),
+    #[error("Unauthorized")]
+    Unauthorized,
+    #[error("{0}")]
+    Forbidden(String),
+    /// The bearer token lacks the named scope
+    #[error("Missing required scope: {0}")]
+    InsufficientScope(String),
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    Conflict(String),
+    /// Well-formed but not something we can act on, e.g. an unsupported type
+    #[error("{0}")]
+    Unprocessable(String),
+    /// Too busy to take on more work; clients should retry after a while
+    #[error("Server is busy, try again later")]
+    Overloaded { retry_after_secs: u64 },
+    /// The sender has used up its share; it may send again after a while
+    #[error("Inbound quota exceeded, try again later")]
+    QuotaExceeded { retry_after_secs: u64 },
+    #[error("Internal server error")]
+    InternalError,
+}
+
+impl FederationError {
+    /// Stable machine-readable identifier for the `code` field
+    pub fn code(&self) -> &'static str {
+        match self {
+            FederationError::ActorNotFound => "actor_not_found",
+            FederationError::NoteNotFound => "note_not_found",
+            FederationError::NotFound(_) => "not_found",
+            FederationError::DatabaseError(e) if e.is_unavailable() => "service_unavailable",
+            FederationError::DatabaseError(_) => "database_error",
+            FederationError::Unauthorized => "unauthorized",
+            FederationError::Forbidden(_) => "forbidden",
+            FederationError::InsufficientScope(_) => "insufficient_scope",
+            FederationError::BadRequest(_) => "bad_request",
+            FederationError::Conflict(_) => "conflict",
+            FederationError::Unprocessable(_) => "unprocessable_entity",
+            FederationError::Overloaded { .. } => "overloaded",
+            FederationError::QuotaExceeded { .. } => "quota_exceeded",
+            FederationError::InternalError => "internal_error",
+        }
+    }
+
+    /// Message shown to clients
+    fn public_message(&self) -> String {
+        match self {
+            FederationError::DatabaseError(e) if e.is_unavailable() => {
+                "Service temporarily unavailable".to_string()
+            }
+            FederationError::DatabaseError(_) => "Internal server error".to_string(),
+            other => other.to_string(),
+        }
+    }
+}
+
+impl ResponseError for FederationError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            FederationError::ActorNotFound
+            | FederationError::NoteNotFound
+            | FederationError::NotFound(_) => StatusCode::NOT_FOUND,
+            FederationError::DatabaseError(e) if e.is_unavailable() => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            FederationError::DatabaseError(_) | FederationError::InternalError => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+            FederationError::Unauthorized => StatusCode::UNAUTHORIZED,
+            FederationError::Forbidden(_) | FederationError::InsufficientScope(_) => {
+                StatusCode::FORBIDDEN
+            }
+            FederationError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            FederationError::Conflict(_) => StatusCode::CONFLICT,
+            FederationError::Unprocessable(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            FederationError::Overloaded { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            FederationError::QuotaExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        match self {
+            FederationError::Unauthorized => {
+                response.insert_header((header::WWW_AUTHENTICATE, "Bearer"));
+            }
+            FederationError::Overloaded { retry_after_secs }
+            | FederationError::QuotaExceeded { retry_after_secs } => {
+                response.insert_header((header::RETRY_AFTER, retry_after_secs.to_string()));
+            }
+            // ServiceUnavailable adds the configured Retry-After
+            FederationError::DatabaseError(e) if e.is_unavailable() => {
+                response.extensions_mut().insert(DatabaseUnavailable);
+            }
+            _ => {}
+        }
+        let mut body = serde_json::json!({
+            "error": self.public_message(),
+            "code": self.code()
+        });
+        if let FederationError::InsufficientScope(scope) = self {
+            body["required_scope"] = scope.as_str().into();
+        }
+        response.json(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::body::to_bytes;
+    use serde_json::Value;
+
+    async fn body_of(error: FederationError) -> (StatusCode, Value) {
+        let response = error.error_response();
+        let status = response.status();
+        let body = to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_each_variant_has_its_status_and_code() {
+        let cases = [
+            (FederationError::ActorNotFound, 404, "actor_not_found"),
+            (FederationError::NoteNotFound, 404, "note_not_found"),
+            (
+                FederationError::NotFound("Follow not found".into()),
+                404,
+                "not_found",
+            ),
+            (
+                FederationError::DatabaseError(DatabaseError::Query("boom".into())),
+                500,
+                "database_error",
+            ),
+            (
+                FederationError::DatabaseError(DatabaseError::Connection("pool timed out".into())),
+                503,
+                "service_unavailable",
+            ),
+            (FederationError::Unauthorized, 401, "unauthorized"),
+            (FederationError::Forbidden("No".into()), 403, "forbidden"),
+            (
+                FederationError::InsufficientScope("write:statuses".into()),
+                403,
+                "insufficient_scope",
+            ),
+            (
+                FederationError::BadRequest("Bad".into()),
+                400,
+                "bad_request",
+            ),
+            (FederationError::Conflict("Again".into()), 409, "conflict"),
+            (
+                FederationError::Unprocessable("Odd".into()),
+                422,
+                "unprocessable_entity",
+            ),
+            (
+                FederationError::Overloaded {
+                    retry_after_secs: 30,
+                },
+                503,
+                "overloaded",
+            ),
+            (
+                FederationError::QuotaExceeded {
+                    retry_after_secs: 60,
+                },
+                429,
+                "quota_exceeded",
+            ),
+            (FederationError::InternalError, 500, "internal_error"),
+        ];
+
+        for (error, status, code) in cases {
+            let (actual_status, body) = body_of(error).await;
+            assert_eq!(actual_status.as_u16(), status, "{code}");
+            assert_eq!(body["code"], code);
+            assert!(body["error"].is_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_messages() {
+        let (_, body) = body_of(FederationError::ActorNotFound).await;
+        assert_eq!(body["error"], "Actor not found");
+
+        let (_, body) = body_of(FederationError::BadRequest("Missing activity type".into())).await;
+        assert_eq!(body["error"], "Missing activity type");
+    }
+
+    #[tokio::test]
+    async fn test_database_errors_do_not_leak_details() {
+        let error = FederationError::from(DatabaseError::Query("syntax error near users".into()));
+        assert!(error.to_string().contains("syntax error"));
+
+        let (_, body) = body_of(error).await;
+        assert_eq!(body["error"], "Internal server error");
+
+        let error = FederationError::from(DatabaseError::Connection("pool timed out".into()));
+        let (_, body) = body_of(error).await;
+        assert_eq!(body["error"], "Service temporarily unavailable");
+    }
+
+    #[test]
+    fn test_unavailable_database_marks_the_response() {
+        let response =
+            FederationError::from(DatabaseError::Connection("database is locked".into()))
+                .error_response();
+        assert!(response.extensions().get::<DatabaseUnavailable>().is_some());
+
+        let response =
+            FederationError::from(DatabaseError::Query("no such table".into())).error_response();
+        assert!(response.extensions().get::<DatabaseUnavailable>().is_none());
+    }
+
+    #[test]
+    fn test_unauthorized_asks_for_bearer_token() {
+        let response = FederationError::Unauthorized.error_response();
+        assert_eq!(
+            response.headers().get(header::WWW_AUTHENTICATE).unwrap(),
+            "Bearer"
+        );
+    }
+
+    #[test]
+    fn test_overloaded_says_when_to_retry() {
+        let response = FederationError::Overloaded {
+            retry_after_secs: 30,
+        }
+        .error_response();
+        assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "30");
+
+        let response = FederationError::QuotaExceeded {
+            retry_after_secs: 60,
+        }
+        .error_response();
+        assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "60");
+    }
+}
@@ -1,43 +1,217 @@
-use crate::config::Config;
-use crate::database::DatabaseRef;
-use crate::models::Actor;
-use actix_web::{get, web, HttpResponse, Result};
-use tracing::warn;
+use crate::database::{DatabaseRef, DbActorSummary, DbNote, PublishState};
+use crate::errors::FederationError;
+use crate::handlers::html::{escape_html, render_note_article, wants_html};
+use crate::handlers::parse_failure_recorder;
+use crate::http::{caching, content_type, HttpClient};
+use crate::models::{Actor, Visibility};
+use crate::services::actor_profiles;
+use crate::services::document_cache::DocumentCache;
+use crate::urls::UrlBuilder;
+use actix_web::http::header;
+use actix_web::{get, web, HttpRequest, HttpResponse, Result};
+use tracing::{instrument, warn};
 
+/// Followers listed on the HTML profile
+const PROFILE_FOLLOWERS_LIMIT: u32 = 20;
+
+/// Recent notes looked at for the HTML profile; only public ones are shown
+const PROFILE_NOTES_LIMIT: u32 = 20;
+
+/// An actor document or, for browsers, an HTML profile. Documents are served
+/// from the cache when it has them, without touching the database.
 #[get("/users/{username}")]
+#[instrument(skip(req, urls, db, http_client, cache))]
 pub async fn get_actor(
+    req: HttpRequest,
     path: web::Path<String>,
-    config: web::Data<Config>,
+    urls: web::Data<UrlBuilder>,
     db: web::Data<DatabaseRef>,
+    http_client: web::Data<dyn HttpClient>,
+    cache: web::Data<DocumentCache>,
 ) -> Result<HttpResponse> {
     let username = path.into_inner();
+    let wants_html = wants_html(&req);
+
+    if !wants_html {
+        if let Some(document) = cache.get(&urls.actor(&username)) {
+            let mut response = HttpResponse::Ok();
+            response
+                .insert_header((header::VARY, "Accept"))
+                .content_type(content_type::negotiate_request(&req).as_str());
+            return Ok(caching::respond_with_etag(
+                &req,
+                response,
+                document.body,
+                document.etag,
+                document.last_modified,
+            ));
+        }
+    }
 
     // Load actor from database
     match db.get_actor_by_username(&username).await {
         Ok(Some(db_actor)) => {
-            let actor = Actor::new(
+            let summary = db_actor.summary.clone();
+            let last_modified = Some(db_actor.updated_at);
+            let published = db_actor.created_at;
+            let actor_type = db_actor.actor_type.clone();
+            let mut actor = Actor::new(
                 db_actor.id.clone(),
                 db_actor.name,
                 db_actor.username,
-                &config.server_url,
+                &urls,
                 db_actor.public_key_pem,
-            );
+            )
+            .with_migration(db_actor.moved_to, db_actor.also_known_as);
+            actor.actor_type = actor_type;
+            // A stable body keeps the ETag stable between requests
+            actor.published = published;
+
+            let mut response = HttpResponse::Ok();
+            response.insert_header((header::VARY, "Accept"));
 
-            Ok(HttpResponse::Ok()
-                .content_type("application/activity+json")
-                .json(actor))
+            if wants_html {
+                let followers = actor_profiles::followers_with_profiles(
+                    &db,
+                    &http_client.into_inner(),
+                    &parse_failure_recorder(&req, &db),
+                    &actor.id,
+                    PROFILE_FOLLOWERS_LIMIT,
+                    0,
+                )
+                .await
+                .unwrap_or_else(|e| {
+                    warn!(
+                        "Database error while listing followers of {}: {}",
+                        username, e
+                    );
+                    Vec::new()
+                });
+
+                let notes: Vec<DbNote> = db
+                    .get_notes_by_actor(&actor.id, PROFILE_NOTES_LIMIT, 0)
+                    .await
+                    .unwrap_or_else(|e| {
+                        warn!("Database error while listing notes of {}: {}", username, e);
+                        Vec::new()
+                    })
+                    .into_iter()
+                    .filter(|note| {
+                        note.state == PublishState::Published
+                            && note.visibility == Visibility::Public
+                    })
+                    .collect();
+                // The page changes whenever a note is posted, not just the profile
+                let last_modified = notes
+                    .iter()
+                    .map(|note| note.published)
+                    .chain(last_modified)
+                    .max();
+
+                response.content_type("text/html; charset=utf-8");
+                let body = render_profile_html(&actor, summary.as_deref(), &notes, &followers);
+                return Ok(caching::respond(
+                    &req,
+                    response,
+                    body.into_bytes(),
+                    last_modified,
+                ));
+            }
+
+            response.content_type(content_type::negotiate_request(&req).as_str());
+            let body = serde_json::to_vec(&actor)?;
+            let document = cache.insert(&actor.id, None, body, last_modified);
+            Ok(caching::respond_with_etag(
+                &req,
+                response,
+                document.body,
+                document.etag,
+                document.last_modified,
+            ))
         }
         Ok(None) => {
             warn!("Actor not found: {}", username);
-            Ok(HttpResponse::NotFound().json(serde_json::json!({
-                "error": "Actor not found"
-            })))
+            Err(FederationError::ActorNotFound.into())
         }
         Err(e) => {
             warn!("Database error while fetching actor {}: {}", username, e);
-            Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Internal server error"
-            })))
+            Err(FederationError::DatabaseError(e).into())
         }
     }
 }
+
+fn render_profile_html(
+    actor: &Actor,
+    summary: Option<&str>,
+    notes: &[DbNote],
+    followers: &[DbActorSummary],
+) -> String {
+    let name = escape_html(&actor.name);
+    let username = escape_html(&actor.preferred_username);
+    let summary = summary
+        .map(|s| format!("\n  <p class=\"summary\">{}</p>", escape_html(s)))
+        .unwrap_or_default();
+    let notes = render_notes_html(notes);
+    let followers = render_followers_html(followers);
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>{name} (@{username})</title>
+  <link rel="alternate" type="application/activity+json" href="{id}">
+</head>
+<body>
+  <h1>{name}</h1>
+  <p class="username">@{username}</p>{summary}
+  <p><a href="{outbox}">Outbox</a></p>{notes}{followers}
+</body>
+</html>
+"#,
+        id = escape_html(&actor.id),
+        outbox = escape_html(&actor.outbox),
+    )
+}
+
+fn render_notes_html(notes: &[DbNote]) -> String {
+    if notes.is_empty() {
+        return String::new();
+    }
+
+    let articles: String = notes
+        .iter()
+        .map(|note| format!("\n{}", render_note_article(note, "    ")))
+        .collect();
+
+    format!("\n  <h2>Posts</h2>\n  <section class=\"notes\">{articles}\n  </section>")
+}
+
+fn render_followers_html(followers: &[DbActorSummary]) -> String {
+    if followers.is_empty() {
+        return String::new();
+    }
+
+    let items: String = followers
+        .iter()
+        .map(|follower| {
+            let acct = escape_html(&actor_profiles::acct(follower));
+            let avatar = follower
+                .avatar_url
+                .as_deref()
+                .map(|url| format!("<img src=\"{}\" alt=\"\"> ", escape_html(url)))
+                .unwrap_or_default();
+            let name = follower
+                .name
+                .as_deref()
+                .map(|name| format!("{} ", escape_html(name)))
+                .unwrap_or_default();
+            format!(
+                "\n    <li><a href=\"{}\">{avatar}{name}@{acct}</a></li>",
+                escape_html(&follower.id)
+            )
+        })
+        .collect();
+
+    format!("\n  <h2>Followers</h2>\n  <ul class=\"followers\">{items}\n  </ul>")
+}
@@ -0,0 +1,168 @@
+use super::{AdminAuth, PageQuery};
+use crate::database::{DatabaseRef, DbActor};
+use crate::errors::FederationError;
+use crate::services::events::{EventBus, ObjectEvent};
+use crate::services::groups::{GROUP_ACTOR_TYPE, PERSON_ACTOR_TYPE};
+use crate::services::keys::KeyManager;
+use crate::urls::UrlBuilder;
+use actix_web::{delete, get, post, web, HttpResponse, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{info, instrument, warn};
+
+#[derive(Debug, Deserialize)]
+pub struct CreateActorRequest {
+    pub username: String,
+    pub name: Option<String>,
+    pub summary: Option<String>,
+    /// `Person` unless given; `Group` makes a group that boosts mentions
+    #[serde(rename = "type")]
+    pub actor_type: Option<String>,
+}
+
+/// Admin view of an actor; never includes the private key
+fn actor_summary(actor: &DbActor) -> Value {
+    serde_json::json!({
+        "id": actor.id,
+        "username": actor.username,
+        "name": actor.name,
+        "summary": actor.summary,
+        "type": actor.actor_type,
+        "public_key_pem": actor.public_key_pem,
+        "created_at": actor.created_at,
+        "updated_at": actor.updated_at
+    })
+}
+
+fn is_valid_username(username: &str) -> bool {
+    !username.is_empty()
+        && username.len() <= 64
+        && username
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+#[get("/api/admin/actors")]
+#[instrument(skip(_auth, db))]
+pub async fn list_actors(
+    _auth: AdminAuth,
+    query: web::Query<PageQuery>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let (limit, offset) = (query.limit(), query.offset());
+
+    match db.list_actors(limit, offset).await {
+        Ok(actors) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "actors": actors.iter().map(actor_summary).collect::<Vec<_>>(),
+            "limit": limit,
+            "offset": offset
+        }))),
+        Err(e) => {
+            warn!("Database error while listing actors: {}", e);
+            Err(FederationError::DatabaseError(e).into())
+        }
+    }
+}
+
+#[post("/api/admin/actors")]
+#[instrument(skip(_auth, urls, db, key_manager))]
+pub async fn create_actor(
+    _auth: AdminAuth,
+    payload: web::Json<CreateActorRequest>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+    key_manager: web::Data<KeyManager>,
+) -> Result<HttpResponse> {
+    let request = payload.into_inner();
+
+    if !is_valid_username(&request.username) {
+        return Err(FederationError::BadRequest("Invalid username".to_string()).into());
+    }
+
+    let actor_type = request
+        .actor_type
+        .clone()
+        .unwrap_or_else(|| PERSON_ACTOR_TYPE.to_string());
+    if actor_type != PERSON_ACTOR_TYPE && actor_type != GROUP_ACTOR_TYPE {
+        return Err(
+            FederationError::BadRequest(format!("Unsupported actor type: {actor_type}")).into(),
+        );
+    }
+
+    match db.get_actor_by_username(&request.username).await {
+        Ok(Some(_)) => {
+            return Err(FederationError::Conflict("Actor already exists".to_string()).into());
+        }
+        Ok(None) => {}
+        Err(e) => {
+            warn!(
+                "Database error while checking actor {}: {}",
+                request.username, e
+            );
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    }
+
+    let now = chrono::Utc::now();
+    let mut actor = DbActor {
+        id: urls.actor(&request.username),
+        name: request.name.unwrap_or_else(|| request.username.clone()),
+        username: request.username,
+        summary: request.summary,
+        public_key_pem: String::new(),
+        private_key_pem: None,
+        is_admin: false,
+        actor_type,
+        moved_to: None,
+        also_known_as: Vec::new(),
+        created_at: now,
+        updated_at: now,
+    };
+
+    if let Err(e) = key_manager.ensure_keys(&mut actor) {
+        warn!("Key generation failed for {}: {}", actor.id, e);
+        return Err(FederationError::InternalError.into());
+    }
+
+    if let Err(e) = db.create_actor(&actor).await {
+        warn!("Database error while creating actor {}: {}", actor.id, e);
+        return Err(FederationError::DatabaseError(e).into());
+    }
+
+    info!("Created local actor {}", actor.id);
+    Ok(HttpResponse::Created().json(actor_summary(&actor)))
+}
+
+/// Remove a local actor by id, percent-encoded in the path. Its tokens,
+/// blocks and preferences go through cascading foreign keys, and its notes,
+/// activities and follows through `AFTER DELETE` triggers on `actors`.
+#[delete("/api/admin/actors/{id}")]
+#[instrument(skip(_auth, db, events))]
+pub async fn delete_actor(
+    _auth: AdminAuth,
+    path: web::Path<String>,
+    db: web::Data<DatabaseRef>,
+    events: web::Data<EventBus>,
+) -> Result<HttpResponse> {
+    let id = path.into_inner();
+
+    let actor = match db.get_actor_by_id(&id).await {
+        Ok(Some(actor)) => actor,
+        Ok(None) => {
+            return Err(FederationError::ActorNotFound.into());
+        }
+        Err(e) => {
+            warn!("Database error while fetching actor {}: {}", id, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    if let Err(e) = db.delete_actor(&actor.id).await {
+        warn!("Database error while deleting actor {}: {}", actor.id, e);
+        return Err(FederationError::DatabaseError(e).into());
+    }
+
+    events.publish(ObjectEvent::ActorDeleted(actor.id.clone()));
+    info!("Deleted local actor {}", actor.id);
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,51 @@
+use super::AdminAuth;
+use crate::container::Container;
+use crate::database::{open_database_at, DatabaseError};
+use crate::errors::FederationError;
+use crate::services::delivery_queue::DELIVERY_JOB;
+use crate::services::scheduler::Scheduler;
+use actix_web::{post, web, HttpResponse, Result};
+use serde::Deserialize;
+use tracing::{info, instrument, warn};
+
+#[derive(Debug, Deserialize)]
+pub struct SwapDatabaseRequest {
+    pub database_url: String,
+}
+
+/// Repoint the server at another database without a restart. The target's
+/// backend follows its URL scheme, and it is migrated to the latest schema
+/// and must answer a query before anything changes; the delivery worker is
+/// drained and paused for the switch so no run straddles two databases.
+#[post("/api/admin/database/swap")]
+#[instrument(skip(_auth, payload, container, scheduler))]
+pub async fn swap_database(
+    _auth: AdminAuth,
+    payload: web::Json<SwapDatabaseRequest>,
+    container: web::Data<Container>,
+    scheduler: web::Data<Scheduler>,
+) -> Result<HttpResponse> {
+    let target = match open_database_at(&payload.database_url, container.config()).await {
+        Ok(db) => db,
+        Err(e) => {
+            warn!("Could not connect to new database: {}", e);
+            return Err(unreachable_target(&e).into());
+        }
+    };
+    if let Err(e) = target.ping().await {
+        warn!("New database did not answer: {}", e);
+        return Err(unreachable_target(&e).into());
+    }
+
+    scheduler.pause(DELIVERY_JOB);
+    scheduler.wait_idle(DELIVERY_JOB).await;
+    container.swap_database(target);
+    scheduler.resume(DELIVERY_JOB);
+
+    info!("Switched to new database");
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "swapped": true })))
+}
+
+fn unreachable_target(error: &DatabaseError) -> FederationError {
+    FederationError::BadRequest(format!("New database is not reachable: {error}"))
+}
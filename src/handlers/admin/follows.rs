@@ -0,0 +1,101 @@
+use super::{AdminAuth, PageQuery};
+use crate::database::{DatabaseRef, DbFollowRelation};
+use crate::errors::FederationError;
+use crate::urls::UrlBuilder;
+use actix_web::{get, post, web, HttpResponse, Result};
+use serde_json::Value;
+use tracing::{info, instrument, warn};
+
+fn follow_summary(follow: &DbFollowRelation) -> Value {
+    serde_json::json!({
+        "id": follow.id,
+        "follower_id": follow.follower_id,
+        "following_id": follow.following_id,
+        "status": follow.status,
+        "created_at": follow.created_at,
+        "updated_at": follow.updated_at
+    })
+}
+
+#[get("/api/admin/follows")]
+#[instrument(skip(_auth, db))]
+pub async fn list_pending_follows(
+    _auth: AdminAuth,
+    query: web::Query<PageQuery>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let (limit, offset) = (query.limit(), query.offset());
+
+    match db.get_pending_follows(limit, offset).await {
+        Ok(follows) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "follows": follows.iter().map(follow_summary).collect::<Vec<_>>(),
+            "limit": limit,
+            "offset": offset
+        }))),
+        Err(e) => {
+            warn!("Database error while listing pending follows: {}", e);
+            Err(FederationError::DatabaseError(e).into())
+        }
+    }
+}
+
+#[post("/api/admin/follows/{id}/accept")]
+#[instrument(skip(_auth, urls, db))]
+pub async fn accept_follow(
+    _auth: AdminAuth,
+    path: web::Path<String>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    set_follow_status(&path.into_inner(), "accepted", &urls, &db).await
+}
+
+#[post("/api/admin/follows/{id}/reject")]
+#[instrument(skip(_auth, urls, db))]
+pub async fn reject_follow(
+    _auth: AdminAuth,
+    path: web::Path<String>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    set_follow_status(&path.into_inner(), "rejected", &urls, &db).await
+}
+
+/// Resolve a pending follow from the last segment of its id and update it
+async fn set_follow_status(
+    id: &str,
+    status: &str,
+    urls: &UrlBuilder,
+    db: &DatabaseRef,
+) -> Result<HttpResponse> {
+    let follow_id = urls.follow(id);
+
+    let follow = match db.get_follow_by_id(&follow_id).await {
+        Ok(Some(follow)) => follow,
+        Ok(None) => {
+            return Err(FederationError::NotFound("Follow not found".to_string()).into());
+        }
+        Err(e) => {
+            warn!("Database error while fetching follow {}: {}", follow_id, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    if follow.status != "pending" {
+        return Err(
+            FederationError::Conflict(format!("Follow is already {}", follow.status)).into(),
+        );
+    }
+
+    if let Err(e) = db.update_follow_status(&follow_id, status).await {
+        warn!("Database error while updating follow {}: {}", follow_id, e);
+        return Err(FederationError::DatabaseError(e).into());
+    }
+
+    info!("Follow {} marked {}", follow_id, status);
+    Ok(HttpResponse::Ok().json(follow_summary(&DbFollowRelation {
+        status: status.to_string(),
+        updated_at: chrono::Utc::now(),
+        ..follow
+    })))
+}
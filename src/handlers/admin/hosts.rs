@@ -0,0 +1,35 @@
+use super::AdminAuth;
+use crate::services::backpressure::InboxBackpressure;
+use actix_web::{get, web, HttpResponse, Result};
+use serde_json::json;
+use tracing::instrument;
+
+/// What each remote address has sent the inbox over the last hour, against
+/// its quota, heaviest first
+#[get("/api/admin/hosts")]
+#[instrument(skip(_auth, backpressure))]
+pub async fn list_hosts(
+    _auth: AdminAuth,
+    backpressure: web::Data<InboxBackpressure>,
+) -> Result<HttpResponse> {
+    let usage = backpressure.usage();
+    let quota = usage.quota_bytes();
+
+    let hosts: Vec<_> = usage
+        .by_host(chrono::Utc::now())
+        .into_iter()
+        .map(|(host, bytes)| {
+            json!({
+                "host": host,
+                "bytes": bytes,
+                "remaining": (quota > 0).then(|| quota.saturating_sub(bytes)),
+            })
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(json!({
+        "quota_bytes": (quota > 0).then_some(quota),
+        "window_secs": crate::services::inbound_usage::window().num_seconds(),
+        "hosts": hosts,
+    })))
+}
@@ -0,0 +1,12 @@
+use super::AdminAuth;
+use crate::services::scheduler::Scheduler;
+use actix_web::{get, web, HttpResponse, Result};
+use tracing::instrument;
+
+#[get("/api/admin/jobs")]
+#[instrument(skip(_auth, scheduler))]
+pub async fn list_jobs(_auth: AdminAuth, scheduler: web::Data<Scheduler>) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "jobs": scheduler.statuses()
+    })))
+}
@@ -0,0 +1,80 @@
+pub mod actors;
+pub mod database;
+pub mod follows;
+pub mod hosts;
+pub mod jobs;
+pub mod parse_failures;
+pub mod relays;
+pub mod stats;
+pub mod tokens;
+pub mod traces;
+
+use crate::auth::{authorize, ADMIN_SCOPE};
+use crate::config::Config;
+use actix_web::{dev::Payload, web, FromRequest, HttpRequest};
+use serde::Deserialize;
+use std::future::{ready, Future};
+use std::pin::Pin;
+use subtle::ConstantTimeEq;
+use tracing::warn;
+
+/// Default and maximum page sizes for admin listings
+const DEFAULT_PAGE_SIZE: u32 = 20;
+const MAX_PAGE_SIZE: u32 = 100;
+
+/// Extractor guarding admin routes. Accepts either the static `ADMIN_TOKEN`
+/// or a scoped token carrying the `admin` scope issued to an admin actor.
+pub struct AdminAuth;
+
+impl FromRequest for AdminAuth {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut Payload) -> Self::Future {
+        let expected = req
+            .app_data::<web::Data<Config>>()
+            .and_then(|config| config.admin_token.clone());
+
+        let provided = req
+            .headers()
+            .get("Authorization")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "));
+
+        if let (Some(expected), Some(provided)) = (expected, provided) {
+            // Constant time, so response timing doesn't give the token away
+            if bool::from(expected.as_bytes().ct_eq(provided.as_bytes())) {
+                return Box::pin(ready(Ok(AdminAuth)));
+            }
+        }
+
+        let req = req.clone();
+        Box::pin(async move {
+            match authorize(&req, ADMIN_SCOPE).await {
+                Ok(_) => Ok(AdminAuth),
+                Err(e) => {
+                    warn!("Admin request rejected: {}", e);
+                    Err(e.into())
+                }
+            }
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PageQuery {
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+}
+
+impl PageQuery {
+    pub fn limit(&self) -> u32 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+            .clamp(1, MAX_PAGE_SIZE)
+    }
+
+    pub fn offset(&self) -> u32 {
+        self.offset.unwrap_or(0)
+    }
+}
@@ -0,0 +1,79 @@
+use super::{AdminAuth, PageQuery};
+use crate::database::{DatabaseRef, DbParseFailure};
+use crate::errors::FederationError;
+use actix_web::http::header::{self, ContentDisposition, DispositionParam, DispositionType};
+use actix_web::{get, web, HttpResponse, Result};
+use serde_json::{json, Value};
+use tracing::{instrument, warn};
+
+/// Admin view of a parse failure, without the document itself
+fn failure_summary(failure: &DbParseFailure) -> Value {
+    json!({
+        "id": failure.id,
+        "kind": failure.kind,
+        "url": failure.url,
+        "error_path": failure.error_path,
+        "error": failure.error,
+        "document_bytes": failure.document.len(),
+        "truncated": failure.truncated,
+        "created_at": failure.created_at
+    })
+}
+
+/// Remote documents that recently failed to parse, newest first
+#[get("/api/admin/parse-failures")]
+#[instrument(skip(_auth, db))]
+pub async fn list_parse_failures(
+    _auth: AdminAuth,
+    query: web::Query<PageQuery>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let (limit, offset) = (query.limit(), query.offset());
+
+    match db.get_parse_failures(limit, offset).await {
+        Ok(failures) => Ok(HttpResponse::Ok().json(json!({
+            "parse_failures": failures.iter().map(failure_summary).collect::<Vec<_>>(),
+            "limit": limit,
+            "offset": offset
+        }))),
+        Err(e) => {
+            warn!("Database error while listing parse failures: {}", e);
+            Err(FederationError::DatabaseError(e).into())
+        }
+    }
+}
+
+/// The raw document behind a parse failure, as a download
+#[get("/api/admin/parse-failures/{id}")]
+#[instrument(skip(_auth, db))]
+pub async fn download_parse_failure(
+    _auth: AdminAuth,
+    path: web::Path<String>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let id = path.into_inner();
+
+    let failure = match db.get_parse_failure(&id).await {
+        Ok(Some(failure)) => failure,
+        Ok(None) => {
+            return Err(FederationError::NotFound(format!("Parse failure {id} not found")).into())
+        }
+        Err(e) => {
+            warn!("Database error while fetching parse failure {}: {}", id, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(format!(
+                "{}-{}.json",
+                failure.kind, failure.id
+            ))],
+        })
+        .insert_header(("X-Parse-Error-Path", failure.error_path))
+        .insert_header((header::CACHE_CONTROL, "no-store"))
+        .body(failure.document))
+}
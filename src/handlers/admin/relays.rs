@@ -0,0 +1,135 @@
+use super::AdminAuth;
+use crate::config::Config;
+use crate::database::{DatabaseRef, DbRelay};
+use crate::errors::FederationError;
+use crate::services::addressing::is_http_url;
+use crate::services::delivery::DeliveryService;
+use crate::services::relay::RelayService;
+use crate::urls::UrlBuilder;
+use actix_web::{delete, get, post, web, HttpResponse, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{instrument, warn};
+
+#[derive(Debug, Deserialize)]
+pub struct SubscribeRelayRequest {
+    /// The relay's inbox, e.g. `https://relay.example/inbox`
+    pub inbox_url: String,
+    /// Local actor the relay delivers to; the configured actor unless given
+    pub username: Option<String>,
+}
+
+fn relay_summary(relay: &DbRelay) -> Value {
+    serde_json::json!({
+        "id": relay.id,
+        "inbox_url": relay.inbox_url,
+        "actor_id": relay.actor_id,
+        "follow_id": relay.follow_id,
+        "status": relay.status,
+        "created_at": relay.created_at
+    })
+}
+
+#[get("/api/admin/relays")]
+#[instrument(skip(_auth, db))]
+pub async fn list_relays(_auth: AdminAuth, db: web::Data<DatabaseRef>) -> Result<HttpResponse> {
+    match db.list_relays().await {
+        Ok(relays) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "relays": relays.iter().map(relay_summary).collect::<Vec<_>>()
+        }))),
+        Err(e) => {
+            warn!("Database error while listing relays: {}", e);
+            Err(FederationError::DatabaseError(e).into())
+        }
+    }
+}
+
+/// Subscribe a local actor to a relay by sending it a Follow of Public. The
+/// subscription stays `pending` until the relay Accepts.
+#[post("/api/admin/relays")]
+#[instrument(skip(_auth, config, urls, db, delivery))]
+pub async fn subscribe_relay(
+    _auth: AdminAuth,
+    payload: web::Json<SubscribeRelayRequest>,
+    config: web::Data<Config>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+    delivery: web::Data<DeliveryService>,
+) -> Result<HttpResponse> {
+    let request = payload.into_inner();
+    if !is_http_url(&request.inbox_url) {
+        return Err(FederationError::BadRequest("Invalid relay inbox URL".to_string()).into());
+    }
+
+    let username = request.username.as_deref().unwrap_or(&config.actor_name);
+    let actor = match db.get_actor_by_username(username).await {
+        Ok(Some(actor)) => actor,
+        Ok(None) => return Err(FederationError::ActorNotFound.into()),
+        Err(e) => {
+            warn!("Database error while fetching actor {}: {}", username, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let mut relays = match RelayService::load(db.get_ref().clone(), delivery.into_inner()).await {
+        Ok(relays) => relays,
+        Err(e) => {
+            warn!("Database error while loading relays: {}", e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+    if relays.is_subscribed(&request.inbox_url) {
+        return Err(
+            FederationError::Conflict("Already subscribed to this relay".to_string()).into(),
+        );
+    }
+
+    match relays.subscribe(&request.inbox_url, &actor.id, &urls).await {
+        Ok(relay) => Ok(HttpResponse::Created().json(relay_summary(&relay))),
+        Err(e) => {
+            warn!(
+                "Database error while subscribing to relay {}: {}",
+                request.inbox_url, e
+            );
+            Err(FederationError::DatabaseError(e).into())
+        }
+    }
+}
+
+/// End a relay subscription, sending the relay an Undo of its Follow
+#[delete("/api/admin/relays/{id}")]
+#[instrument(skip(_auth, urls, db, delivery))]
+pub async fn unsubscribe_relay(
+    _auth: AdminAuth,
+    path: web::Path<String>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+    delivery: web::Data<DeliveryService>,
+) -> Result<HttpResponse> {
+    let id = path.into_inner();
+
+    let relay = match db.get_relay(&id).await {
+        Ok(Some(relay)) => relay,
+        Ok(None) => {
+            return Err(FederationError::NotFound("Relay not found".to_string()).into());
+        }
+        Err(e) => {
+            warn!("Database error while fetching relay {}: {}", id, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let mut relays = match RelayService::load(db.get_ref().clone(), delivery.into_inner()).await {
+        Ok(relays) => relays,
+        Err(e) => {
+            warn!("Database error while loading relays: {}", e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+    if let Err(e) = relays.unsubscribe(&relay, &urls).await {
+        warn!("Database error while deleting relay {}: {}", id, e);
+        return Err(FederationError::DatabaseError(e).into());
+    }
+
+    Ok(HttpResponse::NoContent().finish())
+}
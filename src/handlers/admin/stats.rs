@@ -0,0 +1,49 @@
+use super::AdminAuth;
+use crate::database::DatabaseRef;
+use crate::errors::FederationError;
+use crate::services::delivery_queue;
+use actix_web::{get, web, HttpResponse, Result};
+use serde_json::json;
+use tracing::{instrument, warn};
+
+/// Operational numbers for alerting: how far behind the delivery queue is,
+/// and which hosts it is waiting on
+#[get("/api/admin/stats")]
+#[instrument(skip(_auth, db))]
+pub async fn get_stats(_auth: AdminAuth, db: web::Data<DatabaseRef>) -> Result<HttpResponse> {
+    let backlog = match delivery_queue::backlog(&db, chrono::Utc::now()).await {
+        Ok(backlog) => backlog,
+        Err(e) => {
+            warn!("Database error while measuring the delivery queue: {}", e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let by_host: Vec<_> = backlog
+        .by_host
+        .iter()
+        .map(|host| {
+            json!({
+                "host": host.host,
+                "pending": host.pending,
+                "oldest": host.oldest,
+            })
+        })
+        .collect();
+
+    let by_priority: serde_json::Map<_, _> = backlog
+        .by_priority
+        .iter()
+        .map(|(priority, pending)| (priority.as_str().to_string(), json!(pending)))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(json!({
+        "delivery_queue": {
+            "pending": backlog.pending,
+            "by_priority": by_priority,
+            "oldest_age_secs": backlog.oldest.as_ref().map(|oldest| oldest.age.num_seconds()),
+            "oldest_inbox": backlog.oldest.as_ref().map(|oldest| &oldest.inbox_url),
+            "by_host": by_host,
+        }
+    })))
+}
@@ -0,0 +1,76 @@
+use super::AdminAuth;
+use crate::auth::{generate_token, hash_token};
+use crate::database::{DatabaseRef, DbToken};
+use crate::errors::FederationError;
+use actix_web::{post, web, HttpResponse, Result};
+use serde::Deserialize;
+use tracing::{info, instrument, warn};
+
+#[derive(Debug, Deserialize)]
+pub struct IssueTokenRequest {
+    pub username: String,
+    pub scopes: Vec<String>,
+    /// Lifetime in seconds; tokens without one never expire
+    pub expires_in: Option<i64>,
+}
+
+/// Issue a bearer token for a local actor. The plaintext is only ever
+/// returned here; the database keeps its hash.
+#[post("/api/admin/tokens")]
+#[instrument(skip(_auth, payload, db), fields(username = %payload.username))]
+pub async fn issue_token(
+    _auth: AdminAuth,
+    payload: web::Json<IssueTokenRequest>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let request = payload.into_inner();
+
+    if request.scopes.is_empty() || request.scopes.iter().any(|scope| scope.trim().is_empty()) {
+        return Err(
+            FederationError::BadRequest("At least one scope is required".to_string()).into(),
+        );
+    }
+    if request.expires_in.is_some_and(|secs| secs <= 0) {
+        return Err(FederationError::BadRequest("expires_in must be positive".to_string()).into());
+    }
+
+    let actor = match db.get_actor_by_username(&request.username).await {
+        Ok(Some(actor)) => actor,
+        Ok(None) => return Err(FederationError::ActorNotFound.into()),
+        Err(e) => {
+            warn!(
+                "Database error while fetching actor {}: {}",
+                request.username, e
+            );
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let plaintext = generate_token();
+    let now = chrono::Utc::now();
+    let token = DbToken {
+        id: uuid::Uuid::new_v4().to_string(),
+        token_hash: hash_token(&plaintext),
+        actor_id: actor.id,
+        scopes: request.scopes,
+        created_at: now,
+        expires_at: request
+            .expires_in
+            .map(|secs| now + chrono::Duration::seconds(secs)),
+    };
+
+    if let Err(e) = db.create_token(&token).await {
+        warn!("Database error while issuing token: {}", e);
+        return Err(FederationError::DatabaseError(e).into());
+    }
+
+    info!("Issued token {} for {}", token.id, token.actor_id);
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "id": token.id,
+        "token": plaintext,
+        "actor_id": token.actor_id,
+        "scopes": token.scopes,
+        "created_at": token.created_at,
+        "expires_at": token.expires_at
+    })))
+}
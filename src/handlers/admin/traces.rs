@@ -0,0 +1,53 @@
+use super::AdminAuth;
+use crate::database::DatabaseRef;
+use crate::errors::FederationError;
+use actix_web::{get, web, HttpResponse, Result};
+use serde_json::json;
+use tracing::{instrument, warn};
+
+/// How a sampled inbox activity was processed: each stage in the order it
+/// ran, with its outcome and duration. The id is percent-encoded in the path.
+#[get("/api/admin/trace/{activity_id}")]
+#[instrument(skip(_auth, db))]
+pub async fn get_trace(
+    _auth: AdminAuth,
+    path: web::Path<String>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let activity_id = path.into_inner();
+
+    let trace = match db.get_processing_trace(&activity_id).await {
+        Ok(Some(trace)) => trace,
+        Ok(None) => {
+            return Err(
+                FederationError::NotFound(format!("No processing trace for {activity_id}")).into(),
+            )
+        }
+        Err(e) => {
+            warn!(
+                "Database error while fetching processing trace of {}: {}",
+                activity_id, e
+            );
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let total_duration_us: u64 = trace
+        .stages
+        .as_array()
+        .map(|stages| {
+            stages
+                .iter()
+                .filter_map(|stage| stage["duration_us"].as_u64())
+                .sum()
+        })
+        .unwrap_or(0);
+
+    Ok(HttpResponse::Ok().json(json!({
+        "activity_id": trace.activity_id,
+        "activity_type": trace.activity_type,
+        "stages": trace.stages,
+        "total_duration_us": total_duration_us,
+        "created_at": trace.created_at
+    })))
+}
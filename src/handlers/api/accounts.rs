@@ -0,0 +1,163 @@
+use crate::auth::{self, AuthContext, RequireScope};
+use crate::database::{DatabaseRef, DbActorSummary};
+use crate::errors::FederationError;
+use crate::handlers::admin::PageQuery;
+use crate::handlers::parse_failure_recorder;
+use crate::http::{pagination, HttpClient};
+use crate::services::events::{EventBus, ObjectEvent};
+use crate::services::{actor_profiles, addressing};
+use crate::urls::UrlBuilder;
+use actix_web::http::header;
+use actix_web::{get, put, web, HttpRequest, HttpResponse, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{info, instrument, warn};
+
+/// Most accounts an actor can list in `alsoKnownAs`
+const MAX_ALIASES: usize = 10;
+
+/// The accounts the caller also goes by, replacing any listed before
+#[derive(Debug, Deserialize)]
+pub struct AliasesUpdate {
+    pub also_known_as: Vec<String>,
+}
+
+/// Mastodon `Account` entity for a follower, followee or search result.
+/// Profiles that haven't been fetched yet fall back to the id for everything
+/// but `acct`.
+pub(crate) fn account(summary: &DbActorSummary) -> Value {
+    let acct = actor_profiles::acct(summary);
+    let username = acct.split('@').next().unwrap_or(&acct).to_string();
+    serde_json::json!({
+        "id": summary.id,
+        "username": username,
+        "acct": acct,
+        "display_name": summary.name.clone().unwrap_or_default(),
+        "avatar": summary.avatar_url,
+        "url": summary.id,
+    })
+}
+
+/// Followers of a local account, identified by its username
+#[get("/api/v1/accounts/{id}/followers")]
+#[instrument(skip(req, urls, db, http_client))]
+pub async fn get_followers(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<PageQuery>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+    http_client: web::Data<dyn HttpClient>,
+) -> Result<HttpResponse> {
+    let username = path.into_inner();
+
+    let actor = match db.get_actor_by_username(&username).await {
+        Ok(Some(actor)) => actor,
+        Ok(None) => return Err(FederationError::ActorNotFound.into()),
+        Err(e) => {
+            warn!("Database error while fetching actor {}: {}", username, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let followers = actor_profiles::followers_with_profiles(
+        &db,
+        &http_client.into_inner(),
+        &parse_failure_recorder(&req, &db),
+        &actor.id,
+        query.limit(),
+        query.offset(),
+    )
+    .await;
+
+    match followers {
+        Ok(followers) => {
+            let links = pagination::offset_links(
+                &format!("{}/api/v1/accounts/{}/followers", urls.base(), username),
+                req.query_string(),
+                query.offset(),
+                query.limit(),
+                followers.len(),
+                &[],
+            );
+            let mut response = HttpResponse::Ok();
+            if let Some(links) = links {
+                response.insert_header((header::LINK, links));
+            }
+            Ok(response.json(followers.iter().map(account).collect::<Vec<_>>()))
+        }
+        Err(e) => {
+            warn!(
+                "Database error while listing followers of {}: {}",
+                username, e
+            );
+            Err(FederationError::DatabaseError(e).into())
+        }
+    }
+}
+
+/// Set the caller's `alsoKnownAs`, which an account elsewhere must be listed
+/// in before it can `Move` here. Each alias is an HTTPS actor URL other than
+/// the caller's own.
+#[put(
+    "/api/v1/accounts/aliases",
+    wrap = "RequireScope(auth::WRITE_ACCOUNTS_SCOPE)"
+)]
+#[instrument(skip(req, auth, update, db))]
+pub async fn set_aliases(
+    req: HttpRequest,
+    auth: AuthContext,
+    update: web::Json<AliasesUpdate>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let actor = match db.get_actor_by_id(&auth.actor_id).await {
+        Ok(Some(actor)) => actor,
+        Ok(None) => return Err(FederationError::ActorNotFound.into()),
+        Err(e) => {
+            warn!(
+                "Database error while fetching actor {}: {}",
+                auth.actor_id, e
+            );
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let mut aliases: Vec<String> = Vec::new();
+    for alias in &update.also_known_as {
+        if !alias.starts_with("https://") || !addressing::is_http_url(alias) || *alias == actor.id {
+            info!("Invalid alias for {}: {}", actor.id, alias);
+            return Err(FederationError::BadRequest(format!(
+                "{alias} is not an HTTPS actor URL of another account"
+            ))
+            .into());
+        }
+        if !aliases.contains(alias) {
+            aliases.push(alias.clone());
+        }
+    }
+    if aliases.len() > MAX_ALIASES {
+        return Err(FederationError::BadRequest(format!(
+            "At most {MAX_ALIASES} aliases are allowed"
+        ))
+        .into());
+    }
+
+    if let Err(e) = db
+        .update_actor_migration(&actor.id, actor.moved_to.clone(), &aliases)
+        .await
+    {
+        warn!(
+            "Database error while setting aliases of {}: {}",
+            actor.id, e
+        );
+        return Err(FederationError::DatabaseError(e).into());
+    }
+
+    // Cached copies of the actor document would hide the new aliases
+    if let Some(events) = req.app_data::<web::Data<EventBus>>() {
+        events.publish(ObjectEvent::ActorUpdated(actor.id.clone()));
+    }
+
+    info!("{} set {} aliases", actor.id, aliases.len());
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "also_known_as": aliases })))
+}
@@ -0,0 +1,65 @@
+use crate::config::Config;
+use crate::database::{DatabaseRef, DbInstanceStats};
+use crate::errors::FederationError;
+use crate::urls::UrlBuilder;
+use actix_web::{get, web, HttpResponse, Result};
+use serde_json::Value;
+use tracing::{instrument, warn};
+
+/// Mastodon API version we report compatibility with. Clients parse the
+/// leading version to decide which endpoints to try.
+const MASTODON_COMPAT_VERSION: &str = "4.0.0";
+
+/// Mastodon `Instance` (v1) entity describing this server
+fn instance(config: &Config, stats: &DbInstanceStats) -> Value {
+    let description = config.instance_description.as_str();
+    serde_json::json!({
+        "uri": UrlBuilder::from_config(config).domain(),
+        "title": config.server_name,
+        "short_description": description,
+        "description": description,
+        "email": config.contact_email.as_deref().unwrap_or(""),
+        "version": format!(
+            "{MASTODON_COMPAT_VERSION} (compatible; {} {})",
+            env!("CARGO_PKG_NAME"),
+            env!("CARGO_PKG_VERSION")
+        ),
+        "urls": {},
+        "stats": {
+            "user_count": stats.user_count,
+            "status_count": stats.status_count,
+            "domain_count": stats.domain_count,
+        },
+        "thumbnail": null,
+        "languages": [],
+        "registrations": false,
+        "approval_required": false,
+        "invites_enabled": false,
+        "max_toot_chars": config.max_note_length,
+        "configuration": {
+            "statuses": {
+                "max_characters": config.max_note_length,
+                "max_media_attachments": 0,
+            },
+        },
+        "contact_account": null,
+        "rules": [],
+    })
+}
+
+#[get("/api/v1/instance")]
+#[instrument(skip(config, db))]
+pub async fn get_instance(
+    config: web::Data<Config>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let stats = match db.get_instance_stats().await {
+        Ok(stats) => stats,
+        Err(e) => {
+            warn!("Database error while counting instance stats: {}", e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(instance(&config, &stats)))
+}
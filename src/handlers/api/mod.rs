@@ -0,0 +1,7 @@
+pub mod accounts;
+pub mod instance;
+pub mod preferences;
+pub mod search;
+pub mod statuses;
+pub mod stream;
+pub mod timelines;
@@ -0,0 +1,80 @@
+use crate::auth::{self, AuthContext, RequireScope};
+use crate::database::{DatabaseRef, DbPreferences};
+use crate::errors::FederationError;
+use actix_web::{get, patch, web, HttpResponse, Result};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{instrument, warn};
+
+/// Changes to the caller's preferences; fields left out keep their value
+#[derive(Debug, Deserialize)]
+pub struct PreferencesUpdate {
+    pub expand_sensitive: Option<bool>,
+    pub auto_content_warnings: Option<bool>,
+}
+
+/// The caller's preferences, under the keys Mastodon clients read where
+/// there are any
+#[get(
+    "/api/v1/preferences",
+    wrap = "RequireScope(auth::READ_ACCOUNTS_SCOPE)"
+)]
+#[instrument(skip(auth, db))]
+pub async fn get_preferences(
+    auth: AuthContext,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let preferences = load(&db, &auth.actor_id).await?;
+
+    Ok(HttpResponse::Ok().json(preferences_json(&preferences)))
+}
+
+#[patch(
+    "/api/v1/preferences",
+    wrap = "RequireScope(auth::WRITE_ACCOUNTS_SCOPE)"
+)]
+#[instrument(skip(auth, db))]
+pub async fn update_preferences(
+    auth: AuthContext,
+    update: web::Json<PreferencesUpdate>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let mut preferences = load(&db, &auth.actor_id).await?;
+    if let Some(expand_sensitive) = update.expand_sensitive {
+        preferences.expand_sensitive = expand_sensitive;
+    }
+    if let Some(auto_content_warnings) = update.auto_content_warnings {
+        preferences.auto_content_warnings = auto_content_warnings;
+    }
+
+    if let Err(e) = db.set_preferences(&auth.actor_id, &preferences).await {
+        warn!(
+            "Database error while saving preferences of {}: {}",
+            auth.actor_id, e
+        );
+        return Err(FederationError::DatabaseError(e).into());
+    }
+
+    Ok(HttpResponse::Ok().json(preferences_json(&preferences)))
+}
+
+async fn load(db: &DatabaseRef, actor_id: &str) -> Result<DbPreferences, FederationError> {
+    db.get_preferences(actor_id).await.map_err(|e| {
+        warn!(
+            "Database error while fetching preferences of {}: {}",
+            actor_id, e
+        );
+        FederationError::DatabaseError(e)
+    })
+}
+
+/// Mastodon reports one setting for content warnings and one for media
+/// marked sensitive; ours covers both. Automatic content warnings are ours
+/// alone.
+fn preferences_json(preferences: &DbPreferences) -> serde_json::Value {
+    json!({
+        "reading:expand:spoilers": preferences.expand_sensitive,
+        "reading:expand:media": if preferences.expand_sensitive { "show_all" } else { "default" },
+        "posting:auto_content_warnings": preferences.auto_content_warnings,
+    })
+}
@@ -0,0 +1,231 @@
+use crate::auth;
+use crate::config::Config;
+use crate::database::{DatabaseRef, DbActorSummary};
+use crate::errors::FederationError;
+use crate::handlers::api::accounts::account;
+use crate::handlers::api::statuses::status;
+use crate::handlers::{actor_fetch, note_object};
+use crate::http::HttpClient;
+use crate::services::actor_fetch::ActorFetchService;
+use crate::services::addressing::is_http_url;
+use crate::services::search::SearchService;
+use crate::services::webfinger::{self, WebFingerResolver};
+use crate::urls::{is_public_https_url, UrlBuilder};
+use actix_web::{get, web, HttpRequest, HttpResponse, Result};
+use anyhow::bail;
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{instrument, warn};
+
+/// Default and maximum number of local matches or notes
+const DEFAULT_SEARCH_LIMIT: u32 = 20;
+const MAX_SEARCH_LIMIT: u32 = 40;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    pub limit: Option<u32>,
+    pub offset: Option<u32>,
+    /// Narrows `/api/v1/search` to one kind of result
+    #[serde(rename = "type")]
+    pub search_type: Option<SearchType>,
+    /// Look remote handles and URLs up on their servers; only honoured for
+    /// signed-in callers
+    #[serde(default)]
+    pub resolve: bool,
+}
+
+impl SearchQuery {
+    fn limit(&self) -> u32 {
+        self.limit
+            .unwrap_or(DEFAULT_SEARCH_LIMIT)
+            .clamp(1, MAX_SEARCH_LIMIT)
+    }
+
+    /// The trimmed query, refusing an empty one
+    fn terms(&self) -> Result<&str, FederationError> {
+        match self.q.trim() {
+            "" => Err(FederationError::BadRequest(
+                "Missing search query".to_string(),
+            )),
+            q => Ok(q),
+        }
+    }
+
+    fn includes(&self, search_type: SearchType) -> bool {
+        self.search_type.is_none_or(|wanted| wanted == search_type)
+    }
+}
+
+/// Actors matching `q`: local ones whose username or display name contains
+/// it, plus the actor a `@user@domain` handle or actor URL points at. Remote
+/// actors are fetched from their server only for signed-in callers passing
+/// `resolve=true`, and only from public `https` hosts.
+#[get("/api/search")]
+#[instrument(skip(req, config, urls, db, http_client))]
+pub async fn search(
+    req: HttpRequest,
+    query: web::Query<SearchQuery>,
+    config: web::Data<Config>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+    http_client: web::Data<dyn HttpClient>,
+) -> Result<HttpResponse> {
+    let q = query.terms()?;
+    let fetch = query.resolve && auth::viewer(&req).await?.is_some();
+
+    let mut actors = match db.search_actors(q, query.limit()).await {
+        Ok(actors) => actors,
+        Err(e) => {
+            warn!("Database error while searching actors for {}: {}", q, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    // A lookup that fails still leaves the local matches worth returning
+    let resolver = WebFingerResolver::from_config(http_client.get_ref(), &config);
+    let actor_fetch = actor_fetch(&req, &config, &db, &http_client);
+    match resolve(q, fetch, &urls, &db, &resolver, &actor_fetch).await {
+        Ok(Some(actor)) => {
+            if !actors.iter().any(|known| known.id == actor.id) {
+                actors.push(actor);
+            }
+        }
+        Ok(None) => {}
+        Err(e) => warn!("Could not resolve {} for search: {:#}", q, e),
+    }
+
+    Ok(HttpResponse::Ok().json(actors.iter().map(account).collect::<Vec<_>>()))
+}
+
+/// Public notes containing every word of `q`, most relevant first, paged
+/// with `limit` and `offset`
+#[get("/api/search/notes")]
+#[instrument(skip(urls, db))]
+pub async fn search_notes(
+    query: web::Query<SearchQuery>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let q = query.terms()?;
+
+    let service = SearchService::new(db.get_ref().clone());
+    let notes = match service
+        .search_notes(q, query.limit(), query.offset.unwrap_or(0))
+        .await
+    {
+        Ok(notes) => notes,
+        Err(e) => {
+            warn!("Database error while searching notes for {}: {}", q, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let items: Vec<Value> = notes.iter().map(|note| note_object(note, &urls)).collect();
+    Ok(HttpResponse::Ok().json(items))
+}
+
+/// What a Mastodon search is restricted to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchType {
+    Accounts,
+    Statuses,
+    Hashtags,
+}
+
+/// Mastodon `Search` entity: local accounts and public notes matching `q`,
+/// narrowed to one kind with `type`. Hashtags aren't indexed, so that list
+/// is always empty.
+#[get("/api/v1/search")]
+#[instrument(skip(db))]
+pub async fn search_v1(
+    query: web::Query<SearchQuery>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let q = query.terms()?;
+    let limit = query.limit();
+    let offset = query.offset.unwrap_or(0);
+    let service = SearchService::new(db.get_ref().clone());
+
+    let mut accounts = Vec::new();
+    if query.includes(SearchType::Accounts) {
+        accounts = match service.search_accounts(q, limit, offset).await {
+            Ok(actors) => actors.iter().map(account).collect(),
+            Err(e) => {
+                warn!("Database error while searching actors for {}: {}", q, e);
+                return Err(FederationError::DatabaseError(e).into());
+            }
+        };
+    }
+
+    let mut statuses = Vec::new();
+    if query.includes(SearchType::Statuses) {
+        statuses = match service.search_notes(q, limit, offset).await {
+            Ok(notes) => notes.iter().map(status).collect(),
+            Err(e) => {
+                warn!("Database error while searching notes for {}: {}", q, e);
+                return Err(FederationError::DatabaseError(e).into());
+            }
+        };
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "accounts": accounts,
+        "statuses": statuses,
+        "hashtags": Vec::<Value>::new(),
+    })))
+}
+
+/// The actor behind a handle or URL query; `None` for plain search terms,
+/// and for remote actors unless `fetch` allows looking them up
+async fn resolve(
+    q: &str,
+    fetch: bool,
+    urls: &UrlBuilder,
+    db: &DatabaseRef,
+    resolver: &WebFingerResolver<'_>,
+    actor_fetch: &ActorFetchService,
+) -> anyhow::Result<Option<DbActorSummary>> {
+    let id = if is_http_url(q) {
+        q.to_string()
+    } else if let Some((user, domain)) = webfinger::parse_acct(q) {
+        if urls.is_local_domain(domain) == Some(true) {
+            urls.actor(user)
+        } else if !fetch {
+            return Ok(None);
+        } else if !is_public_https_url(&format!("https://{domain}/")) {
+            bail!("Refusing to look up {q}: {domain} is not a public host");
+        } else {
+            resolver.resolve_acct(q).await?
+        }
+    } else {
+        return Ok(None);
+    };
+
+    if urls.is_local(&id) {
+        let actor = db.get_actor_by_id(&id).await?;
+        return Ok(actor.map(|actor| DbActorSummary {
+            id: actor.id,
+            username: Some(actor.username),
+            name: Some(actor.name),
+            avatar_url: None,
+            local: true,
+        }));
+    }
+
+    if !fetch {
+        return Ok(None);
+    }
+    if !is_public_https_url(&id) {
+        bail!("Refusing to fetch {id}: not a public https URL");
+    }
+    let remote = actor_fetch.get_or_fetch(&id).await?;
+    Ok(Some(DbActorSummary {
+        id: remote.id,
+        username: Some(remote.username),
+        name: remote.name,
+        avatar_url: remote.avatar_url,
+        local: false,
+    }))
+}
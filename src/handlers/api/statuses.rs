@@ -0,0 +1,154 @@
+use crate::auth;
+use crate::config::Config;
+use crate::database::{DatabaseRef, DbNote, PublishState};
+use crate::errors::FederationError;
+use crate::models::Visibility;
+use crate::urls::UrlBuilder;
+use actix_web::{get, web, HttpRequest, HttpResponse, Result};
+use serde_json::Value;
+use std::collections::HashMap;
+use tracing::{instrument, warn};
+
+/// Mastodon's name for a visibility. Mastodon has no local-only posts, so
+/// they read as public, flagged `local_only` under `feder8`.
+fn mastodon_visibility(visibility: Visibility) -> &'static str {
+    match visibility {
+        Visibility::Public | Visibility::Local => "public",
+        Visibility::Unlisted => "unlisted",
+        Visibility::Followers => "private",
+        Visibility::Direct => "direct",
+    }
+}
+
+/// Mastodon `Status` entity for a stored note. Thread placement we track
+/// beyond Mastodon's fields goes under `feder8`.
+pub(crate) fn status(note: &DbNote) -> Value {
+    let id = note.id.rsplit('/').next().unwrap_or(&note.id);
+    serde_json::json!({
+        "id": id,
+        "uri": note.id,
+        "url": note.id,
+        "created_at": note.published,
+        "content": note.content,
+        "sensitive": note.sensitive,
+        "spoiler_text": note.summary.as_deref().unwrap_or(""),
+        "language": note.language,
+        "visibility": mastodon_visibility(note.visibility),
+        "in_reply_to_id": note.in_reply_to,
+        "in_reply_to_account_id": note.in_reply_to_actor,
+        "feder8": {
+            "thread_depth": note.thread_depth,
+            "thread_truncated": note.thread_truncated,
+            "local_only": note.visibility == Visibility::Local,
+        },
+    })
+}
+
+/// A local note, by the last segment of its id, with how many visible
+/// replies it has. Local-only notes need a signed-in reader.
+#[get("/api/v1/statuses/{id}")]
+#[instrument(skip(req, urls, db))]
+pub async fn get_status(
+    req: HttpRequest,
+    path: web::Path<String>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let note_id = urls.note(&path.into_inner());
+    let local_viewer = auth::viewer(&req).await?.is_some();
+
+    let note = match db.get_note_by_id(&note_id).await {
+        Ok(Some(note)) if is_visible(&note, local_viewer) => note,
+        Ok(_) => return Err(FederationError::NoteNotFound.into()),
+        Err(e) => {
+            warn!("Database error while fetching note {}: {}", note_id, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let replies_count = match db.count_replies(&note_id).await {
+        Ok(count) => count,
+        Err(e) => {
+            warn!(
+                "Database error while counting replies to {}: {}",
+                note_id, e
+            );
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let mut body = status(&note);
+    body["replies_count"] = replies_count.into();
+    Ok(HttpResponse::Ok().json(body))
+}
+
+/// Mastodon `Context` entity for one of an actor's notes: the visible notes
+/// above it in its thread, root first, and those below it, oldest first
+#[get("/users/{username}/statuses/{id}/context")]
+#[instrument(skip(req, config, urls, db))]
+pub async fn get_status_context(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    config: web::Data<Config>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let (username, id) = path.into_inner();
+    let note_id = urls.note(&id);
+    let local_viewer = auth::viewer(&req).await?.is_some();
+
+    let actor = match db.get_actor_by_username(&username).await {
+        Ok(Some(actor)) => actor,
+        Ok(None) => return Err(FederationError::ActorNotFound.into()),
+        Err(e) => {
+            warn!("Database error while fetching actor {}: {}", username, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let thread = match db.get_thread(&note_id, config.max_thread_depth).await {
+        Ok(thread) => thread,
+        Err(e) => {
+            warn!("Database error while fetching thread of {}: {}", note_id, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+    let mut notes: HashMap<&str, &DbNote> =
+        thread.iter().map(|note| (note.id.as_str(), note)).collect();
+
+    let note = match notes.remove(note_id.as_str()) {
+        Some(note) if note.attributed_to == actor.id && is_visible(note, local_viewer) => note,
+        _ => return Err(FederationError::NoteNotFound.into()),
+    };
+
+    let mut ancestors = Vec::new();
+    let mut parent = note.in_reply_to.as_deref();
+    while let Some(ancestor) = parent.and_then(|id| notes.remove(id)) {
+        ancestors.push(ancestor);
+        parent = ancestor.in_reply_to.as_deref();
+    }
+    ancestors.reverse();
+
+    // Everything else the walk found hangs below the note
+    let descendants: Vec<Value> = thread
+        .iter()
+        .filter(|reply| notes.contains_key(reply.id.as_str()) && is_visible(reply, local_viewer))
+        .map(status)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "ancestors": ancestors
+            .into_iter()
+            .filter(|ancestor| is_visible(ancestor, local_viewer))
+            .map(status)
+            .collect::<Vec<_>>(),
+        "descendants": descendants,
+    })))
+}
+
+/// Whether a reader may see `note`; local-only notes need one signed in here
+fn is_visible(note: &DbNote, local_viewer: bool) -> bool {
+    note.state == PublishState::Published
+        && (note.visibility.is_publicly_visible()
+            || (local_viewer && note.visibility == Visibility::Local))
+}
@@ -0,0 +1,91 @@
+use crate::auth::{self, AuthContext, RequireScope};
+use crate::services::inbox_stream::{InboxEvent, InboxStream};
+use actix_web::http::header;
+use actix_web::web::Bytes;
+use actix_web::{get, web, HttpResponse, Result};
+use futures::{Stream, StreamExt};
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use tokio::time::{interval_at, Instant, Interval};
+use tokio_util::sync::CancellationToken;
+use tracing::{info, instrument};
+
+/// How often an idle stream sends a comment, so proxies keep it open and a
+/// gone client is noticed
+const HEARTBEAT: Duration = Duration::from_secs(15);
+
+/// Server-sent events of each activity the inbox stores for the caller's
+/// actor, as `activity` events with the activity as JSON data
+#[get("/api/stream", wrap = "RequireScope(auth::READ_STATUSES_SCOPE)")]
+#[instrument(skip(auth, inbox_stream))]
+pub async fn stream(
+    auth: AuthContext,
+    inbox_stream: web::Data<InboxStream>,
+) -> Result<HttpResponse> {
+    info!("Stream client of {} connected", auth.actor_id);
+
+    let client = Client {
+        actor_id: auth.actor_id,
+        receiver: inbox_stream.subscribe(),
+        closing: inbox_stream.closing(),
+        heartbeat: interval_at(Instant::now() + HEARTBEAT, HEARTBEAT),
+    };
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(client.into_stream()))
+}
+
+/// One connected client. Dropped, with its receiver, when the client goes
+/// away and actix stops polling the response.
+struct Client {
+    actor_id: String,
+    receiver: Receiver<Arc<InboxEvent>>,
+    closing: CancellationToken,
+    heartbeat: Interval,
+}
+
+impl Client {
+    /// A comment straight away, so the client sees the stream open, then
+    /// events as they come
+    fn into_stream(self) -> impl Stream<Item = Result<Bytes, Infallible>> {
+        let connected = futures::stream::once(async { Ok(Bytes::from_static(b": connected\n\n")) });
+        let events = futures::stream::unfold(self, |mut client| async move {
+            let frame = client.next_frame().await?;
+            Some((Ok(frame), client))
+        });
+        connected.chain(events)
+    }
+
+    /// The next event for this client's actor or heartbeat; `None` once the
+    /// server is shutting down
+    async fn next_frame(&mut self) -> Option<Bytes> {
+        loop {
+            tokio::select! {
+                event = self.receiver.recv() => match event {
+                    Ok(event) if event.actor_id == self.actor_id => {
+                        return Some(Bytes::from(format!(
+                            "event: activity\ndata: {}\n\n",
+                            event.activity
+                        )));
+                    }
+                    Ok(_) => {}
+                    Err(RecvError::Lagged(missed)) => {
+                        info!("Stream client of {} missed {} events", self.actor_id, missed);
+                    }
+                    Err(RecvError::Closed) => return None,
+                },
+                _ = self.heartbeat.tick() => return Some(Bytes::from_static(b": heartbeat\n\n")),
+                _ = self.closing.cancelled() => return None,
+            }
+        }
+    }
+}
+
+impl Drop for Client {
+    fn drop(&mut self) {
+        info!("Stream client of {} disconnected", self.actor_id);
+    }
+}
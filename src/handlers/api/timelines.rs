@@ -0,0 +1,67 @@
+use crate::auth;
+use crate::database::DatabaseRef;
+use crate::errors::FederationError;
+use crate::handlers::note_object;
+use crate::urls::UrlBuilder;
+use actix_web::{get, web, HttpRequest, HttpResponse, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{instrument, warn};
+
+/// Default and maximum number of notes per page
+const DEFAULT_TIMELINE_LIMIT: u32 = 20;
+const MAX_TIMELINE_LIMIT: u32 = 40;
+
+/// `before_id` takes a note's full id, since federated notes have no id of
+/// ours to go by
+#[derive(Debug, Deserialize)]
+pub struct TimelineQuery {
+    #[serde(default)]
+    pub local: bool,
+    /// Leave out notes marked sensitive
+    #[serde(default)]
+    pub hide_sensitive: bool,
+    pub before_id: Option<String>,
+    pub limit: Option<u32>,
+}
+
+/// Recent public notes we have stored, ours and federated, newest first;
+/// only our own actors' with `?local=true`, and without sensitive ones with
+/// `?hide_sensitive=true`. Readers signed in here also get local-only notes
+/// on the local timeline.
+#[get("/api/timelines/public")]
+#[instrument(skip(req, urls, db))]
+pub async fn get_public_timeline(
+    req: HttpRequest,
+    query: web::Query<TimelineQuery>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let query = query.into_inner();
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_TIMELINE_LIMIT)
+        .clamp(1, MAX_TIMELINE_LIMIT);
+
+    let with_local_visibility = query.local && auth::viewer(&req).await?.is_some();
+
+    let notes = match db
+        .get_public_notes(
+            limit,
+            query.before_id,
+            query.local,
+            query.hide_sensitive,
+            with_local_visibility,
+        )
+        .await
+    {
+        Ok(notes) => notes,
+        Err(e) => {
+            warn!("Database error while fetching the public timeline: {}", e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let items: Vec<Value> = notes.iter().map(|note| note_object(note, &urls)).collect();
+    Ok(HttpResponse::Ok().json(items))
+}
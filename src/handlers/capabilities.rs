@@ -0,0 +1,9 @@
+use crate::capabilities::Capabilities;
+use actix_web::{get, web, HttpResponse, Result};
+use tracing::instrument;
+
+#[get("/api/capabilities")]
+#[instrument(skip(capabilities))]
+pub async fn get_capabilities(capabilities: web::Data<Capabilities>) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(capabilities.get_ref()))
+}
@@ -0,0 +1,391 @@
+use crate::config::Config;
+use crate::database::{DatabaseRef, DbActor, DbNote};
+use crate::errors::FederationError;
+use crate::handlers::note_object;
+use crate::http::content_type;
+use crate::models::object::Tag;
+use crate::models::{
+    ContextBuilder, OrderedCollection, OrderedCollectionPage, PagedOrderedCollection,
+};
+use crate::urls::UrlBuilder;
+use actix_web::http::header;
+use actix_web::{get, web, HttpRequest, HttpResponse, Result};
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{instrument, warn};
+
+/// Page size for hashtag collections
+const TAG_COLLECTION_LIMIT: u32 = 20;
+
+/// Page size for replies collections
+const REPLIES_PAGE_SIZE: u32 = 20;
+
+/// Page size for likes and shares collections
+const INTERACTIONS_PAGE_SIZE: u32 = 20;
+
+#[derive(Debug, Deserialize)]
+pub struct PageQuery {
+    #[serde(default)]
+    page: bool,
+    #[serde(default)]
+    offset: u32,
+}
+
+/// Timeline-style filters for collections read by clients
+#[derive(Debug, Deserialize)]
+pub struct FilterQuery {
+    /// Leave out notes marked sensitive
+    #[serde(default)]
+    hide_sensitive: bool,
+}
+
+#[get("/users/{username}/collections/featured")]
+#[instrument(skip(req, urls, db))]
+pub async fn get_featured(
+    req: HttpRequest,
+    path: web::Path<String>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let username = path.into_inner();
+
+    let actor = find_actor(&db, &username).await?;
+
+    let notes = match db.get_pinned_notes(&actor.id).await {
+        Ok(notes) => notes,
+        Err(e) => {
+            warn!(
+                "Database error while fetching pinned notes for {}: {}",
+                username, e
+            );
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let items: Vec<Value> = notes
+        .iter()
+        .filter(|note| note.visibility.is_publicly_visible())
+        .map(|note| note_object(note, &urls))
+        .collect();
+
+    let collection = OrderedCollection::new(urls.featured(&username), items.len() as u32, items);
+
+    Ok(collection_response(&req, collection))
+}
+
+#[get("/users/{username}/collections/tags/{tag}")]
+#[instrument(skip(req, urls, db))]
+pub async fn get_tag_collection(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    query: web::Query<FilterQuery>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let (username, tag) = path.into_inner();
+    let hashtag = Tag::normalize_hashtag(&tag);
+
+    let actor = find_actor(&db, &username).await?;
+
+    let total_items = match db
+        .count_notes_by_hashtag(&actor.id, &hashtag, query.hide_sensitive)
+        .await
+    {
+        Ok(count) => count,
+        Err(e) => {
+            warn!(
+                "Database error while counting #{} notes for {}: {}",
+                hashtag, username, e
+            );
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let notes = match db
+        .search_notes_by_hashtag(
+            &actor.id,
+            &hashtag,
+            query.hide_sensitive,
+            TAG_COLLECTION_LIMIT,
+            0,
+        )
+        .await
+    {
+        Ok(notes) => notes,
+        Err(e) => {
+            warn!(
+                "Database error while fetching #{} notes for {}: {}",
+                hashtag, username, e
+            );
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let collection = OrderedCollection::new(
+        urls.tag_collection(&username, &hashtag),
+        total_items,
+        notes.iter().map(|note| note_object(note, &urls)).collect(),
+    );
+
+    Ok(collection_response(&req, collection))
+}
+
+#[get("/notes/{id}/replies")]
+#[instrument(skip(req, urls, db))]
+pub async fn get_replies(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<PageQuery>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let id = path.into_inner();
+    let note = find_public_note(&db, &urls, &id).await?;
+
+    replies_response(&req, &query, &urls, &db, &id, &note.id).await
+}
+
+/// The replies collection of a note, under its author's statuses. Notes
+/// belonging to someone else are not found here.
+#[get("/users/{username}/statuses/{id}/replies")]
+#[instrument(skip(req, urls, db))]
+pub async fn get_status_replies(
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    query: web::Query<PageQuery>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let (username, id) = path.into_inner();
+
+    let actor = find_actor(&db, &username).await?;
+    let note = find_public_note(&db, &urls, &id).await?;
+    if note.attributed_to != actor.id {
+        warn!("Note {} is not by {}", note.id, username);
+        return Err(FederationError::NoteNotFound.into());
+    }
+
+    replies_response(&req, &query, &urls, &db, &id, &note.id).await
+}
+
+/// Public and unlisted replies to `note_id`, oldest first. The collection
+/// keeps the id it has under `/notes`, whichever route served it.
+async fn replies_response(
+    req: &HttpRequest,
+    query: &PageQuery,
+    urls: &UrlBuilder,
+    db: &DatabaseRef,
+    id: &str,
+    note_id: &str,
+) -> Result<HttpResponse> {
+    let offset = if query.page { query.offset } else { 0 };
+
+    let total_items = match db.count_replies(note_id).await {
+        Ok(count) => count,
+        Err(e) => {
+            warn!(
+                "Database error while counting replies to {}: {}",
+                note_id, e
+            );
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let replies = match db.get_replies(note_id, REPLIES_PAGE_SIZE, offset).await {
+        Ok(replies) => replies,
+        Err(e) => {
+            warn!(
+                "Database error while fetching replies to {}: {}",
+                note_id, e
+            );
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let page = OrderedCollectionPage::new(
+        &urls.replies(id),
+        offset,
+        REPLIES_PAGE_SIZE,
+        total_items,
+        replies.iter().map(|note| note_object(note, urls)).collect(),
+    );
+
+    paged_response(req, query, page, total_items, REPLIES_PAGE_SIZE)
+}
+
+/// Who liked a note, as the ids of their Like activities, or just how many
+/// when interaction collections are count-only
+#[get("/notes/{id}/likes")]
+#[instrument(skip(req, config, urls, db))]
+pub async fn get_likes(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<PageQuery>,
+    config: web::Data<Config>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let id = path.into_inner();
+    let note_id = find_public_note(&db, &urls, &id).await?.id;
+
+    let total_items = match db.count_likes(&note_id).await {
+        Ok(count) => count,
+        Err(e) => {
+            warn!("Database error while counting likes of {}: {}", note_id, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+    if config.interaction_collections_count_only {
+        return Ok(count_only_response(&req, &urls.likes(&id), total_items));
+    }
+
+    let offset = if query.page { query.offset } else { 0 };
+    let likes = match db.get_likes(&note_id, INTERACTIONS_PAGE_SIZE, offset).await {
+        Ok(likes) => likes,
+        Err(e) => {
+            warn!("Database error while fetching likes of {}: {}", note_id, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let page = OrderedCollectionPage::new(
+        &urls.likes(&id),
+        offset,
+        INTERACTIONS_PAGE_SIZE,
+        total_items,
+        likes
+            .into_iter()
+            .map(|like| Value::String(like.id))
+            .collect(),
+    );
+
+    paged_response(&req, &query, page, total_items, INTERACTIONS_PAGE_SIZE)
+}
+
+/// Who shared (boosted) a note, as the ids of their Announce activities, or
+/// just how many when interaction collections are count-only
+#[get("/notes/{id}/shares")]
+#[instrument(skip(req, config, urls, db))]
+pub async fn get_shares(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<PageQuery>,
+    config: web::Data<Config>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let id = path.into_inner();
+    let note_id = find_public_note(&db, &urls, &id).await?.id;
+
+    let total_items = match db.count_shares(&note_id).await {
+        Ok(count) => count,
+        Err(e) => {
+            warn!("Database error while counting shares of {}: {}", note_id, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+    if config.interaction_collections_count_only {
+        return Ok(count_only_response(&req, &urls.shares(&id), total_items));
+    }
+
+    let offset = if query.page { query.offset } else { 0 };
+    let shares = match db
+        .get_shares(&note_id, INTERACTIONS_PAGE_SIZE, offset)
+        .await
+    {
+        Ok(shares) => shares,
+        Err(e) => {
+            warn!("Database error while fetching shares of {}: {}", note_id, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let page = OrderedCollectionPage::new(
+        &urls.shares(&id),
+        offset,
+        INTERACTIONS_PAGE_SIZE,
+        total_items,
+        shares
+            .into_iter()
+            .map(|share| Value::String(share.id))
+            .collect(),
+    );
+
+    paged_response(&req, &query, page, total_items, INTERACTIONS_PAGE_SIZE)
+}
+
+/// Local note `id`, provided anyone may see it. Collections of a note nobody
+/// may see would leak that it exists.
+async fn find_public_note(
+    db: &DatabaseRef,
+    urls: &UrlBuilder,
+    id: &str,
+) -> Result<DbNote, FederationError> {
+    let note_id = urls.note(id);
+    match db.get_note_by_id(&note_id).await {
+        Ok(Some(note)) if note.visibility.is_publicly_visible() => Ok(note),
+        Ok(_) => {
+            warn!("Note not found for collection: {}", note_id);
+            Err(FederationError::NoteNotFound)
+        }
+        Err(e) => {
+            warn!("Database error while fetching note {}: {}", note_id, e);
+            Err(FederationError::DatabaseError(e))
+        }
+    }
+}
+
+async fn find_actor(db: &DatabaseRef, username: &str) -> Result<DbActor, FederationError> {
+    match db.get_actor_by_username(username).await {
+        Ok(Some(actor)) => Ok(actor),
+        Ok(None) => {
+            warn!("Actor not found for collection: {}", username);
+            Err(FederationError::ActorNotFound)
+        }
+        Err(e) => {
+            warn!("Database error while fetching actor {}: {}", username, e);
+            Err(FederationError::DatabaseError(e))
+        }
+    }
+}
+
+/// Serve `page` on its own when one was asked for, else embedded as the
+/// first page of its collection
+fn paged_response(
+    req: &HttpRequest,
+    query: &PageQuery,
+    page: OrderedCollectionPage,
+    total_items: u32,
+    page_size: u32,
+) -> Result<HttpResponse> {
+    let body = if query.page {
+        serde_json::to_value(page.standalone())?
+    } else {
+        serde_json::to_value(PagedOrderedCollection::new(page, total_items, page_size))?
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type::negotiate_request(req).as_str())
+        .insert_header((header::VARY, "Accept"))
+        .json(body))
+}
+
+/// A collection that only says how many items it has
+fn count_only_response(req: &HttpRequest, id: &str, total_items: u32) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(content_type::negotiate_request(req).as_str())
+        .insert_header((header::VARY, "Accept"))
+        .json(serde_json::json!({
+            "@context": ContextBuilder::new().build(),
+            "id": id,
+            "type": "OrderedCollection",
+            "totalItems": total_items,
+        }))
+}
+
+fn collection_response(req: &HttpRequest, collection: OrderedCollection) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(content_type::negotiate_request(req).as_str())
+        .insert_header((header::VARY, "Accept"))
+        .json(collection)
+}
@@ -0,0 +1,11 @@
+use actix_web::http::header;
+use actix_web::{options, HttpResponse};
+
+/// Answer `OPTIONS` for any path. Browser preflights are handled by the CORS
+/// middleware before they get here; this covers plain `OPTIONS` requests.
+#[options("/{tail:.*}")]
+pub async fn preflight() -> HttpResponse {
+    HttpResponse::NoContent()
+        .insert_header((header::ALLOW, "GET, POST, PATCH, DELETE, OPTIONS"))
+        .finish()
+}
@@ -0,0 +1,258 @@
+use crate::database::{DatabaseRef, DbActor, DbNote};
+use crate::errors::FederationError;
+use crate::handlers::xml::escape_xml;
+use crate::http::caching;
+use crate::urls::UrlBuilder;
+use actix_web::{get, web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use tracing::{instrument, warn};
+
+/// Recent notes looked at for a feed; only public ones are included
+const FEED_NOTES_LIMIT: u32 = 20;
+
+/// Characters of a note's text used as its title when it has no content
+/// warning
+const FEED_TITLE_LENGTH: usize = 80;
+
+/// The actor's recent public notes as an Atom feed, for feed readers
+#[get("/users/{username}/feed.atom")]
+#[instrument(skip(req, urls, db))]
+pub async fn get_atom_feed(
+    req: HttpRequest,
+    path: web::Path<String>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let username = path.into_inner();
+    let (actor, notes) = load_feed(&db, &username).await?;
+    let updated = updated(&actor, &notes);
+
+    let body = render_atom(&actor, &notes, &urls.feed(&username, "atom"), updated);
+    let mut response = HttpResponse::Ok();
+    response.content_type("application/atom+xml; charset=utf-8");
+    Ok(caching::respond(
+        &req,
+        response,
+        body.into_bytes(),
+        Some(updated),
+    ))
+}
+
+/// The same feed in RSS 2.0
+#[get("/users/{username}/feed.rss")]
+#[instrument(skip(req, urls, db))]
+pub async fn get_rss_feed(
+    req: HttpRequest,
+    path: web::Path<String>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let username = path.into_inner();
+    let (actor, notes) = load_feed(&db, &username).await?;
+    let updated = updated(&actor, &notes);
+
+    let body = render_rss(&actor, &notes, &urls.feed(&username, "rss"), updated);
+    let mut response = HttpResponse::Ok();
+    response.content_type("application/rss+xml; charset=utf-8");
+    Ok(caching::respond(
+        &req,
+        response,
+        body.into_bytes(),
+        Some(updated),
+    ))
+}
+
+/// The actor and their recent published public notes, newest first
+async fn load_feed(db: &DatabaseRef, username: &str) -> Result<(DbActor, Vec<DbNote>)> {
+    let actor = match db.get_actor_by_username(username).await {
+        Ok(Some(actor)) => actor,
+        Ok(None) => return Err(FederationError::ActorNotFound.into()),
+        Err(e) => {
+            warn!("Database error while fetching actor {}: {}", username, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let notes = match db
+        .get_public_notes_by_actor(&actor.id, FEED_NOTES_LIMIT)
+        .await
+    {
+        Ok(notes) => notes,
+        Err(e) => {
+            warn!("Database error while listing notes of {}: {}", username, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    Ok((actor, notes))
+}
+
+/// When the feed last changed: its newest note, or the profile itself when
+/// there are none
+fn updated(actor: &DbActor, notes: &[DbNote]) -> DateTime<Utc> {
+    notes
+        .iter()
+        .map(|note| note.published)
+        .max()
+        .unwrap_or(actor.updated_at)
+}
+
+fn render_atom(
+    actor: &DbActor,
+    notes: &[DbNote],
+    self_url: &str,
+    updated: DateTime<Utc>,
+) -> String {
+    let subtitle = actor
+        .summary
+        .as_deref()
+        .map(|s| format!("\n  <subtitle>{}</subtitle>", escape_xml(s)))
+        .unwrap_or_default();
+    let entries: String = notes
+        .iter()
+        .map(|note| {
+            format!(
+                r#"
+  <entry>
+    <id>{id}</id>
+    <title>{title}</title>
+    <link rel="alternate" type="text/html" href="{id}"/>
+    <published>{published}</published>
+    <updated>{published}</updated>
+    <content type="html">{content}</content>
+  </entry>"#,
+                id = escape_xml(&note.id),
+                title = escape_xml(&title(note)),
+                published = note.published.to_rfc3339(),
+                content = escape_xml(&note.content),
+            )
+        })
+        .collect();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+  <id>{id}</id>
+  <title>{feed_title}</title>{subtitle}
+  <updated>{updated}</updated>
+  <link rel="alternate" type="text/html" href="{id}"/>
+  <link rel="self" type="application/atom+xml" href="{self_url}"/>
+  <author>
+    <name>{name}</name>
+    <uri>{id}</uri>
+  </author>{entries}
+</feed>
+"#,
+        id = escape_xml(&actor.id),
+        feed_title = escape_xml(&feed_title(actor)),
+        updated = updated.to_rfc3339(),
+        self_url = escape_xml(self_url),
+        name = escape_xml(&actor.name),
+    )
+}
+
+fn render_rss(actor: &DbActor, notes: &[DbNote], self_url: &str, updated: DateTime<Utc>) -> String {
+    let description = actor
+        .summary
+        .clone()
+        .unwrap_or_else(|| format!("Public posts from @{}", actor.username));
+    let items: String = notes
+        .iter()
+        .map(|note| {
+            format!(
+                r#"
+    <item>
+      <guid isPermaLink="true">{id}</guid>
+      <link>{id}</link>
+      <title>{title}</title>
+      <pubDate>{published}</pubDate>
+      <description>{content}</description>
+    </item>"#,
+                id = escape_xml(&note.id),
+                title = escape_xml(&title(note)),
+                published = note.published.to_rfc2822(),
+                content = escape_xml(&note.content),
+            )
+        })
+        .collect();
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom">
+  <channel>
+    <title>{feed_title}</title>
+    <link>{id}</link>
+    <description>{description}</description>
+    <atom:link rel="self" type="application/rss+xml" href="{self_url}"/>
+    <lastBuildDate>{updated}</lastBuildDate>{items}
+  </channel>
+</rss>
+"#,
+        feed_title = escape_xml(&feed_title(actor)),
+        id = escape_xml(&actor.id),
+        description = escape_xml(&description),
+        self_url = escape_xml(self_url),
+        updated = updated.to_rfc2822(),
+    )
+}
+
+fn feed_title(actor: &DbActor) -> String {
+    format!("{} (@{})", actor.name, actor.username)
+}
+
+/// A note's content warning, or else the start of its text without markup
+fn title(note: &DbNote) -> String {
+    if let Some(summary) = note.summary.as_deref().filter(|s| !s.trim().is_empty()) {
+        return summary.to_string();
+    }
+
+    let text = plain_text(&note.content);
+    match text.char_indices().nth(FEED_TITLE_LENGTH) {
+        Some((end, _)) => format!("{}…", text[..end].trim_end()),
+        None => text,
+    }
+}
+
+/// The text of an HTML fragment: tags dropped, whitespace collapsed and the
+/// common entities decoded
+fn plain_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                text.push(' ');
+            }
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_text_drops_markup_and_decodes_entities() {
+        assert_eq!(
+            plain_text("<p>Fish &amp; chips</p><p>&lt;3 <a href=\"x\">you</a></p>"),
+            "Fish & chips <3 you"
+        );
+        // Decoding once only, so escaped entities stay escaped
+        assert_eq!(plain_text("&amp;lt;"), "&lt;");
+    }
+}
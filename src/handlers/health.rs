@@ -0,0 +1,55 @@
+use crate::database::DatabaseRef;
+use crate::health::DatabaseHealth;
+use actix_web::{get, web, HttpResponse, Result};
+use serde_json::{json, Value};
+use tracing::{instrument, warn};
+
+/// Liveness: answers whenever the process is serving requests
+#[get("/healthz")]
+pub async fn healthz() -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(json!({ "status": "ok" })))
+}
+
+/// Ready when the database answers a ping and calls to it are within the
+/// error budget. Each check is reported on its own so operators can see which
+/// dependency is holding the node back.
+#[get("/readyz")]
+#[instrument(skip(db, health))]
+pub async fn readyz(
+    db: web::Data<DatabaseRef>,
+    health: web::Data<DatabaseHealth>,
+) -> Result<HttpResponse> {
+    // Read the budget before pinging, so the ping itself doesn't skew it
+    let error_budget = if health.is_ready() {
+        json!({ "status": "ok", "failure_rate": health.failure_rate() })
+    } else {
+        json!({ "status": "error", "failure_rate": health.failure_rate() })
+    };
+
+    let database = match db.ping().await {
+        Ok(()) => json!({ "status": "ok" }),
+        // The error can name hosts and paths, so it only goes to the log
+        Err(e) => {
+            warn!("Readiness ping failed: {}", e);
+            json!({ "status": "error" })
+        }
+    };
+
+    let checks = json!({ "database": database, "error_budget": error_budget });
+    let ready = checks
+        .as_object()
+        .is_some_and(|checks| checks.values().all(check_passed));
+
+    if ready {
+        Ok(HttpResponse::Ok().json(json!({ "status": "ready", "checks": checks })))
+    } else {
+        Ok(HttpResponse::ServiceUnavailable().json(json!({
+            "status": "not ready",
+            "checks": checks
+        })))
+    }
+}
+
+fn check_passed(check: &Value) -> bool {
+    check["status"] == "ok"
+}
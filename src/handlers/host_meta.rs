@@ -0,0 +1,109 @@
+use crate::handlers::xml::escape_xml;
+use crate::urls::UrlBuilder;
+use actix_web::http::header;
+use actix_web::{get, web, HttpRequest, HttpResponse, Result};
+use tracing::instrument;
+
+/// Legacy WebFinger discovery: an XRD document with the WebFinger URI
+/// template. Clients asking for JSON get the JRD form instead.
+#[get("/.well-known/host-meta")]
+#[instrument(skip(req, urls))]
+pub async fn host_meta(req: HttpRequest, urls: web::Data<UrlBuilder>) -> Result<HttpResponse> {
+    let accept = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+
+    if prefers_json(accept) {
+        let mut response = json_response(&urls);
+        response
+            .headers_mut()
+            .insert(header::VARY, header::HeaderValue::from_static("Accept"));
+        return Ok(response);
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/xrd+xml; charset=utf-8")
+        .insert_header((header::VARY, "Accept"))
+        .body(render_xrd(&webfinger_template(&urls))))
+}
+
+#[get("/.well-known/host-meta.json")]
+#[instrument(skip(urls))]
+pub async fn host_meta_json(urls: web::Data<UrlBuilder>) -> Result<HttpResponse> {
+    Ok(json_response(&urls))
+}
+
+fn json_response(urls: &UrlBuilder) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type("application/jrd+json")
+        .json(serde_json::json!({
+            "links": [{
+                "rel": "lrdd",
+                "template": webfinger_template(urls)
+            }]
+        }))
+}
+
+fn webfinger_template(urls: &UrlBuilder) -> String {
+    format!("{}?resource={{uri}}", urls.well_known("webfinger"))
+}
+
+fn render_xrd(template: &str) -> String {
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<XRD xmlns="http://docs.oasis-open.org/ns/xri/xrd-1.0">
+  <Link rel="lrdd" template="{}"/>
+</XRD>
+"#,
+        escape_xml(template)
+    )
+}
+
+/// Whether a JSON media type outranks XRD in an `Accept` header. XRD wins
+/// ties and is the default, as the host-meta spec makes it the primary form.
+fn prefers_json(accept: &str) -> bool {
+    let mut json = 0.0f32;
+    let mut xml = 0.0f32;
+
+    for range in accept.split(',') {
+        let mut parts = range.split(';').map(str::trim);
+        let media_type = parts.next().unwrap_or_default().to_ascii_lowercase();
+        let quality = parts
+            .filter_map(|param| param.strip_prefix("q="))
+            .find_map(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        match media_type.as_str() {
+            "application/json" | "application/jrd+json" => json = json.max(quality),
+            "application/xrd+xml" | "application/xml" | "text/xml" => xml = xml.max(quality),
+            _ => {}
+        }
+    }
+
+    json > xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefers_json() {
+        assert!(prefers_json("application/json"));
+        assert!(prefers_json(
+            "application/jrd+json, application/xrd+xml;q=0.5"
+        ));
+        assert!(!prefers_json(""));
+        assert!(!prefers_json("*/*"));
+        assert!(!prefers_json("application/xrd+xml, application/json"));
+        assert!(!prefers_json("application/json;q=0.2, application/xml"));
+    }
+
+    #[test]
+    fn test_render_xrd_escapes_template() {
+        let xrd = render_xrd("https://example.com/a?b=1&c=2");
+        assert!(xrd.contains(r#"template="https://example.com/a?b=1&amp;c=2""#));
+    }
+}
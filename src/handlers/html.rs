@@ -0,0 +1,47 @@
+use crate::database::DbNote;
+use actix_web::http::header;
+use actix_web::HttpRequest;
+
+/// Browsers (and clients that don't say what they want) get HTML pages;
+/// ActivityPub clients asking for JSON-LD get the ActivityStreams document
+pub(crate) fn wants_html(req: &HttpRequest) -> bool {
+    let Some(accept) = req
+        .headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return true;
+    };
+
+    if accept.contains("application/activity+json") || accept.contains("application/ld+json") {
+        return false;
+    }
+    accept.contains("text/html")
+}
+
+/// A note as an `<article>`, its content warning shown above the text.
+/// Content is escaped rather than trusted as markup.
+pub(crate) fn render_note_article(note: &DbNote, indent: &str) -> String {
+    let summary = note
+        .summary
+        .as_deref()
+        .map(|s| format!("\n{indent}  <p class=\"summary\">{}</p>", escape_html(s)))
+        .unwrap_or_default();
+    let published = note.published.format("%Y-%m-%d %H:%M UTC");
+
+    format!(
+        "{indent}<article>{summary}\n{indent}  <p class=\"content\">{content}</p>\n{indent}  <a href=\"{id}\"><time datetime=\"{datetime}\">{published}</time></a>\n{indent}</article>",
+        content = escape_html(&note.content),
+        id = escape_html(&note.id),
+        datetime = note.published.to_rfc3339(),
+    )
+}
+
+pub(crate) fn escape_html(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
@@ -1,18 +1,209 @@
+use crate::auth::{self, AuthContext, RequireScope};
 use crate::config::Config;
-use crate::database::DatabaseRef;
-use actix_web::{post, web, HttpResponse, Result};
+use crate::database::{DatabaseRef, DbActivity, DbLike, PublishState};
+use crate::errors::FederationError;
+use crate::handlers::admin::PageQuery;
+use crate::handlers::{activity_cursor, activity_type_of, cursor_param};
+use crate::http::client::HttpClient;
+use crate::http::{client_ip, content_type, pagination, ActivityPayload};
+use crate::models::addressing::normalize_audience;
+use crate::models::{OrderedCollection, Visibility};
+use crate::services::backpressure::InboxBackpressure;
+use crate::services::delivery::DeliveryService;
+use crate::services::groups;
+use crate::services::inbox_stream::InboxStream;
+use crate::services::log_dedup::LogDedup;
+use crate::services::pending_accepts;
+use crate::services::processing_trace::{ProcessingTrace, Stage};
+use crate::services::published::resolve_published;
+use crate::services::relay::RelayService;
+use crate::services::scheduler::SystemClock;
+use crate::services::seen_activities::SeenActivities;
+use crate::services::threads::{self, ThreadPosition};
+use crate::urls::UrlBuilder;
+use actix_web::http::header;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Result};
+use serde::Deserialize;
 use serde_json::Value;
-use tracing::{info, warn};
+use tracing::{debug, info, instrument, warn};
+
+#[derive(Debug, Deserialize)]
+pub struct InboxQuery {
+    limit: Option<u32>,
+    offset: Option<u32>,
+    /// Mastodon-style paging: `max_id` pages back from an activity, `min_id`
+    /// forward from one. Both take the activity's id.
+    max_id: Option<String>,
+    min_id: Option<String>,
+}
+
+impl InboxQuery {
+    fn page(&self) -> PageQuery {
+        PageQuery {
+            limit: self.limit,
+            offset: self.offset,
+        }
+    }
+}
+
+/// Activities addressed to the actor, newest first. Only the actor's own
+/// tokens may read it.
+#[get(
+    "/users/{username}/inbox",
+    wrap = "RequireScope(auth::READ_STATUSES_SCOPE)"
+)]
+#[instrument(skip(req, auth, urls, db))]
+pub async fn get_inbox(
+    req: HttpRequest,
+    auth: AuthContext,
+    path: web::Path<String>,
+    query: web::Query<InboxQuery>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let username = path.into_inner();
+
+    let actor = match db.get_actor_by_username(&username).await {
+        Ok(Some(actor)) => actor,
+        Ok(None) => return Err(FederationError::ActorNotFound.into()),
+        Err(e) => {
+            warn!("Database error while fetching actor {}: {}", username, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+    auth.ensure_actor(&actor.id)?;
+
+    // Only activities in this inbox, so a cursor can't probe for others
+    let in_inbox = |activity: &DbActivity| {
+        activity.state == PublishState::Published
+            && (activity.to_recipients.contains(&actor.id)
+                || activity.cc_recipients.contains(&actor.id))
+    };
+    let max = match &query.max_id {
+        Some(id) => Some(activity_cursor(&db, id, in_inbox).await?),
+        None => None,
+    };
+    let min = match &query.min_id {
+        Some(id) => Some(activity_cursor(&db, id, in_inbox).await?),
+        None => None,
+    };
+    // Pages go by cursor, so they don't shift as activities arrive. Offsets
+    // are still served for links handed out before.
+    let page = query.page();
+    let limit = page.limit();
+    let paginating = max.is_some() || min.is_some();
+    let by_cursor = page.offset() == 0 || paginating;
+
+    let activities = match (&min, &max) {
+        (Some((published, id)), _) => {
+            db.get_inbox_activities_after(&actor.id, *published, id, limit)
+                .await
+        }
+        (None, Some((published, id))) => {
+            db.get_inbox_activities_before(&actor.id, *published, id, limit)
+                .await
+        }
+        (None, None) => {
+            db.get_inbox_activities(&actor.id, limit, page.offset())
+                .await
+        }
+    };
+    let mut activities = match activities {
+        Ok(activities) => activities,
+        Err(e) => {
+            warn!("Database error while fetching inbox of {}: {}", username, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+    if let (Some(_), Some((published, id))) = (&min, &max) {
+        activities.retain(|activity| (activity.published, &activity.id) < (*published, id));
+    }
+    let total_items = match db.count_inbox_activities(&actor.id).await {
+        Ok(count) => count,
+        Err(e) => {
+            warn!("Database error while counting inbox of {}: {}", username, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let links = if by_cursor {
+        let (next, prev) = pagination::id_page_urls(
+            &urls.inbox(&username),
+            req.query_string(),
+            activities
+                .first()
+                .map(|activity| cursor_param(&activity.id))
+                .as_deref(),
+            activities
+                .last()
+                .map(|activity| cursor_param(&activity.id))
+                .as_deref(),
+            activities.len() as u32 == limit || min.is_some(),
+            paginating,
+            &[("offset", None)],
+        );
+        pagination::link_header(next.as_deref(), prev.as_deref())
+    } else {
+        pagination::offset_links(
+            &urls.inbox(&username),
+            req.query_string(),
+            page.offset(),
+            limit,
+            activities.len(),
+            &[],
+        )
+    };
+    let items: Vec<Value> = activities
+        .into_iter()
+        .map(|activity| {
+            serde_json::json!({
+                "id": activity.id,
+                "type": activity.activity_type,
+                "actor": activity.actor_id,
+                "object": activity.object,
+                "to": activity.to_recipients,
+                "cc": activity.cc_recipients,
+                "published": activity.published
+            })
+        })
+        .collect();
+
+    let collection = OrderedCollection::new(urls.inbox(&username), total_items, items);
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type(content_type::negotiate_request(&req).as_str())
+        .insert_header((header::VARY, "Accept"));
+    if let Some(links) = links {
+        response.insert_header((header::LINK, links));
+    }
+    Ok(response.json(collection))
+}
 
 #[post("/users/{username}/inbox")]
+#[allow(clippy::too_many_arguments)]
+#[instrument(
+    skip(req, payload, config, urls, db, http_client, seen, backpressure, warnings, delivery),
+    fields(activity_type = %activity_type_of(&payload))
+)]
 pub async fn inbox(
+    req: HttpRequest,
     path: web::Path<String>,
-    payload: web::Json<Value>,
+    payload: ActivityPayload,
     config: web::Data<Config>,
+    urls: web::Data<UrlBuilder>,
     db: web::Data<DatabaseRef>,
+    http_client: web::Data<dyn HttpClient>,
+    seen: web::Data<SeenActivities>,
+    backpressure: web::Data<InboxBackpressure>,
+    warnings: web::Data<LogDedup>,
+    delivery: web::Data<DeliveryService>,
 ) -> Result<HttpResponse> {
     let username = path.into_inner();
     let activity = payload.into_inner();
+    let mut trace = ProcessingTrace::sample(&activity, config.trace_sample_rate);
+    trace.record(Stage::Parsed, "ok");
+    // Deliveries aren't signed yet, so there is nothing to verify
+    trace.record_with(Stage::Signature, "skipped", "signatures are not verified");
 
     info!(
         "Received activity in inbox for user {}: {:?}",
@@ -24,21 +215,81 @@ pub async fn inbox(
         Ok(Some(actor)) => actor,
         Ok(None) => {
             warn!("Target actor not found for inbox: {}", username);
-            return Ok(HttpResponse::NotFound().json(serde_json::json!({
-                "error": "Actor not found"
-            })));
+            return Err(FederationError::ActorNotFound.into());
         }
         Err(e) => {
             warn!(
                 "Database error while fetching target actor {}: {}",
                 username, e
             );
-            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Internal server error"
-            })));
+            return Err(FederationError::DatabaseError(e).into());
         }
     };
 
+    // Every activity counts against its sending address's hourly byte quota,
+    // measured as sent where the sender said, or as re-serialized otherwise
+    let size = req
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_else(|| activity.to_string().len() as u64);
+    let sender = activity.get("actor").and_then(|v| v.as_str());
+    let peer = client_ip(&req, &config.trusted_proxies);
+    backpressure.charge(&db, peer, sender, size).await?;
+
+    // Actors the recipient has blocked cannot reach them at all
+    if let Some(sender) = sender {
+        match db.is_blocked(&target_actor.id, sender).await {
+            Ok(false) => {}
+            Ok(true) => {
+                info!(
+                    "Rejecting activity from {} blocked by {}",
+                    sender, target_actor.id
+                );
+                trace.record_with(Stage::Audience, "rejected", format!("{sender} is blocked"));
+                trace.save(&db).await;
+                return Err(FederationError::Forbidden(format!(
+                    "{sender} is blocked by {}",
+                    target_actor.id
+                ))
+                .into());
+            }
+            Err(e) => {
+                warn!("Database error while checking block of {}: {}", sender, e);
+                trace.record_with(Stage::Audience, "failed", &e);
+                trace.save(&db).await;
+                return Err(FederationError::DatabaseError(e).into());
+            }
+        }
+    }
+    trace.record(Stage::Audience, "ok");
+
+    // Held until the activity is processed. A saturated inbox turns senders
+    // away with 503 and Retry-After, unless we follow their host.
+    let _permit = backpressure.admit(&db, peer, sender).await?;
+
+    // Redeliveries and relay echoes stop here. The filter only remembers
+    // recent ids, so a miss can't rule out a replay of an older one: every id
+    // is checked against the database, and a hit only marks a relay echo.
+    let activity_id = activity.get("id").and_then(|v| v.as_str());
+    if let Some(activity_id) = activity_id {
+        if seen.might_contain(activity_id) {
+            debug!("Activity {} may have been seen recently", activity_id);
+        }
+        match db.get_activity_by_id(activity_id).await {
+            Ok(Some(_)) => {
+                info!("Ignoring already processed activity {}", activity_id);
+                trace.record(Stage::Dedupe, "duplicate");
+                trace.save(&db).await;
+                return Ok(HttpResponse::Accepted().finish());
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Database error while checking for {}: {}", activity_id, e),
+        }
+    }
+    trace.record(Stage::Dedupe, "ok");
+
     // Extract activity type
     if let Some(activity_type) = activity.get("type").and_then(|v| v.as_str()) {
         match activity_type {
@@ -50,64 +301,70 @@ pub async fn inbox(
                         if object_type == "Note" {
                             info!("Received Note: {:?}", object);
 
-                            // Extract note data
                             let note_id = object
                                 .get("id")
                                 .and_then(|v| v.as_str())
                                 .unwrap_or("")
                                 .to_string();
-                            let attributed_to = object
-                                .get("attributedTo")
-                                .and_then(|v| v.as_str())
-                                .unwrap_or("")
-                                .to_string();
-                            let content = object
-                                .get("content")
-                                .and_then(|v| v.as_str())
-                                .unwrap_or("")
-                                .to_string();
-                            let to_recipients = object
-                                .get("to")
-                                .and_then(|v| v.as_array())
-                                .map(|arr| {
-                                    arr.iter()
-                                        .filter_map(|v| v.as_str().map(|s| s.to_string()))
-                                        .collect()
-                                })
-                                .unwrap_or_else(Vec::new);
-                            let cc_recipients = object
-                                .get("cc")
-                                .and_then(|v| v.as_array())
-                                .map(|arr| {
-                                    arr.iter()
-                                        .filter_map(|v| v.as_str().map(|s| s.to_string()))
-                                        .collect()
-                                })
-                                .unwrap_or_else(Vec::new);
+
+                            // The note and its activity share one timestamp
+                            let published = resolve_published(
+                                object.get("published").or(activity.get("published")),
+                                &SystemClock,
+                            );
 
                             // Create the note in database if it doesn't exist
-                            if let Ok(None) = db.get_note_by_id(&note_id).await {
-                                let db_note = crate::database::DbNote {
-                                    id: note_id.clone(),
-                                    attributed_to,
-                                    content,
-                                    to_recipients: to_recipients.clone(),
-                                    cc_recipients: cc_recipients.clone(),
-                                    published: object
-                                        .get("published")
-                                        .and_then(|v| v.as_str())
-                                        .and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok())
-                                        .map(|dt| dt.with_timezone(&chrono::Utc))
-                                        .unwrap_or_else(chrono::Utc::now),
-                                    in_reply_to: object
-                                        .get("inReplyTo")
-                                        .and_then(|v| v.as_str().map(|s| s.to_string())),
-                                    tags: vec![], // TODO: Extract tags from object
-                                    created_at: chrono::Utc::now(),
+                            let existing = db.get_note_by_id(&note_id).await;
+                            if let Ok(None) = existing {
+                                let in_reply_to = object.get("inReplyTo").and_then(|v| v.as_str());
+
+                                // Pull in the remote thread above a reply so it
+                                // can be shown in context and placed in it
+                                if let Some(parent) = in_reply_to {
+                                    threads::fetch_ancestors(
+                                        &db,
+                                        http_client.get_ref(),
+                                        &urls,
+                                        parent,
+                                        config.reply_fetch_depth,
+                                        config.max_thread_depth,
+                                    )
+                                    .await;
+                                }
+
+                                let thread = match threads::position_of(
+                                    &db,
+                                    in_reply_to,
+                                    None,
+                                    config.max_thread_depth,
+                                )
+                                .await
+                                {
+                                    Ok(thread) => thread,
+                                    Err(e) => {
+                                        warn!(
+                                            "Database error while placing {} in its thread: {}",
+                                            note_id, e
+                                        );
+                                        ThreadPosition::default()
+                                    }
                                 };
+                                let db_note = threads::note_from_object(object, published, thread);
 
-                                if let Err(e) = db.create_note(&db_note).await {
-                                    warn!("Database error while creating note from inbox: {}", e);
+                                match db.create_note(&db_note).await {
+                                    Ok(()) => trace.record(Stage::StoredNote, "ok"),
+                                    Err(e) => {
+                                        warn!(
+                                            "Database error while creating note from inbox: {}",
+                                            e
+                                        );
+                                        trace.record_with(Stage::StoredNote, "failed", e);
+                                    }
+                                }
+                            } else {
+                                match existing {
+                                    Err(e) => trace.record_with(Stage::StoredNote, "failed", e),
+                                    _ => trace.record(Stage::StoredNote, "exists"),
                                 }
                             }
 
@@ -122,43 +379,79 @@ pub async fn inbox(
                                 .and_then(|v| v.as_str())
                                 .unwrap_or("")
                                 .to_string();
-                            let activity_to = activity
-                                .get("to")
-                                .and_then(|v| v.as_array())
-                                .map(|arr| {
-                                    arr.iter()
-                                        .filter_map(|v| v.as_str().map(|s| s.to_string()))
-                                        .collect()
-                                })
-                                .unwrap_or_default();
-                            let activity_cc = activity
-                                .get("cc")
-                                .and_then(|v| v.as_array())
-                                .map(|arr| {
-                                    arr.iter()
-                                        .filter_map(|v| v.as_str().map(|s| s.to_string()))
-                                        .collect()
-                                })
-                                .unwrap_or_default();
+                            let activity_to = normalize_audience(
+                                activity
+                                    .get("to")
+                                    .and_then(|v| v.as_array())
+                                    .map(|arr| {
+                                        arr.iter()
+                                            .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                                            .collect()
+                                    })
+                                    .unwrap_or_default(),
+                            );
+                            let activity_cc = normalize_audience(
+                                activity
+                                    .get("cc")
+                                    .and_then(|v| v.as_array())
+                                    .map(|arr| {
+                                        arr.iter()
+                                            .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                                            .collect()
+                                    })
+                                    .unwrap_or_default(),
+                            );
 
+                            let activity_visibility =
+                                Visibility::from_addressing(&activity_to, &activity_cc);
                             let db_activity = crate::database::DbActivity {
                                 id: activity_id,
                                 actor_id,
                                 activity_type: "Create".to_string(),
                                 object: object.clone(),
+                                target: None,
                                 to_recipients: activity_to,
                                 cc_recipients: activity_cc,
-                                published: activity
-                                    .get("published")
-                                    .and_then(|v| v.as_str())
-                                    .and_then(|s| chrono::DateTime::parse_from_rfc3339(s).ok())
-                                    .map(|dt| dt.with_timezone(&chrono::Utc))
-                                    .unwrap_or_else(chrono::Utc::now),
+                                published,
+                                visibility: activity_visibility,
+                                state: PublishState::Published,
                                 created_at: chrono::Utc::now(),
                             };
 
-                            if let Err(e) = db.create_activity(&db_activity).await {
-                                warn!("Database error while creating activity from inbox: {}", e);
+                            match db.create_activity(&db_activity).await {
+                                Ok(()) => {
+                                    trace.record(Stage::StoredActivity, "ok");
+                                    // Live clients of the recipient hear about it
+                                    if let Some(stream) = req.app_data::<web::Data<InboxStream>>() {
+                                        stream.publish(&target_actor.id, activity.clone());
+                                        trace.record(Stage::Notifications, "ok");
+                                    } else {
+                                        trace.record(Stage::Notifications, "skipped");
+                                    }
+                                }
+                                Err(e) => {
+                                    warn!(
+                                        "Database error while creating activity from inbox: {}",
+                                        e
+                                    );
+                                    trace.record_with(Stage::StoredActivity, "failed", e);
+                                }
+                            }
+
+                            // Groups pass on what is posted to them
+                            if groups::is_group_actor(&target_actor) {
+                                groups::boost_mention(
+                                    &db,
+                                    delivery.into_inner(),
+                                    &urls,
+                                    &target_actor,
+                                    object,
+                                    activity_visibility,
+                                )
+                                .await;
+                                trace.record(Stage::Forwarded, "ok");
+                            } else {
+                                trace.record(Stage::Forwarded, "skipped");
                             }
                         }
                     }
@@ -185,8 +478,7 @@ pub async fn inbox(
 
                 // Check if this is targeting our actor
                 if following_id == target_actor.id {
-                    let follow_id =
-                        format!("{}/follows/{}", config.server_url, uuid::Uuid::new_v4());
+                    let follow_id = urls.follow(&uuid::Uuid::new_v4().to_string());
                     let db_follow = crate::database::DbFollowRelation {
                         id: follow_id,
                         follower_id,
@@ -200,6 +492,12 @@ pub async fn inbox(
                         warn!("Database error while creating follow relationship: {}", e);
                     } else {
                         info!("Created follow relationship: {:?}", db_follow);
+                        // An Accept or Undo for this pair may have beaten the Follow here
+                        if let Err(e) =
+                            pending_accepts::apply_parked_activities(&db, &db_follow).await
+                        {
+                            warn!("Database error while applying parked activities: {}", e);
+                        }
                         // TODO: Auto-accept or require manual approval
                     }
                 }
@@ -208,15 +506,154 @@ pub async fn inbox(
                 info!("Processing Accept activity");
                 // Handle Accept activity (response to Follow)
                 if let Some(object) = activity.get("object") {
-                    if let Some(follow_id) = object.get("id").and_then(|v| v.as_str()) {
-                        if let Err(e) = db.update_follow_status(follow_id, "accepted").await {
-                            warn!("Database error while updating follow status: {}", e);
-                        } else {
-                            info!("Updated follow status to accepted for: {}", follow_id);
+                    let follow_ref = object
+                        .get("id")
+                        .and_then(|v| v.as_str())
+                        .or_else(|| object.as_str());
+                    let follower_id = object
+                        .get("actor")
+                        .and_then(|v| v.as_str())
+                        .unwrap_or(&target_actor.id);
+                    let following_id = activity.get("actor").and_then(|v| v.as_str()).unwrap_or("");
+
+                    // Match by follow id first, then fall back to the actor pair
+                    let follow = match follow_ref {
+                        Some(follow_id) => db.get_follow_by_id(follow_id).await,
+                        None => Ok(None),
+                    };
+                    let follow = match follow {
+                        Ok(None) => db.get_follow_by_actors(follower_id, following_id).await,
+                        other => other,
+                    };
+
+                    match follow {
+                        Ok(Some(follow)) => {
+                            // Only the followed actor accepts, and only a follow by this inbox's owner
+                            if follow.following_id != following_id
+                                || follow.follower_id != target_actor.id
+                            {
+                                info!("Rejecting Accept of {} from {}", follow.id, following_id);
+                                trace.save(&db).await;
+                                return Err(FederationError::Forbidden(format!(
+                                    "{following_id} cannot accept {}",
+                                    follow.id
+                                ))
+                                .into());
+                            }
+                            if let Err(e) = db.update_follow_status(&follow.id, "accepted").await {
+                                warn!("Database error while updating follow status: {}", e);
+                            } else {
+                                info!("Updated follow status to accepted for: {}", follow.id);
+                            }
+                        }
+                        Ok(None) => {
+                            // Relays accept the Follow of Public that subscribed to them
+                            let relay_accepted = match follow_ref {
+                                Some(follow_id) => {
+                                    match RelayService::load(
+                                        db.get_ref().clone(),
+                                        delivery.into_inner(),
+                                    )
+                                    .await
+                                    {
+                                        Ok(relays) => relays.accept(follow_id, following_id).await,
+                                        Err(e) => Err(e),
+                                    }
+                                }
+                                None => Ok(false),
+                            };
+                            match relay_accepted {
+                                Ok(true) => {}
+                                Ok(false) => {
+                                    // The Follow may not be committed yet; hold on to the Accept
+                                    if let Err(e) = pending_accepts::park_activity(
+                                        &db,
+                                        "Accept",
+                                        follower_id,
+                                        following_id,
+                                        &activity,
+                                        chrono::Duration::seconds(
+                                            config.pending_activity_ttl_secs as i64,
+                                        ),
+                                    )
+                                    .await
+                                    {
+                                        warn!("Database error while parking Accept: {}", e);
+                                    }
+                                }
+                                Err(e) => {
+                                    warn!("Database error while checking relays for Accept: {}", e);
+                                }
+                            }
+                        }
+                        Err(e) => {
+                            warn!("Database error while looking up follow for Accept: {}", e);
                         }
                     }
                 }
             }
+            "Like" => {
+                info!("Processing Like activity");
+                let liker = activity.get("actor").and_then(|v| v.as_str());
+                let object_id = activity.get("object").and_then(object_id_of);
+
+                if let (Some(liker), Some(object_id)) = (liker, object_id) {
+                    // Only likes of our own notes are counted
+                    match db.get_note_by_id(object_id).await {
+                        Ok(Some(_)) => {
+                            let db_like = DbLike {
+                                id: activity
+                                    .get("id")
+                                    .and_then(|v| v.as_str())
+                                    .map(String::from)
+                                    .unwrap_or_else(|| {
+                                        urls.like(&uuid::Uuid::new_v4().to_string())
+                                    }),
+                                actor_id: liker.to_string(),
+                                object_id: object_id.to_string(),
+                                created_at: chrono::Utc::now(),
+                            };
+                            if let Err(e) = db.create_like(&db_like).await {
+                                warn!("Database error while storing like: {}", e);
+                            }
+                        }
+                        Ok(None) => info!("Ignoring Like of unknown object {}", object_id),
+                        Err(e) => warn!("Database error while fetching liked note: {}", e),
+                    }
+                }
+            }
+            "Announce" => {
+                let announcer = activity
+                    .get("actor")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("unknown");
+                // Only relays we subscribe to have their Announces stored
+                match RelayService::load(db.get_ref().clone(), delivery.into_inner()).await {
+                    Ok(relays) if relays.is_relay_actor(announcer) => {
+                        info!("Processing Announce from relay {}", announcer);
+                        relays
+                            .receive_announce(
+                                &activity,
+                                http_client.get_ref(),
+                                &urls,
+                                config.max_thread_depth,
+                            )
+                            .await;
+                    }
+                    Ok(_) => info!("Ignoring Announce from {}, which is not a relay", announcer),
+                    Err(e) => warn!("Database error while loading relays: {}", e),
+                }
+            }
+            "Block" => {
+                info!("Processing Block activity");
+                let blocker = activity.get("actor").and_then(|v| v.as_str());
+                let blocked = activity.get("object").and_then(object_id_of);
+                if let (Some(blocker), Some(blocked)) = (blocker, blocked) {
+                    if blocked == target_actor.id {
+                        remove_follows_between(&db, blocker, blocked).await;
+                    }
+                }
+            }
             "Undo" => {
                 info!("Processing Undo activity");
                 // Handle Undo activity
@@ -230,23 +667,125 @@ pub async fn inbox(
                                 object.get("object").and_then(|v| v.as_str()).unwrap_or("");
 
                             if following_id == target_actor.id {
-                                // Find and delete the follow relationship
-                                // This is a simplified approach - in practice you'd query for the specific follow
                                 info!(
                                     "Processing unfollow from {} to {}",
                                     follower_id, following_id
                                 );
+                                match db.get_follow_by_actors(follower_id, following_id).await {
+                                    Ok(Some(follow)) => {
+                                        if let Err(e) = db.delete_follow(&follow.id).await {
+                                            warn!("Database error while deleting follow: {}", e);
+                                        }
+                                    }
+                                    Ok(None) => {
+                                        // Undo raced ahead of its Follow; apply it once the Follow lands
+                                        if let Err(e) = pending_accepts::park_activity(
+                                            &db,
+                                            "Undo",
+                                            follower_id,
+                                            following_id,
+                                            &activity,
+                                            chrono::Duration::seconds(
+                                                config.pending_activity_ttl_secs as i64,
+                                            ),
+                                        )
+                                        .await
+                                        {
+                                            warn!("Database error while parking Undo: {}", e);
+                                        }
+                                    }
+                                    Err(e) => {
+                                        warn!(
+                                            "Database error while looking up follow for Undo: {}",
+                                            e
+                                        );
+                                    }
+                                }
                             }
+                        } else if object_type == "Like" {
+                            undo_like(&db, &activity, object).await;
                         }
                     }
                 }
             }
             _ => {
-                warn!("Unknown activity type: {}", activity_type);
+                let sender = activity
+                    .get("actor")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or("unknown");
+                warnings.warn(
+                    "unknown_activity_type",
+                    sender,
+                    format_args!("Unknown activity type {} from {}", activity_type, sender),
+                );
             }
         }
     }
 
+    if let Some(activity_id) = activity_id {
+        seen.insert(activity_id);
+    }
+    trace.save(&db).await;
+
     // Always return 202 Accepted for inbox POST requests
     Ok(HttpResponse::Accepted().finish())
 }
+
+/// Remove a like when its author takes it back. Undoing a like that was never
+/// stored is a no-op.
+async fn undo_like(db: &DatabaseRef, activity: &Value, like: &Value) {
+    let Some(actor_id) = activity.get("actor").and_then(|v| v.as_str()) else {
+        return;
+    };
+    let Some(object_id) = like.get("object").and_then(object_id_of) else {
+        return;
+    };
+
+    // Only the original liker may take a like back
+    if let Some(liker) = like.get("actor").and_then(|v| v.as_str()) {
+        if liker != actor_id {
+            warn!("{} tried to undo a Like by {}", actor_id, liker);
+            return;
+        }
+    }
+
+    match db.get_like(actor_id, object_id).await {
+        Ok(Some(stored)) => {
+            if let Err(e) = db.delete_like(&stored.actor_id, &stored.object_id).await {
+                warn!("Database error while deleting like: {}", e);
+            } else {
+                info!("{} unliked {}", actor_id, object_id);
+            }
+        }
+        Ok(None) => info!("No like by {} on {} to undo", actor_id, object_id),
+        Err(e) => warn!("Database error while looking up like for Undo: {}", e),
+    }
+}
+
+/// Drop follows in both directions once one of the pair blocks the other.
+/// The blocker's server enforces the block itself; following on is pointless.
+async fn remove_follows_between(db: &DatabaseRef, a: &str, b: &str) {
+    for (follower, following) in [(a, b), (b, a)] {
+        match db.get_follow_by_actors(follower, following).await {
+            Ok(Some(follow)) => {
+                if let Err(e) = db.delete_follow(&follow.id).await {
+                    warn!("Database error while deleting follow {}: {}", follow.id, e);
+                } else {
+                    info!(
+                        "Removed follow of {} by {} after Block",
+                        following, follower
+                    );
+                }
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Database error while looking up follow for Block: {}", e),
+        }
+    }
+}
+
+/// The id of an activity's object, which may be a bare IRI or an embedded object
+fn object_id_of(object: &Value) -> Option<&str> {
+    object
+        .as_str()
+        .or_else(|| object.get("id").and_then(|v| v.as_str()))
+}
@@ -0,0 +1,20 @@
+use crate::config::Config;
+use crate::errors::FederationError;
+use crate::metrics::Metrics;
+use actix_web::{get, web, HttpResponse, Result};
+
+/// Prometheus scrape target, in the text exposition format. Not served when
+/// metrics are disabled.
+#[get("/metrics")]
+pub async fn metrics(
+    config: web::Data<Config>,
+    metrics: web::Data<Metrics>,
+) -> Result<HttpResponse> {
+    if !config.metrics_enabled {
+        return Err(FederationError::NotFound("Metrics are disabled".to_string()).into());
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .body(metrics.render()))
+}
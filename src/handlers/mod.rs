@@ -1,4 +1,150 @@
 pub mod actor;
+pub mod admin;
+pub mod api;
+pub mod capabilities;
+pub mod collections;
+pub mod cors;
+pub mod feed;
+pub mod health;
+pub mod host_meta;
+pub(crate) mod html;
 pub mod inbox;
+pub mod metrics;
+pub mod note;
 pub mod outbox;
 pub mod webfinger;
+pub(crate) mod xml;
+
+use crate::config::Config;
+use crate::database::{DatabaseRef, DbActivity, DbNote};
+use crate::errors::FederationError;
+use crate::http::HttpClient;
+use crate::metrics::Metrics;
+use crate::services::actor_fetch::ActorFetchService;
+use crate::services::parse_failures::ParseFailureRecorder;
+use crate::urls::UrlBuilder;
+use actix_web::{web, HttpRequest};
+use chrono::{DateTime, Utc};
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::warn;
+
+/// Records remote documents fetched while serving `req` that fail to parse,
+/// counted in the app's metrics when it has them
+pub(crate) fn parse_failure_recorder(req: &HttpRequest, db: &DatabaseRef) -> ParseFailureRecorder {
+    let metrics = req
+        .app_data::<web::Data<Metrics>>()
+        .map(|metrics| metrics.clone().into_inner());
+    ParseFailureRecorder::new(db.clone(), metrics)
+}
+
+/// The app's remote actor cache, or one over `db` and `http_client` when the
+/// app was built without it
+pub(crate) fn actor_fetch(
+    req: &HttpRequest,
+    config: &Config,
+    db: &DatabaseRef,
+    http_client: &web::Data<dyn HttpClient>,
+) -> Arc<ActorFetchService> {
+    match req.app_data::<web::Data<ActorFetchService>>() {
+        Some(actor_fetch) => actor_fetch.clone().into_inner(),
+        None => Arc::new(ActorFetchService::from_config(
+            config,
+            db.clone(),
+            http_client.clone().into_inner(),
+            parse_failure_recorder(req, db),
+        )),
+    }
+}
+
+/// Activity `type` for span fields, or `unknown` when missing
+pub(crate) fn activity_type_of(activity: &Value) -> &str {
+    activity
+        .get("type")
+        .and_then(|v| v.as_str())
+        .unwrap_or("unknown")
+}
+
+/// Where a `max_id`/`min_id` page of activities starts or stops: an
+/// activity's publication time, with its id to break ties
+pub(crate) type ActivityCursor = (DateTime<Utc>, String);
+
+/// The cursor at activity `id`. Activities that aren't stored or that
+/// `visible` turns down are refused alike, so paging can't be used to probe
+/// for hidden ones.
+pub(crate) async fn activity_cursor(
+    db: &DatabaseRef,
+    id: &str,
+    visible: impl Fn(&DbActivity) -> bool,
+) -> actix_web::Result<ActivityCursor> {
+    match db.get_activity_by_id(id).await {
+        Ok(Some(activity)) if visible(&activity) => Ok((activity.published, activity.id)),
+        Ok(_) => Err(FederationError::BadRequest(format!("Unknown activity {id}")).into()),
+        Err(e) => {
+            warn!("Database error while fetching activity {}: {}", id, e);
+            Err(FederationError::DatabaseError(e).into())
+        }
+    }
+}
+
+/// Everything but the characters a query value may hold as they are
+const QUERY_VALUE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// An activity id as a `max_id`/`min_id` value in a page link. Activity ids
+/// are URLs, so they are percent-encoded to fit in a query string.
+pub(crate) fn cursor_param(id: &str) -> String {
+    utf8_percent_encode(id, QUERY_VALUE).to_string()
+}
+
+/// A note's `contentMap`, when it has more than one language to offer
+pub(crate) fn outgoing_content_map(note: &DbNote) -> Option<&Value> {
+    note.content_map
+        .as_ref()
+        .filter(|map| map.as_object().is_some_and(|map| map.len() > 1))
+}
+
+/// Render a stored note as an ActivityStreams Note object. Only our own
+/// notes link a replies collection, since we can't serve anyone else's.
+pub(crate) fn note_object(note: &DbNote, urls: &UrlBuilder) -> Value {
+    let mut object = serde_json::json!({
+        "id": note.id,
+        "type": "Note",
+        "attributedTo": note.attributed_to,
+        "content": note.content,
+        "to": note.to_recipients,
+        "cc": note.cc_recipients,
+        "published": note.published,
+    });
+    if urls.is_local(&note.id) {
+        object["replies"] = Value::String(format!("{}/replies", note.id));
+        object["likes"] = Value::String(format!("{}/likes", note.id));
+        object["shares"] = Value::String(format!("{}/shares", note.id));
+    }
+    if let Some(content_map) = outgoing_content_map(note) {
+        object["contentMap"] = content_map.clone();
+    }
+    if note.sensitive {
+        object["sensitive"] = Value::Bool(true);
+    }
+    if let Some(summary) = &note.summary {
+        object["summary"] = Value::String(summary.clone());
+    }
+    if let Some(in_reply_to) = &note.in_reply_to {
+        object["inReplyTo"] = Value::String(in_reply_to.clone());
+        // Extension fields so clients can show "replying to" without a fetch
+        object["inReplyToActor"] = serde_json::json!(note.in_reply_to_actor);
+        object["threadDepth"] = serde_json::json!(note.thread_depth);
+        if note.thread_truncated {
+            object["threadTruncated"] = Value::Bool(true);
+        }
+    }
+    if !note.attachments.is_empty() {
+        object["attachment"] = Value::Array(note.attachments.clone());
+    }
+    object
+}
@@ -0,0 +1,347 @@
+use crate::auth;
+use crate::database::{DatabaseError, DatabaseRef, DbActor, DbNote, PublishState};
+use crate::errors::FederationError;
+use crate::handlers::html::{escape_html, render_note_article, wants_html};
+use crate::handlers::note_object;
+use crate::http::{caching, content_type, pagination};
+use crate::models::{ContextBuilder, OrderedCollection, Visibility};
+use crate::services::document_cache::DocumentCache;
+use crate::urls::UrlBuilder;
+use actix_web::http::header;
+use actix_web::{get, web, HttpRequest, HttpResponse, Result};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+use tracing::{instrument, warn};
+
+/// Default and maximum number of statuses per page
+const DEFAULT_STATUSES_LIMIT: u32 = 20;
+const MAX_STATUSES_LIMIT: u32 = 40;
+
+/// Notes read per database call while filtering an actor's statuses
+const STATUSES_SCAN_SIZE: u32 = 100;
+
+/// Mastodon-style paging: `max_id` pages back from a status, `min_id`
+/// forward from one. Both take the id a note has in its URL.
+#[derive(Debug, Deserialize)]
+pub struct StatusesQuery {
+    pub max_id: Option<String>,
+    pub min_id: Option<String>,
+    pub limit: Option<u32>,
+}
+
+impl StatusesQuery {
+    fn limit(&self) -> u32 {
+        self.limit
+            .unwrap_or(DEFAULT_STATUSES_LIMIT)
+            .clamp(1, MAX_STATUSES_LIMIT)
+    }
+}
+
+/// One of our notes, as a Note document or, for browsers, an HTML page.
+/// Followers-only and direct notes are not served, and local-only ones only
+/// to viewers signed in here. Documents are served from the cache when it
+/// has them, without touching the database.
+#[get("/notes/{id}")]
+#[instrument(skip(req, urls, db, cache))]
+pub async fn get_note(
+    req: HttpRequest,
+    path: web::Path<String>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+    cache: web::Data<DocumentCache>,
+) -> Result<HttpResponse> {
+    let note_id = urls.note(&path.into_inner());
+    let wants_html = wants_html(&req);
+
+    let mut response = HttpResponse::Ok();
+    response.insert_header((header::VARY, "Accept"));
+
+    if !wants_html {
+        if let Some(document) = cache.get(&note_id) {
+            response.content_type(content_type::negotiate_request(&req).as_str());
+            return Ok(caching::respond_with_etag(
+                &req,
+                response,
+                document.body,
+                document.etag,
+                document.last_modified,
+            ));
+        }
+    }
+
+    let note = match db.get_note_by_id(&note_id).await {
+        Ok(Some(note)) if note.state == PublishState::Published => note,
+        Ok(_) => return Err(FederationError::NoteNotFound.into()),
+        Err(e) => {
+            warn!("Database error while fetching note {}: {}", note_id, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+    // Local-only notes are served to anyone signed in here, and never cached
+    let local_only = note.visibility == Visibility::Local;
+    if local_only {
+        if auth::viewer(&req).await?.is_none() {
+            return Err(FederationError::NoteNotFound.into());
+        }
+        response.insert_header((header::CACHE_CONTROL, "private"));
+        response.insert_header((header::VARY, "Accept, Authorization"));
+    } else if !note.visibility.is_publicly_visible() {
+        return Err(FederationError::NoteNotFound.into());
+    }
+
+    if wants_html {
+        let author = match db.get_actor_by_id(&note.attributed_to).await {
+            Ok(author) => author,
+            Err(e) => {
+                warn!("Database error while fetching author of {}: {}", note_id, e);
+                None
+            }
+        };
+
+        response.content_type("text/html; charset=utf-8");
+        let body = render_note_html(&note, author.as_ref());
+        return Ok(caching::respond(
+            &req,
+            response,
+            body.into_bytes(),
+            Some(note.published),
+        ));
+    }
+
+    let mut object = note_object(&note, &urls);
+    object["@context"] = ContextBuilder::for_document(&object).build().into();
+    response.content_type(content_type::negotiate_request(&req).as_str());
+    let body = serde_json::to_vec(&object)?;
+    if local_only {
+        return Ok(caching::respond(&req, response, body, Some(note.published)));
+    }
+    // Only notes anyone may fetch get this far, so only they are cached
+    let document = cache.insert(
+        &note.id,
+        Some(&note.attributed_to),
+        body,
+        Some(note.published),
+    );
+    Ok(caching::respond_with_etag(
+        &req,
+        response,
+        document.body,
+        document.etag,
+        document.last_modified,
+    ))
+}
+
+/// An actor's published notes, newest first. The actor's own token (with
+/// `read:statuses`) sees every note; other local readers also see local-only
+/// ones, and everyone else the ones addressed to Public. Sensitive notes are
+/// included, flagged `sensitive`.
+#[get("/users/{username}/statuses")]
+#[instrument(skip(req, urls, db))]
+pub async fn get_statuses(
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<StatusesQuery>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
+) -> Result<HttpResponse> {
+    let username = path.into_inner();
+
+    let actor = match db.get_actor_by_username(&username).await {
+        Ok(Some(actor)) => actor,
+        Ok(None) => return Err(FederationError::ActorNotFound.into()),
+        Err(e) => {
+            warn!("Database error while fetching actor {}: {}", username, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    // A bad token is refused rather than quietly given the public view
+    let viewer = auth::viewer(&req).await?;
+    let include_private = viewer.as_ref().is_some_and(|v| v.actor_id == actor.id);
+    let include_local = viewer.is_some();
+
+    let max = match &query.max_id {
+        Some(id) => Some(cursor(&db, &actor.id, &urls.note(id)).await?),
+        None => None,
+    };
+    let min = match &query.min_id {
+        Some(id) => Some(cursor(&db, &actor.id, &urls.note(id)).await?),
+        None => None,
+    };
+
+    let notes = match statuses_between(
+        &db,
+        &actor.id,
+        include_private,
+        include_local,
+        max.as_ref(),
+        min.as_ref(),
+        query.limit(),
+    )
+    .await
+    {
+        Ok(notes) => notes,
+        Err(e) => {
+            warn!("Database error while fetching notes of {}: {}", username, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let total_items = match db
+        .count_notes_by_actor(&actor.id, include_private, include_local)
+        .await
+    {
+        Ok(count) => count,
+        Err(e) => {
+            warn!("Database error while counting notes of {}: {}", username, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    // Links name notes by the id they have in their URL, as the query does
+    let url_id = |note: &DbNote| note.id.rsplit('/').next().unwrap_or(&note.id).to_string();
+    let links = pagination::id_links(
+        &urls.statuses(&username),
+        req.query_string(),
+        notes.first().map(url_id).as_deref(),
+        notes.last().map(url_id).as_deref(),
+        notes.len() as u32 == query.limit() || query.min_id.is_some(),
+        query.max_id.is_some() || query.min_id.is_some(),
+    );
+
+    let items: Vec<Value> = notes.iter().map(|note| note_object(note, &urls)).collect();
+    let collection = OrderedCollection::new(urls.statuses(&username), total_items, items);
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type(content_type::negotiate_request(&req).as_str())
+        .insert_header((header::VARY, "Accept, Authorization"));
+    if let Some(links) = links {
+        response.insert_header((header::LINK, links));
+    }
+    if viewer.is_some() {
+        response.insert_header((header::CACHE_CONTROL, "private"));
+    }
+    Ok(response.json(collection))
+}
+
+/// Where a page starts or stops: a note's publication time, with its id to
+/// break ties
+type Cursor = (DateTime<Utc>, String);
+
+async fn cursor(db: &DatabaseRef, actor_id: &str, note_id: &str) -> Result<Cursor> {
+    match db.get_note_by_id(note_id).await {
+        Ok(Some(note)) if note.attributed_to == actor_id => Ok((note.published, note.id)),
+        Ok(_) => Err(FederationError::BadRequest(format!("Unknown status {note_id}")).into()),
+        Err(e) => {
+            warn!("Database error while fetching note {}: {}", note_id, e);
+            Err(FederationError::DatabaseError(e).into())
+        }
+    }
+}
+
+/// Up to `limit` visible notes older than `max` and newer than `min`. With
+/// `min` the page is the one just after it, so the newest notes may be left
+/// for a later page. Notes are read by cursor from `max` down, so notes
+/// written meanwhile don't shift the scan.
+async fn statuses_between(
+    db: &DatabaseRef,
+    actor_id: &str,
+    include_private: bool,
+    include_local: bool,
+    max: Option<&Cursor>,
+    min: Option<&Cursor>,
+    limit: u32,
+) -> Result<Vec<DbNote>, DatabaseError> {
+    let mut notes = Vec::new();
+    let mut before = max.cloned();
+
+    loop {
+        let page = match &before {
+            Some((published, id)) => {
+                db.get_notes_by_actor_before(actor_id, *published, id, STATUSES_SCAN_SIZE)
+                    .await?
+            }
+            None => {
+                db.get_notes_by_actor(actor_id, STATUSES_SCAN_SIZE, 0)
+                    .await?
+            }
+        };
+        let exhausted = (page.len() as u32) < STATUSES_SCAN_SIZE;
+        before = page.last().map(|note| (note.published, note.id.clone()));
+
+        for note in page {
+            let position = (note.published, note.id.clone());
+            if min.is_some_and(|min| note.published < min.0) {
+                return Ok(page_after_min(notes, limit));
+            }
+            if note.state != PublishState::Published
+                || !(include_private
+                    || note.visibility.is_publicly_visible()
+                    || (include_local && note.visibility == Visibility::Local))
+                || min.is_some_and(|min| position <= *min)
+            {
+                continue;
+            }
+            notes.push(note);
+            if min.is_none() && notes.len() as u32 == limit {
+                return Ok(notes);
+            }
+        }
+
+        if exhausted {
+            return Ok(page_after_min(notes, limit));
+        }
+    }
+}
+
+/// The oldest `limit` of `notes`, still newest first
+fn page_after_min(mut notes: Vec<DbNote>, limit: u32) -> Vec<DbNote> {
+    let excess = notes.len().saturating_sub(limit as usize);
+    notes.drain(..excess);
+    notes
+}
+
+fn render_note_html(note: &DbNote, author: Option<&DbActor>) -> String {
+    let (title, byline) = match author {
+        Some(author) => {
+            let name = escape_html(&author.name);
+            let username = escape_html(&author.username);
+            (
+                format!("{name} (@{username})"),
+                format!(
+                    "\n  <p class=\"author\"><a href=\"{}\">{name}</a> @{username}</p>",
+                    escape_html(&author.id)
+                ),
+            )
+        }
+        None => (escape_html(&note.attributed_to), String::new()),
+    };
+    let in_reply_to = note
+        .in_reply_to
+        .as_deref()
+        .map(|parent| {
+            format!(
+                "\n  <p class=\"in-reply-to\">In reply to <a href=\"{0}\">{0}</a></p>",
+                escape_html(parent)
+            )
+        })
+        .unwrap_or_default();
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>{title}</title>
+  <link rel="alternate" type="application/activity+json" href="{id}">
+</head>
+<body>{byline}{in_reply_to}
+{article}
+</body>
+</html>
+"#,
+        id = escape_html(&note.id),
+        article = render_note_article(note, "  "),
+    )
+}
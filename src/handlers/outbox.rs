@@ -1,95 +1,418 @@
+use crate::auth::{self, AuthContext, RequireScope};
 use crate::config::Config;
-use crate::database::DatabaseRef;
-use crate::models::OrderedCollection;
-use actix_web::{get, post, web, HttpResponse, Result};
+use crate::database::{
+    DatabaseError, DatabaseRef, DbActivity, DbActor, DbBlock, DbFollowRelation, DbNote,
+    DeliveryPriority, PublishState,
+};
+use crate::errors::FederationError;
+use crate::handlers::{
+    activity_cursor, activity_type_of, actor_fetch, cursor_param, note_object,
+    outgoing_content_map, ActivityCursor,
+};
+use crate::http::{
+    caching, content_type, pagination, request_id, ActivityPayload, ContentType, HttpClient,
+};
+use crate::models::addressing::{is_public, normalize_audience, PUBLIC};
+use crate::models::object::{Attachment, Note, Tag};
+use crate::models::{
+    ContextBuilder, OrderedCollection, OrderedCollectionPage, PagedOrderedCollection, Visibility,
+};
+use crate::services::actor_fetch::ActorFetchService;
+use crate::services::classify::{self, ContentClassifier};
+use crate::services::delivery::DeliveryService;
+use crate::services::events::{EventBus, ObjectEvent};
+use crate::services::scheduler::SystemClock;
+use crate::services::webfinger::{self, WebFingerResolver};
+use crate::services::{addressing, delivery_queue, pending_accepts, published, threads};
+use crate::urls::UrlBuilder;
+use actix_web::http::header;
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Result};
+use serde::Deserialize;
 use serde_json::Value;
-use tracing::{info, warn};
+use std::collections::HashMap;
+use tracing::{info, instrument, warn};
+
+/// Activities per outbox page
+const OUTBOX_PAGE_SIZE: u32 = 20;
+
+#[derive(Debug, Deserialize)]
+pub struct OutboxQuery {
+    #[serde(default)]
+    page: bool,
+    #[serde(default)]
+    offset: u32,
+    /// Mastodon-style paging: `max_id` pages back from an activity, `min_id`
+    /// forward from one. Both take the activity's id.
+    max_id: Option<String>,
+    min_id: Option<String>,
+    /// Comma-separated activity types to keep, e.g. `Create,Announce`
+    #[serde(default, rename = "type")]
+    types: Option<String>,
+}
+
+impl OutboxQuery {
+    /// The requested activity types, or `None` when the outbox is unfiltered.
+    /// Types are plain ActivityStreams names, so anything else is rejected.
+    fn activity_types(&self) -> Result<Option<Vec<String>>, String> {
+        let Some(raw) = self.types.as_deref() else {
+            return Ok(None);
+        };
+        let mut types = Vec::new();
+        for name in raw.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            if !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+                return Err(format!("Invalid activity type: {name}"));
+            }
+            types.push(name.to_string());
+        }
+        Ok((!types.is_empty()).then_some(types))
+    }
+}
 
 #[get("/users/{username}/outbox")]
+#[instrument(skip(req, config, urls, db))]
 pub async fn get_outbox(
+    req: HttpRequest,
     path: web::Path<String>,
+    query: web::Query<OutboxQuery>,
     config: web::Data<Config>,
+    urls: web::Data<UrlBuilder>,
     db: web::Data<DatabaseRef>,
 ) -> Result<HttpResponse> {
     let username = path.into_inner();
+    let offset = if query.page { query.offset } else { 0 };
+    let types = match query.activity_types() {
+        Ok(types) => types,
+        Err(message) => {
+            return Err(FederationError::BadRequest(message).into());
+        }
+    };
+    let paginating = query.max_id.is_some() || query.min_id.is_some();
+    if paginating && types.is_some() {
+        return Err(FederationError::BadRequest(
+            "max_id and min_id can't be combined with type".to_string(),
+        )
+        .into());
+    }
 
     // First, get the actor to make sure they exist
     let actor = match db.get_actor_by_username(&username).await {
         Ok(Some(actor)) => actor,
         Ok(None) => {
             warn!("Actor not found for outbox: {}", username);
-            return Ok(HttpResponse::NotFound().json(serde_json::json!({
-                "error": "Actor not found"
-            })));
+            return Err(FederationError::ActorNotFound.into());
         }
         Err(e) => {
             warn!("Database error while fetching actor {}: {}", username, e);
-            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Internal server error"
-            })));
+            return Err(FederationError::DatabaseError(e).into());
         }
     };
 
+    // Only the owner sees followers-only and direct items
+    let is_owner = matches!(auth::authenticate(&req).await, Ok(auth) if auth.actor_id == actor.id);
+
+    let visible = |activity: &DbActivity| {
+        activity.actor_id == actor.id && (is_owner || activity.visibility.is_publicly_visible())
+    };
+    let max = match &query.max_id {
+        Some(id) => Some(activity_cursor(&db, id, visible).await?),
+        None => None,
+    };
+    let min = match &query.min_id {
+        Some(id) => Some(activity_cursor(&db, id, visible).await?),
+        None => None,
+    };
+    // The unfiltered outbox pages by cursor, so its pages don't shift as
+    // activities are added. Offsets are still served for links handed out
+    // before, and for outboxes filtered by type.
+    let by_cursor = types.is_none() && (offset == 0 || paginating);
+
     // Get the outbox count and activities
-    let total_items = match db.get_actor_outbox_count(&actor.id).await {
+    let total_items = match (&types, is_owner) {
+        (Some(types), _) => {
+            db.count_activities_by_actor_and_types(&actor.id, types, !is_owner)
+                .await
+        }
+        (None, true) => db.get_actor_outbox_count(&actor.id).await,
+        (None, false) => db.get_actor_public_outbox_count(&actor.id).await,
+    };
+    let total_items = match total_items {
         Ok(count) => count,
         Err(e) => {
             warn!(
                 "Database error while counting outbox items for {}: {}",
                 username, e
             );
-            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Internal server error"
-            })));
+            return Err(FederationError::DatabaseError(e).into());
         }
     };
 
-    // Get recent activities (limit to 20 for now)
-    let activities = match db.get_activities_by_actor(&actor.id, 20, 0).await {
+    let activities = match (&types, is_owner) {
+        (Some(types), _) => {
+            db.get_activities_by_actor_and_types(
+                &actor.id,
+                types,
+                !is_owner,
+                OUTBOX_PAGE_SIZE,
+                offset,
+            )
+            .await
+        }
+        (None, _) if by_cursor => {
+            activities_between(&db, &actor.id, !is_owner, max.as_ref(), min.as_ref()).await
+        }
+        (None, true) => {
+            db.get_activities_by_actor(&actor.id, OUTBOX_PAGE_SIZE, offset)
+                .await
+        }
+        (None, false) => {
+            db.get_public_activities_by_actor(&actor.id, OUTBOX_PAGE_SIZE, offset)
+                .await
+        }
+    };
+    let activities = match activities {
         Ok(activities) => activities,
         Err(e) => {
             warn!(
                 "Database error while fetching outbox activities for {}: {}",
                 username, e
             );
-            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Internal server error"
-            })));
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    let announced_notes = match resolve_announced_notes(&db, &activities).await {
+        Ok(mut notes) => {
+            if !is_owner {
+                notes.retain(|_, note| note.visibility.is_publicly_visible());
+            }
+            notes
+        }
+        Err(e) => {
+            warn!(
+                "Database error while resolving announced notes for {}: {}",
+                username, e
+            );
+            return Err(FederationError::DatabaseError(e).into());
         }
     };
 
+    // The newest item on the page dates the page
+    let last_modified = activities.iter().map(|activity| activity.published).max();
+    let cursor_links = by_cursor.then(|| {
+        // Without a cursor this is the first page, and the total says
+        // whether there is more
+        let more_older = if paginating {
+            activities.len() as u32 == OUTBOX_PAGE_SIZE || min.is_some()
+        } else {
+            total_items as usize > activities.len()
+        };
+        pagination::id_page_urls(
+            &urls.outbox(&username),
+            req.query_string(),
+            activities
+                .first()
+                .map(|activity| cursor_param(&activity.id))
+                .as_deref(),
+            activities
+                .last()
+                .map(|activity| cursor_param(&activity.id))
+                .as_deref(),
+            more_older,
+            paginating,
+            &[("page", Some("true")), ("offset", None)],
+        )
+    });
+    let links = match &cursor_links {
+        Some((next, prev)) => pagination::link_header(next.as_deref(), prev.as_deref()),
+        None => pagination::offset_links(
+            &urls.outbox(&username),
+            req.query_string(),
+            offset,
+            OUTBOX_PAGE_SIZE,
+            activities.len(),
+            &[("page", Some("true"))],
+        ),
+    };
+
     let activity_objects: Vec<Value> = activities
         .into_iter()
         .map(|activity| {
-            serde_json::json!({
+            let mut item = serde_json::json!({
                 "id": activity.id,
                 "type": activity.activity_type,
                 "actor": activity.actor_id,
-                "object": activity.object,
+                "object": inline_announced_object(&activity, &announced_notes, &urls),
                 "to": activity.to_recipients,
                 "cc": activity.cc_recipients,
                 "published": activity.published
-            })
+            });
+            if let Some(target) = activity.target {
+                item["target"] = Value::String(target);
+            }
+            item
         })
         .collect();
 
-    let outbox = OrderedCollection::new(
-        format!("{}/users/{}/outbox", config.server_url, username),
-        total_items,
-        activity_objects,
-    );
+    // A filtered outbox is its own collection, so its pages keep the filter
+    let mut outbox_id = urls.outbox(&username);
+    if let Some(types) = &types {
+        outbox_id = format!("{}?type={}", outbox_id, types.join(","));
+    }
+    let mut response = HttpResponse::Ok();
+    response
+        .content_type(content_type::negotiate_request(&req).as_str())
+        .insert_header((header::VARY, "Accept, Authorization"));
+    if let Some(links) = links {
+        response.insert_header((header::LINK, links));
+    }
+    // The owner's view has private items, which no shared cache should keep
+    if is_owner {
+        response.insert_header((header::CACHE_CONTROL, "private"));
+    }
+
+    let body = if config.outbox_legacy_shape && !query.page && !paginating {
+        serde_json::to_vec(&OrderedCollection::new(
+            outbox_id,
+            total_items,
+            activity_objects,
+        ))?
+    } else {
+        let page = match cursor_links {
+            Some((next, prev)) => {
+                let id = if paginating {
+                    let max_id = query.max_id.as_deref().map(cursor_param);
+                    let min_id = query.min_id.as_deref().map(cursor_param);
+                    pagination::page_url(
+                        &outbox_id,
+                        req.query_string(),
+                        &[
+                            ("page", Some("true")),
+                            ("offset", None),
+                            ("max_id", max_id.as_deref()),
+                            ("min_id", min_id.as_deref()),
+                        ],
+                    )
+                } else {
+                    OrderedCollectionPage::url(&outbox_id, 0)
+                };
+                OrderedCollectionPage::linked(id, &outbox_id, next, prev, activity_objects)
+            }
+            None => OrderedCollectionPage::new(
+                &outbox_id,
+                offset,
+                OUTBOX_PAGE_SIZE,
+                total_items,
+                activity_objects,
+            ),
+        };
+        if query.page || paginating {
+            serde_json::to_vec(&page.standalone())?
+        } else {
+            serde_json::to_vec(&PagedOrderedCollection::new(
+                page,
+                total_items,
+                OUTBOX_PAGE_SIZE,
+            ))?
+        }
+    };
+
+    Ok(caching::respond(&req, response, body, last_modified))
+}
+
+/// A page of the actor's activities, newest first: the newest ones, the
+/// ones older than `max`, or with `min` the ones just newer than it (and
+/// still older than `max`)
+async fn activities_between(
+    db: &DatabaseRef,
+    actor_id: &str,
+    public_only: bool,
+    max: Option<&ActivityCursor>,
+    min: Option<&ActivityCursor>,
+) -> Result<Vec<DbActivity>, DatabaseError> {
+    let limit = OUTBOX_PAGE_SIZE;
+    let mut activities = match (min, max) {
+        (Some((published, id)), _) if public_only => {
+            db.get_public_activities_by_actor_after(actor_id, *published, id, limit)
+                .await?
+        }
+        (Some((published, id)), _) => {
+            db.get_activities_by_actor_after(actor_id, *published, id, limit)
+                .await?
+        }
+        (None, Some((published, id))) if public_only => {
+            db.get_public_activities_by_actor_before(actor_id, *published, id, limit)
+                .await?
+        }
+        (None, Some((published, id))) => {
+            db.get_activities_by_actor_before(actor_id, *published, id, limit)
+                .await?
+        }
+        (None, None) if public_only => {
+            db.get_public_activities_by_actor(actor_id, limit, 0)
+                .await?
+        }
+        (None, None) => db.get_activities_by_actor(actor_id, limit, 0).await?,
+    };
+    if let (Some(_), Some((published, id))) = (min, max) {
+        activities.retain(|activity| (activity.published, &activity.id) < (*published, id));
+    }
+    Ok(activities)
+}
+
+/// Fetch the locally stored notes boosted by any Announce activities, in a
+/// single batch query keyed by note id
+async fn resolve_announced_notes(
+    db: &DatabaseRef,
+    activities: &[DbActivity],
+) -> Result<HashMap<String, DbNote>, DatabaseError> {
+    let ids: Vec<String> = activities
+        .iter()
+        .filter(|activity| activity.activity_type == "Announce")
+        .filter_map(|activity| activity.object.as_str().map(str::to_string))
+        .collect();
+    if ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let notes = db.get_notes_by_ids(&ids).await?;
+    Ok(notes.into_iter().map(|n| (n.id.clone(), n)).collect())
+}
 
-    Ok(HttpResponse::Ok()
-        .content_type("application/activity+json")
-        .json(outbox))
+/// Replace an Announce's object IRI with the boosted note when we have it
+fn inline_announced_object(
+    activity: &DbActivity,
+    notes: &HashMap<String, DbNote>,
+    urls: &UrlBuilder,
+) -> Value {
+    if activity.activity_type != "Announce" {
+        return activity.object.clone();
+    }
+    match activity.object.as_str().and_then(|id| notes.get(id)) {
+        Some(note) => note_object(note, urls),
+        None => activity.object.clone(),
+    }
 }
 
-#[post("/users/{username}/outbox")]
+/// Only the actor's own tokens may post as them
+#[post(
+    "/users/{username}/outbox",
+    wrap = "RequireScope(auth::WRITE_STATUSES_SCOPE)"
+)]
+#[allow(clippy::too_many_arguments)]
+#[instrument(
+    skip(req, auth, payload, config, urls, db, http_client, delivery),
+    fields(activity_type = %activity_type_of(&payload))
+)]
 pub async fn post_outbox(
+    req: HttpRequest,
+    auth: AuthContext,
     path: web::Path<String>,
-    payload: web::Json<Value>,
+    payload: ActivityPayload,
     config: web::Data<Config>,
+    urls: web::Data<UrlBuilder>,
     db: web::Data<DatabaseRef>,
+    http_client: web::Data<dyn HttpClient>,
+    delivery: web::Data<DeliveryService>,
 ) -> Result<HttpResponse> {
     let username = path.into_inner();
     let activity = payload.into_inner();
@@ -101,135 +424,1087 @@ pub async fn post_outbox(
         Ok(Some(actor)) => actor,
         Ok(None) => {
             warn!("Actor not found for outbox POST: {}", username);
-            return Ok(HttpResponse::NotFound().json(serde_json::json!({
-                "error": "Actor not found"
-            })));
+            return Err(FederationError::ActorNotFound.into());
         }
         Err(e) => {
             warn!("Database error while fetching actor {}: {}", username, e);
-            return Ok(HttpResponse::InternalServerError().json(serde_json::json!({
-                "error": "Internal server error"
-            })));
+            return Err(FederationError::DatabaseError(e).into());
         }
     };
+    auth.ensure_actor(&actor.id)?;
 
     // Extract activity type
-    if let Some(activity_type) = activity.get("type").and_then(|v| v.as_str()) {
-        match activity_type {
-            "Create" => {
-                info!("Processing Create activity in outbox");
-                // Handle Create activity (new post/note)
-                if let Some(object) = activity.get("object") {
-                    if let Some(object_type) = object.get("type").and_then(|v| v.as_str()) {
-                        if object_type == "Note" {
-                            info!("Creating Note: {:?}", object);
-
-                            // Generate unique IDs
-                            let activity_id = format!(
-                                "{}/activities/{}",
-                                config.server_url,
-                                uuid::Uuid::new_v4()
-                            );
-                            let note_id =
-                                format!("{}/notes/{}", config.server_url, uuid::Uuid::new_v4());
-
-                            // Extract note data
-                            let content = object
-                                .get("content")
-                                .and_then(|v| v.as_str())
-                                .unwrap_or_default()
-                                .to_string();
-                            let to_recipients: Vec<String> = activity
-                                .get("to")
-                                .and_then(|v| v.as_array())
-                                .map(|arr| {
-                                    arr.iter()
-                                        .filter_map(|v| v.as_str().map(|s| s.to_string()))
-                                        .collect()
-                                })
-                                .unwrap_or_default();
-                            let cc_recipients: Vec<String> = activity
-                                .get("cc")
-                                .and_then(|v| v.as_array())
-                                .map(|arr| {
-                                    arr.iter()
-                                        .filter_map(|v| v.as_str().map(|s| s.to_string()))
-                                        .collect()
-                                })
-                                .unwrap_or_default();
-
-                            // Create the note in database
-                            let db_note = crate::database::DbNote {
-                                id: note_id.clone(),
-                                attributed_to: actor.id.clone(),
-                                content,
-                                to_recipients: to_recipients.clone(),
-                                cc_recipients: cc_recipients.clone(),
-                                published: chrono::Utc::now(),
-                                in_reply_to: object
-                                    .get("inReplyTo")
-                                    .and_then(|v| v.as_str().map(|s| s.to_string())),
-                                tags: vec![], // TODO: Extract tags from object
-                                created_at: chrono::Utc::now(),
-                            };
-
-                            if let Err(e) = db.create_note(&db_note).await {
-                                warn!("Database error while creating note: {}", e);
-                                return Ok(HttpResponse::InternalServerError().json(
-                                    serde_json::json!({
-                                        "error": "Failed to create note"
-                                    }),
-                                ));
-                            }
-
-                            // Create the activity in database
-                            let mut activity_object = object.clone();
-                            activity_object["id"] = serde_json::Value::String(note_id);
-                            activity_object["attributedTo"] =
-                                serde_json::Value::String(actor.id.clone());
-
-                            let db_activity = crate::database::DbActivity {
-                                id: activity_id.clone(),
-                                actor_id: actor.id.clone(),
-                                activity_type: "Create".to_string(),
-                                object: activity_object,
-                                to_recipients,
-                                cc_recipients,
-                                published: chrono::Utc::now(),
-                                created_at: chrono::Utc::now(),
-                            };
-
-                            if let Err(e) = db.create_activity(&db_activity).await {
-                                warn!("Database error while creating activity: {}", e);
-                                return Ok(HttpResponse::InternalServerError().json(
-                                    serde_json::json!({
-                                        "error": "Failed to create activity"
-                                    }),
-                                ));
-                            }
-
-                            info!("Successfully created note and activity");
-
-                            // Return the created activity
-                            return Ok(HttpResponse::Created().json(serde_json::json!({
-                                "id": activity_id,
-                                "type": "Create",
-                                "actor": actor.id,
-                                "object": db_activity.object,
-                                "to": db_activity.to_recipients,
-                                "cc": db_activity.cc_recipients,
-                                "published": db_activity.published
-                            })));
+    let activity_type = match activity.get("type").and_then(|v| v.as_str()) {
+        Some(activity_type) => activity_type,
+        None => {
+            warn!("Outbox POST without activity type for user {}", username);
+            return Err(FederationError::BadRequest("Missing activity type".to_string()).into());
+        }
+    };
+
+    let object = match activity.get("object") {
+        Some(object) => object,
+        None => {
+            warn!("Outbox POST {} activity without object", activity_type);
+            return Err(FederationError::BadRequest("Missing activity object".to_string()).into());
+        }
+    };
+
+    match activity_type {
+        "Create" => {
+            info!("Processing Create activity in outbox");
+            // Handle Create activity (new post/note)
+            let object_type = object.get("type").and_then(|v| v.as_str());
+            if object_type != Some("Note") {
+                let object_type = object_type.unwrap_or("none");
+                info!("Unsupported object type in outbox Create: {}", object_type);
+                return Err(FederationError::Unprocessable(format!(
+                    "Unsupported object type: {object_type}"
+                ))
+                .into());
+            }
+
+            // A reply must point at something we can resolve, so its author can be addressed
+            let in_reply_to = match object.get("inReplyTo") {
+                None | Some(Value::Null) => None,
+                Some(Value::String(url)) if addressing::is_http_url(url) => Some(url.as_str()),
+                Some(other) => {
+                    info!("Invalid inReplyTo in outbox Create: {}", other);
+                    return Err(
+                        FederationError::BadRequest("inReplyTo must be a URL".to_string()).into(),
+                    );
+                }
+            };
+            let reply_author = match in_reply_to {
+                Some(url) => {
+                    match addressing::resolve_reply_author(url, &urls, &db, http_client.get_ref())
+                        .await
+                    {
+                        Some(author) => Some(author),
+                        None => {
+                            info!("Could not resolve inReplyTo {}", url);
+                            return Err(FederationError::BadRequest(format!(
+                                "Could not resolve inReplyTo: {url}"
+                            ))
+                            .into());
                         }
                     }
                 }
+                None => None,
+            };
+            let thread = match threads::position_of(
+                &db,
+                in_reply_to,
+                reply_author.as_deref(),
+                config.max_thread_depth,
+            )
+            .await
+            {
+                Ok(thread) => thread,
+                Err(e) => {
+                    warn!(
+                        "Database error while placing reply to {:?}: {}",
+                        in_reply_to, e
+                    );
+                    return Err(FederationError::DatabaseError(e).into());
+                }
+            };
+
+            let attachments = match Attachment::from_object(object)
+                .into_iter()
+                .collect::<Result<Vec<_>, _>>()
+            {
+                Ok(attachments) => attachments,
+                Err(message) => {
+                    info!("Rejected outbox Create attachment: {}", message);
+                    return Err(FederationError::BadRequest(message).into());
+                }
+            };
+            let attachments: Vec<Value> = attachments
+                .iter()
+                .map(serde_json::to_value)
+                .collect::<Result<_, _>>()?;
+
+            info!("Creating Note: {:?}", object);
+
+            // Generate unique IDs
+            let activity_id = urls.activity(&uuid::Uuid::new_v4().to_string());
+            let note_uuid = uuid::Uuid::new_v4().to_string();
+            let note_id = urls.note(&note_uuid);
+
+            // Extract note data
+            let content = Note::content_of(object);
+            let mut to_recipients = normalize_audience(
+                activity
+                    .get("to")
+                    .and_then(|v| v.as_array())
+                    .map(|arr| {
+                        arr.iter()
+                            .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            );
+            let mut cc_recipients = normalize_audience(
+                activity
+                    .get("cc")
+                    .and_then(|v| v.as_array())
+                    .map(|arr| {
+                        arr.iter()
+                            .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            );
+
+            // Address the author of the post being replied to
+            if let Some(author) = &reply_author {
+                if *author != actor.id
+                    && !to_recipients.contains(author)
+                    && !cc_recipients.contains(author)
+                {
+                    cc_recipients.push(author.clone());
+                }
+            }
+
+            // Address everyone mentioned in the note
+            let mut mentioned = Vec::new();
+            let resolver = WebFingerResolver::from_config(http_client.get_ref(), &config);
+            for mention in Tag::mentions_of(object) {
+                match addressing::resolve_mention(&mention, &urls, &db, &resolver).await {
+                    Some(actor_id) => mentioned.push(actor_id),
+                    None => info!("Could not resolve mention {}", mention.name),
+                }
+            }
+            for actor_id in &mentioned {
+                if *actor_id != actor.id
+                    && !to_recipients.contains(actor_id)
+                    && !cc_recipients.contains(actor_id)
+                {
+                    cc_recipients.push(actor_id.clone());
+                }
+            }
+
+            // Local-only notes never say Public on the wire, whatever was asked for
+            let local_only = is_local_only(&activity, object);
+            let visibility = if local_only {
+                to_recipients.retain(|address| !is_public(address));
+                cc_recipients.retain(|address| !is_public(address));
+                Visibility::Local
+            } else {
+                Visibility::from_addressing(&to_recipients, &cc_recipients)
+            };
+
+            // Imports may carry their original date and notes dated in the future
+            // are held back until then; the note and activity share the timestamp
+            let (published, state) =
+                match published::scheduled_for(object.get("published"), &SystemClock) {
+                    Some(at) => (at, PublishState::Scheduled),
+                    None => (
+                        published::resolve_published(object.get("published"), &SystemClock),
+                        PublishState::Published,
+                    ),
+                };
+
+            // A content warning always marks the note sensitive, as Mastodon
+            // does, even when the client left the flag off
+            let summary = Note::summary_of(object);
+            let sensitive = Note::sensitive_of(object) || summary.is_some();
+
+            // Create the note in database
+            let mut db_note = crate::database::DbNote {
+                id: note_id.clone(),
+                attributed_to: actor.id.clone(),
+                content,
+                to_recipients: to_recipients.clone(),
+                cc_recipients: cc_recipients.clone(),
+                published,
+                in_reply_to: in_reply_to.map(|s| s.to_string()),
+                tags: Tag::hashtags_of(object),
+                attachments: attachments.clone(),
+                visibility,
+                state,
+                pinned: false,
+                created_at: chrono::Utc::now(),
+                in_reply_to_actor: thread.in_reply_to_actor,
+                thread_depth: thread.depth,
+                thread_truncated: thread.truncated,
+                sensitive,
+                summary,
+                language: Note::language_of(object),
+                content_map: Note::content_map_of(object).map(Value::Object),
+            };
+
+            // Configured classifiers may add a content warning or mark the
+            // note sensitive, unless the author opted out
+            let labels = match req.app_data::<web::Data<dyn ContentClassifier>>() {
+                Some(classifier) => {
+                    match classify::classify_note(classifier.get_ref(), &db, &mut db_note).await {
+                        Ok(labels) => labels,
+                        Err(e) => {
+                            warn!("Database error while classifying note: {}", e);
+                            return Err(FederationError::DatabaseError(e).into());
+                        }
+                    }
+                }
+                None => Vec::new(),
+            };
+
+            if let Err(e) = db.create_note(&db_note).await {
+                warn!("Database error while creating note: {}", e);
+                return Err(FederationError::DatabaseError(e).into());
+            }
+            if !labels.is_empty() {
+                if let Err(e) = db.add_note_labels(&db_note.id, &labels).await {
+                    warn!(
+                        "Database error while recording labels of {}: {}",
+                        db_note.id, e
+                    );
+                    return Err(FederationError::DatabaseError(e).into());
+                }
+            }
+
+            // Create the activity in database
+            let mut activity_object = object.clone();
+            activity_object["id"] = serde_json::Value::String(note_id);
+            activity_object["attributedTo"] = serde_json::Value::String(actor.id.clone());
+            activity_object["published"] = serde_json::to_value(published)?;
+            activity_object["replies"] = Value::String(urls.replies(&note_uuid));
+            activity_object["likes"] = Value::String(urls.likes(&note_uuid));
+            activity_object["shares"] = Value::String(urls.shares(&note_uuid));
+            // Send what was stored: only flag notes that are sensitive, as
+            // other servers do, drop blank content warnings, and only offer a
+            // contentMap with translations in it
+            if let Some(fields) = activity_object.as_object_mut() {
+                fields.remove("sensitive");
+                fields.remove("summary");
+                fields.remove("contentMap");
+                fields.remove("local_only");
+                if local_only {
+                    fields.remove("visibility");
+                    fields.insert("to".to_string(), to_recipients.clone().into());
+                    fields.insert("cc".to_string(), cc_recipients.clone().into());
+                }
+            }
+            activity_object["content"] = Value::String(db_note.content.clone());
+            if let Some(content_map) = outgoing_content_map(&db_note) {
+                activity_object["contentMap"] = content_map.clone();
+            }
+            if db_note.sensitive {
+                activity_object["sensitive"] = Value::Bool(true);
+            }
+            if let Some(summary) = &db_note.summary {
+                activity_object["summary"] = Value::String(summary.clone());
+            }
+            if !attachments.is_empty() {
+                activity_object["attachment"] = Value::Array(attachments);
+            }
+
+            let db_activity = crate::database::DbActivity {
+                id: activity_id.clone(),
+                actor_id: actor.id.clone(),
+                activity_type: "Create".to_string(),
+                object: activity_object,
+                target: None,
+                to_recipients,
+                cc_recipients,
+                published,
+                visibility,
+                state,
+                created_at: chrono::Utc::now(),
+            };
+
+            if let Err(e) = db.create_activity(&db_activity).await {
+                warn!("Database error while creating activity: {}", e);
+                return Err(FederationError::DatabaseError(e).into());
+            }
+
+            info!("Successfully created note and activity");
+
+            let created = activity_document(&db_activity);
+
+            // Let a remote parent author and remote mentioned actors know about
+            // the note; local ones find it in their inbox through the addressing.
+            // Scheduled notes are delivered once they are published, and
+            // local-only ones never are.
+            if state == PublishState::Published && visibility.federates() {
+                // Followers get everything but direct messages, so those who
+                // are also addressed get it once, through the fan-out
+                let to_followers = visibility != Visibility::Direct;
+                let recipients: Vec<String> = reply_author.into_iter().chain(mentioned).collect();
+                let queued = delivery_queue::enqueue_for_actors(
+                    db.get_ref(),
+                    &actor_fetch(&req, &config, &db, &http_client),
+                    &urls,
+                    &created,
+                    &recipients,
+                    to_followers.then_some(actor.id.as_str()),
+                )
+                .await;
+
+                // Delivered in the background so slow inboxes don't hold up
+                // the response
+                if to_followers {
+                    let delivery = delivery.into_inner();
+                    let activity = created.clone();
+                    let actor_id = actor.id.clone();
+                    tokio::spawn(request_id::propagate(async move {
+                        if let Err(e) = delivery.fan_out_except(activity, &actor_id, &queued).await
+                        {
+                            warn!(
+                                "Database error while fanning out to followers of {}: {}",
+                                actor_id, e
+                            );
+                        }
+                    }));
+                }
+            } else if state == PublishState::Scheduled {
+                info!("Scheduled {} for {}", activity_id, published);
+            }
+
+            Ok(created_response(created))
+        }
+        "Follow" => {
+            follow(
+                &actor,
+                object,
+                &urls,
+                db.get_ref(),
+                http_client.get_ref(),
+                &actor_fetch(&req, &config, &db, &http_client),
+                &config,
+            )
+            .await
+        }
+        "Block" => {
+            let fetch = actor_fetch(&req, &config, &db, &http_client);
+            block(&actor, object, &urls, db.get_ref(), &fetch).await
+        }
+        "Undo" => {
+            let fetch = actor_fetch(&req, &config, &db, &http_client);
+            undo(&actor, object, &urls, db.get_ref(), &fetch).await
+        }
+        "Add" | "Remove" => {
+            let pinned = activity_type == "Add";
+            feature(&actor, pinned, &activity, object, &urls, db.get_ref()).await
+        }
+        "Move" => {
+            let target = move_target(&actor, &activity, object)?;
+            let moved =
+                move_account(&actor, target, &urls, db.get_ref(), http_client.get_ref()).await?;
+
+            // Cached copies of the actor document would hide `movedTo`
+            if let Some(events) = req.app_data::<web::Data<EventBus>>() {
+                events.publish(ObjectEvent::ActorUpdated(actor.id.clone()));
             }
-            _ => {
-                info!("Unsupported activity type in outbox: {}", activity_type);
+
+            let delivery = delivery.into_inner();
+            let activity = moved.clone();
+            let actor_id = actor.id.clone();
+            tokio::spawn(request_id::propagate(async move {
+                if let Err(e) = delivery.fan_out_activity(activity, &actor_id).await {
+                    warn!(
+                        "Database error while fanning out to followers of {}: {}",
+                        actor_id, e
+                    );
+                }
+            }));
+
+            Ok(created_response(moved))
+        }
+        _ => {
+            info!("Unsupported activity type in outbox: {}", activity_type);
+            Err(FederationError::Unprocessable(format!(
+                "Unsupported activity type: {activity_type}"
+            ))
+            .into())
+        }
+    }
+}
+
+/// Follow a (usually remote) actor, given by URL or `user@domain` handle:
+/// record a pending follow, store the activity and queue it for delivery to
+/// the target's inbox
+async fn follow(
+    actor: &DbActor,
+    object: &Value,
+    urls: &UrlBuilder,
+    db: &DatabaseRef,
+    http_client: &dyn HttpClient,
+    actor_fetch: &ActorFetchService,
+    config: &Config,
+) -> Result<HttpResponse> {
+    let mut target = object
+        .as_str()
+        .or_else(|| object.get("id").and_then(|v| v.as_str()))
+        .unwrap_or_default()
+        .to_string();
+
+    // Actors known only by their handle are discovered through WebFinger
+    if webfinger::is_acct(&target) {
+        target = match WebFingerResolver::from_config(http_client, config)
+            .resolve_acct(&target)
+            .await
+        {
+            Ok(actor_id) => actor_id,
+            Err(e) => {
+                info!("Could not resolve Follow target {}: {:#}", target, e);
+                return Err(
+                    FederationError::NotFound(format!("Could not resolve {target}")).into(),
+                );
             }
+        };
+    }
+
+    if !(target.starts_with("https://") && addressing::is_http_url(&target)) {
+        info!("Invalid Follow object in outbox: {}", object);
+        return Err(FederationError::BadRequest(
+            "Follow object must be an HTTPS actor URL or acct handle".to_string(),
+        )
+        .into());
+    }
+    let target = target.as_str();
+
+    if target == actor.id {
+        return Err(FederationError::BadRequest("Cannot follow yourself".to_string()).into());
+    }
+
+    match db.get_follow_by_actors(&actor.id, target).await {
+        Ok(None) => {}
+        Ok(Some(_)) => {
+            return Err(
+                FederationError::Conflict("Already following this actor".to_string()).into(),
+            );
         }
+        Err(e) => {
+            warn!("Database error while checking follow of {}: {}", target, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    }
+
+    let inbox = match actor_inbox(target, urls, db, actor_fetch).await {
+        Ok(inbox) => inbox,
+        Err(e) => {
+            warn!("Database error while fetching actor {}: {}", target, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+    let Some(inbox) = inbox else {
+        warn!("Could not find inbox for follow target {}", target);
+        return Err(FederationError::NotFound("Target actor not found".to_string()).into());
+    };
+
+    let activity_id = urls.activity(&uuid::Uuid::new_v4().to_string());
+    let now = chrono::Utc::now();
+
+    let db_follow = DbFollowRelation {
+        id: activity_id.clone(),
+        follower_id: actor.id.clone(),
+        following_id: target.to_string(),
+        status: "pending".to_string(),
+        created_at: now,
+        updated_at: now,
+    };
+    if let Err(e) = db.create_follow(&db_follow).await {
+        warn!("Database error while creating follow: {}", e);
+        return Err(FederationError::DatabaseError(e).into());
+    }
+    // An Accept or Undo for this pair may have beaten the Follow here
+    if let Err(e) = pending_accepts::apply_parked_activities(db, &db_follow).await {
+        warn!("Database error while applying parked activities: {}", e);
+    }
+
+    let to_recipients = vec![target.to_string()];
+    let db_activity = DbActivity {
+        id: activity_id.clone(),
+        actor_id: actor.id.clone(),
+        activity_type: "Follow".to_string(),
+        object: Value::String(target.to_string()),
+        target: None,
+        to_recipients: to_recipients.clone(),
+        cc_recipients: vec![],
+        published: now,
+        visibility: Visibility::from_addressing(&to_recipients, &[]),
+        state: PublishState::Published,
+        created_at: now,
+    };
+    if let Err(e) = db.create_activity(&db_activity).await {
+        warn!("Database error while creating activity: {}", e);
+        return Err(FederationError::DatabaseError(e).into());
+    }
+
+    let created = activity_document(&db_activity);
+
+    if let Err(e) =
+        delivery_queue::enqueue(db, &inbox, created.clone(), DeliveryPriority::Interactive).await
+    {
+        warn!("Failed to queue Follow delivery to {}: {}", inbox, e);
+    }
+
+    info!("{} requested to follow {}", actor.id, target);
+    Ok(created_response(created))
+}
+
+/// Block an actor: they can no longer deliver to the blocker's inbox, follows
+/// between the two are removed, and the Block is delivered to them
+async fn block(
+    actor: &DbActor,
+    object: &Value,
+    urls: &UrlBuilder,
+    db: &DatabaseRef,
+    actor_fetch: &ActorFetchService,
+) -> Result<HttpResponse> {
+    let target = object
+        .as_str()
+        .or_else(|| object.get("id").and_then(|v| v.as_str()))
+        .unwrap_or_default();
+    if !addressing::is_http_url(target) {
+        info!("Invalid Block object in outbox: {}", object);
+        return Err(
+            FederationError::BadRequest("Block object must be an actor URL".to_string()).into(),
+        );
+    }
+    if target == actor.id {
+        return Err(FederationError::BadRequest("Cannot block yourself".to_string()).into());
+    }
+
+    match db.is_blocked(&actor.id, target).await {
+        Ok(false) => {}
+        Ok(true) => {
+            return Err(
+                FederationError::Conflict("Already blocking this actor".to_string()).into(),
+            );
+        }
+        Err(e) => {
+            warn!("Database error while checking block of {}: {}", target, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    }
+
+    let activity_id = urls.activity(&uuid::Uuid::new_v4().to_string());
+    let now = chrono::Utc::now();
+
+    let db_block = DbBlock {
+        id: activity_id.clone(),
+        blocker_id: actor.id.clone(),
+        blocked_id: target.to_string(),
+        created_at: now,
+    };
+    if let Err(e) = db.create_block(&db_block).await {
+        warn!("Database error while creating block: {}", e);
+        return Err(FederationError::DatabaseError(e).into());
+    }
+
+    // A block ends following in both directions
+    for (follower, following) in [(actor.id.as_str(), target), (target, actor.id.as_str())] {
+        match db.get_follow_by_actors(follower, following).await {
+            Ok(Some(follow)) => {
+                if let Err(e) = db.delete_follow(&follow.id).await {
+                    warn!("Database error while deleting follow {}: {}", follow.id, e);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => warn!(
+                "Database error while looking up follow of {}: {}",
+                following, e
+            ),
+        }
+    }
+
+    let to_recipients = vec![target.to_string()];
+    let db_activity = DbActivity {
+        id: activity_id,
+        actor_id: actor.id.clone(),
+        activity_type: "Block".to_string(),
+        object: Value::String(target.to_string()),
+        target: None,
+        to_recipients: to_recipients.clone(),
+        cc_recipients: vec![],
+        published: now,
+        visibility: Visibility::from_addressing(&to_recipients, &[]),
+        state: PublishState::Published,
+        created_at: now,
+    };
+    if let Err(e) = db.create_activity(&db_activity).await {
+        warn!("Database error while creating activity: {}", e);
+        return Err(FederationError::DatabaseError(e).into());
+    }
+
+    let created = activity_document(&db_activity);
+    deliver_to_actor(target, &created, urls, db, actor_fetch).await;
+
+    info!("{} blocked {}", actor.id, target);
+    Ok(created_response(created))
+}
+
+/// Undo one of the actor's Follows or Blocks
+async fn undo(
+    actor: &DbActor,
+    object: &Value,
+    urls: &UrlBuilder,
+    db: &DatabaseRef,
+    actor_fetch: &ActorFetchService,
+) -> Result<HttpResponse> {
+    match object.get("type").and_then(|v| v.as_str()) {
+        Some("Follow") => undo_follow(actor, object, urls, db, actor_fetch).await,
+        Some("Block") => undo_block(actor, object, urls, db, actor_fetch).await,
+        object_type => {
+            let object_type = object_type.unwrap_or("none");
+            info!("Unsupported object type in outbox Undo: {}", object_type);
+            Err(FederationError::Unprocessable(format!(
+                "Unsupported Undo object type: {object_type}"
+            ))
+            .into())
+        }
+    }
+}
+
+async fn undo_follow(
+    actor: &DbActor,
+    object: &Value,
+    urls: &UrlBuilder,
+    db: &DatabaseRef,
+    actor_fetch: &ActorFetchService,
+) -> Result<HttpResponse> {
+    let Some(target) = object.get("object").and_then(|followed| {
+        followed
+            .as_str()
+            .or_else(|| followed.get("id").and_then(|v| v.as_str()))
+    }) else {
+        info!("Undo Follow without a followed actor: {}", object);
+        return Err(FederationError::BadRequest("Missing Follow object".to_string()).into());
+    };
+
+    let follow = match db.get_follow_by_actors(&actor.id, target).await {
+        Ok(Some(follow)) => follow,
+        Ok(None) => {
+            info!("{} is not following {}", actor.id, target);
+            return Err(FederationError::NotFound("Follow not found".to_string()).into());
+        }
+        Err(e) => {
+            warn!(
+                "Database error while looking up follow of {}: {}",
+                target, e
+            );
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    };
+
+    if let Err(e) = db.delete_follow(&follow.id).await {
+        warn!("Database error while deleting follow {}: {}", follow.id, e);
+        return Err(FederationError::DatabaseError(e).into());
+    }
+
+    let activity_id = urls.activity(&uuid::Uuid::new_v4().to_string());
+    let now = chrono::Utc::now();
+    let to_recipients = vec![target.to_string()];
+    let db_activity = DbActivity {
+        id: activity_id.clone(),
+        actor_id: actor.id.clone(),
+        activity_type: "Undo".to_string(),
+        object: serde_json::json!({
+            "id": follow.id,
+            "type": "Follow",
+            "actor": actor.id,
+            "object": target
+        }),
+        target: None,
+        to_recipients: to_recipients.clone(),
+        cc_recipients: vec![],
+        published: now,
+        visibility: Visibility::from_addressing(&to_recipients, &[]),
+        state: PublishState::Published,
+        created_at: now,
+    };
+    if let Err(e) = db.create_activity(&db_activity).await {
+        warn!("Database error while creating activity: {}", e);
+        return Err(FederationError::DatabaseError(e).into());
+    }
+
+    let undo = activity_document(&db_activity);
+    deliver_to_actor(target, &undo, urls, db, actor_fetch).await;
+
+    info!("{} unfollowed {}", actor.id, target);
+    Ok(created_response(undo))
+}
+
+async fn undo_block(
+    actor: &DbActor,
+    object: &Value,
+    urls: &UrlBuilder,
+    db: &DatabaseRef,
+    actor_fetch: &ActorFetchService,
+) -> Result<HttpResponse> {
+    let Some(target) = object.get("object").and_then(|blocked| {
+        blocked
+            .as_str()
+            .or_else(|| blocked.get("id").and_then(|v| v.as_str()))
+    }) else {
+        info!("Undo Block without a blocked actor: {}", object);
+        return Err(FederationError::BadRequest("Missing Block object".to_string()).into());
+    };
+
+    match db.is_blocked(&actor.id, target).await {
+        Ok(true) => {}
+        Ok(false) => {
+            info!("{} is not blocking {}", actor.id, target);
+            return Err(FederationError::NotFound("Block not found".to_string()).into());
+        }
+        Err(e) => {
+            warn!("Database error while looking up block of {}: {}", target, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    }
+
+    if let Err(e) = db.delete_block(&actor.id, target).await {
+        warn!("Database error while deleting block of {}: {}", target, e);
+        return Err(FederationError::DatabaseError(e).into());
+    }
+
+    let mut undone = serde_json::json!({
+        "type": "Block",
+        "actor": actor.id,
+        "object": target
+    });
+    if let Some(block_id) = object.get("id") {
+        undone["id"] = block_id.clone();
+    }
+
+    let activity_id = urls.activity(&uuid::Uuid::new_v4().to_string());
+    let now = chrono::Utc::now();
+    let to_recipients = vec![target.to_string()];
+    let db_activity = DbActivity {
+        id: activity_id,
+        actor_id: actor.id.clone(),
+        activity_type: "Undo".to_string(),
+        object: undone,
+        target: None,
+        to_recipients: to_recipients.clone(),
+        cc_recipients: vec![],
+        published: now,
+        visibility: Visibility::from_addressing(&to_recipients, &[]),
+        state: PublishState::Published,
+        created_at: now,
+    };
+    if let Err(e) = db.create_activity(&db_activity).await {
+        warn!("Database error while creating activity: {}", e);
+        return Err(FederationError::DatabaseError(e).into());
+    }
+
+    let undo = activity_document(&db_activity);
+    deliver_to_actor(target, &undo, urls, db, actor_fetch).await;
+
+    info!("{} unblocked {}", actor.id, target);
+    Ok(created_response(undo))
+}
+
+/// Pin (`Add`) or unpin (`Remove`) one of the actor's notes in their
+/// featured collection
+async fn feature(
+    actor: &DbActor,
+    pinned: bool,
+    activity: &Value,
+    object: &Value,
+    urls: &UrlBuilder,
+    db: &DatabaseRef,
+) -> Result<HttpResponse> {
+    let activity_type = if pinned { "Add" } else { "Remove" };
+    let featured = urls.featured(&actor.username);
+    let target = activity.get("target").and_then(|target| {
+        target
+            .as_str()
+            .or_else(|| target.get("id").and_then(|v| v.as_str()))
+    });
+    if target != Some(featured.as_str()) {
+        info!(
+            "Unsupported {} target in outbox: {:?}",
+            activity_type, target
+        );
+        return Err(FederationError::Unprocessable(format!(
+            "{activity_type} target must be {featured}"
+        ))
+        .into());
+    }
+
+    let Some(note_id) = object
+        .as_str()
+        .or_else(|| object.get("id").and_then(|v| v.as_str()))
+    else {
+        return Err(FederationError::BadRequest("Missing note to feature".to_string()).into());
+    };
+
+    match db.get_note_by_id(note_id).await {
+        Ok(Some(note)) if note.attributed_to == actor.id => {}
+        Ok(Some(_)) => {
+            info!("{} tried to feature {} by someone else", actor.id, note_id);
+            return Err(FederationError::Forbidden(
+                "Only your own notes can be featured".to_string(),
+            )
+            .into());
+        }
+        Ok(None) => {
+            return Err(FederationError::NoteNotFound.into());
+        }
+        Err(e) => {
+            warn!("Database error while fetching note {}: {}", note_id, e);
+            return Err(FederationError::DatabaseError(e).into());
+        }
+    }
+
+    if let Err(e) = db.set_note_pinned(note_id, pinned).await {
+        warn!("Database error while featuring note {}: {}", note_id, e);
+        return Err(FederationError::DatabaseError(e).into());
+    }
+
+    let activity_id = urls.activity(&uuid::Uuid::new_v4().to_string());
+    let now = chrono::Utc::now();
+    let to_recipients = vec![PUBLIC.to_string()];
+    let db_activity = DbActivity {
+        id: activity_id.clone(),
+        actor_id: actor.id.clone(),
+        activity_type: activity_type.to_string(),
+        object: Value::String(note_id.to_string()),
+        target: Some(featured),
+        to_recipients: to_recipients.clone(),
+        cc_recipients: vec![],
+        published: now,
+        visibility: Visibility::from_addressing(&to_recipients, &[]),
+        state: PublishState::Published,
+        created_at: now,
+    };
+    if let Err(e) = db.create_activity(&db_activity).await {
+        warn!("Database error while creating activity: {}", e);
+        return Err(FederationError::DatabaseError(e).into());
+    }
+
+    let created = activity_document(&db_activity);
+
+    info!("{} set pinned={} on {}", actor.id, pinned, note_id);
+    Ok(created_response(created))
+}
+
+/// The account a `Move` migrates to. Actors can only move themselves, and
+/// only once, so the object must be the posting actor and it must not have
+/// moved already.
+fn move_target<'a>(
+    actor: &DbActor,
+    activity: &'a Value,
+    object: &Value,
+) -> Result<&'a str, FederationError> {
+    let moved = object
+        .as_str()
+        .or_else(|| object.get("id").and_then(|v| v.as_str()));
+    if moved != Some(actor.id.as_str()) {
+        info!("{} tried to move {:?}", actor.id, moved);
+        return Err(FederationError::Forbidden(
+            "Only your own account can be moved".to_string(),
+        ));
+    }
+    if let Some(moved_to) = &actor.moved_to {
+        info!(
+            "{} tried to move again after moving to {}",
+            actor.id, moved_to
+        );
+        return Err(FederationError::Conflict(format!(
+            "Already moved to {moved_to}"
+        )));
+    }
+
+    let target = activity.get("target").and_then(|target| {
+        target
+            .as_str()
+            .or_else(|| target.get("id").and_then(|v| v.as_str()))
+    });
+    match target {
+        Some(target) if target.starts_with("https://") && addressing::is_http_url(target) => {
+            if target == actor.id {
+                return Err(FederationError::BadRequest(
+                    "Cannot move to yourself".to_string(),
+                ));
+            }
+            Ok(target)
+        }
+        _ => {
+            info!("Invalid Move target in outbox: {:?}", target);
+            Err(FederationError::BadRequest(
+                "Move target must be an HTTPS actor URL".to_string(),
+            ))
+        }
+    }
+}
+
+/// Move the actor to `target`, which must already list the actor among its
+/// `alsoKnownAs`: record `movedTo` and store the `Move` activity, addressed
+/// to the actor's followers
+async fn move_account(
+    actor: &DbActor,
+    target: &str,
+    urls: &UrlBuilder,
+    db: &DatabaseRef,
+    http_client: &dyn HttpClient,
+) -> Result<Value> {
+    let aliases = if urls.is_local(target) {
+        match db.get_actor_by_id(target).await {
+            Ok(local) => local.map(|local| local.also_known_as),
+            Err(e) => {
+                warn!("Database error while fetching actor {}: {}", target, e);
+                return Err(FederationError::DatabaseError(e).into());
+            }
+        }
+    } else {
+        addressing::fetch_document(target, http_client)
+            .await
+            .map(|document| also_known_as(&document))
+    };
+    let Some(aliases) = aliases else {
+        info!("Could not find Move target {}", target);
+        return Err(FederationError::NotFound("Target actor not found".to_string()).into());
+    };
+    if !aliases.contains(&actor.id) {
+        info!(
+            "Move target {} does not list {} as an alias",
+            target, actor.id
+        );
+        return Err(FederationError::Unprocessable(format!(
+            "{target} must list {} in alsoKnownAs",
+            actor.id
+        ))
+        .into());
+    }
+
+    if let Err(e) = db
+        .update_actor_migration(&actor.id, Some(target.to_string()), &actor.also_known_as)
+        .await
+    {
+        warn!("Database error while moving {}: {}", actor.id, e);
+        return Err(FederationError::DatabaseError(e).into());
+    }
+
+    let activity_id = urls.activity(&uuid::Uuid::new_v4().to_string());
+    let now = chrono::Utc::now();
+    let to_recipients = vec![urls.followers(&actor.username)];
+    let db_activity = DbActivity {
+        id: activity_id,
+        actor_id: actor.id.clone(),
+        activity_type: "Move".to_string(),
+        object: Value::String(actor.id.clone()),
+        target: Some(target.to_string()),
+        to_recipients: to_recipients.clone(),
+        cc_recipients: vec![],
+        published: now,
+        visibility: Visibility::from_addressing(&to_recipients, &[]),
+        state: PublishState::Published,
+        created_at: now,
+    };
+    if let Err(e) = db.create_activity(&db_activity).await {
+        warn!("Database error while creating activity: {}", e);
+        return Err(FederationError::DatabaseError(e).into());
+    }
+
+    let moved = activity_document(&db_activity);
+
+    info!("{} moved to {}", actor.id, target);
+    Ok(moved)
+}
+
+/// The ids an actor document lists in `alsoKnownAs`, a single link or a list
+fn also_known_as(document: &Value) -> Vec<String> {
+    let aliases = match document.get("alsoKnownAs") {
+        Some(Value::Array(aliases)) => aliases.iter().collect(),
+        Some(alias) => vec![alias],
+        None => vec![],
+    };
+    aliases
+        .into_iter()
+        .filter_map(|alias| {
+            alias
+                .as_str()
+                .or_else(|| alias.get("id").and_then(|v| v.as_str()))
+        })
+        .map(String::from)
+        .collect()
+}
+
+/// Whether a Create asks to stay on this server, with `local_only: true` or
+/// `visibility: "local"` on the activity or its object
+fn is_local_only(activity: &Value, object: &Value) -> bool {
+    [activity, object].into_iter().any(|value| {
+        value.get("local_only").and_then(|v| v.as_bool()) == Some(true)
+            || value.get("visibility").and_then(|v| v.as_str()) == Some("local")
+    })
+}
+
+/// Render a stored activity as a standalone ActivityStreams document
+fn activity_document(activity: &DbActivity) -> Value {
+    let mut document = serde_json::json!({
+        "id": activity.id,
+        "type": activity.activity_type,
+        "actor": activity.actor_id,
+        "object": activity.object,
+        "to": activity.to_recipients,
+        "cc": activity.cc_recipients,
+        "published": activity.published
+    });
+    if let Some(target) = &activity.target {
+        document["target"] = Value::String(target.clone());
+    }
+    document["@context"] = ContextBuilder::for_document(&document).build().into();
+    document
+}
+
+/// 201 Created for a new outbox activity, with `Location` pointing at it
+fn created_response(activity: Value) -> HttpResponse {
+    let location = activity["id"].as_str().unwrap_or_default().to_string();
+    HttpResponse::Created()
+        .content_type(ContentType::ActivityJson.as_str())
+        .insert_header((header::LOCATION, location))
+        .json(activity)
+}
+
+/// Queue `activity` for the inbox of the actor it is about. Failures are
+/// logged; the activity itself has already taken effect locally.
+async fn deliver_to_actor(
+    actor_id: &str,
+    activity: &Value,
+    urls: &UrlBuilder,
+    db: &DatabaseRef,
+    actor_fetch: &ActorFetchService,
+) {
+    let activity_type = activity["type"].as_str().unwrap_or_default();
+    match actor_inbox(actor_id, urls, db, actor_fetch).await {
+        Ok(Some(inbox)) => {
+            if let Err(e) =
+                delivery_queue::enqueue(db, &inbox, activity.clone(), DeliveryPriority::Interactive)
+                    .await
+            {
+                warn!(
+                    "Failed to queue {} delivery to {}: {}",
+                    activity_type, inbox, e
+                );
+            }
+        }
+        Ok(None) => warn!("Could not find inbox for {} of {}", activity_type, actor_id),
+        Err(e) => warn!("Database error while fetching actor {}: {}", actor_id, e),
+    }
+}
+
+/// The inbox of an actor: local actors are looked up directly, remote ones
+/// through the remote actor cache
+async fn actor_inbox(
+    actor_id: &str,
+    urls: &UrlBuilder,
+    db: &DatabaseRef,
+    actor_fetch: &ActorFetchService,
+) -> Result<Option<String>, DatabaseError> {
+    if urls.is_local(actor_id) {
+        return Ok(db
+            .get_actor_by_id(actor_id)
+            .await?
+            .map(|local| urls.inbox(&local.username)));
     }
 
-    // Return 201 Created for successful outbox POST requests
-    Ok(HttpResponse::Created().finish())
+    Ok(actor_fetch
+        .find(actor_id)
+        .await?
+        .and_then(|remote| remote.inbox))
 }
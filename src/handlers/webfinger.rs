@@ -1,6 +1,10 @@
-use crate::config::Config;
-use actix_web::{get, web, HttpResponse, Result};
+use crate::database::{DatabaseRef, DbActor};
+use crate::errors::FederationError;
+use crate::urls::UrlBuilder;
+use actix_web::{get, web, HttpRequest, HttpResponse, Result};
 use serde::{Deserialize, Serialize};
+use tracing::{instrument, warn};
+use url::Url;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WebFingerQuery {
@@ -10,6 +14,8 @@ pub struct WebFingerQuery {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct WebFingerResponse {
     pub subject: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
     pub links: Vec<WebFingerLink>,
 }
 
@@ -22,46 +28,179 @@ pub struct WebFingerLink {
 }
 
 #[get("/.well-known/webfinger")]
+#[instrument(skip(req, urls, db))]
 pub async fn webfinger(
+    req: HttpRequest,
     query: web::Query<WebFingerQuery>,
-    config: web::Data<Config>,
+    urls: web::Data<UrlBuilder>,
+    db: web::Data<DatabaseRef>,
 ) -> Result<HttpResponse> {
-    let resource = &query.resource;
-
-    // Parse the resource to extract username
-    // Expected format: acct:username@domain
-    if let Some(username) = resource.strip_prefix("acct:") {
-        if let Some((user, domain)) = username.rsplit_once('@') {
-            if domain
-                == config
-                    .server_url
-                    .replace("http://", "")
-                    .replace("https://", "")
-            {
-                let actor_url = format!("{}/users/{}", config.server_url, user);
-
-                let response = WebFingerResponse {
-                    subject: resource.clone(),
-                    links: vec![
-                        WebFingerLink {
-                            rel: "self".to_string(),
-                            link_type: Some("application/activity+json".to_string()),
-                            href: actor_url,
-                        },
-                        WebFingerLink {
-                            rel: "http://webfinger.net/rel/profile-page".to_string(),
-                            link_type: Some("text/html".to_string()),
-                            href: format!("{}/users/{}", config.server_url, user),
-                        },
-                    ],
-                };
-
-                return Ok(HttpResponse::Ok()
-                    .content_type("application/jrd+json")
-                    .json(response));
+    let resource = query.resource.as_str();
+    let rels = requested_rels(req.query_string());
+
+    // acct:username@domain
+    if let Some(acct) = strip_prefix_ignore_case(resource, "acct:") {
+        let Some((user, domain)) = acct
+            .rsplit_once('@')
+            .filter(|(user, domain)| !user.is_empty() && !domain.is_empty())
+        else {
+            return Err(
+                FederationError::BadRequest(format!("Invalid acct resource: {resource}")).into(),
+            );
+        };
+        return match urls.is_local_domain(domain) {
+            Some(true) => match db.get_actor_by_username(user).await {
+                Ok(Some(actor)) => Ok(jrd_response(&urls, &actor, &rels)),
+                Ok(None) => Ok(HttpResponse::NotFound().finish()),
+                Err(e) => {
+                    warn!("Database error while resolving {}: {}", resource, e);
+                    Ok(HttpResponse::InternalServerError().finish())
+                }
+            },
+            Some(false) => Ok(HttpResponse::NotFound().finish()),
+            None => Err(FederationError::BadRequest(format!(
+                "Invalid domain in resource: {domain}"
+            ))
+            .into()),
+        };
+    }
+
+    // https://domain/users/username, as stored for the actor
+    if has_http_scheme(resource) {
+        let Some(actor_url) = normalize_url(resource) else {
+            return Err(
+                FederationError::BadRequest(format!("Invalid resource URL: {resource}")).into(),
+            );
+        };
+        return match db.get_actor_by_id(&actor_url).await {
+            Ok(Some(actor)) => Ok(jrd_response(&urls, &actor, &rels)),
+            Ok(None) => Ok(HttpResponse::NotFound().finish()),
+            Err(e) => {
+                warn!("Database error while resolving {}: {}", actor_url, e);
+                Ok(HttpResponse::InternalServerError().finish())
             }
-        }
+        };
     }
 
-    Ok(HttpResponse::NotFound().finish())
+    // Other schemes are well-formed, just nothing we have
+    if Url::parse(resource).is_ok() {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    Err(FederationError::BadRequest(format!("Invalid resource: {resource}")).into())
+}
+
+/// The JRD for a local actor. The subject is always the acct: form, with the
+/// actor's URL among the aliases, whichever form was asked for. When `rels`
+/// is non-empty only links with one of those relations are returned.
+fn jrd_response(urls: &UrlBuilder, actor: &DbActor, rels: &[String]) -> HttpResponse {
+    let actor_url = urls.actor(&actor.username);
+    let subject = format!("acct:{}@{}", actor.username, urls.domain());
+    let links = vec![
+        WebFingerLink {
+            rel: "self".to_string(),
+            link_type: Some("application/activity+json".to_string()),
+            href: actor_url.clone(),
+        },
+        WebFingerLink {
+            rel: "http://webfinger.net/rel/profile-page".to_string(),
+            link_type: Some("text/html".to_string()),
+            href: actor_url.clone(),
+        },
+    ];
+    let response = WebFingerResponse {
+        aliases: vec![subject.clone(), actor_url],
+        subject,
+        links: links
+            .into_iter()
+            .filter(|link| rels.is_empty() || rels.contains(&link.rel))
+            .collect(),
+    };
+
+    HttpResponse::Ok()
+        .content_type("application/jrd+json")
+        .json(response)
+}
+
+/// The `rel` parameters of a WebFinger query, which may be repeated
+fn requested_rels(query: &str) -> Vec<String> {
+    url::form_urlencoded::parse(query.as_bytes())
+        .filter(|(name, _)| name == "rel")
+        .map(|(_, rel)| rel.into_owned())
+        .filter(|rel| !rel.is_empty())
+        .collect()
+}
+
+fn strip_prefix_ignore_case<'a>(value: &'a str, prefix: &str) -> Option<&'a str> {
+    value
+        .get(..prefix.len())
+        .filter(|head| head.eq_ignore_ascii_case(prefix))
+        .map(|_| &value[prefix.len()..])
+}
+
+fn has_http_scheme(resource: &str) -> bool {
+    strip_prefix_ignore_case(resource, "http://").is_some()
+        || strip_prefix_ignore_case(resource, "https://").is_some()
+}
+
+/// Normalize an `http(s)` resource URI for comparison with stored actor ids:
+/// the scheme and host are lowercased and any trailing slash is dropped.
+/// Returns `None` for anything that isn't an absolute http(s) URL.
+fn normalize_url(resource: &str) -> Option<String> {
+    let (scheme, rest) = resource.split_once("://")?;
+    let scheme = scheme.to_ascii_lowercase();
+    if scheme != "https" && scheme != "http" {
+        return None;
+    }
+
+    let (host, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, ""),
+    };
+    if host.is_empty() || host.contains(['@', ' ', '?', '#']) {
+        return None;
+    }
+
+    Some(format!(
+        "{scheme}://{}{}",
+        host.to_ascii_lowercase(),
+        path.trim_end_matches('/')
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_url_lowercases_host_and_strips_trailing_slash() {
+        assert_eq!(
+            normalize_url("HTTPS://Example.COM/users/Alice/").as_deref(),
+            Some("https://example.com/users/Alice")
+        );
+        assert_eq!(
+            normalize_url("https://example.com").as_deref(),
+            Some("https://example.com")
+        );
+    }
+
+    #[test]
+    fn test_normalize_url_rejects_non_http_resources() {
+        assert_eq!(normalize_url("acct:alice@example.com"), None);
+        assert_eq!(normalize_url("mailto:alice@example.com"), None);
+        assert_eq!(normalize_url("ftp://example.com/users/alice"), None);
+        assert_eq!(normalize_url("https:///users/alice"), None);
+        assert_eq!(normalize_url("https://user@example.com/"), None);
+        assert_eq!(normalize_url("invalid-resource"), None);
+    }
+
+    #[test]
+    fn test_requested_rels_collects_repeated_parameters() {
+        assert_eq!(
+            requested_rels(
+                "resource=acct:a@b&rel=self&rel=http%3A%2F%2Fwebfinger.net%2Frel%2Fprofile-page"
+            ),
+            vec!["self", "http://webfinger.net/rel/profile-page"]
+        );
+        assert!(requested_rels("resource=acct:a@b&rel=").is_empty());
+    }
 }
@@ -0,0 +1,197 @@
+use crate::metrics::Metrics;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{self, HeaderValue};
+use std::collections::VecDeque;
+use std::future::{ready, Future, Ready};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// How far back database call outcomes count towards the failure rate
+const FAILURE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Calls needed in the window before the failure rate can mark us not ready
+const MIN_SAMPLES: usize = 10;
+
+/// Error budget for database availability: a sliding window of call outcomes
+/// that decides readiness
+pub struct DatabaseHealth {
+    max_failure_rate: f64,
+    samples: Mutex<VecDeque<(Instant, bool)>>,
+    metrics: Arc<Metrics>,
+}
+
+impl DatabaseHealth {
+    pub fn new(max_failure_rate: f64, metrics: Arc<Metrics>) -> Self {
+        Self {
+            max_failure_rate,
+            samples: Mutex::new(VecDeque::new()),
+            metrics,
+        }
+    }
+
+    /// Record the outcome of one database call
+    pub fn record(&self, unavailable: bool) {
+        if unavailable {
+            self.metrics.inc_db_unavailable();
+        }
+
+        let now = Instant::now();
+        let mut samples = self.samples.lock().unwrap();
+        samples.push_back((now, unavailable));
+        Self::prune(&mut samples, now);
+        self.metrics.set_db_failure_rate(Self::rate(&samples));
+    }
+
+    /// Share of calls in the window that found the database unavailable
+    pub fn failure_rate(&self) -> f64 {
+        let mut samples = self.samples.lock().unwrap();
+        Self::prune(&mut samples, Instant::now());
+        Self::rate(&samples)
+    }
+
+    /// False while the failure rate is over budget
+    pub fn is_ready(&self) -> bool {
+        let mut samples = self.samples.lock().unwrap();
+        Self::prune(&mut samples, Instant::now());
+        samples.len() < MIN_SAMPLES || Self::rate(&samples) <= self.max_failure_rate
+    }
+
+    fn prune(samples: &mut VecDeque<(Instant, bool)>, now: Instant) {
+        while samples
+            .front()
+            .is_some_and(|(at, _)| now.duration_since(*at) > FAILURE_WINDOW)
+        {
+            samples.pop_front();
+        }
+    }
+
+    fn rate(samples: &VecDeque<(Instant, bool)>) -> f64 {
+        if samples.is_empty() {
+            return 0.0;
+        }
+        samples.iter().filter(|(_, failed)| *failed).count() as f64 / samples.len() as f64
+    }
+}
+
+/// Marks a response as failed because the database was unavailable, so
+/// [`ServiceUnavailable`] can tell it apart from other 503s
+pub struct DatabaseUnavailable;
+
+/// Middleware adding a `Retry-After` to responses that failed because the
+/// database was unavailable, so remote servers back off instead of retrying
+/// at once
+#[derive(Clone)]
+pub struct ServiceUnavailable {
+    retry_after_secs: u64,
+}
+
+impl ServiceUnavailable {
+    pub fn new(retry_after_secs: u64) -> Self {
+        Self { retry_after_secs }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ServiceUnavailable
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = ServiceUnavailableMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ServiceUnavailableMiddleware {
+            service: Rc::new(service),
+            config: self.clone(),
+        }))
+    }
+}
+
+pub struct ServiceUnavailableMiddleware<S> {
+    service: Rc<S>,
+    config: ServiceUnavailable,
+}
+
+type LocalBoxFuture<T> = Pin<Box<dyn Future<Output = T>>>;
+
+impl<S, B> Service<ServiceRequest> for ServiceUnavailableMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let retry_after_secs = self.config.retry_after_secs;
+
+        Box::pin(async move {
+            let mut res = service.call(req).await?;
+
+            // Only this request's own error marks it; other 503s pass through
+            if res
+                .response()
+                .extensions()
+                .get::<DatabaseUnavailable>()
+                .is_none()
+            {
+                return Ok(res);
+            }
+
+            warn!(
+                "Database unavailable while serving {}; answering 503",
+                res.request().path()
+            );
+            res.headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after_secs));
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn health() -> DatabaseHealth {
+        DatabaseHealth::new(0.5, Arc::new(Metrics::new()))
+    }
+
+    #[test]
+    fn test_ready_until_failure_rate_exceeds_budget() {
+        let health = health();
+        for _ in 0..5 {
+            health.record(false);
+        }
+        for _ in 0..5 {
+            health.record(true);
+        }
+        // Exactly at the budget is still ready
+        assert_eq!(health.failure_rate(), 0.5);
+        assert!(health.is_ready());
+
+        health.record(true);
+        assert!(!health.is_ready());
+    }
+
+    #[test]
+    fn test_few_samples_never_mark_not_ready() {
+        let health = health();
+        for _ in 0..MIN_SAMPLES - 1 {
+            health.record(true);
+        }
+
+        assert_eq!(health.failure_rate(), 1.0);
+        assert!(health.is_ready());
+    }
+}
@@ -0,0 +1,155 @@
+use crate::http::json_errors::{truncate, MAX_JSON_PAYLOAD};
+use actix_web::dev::Payload;
+use actix_web::error::PayloadError;
+use actix_web::http::StatusCode;
+use actix_web::{web, FromRequest, HttpMessage, HttpRequest, HttpResponse, ResponseError};
+use futures::future::LocalBoxFuture;
+use futures::StreamExt;
+use serde_json::{json, Value};
+use std::ops::Deref;
+use tracing::info;
+
+/// Media type some older clients post activities with
+const FORM_CONTENT_TYPE: &str = "application/x-www-form-urlencoded";
+
+/// Form parameter legacy clients put the JSON activity in
+const FORM_BODY_PARAM: &str = "body";
+
+/// An activity posted to an inbox or outbox. JSON bodies are extracted like
+/// `web::Json<Value>`; form-encoded ones are taken as JSON if they parse as
+/// such, and otherwise from their `body` parameter.
+#[derive(Debug)]
+pub struct ActivityPayload(pub Value);
+
+impl ActivityPayload {
+    pub fn into_inner(self) -> Value {
+        self.0
+    }
+}
+
+impl Deref for ActivityPayload {
+    type Target = Value;
+
+    fn deref(&self) -> &Value {
+        &self.0
+    }
+}
+
+impl FromRequest for ActivityPayload {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        if !req.content_type().eq_ignore_ascii_case(FORM_CONTENT_TYPE) {
+            let json = web::Json::<Value>::from_request(req, payload);
+            return Box::pin(async move { Ok(ActivityPayload(json.await?.into_inner())) });
+        }
+
+        let path = req.path().to_string();
+        let mut payload = payload.take();
+        Box::pin(async move {
+            let parsed = match read_body(&mut payload, MAX_JSON_PAYLOAD).await {
+                Ok(body) => parse_form_body(&body),
+                Err(e) => Err(e),
+            };
+            parsed.map(ActivityPayload).map_err(|e| {
+                info!("Rejected form-encoded payload for {}: {}", path, e);
+                e.into()
+            })
+        })
+    }
+}
+
+/// Why a form-encoded activity could not be extracted
+#[derive(Debug, thiserror::Error)]
+pub enum ActivityPayloadError {
+    #[error("Payload exceeds the limit of {0} bytes")]
+    Overflow(usize),
+    #[error("Form has no `body` parameter")]
+    MissingBody,
+    #[error("{0}")]
+    InvalidJson(#[from] serde_json::Error),
+    #[error("{0}")]
+    Payload(#[from] PayloadError),
+}
+
+impl ResponseError for ActivityPayloadError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ActivityPayloadError::Overflow(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let error = match self {
+            ActivityPayloadError::Overflow(_) => "Payload too large",
+            ActivityPayloadError::InvalidJson(_) => "Invalid JSON",
+            ActivityPayloadError::MissingBody | ActivityPayloadError::Payload(_) => {
+                "Invalid request body"
+            }
+        };
+        HttpResponse::build(self.status_code()).json(json!({
+            "error": error,
+            "detail": truncate(&self.to_string())
+        }))
+    }
+}
+
+async fn read_body(payload: &mut Payload, limit: usize) -> Result<Vec<u8>, ActivityPayloadError> {
+    let mut body = Vec::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > limit {
+            return Err(ActivityPayloadError::Overflow(limit));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+/// The activity in a form-encoded body: the body itself when it is JSON
+/// mislabelled as a form, otherwise the JSON in its `body` parameter
+fn parse_form_body(body: &[u8]) -> Result<Value, ActivityPayloadError> {
+    if let Ok(activity @ Value::Object(_)) = serde_json::from_slice(body) {
+        return Ok(activity);
+    }
+
+    let (_, activity) = url::form_urlencoded::parse(body)
+        .find(|(name, _)| name == FORM_BODY_PARAM)
+        .ok_or(ActivityPayloadError::MissingBody)?;
+    Ok(serde_json::from_str(&activity)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_form_body() {
+        let activity = json!({"type": "Follow", "object": "https://example.com/users/alice"});
+        let encoded: String = url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("other", "ignored")
+            .append_pair("body", &activity.to_string())
+            .finish();
+        assert_eq!(parse_form_body(encoded.as_bytes()).unwrap(), activity);
+
+        // JSON sent with the wrong content type is still JSON
+        assert_eq!(
+            parse_form_body(activity.to_string().as_bytes()).unwrap(),
+            activity
+        );
+    }
+
+    #[test]
+    fn test_parse_form_body_errors() {
+        assert!(matches!(
+            parse_form_body(b"activity=%7B%7D"),
+            Err(ActivityPayloadError::MissingBody)
+        ));
+        assert!(matches!(
+            parse_form_body(b"body=%7Bnot+json"),
+            Err(ActivityPayloadError::InvalidJson(_))
+        ));
+    }
+}
@@ -0,0 +1,144 @@
+use actix_web::http::header::{
+    self, EntityTag, Header, HttpDate, IfModifiedSince, IfNoneMatch, LastModified,
+};
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use actix_web::{HttpRequest, HttpResponse, HttpResponseBuilder};
+use chrono::{DateTime, Utc};
+use sha2::{Digest, Sha256};
+use std::time::SystemTime;
+
+/// Strong entity tag for a response body: a truncated SHA-256 of its bytes
+pub fn etag_for(body: &[u8]) -> EntityTag {
+    let digest = Sha256::digest(body);
+    let hex: String = digest[..16].iter().map(|b| format!("{b:02x}")).collect();
+    EntityTag::new_strong(hex)
+}
+
+/// Whether the request's validators show the client already has this
+/// version. `If-None-Match` takes precedence over `If-Modified-Since`.
+pub fn is_not_modified(
+    req: &HttpRequest,
+    etag: &EntityTag,
+    last_modified: Option<DateTime<Utc>>,
+) -> bool {
+    if req.headers().contains_key(header::IF_NONE_MATCH) {
+        return match IfNoneMatch::parse(req) {
+            Ok(IfNoneMatch::Any) => true,
+            Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+            Err(_) => false,
+        };
+    }
+
+    match (IfModifiedSince::parse(req), last_modified) {
+        (Ok(IfModifiedSince(since)), Some(modified)) => http_date(modified) <= since,
+        _ => false,
+    }
+}
+
+/// Finish a GET response with `ETag` and `Last-Modified`, answering 304 with
+/// no body when the client's cached copy is still current
+pub fn respond(
+    req: &HttpRequest,
+    response: HttpResponseBuilder,
+    body: Vec<u8>,
+    last_modified: Option<DateTime<Utc>>,
+) -> HttpResponse {
+    let etag = etag_for(&body);
+    respond_with_etag(req, response, Bytes::from(body), etag, last_modified)
+}
+
+/// As [`respond`], for a body whose ETag was computed earlier
+pub fn respond_with_etag(
+    req: &HttpRequest,
+    mut response: HttpResponseBuilder,
+    body: Bytes,
+    etag: EntityTag,
+    last_modified: Option<DateTime<Utc>>,
+) -> HttpResponse {
+    let not_modified = is_not_modified(req, &etag, last_modified);
+
+    response.insert_header(header::ETag(etag));
+    if let Some(modified) = last_modified {
+        response.insert_header(LastModified(http_date(modified)));
+    }
+
+    if not_modified {
+        response.status(StatusCode::NOT_MODIFIED);
+        return response.finish();
+    }
+    response.body(body)
+}
+
+/// HTTP dates only carry whole seconds
+fn http_date(time: DateTime<Utc>) -> HttpDate {
+    let seconds = time.timestamp().max(0) as u64;
+    HttpDate::from(SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    use chrono::TimeZone;
+
+    fn modified() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_etag_depends_on_body() {
+        assert_eq!(etag_for(b"{}"), etag_for(b"{}"));
+        assert_ne!(etag_for(b"{}"), etag_for(b"[]"));
+    }
+
+    #[test]
+    fn test_if_none_match() {
+        let etag = etag_for(b"{}");
+
+        let req = TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, etag.to_string()))
+            .to_http_request();
+        assert!(is_not_modified(&req, &etag, None));
+
+        let req = TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, etag_for(b"[]").to_string()))
+            .to_http_request();
+        assert!(!is_not_modified(&req, &etag, None));
+
+        let req = TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, "*"))
+            .to_http_request();
+        assert!(is_not_modified(&req, &etag, None));
+    }
+
+    #[test]
+    fn test_if_modified_since() {
+        let etag = etag_for(b"{}");
+        let since = |time: DateTime<Utc>| {
+            TestRequest::default()
+                .insert_header(IfModifiedSince(http_date(time)))
+                .to_http_request()
+        };
+
+        assert!(is_not_modified(&since(modified()), &etag, Some(modified())));
+        assert!(!is_not_modified(
+            &since(modified() - chrono::Duration::seconds(1)),
+            &etag,
+            Some(modified())
+        ));
+        // Nothing to compare against without a modification time
+        assert!(!is_not_modified(&since(modified()), &etag, None));
+    }
+
+    #[test]
+    fn test_if_none_match_wins_over_if_modified_since() {
+        let etag = etag_for(b"{}");
+        let req = TestRequest::default()
+            .insert_header((header::IF_NONE_MATCH, etag_for(b"[]").to_string()))
+            .insert_header(IfModifiedSince(http_date(modified())))
+            .to_http_request();
+
+        assert!(!is_not_modified(&req, &etag, Some(modified())));
+    }
+}
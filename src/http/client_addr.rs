@@ -0,0 +1,32 @@
+use actix_web::HttpRequest;
+use std::net::{IpAddr, SocketAddr};
+
+/// The address `req` came from: the client a trusted proxy says it forwarded
+/// the request for, or else the connecting address. Anyone else can put
+/// whatever they like in `Forwarded` and `X-Forwarded-For`, so those headers
+/// only count when the connection comes from one of `trusted_proxies`.
+pub fn client_ip(req: &HttpRequest, trusted_proxies: &[IpAddr]) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    if !trusted_proxies.contains(&peer) {
+        return Some(peer);
+    }
+    let forwarded = req
+        .connection_info()
+        .realip_remote_addr()
+        .and_then(parse_ip);
+    Some(forwarded.unwrap_or(peer))
+}
+
+/// An address as proxies write it: bare, with a port, or in brackets
+fn parse_ip(value: &str) -> Option<IpAddr> {
+    value
+        .parse()
+        .ok()
+        .or_else(|| value.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| {
+            value
+                .strip_prefix('[')
+                .and_then(|v| v.strip_suffix(']'))
+                .and_then(|v| v.parse().ok())
+        })
+}
@@ -0,0 +1,124 @@
+use actix_web::http::header;
+use actix_web::HttpRequest;
+
+/// Media types we serve ActivityStreams documents as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContentType {
+    /// `application/activity+json`
+    ActivityJson,
+    /// `application/ld+json; profile="https://www.w3.org/ns/activitystreams"`
+    LdJson,
+}
+
+impl ContentType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentType::ActivityJson => "application/activity+json",
+            ContentType::LdJson => {
+                "application/ld+json; profile=\"https://www.w3.org/ns/activitystreams\""
+            }
+        }
+    }
+}
+
+/// Pick the ActivityStreams media type that best matches an `Accept` header,
+/// honouring q-values. Falls back to `application/activity+json`.
+pub fn negotiate_content_type(accept: &str) -> ContentType {
+    let mut best: Option<(f32, ContentType)> = None;
+
+    for range in accept.split(',') {
+        let mut parts = range.split(';').map(str::trim);
+        let media_type = parts.next().unwrap_or_default().to_ascii_lowercase();
+
+        let content_type = match media_type.as_str() {
+            "application/activity+json" => ContentType::ActivityJson,
+            "application/ld+json" => ContentType::LdJson,
+            _ => continue,
+        };
+
+        let quality = parts
+            .filter_map(|param| param.strip_prefix("q="))
+            .find_map(|q| q.parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        // Earlier ranges win ties
+        if quality > 0.0 && best.is_none_or(|(q, _)| quality > q) {
+            best = Some((quality, content_type));
+        }
+    }
+
+    best.map_or(ContentType::ActivityJson, |(_, content_type)| content_type)
+}
+
+/// Negotiate the response content type for a request's `Accept` header
+pub fn negotiate_request(req: &HttpRequest) -> ContentType {
+    req.headers()
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .map_or(ContentType::ActivityJson, negotiate_content_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_defaults_to_activity_json() {
+        assert_eq!(negotiate_content_type(""), ContentType::ActivityJson);
+        assert_eq!(negotiate_content_type("*/*"), ContentType::ActivityJson);
+        assert_eq!(
+            negotiate_content_type("application/json"),
+            ContentType::ActivityJson
+        );
+    }
+
+    #[test]
+    fn test_ld_json_with_profile() {
+        assert_eq!(
+            negotiate_content_type(
+                "application/ld+json; profile=\"https://www.w3.org/ns/activitystreams\""
+            ),
+            ContentType::LdJson
+        );
+        assert_eq!(
+            negotiate_content_type("application/ld+json"),
+            ContentType::LdJson
+        );
+    }
+
+    #[test]
+    fn test_first_match_wins_ties() {
+        assert_eq!(
+            negotiate_content_type("application/activity+json, application/ld+json"),
+            ContentType::ActivityJson
+        );
+        assert_eq!(
+            negotiate_content_type("application/ld+json, application/activity+json"),
+            ContentType::LdJson
+        );
+    }
+
+    #[test]
+    fn test_quality_values() {
+        assert_eq!(
+            negotiate_content_type("application/activity+json;q=0.5, application/ld+json"),
+            ContentType::LdJson
+        );
+        assert_eq!(
+            negotiate_content_type("application/ld+json;q=0, text/html"),
+            ContentType::ActivityJson
+        );
+    }
+
+    #[test]
+    fn test_content_type_strings() {
+        assert_eq!(
+            ContentType::ActivityJson.as_str(),
+            "application/activity+json"
+        );
+        assert_eq!(
+            ContentType::LdJson.as_str(),
+            "application/ld+json; profile=\"https://www.w3.org/ns/activitystreams\""
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use super::request_id::REQUEST_ID_HEADER;
+use actix_cors::Cors;
+use actix_web::dev::RequestHead;
+use actix_web::http::{header, Method};
+
+/// Response headers browser clients may read from cross-origin responses
+const EXPOSED_HEADERS: [&str; 5] = [
+    "Link",
+    "X-RateLimit-Limit",
+    "X-RateLimit-Remaining",
+    "X-RateLimit-Reset",
+    "X-Request-ID",
+];
+
+/// How long browsers may cache a preflight result
+const PREFLIGHT_MAX_AGE_SECS: usize = 3600;
+
+/// CORS policy for browser-based clients calling the API. Listed origins may
+/// make any request; `*`, the development default, lets any origin read with
+/// `GET`. Inboxes are for servers, so only listed origins reach them.
+pub fn cors(allowed_origins: &[String]) -> Cors {
+    let any_origin_reads = allowed_origins.iter().any(|origin| origin == "*");
+    let listed: Vec<String> = allowed_origins
+        .iter()
+        .filter(|origin| *origin != "*")
+        .cloned()
+        .collect();
+
+    Cors::default()
+        .allowed_origin_fn(move |origin, req| {
+            listed.iter().any(|listed| origin == listed.as_str())
+                || (any_origin_reads && is_public_read(req))
+        })
+        .allowed_methods([
+            Method::GET,
+            Method::POST,
+            Method::PATCH,
+            Method::DELETE,
+            Method::OPTIONS,
+        ])
+        .allowed_headers([
+            header::AUTHORIZATION,
+            header::CONTENT_TYPE,
+            header::ACCEPT,
+            header::HeaderName::from_static(REQUEST_ID_HEADER),
+        ])
+        .expose_headers(EXPOSED_HEADERS)
+        .max_age(PREFLIGHT_MAX_AGE_SECS)
+}
+
+/// Whether a request, or the one a preflight asks about, reads something
+/// other than an inbox
+fn is_public_read(req: &RequestHead) -> bool {
+    let method = if req.method == Method::OPTIONS {
+        req.headers()
+            .get(header::ACCESS_CONTROL_REQUEST_METHOD)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default()
+    } else {
+        req.method.as_str()
+    };
+    matches!(method, "GET" | "HEAD") && !req.uri.path().ends_with("/inbox")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn head(method: Method, path: &str, requested: Option<&str>) -> RequestHead {
+        let mut req = TestRequest::default().method(method).uri(path);
+        if let Some(requested) = requested {
+            req = req.insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, requested));
+        }
+        req.to_srv_request().head().clone()
+    }
+
+    #[test]
+    fn test_public_reads() {
+        assert!(is_public_read(&head(Method::GET, "/users/alice", None)));
+        assert!(is_public_read(&head(
+            Method::OPTIONS,
+            "/users/alice/outbox",
+            Some("GET")
+        )));
+        assert!(!is_public_read(&head(
+            Method::OPTIONS,
+            "/users/alice/outbox",
+            Some("POST")
+        )));
+        assert!(!is_public_read(&head(
+            Method::GET,
+            "/users/alice/inbox",
+            None
+        )));
+        assert!(!is_public_read(&head(
+            Method::OPTIONS,
+            "/users/alice",
+            None
+        )));
+    }
+}
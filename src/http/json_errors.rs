@@ -0,0 +1,74 @@
+use actix_web::error::{InternalError, JsonPayloadError};
+use actix_web::{web, HttpRequest, HttpResponse};
+use tracing::info;
+
+/// Largest JSON body accepted by any endpoint
+pub const MAX_JSON_PAYLOAD: usize = 1024 * 1024;
+
+/// Longest parser message echoed back in an error `detail`
+const MAX_DETAIL_LEN: usize = 200;
+
+/// JSON extractor configuration that answers bad payloads with our usual
+/// `{"error", "detail"}` bodies instead of actix's plain-text errors
+pub fn json_config() -> web::JsonConfig {
+    web::JsonConfig::default()
+        .limit(MAX_JSON_PAYLOAD)
+        .error_handler(json_error_handler)
+}
+
+fn json_error_handler(err: JsonPayloadError, req: &HttpRequest) -> actix_web::Error {
+    info!("Rejected JSON payload for {}: {}", req.path(), err);
+
+    let response = match &err {
+        JsonPayloadError::OverflowKnownLength { length, limit } => HttpResponse::PayloadTooLarge()
+            .json(serde_json::json!({
+                "error": "Payload too large",
+                "detail": format!("Payload of {length} bytes exceeds the limit of {limit} bytes")
+            })),
+        JsonPayloadError::Overflow { limit } => {
+            HttpResponse::PayloadTooLarge().json(serde_json::json!({
+                "error": "Payload too large",
+                "detail": format!("Payload exceeds the limit of {limit} bytes")
+            }))
+        }
+        JsonPayloadError::ContentType => {
+            HttpResponse::UnsupportedMediaType().json(serde_json::json!({
+                "error": "Unsupported content type",
+                "detail": "Expected application/activity+json or application/json"
+            }))
+        }
+        // serde_json reports the line and column where parsing stopped
+        JsonPayloadError::Deserialize(e) => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Invalid JSON",
+            "detail": truncate(&e.to_string())
+        })),
+        other => HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Invalid request body",
+            "detail": truncate(&other.to_string())
+        })),
+    };
+
+    InternalError::from_response(err, response).into()
+}
+
+pub(crate) fn truncate(detail: &str) -> String {
+    match detail.char_indices().nth(MAX_DETAIL_LEN) {
+        Some((end, _)) => format!("{}...", &detail[..end]),
+        None => detail.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate_long_details() {
+        assert_eq!(truncate("short"), "short");
+
+        let long = "x".repeat(MAX_DETAIL_LEN + 50);
+        let truncated = truncate(&long);
+        assert_eq!(truncated.len(), MAX_DETAIL_LEN + 3);
+        assert!(truncated.ends_with("..."));
+    }
+}
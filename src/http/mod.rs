@@ -1,7 +1,27 @@
+pub mod activity_payload;
+pub mod caching;
 pub mod client;
+pub mod client_addr;
+pub mod content_type;
+pub mod cors;
+pub mod json_errors;
+pub mod pagination;
+pub mod rate_limit;
+pub mod recording;
+pub mod request_id;
 
 // Re-export the main traits for easy access
+pub use activity_payload::ActivityPayload;
 pub use client::HttpClient;
+pub use client_addr::client_ip;
+#[allow(unused_imports)]
+pub use content_type::{negotiate_content_type, ContentType};
+pub use cors::cors;
+pub use json_errors::json_config;
+pub use rate_limit::ApiRateLimit;
+pub use request_id::RequestId;
 
 // Re-export implementations
 pub use client::reqwest::ReqwestClient;
+#[allow(unused_imports)]
+pub use recording::RecordingHttpClient;
@@ -0,0 +1,182 @@
+//! Mastodon-style `Link` headers, which clients follow to page through
+//! collections instead of reading `next` and `prev` from the body
+
+/// `Link` header value pointing at the pages either side of this one, or
+/// `None` when there are neither
+pub fn link_header(next: Option<&str>, prev: Option<&str>) -> Option<String> {
+    let links: Vec<String> = [(next, "next"), (prev, "prev")]
+        .into_iter()
+        .filter_map(|(url, rel)| url.map(|url| format!("<{url}>; rel=\"{rel}\"")))
+        .collect();
+    (!links.is_empty()).then(|| links.join(", "))
+}
+
+/// `base_url` with the request's query string, each of `params` replacing
+/// any value it already had there, or removing it when `None`. Other
+/// parameters, such as filters, are carried over untouched.
+pub fn page_url(base_url: &str, query_string: &str, params: &[(&str, Option<&str>)]) -> String {
+    let mut pairs: Vec<String> = query_string
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .filter(|pair| {
+            let key = pair.split('=').next().unwrap_or_default();
+            !params.iter().any(|(name, _)| *name == key)
+        })
+        .map(str::to_string)
+        .collect();
+    pairs.extend(
+        params
+            .iter()
+            .filter_map(|(name, value)| value.map(|value| format!("{name}={value}"))),
+    );
+
+    if pairs.is_empty() {
+        base_url.to_string()
+    } else {
+        format!("{base_url}?{}", pairs.join("&"))
+    }
+}
+
+/// Links for `offset` paging. A full page may have another after it; any
+/// page past the start has one before it. `params` are set on both links,
+/// for endpoints that need more than an offset to serve a page.
+pub fn offset_links(
+    base_url: &str,
+    query_string: &str,
+    offset: u32,
+    limit: u32,
+    returned: usize,
+    params: &[(&str, Option<&str>)],
+) -> Option<String> {
+    let link = |offset: u32| {
+        let offset = offset.to_string();
+        let mut params = params.to_vec();
+        params.push(("offset", Some(&offset)));
+        page_url(base_url, query_string, &params)
+    };
+
+    let next = (returned as u32 >= limit).then(|| link(offset.saturating_add(limit)));
+    let prev = (offset > 0).then(|| link(offset.saturating_sub(limit)));
+    link_header(next.as_deref(), prev.as_deref())
+}
+
+/// Links for `max_id`/`min_id` paging over a page running from `newest` to
+/// `oldest`: the next page is older than its last item, the previous newer
+/// than its first. `more_older` says whether anything is left past the last
+/// item, and `paginating` whether this is a later page rather than the
+/// first, which has nothing before it.
+pub fn id_links(
+    base_url: &str,
+    query_string: &str,
+    newest: Option<&str>,
+    oldest: Option<&str>,
+    more_older: bool,
+    paginating: bool,
+) -> Option<String> {
+    let (next, prev) = id_page_urls(
+        base_url,
+        query_string,
+        newest,
+        oldest,
+        more_older,
+        paginating,
+        &[],
+    );
+    link_header(next.as_deref(), prev.as_deref())
+}
+
+/// The next and previous page URLs behind [`id_links`], for collections
+/// that also link them from the body. `params` are set on both, like
+/// [`offset_links`]' are. Ids go in as given, so they must be safe in a
+/// query string.
+pub fn id_page_urls(
+    base_url: &str,
+    query_string: &str,
+    newest: Option<&str>,
+    oldest: Option<&str>,
+    more_older: bool,
+    paginating: bool,
+    params: &[(&str, Option<&str>)],
+) -> (Option<String>, Option<String>) {
+    let link = |cursor: [(&str, Option<&str>); 2]| {
+        let mut params = params.to_vec();
+        params.extend(cursor);
+        page_url(base_url, query_string, &params)
+    };
+
+    let next = oldest
+        .filter(|_| more_older)
+        .map(|id| link([("max_id", Some(id)), ("min_id", None)]));
+    let prev = newest
+        .filter(|_| paginating)
+        .map(|id| link([("min_id", Some(id)), ("max_id", None)]));
+    (next, prev)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BASE: &str = "https://example.com/users/alice/statuses";
+
+    #[test]
+    fn test_link_header_lists_next_before_prev() {
+        assert_eq!(link_header(None, None), None);
+        assert_eq!(
+            link_header(Some("https://a/?n"), Some("https://a/?p")).unwrap(),
+            r#"<https://a/?n>; rel="next", <https://a/?p>; rel="prev""#
+        );
+        assert_eq!(
+            link_header(None, Some("https://a/?p")).unwrap(),
+            r#"<https://a/?p>; rel="prev""#
+        );
+    }
+
+    #[test]
+    fn test_page_url_replaces_and_keeps_parameters() {
+        assert_eq!(page_url(BASE, "", &[]), BASE);
+        assert_eq!(
+            page_url(
+                BASE,
+                "limit=5&max_id=abc&type=Create",
+                &[("max_id", Some("def")), ("min_id", None)]
+            ),
+            format!("{BASE}?limit=5&type=Create&max_id=def")
+        );
+        assert_eq!(
+            page_url(BASE, "min_id=abc", &[("min_id", None)]),
+            BASE.to_string()
+        );
+    }
+
+    #[test]
+    fn test_offset_links() {
+        // A short first page has nowhere to go
+        assert_eq!(offset_links(BASE, "limit=2", 0, 2, 1, &[]), None);
+        assert_eq!(
+            offset_links(BASE, "limit=2", 0, 2, 2, &[]).unwrap(),
+            format!(r#"<{BASE}?limit=2&offset=2>; rel="next""#)
+        );
+        assert_eq!(
+            offset_links(BASE, "limit=2&offset=3", 3, 2, 2, &[("page", Some("true"))]).unwrap(),
+            format!(
+                r#"<{BASE}?limit=2&page=true&offset=5>; rel="next", <{BASE}?limit=2&page=true&offset=1>; rel="prev""#
+            )
+        );
+    }
+
+    #[test]
+    fn test_id_page_urls_set_params_on_both() {
+        let (next, prev) = id_page_urls(
+            BASE,
+            "max_id=c&offset=20",
+            Some("b"),
+            Some("a"),
+            true,
+            true,
+            &[("page", Some("true")), ("offset", None)],
+        );
+        assert_eq!(next.unwrap(), format!("{BASE}?page=true&max_id=a"));
+        assert_eq!(prev.unwrap(), format!("{BASE}?page=true&min_id=b"));
+    }
+}
@@ -0,0 +1,187 @@
+use crate::auth;
+use crate::config::Config;
+use crate::database::DatabaseRef;
+use crate::errors::FederationError;
+use crate::http::client_ip;
+use crate::services::rate_limit::{ApiRateLimiter, RateLimitStatus, RequestClass};
+use actix_web::body::EitherBody;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue};
+use actix_web::http::Method;
+use actix_web::{web, ResponseError};
+use futures::future::LocalBoxFuture;
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+const LIMIT_HEADER: &str = "x-ratelimit-limit";
+const REMAINING_HEADER: &str = "x-ratelimit-remaining";
+const RESET_HEADER: &str = "x-ratelimit-reset";
+
+/// Limits client API requests (`/api/...` and outbox posts) per bearer
+/// token, or per IP address for requests without a valid one. Reads and writes
+/// spend separate budgets. Limited responses carry `X-RateLimit-Limit`,
+/// `X-RateLimit-Remaining` and `X-RateLimit-Reset`; refused ones are a 429
+/// with `Retry-After`.
+#[derive(Clone)]
+pub struct ApiRateLimit {
+    limiter: Arc<ApiRateLimiter>,
+    base_path: String,
+}
+
+impl ApiRateLimit {
+    pub fn new(limiter: Arc<ApiRateLimiter>, base_path: &str) -> Self {
+        Self {
+            limiter,
+            base_path: base_path.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Which budget `method` on `path` spends, if it is a client API request
+    fn classify(&self, method: &Method, path: &str) -> Option<RequestClass> {
+        let path = path.strip_prefix(&self.base_path)?;
+        let outbox_post =
+            *method == Method::POST && path.starts_with("/users/") && path.ends_with("/outbox");
+        if !(path.starts_with("/api/") || outbox_post) {
+            return None;
+        }
+        match *method {
+            Method::OPTIONS => None,
+            Method::GET | Method::HEAD => Some(RequestClass::Read),
+            _ => Some(RequestClass::Write),
+        }
+    }
+}
+
+/// The bucket a request is counted in: the token it signs in with, or else
+/// the client's address. Tokens are checked first, so made-up ones can't
+/// each get a fresh budget.
+async fn client_key(req: &ServiceRequest) -> String {
+    let token = auth::bearer_token(req.headers());
+    let db = req.app_data::<web::Data<DatabaseRef>>();
+    if let (Some(token), Some(db)) = (token, db) {
+        match db.validate_token(token).await {
+            Ok(Some(token)) => return format!("token:{}", token.id),
+            Ok(None) => {}
+            Err(e) => warn!("Database error while validating token: {}", e),
+        }
+    }
+    let trusted_proxies = req
+        .app_data::<web::Data<Config>>()
+        .map(|config| config.trusted_proxies.as_slice())
+        .unwrap_or_default();
+    match client_ip(req.request(), trusted_proxies) {
+        Some(ip) => format!("ip:{ip}"),
+        None => "ip:unknown".to_string(),
+    }
+}
+
+fn insert_headers(headers: &mut HeaderMap, status: &RateLimitStatus) {
+    let values = [
+        (LIMIT_HEADER, status.limit.to_string()),
+        (REMAINING_HEADER, status.remaining.to_string()),
+        (RESET_HEADER, status.reset_at.to_rfc3339()),
+    ];
+    for (name, value) in values {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(HeaderName::from_static(name), value);
+        }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for ApiRateLimit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = ApiRateLimitMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ApiRateLimitMiddleware {
+            service: Rc::new(service),
+            config: self.clone(),
+        }))
+    }
+}
+
+pub struct ApiRateLimitMiddleware<S> {
+    service: Rc<S>,
+    config: ApiRateLimit,
+}
+
+impl<S, B> Service<ServiceRequest> for ApiRateLimitMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let limiter = self.config.limiter.clone();
+        let class = self.config.classify(req.method(), req.path());
+
+        Box::pin(async move {
+            let status = match class {
+                Some(class) => limiter.check(class, &client_key(&req).await),
+                None => None,
+            };
+            let Some(status) = status else {
+                return Ok(service.call(req).await?.map_into_left_body());
+            };
+
+            if let Some(retry_after_secs) = status.retry_after_secs {
+                info!("Rate limited {} {}", req.method(), req.path());
+                let mut response =
+                    FederationError::QuotaExceeded { retry_after_secs }.error_response();
+                insert_headers(response.headers_mut(), &status);
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+
+            let mut res = service.call(req).await?;
+            insert_headers(res.headers_mut(), &status);
+            Ok(res.map_into_left_body())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn test_classify_covers_the_client_api_only() {
+        let limit = ApiRateLimit::new(
+            Arc::new(ApiRateLimiter::from_config(&Config::default())),
+            "/fedi/",
+        );
+        let class = |method: Method, path: &str| limit.classify(&method, path);
+
+        assert_eq!(
+            class(Method::GET, "/fedi/api/v1/statuses/1"),
+            Some(RequestClass::Read)
+        );
+        assert_eq!(
+            class(Method::PATCH, "/fedi/api/v1/preferences"),
+            Some(RequestClass::Write)
+        );
+        assert_eq!(
+            class(Method::POST, "/fedi/users/alice/outbox"),
+            Some(RequestClass::Write)
+        );
+        assert_eq!(class(Method::OPTIONS, "/fedi/api/v1/preferences"), None);
+        assert_eq!(class(Method::GET, "/fedi/users/alice/outbox"), None);
+        assert_eq!(class(Method::POST, "/fedi/users/alice/inbox"), None);
+        assert_eq!(class(Method::GET, "/api/v1/statuses/1"), None);
+    }
+}
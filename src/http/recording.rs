@@ -0,0 +1,140 @@
+use super::client::{HttpClient, HttpRequest, HttpResponse};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+
+/// An `HttpClient` that passes every request on to another client and keeps
+/// a copy of it, so tests can see exactly what a node sent.
+#[allow(dead_code)]
+pub struct RecordingHttpClient {
+    inner: Arc<dyn HttpClient>,
+    log: Mutex<Vec<HttpRequest>>,
+    recorded: Notify,
+}
+
+#[allow(dead_code)]
+impl RecordingHttpClient {
+    pub fn new(inner: Arc<dyn HttpClient>) -> Self {
+        Self {
+            inner,
+            log: Mutex::new(Vec::new()),
+            recorded: Notify::new(),
+        }
+    }
+
+    /// Every request sent so far, oldest first
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        self.log.lock().unwrap().clone()
+    }
+
+    /// The POSTs sent to `url`, oldest first
+    pub fn deliveries_to(&self, url: &str) -> Vec<HttpRequest> {
+        self.log
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|request| request.method == "POST" && request.url == url)
+            .cloned()
+            .collect()
+    }
+
+    /// Wait until a request matching `predicate` has been sent, returning the
+    /// first one that does, or `None` once `timeout` passes without one
+    pub async fn wait_for_delivery<F>(&self, predicate: F, timeout: Duration) -> Option<HttpRequest>
+    where
+        F: Fn(&HttpRequest) -> bool,
+    {
+        tokio::time::timeout(timeout, async {
+            loop {
+                // Register before looking, so a request recorded in between
+                // still wakes us
+                let recorded = self.recorded.notified();
+                if let Some(request) = self.log.lock().unwrap().iter().find(|r| predicate(r)) {
+                    return request.clone();
+                }
+                recorded.await;
+            }
+        })
+        .await
+        .ok()
+    }
+
+    /// Forget everything recorded so far
+    pub fn clear(&self) {
+        self.log.lock().unwrap().clear();
+    }
+}
+
+#[async_trait]
+impl HttpClient for RecordingHttpClient {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+        self.log.lock().unwrap().push(request.clone());
+        self.recorded.notify_waiters();
+        self.inner.send(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::client::StatusCode;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    struct OkClient;
+
+    #[async_trait]
+    impl HttpClient for OkClient {
+        async fn send(&self, _request: HttpRequest) -> Result<HttpResponse> {
+            Ok(HttpResponse {
+                status: StatusCode(202),
+                headers: HashMap::new(),
+                body: Vec::new(),
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_records_and_delegates() {
+        let client = RecordingHttpClient::new(Arc::new(OkClient));
+        let response = client
+            .post_json("https://b.example/inbox", &json!({"type": "Create"}))
+            .await
+            .unwrap();
+        client.get("https://b.example/users/bob").await.unwrap();
+
+        assert_eq!(response.status(), StatusCode(202));
+        assert_eq!(client.requests().len(), 2);
+        let deliveries = client.deliveries_to("https://b.example/inbox");
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(
+            deliveries[0].body.as_deref(),
+            Some(&br#"{"type":"Create"}"#[..])
+        );
+
+        client.clear();
+        assert!(client.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_delivery_sees_later_requests() {
+        let client = Arc::new(RecordingHttpClient::new(Arc::new(OkClient)));
+        let sender = client.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            sender.get("https://b.example/late").await.unwrap();
+        });
+
+        let request = client
+            .wait_for_delivery(|r| r.url.ends_with("/late"), Duration::from_secs(5))
+            .await;
+        assert!(request.is_some());
+
+        let missing = client
+            .wait_for_delivery(|r| r.url.ends_with("/never"), Duration::from_millis(20))
+            .await;
+        assert!(missing.is_none());
+    }
+}
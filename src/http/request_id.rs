@@ -0,0 +1,131 @@
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderName, HeaderValue};
+use futures::future::LocalBoxFuture;
+use std::future::{ready, Future, Ready};
+use std::rc::Rc;
+use tracing::Instrument;
+
+/// Header carrying the ID that ties one request's log lines together, here
+/// and on servers we call while handling it
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest client-supplied ID we keep; longer ones are replaced
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// The ID of the request being handled, if any
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Carry the current request's ID into `future`, for work spawned off a
+/// request that outlives it
+pub fn propagate<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let id = current();
+    async move {
+        match id {
+            Some(id) => REQUEST_ID.scope(id, future).await,
+            None => future.await,
+        }
+    }
+}
+
+/// A client's ID, if it is short printable ASCII that is safe to log and echo
+fn accept(value: &HeaderValue) -> Option<String> {
+    let id = value.to_str().ok()?;
+    let printable = id.bytes().all(|b| b.is_ascii_graphic());
+    (!id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN && printable).then(|| id.to_string())
+}
+
+/// Gives every request an `X-Request-ID`, the client's or a new UUID, which
+/// is recorded on the request's tracing span, echoed on the response and
+/// available to outgoing calls through [`current`]
+pub struct RequestId;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestId
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = RequestIdMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestIdMiddleware {
+            service: Rc::new(service),
+        }))
+    }
+}
+
+pub struct RequestIdMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestIdMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let id = req
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(accept)
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+        let span = tracing::info_span!(
+            "request",
+            request_id = %id,
+            method = %req.method(),
+            path = %req.path()
+        );
+
+        let scoped = id.clone();
+        let handled = async move {
+            let mut res = service.call(req).await?;
+            if let Ok(value) = HeaderValue::from_str(&id) {
+                res.headers_mut()
+                    .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+            }
+            Ok(res)
+        };
+        Box::pin(REQUEST_ID.scope(scoped, handled.instrument(span)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accepts_short_printable_ids_only() {
+        let id = |value: &str| accept(&HeaderValue::from_str(value).unwrap());
+        assert_eq!(id("abc-123").as_deref(), Some("abc-123"));
+        assert_eq!(id(""), None);
+        assert_eq!(id("two words"), None);
+        assert_eq!(id(&"x".repeat(MAX_REQUEST_ID_LEN + 1)), None);
+    }
+
+    #[tokio::test]
+    async fn test_propagate_carries_the_id_into_spawned_work() {
+        assert_eq!(current(), None);
+        let spawned = REQUEST_ID
+            .scope("req-1".to_string(), async {
+                tokio::spawn(propagate(async { current() })).await
+            })
+            .await;
+        assert_eq!(spawned.unwrap().as_deref(), Some("req-1"));
+    }
+}
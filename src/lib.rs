@@ -1,12 +1,24 @@
+pub mod auth;
+pub mod capabilities;
 pub mod config;
 pub mod container;
 pub mod database;
+pub mod errors;
 pub mod handlers;
+pub mod health;
 pub mod http;
+pub mod metered_database;
+pub mod metrics;
 pub mod models;
+#[cfg(feature = "postgres")]
+pub mod postgres_database;
 pub mod services;
+pub mod strict_mock_database;
+pub mod swappable_database;
+pub mod urls;
 
 // Re-export commonly used types for easier access
+pub use capabilities::Capabilities;
 pub use config::Config;
 pub use container::Container;
 pub use database::{Database, DatabaseRef, MockDatabase};
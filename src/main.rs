@@ -1,49 +1,408 @@
+mod auth;
+mod capabilities;
+mod cli;
 mod config;
 mod container;
 mod database;
+mod errors;
 mod handlers;
+mod health;
 mod http;
+mod metered_database;
+mod metrics;
 mod models;
+#[cfg(feature = "postgres")]
+mod postgres_database;
 mod services;
+mod strict_mock_database;
+mod swappable_database;
+mod urls;
 
-use actix_web::{middleware::Logger, web, App, HttpServer};
+use actix_web::{
+    middleware::{Condition, Logger},
+    web, App, HttpServer,
+};
 use container::Container;
-use database::{create_configured_mock_database, DatabaseRef};
+use health::ServiceUnavailable;
+use metrics::RequestMetrics;
+use services::scheduler::{Schedule, Scheduler, SystemClock};
+use services::{
+    delivery_queue, gc, inbound_usage, log_dedup, pending_accepts, scheduled_publishing,
+    seen_activities,
+};
 use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
+use tracing_subscriber::EnvFilter;
+
+/// Build the scheduler with the server's background jobs
+fn build_scheduler(container: &Container) -> Scheduler {
+    let mut scheduler =
+        Scheduler::new(Arc::new(SystemClock)).with_metrics(container.metrics().clone());
+
+    let db = container.database().clone();
+    let delivery = container.delivery_service().clone();
+    scheduler.register(
+        delivery_queue::DELIVERY_JOB,
+        Schedule::Every(chrono::Duration::seconds(5)),
+        move || {
+            let db = db.clone();
+            let delivery = delivery.clone();
+            async move {
+                let run = delivery_queue::process_queue(&db, &delivery, chrono::Utc::now()).await?;
+                if run != delivery_queue::DeliveryRun::default() {
+                    tracing::info!(
+                        "Delivery run: {} delivered, {} retried, {} dropped",
+                        run.delivered,
+                        run.retried,
+                        run.dropped
+                    );
+                }
+                Ok(())
+            }
+        },
+    );
+
+    let db = container.database().clone();
+    let metrics = container.metrics().clone();
+    let warnings = container.log_dedup().clone();
+    let max_age = chrono::Duration::seconds(container.config().delivery_queue_max_age_secs as i64);
+    scheduler.register(
+        delivery_queue::QUEUE_STATS_JOB,
+        Schedule::Every(chrono::Duration::seconds(30)),
+        move || {
+            let db = db.clone();
+            let metrics = metrics.clone();
+            let warnings = warnings.clone();
+            async move {
+                delivery_queue::report_backlog(
+                    &db,
+                    &metrics,
+                    &warnings,
+                    max_age,
+                    chrono::Utc::now(),
+                )
+                .await?;
+                Ok(())
+            }
+        },
+    );
+
+    let db = container.database().clone();
+    scheduler.register(
+        "pending-accepts-prune",
+        Schedule::Every(chrono::Duration::minutes(5)),
+        move || {
+            let db = db.clone();
+            async move {
+                pending_accepts::prune_expired(&db).await?;
+                Ok(())
+            }
+        },
+    );
+
+    let warnings = container.log_dedup().clone();
+    let window = container.config().log_dedup_window_secs as i64;
+    scheduler.register(
+        log_dedup::LOG_SUMMARY_JOB,
+        Schedule::Every(chrono::Duration::seconds(window)),
+        move || {
+            let warnings = warnings.clone();
+            async move {
+                warnings.summarize();
+                Ok(())
+            }
+        },
+    );
+
+    let db = container.database().clone();
+    let actor_fetch = container.actor_fetch().clone();
+    let urls = container.urls().clone();
+    let delivery = container.delivery_service().clone();
+    scheduler.register(
+        "scheduled-publishing",
+        Schedule::Every(chrono::Duration::seconds(10)),
+        move || {
+            let db = db.clone();
+            let actor_fetch = actor_fetch.clone();
+            let urls = urls.clone();
+            let delivery = delivery.clone();
+            async move {
+                scheduled_publishing::publish_due(
+                    &db,
+                    &actor_fetch,
+                    &urls,
+                    &delivery,
+                    chrono::Utc::now(),
+                )
+                .await?;
+                Ok(())
+            }
+        },
+    );
+
+    let db = container.database().clone();
+    let metrics = container.metrics().clone();
+    let usage = container.inbox_backpressure().usage().clone();
+    scheduler.register(
+        inbound_usage::SNAPSHOT_JOB,
+        Schedule::Every(chrono::Duration::minutes(1)),
+        move || {
+            let db = db.clone();
+            let metrics = metrics.clone();
+            let usage = usage.clone();
+            async move {
+                inbound_usage::persist(&usage, &db, &metrics, chrono::Utc::now()).await?;
+                Ok(())
+            }
+        },
+    );
+
+    let db = container.database().clone();
+    let collector = Arc::new(gc::GarbageCollector::new(Arc::new(SystemClock)));
+    let retention_days = container.config().activity_retention_days;
+    let parse_failure_retention_days = container.config().parse_failure_retention_days;
+    let trace_retention_secs = container.config().trace_retention_secs;
+    scheduler.register(
+        gc::GC_JOB,
+        Schedule::Every(chrono::Duration::seconds(
+            container.config().gc_interval_secs as i64,
+        )),
+        move || {
+            let db = db.clone();
+            let collector = collector.clone();
+            async move {
+                collector.run(&db, retention_days).await?;
+                collector
+                    .prune_parse_failures(&db, parse_failure_retention_days)
+                    .await?;
+                collector
+                    .prune_processing_traces(&db, trace_retention_secs)
+                    .await?;
+                Ok(())
+            }
+        },
+    );
+
+    scheduler
+}
+
+/// Resolves on a signal actix shuts down on: SIGTERM, or Ctrl-C
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    if let Ok(mut terminate) =
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+    {
+        tokio::select! {
+            _ = terminate.recv() => {}
+            _ = tokio::signal::ctrl_c() => {}
+        }
+        return;
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     // Initialize tracing
-    tracing_subscriber::fmt::init();
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| EnvFilter::new("feder8=debug,actix_web=info")),
+        )
+        .init();
+
+    let mut config = config::Config::default();
 
-    let config = config::Config::default();
+    match cli::parse_args(std::env::args().skip(1)) {
+        Ok(cli::Command::Serve(options)) => {
+            config.mock_database |= options.mock_db;
+        }
+        Ok(cli::Command::KeysAudit(options)) => {
+            return cli::run_keys_audit(&config, &options)
+                .await
+                .map_err(|e| std::io::Error::other(e.to_string()));
+        }
+        Err(message) => {
+            eprintln!("{message}\n\n{}", cli::USAGE);
+            std::process::exit(2);
+        }
+    }
 
     tracing::info!("Starting Fediverse server on port {}", config.port);
     tracing::info!("Server URL: {}", config.server_url);
     tracing::info!("Actor name: {}", config.actor_name);
+    if config.admin_token.is_none() {
+        tracing::warn!("ADMIN_TOKEN is not set; admin API is disabled");
+    }
 
-    // Initialize database (using mock for now)
-    let db: DatabaseRef = Arc::new(create_configured_mock_database());
-    tracing::info!("Database initialized (using mock)");
+    // A server that can't store anything is no use, so refuse to start
+    let db = match database::open_database(&config).await {
+        Ok(db) => db,
+        Err(e) => {
+            let database_url = config.redacted_database_url();
+            tracing::error!("Failed to open database {}: {}", database_url, e);
+            return Err(std::io::Error::other(format!(
+                "failed to open database {database_url}: {e}"
+            )));
+        }
+    };
+    if config.mock_database {
+        tracing::warn!("Using a mock database; nothing is kept across restarts");
+    } else {
+        tracing::info!("Database initialized at {}", config.redacted_database_url());
+    }
 
     // Initialize dependency injection container
     let container = Container::new(config.clone(), db);
     tracing::info!("Dependency injection container initialized");
 
+    let capabilities = container.capabilities();
+    tracing::info!(
+        "{} v{} (capabilities api v{})",
+        capabilities.software.name,
+        capabilities.software.version,
+        capabilities.api_version
+    );
+    tracing::info!(
+        "Enabled features: [{}], max note length: {}",
+        capabilities.enabled_features().join(", "),
+        capabilities.limits.max_note_length
+    );
+
+    if let Err(e) =
+        seen_activities::remember_recent(container.seen_activities(), container.database()).await
+    {
+        tracing::warn!("Could not load recent activity ids: {}", e);
+    }
+
+    if let Err(e) = inbound_usage::restore(
+        container.inbox_backpressure().usage(),
+        container.database(),
+        chrono::Utc::now(),
+    )
+    .await
+    {
+        tracing::warn!("Could not load inbound usage: {}", e);
+    }
+
+    let scheduler = Arc::new(build_scheduler(&container));
+    let shutdown = CancellationToken::new();
+    let scheduler_task = tokio::spawn(scheduler.clone().run(shutdown.clone()));
+
+    // Open streams would otherwise hold a graceful shutdown until it times out
+    let inbox_stream = container.inbox_stream().clone();
+    tokio::spawn(async move {
+        shutdown_signal().await;
+        inbox_stream.close();
+    });
+
     let container_clone = container.clone();
-    HttpServer::new(move || {
+    let server = HttpServer::new(move || {
         App::new()
+            .wrap(ServiceUnavailable::new(
+                container_clone.config().db_retry_after_secs,
+            ))
+            .wrap(Condition::new(
+                container_clone.config().metrics_enabled,
+                RequestMetrics::new(container_clone.metrics().clone()),
+            ))
+            .wrap(http::ApiRateLimit::new(
+                container_clone.api_rate_limiter().clone(),
+                container_clone.urls().base_path(),
+            ))
             .wrap(Logger::default())
+            .wrap(http::cors(&container_clone.config().allowed_origins))
+            .wrap(http::RequestId)
+            .app_data(http::json_config())
             .app_data(web::Data::new(container_clone.config().clone()))
             .app_data(web::Data::new(container_clone.database().clone()))
+            .app_data(web::Data::new(container_clone.capabilities().clone()))
+            .app_data(web::Data::new(container_clone.key_manager().clone()))
+            .app_data(web::Data::new(container_clone.urls().clone()))
+            .app_data(web::Data::from(container_clone.http_client().clone()))
+            .app_data(web::Data::from(container_clone.delivery_service().clone()))
+            .app_data(web::Data::from(container_clone.actor_fetch().clone()))
             .app_data(web::Data::new(container_clone.clone()))
+            .app_data(web::Data::from(scheduler.clone()))
+            .app_data(web::Data::from(container_clone.health().clone()))
+            .app_data(web::Data::from(container_clone.metrics().clone()))
+            .app_data(web::Data::from(container_clone.seen_activities().clone()))
+            .app_data(web::Data::from(
+                container_clone.inbox_backpressure().clone(),
+            ))
+            .app_data(web::Data::from(container_clone.log_dedup().clone()))
+            .app_data(web::Data::from(container_clone.event_bus().clone()))
+            .app_data(web::Data::from(container_clone.document_cache().clone()))
+            .app_data(web::Data::from(
+                container_clone.content_classifier().clone(),
+            ))
+            .app_data(web::Data::from(container_clone.inbox_stream().clone()))
+            // Discovery and probes stay at the root whatever the base path
             .service(handlers::webfinger::webfinger)
-            .service(handlers::actor::get_actor)
-            .service(handlers::inbox::inbox)
-            .service(handlers::outbox::get_outbox)
-            .service(handlers::outbox::post_outbox)
+            .service(handlers::host_meta::host_meta)
+            .service(handlers::host_meta::host_meta_json)
+            .service(handlers::health::healthz)
+            .service(handlers::health::readyz)
+            .service(handlers::metrics::metrics)
+            .service(handlers::cors::preflight)
+            .service(
+                web::scope(container_clone.urls().base_path())
+                    .service(handlers::capabilities::get_capabilities)
+                    .service(handlers::actor::get_actor)
+                    .service(handlers::inbox::get_inbox)
+                    .service(handlers::inbox::inbox)
+                    .service(handlers::outbox::get_outbox)
+                    .service(handlers::outbox::post_outbox)
+                    .service(handlers::collections::get_featured)
+                    .service(handlers::collections::get_tag_collection)
+                    .service(handlers::note::get_note)
+                    .service(handlers::note::get_statuses)
+                    .service(handlers::feed::get_atom_feed)
+                    .service(handlers::feed::get_rss_feed)
+                    .service(handlers::collections::get_replies)
+                    .service(handlers::collections::get_status_replies)
+                    .service(handlers::collections::get_likes)
+                    .service(handlers::collections::get_shares)
+                    .service(handlers::api::accounts::get_followers)
+                    .service(handlers::api::accounts::set_aliases)
+                    .service(handlers::api::instance::get_instance)
+                    .service(handlers::api::preferences::get_preferences)
+                    .service(handlers::api::preferences::update_preferences)
+                    .service(handlers::api::statuses::get_status)
+                    .service(handlers::api::statuses::get_status_context)
+                    .service(handlers::api::timelines::get_public_timeline)
+                    .service(handlers::api::search::search)
+                    .service(handlers::api::search::search_notes)
+                    .service(handlers::api::search::search_v1)
+                    .service(handlers::api::stream::stream)
+                    .service(handlers::admin::actors::list_actors)
+                    .service(handlers::admin::actors::create_actor)
+                    .service(handlers::admin::actors::delete_actor)
+                    .service(handlers::admin::follows::list_pending_follows)
+                    .service(handlers::admin::follows::accept_follow)
+                    .service(handlers::admin::follows::reject_follow)
+                    .service(handlers::admin::relays::list_relays)
+                    .service(handlers::admin::relays::subscribe_relay)
+                    .service(handlers::admin::relays::unsubscribe_relay)
+                    .service(handlers::admin::hosts::list_hosts)
+                    .service(handlers::admin::jobs::list_jobs)
+                    .service(handlers::admin::parse_failures::list_parse_failures)
+                    .service(handlers::admin::parse_failures::download_parse_failure)
+                    .service(handlers::admin::traces::get_trace)
+                    .service(handlers::admin::stats::get_stats)
+                    .service(handlers::admin::tokens::issue_token)
+                    .service(handlers::admin::database::swap_database),
+            )
     })
     .bind(("127.0.0.1", config.port))?
     .run()
-    .await
+    .await;
+
+    // Let background jobs finish their current run before exiting
+    shutdown.cancel();
+    if let Err(e) = scheduler_task.await {
+        tracing::error!("Scheduler task failed: {}", e);
+    }
+
+    server
 }
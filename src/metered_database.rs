@@ -0,0 +1,1400 @@
+use crate::database::{
+    Database, DatabaseError, DatabaseRef, DbActivity, DbActor, DbActorSummary, DbBlock, DbDelivery,
+    DbFollowRelation, DbHostBacklog, DbInboundUsage, DbInstanceStats, DbLike, DbNote, DbNoteLabel,
+    DbOldestDelivery, DbParseFailure, DbPendingAccept, DbPreferences, DbProcessingTrace, DbRelay,
+    DbRemoteActor, DbToken, DeliveryPriority,
+};
+use crate::health::DatabaseHealth;
+use crate::metrics::Metrics;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+/// `Database` decorator that records per-method latency into the metrics
+/// registry, logs calls slower than a threshold and reports call outcomes to
+/// the database health tracker
+#[allow(dead_code)]
+pub struct MeteredDatabase {
+    inner: DatabaseRef,
+    metrics: Option<Arc<Metrics>>,
+    health: Option<Arc<DatabaseHealth>>,
+    slow_query_threshold: Duration,
+}
+
+#[allow(dead_code)]
+impl MeteredDatabase {
+    pub fn new(inner: DatabaseRef, metrics: Arc<Metrics>, slow_query_threshold: Duration) -> Self {
+        Self {
+            inner,
+            metrics: Some(metrics),
+            health: None,
+            slow_query_threshold,
+        }
+    }
+
+    /// Wrap without recording latency histograms, e.g. when metrics are disabled
+    pub fn unmetered(inner: DatabaseRef, slow_query_threshold: Duration) -> Self {
+        Self {
+            inner,
+            metrics: None,
+            health: None,
+            slow_query_threshold,
+        }
+    }
+
+    /// Report whether each call failed because the database was unavailable
+    pub fn with_health(mut self, health: Arc<DatabaseHealth>) -> Self {
+        self.health = Some(health);
+        self
+    }
+
+    /// Time a delegated call. `args` describes the key arguments and is only
+    /// evaluated when the call is slow enough to be logged.
+    async fn timed<T>(
+        &self,
+        method: &'static str,
+        args: impl FnOnce() -> String,
+        call: impl Future<Output = Result<T, DatabaseError>>,
+    ) -> Result<T, DatabaseError> {
+        let start = Instant::now();
+        let result = call.await;
+        let elapsed = start.elapsed();
+
+        if let Some(metrics) = &self.metrics {
+            metrics.observe_db_query(method, elapsed.as_secs_f64());
+        }
+        if let Some(health) = &self.health {
+            health.record(matches!(&result, Err(e) if e.is_unavailable()));
+        }
+        if elapsed >= self.slow_query_threshold {
+            warn!(
+                method,
+                elapsed_ms = elapsed.as_millis() as u64,
+                "Slow database query: {} {}",
+                method,
+                args()
+            );
+        }
+
+        result
+    }
+}
+
+#[async_trait]
+impl Database for MeteredDatabase {
+    async fn create_actor(&self, actor: &DbActor) -> Result<(), DatabaseError> {
+        self.timed(
+            "create_actor",
+            || format!("id={}", actor.id),
+            self.inner.create_actor(actor),
+        )
+        .await
+    }
+
+    async fn get_actor_by_id(&self, id: &str) -> Result<Option<DbActor>, DatabaseError> {
+        self.timed(
+            "get_actor_by_id",
+            || format!("id={id}"),
+            self.inner.get_actor_by_id(id),
+        )
+        .await
+    }
+
+    async fn get_actor_by_username(
+        &self,
+        username: &str,
+    ) -> Result<Option<DbActor>, DatabaseError> {
+        self.timed(
+            "get_actor_by_username",
+            || format!("username={username}"),
+            self.inner.get_actor_by_username(username),
+        )
+        .await
+    }
+
+    async fn update_actor(&self, actor: &DbActor) -> Result<(), DatabaseError> {
+        self.timed(
+            "update_actor",
+            || format!("id={}", actor.id),
+            self.inner.update_actor(actor),
+        )
+        .await
+    }
+
+    async fn touch_actor(&self, id: &str) -> Result<(), DatabaseError> {
+        self.timed(
+            "touch_actor",
+            || format!("id={id}"),
+            self.inner.touch_actor(id),
+        )
+        .await
+    }
+
+    async fn update_actor_migration(
+        &self,
+        id: &str,
+        moved_to: Option<String>,
+        also_known_as: &[String],
+    ) -> Result<(), DatabaseError> {
+        self.timed(
+            "update_actor_migration",
+            || format!("id={id}"),
+            self.inner
+                .update_actor_migration(id, moved_to, also_known_as),
+        )
+        .await
+    }
+
+    async fn delete_actor(&self, id: &str) -> Result<(), DatabaseError> {
+        self.timed(
+            "delete_actor",
+            || format!("id={id}"),
+            self.inner.delete_actor(id),
+        )
+        .await
+    }
+
+    async fn list_actors(&self, limit: u32, offset: u32) -> Result<Vec<DbActor>, DatabaseError> {
+        self.timed(
+            "list_actors",
+            || format!("limit={limit}"),
+            self.inner.list_actors(limit, offset),
+        )
+        .await
+    }
+
+    async fn search_actors(
+        &self,
+        query: &str,
+        limit: u32,
+    ) -> Result<Vec<DbActorSummary>, DatabaseError> {
+        self.timed(
+            "search_actors",
+            || format!("query={query} limit={limit}"),
+            self.inner.search_actors(query, limit),
+        )
+        .await
+    }
+
+    async fn create_activity(&self, activity: &DbActivity) -> Result<(), DatabaseError> {
+        self.timed(
+            "create_activity",
+            || format!("id={}", activity.id),
+            self.inner.create_activity(activity),
+        )
+        .await
+    }
+
+    async fn get_activity_by_id(&self, id: &str) -> Result<Option<DbActivity>, DatabaseError> {
+        self.timed(
+            "get_activity_by_id",
+            || format!("id={id}"),
+            self.inner.get_activity_by_id(id),
+        )
+        .await
+    }
+
+    async fn get_recent_activity_ids(&self, limit: u32) -> Result<Vec<String>, DatabaseError> {
+        self.timed(
+            "get_recent_activity_ids",
+            || format!("limit={limit}"),
+            self.inner.get_recent_activity_ids(limit),
+        )
+        .await
+    }
+
+    async fn get_activities_by_actor(
+        &self,
+        actor_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.timed(
+            "get_activities_by_actor",
+            || format!("actor_id={actor_id} limit={limit}"),
+            self.inner.get_activities_by_actor(actor_id, limit, offset),
+        )
+        .await
+    }
+
+    async fn get_public_activities_by_actor(
+        &self,
+        actor_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.timed(
+            "get_public_activities_by_actor",
+            || format!("actor_id={actor_id} limit={limit}"),
+            self.inner
+                .get_public_activities_by_actor(actor_id, limit, offset),
+        )
+        .await
+    }
+
+    async fn get_activities_by_actor_before(
+        &self,
+        actor_id: &str,
+        before_published: DateTime<Utc>,
+        before_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.timed(
+            "get_activities_by_actor_before",
+            || format!("actor_id={actor_id} before_id={before_id} limit={limit}"),
+            self.inner
+                .get_activities_by_actor_before(actor_id, before_published, before_id, limit),
+        )
+        .await
+    }
+
+    async fn get_activities_by_actor_after(
+        &self,
+        actor_id: &str,
+        after_published: DateTime<Utc>,
+        after_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.timed(
+            "get_activities_by_actor_after",
+            || format!("actor_id={actor_id} after_id={after_id} limit={limit}"),
+            self.inner
+                .get_activities_by_actor_after(actor_id, after_published, after_id, limit),
+        )
+        .await
+    }
+
+    async fn get_public_activities_by_actor_before(
+        &self,
+        actor_id: &str,
+        before_published: DateTime<Utc>,
+        before_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.timed(
+            "get_public_activities_by_actor_before",
+            || format!("actor_id={actor_id} before_id={before_id} limit={limit}"),
+            self.inner.get_public_activities_by_actor_before(
+                actor_id,
+                before_published,
+                before_id,
+                limit,
+            ),
+        )
+        .await
+    }
+
+    async fn get_public_activities_by_actor_after(
+        &self,
+        actor_id: &str,
+        after_published: DateTime<Utc>,
+        after_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.timed(
+            "get_public_activities_by_actor_after",
+            || format!("actor_id={actor_id} after_id={after_id} limit={limit}"),
+            self.inner.get_public_activities_by_actor_after(
+                actor_id,
+                after_published,
+                after_id,
+                limit,
+            ),
+        )
+        .await
+    }
+
+    async fn get_activities_by_actor_and_types(
+        &self,
+        actor_id: &str,
+        types: &[String],
+        public_only: bool,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.timed(
+            "get_activities_by_actor_and_types",
+            || format!("actor_id={actor_id} types={types:?} limit={limit}"),
+            self.inner.get_activities_by_actor_and_types(
+                actor_id,
+                types,
+                public_only,
+                limit,
+                offset,
+            ),
+        )
+        .await
+    }
+
+    async fn count_activities_by_actor_and_types(
+        &self,
+        actor_id: &str,
+        types: &[String],
+        public_only: bool,
+    ) -> Result<u32, DatabaseError> {
+        self.timed(
+            "count_activities_by_actor_and_types",
+            || format!("actor_id={actor_id} types={types:?}"),
+            self.inner
+                .count_activities_by_actor_and_types(actor_id, types, public_only),
+        )
+        .await
+    }
+
+    async fn get_inbox_activities(
+        &self,
+        actor_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.timed(
+            "get_inbox_activities",
+            || format!("actor_id={actor_id} limit={limit}"),
+            self.inner.get_inbox_activities(actor_id, limit, offset),
+        )
+        .await
+    }
+
+    async fn count_inbox_activities(&self, actor_id: &str) -> Result<u32, DatabaseError> {
+        self.timed(
+            "count_inbox_activities",
+            || format!("actor_id={actor_id}"),
+            self.inner.count_inbox_activities(actor_id),
+        )
+        .await
+    }
+
+    async fn get_inbox_activities_before(
+        &self,
+        actor_id: &str,
+        before_published: DateTime<Utc>,
+        before_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.timed(
+            "get_inbox_activities_before",
+            || format!("actor_id={actor_id} before_id={before_id} limit={limit}"),
+            self.inner
+                .get_inbox_activities_before(actor_id, before_published, before_id, limit),
+        )
+        .await
+    }
+
+    async fn get_inbox_activities_after(
+        &self,
+        actor_id: &str,
+        after_published: DateTime<Utc>,
+        after_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.timed(
+            "get_inbox_activities_after",
+            || format!("actor_id={actor_id} after_id={after_id} limit={limit}"),
+            self.inner
+                .get_inbox_activities_after(actor_id, after_published, after_id, limit),
+        )
+        .await
+    }
+
+    async fn update_activity_object(&self, id: &str, object: &Value) -> Result<(), DatabaseError> {
+        self.timed(
+            "update_activity_object",
+            || format!("id={id}"),
+            self.inner.update_activity_object(id, object),
+        )
+        .await
+    }
+
+    async fn get_shares(
+        &self,
+        object_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.timed(
+            "get_shares",
+            || format!("object_id={object_id} limit={limit} offset={offset}"),
+            self.inner.get_shares(object_id, limit, offset),
+        )
+        .await
+    }
+
+    async fn count_shares(&self, object_id: &str) -> Result<u32, DatabaseError> {
+        self.timed(
+            "count_shares",
+            || format!("object_id={object_id}"),
+            self.inner.count_shares(object_id),
+        )
+        .await
+    }
+
+    async fn get_due_scheduled_activities(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.timed(
+            "get_due_scheduled_activities",
+            || format!("now={now}"),
+            self.inner.get_due_scheduled_activities(now),
+        )
+        .await
+    }
+
+    async fn publish_scheduled_activity(&self, id: &str) -> Result<(), DatabaseError> {
+        self.timed(
+            "publish_scheduled_activity",
+            || format!("id={id}"),
+            self.inner.publish_scheduled_activity(id),
+        )
+        .await
+    }
+
+    async fn delete_activities_older_than(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64, DatabaseError> {
+        self.timed(
+            "delete_activities_older_than",
+            || format!("cutoff={cutoff}"),
+            self.inner.delete_activities_older_than(cutoff),
+        )
+        .await
+    }
+
+    async fn create_note(&self, note: &DbNote) -> Result<(), DatabaseError> {
+        self.timed(
+            "create_note",
+            || format!("id={}", note.id),
+            self.inner.create_note(note),
+        )
+        .await
+    }
+
+    async fn get_note_by_id(&self, id: &str) -> Result<Option<DbNote>, DatabaseError> {
+        self.timed(
+            "get_note_by_id",
+            || format!("id={id}"),
+            self.inner.get_note_by_id(id),
+        )
+        .await
+    }
+
+    async fn get_notes_by_ids(&self, ids: &[String]) -> Result<Vec<DbNote>, DatabaseError> {
+        self.timed(
+            "get_notes_by_ids",
+            || format!("ids={}", ids.len()),
+            self.inner.get_notes_by_ids(ids),
+        )
+        .await
+    }
+
+    async fn get_notes_by_actor(
+        &self,
+        actor_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        self.timed(
+            "get_notes_by_actor",
+            || format!("actor_id={actor_id} limit={limit}"),
+            self.inner.get_notes_by_actor(actor_id, limit, offset),
+        )
+        .await
+    }
+
+    async fn get_notes_by_actor_before(
+        &self,
+        actor_id: &str,
+        before_published: DateTime<Utc>,
+        before_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        self.timed(
+            "get_notes_by_actor_before",
+            || format!("actor_id={actor_id} before_id={before_id} limit={limit}"),
+            self.inner
+                .get_notes_by_actor_before(actor_id, before_published, before_id, limit),
+        )
+        .await
+    }
+
+    async fn get_public_notes_by_actor(
+        &self,
+        actor_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        self.timed(
+            "get_public_notes_by_actor",
+            || format!("actor_id={actor_id} limit={limit}"),
+            self.inner.get_public_notes_by_actor(actor_id, limit),
+        )
+        .await
+    }
+
+    async fn count_notes_by_actor(
+        &self,
+        actor_id: &str,
+        include_private: bool,
+        include_local: bool,
+    ) -> Result<u32, DatabaseError> {
+        self.timed(
+            "count_notes_by_actor",
+            || format!("actor_id={actor_id}"),
+            self.inner
+                .count_notes_by_actor(actor_id, include_private, include_local),
+        )
+        .await
+    }
+
+    async fn get_notes_by_language(
+        &self,
+        actor_id: &str,
+        language: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        self.timed(
+            "get_notes_by_language",
+            || format!("actor_id={actor_id} language={language} limit={limit}"),
+            self.inner
+                .get_notes_by_language(actor_id, language, limit, offset),
+        )
+        .await
+    }
+
+    async fn get_public_notes(
+        &self,
+        limit: u32,
+        before_id: Option<String>,
+        local_only: bool,
+        hide_sensitive: bool,
+        with_local_visibility: bool,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        self.timed(
+            "get_public_notes",
+            || {
+                format!(
+                    "limit={limit} before_id={before_id:?} local_only={local_only} hide_sensitive={hide_sensitive} with_local_visibility={with_local_visibility}"
+                )
+            },
+            self.inner.get_public_notes(
+                limit,
+                before_id.clone(),
+                local_only,
+                hide_sensitive,
+                with_local_visibility,
+            ),
+        )
+        .await
+    }
+
+    async fn search_notes(
+        &self,
+        query: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        self.timed(
+            "search_notes",
+            || format!("query={query} limit={limit} offset={offset}"),
+            self.inner.search_notes(query, limit, offset),
+        )
+        .await
+    }
+
+    async fn get_pinned_notes(&self, actor_id: &str) -> Result<Vec<DbNote>, DatabaseError> {
+        self.timed(
+            "get_pinned_notes",
+            || format!("actor_id={actor_id}"),
+            self.inner.get_pinned_notes(actor_id),
+        )
+        .await
+    }
+
+    async fn set_note_pinned(&self, id: &str, pinned: bool) -> Result<(), DatabaseError> {
+        self.timed(
+            "set_note_pinned",
+            || format!("id={id} pinned={pinned}"),
+            self.inner.set_note_pinned(id, pinned),
+        )
+        .await
+    }
+
+    async fn search_notes_by_hashtag(
+        &self,
+        actor_id: &str,
+        hashtag: &str,
+        hide_sensitive: bool,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        self.timed(
+            "search_notes_by_hashtag",
+            || format!("actor_id={actor_id} hashtag={hashtag} limit={limit}"),
+            self.inner
+                .search_notes_by_hashtag(actor_id, hashtag, hide_sensitive, limit, offset),
+        )
+        .await
+    }
+
+    async fn count_notes_by_hashtag(
+        &self,
+        actor_id: &str,
+        hashtag: &str,
+        hide_sensitive: bool,
+    ) -> Result<u32, DatabaseError> {
+        self.timed(
+            "count_notes_by_hashtag",
+            || format!("actor_id={actor_id} hashtag={hashtag}"),
+            self.inner
+                .count_notes_by_hashtag(actor_id, hashtag, hide_sensitive),
+        )
+        .await
+    }
+
+    async fn get_replies(
+        &self,
+        note_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        self.timed(
+            "get_replies",
+            || format!("note_id={note_id} limit={limit} offset={offset}"),
+            self.inner.get_replies(note_id, limit, offset),
+        )
+        .await
+    }
+
+    async fn get_thread(&self, note_id: &str, depth: u32) -> Result<Vec<DbNote>, DatabaseError> {
+        self.timed(
+            "get_thread",
+            || format!("note_id={note_id} depth={depth}"),
+            self.inner.get_thread(note_id, depth),
+        )
+        .await
+    }
+
+    async fn count_replies(&self, note_id: &str) -> Result<u32, DatabaseError> {
+        self.timed(
+            "count_replies",
+            || format!("note_id={note_id}"),
+            self.inner.count_replies(note_id),
+        )
+        .await
+    }
+
+    async fn delete_note(&self, id: &str) -> Result<(), DatabaseError> {
+        self.timed(
+            "delete_note",
+            || format!("id={id}"),
+            self.inner.delete_note(id),
+        )
+        .await
+    }
+
+    async fn create_follow(&self, follow: &DbFollowRelation) -> Result<(), DatabaseError> {
+        self.timed(
+            "create_follow",
+            || format!("id={}", follow.id),
+            self.inner.create_follow(follow),
+        )
+        .await
+    }
+
+    async fn get_follow_by_id(&self, id: &str) -> Result<Option<DbFollowRelation>, DatabaseError> {
+        self.timed(
+            "get_follow_by_id",
+            || format!("id={id}"),
+            self.inner.get_follow_by_id(id),
+        )
+        .await
+    }
+
+    async fn get_follow_by_actors(
+        &self,
+        follower_id: &str,
+        following_id: &str,
+    ) -> Result<Option<DbFollowRelation>, DatabaseError> {
+        self.timed(
+            "get_follow_by_actors",
+            || format!("follower_id={follower_id} following_id={following_id}"),
+            self.inner.get_follow_by_actors(follower_id, following_id),
+        )
+        .await
+    }
+
+    async fn is_following_host(&self, host: &str) -> Result<bool, DatabaseError> {
+        self.timed(
+            "is_following_host",
+            || format!("host={host}"),
+            self.inner.is_following_host(host),
+        )
+        .await
+    }
+
+    async fn get_followers(
+        &self,
+        actor_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbFollowRelation>, DatabaseError> {
+        self.timed(
+            "get_followers",
+            || format!("actor_id={actor_id} limit={limit}"),
+            self.inner.get_followers(actor_id, limit, offset),
+        )
+        .await
+    }
+
+    async fn get_following(
+        &self,
+        actor_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbFollowRelation>, DatabaseError> {
+        self.timed(
+            "get_following",
+            || format!("actor_id={actor_id} limit={limit}"),
+            self.inner.get_following(actor_id, limit, offset),
+        )
+        .await
+    }
+
+    async fn update_follow_status(
+        &self,
+        follow_id: &str,
+        status: &str,
+    ) -> Result<(), DatabaseError> {
+        self.timed(
+            "update_follow_status",
+            || format!("follow_id={follow_id}"),
+            self.inner.update_follow_status(follow_id, status),
+        )
+        .await
+    }
+
+    async fn delete_follow(&self, id: &str) -> Result<(), DatabaseError> {
+        self.timed(
+            "delete_follow",
+            || format!("id={id}"),
+            self.inner.delete_follow(id),
+        )
+        .await
+    }
+
+    async fn get_pending_follows(
+        &self,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbFollowRelation>, DatabaseError> {
+        self.timed(
+            "get_pending_follows",
+            || format!("limit={limit}"),
+            self.inner.get_pending_follows(limit, offset),
+        )
+        .await
+    }
+
+    async fn get_followers_with_profiles(
+        &self,
+        actor_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbActorSummary>, DatabaseError> {
+        self.timed(
+            "get_followers_with_profiles",
+            || format!("actor_id={actor_id} limit={limit}"),
+            self.inner
+                .get_followers_with_profiles(actor_id, limit, offset),
+        )
+        .await
+    }
+
+    async fn upsert_remote_actor(&self, actor: &DbRemoteActor) -> Result<(), DatabaseError> {
+        self.timed(
+            "upsert_remote_actor",
+            || format!("id={}", actor.id),
+            self.inner.upsert_remote_actor(actor),
+        )
+        .await
+    }
+
+    async fn get_remote_actor(&self, id: &str) -> Result<Option<DbRemoteActor>, DatabaseError> {
+        self.timed(
+            "get_remote_actor",
+            || format!("id={id}"),
+            self.inner.get_remote_actor(id),
+        )
+        .await
+    }
+
+    async fn create_parse_failure(&self, failure: &DbParseFailure) -> Result<(), DatabaseError> {
+        self.timed(
+            "create_parse_failure",
+            || format!("id={}", failure.id),
+            self.inner.create_parse_failure(failure),
+        )
+        .await
+    }
+
+    async fn get_parse_failures(
+        &self,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbParseFailure>, DatabaseError> {
+        self.timed(
+            "get_parse_failures",
+            || format!("limit={limit} offset={offset}"),
+            self.inner.get_parse_failures(limit, offset),
+        )
+        .await
+    }
+
+    async fn get_parse_failure(&self, id: &str) -> Result<Option<DbParseFailure>, DatabaseError> {
+        self.timed(
+            "get_parse_failure",
+            || format!("id={id}"),
+            self.inner.get_parse_failure(id),
+        )
+        .await
+    }
+
+    async fn delete_parse_failures_older_than(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64, DatabaseError> {
+        self.timed(
+            "delete_parse_failures_older_than",
+            || format!("cutoff={cutoff}"),
+            self.inner.delete_parse_failures_older_than(cutoff),
+        )
+        .await
+    }
+
+    async fn save_processing_trace(&self, trace: &DbProcessingTrace) -> Result<(), DatabaseError> {
+        self.timed(
+            "save_processing_trace",
+            || format!("activity_id={}", trace.activity_id),
+            self.inner.save_processing_trace(trace),
+        )
+        .await
+    }
+
+    async fn get_processing_trace(
+        &self,
+        activity_id: &str,
+    ) -> Result<Option<DbProcessingTrace>, DatabaseError> {
+        self.timed(
+            "get_processing_trace",
+            || format!("activity_id={activity_id}"),
+            self.inner.get_processing_trace(activity_id),
+        )
+        .await
+    }
+
+    async fn delete_processing_traces_older_than(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64, DatabaseError> {
+        self.timed(
+            "delete_processing_traces_older_than",
+            || format!("cutoff={cutoff}"),
+            self.inner.delete_processing_traces_older_than(cutoff),
+        )
+        .await
+    }
+
+    async fn create_block(&self, block: &DbBlock) -> Result<(), DatabaseError> {
+        self.timed(
+            "create_block",
+            || format!("id={}", block.id),
+            self.inner.create_block(block),
+        )
+        .await
+    }
+
+    async fn delete_block(&self, blocker_id: &str, blocked_id: &str) -> Result<(), DatabaseError> {
+        self.timed(
+            "delete_block",
+            || format!("blocker_id={blocker_id} blocked_id={blocked_id}"),
+            self.inner.delete_block(blocker_id, blocked_id),
+        )
+        .await
+    }
+
+    async fn is_blocked(&self, blocker_id: &str, blocked_id: &str) -> Result<bool, DatabaseError> {
+        self.timed(
+            "is_blocked",
+            || format!("blocker_id={blocker_id} blocked_id={blocked_id}"),
+            self.inner.is_blocked(blocker_id, blocked_id),
+        )
+        .await
+    }
+
+    async fn list_blocks(
+        &self,
+        blocker_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbBlock>, DatabaseError> {
+        self.timed(
+            "list_blocks",
+            || format!("blocker_id={blocker_id} limit={limit} offset={offset}"),
+            self.inner.list_blocks(blocker_id, limit, offset),
+        )
+        .await
+    }
+
+    async fn create_relay(&self, relay: &DbRelay) -> Result<(), DatabaseError> {
+        self.timed(
+            "create_relay",
+            || format!("id={}", relay.id),
+            self.inner.create_relay(relay),
+        )
+        .await
+    }
+
+    async fn get_relay(&self, id: &str) -> Result<Option<DbRelay>, DatabaseError> {
+        self.timed("get_relay", || format!("id={id}"), self.inner.get_relay(id))
+            .await
+    }
+
+    async fn list_relays(&self) -> Result<Vec<DbRelay>, DatabaseError> {
+        self.timed("list_relays", String::new, self.inner.list_relays())
+            .await
+    }
+
+    async fn update_relay_status(&self, id: &str, status: &str) -> Result<(), DatabaseError> {
+        self.timed(
+            "update_relay_status",
+            || format!("id={id} status={status}"),
+            self.inner.update_relay_status(id, status),
+        )
+        .await
+    }
+
+    async fn delete_relay(&self, id: &str) -> Result<(), DatabaseError> {
+        self.timed(
+            "delete_relay",
+            || format!("id={id}"),
+            self.inner.delete_relay(id),
+        )
+        .await
+    }
+
+    async fn create_like(&self, like: &DbLike) -> Result<(), DatabaseError> {
+        self.timed(
+            "create_like",
+            || format!("id={}", like.id),
+            self.inner.create_like(like),
+        )
+        .await
+    }
+
+    async fn get_like(
+        &self,
+        actor_id: &str,
+        object_id: &str,
+    ) -> Result<Option<DbLike>, DatabaseError> {
+        self.timed(
+            "get_like",
+            || format!("actor_id={actor_id} object_id={object_id}"),
+            self.inner.get_like(actor_id, object_id),
+        )
+        .await
+    }
+
+    async fn delete_like(&self, actor_id: &str, object_id: &str) -> Result<(), DatabaseError> {
+        self.timed(
+            "delete_like",
+            || format!("actor_id={actor_id} object_id={object_id}"),
+            self.inner.delete_like(actor_id, object_id),
+        )
+        .await
+    }
+
+    async fn get_likes(
+        &self,
+        object_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbLike>, DatabaseError> {
+        self.timed(
+            "get_likes",
+            || format!("object_id={object_id} limit={limit} offset={offset}"),
+            self.inner.get_likes(object_id, limit, offset),
+        )
+        .await
+    }
+
+    async fn count_likes(&self, object_id: &str) -> Result<u32, DatabaseError> {
+        self.timed(
+            "count_likes",
+            || format!("object_id={object_id}"),
+            self.inner.count_likes(object_id),
+        )
+        .await
+    }
+
+    async fn create_pending_accept(&self, pending: &DbPendingAccept) -> Result<(), DatabaseError> {
+        self.timed(
+            "create_pending_accept",
+            || format!("id={}", pending.id),
+            self.inner.create_pending_accept(pending),
+        )
+        .await
+    }
+
+    async fn get_pending_accepts(
+        &self,
+        follower_id: &str,
+        following_id: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<DbPendingAccept>, DatabaseError> {
+        self.timed(
+            "get_pending_accepts",
+            || format!("follower_id={follower_id} following_id={following_id}"),
+            self.inner
+                .get_pending_accepts(follower_id, following_id, now),
+        )
+        .await
+    }
+
+    async fn delete_pending_accept(&self, id: &str) -> Result<(), DatabaseError> {
+        self.timed(
+            "delete_pending_accept",
+            || format!("id={id}"),
+            self.inner.delete_pending_accept(id),
+        )
+        .await
+    }
+
+    async fn delete_expired_pending_accepts(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<u64, DatabaseError> {
+        self.timed(
+            "delete_expired_pending_accepts",
+            String::new,
+            self.inner.delete_expired_pending_accepts(now),
+        )
+        .await
+    }
+
+    async fn enqueue_delivery(&self, delivery: &DbDelivery) -> Result<(), DatabaseError> {
+        self.timed(
+            "enqueue_delivery",
+            || format!("id={}", delivery.id),
+            self.inner.enqueue_delivery(delivery),
+        )
+        .await
+    }
+
+    async fn get_due_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        priority: DeliveryPriority,
+        limit: u32,
+    ) -> Result<Vec<DbDelivery>, DatabaseError> {
+        self.timed(
+            "get_due_deliveries",
+            || format!("priority={} limit={limit}", priority.as_str()),
+            self.inner.get_due_deliveries(now, priority, limit),
+        )
+        .await
+    }
+
+    async fn delete_delivery(&self, id: &str) -> Result<(), DatabaseError> {
+        self.timed(
+            "delete_delivery",
+            || format!("id={id}"),
+            self.inner.delete_delivery(id),
+        )
+        .await
+    }
+
+    async fn reschedule_delivery(
+        &self,
+        id: &str,
+        attempts: u32,
+        next_attempt_at: DateTime<Utc>,
+        last_error: &str,
+    ) -> Result<(), DatabaseError> {
+        self.timed(
+            "reschedule_delivery",
+            || format!("id={id}"),
+            self.inner
+                .reschedule_delivery(id, attempts, next_attempt_at, last_error),
+        )
+        .await
+    }
+
+    async fn count_pending_deliveries(&self) -> Result<u32, DatabaseError> {
+        self.timed(
+            "count_pending_deliveries",
+            String::new,
+            self.inner.count_pending_deliveries(),
+        )
+        .await
+    }
+
+    async fn count_pending_by_priority(
+        &self,
+    ) -> Result<Vec<(DeliveryPriority, u32)>, DatabaseError> {
+        self.timed(
+            "count_pending_by_priority",
+            String::new,
+            self.inner.count_pending_by_priority(),
+        )
+        .await
+    }
+
+    async fn oldest_pending_age(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Option<DbOldestDelivery>, DatabaseError> {
+        self.timed(
+            "oldest_pending_age",
+            String::new,
+            self.inner.oldest_pending_age(now),
+        )
+        .await
+    }
+
+    async fn pending_by_host(&self, limit: u32) -> Result<Vec<DbHostBacklog>, DatabaseError> {
+        self.timed(
+            "pending_by_host",
+            || format!("limit={limit}"),
+            self.inner.pending_by_host(limit),
+        )
+        .await
+    }
+
+    async fn add_note_labels(
+        &self,
+        note_id: &str,
+        labels: &[DbNoteLabel],
+    ) -> Result<(), DatabaseError> {
+        self.timed(
+            "add_note_labels",
+            || format!("note_id={note_id} labels={}", labels.len()),
+            self.inner.add_note_labels(note_id, labels),
+        )
+        .await
+    }
+
+    async fn get_note_labels(&self, note_id: &str) -> Result<Vec<DbNoteLabel>, DatabaseError> {
+        self.timed(
+            "get_note_labels",
+            || format!("note_id={note_id}"),
+            self.inner.get_note_labels(note_id),
+        )
+        .await
+    }
+
+    async fn replace_inbound_usage(&self, usage: &[DbInboundUsage]) -> Result<(), DatabaseError> {
+        self.timed(
+            "replace_inbound_usage",
+            || format!("rows={}", usage.len()),
+            self.inner.replace_inbound_usage(usage),
+        )
+        .await
+    }
+
+    async fn get_inbound_usage(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<DbInboundUsage>, DatabaseError> {
+        self.timed(
+            "get_inbound_usage",
+            || format!("since={since}"),
+            self.inner.get_inbound_usage(since),
+        )
+        .await
+    }
+
+    async fn create_token(&self, token: &DbToken) -> Result<(), DatabaseError> {
+        self.timed(
+            "create_token",
+            || format!("id={}", token.id),
+            self.inner.create_token(token),
+        )
+        .await
+    }
+
+    async fn validate_token(&self, token: &str) -> Result<Option<DbToken>, DatabaseError> {
+        self.timed(
+            "validate_token",
+            String::new,
+            self.inner.validate_token(token),
+        )
+        .await
+    }
+
+    async fn get_preferences(&self, actor_id: &str) -> Result<DbPreferences, DatabaseError> {
+        self.timed(
+            "get_preferences",
+            || format!("actor_id={actor_id}"),
+            self.inner.get_preferences(actor_id),
+        )
+        .await
+    }
+
+    async fn set_preferences(
+        &self,
+        actor_id: &str,
+        preferences: &DbPreferences,
+    ) -> Result<(), DatabaseError> {
+        self.timed(
+            "set_preferences",
+            || format!("actor_id={actor_id}"),
+            self.inner.set_preferences(actor_id, preferences),
+        )
+        .await
+    }
+
+    async fn get_actor_outbox_count(&self, actor_id: &str) -> Result<u32, DatabaseError> {
+        self.timed(
+            "get_actor_outbox_count",
+            || format!("actor_id={actor_id}"),
+            self.inner.get_actor_outbox_count(actor_id),
+        )
+        .await
+    }
+
+    async fn get_actor_public_outbox_count(&self, actor_id: &str) -> Result<u32, DatabaseError> {
+        self.timed(
+            "get_actor_public_outbox_count",
+            || format!("actor_id={actor_id}"),
+            self.inner.get_actor_public_outbox_count(actor_id),
+        )
+        .await
+    }
+
+    async fn get_actor_inbox_count(&self, actor_id: &str) -> Result<u32, DatabaseError> {
+        self.timed(
+            "get_actor_inbox_count",
+            || format!("actor_id={actor_id}"),
+            self.inner.get_actor_inbox_count(actor_id),
+        )
+        .await
+    }
+
+    async fn get_actor_followers_count(&self, actor_id: &str) -> Result<u32, DatabaseError> {
+        self.timed(
+            "get_actor_followers_count",
+            || format!("actor_id={actor_id}"),
+            self.inner.get_actor_followers_count(actor_id),
+        )
+        .await
+    }
+
+    async fn get_actor_following_count(&self, actor_id: &str) -> Result<u32, DatabaseError> {
+        self.timed(
+            "get_actor_following_count",
+            || format!("actor_id={actor_id}"),
+            self.inner.get_actor_following_count(actor_id),
+        )
+        .await
+    }
+
+    async fn get_instance_stats(&self) -> Result<DbInstanceStats, DatabaseError> {
+        self.timed(
+            "get_instance_stats",
+            String::new,
+            self.inner.get_instance_stats(),
+        )
+        .await
+    }
+
+    async fn ping(&self) -> Result<(), DatabaseError> {
+        self.timed("ping", String::new, self.inner.ping()).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::MockDatabase;
+    use mockall::predicate::*;
+    use tracing_test::traced_test;
+
+    fn sample_count(metrics: &Metrics, method: &str) -> u64 {
+        metrics
+            .registry()
+            .gather()
+            .iter()
+            .filter(|family| family.name() == "feder8_db_query_duration_seconds")
+            .flat_map(|family| family.get_metric())
+            .filter(|metric| {
+                metric
+                    .get_label()
+                    .iter()
+                    .any(|label| label.name() == "method" && label.value() == method)
+            })
+            .map(|metric| metric.get_histogram().get_sample_count())
+            .sum()
+    }
+
+    fn metered(mock: MockDatabase, threshold: Duration) -> (MeteredDatabase, Arc<Metrics>) {
+        let metrics = Arc::new(Metrics::new());
+        let db = MeteredDatabase::new(Arc::new(mock), metrics.clone(), threshold);
+        (db, metrics)
+    }
+
+    #[tokio::test]
+    async fn test_delegates_results_and_records_latency() {
+        let mut mock = MockDatabase::new();
+        mock.expect_get_actor_outbox_count()
+            .with(eq("https://example.com/users/alice"))
+            .times(1)
+            .returning(|_| Ok(7));
+        let (db, metrics) = metered(mock, Duration::from_secs(60));
+
+        let count = db
+            .get_actor_outbox_count("https://example.com/users/alice")
+            .await
+            .unwrap();
+
+        assert_eq!(count, 7);
+        assert_eq!(sample_count(&metrics, "get_actor_outbox_count"), 1);
+        assert_eq!(sample_count(&metrics, "get_actor_by_id"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_delegates_errors() {
+        let mut mock = MockDatabase::new();
+        mock.expect_delete_note()
+            .returning(|_| Err(DatabaseError::NotFound));
+        let (db, metrics) = metered(mock, Duration::from_secs(60));
+
+        let result = db.delete_note("https://example.com/notes/1").await;
+
+        assert!(matches!(result, Err(DatabaseError::NotFound)));
+        // Failed calls are timed too
+        assert_eq!(sample_count(&metrics, "delete_note"), 1);
+    }
+
+    #[traced_test]
+    #[tokio::test]
+    async fn test_slow_queries_are_logged_with_key_arguments() {
+        let mut mock = MockDatabase::new();
+        mock.expect_get_activities_by_actor()
+            .returning(|_, _, _| Ok(vec![]));
+        let (db, _metrics) = metered(mock, Duration::ZERO);
+
+        db.get_activities_by_actor("https://example.com/users/alice", 20, 0)
+            .await
+            .unwrap();
+
+        assert!(logs_contain("Slow database query: get_activities_by_actor"));
+        assert!(logs_contain(
+            "actor_id=https://example.com/users/alice limit=20"
+        ));
+    }
+}
@@ -0,0 +1,582 @@
+use crate::database::DeliveryPriority;
+use actix_web::dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform};
+use chrono::{DateTime, Utc};
+use futures::future::LocalBoxFuture;
+use prometheus::{
+    Encoder, Gauge, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+    Opts, Registry, TextEncoder,
+};
+use std::future::{ready, Ready};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Application metrics, registered on a registry owned by this struct so
+/// tests can inspect them without touching global state
+#[allow(dead_code)]
+pub struct Metrics {
+    registry: Registry,
+    db_query_duration: HistogramVec,
+    db_failure_rate: Gauge,
+    db_unavailable: IntCounter,
+    inbox_in_flight: IntGauge,
+    delivery_queue_pending: IntGauge,
+    delivery_queue_oldest_age: IntGauge,
+    delivery_queue_pending_by_host: IntGaugeVec,
+    delivery_queue_pending_by_priority: IntGaugeVec,
+    delivery_queue_processed: IntCounterVec,
+    inbox_rejected: IntCounter,
+    inbox_host_bytes: IntGaugeVec,
+    inbox_quota_rejected: IntCounter,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    deliveries: IntCounterVec,
+    log_suppressed: IntCounterVec,
+    document_cache_lookups: IntCounterVec,
+    parse_failures: IntCounterVec,
+    job_runs: IntCounterVec,
+    job_last_run: IntGaugeVec,
+    job_next_run: IntGaugeVec,
+    job_last_failed: IntGaugeVec,
+}
+
+#[allow(dead_code)]
+impl Metrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+
+        let db_query_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "feder8_db_query_duration_seconds",
+                "Database call latency by Database trait method",
+            ),
+            &["method"],
+        )
+        .expect("valid histogram definition");
+        registry
+            .register(Box::new(db_query_duration.clone()))
+            .expect("metric registered once");
+
+        let db_failure_rate = Gauge::new(
+            "feder8_db_failure_rate",
+            "Share of recent database calls that found the database unavailable",
+        )
+        .expect("valid gauge definition");
+        registry
+            .register(Box::new(db_failure_rate.clone()))
+            .expect("metric registered once");
+
+        let db_unavailable = IntCounter::new(
+            "feder8_db_unavailable_errors_total",
+            "Database calls that failed because the database was unavailable",
+        )
+        .expect("valid counter definition");
+        registry
+            .register(Box::new(db_unavailable.clone()))
+            .expect("metric registered once");
+
+        let inbox_in_flight = IntGauge::new(
+            "feder8_inbox_in_flight",
+            "Inbox activities being processed right now",
+        )
+        .expect("valid gauge definition");
+        registry
+            .register(Box::new(inbox_in_flight.clone()))
+            .expect("metric registered once");
+
+        let delivery_queue_pending = IntGauge::new(
+            "feder8_delivery_queue_pending",
+            "Deliveries waiting in the outbound queue, as last counted",
+        )
+        .expect("valid gauge definition");
+        registry
+            .register(Box::new(delivery_queue_pending.clone()))
+            .expect("metric registered once");
+
+        let delivery_queue_oldest_age = IntGauge::new(
+            "feder8_delivery_queue_oldest_age_seconds",
+            "How long the oldest queued delivery has waited, 0 when the queue is empty",
+        )
+        .expect("valid gauge definition");
+        registry
+            .register(Box::new(delivery_queue_oldest_age.clone()))
+            .expect("metric registered once");
+
+        let delivery_queue_pending_by_host = IntGaugeVec::new(
+            Opts::new(
+                "feder8_delivery_queue_pending_by_host",
+                "Queued deliveries for the hosts with the most waiting",
+            ),
+            &["host"],
+        )
+        .expect("valid gauge definition");
+        registry
+            .register(Box::new(delivery_queue_pending_by_host.clone()))
+            .expect("metric registered once");
+
+        let delivery_queue_pending_by_priority = IntGaugeVec::new(
+            Opts::new(
+                "feder8_delivery_queue_pending_by_priority",
+                "Queued deliveries of each priority",
+            ),
+            &["priority"],
+        )
+        .expect("valid gauge definition");
+        registry
+            .register(Box::new(delivery_queue_pending_by_priority.clone()))
+            .expect("metric registered once");
+
+        let delivery_queue_processed = IntCounterVec::new(
+            Opts::new(
+                "feder8_delivery_queue_processed_total",
+                "Queued deliveries attempted by the worker, by priority and whether they were delivered, retried or dropped",
+            ),
+            &["priority", "outcome"],
+        )
+        .expect("valid counter definition");
+        registry
+            .register(Box::new(delivery_queue_processed.clone()))
+            .expect("metric registered once");
+
+        let inbox_rejected = IntCounter::new(
+            "feder8_inbox_rejected_total",
+            "Inbox activities turned away with a 503 because we were saturated",
+        )
+        .expect("valid counter definition");
+        registry
+            .register(Box::new(inbox_rejected.clone()))
+            .expect("metric registered once");
+
+        let inbox_host_bytes = IntGaugeVec::new(
+            Opts::new(
+                "feder8_inbox_host_bytes",
+                "Bytes each remote host sent the inbox over the last hour",
+            ),
+            &["host"],
+        )
+        .expect("valid gauge definition");
+        registry
+            .register(Box::new(inbox_host_bytes.clone()))
+            .expect("metric registered once");
+
+        let inbox_quota_rejected = IntCounter::new(
+            "feder8_inbox_quota_rejected_total",
+            "Inbox activities turned away with a 429 because their host was over its quota",
+        )
+        .expect("valid counter definition");
+        registry
+            .register(Box::new(inbox_quota_rejected.clone()))
+            .expect("metric registered once");
+
+        let http_requests = IntCounterVec::new(
+            Opts::new(
+                "feder8_http_requests_total",
+                "HTTP requests served, by route pattern, method and status",
+            ),
+            &["handler", "method", "status"],
+        )
+        .expect("valid counter definition");
+        registry
+            .register(Box::new(http_requests.clone()))
+            .expect("metric registered once");
+
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "feder8_http_request_duration_seconds",
+                "HTTP request latency by route pattern and method",
+            ),
+            &["handler", "method"],
+        )
+        .expect("valid histogram definition");
+        registry
+            .register(Box::new(http_request_duration.clone()))
+            .expect("metric registered once");
+
+        let deliveries = IntCounterVec::new(
+            Opts::new(
+                "feder8_deliveries_total",
+                "Activities posted to remote inboxes, by outcome",
+            ),
+            &["outcome"],
+        )
+        .expect("valid counter definition");
+        registry
+            .register(Box::new(deliveries.clone()))
+            .expect("metric registered once");
+
+        let log_suppressed = IntCounterVec::new(
+            Opts::new(
+                "feder8_log_suppressed_total",
+                "Repeated federation warnings logged at debug instead of warn",
+            ),
+            &["warning"],
+        )
+        .expect("valid counter definition");
+        registry
+            .register(Box::new(log_suppressed.clone()))
+            .expect("metric registered once");
+
+        let document_cache_lookups = IntCounterVec::new(
+            Opts::new(
+                "feder8_document_cache_lookups_total",
+                "Actor and note documents looked up in the response cache, by result",
+            ),
+            &["result"],
+        )
+        .expect("valid counter definition");
+        registry
+            .register(Box::new(document_cache_lookups.clone()))
+            .expect("metric registered once");
+
+        let parse_failures = IntCounterVec::new(
+            Opts::new(
+                "feder8_remote_parse_failures_total",
+                "Remote documents that failed to deserialize, by kind and where parsing stopped",
+            ),
+            &["kind", "path"],
+        )
+        .expect("valid counter definition");
+        registry
+            .register(Box::new(parse_failures.clone()))
+            .expect("metric registered once");
+
+        let job_runs = IntCounterVec::new(
+            Opts::new(
+                "feder8_job_runs_total",
+                "Scheduled job runs by job and outcome: success, failure or skipped",
+            ),
+            &["job", "outcome"],
+        )
+        .expect("valid counter definition");
+        registry
+            .register(Box::new(job_runs.clone()))
+            .expect("metric registered once");
+
+        let job_last_run = IntGaugeVec::new(
+            Opts::new(
+                "feder8_job_last_run_timestamp_seconds",
+                "When each scheduled job last started, as a Unix timestamp",
+            ),
+            &["job"],
+        )
+        .expect("valid gauge definition");
+        registry
+            .register(Box::new(job_last_run.clone()))
+            .expect("metric registered once");
+
+        let job_next_run = IntGaugeVec::new(
+            Opts::new(
+                "feder8_job_next_run_timestamp_seconds",
+                "When each scheduled job is next due, as a Unix timestamp",
+            ),
+            &["job"],
+        )
+        .expect("valid gauge definition");
+        registry
+            .register(Box::new(job_next_run.clone()))
+            .expect("metric registered once");
+
+        let job_last_failed = IntGaugeVec::new(
+            Opts::new(
+                "feder8_job_last_run_failed",
+                "1 when a scheduled job's last finished run returned an error",
+            ),
+            &["job"],
+        )
+        .expect("valid gauge definition");
+        registry
+            .register(Box::new(job_last_failed.clone()))
+            .expect("metric registered once");
+
+        Self {
+            registry,
+            db_query_duration,
+            db_failure_rate,
+            db_unavailable,
+            inbox_in_flight,
+            delivery_queue_pending,
+            delivery_queue_oldest_age,
+            delivery_queue_pending_by_host,
+            delivery_queue_pending_by_priority,
+            delivery_queue_processed,
+            inbox_rejected,
+            inbox_host_bytes,
+            inbox_quota_rejected,
+            http_requests,
+            http_request_duration,
+            deliveries,
+            log_suppressed,
+            document_cache_lookups,
+            parse_failures,
+            job_runs,
+            job_last_run,
+            job_next_run,
+            job_last_failed,
+        }
+    }
+
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    /// Record how long a database method took
+    pub fn observe_db_query(&self, method: &str, seconds: f64) {
+        self.db_query_duration
+            .with_label_values(&[method])
+            .observe(seconds);
+    }
+
+    /// Publish the database error budget's current failure rate
+    pub fn set_db_failure_rate(&self, rate: f64) {
+        self.db_failure_rate.set(rate);
+    }
+
+    /// Count a call that failed because the database was unavailable
+    pub fn inc_db_unavailable(&self) {
+        self.db_unavailable.inc();
+    }
+
+    /// Publish how many inbox activities are being processed
+    pub fn set_inbox_in_flight(&self, in_flight: usize) {
+        self.inbox_in_flight.set(in_flight as i64);
+    }
+
+    /// Publish the delivery queue depth
+    pub fn set_delivery_queue_pending(&self, pending: u32) {
+        self.delivery_queue_pending.set(i64::from(pending));
+    }
+
+    /// Publish how long the oldest queued delivery has waited
+    pub fn set_delivery_queue_oldest_age(&self, seconds: i64) {
+        self.delivery_queue_oldest_age.set(seconds);
+    }
+
+    /// Publish per-host queue depth, replacing hosts published before
+    pub fn set_delivery_queue_pending_by_host<'a>(
+        &self,
+        hosts: impl IntoIterator<Item = (&'a str, u32)>,
+    ) {
+        self.delivery_queue_pending_by_host.reset();
+        for (host, pending) in hosts {
+            self.delivery_queue_pending_by_host
+                .with_label_values(&[host])
+                .set(i64::from(pending));
+        }
+    }
+
+    /// Publish per-priority queue depth; priorities left out have none waiting
+    pub fn set_delivery_queue_pending_by_priority(
+        &self,
+        priorities: impl IntoIterator<Item = (DeliveryPriority, u32)>,
+    ) {
+        for priority in DeliveryPriority::ALL {
+            self.delivery_queue_pending_by_priority
+                .with_label_values(&[priority.as_str()])
+                .set(0);
+        }
+        for (priority, pending) in priorities {
+            self.delivery_queue_pending_by_priority
+                .with_label_values(&[priority.as_str()])
+                .set(i64::from(pending));
+        }
+    }
+
+    /// Count a queued delivery the worker attempted: `outcome` is
+    /// "delivered", "retried" or "dropped"
+    pub fn inc_delivery_queue_processed(&self, priority: DeliveryPriority, outcome: &str) {
+        self.delivery_queue_processed
+            .with_label_values(&[priority.as_str(), outcome])
+            .inc();
+    }
+
+    /// Count an inbox activity turned away for backpressure
+    pub fn inc_inbox_rejected(&self) {
+        self.inbox_rejected.inc();
+    }
+
+    /// Publish each host's inbound bytes over the last hour, replacing hosts
+    /// published before
+    pub fn set_inbox_host_bytes<'a>(&self, hosts: impl IntoIterator<Item = (&'a str, u64)>) {
+        self.inbox_host_bytes.reset();
+        for (host, bytes) in hosts {
+            self.inbox_host_bytes
+                .with_label_values(&[host])
+                .set(i64::try_from(bytes).unwrap_or(i64::MAX));
+        }
+    }
+
+    /// Count an inbox activity turned away for its host's quota
+    pub fn inc_inbox_quota_rejected(&self) {
+        self.inbox_quota_rejected.inc();
+    }
+
+    /// Record a served request against the route that handled it
+    pub fn observe_http_request(&self, handler: &str, method: &str, status: u16, seconds: f64) {
+        self.http_requests
+            .with_label_values(&[handler, method, &status.to_string()])
+            .inc();
+        self.http_request_duration
+            .with_label_values(&[handler, method])
+            .observe(seconds);
+    }
+
+    /// Count a delivery attempt to a remote inbox
+    pub fn inc_delivery(&self, succeeded: bool) {
+        let outcome = if succeeded { "success" } else { "failure" };
+        self.deliveries.with_label_values(&[outcome]).inc();
+    }
+
+    /// Count a repeat of a warning that was logged at debug
+    pub fn inc_log_suppressed(&self, warning: &str) {
+        self.log_suppressed.with_label_values(&[warning]).inc();
+    }
+
+    /// Count a response cache lookup as a hit or a miss
+    pub fn inc_document_cache_lookup(&self, hit: bool) {
+        let result = if hit { "hit" } else { "miss" };
+        self.document_cache_lookups
+            .with_label_values(&[result])
+            .inc();
+    }
+
+    /// Count a remote document that failed to deserialize; `path` should
+    /// have array indices folded so the label set stays small
+    pub fn inc_parse_failure(&self, kind: &str, path: &str) {
+        self.parse_failures.with_label_values(&[kind, path]).inc();
+    }
+
+    /// Publish when a scheduled job is next due
+    pub fn set_job_next_run(&self, job: &str, at: DateTime<Utc>) {
+        self.job_next_run
+            .with_label_values(&[job])
+            .set(at.timestamp());
+    }
+
+    /// Publish that a scheduled job started at `at`
+    pub fn set_job_last_run(&self, job: &str, at: DateTime<Utc>) {
+        self.job_last_run
+            .with_label_values(&[job])
+            .set(at.timestamp());
+    }
+
+    /// Count a finished run of a scheduled job
+    pub fn observe_job_run(&self, job: &str, succeeded: bool) {
+        let outcome = if succeeded { "success" } else { "failure" };
+        self.job_runs.with_label_values(&[job, outcome]).inc();
+        self.job_last_failed
+            .with_label_values(&[job])
+            .set(i64::from(!succeeded));
+    }
+
+    /// Count a run of a scheduled job skipped because the last one hadn't
+    /// finished
+    pub fn inc_job_skipped(&self, job: &str) {
+        self.job_runs.with_label_values(&[job, "skipped"]).inc();
+    }
+
+    /// Everything registered, in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .expect("text encoding of gathered metrics");
+        String::from_utf8(buffer).expect("text exposition is UTF-8")
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Middleware counting and timing every request by the route pattern that
+/// matched it, so `/users/{username}/inbox` is one series however many users
+/// there are
+#[derive(Clone)]
+pub struct RequestMetrics {
+    metrics: Arc<Metrics>,
+}
+
+impl RequestMetrics {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self { metrics }
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RequestMetrics
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = RequestMetricsMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestMetricsMiddleware {
+            service: Rc::new(service),
+            metrics: self.metrics.clone(),
+        }))
+    }
+}
+
+pub struct RequestMetricsMiddleware<S> {
+    service: Rc<S>,
+    metrics: Arc<Metrics>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestMetricsMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = Rc::clone(&self.service);
+        let metrics = self.metrics.clone();
+
+        Box::pin(async move {
+            let started = Instant::now();
+            let res = service.call(req).await?;
+
+            // Unrouted paths share one series rather than one per URL
+            let handler = res
+                .request()
+                .match_pattern()
+                .unwrap_or_else(|| "unmatched".to_string());
+            metrics.observe_http_request(
+                &handler,
+                res.request().method().as_str(),
+                res.status().as_u16(),
+                started.elapsed().as_secs_f64(),
+            );
+            Ok(res)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_uses_the_text_exposition_format() {
+        let metrics = Metrics::new();
+        metrics.inc_delivery(true);
+        metrics.inc_delivery(false);
+        metrics.inc_delivery(false);
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE feder8_deliveries_total counter"));
+        assert!(text.contains("feder8_deliveries_total{outcome=\"success\"} 1"));
+        assert!(text.contains("feder8_deliveries_total{outcome=\"failure\"} 2"));
+    }
+}
@@ -1,3 +1,4 @@
+use super::context::ContextBuilder;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -5,7 +6,7 @@ use uuid::Uuid;
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Activity {
     #[serde(rename = "@context")]
-    pub context: Vec<String>,
+    pub context: Vec<serde_json::Value>,
     pub id: String,
     #[serde(rename = "type")]
     pub activity_type: String,
@@ -19,7 +20,7 @@ pub struct Activity {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Create {
     #[serde(rename = "@context")]
-    pub context: Vec<String>,
+    pub context: Vec<serde_json::Value>,
     pub id: String,
     #[serde(rename = "type")]
     pub activity_type: String,
@@ -33,7 +34,7 @@ pub struct Create {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Follow {
     #[serde(rename = "@context")]
-    pub context: Vec<String>,
+    pub context: Vec<serde_json::Value>,
     pub id: String,
     #[serde(rename = "type")]
     pub activity_type: String,
@@ -47,7 +48,7 @@ pub struct Follow {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Accept {
     #[serde(rename = "@context")]
-    pub context: Vec<String>,
+    pub context: Vec<serde_json::Value>,
     pub id: String,
     #[serde(rename = "type")]
     pub activity_type: String,
@@ -68,7 +69,7 @@ impl Activity {
         cc: Vec<String>,
     ) -> Self {
         Self {
-            context: vec!["https://www.w3.org/ns/activitystreams".to_string()],
+            context: ContextBuilder::new().build(),
             id: format!("https://example.com/activities/{}", Uuid::new_v4()),
             activity_type,
             actor,
@@ -84,7 +85,7 @@ impl Create {
     #[allow(dead_code)]
     pub fn new(actor: String, object: serde_json::Value, to: Vec<String>, cc: Vec<String>) -> Self {
         Self {
-            context: vec!["https://www.w3.org/ns/activitystreams".to_string()],
+            context: ContextBuilder::new().build(),
             id: format!("https://example.com/activities/{}", Uuid::new_v4()),
             activity_type: "Create".to_string(),
             actor,
@@ -100,7 +101,7 @@ impl Follow {
     #[allow(dead_code)]
     pub fn new(actor: String, object: String, to: Vec<String>, cc: Vec<String>) -> Self {
         Self {
-            context: vec!["https://www.w3.org/ns/activitystreams".to_string()],
+            context: ContextBuilder::new().build(),
             id: format!("https://example.com/activities/{}", Uuid::new_v4()),
             activity_type: "Follow".to_string(),
             actor,
@@ -116,7 +117,7 @@ impl Accept {
     #[allow(dead_code)]
     pub fn new(actor: String, object: serde_json::Value, to: Vec<String>, cc: Vec<String>) -> Self {
         Self {
-            context: vec!["https://www.w3.org/ns/activitystreams".to_string()],
+            context: ContextBuilder::new().build(),
             id: format!("https://example.com/activities/{}", Uuid::new_v4()),
             activity_type: "Accept".to_string(),
             actor,
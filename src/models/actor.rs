@@ -1,10 +1,12 @@
+use super::context::ContextBuilder;
+use crate::urls::UrlBuilder;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Actor {
     #[serde(rename = "@context")]
-    pub context: Vec<String>,
+    pub context: Vec<serde_json::Value>,
     pub id: String,
     #[serde(rename = "type")]
     pub actor_type: String,
@@ -17,10 +19,17 @@ pub struct Actor {
     pub outbox: String,
     pub followers: String,
     pub following: String,
+    /// Collection of the actor's pinned posts
+    pub featured: String,
     #[serde(rename = "publicKey")]
     pub public_key: PublicKey,
     pub published: DateTime<Utc>,
     pub icon: Option<Icon>,
+    /// Set once the actor has moved to another account
+    #[serde(rename = "movedTo", default, skip_serializing_if = "Option::is_none")]
+    pub moved_to: Option<String>,
+    #[serde(rename = "alsoKnownAs", default, skip_serializing_if = "Vec::is_empty")]
+    pub also_known_as: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,29 +52,32 @@ pub struct Icon {
 }
 
 impl Actor {
+    /// Actors carry a public key, so they need the security vocabulary
+    pub fn context() -> Vec<serde_json::Value> {
+        ContextBuilder::new().with_security().build()
+    }
+
     pub fn new(
         _id: String,
         name: String,
         username: String,
-        server_url: &str,
+        urls: &UrlBuilder,
         public_key_pem: String,
     ) -> Self {
-        let actor_id = format!("{server_url}/users/{username}");
+        let actor_id = urls.actor(&username);
         Self {
-            context: vec![
-                "https://www.w3.org/ns/activitystreams".to_string(),
-                "https://w3id.org/security/v1".to_string(),
-            ],
+            context: Self::context(),
             id: actor_id.clone(),
             actor_type: "Person".to_string(),
             name,
-            preferred_username: username,
             summary: None,
             url: actor_id.clone(),
-            inbox: format!("{actor_id}/inbox"),
-            outbox: format!("{actor_id}/outbox"),
-            followers: format!("{actor_id}/followers"),
-            following: format!("{actor_id}/following"),
+            inbox: urls.inbox(&username),
+            outbox: urls.outbox(&username),
+            followers: urls.followers(&username),
+            following: urls.following(&username),
+            featured: urls.featured(&username),
+            preferred_username: username,
             public_key: PublicKey {
                 id: format!("{actor_id}#main-key"),
                 key_type: "Key".to_string(),
@@ -74,8 +86,24 @@ impl Actor {
             },
             published: Utc::now(),
             icon: None,
+            moved_to: None,
+            also_known_as: Vec::new(),
         }
     }
+
+    /// Add account migration details; their terms come from the Mastodon
+    /// context
+    pub fn with_migration(mut self, moved_to: Option<String>, also_known_as: Vec<String>) -> Self {
+        if moved_to.is_some() || !also_known_as.is_empty() {
+            self.context = ContextBuilder::new()
+                .with_security()
+                .with_mastodon()
+                .build();
+        }
+        self.moved_to = moved_to;
+        self.also_known_as = also_known_as;
+        self
+    }
 }
 
 #[cfg(test)]
@@ -94,7 +122,7 @@ mod tests {
             "unused_id".to_string(),
             name.clone(),
             username.clone(),
-            server_url,
+            &UrlBuilder::new(server_url, None),
             public_key_pem.clone(),
         );
 
@@ -117,6 +145,10 @@ mod tests {
         assert_eq!(actor.outbox, format!("{expected_id}/outbox"));
         assert_eq!(actor.followers, format!("{expected_id}/followers"));
         assert_eq!(actor.following, format!("{expected_id}/following"));
+        assert_eq!(
+            actor.featured,
+            format!("{expected_id}/collections/featured")
+        );
         assert_eq!(actor.icon, None);
 
         // Test public key
@@ -132,7 +164,7 @@ mod tests {
             "test_id".to_string(),
             "Test User".to_string(),
             "testuser".to_string(),
-            "https://example.com",
+            &UrlBuilder::new("https://example.com", None),
             "test_key".to_string(),
         );
 
@@ -155,7 +187,7 @@ mod tests {
             "test".to_string(),
             "Test".to_string(),
             "test".to_string(),
-            "https://example.com",
+            &UrlBuilder::new("https://example.com", None),
             "key".to_string(),
         );
 
@@ -178,7 +210,7 @@ mod tests {
             "test".to_string(),
             "Test".to_string(),
             "test".to_string(),
-            "https://example.com",
+            &UrlBuilder::new("https://example.com", None),
             "key".to_string(),
         );
 
@@ -221,7 +253,7 @@ mod tests {
             "test".to_string(),
             "Test".to_string(),
             "test".to_string(),
-            "https://example.com",
+            &UrlBuilder::new("https://example.com", None),
             "key".to_string(),
         );
 
@@ -237,7 +269,7 @@ mod tests {
             "test".to_string(),
             "Alice".to_string(),
             "alice".to_string(),
-            "https://mastodon.social",
+            &UrlBuilder::new("https://mastodon.social", None),
             "key".to_string(),
         );
 
@@ -251,4 +283,48 @@ mod tests {
         assert_eq!(actor.public_key.id, format!("{base_url}#main-key"));
         assert_eq!(actor.public_key.owner, base_url);
     }
+
+    #[test]
+    fn test_actor_urls_under_base_path() {
+        let actor = Actor::new(
+            "test".to_string(),
+            "Alice".to_string(),
+            "alice".to_string(),
+            &UrlBuilder::new("https://example.com", Some("/fedi")),
+            "key".to_string(),
+        );
+
+        let base_url = "https://example.com/fedi/users/alice";
+        assert_eq!(actor.id, base_url);
+        assert_eq!(actor.inbox, format!("{base_url}/inbox"));
+        assert_eq!(actor.featured, format!("{base_url}/collections/featured"));
+        assert_eq!(actor.public_key.id, format!("{base_url}#main-key"));
+    }
+
+    #[test]
+    fn test_migration_fields_bring_the_mastodon_context() {
+        let actor = Actor::new(
+            "test".to_string(),
+            "Alice".to_string(),
+            "alice".to_string(),
+            &UrlBuilder::new("https://example.com", None),
+            "key".to_string(),
+        );
+        let plain = serde_json::to_value(actor.clone()).unwrap();
+        assert!(plain.get("movedTo").is_none());
+        assert!(plain.get("alsoKnownAs").is_none());
+        assert_eq!(plain["@context"].as_array().unwrap().len(), 2);
+
+        let moved = serde_json::to_value(actor.with_migration(
+            Some("https://new.example/users/alice".to_string()),
+            vec!["https://old.example/users/alice".to_string()],
+        ))
+        .unwrap();
+        assert_eq!(moved["movedTo"], "https://new.example/users/alice");
+        assert_eq!(
+            moved["alsoKnownAs"],
+            serde_json::json!(["https://old.example/users/alice"])
+        );
+        assert_eq!(moved["@context"][2]["movedTo"]["@id"], "as:movedTo");
+    }
 }
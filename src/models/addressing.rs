@@ -0,0 +1,67 @@
+/// The special Public pseudo-address every post visible to anyone carries
+pub const PUBLIC: &str = "https://www.w3.org/ns/activitystreams#Public";
+
+/// Compact spellings of [`PUBLIC`] that servers send after JSON-LD
+/// compaction against the ActivityStreams context
+const PUBLIC_ALIASES: [&str; 2] = ["as:Public", "Public"];
+
+/// Whether `iri` is the Public address, in any of its spellings
+pub fn is_public(iri: &str) -> bool {
+    iri == PUBLIC || PUBLIC_ALIASES.contains(&iri)
+}
+
+/// `audience` with every spelling of Public replaced by [`PUBLIC`], keeping
+/// the order and listing Public once
+pub fn normalize_audience(audience: Vec<String>) -> Vec<String> {
+    let mut seen_public = false;
+    audience
+        .into_iter()
+        .filter_map(|address| {
+            if !is_public(&address) {
+                Some(address)
+            } else if seen_public {
+                None
+            } else {
+                seen_public = true;
+                Some(PUBLIC.to_string())
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_spelling_is_public() {
+        for iri in [PUBLIC, "as:Public", "Public"] {
+            assert!(is_public(iri), "{iri}");
+        }
+        for iri in [
+            "https://example.com/users/alice",
+            "https://example.com/users/alice/followers",
+            "public",
+            "https://www.w3.org/ns/activitystreams#public",
+        ] {
+            assert!(!is_public(iri), "{iri}");
+        }
+    }
+
+    #[test]
+    fn test_normalize_audience_canonicalizes_public() {
+        let followers = "https://example.com/users/alice/followers".to_string();
+        let audience = vec![
+            "as:Public".to_string(),
+            followers.clone(),
+            "Public".to_string(),
+            PUBLIC.to_string(),
+        ];
+        assert_eq!(
+            normalize_audience(audience),
+            vec![PUBLIC.to_string(), followers.clone()]
+        );
+        assert_eq!(normalize_audience(vec![followers.clone()]), vec![followers]);
+        assert!(normalize_audience(vec![]).is_empty());
+    }
+}
@@ -0,0 +1,157 @@
+use serde_json::{json, Value};
+
+pub const ACTIVITYSTREAMS_CONTEXT: &str = "https://www.w3.org/ns/activitystreams";
+pub const SECURITY_CONTEXT: &str = "https://w3id.org/security/v1";
+
+/// Mastodon's extension terms (custom emoji, hashtags, content warnings,
+/// account migration)
+fn mastodon_context() -> Value {
+    json!({
+        "toot": "http://joinmastodon.org/ns#",
+        "Emoji": "toot:Emoji",
+        "Hashtag": "as:Hashtag",
+        "sensitive": "as:sensitive",
+        "movedTo": {"@id": "as:movedTo", "@type": "@id"},
+        "alsoKnownAs": {"@id": "as:alsoKnownAs", "@type": "@id"}
+    })
+}
+
+/// Accumulates the JSON-LD contexts a document needs. The output always
+/// starts with the ActivityStreams context, followed by the security
+/// vocabulary and then extension contexts.
+#[derive(Debug, Clone, Default)]
+pub struct ContextBuilder {
+    security: bool,
+    mastodon: bool,
+}
+
+impl ContextBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build the context for a document based on the fields it carries,
+    /// including those of an embedded `object`
+    pub fn for_document(document: &Value) -> Self {
+        Self::new().with_fields_of(document)
+    }
+
+    pub fn with_security(mut self) -> Self {
+        self.security = true;
+        self
+    }
+
+    pub fn with_mastodon(mut self) -> Self {
+        self.mastodon = true;
+        self
+    }
+
+    /// Add whatever contexts the fields of `document` require
+    pub fn with_fields_of(mut self, document: &Value) -> Self {
+        if document.get("publicKey").is_some() || document.get("signature").is_some() {
+            self.security = true;
+        }
+        if ["sensitive", "movedTo", "alsoKnownAs"]
+            .iter()
+            .any(|field| document.get(field).is_some())
+            || has_extension_tags(document)
+        {
+            self.mastodon = true;
+        }
+        match document.get("object") {
+            Some(object) if object.is_object() => self.with_fields_of(object),
+            _ => self,
+        }
+    }
+
+    pub fn build(&self) -> Vec<Value> {
+        let mut context = vec![Value::String(ACTIVITYSTREAMS_CONTEXT.to_string())];
+        if self.security {
+            context.push(Value::String(SECURITY_CONTEXT.to_string()));
+        }
+        if self.mastodon {
+            context.push(mastodon_context());
+        }
+        context
+    }
+}
+
+fn has_extension_tags(document: &Value) -> bool {
+    let tags = match document.get("tag") {
+        Some(Value::Array(tags)) => tags.iter().collect(),
+        Some(tag) => vec![tag],
+        None => vec![],
+    };
+    tags.iter().any(|tag| {
+        matches!(
+            tag.get("type").and_then(|v| v.as_str()),
+            Some("Emoji" | "Hashtag")
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_document_gets_activitystreams_only() {
+        let context =
+            ContextBuilder::for_document(&json!({"type": "Note", "content": "hi"})).build();
+
+        assert_eq!(context, vec![ACTIVITYSTREAMS_CONTEXT]);
+    }
+
+    #[test]
+    fn test_public_key_adds_security_vocab() {
+        let context = ContextBuilder::for_document(&json!({
+            "type": "Person",
+            "publicKey": {"id": "https://example.com/users/alice#main-key"}
+        }))
+        .build();
+
+        assert_eq!(context, vec![ACTIVITYSTREAMS_CONTEXT, SECURITY_CONTEXT]);
+    }
+
+    #[test]
+    fn test_emoji_tags_add_mastodon_context_last() {
+        let context = ContextBuilder::for_document(&json!({
+            "type": "Create",
+            "signature": {"type": "RsaSignature2017"},
+            "object": {
+                "type": "Note",
+                "tag": [{"type": "Emoji", "name": ":blobcat:"}]
+            }
+        }))
+        .build();
+
+        assert_eq!(context.len(), 3);
+        assert_eq!(context[0], ACTIVITYSTREAMS_CONTEXT);
+        assert_eq!(context[1], SECURITY_CONTEXT);
+        assert_eq!(context[2]["toot"], "http://joinmastodon.org/ns#");
+        assert_eq!(context[2]["Emoji"], "toot:Emoji");
+    }
+
+    #[test]
+    fn test_mention_tags_need_no_extension() {
+        let context = ContextBuilder::for_document(&json!({
+            "type": "Note",
+            "tag": {"type": "Mention", "href": "https://example.com/users/bob"}
+        }))
+        .build();
+
+        assert_eq!(context, vec![ACTIVITYSTREAMS_CONTEXT]);
+    }
+
+    #[test]
+    fn test_explicit_contexts_keep_order() {
+        let context = ContextBuilder::new()
+            .with_mastodon()
+            .with_security()
+            .build();
+
+        assert_eq!(context[0], ACTIVITYSTREAMS_CONTEXT);
+        assert_eq!(context[1], SECURITY_CONTEXT);
+        assert!(context[2].is_object());
+    }
+}
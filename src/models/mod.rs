@@ -1,7 +1,12 @@
 pub mod activity;
 pub mod actor;
+pub mod addressing;
+pub mod context;
 pub mod object;
+pub mod visibility;
 
 // Re-export commonly used types
 pub use actor::Actor;
-pub use object::OrderedCollection;
+pub use context::ContextBuilder;
+pub use object::{OrderedCollection, OrderedCollectionPage, PagedOrderedCollection};
+pub use visibility::Visibility;
@@ -1,10 +1,12 @@
+use super::context::ContextBuilder;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Note {
     #[serde(rename = "@context")]
-    pub context: Vec<String>,
+    pub context: Vec<serde_json::Value>,
     pub id: String,
     #[serde(rename = "type")]
     pub note_type: String,
@@ -15,6 +17,29 @@ pub struct Note {
     pub published: DateTime<Utc>,
     pub in_reply_to: Option<String>,
     pub tag: Vec<Tag>,
+    #[serde(default)]
+    pub attachment: Vec<Attachment>,
+    #[serde(default)]
+    pub sensitive: bool,
+    /// Content warning
+    #[serde(default)]
+    pub summary: Option<String>,
+    /// The OrderedCollection of replies to this note
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replies: Option<String>,
+    /// The collection of Likes of this note
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub likes: Option<String>,
+    /// The collection of Announces of this note
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shares: Option<String>,
+    /// Language tag to content, for notes written in several languages
+    #[serde(
+        rename = "contentMap",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub content_map: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,10 +50,102 @@ pub struct Tag {
     pub href: Option<String>,
 }
 
+impl Tag {
+    /// Normalize a hashtag name for storage and lookup: no leading `#`, lowercased
+    pub fn normalize_hashtag(name: &str) -> String {
+        name.trim_start_matches('#').to_lowercase()
+    }
+
+    /// Normalized names of the Hashtag entries in an object's `tag` property
+    pub fn hashtags_of(object: &serde_json::Value) -> Vec<String> {
+        let mut seen = HashSet::new();
+        Self::entries_of(object, "Hashtag")
+            .into_iter()
+            .filter_map(|tag| tag.get("name").and_then(|v| v.as_str()))
+            .map(Self::normalize_hashtag)
+            .filter(|name| !name.is_empty() && seen.insert(name.clone()))
+            .collect()
+    }
+
+    /// The Mention entries in an object's `tag` property; malformed ones are skipped
+    pub fn mentions_of(object: &serde_json::Value) -> Vec<Tag> {
+        Self::entries_of(object, "Mention")
+            .into_iter()
+            .filter_map(|tag| serde_json::from_value(tag.clone()).ok())
+            .collect()
+    }
+
+    /// Entries of the given type in an object's `tag` property, which may be
+    /// a single object or an array
+    fn entries_of<'a>(object: &'a serde_json::Value, tag_type: &str) -> Vec<&'a serde_json::Value> {
+        let tags = match object.get("tag") {
+            Some(serde_json::Value::Array(tags)) => tags.iter().collect(),
+            Some(tag) => vec![tag],
+            None => vec![],
+        };
+
+        tags.into_iter()
+            .filter(|tag| tag.get("type").and_then(|v| v.as_str()) == Some(tag_type))
+            .collect()
+    }
+}
+
+/// Media types accepted for note attachments
+pub const ALLOWED_ATTACHMENT_MEDIA_TYPES: &[&str] = &[
+    "image/jpeg",
+    "image/png",
+    "image/gif",
+    "image/webp",
+    "image/avif",
+    "video/mp4",
+    "video/webm",
+    "audio/mpeg",
+    "audio/ogg",
+];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attachment {
+    #[serde(rename = "type")]
+    pub attachment_type: String,
+    #[serde(rename = "mediaType")]
+    pub media_type: String,
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub blurhash: Option<String>,
+}
+
+impl Attachment {
+    /// Parse a single attachment, checking its mediaType against the allowlist
+    pub fn from_value(value: &serde_json::Value) -> Result<Self, String> {
+        let attachment: Attachment = serde_json::from_value(value.clone())
+            .map_err(|e| format!("Invalid attachment: {e}"))?;
+
+        if !ALLOWED_ATTACHMENT_MEDIA_TYPES.contains(&attachment.media_type.as_str()) {
+            return Err(format!(
+                "Unsupported attachment mediaType: {}",
+                attachment.media_type
+            ));
+        }
+        Ok(attachment)
+    }
+
+    /// Parse an object's `attachment` property, which may be absent, a single
+    /// object or an array
+    pub fn from_object(object: &serde_json::Value) -> Vec<Result<Self, String>> {
+        match object.get("attachment") {
+            None | Some(serde_json::Value::Null) => vec![],
+            Some(serde_json::Value::Array(items)) => items.iter().map(Self::from_value).collect(),
+            Some(single) => vec![Self::from_value(single)],
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Collection {
     #[serde(rename = "@context")]
-    pub context: Vec<String>,
+    pub context: Vec<serde_json::Value>,
     pub id: String,
     #[serde(rename = "type")]
     pub collection_type: String,
@@ -41,7 +158,7 @@ pub struct Collection {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OrderedCollection {
     #[serde(rename = "@context")]
-    pub context: Vec<String>,
+    pub context: Vec<serde_json::Value>,
     pub id: String,
     #[serde(rename = "type")]
     pub collection_type: String,
@@ -53,6 +170,40 @@ pub struct OrderedCollection {
     pub ordered_items: Vec<serde_json::Value>,
 }
 
+/// One page of an OrderedCollection. Pages embedded in their collection
+/// carry no `@context` of their own.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderedCollectionPage {
+    #[serde(rename = "@context", default, skip_serializing_if = "Vec::is_empty")]
+    pub context: Vec<serde_json::Value>,
+    pub id: String,
+    #[serde(rename = "type")]
+    pub page_type: String,
+    #[serde(rename = "partOf")]
+    pub part_of: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prev: Option<String>,
+    #[serde(rename = "orderedItems")]
+    pub ordered_items: Vec<serde_json::Value>,
+}
+
+/// An OrderedCollection with its first page embedded, so clients can render
+/// it without a second request
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PagedOrderedCollection {
+    #[serde(rename = "@context")]
+    pub context: Vec<serde_json::Value>,
+    pub id: String,
+    #[serde(rename = "type")]
+    pub collection_type: String,
+    #[serde(rename = "totalItems")]
+    pub total_items: u32,
+    pub first: OrderedCollectionPage,
+    pub last: String,
+}
+
 impl Note {
     #[allow(dead_code)]
     pub fn new(
@@ -63,7 +214,7 @@ impl Note {
         cc: Vec<String>,
     ) -> Self {
         Self {
-            context: vec!["https://www.w3.org/ns/activitystreams".to_string()],
+            context: ContextBuilder::new().build(),
             id,
             note_type: "Note".to_string(),
             attributed_to,
@@ -73,15 +224,85 @@ impl Note {
             published: Utc::now(),
             in_reply_to: None,
             tag: vec![],
+            attachment: vec![],
+            sensitive: false,
+            summary: None,
+            replies: None,
+            likes: None,
+            shares: None,
+            content_map: None,
+        }
+    }
+
+    /// The translations in an incoming note object's `contentMap`, keyed by
+    /// language tag. Entries that aren't non-blank strings are dropped.
+    pub fn content_map_of(
+        object: &serde_json::Value,
+    ) -> Option<serde_json::Map<String, serde_json::Value>> {
+        let map: serde_json::Map<_, _> = object
+            .get("contentMap")?
+            .as_object()?
+            .iter()
+            .filter(|(language, content)| {
+                !language.trim().is_empty()
+                    && content.as_str().is_some_and(|c| !c.trim().is_empty())
+            })
+            .map(|(language, content)| (language.clone(), content.clone()))
+            .collect();
+        (!map.is_empty()).then_some(map)
+    }
+
+    /// Language of an incoming note object's primary content: the
+    /// `contentMap` entry with the same text as `content`, else the first one
+    pub fn language_of(object: &serde_json::Value) -> Option<String> {
+        let map = Self::content_map_of(object)?;
+        let content = object.get("content").and_then(|v| v.as_str());
+        map.iter()
+            .find(|(_, translation)| translation.as_str() == content)
+            .or_else(|| map.iter().next())
+            .map(|(language, _)| language.clone())
+    }
+
+    /// Primary content of an incoming note object: `content`, or the
+    /// translation in its language when only a `contentMap` was sent
+    pub fn content_of(object: &serde_json::Value) -> String {
+        match object.get("content").and_then(|v| v.as_str()) {
+            Some(content) if !content.is_empty() => content.to_string(),
+            _ => Self::language_of(object)
+                .and_then(|language| {
+                    object["contentMap"][language.as_str()]
+                        .as_str()
+                        .map(String::from)
+                })
+                .unwrap_or_default(),
         }
     }
+
+    /// Whether an incoming note object is marked sensitive. Anything but a
+    /// boolean `true` counts as not sensitive.
+    pub fn sensitive_of(object: &serde_json::Value) -> bool {
+        object
+            .get("sensitive")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+    }
+
+    /// The content warning of an incoming note object, if it has a non-blank one
+    pub fn summary_of(object: &serde_json::Value) -> Option<String> {
+        object
+            .get("summary")
+            .and_then(|v| v.as_str())
+            .map(str::trim)
+            .filter(|summary| !summary.is_empty())
+            .map(String::from)
+    }
 }
 
 impl Collection {
     #[allow(dead_code)]
     pub fn new(id: String, total_items: u32) -> Self {
         Self {
-            context: vec!["https://www.w3.org/ns/activitystreams".to_string()],
+            context: ContextBuilder::new().build(),
             id: id.clone(),
             collection_type: "Collection".to_string(),
             total_items,
@@ -94,7 +315,7 @@ impl Collection {
 impl OrderedCollection {
     pub fn new(id: String, total_items: u32, ordered_items: Vec<serde_json::Value>) -> Self {
         Self {
-            context: vec!["https://www.w3.org/ns/activitystreams".to_string()],
+            context: ContextBuilder::new().build(),
             id: id.clone(),
             collection_type: "OrderedCollection".to_string(),
             total_items,
@@ -105,6 +326,83 @@ impl OrderedCollection {
     }
 }
 
+impl OrderedCollectionPage {
+    /// The page of `collection_id` starting at `offset`, linking to its
+    /// neighbours when there are any
+    pub fn new(
+        collection_id: &str,
+        offset: u32,
+        page_size: u32,
+        total_items: u32,
+        ordered_items: Vec<serde_json::Value>,
+    ) -> Self {
+        let next_offset = offset.saturating_add(page_size);
+        Self {
+            context: vec![],
+            id: Self::url(collection_id, offset),
+            page_type: "OrderedCollectionPage".to_string(),
+            part_of: collection_id.to_string(),
+            next: (next_offset < total_items).then(|| Self::url(collection_id, next_offset)),
+            prev: (offset > 0).then(|| Self::url(collection_id, offset.saturating_sub(page_size))),
+            ordered_items,
+        }
+    }
+
+    /// The page of `collection_id` at `id`, linking to the given neighbours,
+    /// for collections paged by cursor rather than offset
+    pub fn linked(
+        id: String,
+        collection_id: &str,
+        next: Option<String>,
+        prev: Option<String>,
+        ordered_items: Vec<serde_json::Value>,
+    ) -> Self {
+        Self {
+            context: vec![],
+            id,
+            page_type: "OrderedCollectionPage".to_string(),
+            part_of: collection_id.to_string(),
+            next,
+            prev,
+            ordered_items,
+        }
+    }
+
+    /// URL of the page of `collection_id` starting at `offset`
+    pub fn url(collection_id: &str, offset: u32) -> String {
+        let separator = if collection_id.contains('?') {
+            '&'
+        } else {
+            '?'
+        };
+        if offset == 0 {
+            format!("{collection_id}{separator}page=true")
+        } else {
+            format!("{collection_id}{separator}page=true&offset={offset}")
+        }
+    }
+
+    /// Add the `@context` a page needs when served on its own
+    pub fn standalone(mut self) -> Self {
+        self.context = ContextBuilder::new().build();
+        self
+    }
+}
+
+impl PagedOrderedCollection {
+    pub fn new(first: OrderedCollectionPage, total_items: u32, page_size: u32) -> Self {
+        let last_offset = total_items.saturating_sub(1) / page_size * page_size;
+        Self {
+            context: ContextBuilder::new().build(),
+            id: first.part_of.clone(),
+            collection_type: "OrderedCollection".to_string(),
+            total_items,
+            last: OrderedCollectionPage::url(&first.part_of, last_offset),
+            first,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -135,6 +433,39 @@ mod tests {
         assert_eq!(note.cc, cc);
         assert_eq!(note.in_reply_to, None);
         assert!(note.tag.is_empty());
+        assert!(!note.sensitive);
+        assert_eq!(note.summary, None);
+        assert_eq!(note.replies, None);
+        assert_eq!(note.likes, None);
+        assert_eq!(note.shares, None);
+    }
+
+    #[test]
+    fn test_note_replies_serialization() {
+        let mut note = Note::new(
+            "https://example.com/notes/1".to_string(),
+            "https://example.com/users/test".to_string(),
+            "Hello".to_string(),
+            vec![],
+            vec![],
+        );
+        let json = serde_json::to_value(&note).unwrap();
+        assert!(json.get("replies").is_none());
+        assert!(json.get("likes").is_none());
+        assert!(json.get("shares").is_none());
+
+        note.replies = Some("https://example.com/notes/1/replies".to_string());
+        note.likes = Some("https://example.com/notes/1/likes".to_string());
+        note.shares = Some("https://example.com/notes/1/shares".to_string());
+        let json = serde_json::to_value(&note).unwrap();
+        assert_eq!(json["replies"], "https://example.com/notes/1/replies");
+        assert_eq!(json["likes"], "https://example.com/notes/1/likes");
+        assert_eq!(json["shares"], "https://example.com/notes/1/shares");
+
+        let deserialized: Note = serde_json::from_value(json).unwrap();
+        assert_eq!(deserialized.replies, note.replies);
+        assert_eq!(deserialized.likes, note.likes);
+        assert_eq!(deserialized.shares, note.shares);
     }
 
     #[test]
@@ -191,6 +522,80 @@ mod tests {
         assert_eq!(note.note_type, deserialized.note_type);
     }
 
+    #[test]
+    fn test_note_content_warning_serialization() {
+        let mut note = Note::new(
+            "https://example.com/notes/cw".to_string(),
+            "https://example.com/users/test".to_string(),
+            "Spoilers".to_string(),
+            vec![],
+            vec![],
+        );
+        note.sensitive = true;
+        note.summary = Some("Film ending".to_string());
+
+        let json = serde_json::to_value(&note).unwrap();
+        assert_eq!(json["sensitive"], true);
+        assert_eq!(json["summary"], "Film ending");
+
+        let deserialized: Note = serde_json::from_value(json).unwrap();
+        assert!(deserialized.sensitive);
+        assert_eq!(deserialized.summary.as_deref(), Some("Film ending"));
+
+        // Notes from servers that don't send the fields
+        let mut bare = serde_json::to_value(Note::new(
+            "https://example.com/notes/bare".to_string(),
+            "https://example.com/users/test".to_string(),
+            "Hi".to_string(),
+            vec![],
+            vec![],
+        ))
+        .unwrap();
+        bare.as_object_mut().unwrap().remove("sensitive");
+        bare.as_object_mut().unwrap().remove("summary");
+        let deserialized: Note = serde_json::from_value(bare).unwrap();
+        assert!(!deserialized.sensitive);
+        assert_eq!(deserialized.summary, None);
+    }
+
+    #[test]
+    fn test_content_map_of_objects() {
+        let object = json!({
+            "content": "Bonjour",
+            "contentMap": {"en": "Hello", "fr": "Bonjour", "de": "", "es": 5}
+        });
+        let map = Note::content_map_of(&object).unwrap();
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["en"], "Hello");
+        assert_eq!(Note::language_of(&object).as_deref(), Some("fr"));
+        assert_eq!(Note::content_of(&object), "Bonjour");
+
+        // Without `content` the first translation stands in for it
+        let object = json!({"contentMap": {"fr": "Bonjour", "en": "Hello"}});
+        let language = Note::language_of(&object).unwrap();
+        assert_eq!(Note::content_of(&object), object["contentMap"][&language]);
+
+        let object = json!({"content": "Hi", "contentMap": {"en": ""}});
+        assert_eq!(Note::content_map_of(&object), None);
+        assert_eq!(Note::language_of(&object), None);
+        assert_eq!(Note::content_of(&object), "Hi");
+        assert_eq!(Note::content_of(&json!({})), "");
+    }
+
+    #[test]
+    fn test_content_warning_of_objects() {
+        let object = json!({"sensitive": true, "summary": "  CW: food  "});
+        assert!(Note::sensitive_of(&object));
+        assert_eq!(Note::summary_of(&object).as_deref(), Some("CW: food"));
+
+        let object = json!({"sensitive": "yes", "summary": "   "});
+        assert!(!Note::sensitive_of(&object));
+        assert_eq!(Note::summary_of(&object), None);
+
+        assert!(!Note::sensitive_of(&json!({})));
+        assert_eq!(Note::summary_of(&json!({"summary": null})), None);
+    }
+
     #[test]
     fn test_tag_creation() {
         let mention_tag = Tag {
@@ -224,6 +629,84 @@ mod tests {
         assert!(emoji.href.is_none());
     }
 
+    #[test]
+    fn test_hashtags_of() {
+        let object = json!({
+            "type": "Note",
+            "tag": [
+                {"type": "Hashtag", "name": "#Rust"},
+                {"type": "Mention", "name": "@bob", "href": "https://remote.example/users/bob"},
+                {"type": "Hashtag", "name": "fediverse"},
+                {"type": "Hashtag", "name": "#rust"}
+            ]
+        });
+
+        assert_eq!(Tag::hashtags_of(&object), vec!["rust", "fediverse"]);
+        assert_eq!(
+            Tag::hashtags_of(&json!({"tag": {"type": "Hashtag", "name": "#One"}})),
+            vec!["one"]
+        );
+        assert!(Tag::hashtags_of(&json!({"type": "Note"})).is_empty());
+    }
+
+    #[test]
+    fn test_mentions_of() {
+        let object = json!({
+            "type": "Note",
+            "tag": [
+                {"type": "Hashtag", "name": "#Rust"},
+                {"type": "Mention", "name": "@bob", "href": "https://remote.example/users/bob"},
+                {"type": "Mention", "name": "@carol@remote.example"},
+                {"type": "Mention", "href": "https://remote.example/users/nameless"}
+            ]
+        });
+
+        let mentions = Tag::mentions_of(&object);
+        assert_eq!(mentions.len(), 2);
+        assert_eq!(
+            mentions[0].href.as_deref(),
+            Some("https://remote.example/users/bob")
+        );
+        assert_eq!(mentions[1].name, "@carol@remote.example");
+        assert!(mentions[1].href.is_none());
+    }
+
+    #[test]
+    fn test_ordered_collection_page_links() {
+        let id = "https://example.com/users/alice/outbox";
+
+        let first = OrderedCollectionPage::new(id, 0, 20, 45, vec![]);
+        assert_eq!(first.id, format!("{id}?page=true"));
+        assert_eq!(first.next, Some(format!("{id}?page=true&offset=20")));
+        assert_eq!(first.prev, None);
+
+        let last = OrderedCollectionPage::new(id, 40, 20, 45, vec![]);
+        assert_eq!(last.next, None);
+        assert_eq!(last.prev, Some(format!("{id}?page=true&offset=20")));
+
+        let collection = PagedOrderedCollection::new(first, 45, 20);
+        assert_eq!(collection.id, id);
+        assert_eq!(collection.last, format!("{id}?page=true&offset=40"));
+        assert_eq!(
+            PagedOrderedCollection::new(OrderedCollectionPage::new(id, 0, 20, 0, vec![]), 0, 20)
+                .last,
+            format!("{id}?page=true")
+        );
+
+        // An offset from the query string can be anything
+        let far = OrderedCollectionPage::new(id, u32::MAX, 20, 45, vec![]);
+        assert_eq!(far.next, None);
+        assert!(far.prev.is_some());
+
+        let filtered = format!("{id}?type=Create");
+        let page = OrderedCollectionPage::new(&filtered, 0, 20, 45, vec![]);
+        assert_eq!(page.id, format!("{id}?type=Create&page=true"));
+        assert_eq!(
+            page.next,
+            Some(format!("{id}?type=Create&page=true&offset=20"))
+        );
+    }
+
     #[test]
     fn test_collection_new() {
         let id = "https://example.com/collections/test".to_string();
@@ -373,4 +856,55 @@ mod tests {
             "https://example.com/users/alice/outbox?page=true"
         );
     }
+
+    #[test]
+    fn test_attachment_from_value() {
+        let attachment = Attachment::from_value(&json!({
+            "type": "Image",
+            "mediaType": "image/png",
+            "url": "https://example.com/media/cat.png",
+            "name": "A cat",
+            "blurhash": "LEHV6nWB2yk8pyo0adR*.7kCMdnj"
+        }))
+        .unwrap();
+
+        assert_eq!(attachment.attachment_type, "Image");
+        assert_eq!(attachment.media_type, "image/png");
+        assert_eq!(attachment.name.as_deref(), Some("A cat"));
+
+        let serialized = serde_json::to_value(&attachment).unwrap();
+        assert_eq!(serialized["mediaType"], "image/png");
+        assert_eq!(serialized["blurhash"], "LEHV6nWB2yk8pyo0adR*.7kCMdnj");
+    }
+
+    #[test]
+    fn test_attachment_rejects_disallowed_media_type() {
+        let result = Attachment::from_value(&json!({
+            "type": "Document",
+            "mediaType": "application/x-msdownload",
+            "url": "https://example.com/media/setup.exe"
+        }));
+
+        assert_eq!(
+            result.unwrap_err(),
+            "Unsupported attachment mediaType: application/x-msdownload"
+        );
+        assert!(Attachment::from_value(&json!({"type": "Image"})).is_err());
+    }
+
+    #[test]
+    fn test_attachments_from_object() {
+        let image =
+            json!({"type": "Image", "mediaType": "image/jpeg", "url": "https://example.com/a.jpg"});
+
+        assert!(Attachment::from_object(&json!({"type": "Note"})).is_empty());
+        assert_eq!(
+            Attachment::from_object(&json!({"attachment": image.clone()})).len(),
+            1
+        );
+        assert_eq!(
+            Attachment::from_object(&json!({"attachment": [image.clone(), image]})).len(),
+            2
+        );
+    }
 }
@@ -0,0 +1,147 @@
+use crate::models::addressing::is_public;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Who a post is meant for, derived from its addressing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    /// Public in `to`: shown everywhere
+    Public,
+    /// Public in `cc`: visible to anyone but kept off public timelines
+    Unlisted,
+    /// Addressed to a followers collection and no Public
+    Followers,
+    /// Addressed only to explicit actors
+    Direct,
+    /// Posted to this server only: never delivered, and only shown to
+    /// viewers signed in here. Never derived from addressing.
+    Local,
+}
+
+impl Visibility {
+    pub fn from_addressing(to: &[String], cc: &[String]) -> Self {
+        let addresses_public = |addresses: &[String]| addresses.iter().any(|a| is_public(a));
+
+        if addresses_public(to) {
+            Visibility::Public
+        } else if addresses_public(cc) {
+            Visibility::Unlisted
+        } else if to.iter().chain(cc).any(|a| a.ends_with("/followers")) {
+            Visibility::Followers
+        } else {
+            Visibility::Direct
+        }
+    }
+
+    /// Whether unauthenticated clients may see items with this visibility
+    pub fn is_publicly_visible(&self) -> bool {
+        matches!(self, Visibility::Public | Visibility::Unlisted)
+    }
+
+    /// Whether items with this visibility may leave this server
+    pub fn federates(&self) -> bool {
+        !matches!(self, Visibility::Local)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Visibility::Public => "public",
+            Visibility::Unlisted => "unlisted",
+            Visibility::Followers => "followers",
+            Visibility::Direct => "direct",
+            Visibility::Local => "local",
+        }
+    }
+}
+
+impl fmt::Display for Visibility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Visibility {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "public" => Ok(Visibility::Public),
+            "unlisted" => Ok(Visibility::Unlisted),
+            "followers" => Ok(Visibility::Followers),
+            "direct" => Ok(Visibility::Direct),
+            "local" => Ok(Visibility::Local),
+            other => Err(format!("Unknown visibility: {other}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::addressing::PUBLIC;
+
+    const FOLLOWERS: &str = "https://example.com/users/alice/followers";
+    const BOB: &str = "https://remote.example/users/bob";
+
+    fn addrs(values: &[&str]) -> Vec<String> {
+        values.iter().map(|v| v.to_string()).collect()
+    }
+
+    #[test]
+    fn test_public_in_to_is_public() {
+        let visibility = Visibility::from_addressing(&addrs(&[PUBLIC]), &addrs(&[FOLLOWERS]));
+        assert_eq!(visibility, Visibility::Public);
+    }
+
+    #[test]
+    fn test_public_in_cc_is_unlisted() {
+        let visibility = Visibility::from_addressing(&addrs(&[FOLLOWERS]), &addrs(&["as:Public"]));
+        assert_eq!(visibility, Visibility::Unlisted);
+    }
+
+    #[test]
+    fn test_followers_collection_only_is_followers() {
+        let visibility = Visibility::from_addressing(&addrs(&[FOLLOWERS]), &addrs(&[BOB]));
+        assert_eq!(visibility, Visibility::Followers);
+    }
+
+    #[test]
+    fn test_explicit_actors_only_is_direct() {
+        assert_eq!(
+            Visibility::from_addressing(&addrs(&[BOB]), &[]),
+            Visibility::Direct
+        );
+        assert_eq!(Visibility::from_addressing(&[], &[]), Visibility::Direct);
+    }
+
+    #[test]
+    fn test_round_trips_through_str() {
+        for visibility in [
+            Visibility::Public,
+            Visibility::Unlisted,
+            Visibility::Followers,
+            Visibility::Direct,
+            Visibility::Local,
+        ] {
+            assert_eq!(visibility.as_str().parse(), Ok(visibility));
+        }
+        assert!("secret".parse::<Visibility>().is_err());
+    }
+
+    #[test]
+    fn test_publicly_visible() {
+        assert!(Visibility::Public.is_publicly_visible());
+        assert!(Visibility::Unlisted.is_publicly_visible());
+        assert!(!Visibility::Followers.is_publicly_visible());
+        assert!(!Visibility::Direct.is_publicly_visible());
+        assert!(!Visibility::Local.is_publicly_visible());
+    }
+
+    #[test]
+    fn test_only_local_stays_home() {
+        assert!(Visibility::Direct.federates());
+        assert!(!Visibility::Local.federates());
+    }
+}
@@ -0,0 +1,2321 @@
+//! [`Database`] on Postgres, for running several replicas against one
+//! database. The schema mirrors the SQLite one, with JSON kept in `JSONB`
+//! columns and times in `TIMESTAMPTZ`. Queries are checked at runtime: the
+//! compile-time checked macros verify against a single database per build,
+//! and that database is SQLite.
+
+use crate::database::{
+    escape_like, Database, DatabaseError, DbActivity, DbActor, DbActorSummary, DbBlock, DbDelivery,
+    DbFollowRelation, DbHostBacklog, DbInboundUsage, DbInstanceStats, DbLike, DbNote, DbNoteLabel,
+    DbOldestDelivery, DbParseFailure, DbPendingAccept, DbPreferences, DbProcessingTrace, DbRelay,
+    DbRemoteActor, DbToken, DeliveryPriority,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sqlx::postgres::{PgConnectOptions, PgPool, PgPoolOptions, PgRow};
+use sqlx::Row;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::instrument;
+
+const ACTOR_COLUMNS: &str = "id, username, name, summary, public_key_pem, private_key_pem, is_admin, actor_type, moved_to, also_known_as::text AS also_known_as, created_at, updated_at";
+
+const ACTIVITY_COLUMNS: &str = "id, actor_id, activity_type, object::text AS object, target, to_recipients::text AS to_recipients, cc_recipients::text AS cc_recipients, published, visibility, state, created_at";
+
+const NOTE_COLUMNS: &str = "id, attributed_to, content, to_recipients::text AS to_recipients, cc_recipients::text AS cc_recipients, published, in_reply_to, tags::text AS tags, attachments::text AS attachments, visibility, state, pinned, created_at, in_reply_to_actor, thread_depth, thread_truncated, sensitive, summary, language, content_map::text AS content_map";
+
+const FOLLOW_COLUMNS: &str = "id, follower_id, following_id, status, created_at, updated_at";
+
+/// The text a note is searched by, matching the GIN index in the migration
+const NOTE_SEARCH_VECTOR: &str = "to_tsvector('simple', content || ' ' || COALESCE(summary, ''))";
+
+/// How [`PostgresDatabase`] connects: where to, and how its pool is sized
+#[derive(Debug, Clone)]
+pub struct PostgresDatabaseConfig {
+    pub database_url: String,
+    pub max_connections: u32,
+    pub min_connections: u32,
+    pub connect_timeout: Duration,
+    /// How long a spare connection is kept open; `None` keeps it forever
+    pub idle_timeout: Option<Duration>,
+}
+
+impl PostgresDatabaseConfig {
+    /// The pool settings from `config`, connecting to `database_url`
+    pub fn from_config(database_url: &str, config: &crate::config::Config) -> Self {
+        Self {
+            database_url: database_url.to_string(),
+            max_connections: config.db_max_connections,
+            min_connections: config.db_min_connections,
+            connect_timeout: Duration::from_secs(config.db_connect_timeout_secs),
+            idle_timeout: Some(config.db_idle_timeout_secs)
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs),
+        }
+    }
+}
+
+impl Default for PostgresDatabaseConfig {
+    fn default() -> Self {
+        Self {
+            database_url: "postgres://localhost/feder8".to_string(),
+            max_connections: 10,
+            min_connections: 0,
+            connect_timeout: Duration::from_secs(30),
+            idle_timeout: Some(Duration::from_secs(600)),
+        }
+    }
+}
+
+pub struct PostgresDatabase {
+    pool: PgPool,
+}
+
+impl PostgresDatabase {
+    /// Connect with the default pool settings
+    #[allow(dead_code)]
+    pub async fn new(database_url: &str) -> Result<Self, DatabaseError> {
+        Self::with_config(PostgresDatabaseConfig {
+            database_url: database_url.to_string(),
+            ..PostgresDatabaseConfig::default()
+        })
+        .await
+    }
+
+    /// Connect with the given pool settings
+    pub async fn with_config(config: PostgresDatabaseConfig) -> Result<Self, DatabaseError> {
+        let options = PgConnectOptions::from_str(&config.database_url)
+            .map_err(|e| DatabaseError::Connection(e.to_string()))?;
+
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(config.connect_timeout)
+            .max_connections(config.max_connections.max(1))
+            .min_connections(config.min_connections.min(config.max_connections))
+            .idle_timeout(config.idle_timeout)
+            .connect_with(options)
+            .await
+            .map_err(|e| DatabaseError::Connection(e.to_string()))?;
+
+        Ok(Self { pool })
+    }
+
+    pub async fn run_migrations(&self) -> Result<(), DatabaseError> {
+        sqlx::migrate!("./migrations/postgres")
+            .run(&self.pool)
+            .await
+            .map_err(|e| DatabaseError::Connection(e.to_string()))?;
+        Ok(())
+    }
+}
+
+fn actor_from_row(row: &PgRow) -> Result<DbActor, DatabaseError> {
+    Ok(DbActor {
+        id: row.try_get("id")?,
+        username: row.try_get("username")?,
+        name: row.try_get("name")?,
+        summary: row.try_get("summary")?,
+        public_key_pem: row.try_get("public_key_pem")?,
+        private_key_pem: row.try_get("private_key_pem")?,
+        is_admin: row.try_get("is_admin")?,
+        actor_type: row.try_get("actor_type")?,
+        moved_to: row.try_get("moved_to")?,
+        also_known_as: serde_json::from_str(row.try_get("also_known_as")?)?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+fn activity_from_row(row: &PgRow) -> Result<DbActivity, DatabaseError> {
+    Ok(DbActivity {
+        id: row.try_get("id")?,
+        actor_id: row.try_get("actor_id")?,
+        activity_type: row.try_get("activity_type")?,
+        object: serde_json::from_str(row.try_get("object")?)?,
+        target: row.try_get("target")?,
+        to_recipients: serde_json::from_str(row.try_get("to_recipients")?)?,
+        cc_recipients: serde_json::from_str(row.try_get("cc_recipients")?)?,
+        published: row.try_get("published")?,
+        visibility: row
+            .try_get::<&str, _>("visibility")?
+            .parse()
+            .map_err(DatabaseError::InvalidData)?,
+        state: row
+            .try_get::<&str, _>("state")?
+            .parse()
+            .map_err(DatabaseError::InvalidData)?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+fn note_from_row(row: &PgRow) -> Result<DbNote, DatabaseError> {
+    Ok(DbNote {
+        id: row.try_get("id")?,
+        attributed_to: row.try_get("attributed_to")?,
+        content: row.try_get("content")?,
+        to_recipients: serde_json::from_str(row.try_get("to_recipients")?)?,
+        cc_recipients: serde_json::from_str(row.try_get("cc_recipients")?)?,
+        published: row.try_get("published")?,
+        in_reply_to: row.try_get("in_reply_to")?,
+        tags: serde_json::from_str(row.try_get("tags")?)?,
+        attachments: serde_json::from_str(row.try_get("attachments")?)?,
+        visibility: row
+            .try_get::<&str, _>("visibility")?
+            .parse()
+            .map_err(DatabaseError::InvalidData)?,
+        state: row
+            .try_get::<&str, _>("state")?
+            .parse()
+            .map_err(DatabaseError::InvalidData)?,
+        pinned: row.try_get("pinned")?,
+        created_at: row.try_get("created_at")?,
+        in_reply_to_actor: row.try_get("in_reply_to_actor")?,
+        thread_depth: row
+            .try_get::<Option<i64>, _>("thread_depth")?
+            .map(|depth| depth as u32),
+        thread_truncated: row.try_get("thread_truncated")?,
+        sensitive: row.try_get("sensitive")?,
+        summary: row.try_get("summary")?,
+        language: row.try_get("language")?,
+        content_map: row
+            .try_get::<Option<&str>, _>("content_map")?
+            .map(serde_json::from_str)
+            .transpose()?,
+    })
+}
+
+fn follow_from_row(row: &PgRow) -> Result<DbFollowRelation, DatabaseError> {
+    Ok(DbFollowRelation {
+        id: row.try_get("id")?,
+        follower_id: row.try_get("follower_id")?,
+        following_id: row.try_get("following_id")?,
+        status: row.try_get("status")?,
+        created_at: row.try_get("created_at")?,
+        updated_at: row.try_get("updated_at")?,
+    })
+}
+
+fn parse_failure_from_row(row: &PgRow) -> Result<DbParseFailure, DatabaseError> {
+    Ok(DbParseFailure {
+        id: row.try_get("id")?,
+        kind: row.try_get("kind")?,
+        url: row.try_get("url")?,
+        error_path: row.try_get("error_path")?,
+        error: row.try_get("error")?,
+        document: row.try_get("document")?,
+        truncated: row.try_get("truncated")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+fn relay_from_row(row: &PgRow) -> Result<DbRelay, DatabaseError> {
+    Ok(DbRelay {
+        id: row.try_get("id")?,
+        inbox_url: row.try_get("inbox_url")?,
+        actor_id: row.try_get("actor_id")?,
+        follow_id: row.try_get("follow_id")?,
+        status: row.try_get("status")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+fn like_from_row(row: &PgRow) -> Result<DbLike, DatabaseError> {
+    Ok(DbLike {
+        id: row.try_get("id")?,
+        actor_id: row.try_get("actor_id")?,
+        object_id: row.try_get("object_id")?,
+        created_at: row.try_get("created_at")?,
+    })
+}
+
+fn count(row: &PgRow) -> Result<u32, DatabaseError> {
+    Ok(row.try_get::<i64, _>(0)? as u32)
+}
+
+#[async_trait]
+impl Database for PostgresDatabase {
+    #[instrument(level = "debug", skip(self, actor), fields(actor_id = %actor.id))]
+    async fn create_actor(&self, actor: &DbActor) -> Result<(), DatabaseError> {
+        let also_known_as = serde_json::to_string(&actor.also_known_as)?;
+        sqlx::query(
+            r#"
+            INSERT INTO actors (id, username, name, summary, public_key_pem, private_key_pem, is_admin, actor_type, moved_to, also_known_as, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10::jsonb, $11, $12)
+            "#,
+        )
+        .bind(&actor.id)
+        .bind(&actor.username)
+        .bind(&actor.name)
+        .bind(&actor.summary)
+        .bind(&actor.public_key_pem)
+        .bind(&actor.private_key_pem)
+        .bind(actor.is_admin)
+        .bind(&actor.actor_type)
+        .bind(&actor.moved_to)
+        .bind(also_known_as)
+        .bind(actor.created_at)
+        .bind(actor.updated_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_actor_by_id(&self, id: &str) -> Result<Option<DbActor>, DatabaseError> {
+        sqlx::query(&format!("SELECT {ACTOR_COLUMNS} FROM actors WHERE id = $1"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .as_ref()
+            .map(actor_from_row)
+            .transpose()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_actor_by_username(
+        &self,
+        username: &str,
+    ) -> Result<Option<DbActor>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {ACTOR_COLUMNS} FROM actors WHERE username = $1"
+        ))
+        .bind(username)
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(actor_from_row)
+        .transpose()
+    }
+
+    #[instrument(level = "debug", skip(self, actor), fields(actor_id = %actor.id))]
+    async fn update_actor(&self, actor: &DbActor) -> Result<(), DatabaseError> {
+        let also_known_as = serde_json::to_string(&actor.also_known_as)?;
+        sqlx::query(
+            r#"
+            UPDATE actors
+            SET name = $1, summary = $2, public_key_pem = $3, private_key_pem = $4, is_admin = $5, actor_type = $6, moved_to = $7, also_known_as = $8::jsonb, updated_at = CURRENT_TIMESTAMP
+            WHERE id = $9
+            "#,
+        )
+        .bind(&actor.name)
+        .bind(&actor.summary)
+        .bind(&actor.public_key_pem)
+        .bind(&actor.private_key_pem)
+        .bind(actor.is_admin)
+        .bind(&actor.actor_type)
+        .bind(&actor.moved_to)
+        .bind(also_known_as)
+        .bind(&actor.id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn touch_actor(&self, id: &str) -> Result<(), DatabaseError> {
+        let result = sqlx::query("UPDATE actors SET updated_at = CURRENT_TIMESTAMP WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self, also_known_as))]
+    async fn update_actor_migration(
+        &self,
+        id: &str,
+        moved_to: Option<String>,
+        also_known_as: &[String],
+    ) -> Result<(), DatabaseError> {
+        let also_known_as = serde_json::to_string(also_known_as)?;
+        sqlx::query(
+            "UPDATE actors SET moved_to = $1, also_known_as = $2::jsonb, updated_at = CURRENT_TIMESTAMP WHERE id = $3",
+        )
+        .bind(moved_to)
+        .bind(also_known_as)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn delete_actor(&self, id: &str) -> Result<(), DatabaseError> {
+        sqlx::query("DELETE FROM actors WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn list_actors(&self, limit: u32, offset: u32) -> Result<Vec<DbActor>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {ACTOR_COLUMNS} FROM actors ORDER BY created_at ASC LIMIT $1 OFFSET $2"
+        ))
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(actor_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn search_actors(
+        &self,
+        query: &str,
+        limit: u32,
+    ) -> Result<Vec<DbActorSummary>, DatabaseError> {
+        // `%` and `_` in the query match themselves, not any characters
+        let pattern = format!("%{}%", escape_like(query));
+        let rows = sqlx::query(
+            r#"
+            SELECT id, username, name
+            FROM actors
+            WHERE username ILIKE $1 ESCAPE '\' OR name ILIKE $1 ESCAPE '\'
+            ORDER BY lower(username) = lower($2) DESC, username COLLATE "C" ASC
+            LIMIT $3
+            "#,
+        )
+        .bind(pattern)
+        .bind(query)
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|r| {
+                Ok(DbActorSummary {
+                    id: r.try_get("id")?,
+                    username: Some(r.try_get("username")?),
+                    name: Some(r.try_get("name")?),
+                    avatar_url: None,
+                    local: true,
+                })
+            })
+            .collect()
+    }
+
+    #[instrument(level = "debug", skip(self, activity), fields(activity_id = %activity.id))]
+    async fn create_activity(&self, activity: &DbActivity) -> Result<(), DatabaseError> {
+        let to_json = serde_json::to_string(&activity.to_recipients)?;
+        let cc_json = serde_json::to_string(&activity.cc_recipients)?;
+        let object_json = serde_json::to_string(&activity.object)?;
+
+        // The recipients are indexed alongside, for inbox lookups
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            r#"
+            INSERT INTO activities (id, actor_id, activity_type, object, target, to_recipients, cc_recipients, published, visibility, state, created_at)
+            VALUES ($1, $2, $3, $4::jsonb, $5, $6::jsonb, $7::jsonb, $8, $9, $10, $11)
+            "#,
+        )
+        .bind(&activity.id)
+        .bind(&activity.actor_id)
+        .bind(&activity.activity_type)
+        .bind(object_json)
+        .bind(&activity.target)
+        .bind(&to_json)
+        .bind(&cc_json)
+        .bind(activity.published)
+        .bind(activity.visibility.as_str())
+        .bind(activity.state.as_str())
+        .bind(activity.created_at)
+        .execute(&mut *tx)
+        .await?;
+
+        for (recipients, recipient_type) in [(&to_json, "to"), (&cc_json, "cc")] {
+            sqlx::query(
+                "INSERT INTO activity_recipients (activity_id, recipient_url, recipient_type) SELECT $1, value, $3 FROM jsonb_array_elements_text($2::jsonb) ON CONFLICT DO NOTHING",
+            )
+            .bind(&activity.id)
+            .bind(recipients)
+            .bind(recipient_type)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_activity_by_id(&self, id: &str) -> Result<Option<DbActivity>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {ACTIVITY_COLUMNS} FROM activities WHERE id = $1"
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(activity_from_row)
+        .transpose()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_recent_activity_ids(&self, limit: u32) -> Result<Vec<String>, DatabaseError> {
+        let rows = sqlx::query("SELECT id FROM activities ORDER BY created_at DESC LIMIT $1")
+            .bind(i64::from(limit))
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter().map(|r| Ok(r.try_get("id")?)).collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_activities_by_actor(
+        &self,
+        actor_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {ACTIVITY_COLUMNS} FROM activities WHERE actor_id = $1 AND state = 'published' ORDER BY published DESC, id DESC LIMIT $2 OFFSET $3"
+        ))
+        .bind(actor_id)
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(activity_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_public_activities_by_actor(
+        &self,
+        actor_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {ACTIVITY_COLUMNS} FROM activities WHERE actor_id = $1 AND visibility IN ('public', 'unlisted') AND state = 'published' ORDER BY published DESC, id DESC LIMIT $2 OFFSET $3"
+        ))
+        .bind(actor_id)
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(activity_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_activities_by_actor_before(
+        &self,
+        actor_id: &str,
+        before_published: DateTime<Utc>,
+        before_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {ACTIVITY_COLUMNS} FROM activities WHERE actor_id = $1 AND state = 'published' AND (published, id) < ($2, $3) ORDER BY published DESC, id DESC LIMIT $4"
+        ))
+        .bind(actor_id)
+        .bind(before_published)
+        .bind(before_id)
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(activity_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_activities_by_actor_after(
+        &self,
+        actor_id: &str,
+        after_published: DateTime<Utc>,
+        after_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        // Read oldest first to find the page, served newest first
+        let mut activities = sqlx::query(&format!(
+            "SELECT {ACTIVITY_COLUMNS} FROM activities WHERE actor_id = $1 AND state = 'published' AND (published, id) > ($2, $3) ORDER BY published ASC, id ASC LIMIT $4"
+        ))
+        .bind(actor_id)
+        .bind(after_published)
+        .bind(after_id)
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(activity_from_row)
+        .collect::<Result<Vec<_>, _>>()?;
+        activities.reverse();
+        Ok(activities)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_public_activities_by_actor_before(
+        &self,
+        actor_id: &str,
+        before_published: DateTime<Utc>,
+        before_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {ACTIVITY_COLUMNS} FROM activities WHERE actor_id = $1 AND visibility IN ('public', 'unlisted') AND state = 'published' AND (published, id) < ($2, $3) ORDER BY published DESC, id DESC LIMIT $4"
+        ))
+        .bind(actor_id)
+        .bind(before_published)
+        .bind(before_id)
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(activity_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_public_activities_by_actor_after(
+        &self,
+        actor_id: &str,
+        after_published: DateTime<Utc>,
+        after_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        // Read oldest first to find the page, served newest first
+        let mut activities = sqlx::query(&format!(
+            "SELECT {ACTIVITY_COLUMNS} FROM activities WHERE actor_id = $1 AND visibility IN ('public', 'unlisted') AND state = 'published' AND (published, id) > ($2, $3) ORDER BY published ASC, id ASC LIMIT $4"
+        ))
+        .bind(actor_id)
+        .bind(after_published)
+        .bind(after_id)
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(activity_from_row)
+        .collect::<Result<Vec<_>, _>>()?;
+        activities.reverse();
+        Ok(activities)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_activities_by_actor_and_types(
+        &self,
+        actor_id: &str,
+        types: &[String],
+        public_only: bool,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {ACTIVITY_COLUMNS} FROM activities WHERE actor_id = $1 AND activity_type = ANY($2) AND (NOT $3 OR visibility IN ('public', 'unlisted')) AND state = 'published' ORDER BY published DESC LIMIT $4 OFFSET $5"
+        ))
+        .bind(actor_id)
+        .bind(types)
+        .bind(public_only)
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(activity_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn count_activities_by_actor_and_types(
+        &self,
+        actor_id: &str,
+        types: &[String],
+        public_only: bool,
+    ) -> Result<u32, DatabaseError> {
+        let row = sqlx::query(
+            "SELECT COUNT(*) FROM activities WHERE actor_id = $1 AND activity_type = ANY($2) AND (NOT $3 OR visibility IN ('public', 'unlisted')) AND state = 'published'",
+        )
+        .bind(actor_id)
+        .bind(types)
+        .bind(public_only)
+        .fetch_one(&self.pool)
+        .await?;
+        count(&row)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_inbox_activities(
+        &self,
+        actor_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        sqlx::query(&format!(
+            r#"
+            SELECT {ACTIVITY_COLUMNS}
+            FROM activities
+            WHERE id IN (SELECT activity_id FROM activity_recipients WHERE recipient_url = $1) AND state = 'published'
+            ORDER BY published DESC, id DESC
+            LIMIT $2 OFFSET $3
+            "#
+        ))
+        .bind(actor_id)
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(activity_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn count_inbox_activities(&self, actor_id: &str) -> Result<u32, DatabaseError> {
+        let row = sqlx::query(
+            "SELECT COUNT(*) FROM activities WHERE id IN (SELECT activity_id FROM activity_recipients WHERE recipient_url = $1) AND state = 'published'",
+        )
+        .bind(actor_id)
+        .fetch_one(&self.pool)
+        .await?;
+        count(&row)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_inbox_activities_before(
+        &self,
+        actor_id: &str,
+        before_published: DateTime<Utc>,
+        before_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {ACTIVITY_COLUMNS} FROM activities WHERE id IN (SELECT activity_id FROM activity_recipients WHERE recipient_url = $1) AND state = 'published' AND (published, id) < ($2, $3) ORDER BY published DESC, id DESC LIMIT $4"
+        ))
+        .bind(actor_id)
+        .bind(before_published)
+        .bind(before_id)
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(activity_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_inbox_activities_after(
+        &self,
+        actor_id: &str,
+        after_published: DateTime<Utc>,
+        after_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        // Read oldest first to find the page, served newest first
+        let mut activities = sqlx::query(&format!(
+            "SELECT {ACTIVITY_COLUMNS} FROM activities WHERE id IN (SELECT activity_id FROM activity_recipients WHERE recipient_url = $1) AND state = 'published' AND (published, id) > ($2, $3) ORDER BY published ASC, id ASC LIMIT $4"
+        ))
+        .bind(actor_id)
+        .bind(after_published)
+        .bind(after_id)
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(activity_from_row)
+        .collect::<Result<Vec<_>, _>>()?;
+        activities.reverse();
+        Ok(activities)
+    }
+
+    #[instrument(level = "debug", skip(self, object))]
+    async fn update_activity_object(&self, id: &str, object: &Value) -> Result<(), DatabaseError> {
+        let object_json = serde_json::to_string(object)?;
+
+        let result = sqlx::query("UPDATE activities SET object = $1::jsonb WHERE id = $2")
+            .bind(object_json)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_shares(
+        &self,
+        object_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        // Announces store the boosted note's id as a bare JSON string
+        sqlx::query(&format!(
+            "SELECT {ACTIVITY_COLUMNS} FROM activities WHERE activity_type = 'Announce' AND object = to_jsonb($1::text) AND visibility IN ('public', 'unlisted') AND state = 'published' ORDER BY published ASC LIMIT $2 OFFSET $3"
+        ))
+        .bind(object_id)
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(activity_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn count_shares(&self, object_id: &str) -> Result<u32, DatabaseError> {
+        let row = sqlx::query(
+            "SELECT COUNT(*) FROM activities WHERE activity_type = 'Announce' AND object = to_jsonb($1::text) AND visibility IN ('public', 'unlisted') AND state = 'published'",
+        )
+        .bind(object_id)
+        .fetch_one(&self.pool)
+        .await?;
+        count(&row)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_due_scheduled_activities(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {ACTIVITY_COLUMNS} FROM activities WHERE state = 'scheduled' AND published <= $1 ORDER BY published ASC"
+        ))
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(activity_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn publish_scheduled_activity(&self, id: &str) -> Result<(), DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query(
+            "UPDATE notes SET state = 'published' WHERE id = (SELECT object->>'id' FROM activities WHERE id = $1)",
+        )
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+
+        let result = sqlx::query(
+            "UPDATE activities SET state = 'published' WHERE id = $1 AND state = 'scheduled'",
+        )
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn delete_activities_older_than(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64, DatabaseError> {
+        // The latest Follow of every follow is found in one pass over the
+        // Follow activities, rather than by a lookup per follow
+        let result = sqlx::query(
+            r#"
+            DELETE FROM activities
+            WHERE created_at < $1
+              AND state = 'published'
+              AND actor_id NOT IN (SELECT id FROM actors)
+              AND id NOT IN (SELECT id FROM follows)
+              AND id NOT IN (
+                  SELECT id FROM (
+                      SELECT a.id, ROW_NUMBER() OVER (
+                          PARTITION BY f.follower_id, f.following_id
+                          ORDER BY a.created_at DESC, a.id DESC
+                      ) AS newest
+                      FROM follows f
+                      JOIN activities a
+                        ON a.actor_id = f.follower_id
+                       AND a.activity_type = 'Follow'
+                       AND COALESCE(a.object->>'id', a.object#>>'{}') = f.following_id
+                  ) AS follow_activities
+                  WHERE newest = 1
+              )
+            "#,
+        )
+        .bind(cutoff)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    #[instrument(level = "debug", skip(self, note), fields(note_id = %note.id))]
+    async fn create_note(&self, note: &DbNote) -> Result<(), DatabaseError> {
+        let to_json = serde_json::to_string(&note.to_recipients)?;
+        let cc_json = serde_json::to_string(&note.cc_recipients)?;
+        let tags_json = serde_json::to_string(&note.tags)?;
+        let attachments_json = serde_json::to_string(&note.attachments)?;
+        let content_map_json = note
+            .content_map
+            .as_ref()
+            .map(serde_json::to_string)
+            .transpose()?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO notes (id, attributed_to, content, to_recipients, cc_recipients, published, in_reply_to, tags, attachments, visibility, state, pinned, created_at, in_reply_to_actor, thread_depth, thread_truncated, sensitive, summary, language, content_map)
+            VALUES ($1, $2, $3, $4::jsonb, $5::jsonb, $6, $7, $8::jsonb, $9::jsonb, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20::jsonb)
+            "#,
+        )
+        .bind(&note.id)
+        .bind(&note.attributed_to)
+        .bind(&note.content)
+        .bind(to_json)
+        .bind(cc_json)
+        .bind(note.published)
+        .bind(&note.in_reply_to)
+        .bind(tags_json)
+        .bind(attachments_json)
+        .bind(note.visibility.as_str())
+        .bind(note.state.as_str())
+        .bind(note.pinned)
+        .bind(note.created_at)
+        .bind(&note.in_reply_to_actor)
+        .bind(note.thread_depth.map(i64::from))
+        .bind(note.thread_truncated)
+        .bind(note.sensitive)
+        .bind(&note.summary)
+        .bind(&note.language)
+        .bind(content_map_json)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_note_by_id(&self, id: &str) -> Result<Option<DbNote>, DatabaseError> {
+        sqlx::query(&format!("SELECT {NOTE_COLUMNS} FROM notes WHERE id = $1"))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?
+            .as_ref()
+            .map(note_from_row)
+            .transpose()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_notes_by_ids(&self, ids: &[String]) -> Result<Vec<DbNote>, DatabaseError> {
+        if ids.is_empty() {
+            return Ok(vec![]);
+        }
+        sqlx::query(&format!(
+            "SELECT {NOTE_COLUMNS} FROM notes WHERE id = ANY($1) AND state = 'published'"
+        ))
+        .bind(ids)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(note_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_notes_by_actor(
+        &self,
+        actor_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {NOTE_COLUMNS} FROM notes WHERE attributed_to = $1 ORDER BY published DESC, id DESC LIMIT $2 OFFSET $3"
+        ))
+        .bind(actor_id)
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(note_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_notes_by_actor_before(
+        &self,
+        actor_id: &str,
+        before_published: DateTime<Utc>,
+        before_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {NOTE_COLUMNS} FROM notes WHERE attributed_to = $1 AND (published, id) < ($2, $3) ORDER BY published DESC, id DESC LIMIT $4"
+        ))
+        .bind(actor_id)
+        .bind(before_published)
+        .bind(before_id)
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(note_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_public_notes_by_actor(
+        &self,
+        actor_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {NOTE_COLUMNS} FROM notes WHERE attributed_to = $1 AND visibility = 'public' AND state = 'published' ORDER BY published DESC, id DESC LIMIT $2"
+        ))
+        .bind(actor_id)
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(note_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn count_notes_by_actor(
+        &self,
+        actor_id: &str,
+        include_private: bool,
+        include_local: bool,
+    ) -> Result<u32, DatabaseError> {
+        let row = sqlx::query(
+            "SELECT COUNT(*) FROM notes WHERE attributed_to = $1 AND state = 'published' AND ($2 OR visibility IN ('public', 'unlisted') OR ($3 AND visibility = 'local'))",
+        )
+        .bind(actor_id)
+        .bind(include_private)
+        .bind(include_local)
+        .fetch_one(&self.pool)
+        .await?;
+        count(&row)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_notes_by_language(
+        &self,
+        actor_id: &str,
+        language: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {NOTE_COLUMNS} FROM notes WHERE attributed_to = $1 AND (lower(language) = lower($2) OR EXISTS (SELECT 1 FROM jsonb_object_keys(notes.content_map) AS key WHERE lower(key) = lower($2))) ORDER BY published DESC LIMIT $3 OFFSET $4"
+        ))
+        .bind(actor_id)
+        .bind(language)
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(note_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_public_notes(
+        &self,
+        limit: u32,
+        before_id: Option<String>,
+        local_only: bool,
+        hide_sensitive: bool,
+        with_local_visibility: bool,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        sqlx::query(&format!(
+            r#"
+            SELECT {NOTE_COLUMNS}
+            FROM notes
+            WHERE (visibility = 'public' OR ($5 AND visibility = 'local')) AND state = 'published'
+              AND (NOT $1 OR attributed_to IN (SELECT id FROM actors))
+              AND ($2::text IS NULL OR (published, id) < (SELECT published, id FROM notes WHERE id = $2))
+              AND (NOT $4 OR NOT sensitive)
+            ORDER BY published DESC, id DESC
+            LIMIT $3
+            "#
+        ))
+        .bind(local_only)
+        .bind(before_id)
+        .bind(i64::from(limit))
+        .bind(hide_sensitive)
+        .bind(with_local_visibility)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(note_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn search_notes(
+        &self,
+        query: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        if query.split_whitespace().next().is_none() {
+            return Ok(vec![]);
+        }
+        // `plainto_tsquery` takes the words as they are, without operators
+        sqlx::query(&format!(
+            r#"
+            SELECT {NOTE_COLUMNS}
+            FROM notes
+            WHERE {NOTE_SEARCH_VECTOR} @@ plainto_tsquery('simple', $1)
+              AND visibility = 'public' AND state = 'published'
+            ORDER BY ts_rank({NOTE_SEARCH_VECTOR}, plainto_tsquery('simple', $1)) DESC, published DESC
+            LIMIT $2 OFFSET $3
+            "#
+        ))
+        .bind(query)
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(note_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_pinned_notes(&self, actor_id: &str) -> Result<Vec<DbNote>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {NOTE_COLUMNS} FROM notes WHERE attributed_to = $1 AND pinned AND state = 'published' ORDER BY published DESC"
+        ))
+        .bind(actor_id)
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(note_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn set_note_pinned(&self, id: &str, pinned: bool) -> Result<(), DatabaseError> {
+        let result = sqlx::query("UPDATE notes SET pinned = $1 WHERE id = $2")
+            .bind(pinned)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn search_notes_by_hashtag(
+        &self,
+        actor_id: &str,
+        hashtag: &str,
+        hide_sensitive: bool,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {NOTE_COLUMNS} FROM notes WHERE attributed_to = $1 AND visibility IN ('public', 'unlisted') AND state = 'published' AND (NOT $3 OR NOT sensitive) AND EXISTS (SELECT 1 FROM jsonb_array_elements_text(notes.tags) AS tag WHERE tag = $2) ORDER BY published DESC LIMIT $4 OFFSET $5"
+        ))
+        .bind(actor_id)
+        .bind(hashtag)
+        .bind(hide_sensitive)
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(note_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn count_notes_by_hashtag(
+        &self,
+        actor_id: &str,
+        hashtag: &str,
+        hide_sensitive: bool,
+    ) -> Result<u32, DatabaseError> {
+        let row = sqlx::query(
+            "SELECT COUNT(*) FROM notes WHERE attributed_to = $1 AND visibility IN ('public', 'unlisted') AND state = 'published' AND (NOT $3 OR NOT sensitive) AND EXISTS (SELECT 1 FROM jsonb_array_elements_text(notes.tags) AS tag WHERE tag = $2)",
+        )
+        .bind(actor_id)
+        .bind(hashtag)
+        .bind(hide_sensitive)
+        .fetch_one(&self.pool)
+        .await?;
+        count(&row)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_replies(
+        &self,
+        note_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {NOTE_COLUMNS} FROM notes WHERE in_reply_to = $1 AND visibility IN ('public', 'unlisted') AND state = 'published' ORDER BY published ASC LIMIT $2 OFFSET $3"
+        ))
+        .bind(note_id)
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(note_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn count_replies(&self, note_id: &str) -> Result<u32, DatabaseError> {
+        let row = sqlx::query(
+            "SELECT COUNT(*) FROM notes WHERE in_reply_to = $1 AND visibility IN ('public', 'unlisted') AND state = 'published'",
+        )
+        .bind(note_id)
+        .fetch_one(&self.pool)
+        .await?;
+        count(&row)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_thread(&self, note_id: &str, depth: u32) -> Result<Vec<DbNote>, DatabaseError> {
+        sqlx::query(&format!(
+            r#"
+            WITH RECURSIVE
+                ancestors(id, level) AS (
+                    SELECT in_reply_to, 1 FROM notes WHERE id = $1 AND in_reply_to IS NOT NULL
+                    UNION
+                    SELECT notes.in_reply_to, ancestors.level + 1 FROM notes
+                    JOIN ancestors ON notes.id = ancestors.id
+                    WHERE notes.in_reply_to IS NOT NULL AND ancestors.level < $2
+                ),
+                descendants(id, level) AS (
+                    SELECT id, 1 FROM notes WHERE in_reply_to = $1
+                    UNION
+                    SELECT notes.id, descendants.level + 1 FROM notes
+                    JOIN descendants ON notes.in_reply_to = descendants.id
+                    WHERE descendants.level < $2
+                )
+            SELECT {NOTE_COLUMNS}
+            FROM notes
+            WHERE id = $1 OR id IN (SELECT id FROM ancestors) OR id IN (SELECT id FROM descendants)
+            ORDER BY published ASC
+            "#
+        ))
+        .bind(note_id)
+        .bind(i64::from(depth))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(note_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn delete_note(&self, id: &str) -> Result<(), DatabaseError> {
+        sqlx::query("DELETE FROM notes WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self, follow), fields(follow_id = %follow.id))]
+    async fn create_follow(&self, follow: &DbFollowRelation) -> Result<(), DatabaseError> {
+        sqlx::query(
+            r#"
+            INSERT INTO follows (id, follower_id, following_id, status, created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(&follow.id)
+        .bind(&follow.follower_id)
+        .bind(&follow.following_id)
+        .bind(&follow.status)
+        .bind(follow.created_at)
+        .bind(follow.updated_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_follow_by_id(&self, id: &str) -> Result<Option<DbFollowRelation>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {FOLLOW_COLUMNS} FROM follows WHERE id = $1"
+        ))
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(follow_from_row)
+        .transpose()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_follow_by_actors(
+        &self,
+        follower_id: &str,
+        following_id: &str,
+    ) -> Result<Option<DbFollowRelation>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {FOLLOW_COLUMNS} FROM follows WHERE follower_id = $1 AND following_id = $2"
+        ))
+        .bind(follower_id)
+        .bind(following_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(follow_from_row)
+        .transpose()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn is_following_host(&self, host: &str) -> Result<bool, DatabaseError> {
+        let host = escape_like(host);
+        let row = sqlx::query(
+            r#"SELECT EXISTS (SELECT 1 FROM follows WHERE status = 'accepted' AND follower_id IN (SELECT id FROM actors) AND (following_id ILIKE $1 ESCAPE '\' OR following_id ILIKE $2 ESCAPE '\'))"#,
+        )
+        .bind(format!("https://{host}/%"))
+        .bind(format!("http://{host}/%"))
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row.try_get(0)?)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_followers(
+        &self,
+        actor_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbFollowRelation>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {FOLLOW_COLUMNS} FROM follows WHERE following_id = $1 AND status = 'accepted' ORDER BY created_at DESC LIMIT $2 OFFSET $3"
+        ))
+        .bind(actor_id)
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(follow_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_following(
+        &self,
+        actor_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbFollowRelation>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {FOLLOW_COLUMNS} FROM follows WHERE follower_id = $1 AND status = 'accepted' ORDER BY created_at DESC LIMIT $2 OFFSET $3"
+        ))
+        .bind(actor_id)
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(follow_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn update_follow_status(
+        &self,
+        follow_id: &str,
+        status: &str,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query("UPDATE follows SET status = $1, updated_at = $2 WHERE id = $3")
+            .bind(status)
+            .bind(Utc::now())
+            .bind(follow_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn delete_follow(&self, id: &str) -> Result<(), DatabaseError> {
+        sqlx::query("DELETE FROM follows WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_pending_follows(
+        &self,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbFollowRelation>, DatabaseError> {
+        sqlx::query(&format!(
+            "SELECT {FOLLOW_COLUMNS} FROM follows WHERE status = 'pending' ORDER BY created_at ASC LIMIT $1 OFFSET $2"
+        ))
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(follow_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_followers_with_profiles(
+        &self,
+        actor_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbActorSummary>, DatabaseError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT f.follower_id,
+                   COALESCE(a.username, r.username) AS username,
+                   COALESCE(a.name, r.name) AS name,
+                   r.avatar_url,
+                   a.id IS NOT NULL AS local
+            FROM follows f
+            LEFT JOIN actors a ON a.id = f.follower_id
+            LEFT JOIN remote_actors r ON r.id = f.follower_id
+            WHERE f.following_id = $1 AND f.status = 'accepted'
+            ORDER BY f.created_at DESC
+            LIMIT $2 OFFSET $3
+            "#,
+        )
+        .bind(actor_id)
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|r| {
+                Ok(DbActorSummary {
+                    id: r.try_get("follower_id")?,
+                    username: r.try_get("username")?,
+                    name: r.try_get("name")?,
+                    avatar_url: r.try_get("avatar_url")?,
+                    local: r.try_get("local")?,
+                })
+            })
+            .collect()
+    }
+
+    #[instrument(level = "debug", skip(self, actor), fields(actor_id = %actor.id))]
+    async fn upsert_remote_actor(&self, actor: &DbRemoteActor) -> Result<(), DatabaseError> {
+        sqlx::query(
+            r#"
+            INSERT INTO remote_actors (id, username, name, avatar_url, inbox, shared_inbox, public_key_pem, fetched_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            ON CONFLICT (id) DO UPDATE SET
+                username = EXCLUDED.username,
+                name = EXCLUDED.name,
+                avatar_url = EXCLUDED.avatar_url,
+                inbox = EXCLUDED.inbox,
+                shared_inbox = EXCLUDED.shared_inbox,
+                public_key_pem = EXCLUDED.public_key_pem,
+                fetched_at = EXCLUDED.fetched_at
+            "#,
+        )
+        .bind(&actor.id)
+        .bind(&actor.username)
+        .bind(&actor.name)
+        .bind(&actor.avatar_url)
+        .bind(&actor.inbox)
+        .bind(&actor.shared_inbox)
+        .bind(&actor.public_key_pem)
+        .bind(actor.fetched_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_remote_actor(&self, id: &str) -> Result<Option<DbRemoteActor>, DatabaseError> {
+        let row = sqlx::query(
+            "SELECT id, username, name, avatar_url, inbox, shared_inbox, public_key_pem, fetched_at FROM remote_actors WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|r| {
+            Ok(DbRemoteActor {
+                id: r.try_get("id")?,
+                username: r.try_get("username")?,
+                name: r.try_get("name")?,
+                avatar_url: r.try_get("avatar_url")?,
+                inbox: r.try_get("inbox")?,
+                shared_inbox: r.try_get("shared_inbox")?,
+                public_key_pem: r.try_get("public_key_pem")?,
+                fetched_at: r.try_get("fetched_at")?,
+            })
+        })
+        .transpose()
+    }
+
+    #[instrument(level = "debug", skip(self, failure), fields(failure_id = %failure.id))]
+    async fn create_parse_failure(&self, failure: &DbParseFailure) -> Result<(), DatabaseError> {
+        sqlx::query(
+            r#"
+            INSERT INTO parse_failures (id, kind, url, error_path, error, document, truncated, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            "#,
+        )
+        .bind(&failure.id)
+        .bind(&failure.kind)
+        .bind(&failure.url)
+        .bind(&failure.error_path)
+        .bind(&failure.error)
+        .bind(&failure.document)
+        .bind(failure.truncated)
+        .bind(failure.created_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_parse_failures(
+        &self,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbParseFailure>, DatabaseError> {
+        sqlx::query(
+            r#"
+            SELECT id, kind, url, error_path, error, document, truncated, created_at
+            FROM parse_failures
+            ORDER BY created_at DESC, id
+            LIMIT $1 OFFSET $2
+            "#,
+        )
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(parse_failure_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_parse_failure(&self, id: &str) -> Result<Option<DbParseFailure>, DatabaseError> {
+        sqlx::query(
+            r#"
+            SELECT id, kind, url, error_path, error, document, truncated, created_at
+            FROM parse_failures
+            WHERE id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(parse_failure_from_row)
+        .transpose()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn delete_parse_failures_older_than(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64, DatabaseError> {
+        let result = sqlx::query("DELETE FROM parse_failures WHERE created_at < $1")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    #[instrument(level = "debug", skip(self, trace), fields(activity_id = %trace.activity_id))]
+    async fn save_processing_trace(&self, trace: &DbProcessingTrace) -> Result<(), DatabaseError> {
+        let stages_json = serde_json::to_string(&trace.stages)?;
+        sqlx::query(
+            r#"
+            INSERT INTO processing_traces (activity_id, activity_type, stages, created_at)
+            VALUES ($1, $2, $3::jsonb, $4)
+            ON CONFLICT (activity_id) DO UPDATE SET
+                activity_type = EXCLUDED.activity_type,
+                stages = EXCLUDED.stages,
+                created_at = EXCLUDED.created_at
+            WHERE jsonb_array_length(EXCLUDED.stages) >= jsonb_array_length(processing_traces.stages)
+            "#,
+        )
+        .bind(&trace.activity_id)
+        .bind(&trace.activity_type)
+        .bind(stages_json)
+        .bind(trace.created_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_processing_trace(
+        &self,
+        activity_id: &str,
+    ) -> Result<Option<DbProcessingTrace>, DatabaseError> {
+        let row = sqlx::query(
+            r#"
+            SELECT activity_id, activity_type, stages::text AS stages, created_at
+            FROM processing_traces
+            WHERE activity_id = $1
+            "#,
+        )
+        .bind(activity_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|r| {
+            Ok(DbProcessingTrace {
+                activity_id: r.try_get("activity_id")?,
+                activity_type: r.try_get("activity_type")?,
+                stages: serde_json::from_str(r.try_get("stages")?)?,
+                created_at: r.try_get("created_at")?,
+            })
+        })
+        .transpose()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn delete_processing_traces_older_than(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64, DatabaseError> {
+        let result = sqlx::query("DELETE FROM processing_traces WHERE created_at < $1")
+            .bind(cutoff)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    #[instrument(level = "debug", skip(self, block), fields(block_id = %block.id))]
+    async fn create_block(&self, block: &DbBlock) -> Result<(), DatabaseError> {
+        sqlx::query(
+            r#"
+            INSERT INTO blocks (id, blocker_id, blocked_id, created_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (blocker_id, blocked_id) DO NOTHING
+            "#,
+        )
+        .bind(&block.id)
+        .bind(&block.blocker_id)
+        .bind(&block.blocked_id)
+        .bind(block.created_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn delete_block(&self, blocker_id: &str, blocked_id: &str) -> Result<(), DatabaseError> {
+        sqlx::query("DELETE FROM blocks WHERE blocker_id = $1 AND blocked_id = $2")
+            .bind(blocker_id)
+            .bind(blocked_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn is_blocked(&self, blocker_id: &str, blocked_id: &str) -> Result<bool, DatabaseError> {
+        let row = sqlx::query(
+            "SELECT EXISTS (SELECT 1 FROM blocks WHERE blocker_id = $1 AND blocked_id = $2)",
+        )
+        .bind(blocker_id)
+        .bind(blocked_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(row.try_get(0)?)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn list_blocks(
+        &self,
+        blocker_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbBlock>, DatabaseError> {
+        let rows = sqlx::query(
+            "SELECT id, blocker_id, blocked_id, created_at FROM blocks WHERE blocker_id = $1 ORDER BY created_at DESC LIMIT $2 OFFSET $3",
+        )
+        .bind(blocker_id)
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|r| {
+                Ok(DbBlock {
+                    id: r.try_get("id")?,
+                    blocker_id: r.try_get("blocker_id")?,
+                    blocked_id: r.try_get("blocked_id")?,
+                    created_at: r.try_get("created_at")?,
+                })
+            })
+            .collect()
+    }
+
+    #[instrument(level = "debug", skip(self, relay), fields(relay_id = %relay.id))]
+    async fn create_relay(&self, relay: &DbRelay) -> Result<(), DatabaseError> {
+        sqlx::query(
+            r#"
+            INSERT INTO relays (id, inbox_url, actor_id, follow_id, status, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            "#,
+        )
+        .bind(&relay.id)
+        .bind(&relay.inbox_url)
+        .bind(&relay.actor_id)
+        .bind(&relay.follow_id)
+        .bind(&relay.status)
+        .bind(relay.created_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_relay(&self, id: &str) -> Result<Option<DbRelay>, DatabaseError> {
+        sqlx::query(
+            "SELECT id, inbox_url, actor_id, follow_id, status, created_at FROM relays WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(relay_from_row)
+        .transpose()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn list_relays(&self) -> Result<Vec<DbRelay>, DatabaseError> {
+        sqlx::query(
+            "SELECT id, inbox_url, actor_id, follow_id, status, created_at FROM relays ORDER BY created_at, id",
+        )
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(relay_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn update_relay_status(&self, id: &str, status: &str) -> Result<(), DatabaseError> {
+        sqlx::query("UPDATE relays SET status = $1 WHERE id = $2")
+            .bind(status)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn delete_relay(&self, id: &str) -> Result<(), DatabaseError> {
+        sqlx::query("DELETE FROM relays WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self, like), fields(like_id = %like.id))]
+    async fn create_like(&self, like: &DbLike) -> Result<(), DatabaseError> {
+        sqlx::query(
+            r#"
+            INSERT INTO likes (id, actor_id, object_id, created_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (actor_id, object_id) DO NOTHING
+            "#,
+        )
+        .bind(&like.id)
+        .bind(&like.actor_id)
+        .bind(&like.object_id)
+        .bind(like.created_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_like(
+        &self,
+        actor_id: &str,
+        object_id: &str,
+    ) -> Result<Option<DbLike>, DatabaseError> {
+        sqlx::query(
+            "SELECT id, actor_id, object_id, created_at FROM likes WHERE actor_id = $1 AND object_id = $2",
+        )
+        .bind(actor_id)
+        .bind(object_id)
+        .fetch_optional(&self.pool)
+        .await?
+        .as_ref()
+        .map(like_from_row)
+        .transpose()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn delete_like(&self, actor_id: &str, object_id: &str) -> Result<(), DatabaseError> {
+        sqlx::query("DELETE FROM likes WHERE actor_id = $1 AND object_id = $2")
+            .bind(actor_id)
+            .bind(object_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_likes(
+        &self,
+        object_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbLike>, DatabaseError> {
+        sqlx::query(
+            "SELECT id, actor_id, object_id, created_at FROM likes WHERE object_id = $1 ORDER BY created_at ASC LIMIT $2 OFFSET $3",
+        )
+        .bind(object_id)
+        .bind(i64::from(limit))
+        .bind(i64::from(offset))
+        .fetch_all(&self.pool)
+        .await?
+        .iter()
+        .map(like_from_row)
+        .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn count_likes(&self, object_id: &str) -> Result<u32, DatabaseError> {
+        let row = sqlx::query("SELECT COUNT(*) FROM likes WHERE object_id = $1")
+            .bind(object_id)
+            .fetch_one(&self.pool)
+            .await?;
+        count(&row)
+    }
+
+    #[instrument(level = "debug", skip(self, pending), fields(pending_id = %pending.id))]
+    async fn create_pending_accept(&self, pending: &DbPendingAccept) -> Result<(), DatabaseError> {
+        let activity_json = serde_json::to_string(&pending.activity)?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO pending_accepts (id, activity_type, follower_id, following_id, activity, created_at, expires_at)
+            VALUES ($1, $2, $3, $4, $5::jsonb, $6, $7)
+            "#,
+        )
+        .bind(&pending.id)
+        .bind(&pending.activity_type)
+        .bind(&pending.follower_id)
+        .bind(&pending.following_id)
+        .bind(activity_json)
+        .bind(pending.created_at)
+        .bind(pending.expires_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_pending_accepts(
+        &self,
+        follower_id: &str,
+        following_id: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<DbPendingAccept>, DatabaseError> {
+        let rows = sqlx::query(
+            "SELECT id, activity_type, follower_id, following_id, activity::text AS activity, created_at, expires_at FROM pending_accepts WHERE follower_id = $1 AND following_id = $2 AND expires_at > $3 ORDER BY created_at ASC",
+        )
+        .bind(follower_id)
+        .bind(following_id)
+        .bind(now)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|r| {
+                Ok(DbPendingAccept {
+                    id: r.try_get("id")?,
+                    activity_type: r.try_get("activity_type")?,
+                    follower_id: r.try_get("follower_id")?,
+                    following_id: r.try_get("following_id")?,
+                    activity: serde_json::from_str(r.try_get("activity")?)?,
+                    created_at: r.try_get("created_at")?,
+                    expires_at: r.try_get("expires_at")?,
+                })
+            })
+            .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn delete_pending_accept(&self, id: &str) -> Result<(), DatabaseError> {
+        sqlx::query("DELETE FROM pending_accepts WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn delete_expired_pending_accepts(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<u64, DatabaseError> {
+        let result = sqlx::query("DELETE FROM pending_accepts WHERE expires_at <= $1")
+            .bind(now)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    #[instrument(level = "debug", skip(self, delivery), fields(delivery_id = %delivery.id))]
+    async fn enqueue_delivery(&self, delivery: &DbDelivery) -> Result<(), DatabaseError> {
+        let activity_json = serde_json::to_string(&delivery.activity)?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO delivery_queue (id, inbox_url, activity, priority, attempts, next_attempt_at, last_error, created_at)
+            VALUES ($1, $2, $3::jsonb, $4, $5, $6, $7, $8)
+            "#,
+        )
+        .bind(&delivery.id)
+        .bind(&delivery.inbox_url)
+        .bind(activity_json)
+        .bind(delivery.priority.as_str())
+        .bind(i64::from(delivery.attempts))
+        .bind(delivery.next_attempt_at)
+        .bind(&delivery.last_error)
+        .bind(delivery.created_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_due_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        priority: DeliveryPriority,
+        limit: u32,
+    ) -> Result<Vec<DbDelivery>, DatabaseError> {
+        let rows = sqlx::query(
+            "SELECT id, inbox_url, activity::text AS activity, priority, attempts, next_attempt_at, last_error, created_at FROM delivery_queue WHERE next_attempt_at <= $1 AND priority = $2 ORDER BY next_attempt_at ASC, created_at ASC LIMIT $3",
+        )
+        .bind(now)
+        .bind(priority.as_str())
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|r| {
+                Ok(DbDelivery {
+                    id: r.try_get("id")?,
+                    inbox_url: r.try_get("inbox_url")?,
+                    activity: serde_json::from_str(r.try_get("activity")?)?,
+                    priority: r
+                        .try_get::<&str, _>("priority")?
+                        .parse()
+                        .map_err(DatabaseError::InvalidData)?,
+                    attempts: r.try_get::<i64, _>("attempts")? as u32,
+                    next_attempt_at: r.try_get("next_attempt_at")?,
+                    last_error: r.try_get("last_error")?,
+                    created_at: r.try_get("created_at")?,
+                })
+            })
+            .collect()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn delete_delivery(&self, id: &str) -> Result<(), DatabaseError> {
+        sqlx::query("DELETE FROM delivery_queue WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn reschedule_delivery(
+        &self,
+        id: &str,
+        attempts: u32,
+        next_attempt_at: DateTime<Utc>,
+        last_error: &str,
+    ) -> Result<(), DatabaseError> {
+        let result = sqlx::query(
+            "UPDATE delivery_queue SET attempts = $1, next_attempt_at = $2, last_error = $3 WHERE id = $4",
+        )
+        .bind(i64::from(attempts))
+        .bind(next_attempt_at)
+        .bind(last_error)
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(DatabaseError::NotFound);
+        }
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn count_pending_deliveries(&self) -> Result<u32, DatabaseError> {
+        let row = sqlx::query("SELECT COUNT(*) FROM delivery_queue")
+            .fetch_one(&self.pool)
+            .await?;
+        count(&row)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn count_pending_by_priority(
+        &self,
+    ) -> Result<Vec<(DeliveryPriority, u32)>, DatabaseError> {
+        let rows = sqlx::query(
+            "SELECT priority, COUNT(*) AS pending FROM delivery_queue GROUP BY priority",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut counts = rows
+            .iter()
+            .map(|r| -> Result<(DeliveryPriority, u32), DatabaseError> {
+                Ok((
+                    r.try_get::<&str, _>("priority")?
+                        .parse()
+                        .map_err(DatabaseError::InvalidData)?,
+                    r.try_get::<i64, _>("pending")? as u32,
+                ))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        counts
+            .sort_by_key(|(priority, _)| DeliveryPriority::ALL.iter().position(|p| p == priority));
+        Ok(counts)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn oldest_pending_age(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Option<DbOldestDelivery>, DatabaseError> {
+        let row = sqlx::query(
+            "SELECT inbox_url, created_at FROM delivery_queue ORDER BY created_at ASC LIMIT 1",
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|r| {
+            Ok(DbOldestDelivery {
+                inbox_url: r.try_get("inbox_url")?,
+                age: now - r.try_get::<DateTime<Utc>, _>("created_at")?,
+            })
+        })
+        .transpose()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn pending_by_host(&self, limit: u32) -> Result<Vec<DbHostBacklog>, DatabaseError> {
+        let rows = sqlx::query(
+            r#"
+            SELECT
+                split_part(split_part(inbox_url, '://', 2), '/', 1) AS host,
+                COUNT(*) AS pending,
+                MIN(created_at) AS oldest
+            FROM delivery_queue
+            GROUP BY 1
+            ORDER BY pending DESC, split_part(split_part(inbox_url, '://', 2), '/', 1) COLLATE "C" ASC
+            LIMIT $1
+            "#,
+        )
+        .bind(i64::from(limit))
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|r| {
+                Ok(DbHostBacklog {
+                    host: r.try_get("host")?,
+                    pending: r.try_get::<i64, _>("pending")? as u32,
+                    oldest: r.try_get("oldest")?,
+                })
+            })
+            .collect()
+    }
+
+    #[instrument(level = "debug", skip(self, labels), fields(labels = labels.len()))]
+    async fn add_note_labels(
+        &self,
+        note_id: &str,
+        labels: &[DbNoteLabel],
+    ) -> Result<(), DatabaseError> {
+        let now = Utc::now();
+        let mut tx = self.pool.begin().await?;
+        for label in labels {
+            sqlx::query(
+                r#"
+                INSERT INTO note_labels (note_id, label, applied, created_at)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT (note_id, label) DO UPDATE SET applied = EXCLUDED.applied
+                "#,
+            )
+            .bind(note_id)
+            .bind(&label.label)
+            .bind(label.applied)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_note_labels(&self, note_id: &str) -> Result<Vec<DbNoteLabel>, DatabaseError> {
+        let rows = sqlx::query(
+            r#"SELECT label, applied FROM note_labels WHERE note_id = $1 ORDER BY label COLLATE "C" ASC"#,
+        )
+        .bind(note_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|r| {
+                Ok(DbNoteLabel {
+                    label: r.try_get("label")?,
+                    applied: r.try_get("applied")?,
+                })
+            })
+            .collect()
+    }
+
+    #[instrument(level = "debug", skip(self, usage), fields(rows = usage.len()))]
+    async fn replace_inbound_usage(&self, usage: &[DbInboundUsage]) -> Result<(), DatabaseError> {
+        let mut tx = self.pool.begin().await?;
+
+        sqlx::query("DELETE FROM inbound_usage")
+            .execute(&mut *tx)
+            .await?;
+        for row in usage {
+            let bytes = i64::try_from(row.bytes).unwrap_or(i64::MAX);
+            sqlx::query("INSERT INTO inbound_usage (host, minute, bytes) VALUES ($1, $2, $3)")
+                .bind(&row.host)
+                .bind(row.minute)
+                .bind(bytes)
+                .execute(&mut *tx)
+                .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_inbound_usage(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<DbInboundUsage>, DatabaseError> {
+        let rows = sqlx::query(
+            "SELECT host, minute, bytes FROM inbound_usage WHERE minute >= $1 ORDER BY minute ASC",
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|r| {
+                Ok(DbInboundUsage {
+                    host: r.try_get("host")?,
+                    minute: r.try_get("minute")?,
+                    bytes: r.try_get::<i64, _>("bytes")?.max(0) as u64,
+                })
+            })
+            .collect()
+    }
+
+    #[instrument(level = "debug", skip(self, token), fields(token_id = %token.id))]
+    async fn create_token(&self, token: &DbToken) -> Result<(), DatabaseError> {
+        let scopes_json = serde_json::to_string(&token.scopes)?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO tokens (id, token_hash, actor_id, scopes, created_at, expires_at)
+            VALUES ($1, $2, $3, $4::jsonb, $5, $6)
+            "#,
+        )
+        .bind(&token.id)
+        .bind(&token.token_hash)
+        .bind(&token.actor_id)
+        .bind(scopes_json)
+        .bind(token.created_at)
+        .bind(token.expires_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip_all)]
+    async fn validate_token(&self, token: &str) -> Result<Option<DbToken>, DatabaseError> {
+        let token_hash = crate::auth::hash_token(token);
+
+        let row = sqlx::query(
+            "SELECT id, token_hash, actor_id, scopes::text AS scopes, created_at, expires_at FROM tokens WHERE token_hash = $1 AND (expires_at IS NULL OR expires_at > $2)",
+        )
+        .bind(token_hash)
+        .bind(Utc::now())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        row.map(|r| {
+            Ok(DbToken {
+                id: r.try_get("id")?,
+                token_hash: r.try_get("token_hash")?,
+                actor_id: r.try_get("actor_id")?,
+                scopes: serde_json::from_str(r.try_get("scopes")?)?,
+                created_at: r.try_get("created_at")?,
+                expires_at: r.try_get("expires_at")?,
+            })
+        })
+        .transpose()
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_preferences(&self, actor_id: &str) -> Result<DbPreferences, DatabaseError> {
+        let row = sqlx::query(
+            "SELECT expand_sensitive, auto_content_warnings FROM actor_preferences WHERE actor_id = $1",
+        )
+        .bind(actor_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row
+            .map(|r| -> Result<DbPreferences, DatabaseError> {
+                Ok(DbPreferences {
+                    expand_sensitive: r.try_get("expand_sensitive")?,
+                    auto_content_warnings: r.try_get("auto_content_warnings")?,
+                })
+            })
+            .transpose()?
+            .unwrap_or_default())
+    }
+
+    #[instrument(level = "debug", skip(self, preferences))]
+    async fn set_preferences(
+        &self,
+        actor_id: &str,
+        preferences: &DbPreferences,
+    ) -> Result<(), DatabaseError> {
+        sqlx::query(
+            r#"
+            INSERT INTO actor_preferences (actor_id, expand_sensitive, auto_content_warnings)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (actor_id) DO UPDATE SET
+                expand_sensitive = EXCLUDED.expand_sensitive,
+                auto_content_warnings = EXCLUDED.auto_content_warnings
+            "#,
+        )
+        .bind(actor_id)
+        .bind(preferences.expand_sensitive)
+        .bind(preferences.auto_content_warnings)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_actor_outbox_count(&self, actor_id: &str) -> Result<u32, DatabaseError> {
+        let row = sqlx::query(
+            "SELECT COUNT(*) FROM activities WHERE actor_id = $1 AND state = 'published'",
+        )
+        .bind(actor_id)
+        .fetch_one(&self.pool)
+        .await?;
+        count(&row)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_actor_public_outbox_count(&self, actor_id: &str) -> Result<u32, DatabaseError> {
+        let row = sqlx::query(
+            "SELECT COUNT(*) FROM activities WHERE actor_id = $1 AND visibility IN ('public', 'unlisted') AND state = 'published'",
+        )
+        .bind(actor_id)
+        .fetch_one(&self.pool)
+        .await?;
+        count(&row)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_actor_inbox_count(&self, actor_id: &str) -> Result<u32, DatabaseError> {
+        let row = sqlx::query(
+            "SELECT COUNT(*) FROM activities WHERE id IN (SELECT activity_id FROM activity_recipients WHERE recipient_url = $1) AND state = 'published'",
+        )
+        .bind(actor_id)
+        .fetch_one(&self.pool)
+        .await?;
+        count(&row)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_actor_followers_count(&self, actor_id: &str) -> Result<u32, DatabaseError> {
+        let row = sqlx::query(
+            "SELECT COUNT(*) FROM follows WHERE following_id = $1 AND status = 'accepted'",
+        )
+        .bind(actor_id)
+        .fetch_one(&self.pool)
+        .await?;
+        count(&row)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_actor_following_count(&self, actor_id: &str) -> Result<u32, DatabaseError> {
+        let row = sqlx::query(
+            "SELECT COUNT(*) FROM follows WHERE follower_id = $1 AND status = 'accepted'",
+        )
+        .bind(actor_id)
+        .fetch_one(&self.pool)
+        .await?;
+        count(&row)
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn get_instance_stats(&self) -> Result<DbInstanceStats, DatabaseError> {
+        let row = sqlx::query(
+            r#"
+            SELECT
+                (SELECT COUNT(*) FROM actors) AS user_count,
+                (SELECT COUNT(*) FROM notes
+                 WHERE state = 'published' AND attributed_to IN (SELECT id FROM actors)) AS status_count,
+                (SELECT COUNT(DISTINCT split_part(split_part(id, '://', 2), '/', 1))
+                 FROM remote_actors) AS domain_count
+            "#,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(DbInstanceStats {
+            user_count: row.try_get::<i64, _>("user_count")? as u32,
+            status_count: row.try_get::<i64, _>("status_count")? as u32,
+            domain_count: row.try_get::<i64, _>("domain_count")? as u32,
+        })
+    }
+
+    #[instrument(level = "debug", skip(self))]
+    async fn ping(&self) -> Result<(), DatabaseError> {
+        sqlx::query("SELECT 1").fetch_one(&self.pool).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,104 @@
+use crate::config::Config;
+use crate::database::{DatabaseError, DatabaseRef, DbRemoteActor};
+use crate::http::HttpClient;
+use crate::services::actor_profiles;
+use crate::services::parse_failures::ParseFailureRecorder;
+use crate::services::scheduler::{Clock, SystemClock};
+use anyhow::Result;
+use chrono::Duration;
+use std::sync::Arc;
+use tracing::{debug, warn};
+
+/// Remote actor documents, fetched on first use and kept in `remote_actors`.
+///
+/// A cached actor is used for the TTL and fetched again after that. When the
+/// refetch fails the stale copy is still returned, since a server that is
+/// briefly down hasn't stopped existing.
+pub struct ActorFetchService {
+    db: DatabaseRef,
+    http_client: Arc<dyn HttpClient>,
+    parse_failures: ParseFailureRecorder,
+    clock: Arc<dyn Clock>,
+    ttl: Duration,
+}
+
+impl ActorFetchService {
+    pub fn new(
+        db: DatabaseRef,
+        http_client: Arc<dyn HttpClient>,
+        parse_failures: ParseFailureRecorder,
+        clock: Arc<dyn Clock>,
+        ttl: Duration,
+    ) -> Self {
+        Self {
+            db,
+            http_client,
+            parse_failures,
+            clock,
+            ttl,
+        }
+    }
+
+    /// Actors kept for `remote_actor_ttl_secs` on the system clock
+    pub fn from_config(
+        config: &Config,
+        db: DatabaseRef,
+        http_client: Arc<dyn HttpClient>,
+        parse_failures: ParseFailureRecorder,
+    ) -> Self {
+        Self::new(
+            db,
+            http_client,
+            parse_failures,
+            Arc::new(SystemClock),
+            Duration::seconds(config.remote_actor_ttl_secs as i64),
+        )
+    }
+
+    /// The actor at `actor_id`, from the cache while it is fresh and fetched
+    /// otherwise
+    pub async fn get_or_fetch(&self, actor_id: &str) -> Result<DbRemoteActor> {
+        let cached = self.db.get_remote_actor(actor_id).await?;
+        let now = self.clock.now();
+        if let Some(actor) = cached.as_ref().filter(|a| now - a.fetched_at < self.ttl) {
+            debug!("Using cached actor {}", actor_id);
+            return Ok(actor.clone());
+        }
+
+        match actor_profiles::fetch_remote_actor(
+            self.http_client.as_ref(),
+            &self.parse_failures,
+            actor_id,
+        )
+        .await
+        {
+            Ok(mut actor) => {
+                actor.fetched_at = now;
+                self.db.upsert_remote_actor(&actor).await?;
+                Ok(actor)
+            }
+            Err(e) => match cached {
+                Some(stale) => {
+                    warn!("Using stale copy of {} after: {:#}", actor_id, e);
+                    Ok(stale)
+                }
+                None => Err(e),
+            },
+        }
+    }
+
+    /// `get_or_fetch`, with an actor that can't be fetched given as `None`
+    /// so only database errors remain
+    pub async fn find(&self, actor_id: &str) -> Result<Option<DbRemoteActor>, DatabaseError> {
+        match self.get_or_fetch(actor_id).await {
+            Ok(actor) => Ok(Some(actor)),
+            Err(e) => match e.downcast::<DatabaseError>() {
+                Ok(e) => Err(e),
+                Err(e) => {
+                    debug!("Could not fetch actor {}: {:#}", actor_id, e);
+                    Ok(None)
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use crate::database::{DatabaseError, DatabaseRef, DbActorSummary, DbRemoteActor};
+use crate::http::client::{HttpClient, HttpRequest};
+use crate::services::parse_failures::ParseFailureRecorder;
+use crate::urls::host_of;
+use anyhow::{bail, Context, Result};
+use futures::stream::{self, StreamExt};
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+use tracing::{debug, warn};
+
+/// Profile fetches in flight at once, across every listing that asked for
+/// them, so a burst of follower listings can't flood remote servers
+const MAX_PROFILE_FETCHES: usize = 8;
+
+static PROFILE_FETCHES: Semaphore = Semaphore::const_new(MAX_PROFILE_FETCHES);
+
+/// Followers of `actor_id` with rendered profile data. Remote followers whose
+/// profile isn't cached yet are returned bare and fetched in the background,
+/// so the next listing can show them properly.
+pub async fn followers_with_profiles(
+    db: &DatabaseRef,
+    http_client: &Arc<dyn HttpClient>,
+    parse_failures: &ParseFailureRecorder,
+    actor_id: &str,
+    limit: u32,
+    offset: u32,
+) -> Result<Vec<DbActorSummary>, DatabaseError> {
+    let followers = db
+        .get_followers_with_profiles(actor_id, limit, offset)
+        .await?;
+
+    let uncached: Vec<String> = followers
+        .iter()
+        .filter(|summary| !summary.local && summary.username.is_none())
+        .map(|summary| summary.id.clone())
+        .collect();
+    if !uncached.is_empty() {
+        tokio::spawn(cache_profiles(
+            db.clone(),
+            http_client.clone(),
+            parse_failures.clone(),
+            uncached,
+        ));
+    }
+
+    Ok(followers)
+}
+
+/// Fetch and cache each remote actor profile, logging failures. At most
+/// `MAX_PROFILE_FETCHES` fetches run at a time.
+pub async fn cache_profiles(
+    db: DatabaseRef,
+    http_client: Arc<dyn HttpClient>,
+    parse_failures: ParseFailureRecorder,
+    ids: Vec<String>,
+) {
+    stream::iter(ids)
+        .for_each_concurrent(MAX_PROFILE_FETCHES, |id| {
+            let db = &db;
+            let http_client = &http_client;
+            let parse_failures = &parse_failures;
+            async move {
+                let Ok(_permit) = PROFILE_FETCHES.acquire().await else {
+                    return;
+                };
+                match fetch_remote_actor(http_client.as_ref(), parse_failures, &id).await {
+                    Ok(actor) => {
+                        if let Err(e) = db.upsert_remote_actor(&actor).await {
+                            warn!("Could not cache profile of {}: {}", id, e);
+                        }
+                    }
+                    Err(e) => debug!("Could not fetch profile of {}: {}", id, e),
+                }
+            }
+        })
+        .await;
+}
+
+/// The parts of a remote actor document we cache
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ActorDocument {
+    id: Option<String>,
+    preferred_username: String,
+    name: Option<String>,
+    /// A single Image, a list of them, or a bare link
+    icon: Option<Value>,
+    inbox: Option<String>,
+    endpoints: Option<ActorEndpoints>,
+    public_key: Option<ActorPublicKey>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ActorEndpoints {
+    shared_inbox: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ActorPublicKey {
+    public_key_pem: String,
+}
+
+/// Dereference a remote actor document into a cacheable profile. Documents
+/// of the wrong shape are recorded with `parse_failures`, and ones naming a
+/// different actor are refused.
+pub async fn fetch_remote_actor(
+    http_client: &dyn HttpClient,
+    parse_failures: &ParseFailureRecorder,
+    id: &str,
+) -> Result<DbRemoteActor> {
+    let request = HttpRequest::new("GET", id).with_header("Accept", "application/activity+json");
+    let response = http_client
+        .send(request)
+        .await
+        .with_context(|| format!("Actor request to {id} failed"))?;
+    if !response.status().is_success() {
+        bail!("Actor {id} returned {}", response.status().0);
+    }
+
+    let document: Value = response
+        .json()
+        .with_context(|| format!("Invalid actor document from {id}"))?;
+    let actor: ActorDocument = parse_failures.parse("actor", id, document).await?;
+    // A document for some other actor must not be cached under this id
+    if let Some(document_id) = actor.id.as_deref().filter(|document_id| *document_id != id) {
+        bail!("Actor document from {id} is for {document_id}");
+    }
+
+    Ok(DbRemoteActor {
+        id: id.to_string(),
+        username: actor.preferred_username,
+        name: actor.name,
+        avatar_url: actor.icon.as_ref().and_then(icon_url),
+        inbox: actor.inbox,
+        shared_inbox: actor.endpoints.and_then(|e| e.shared_inbox),
+        public_key_pem: actor.public_key.map(|key| key.public_key_pem),
+        fetched_at: chrono::Utc::now(),
+    })
+}
+
+/// `icon` may be a single Image, a list of them, or a bare link
+fn icon_url(icon: &Value) -> Option<String> {
+    let icon = match icon {
+        Value::Array(icons) => icons.first()?,
+        other => other,
+    };
+    match icon {
+        Value::String(url) => Some(url.clone()),
+        other => other.get("url")?.as_str().map(String::from),
+    }
+}
+
+/// `user@host` for remote actors and the bare username for local ones. Falls
+/// back to the last path segment of the id when the profile isn't cached.
+pub fn acct(summary: &DbActorSummary) -> String {
+    let username = summary
+        .username
+        .clone()
+        .unwrap_or_else(|| last_segment(&summary.id).to_string());
+    if summary.local {
+        return username;
+    }
+    match host_of(&summary.id) {
+        Some(host) => format!("{username}@{host}"),
+        None => username,
+    }
+}
+
+fn last_segment(id: &str) -> &str {
+    id.trim_end_matches('/').rsplit('/').next().unwrap_or(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::MockDatabase;
+    use crate::http::client::{HttpResponse, StatusCode};
+    use async_trait::async_trait;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    /// Answers every actor request after a pause, tracking how many overlap
+    #[derive(Default)]
+    struct SlowClient {
+        in_flight: AtomicUsize,
+        most_in_flight: AtomicUsize,
+        requests: AtomicUsize,
+    }
+
+    #[async_trait]
+    impl HttpClient for SlowClient {
+        async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+            let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.most_in_flight.fetch_max(now, Ordering::SeqCst);
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            let document = json!({"id": request.url, "preferredUsername": "someone"});
+            Ok(HttpResponse {
+                status: StatusCode(200),
+                headers: HashMap::new(),
+                body: serde_json::to_vec(&document)?,
+            })
+        }
+    }
+
+    fn summary(id: &str, username: Option<&str>, local: bool) -> DbActorSummary {
+        DbActorSummary {
+            id: id.to_string(),
+            username: username.map(String::from),
+            name: None,
+            avatar_url: None,
+            local,
+        }
+    }
+
+    #[test]
+    fn test_icon_url_accepts_image_list_and_link() {
+        let image = json!({"type": "Image", "url": "https://r.example/a.png"});
+        let list = json!([{"type": "Image", "url": "https://r.example/b.png"}]);
+        let link = json!("https://r.example/c.png");
+
+        assert_eq!(icon_url(&image).as_deref(), Some("https://r.example/a.png"));
+        assert_eq!(icon_url(&list).as_deref(), Some("https://r.example/b.png"));
+        assert_eq!(icon_url(&link).as_deref(), Some("https://r.example/c.png"));
+        assert_eq!(icon_url(&json!({})), None);
+        assert_eq!(icon_url(&json!([])), None);
+    }
+
+    #[test]
+    fn test_acct_for_local_cached_and_uncached_actors() {
+        assert_eq!(
+            acct(&summary(
+                "https://example.com/users/alice",
+                Some("alice"),
+                true
+            )),
+            "alice"
+        );
+        assert_eq!(
+            acct(&summary(
+                "https://remote.example/users/b",
+                Some("bob"),
+                false
+            )),
+            "bob@remote.example"
+        );
+        assert_eq!(
+            acct(&summary("https://remote.example/users/carol", None, false)),
+            "carol@remote.example"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cache_profiles_bounds_concurrent_fetches() {
+        let mut mock = MockDatabase::new();
+        mock.expect_upsert_remote_actor().returning(|_| Ok(()));
+        let db: DatabaseRef = Arc::new(mock);
+        let client = Arc::new(SlowClient::default());
+        let parse_failures = ParseFailureRecorder::new(db.clone(), None);
+        let ids = (0..3 * MAX_PROFILE_FETCHES)
+            .map(|n| format!("https://remote.example/users/{n}"))
+            .collect();
+
+        cache_profiles(db, client.clone(), parse_failures, ids).await;
+
+        assert_eq!(
+            client.requests.load(Ordering::SeqCst),
+            3 * MAX_PROFILE_FETCHES
+        );
+        let most = client.most_in_flight.load(Ordering::SeqCst);
+        assert!(most > 1 && most <= MAX_PROFILE_FETCHES, "{most} in flight");
+    }
+}
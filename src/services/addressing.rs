@@ -0,0 +1,589 @@
+use crate::database::DatabaseRef;
+use crate::http::client::{HttpClient, HttpRequest};
+use crate::models::addressing::is_public;
+use crate::models::object::Tag;
+use crate::models::visibility::Visibility;
+use crate::services::webfinger::WebFingerResolver;
+use crate::urls::UrlBuilder;
+use serde_json::Value;
+use std::collections::HashSet;
+use tracing::{debug, warn};
+
+/// Page size used when expanding a local followers collection
+const FOLLOWERS_PAGE_SIZE: u32 = 100;
+
+/// A concrete inbox an activity should be POSTed to
+#[allow(dead_code)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InboxTarget {
+    pub actor_id: String,
+    pub inbox: String,
+    pub shared_inbox: Option<String>,
+}
+
+#[allow(dead_code)]
+impl InboxTarget {
+    /// The URL to deliver to, preferring the shared inbox when there is one
+    pub fn delivery_url(&self) -> &str {
+        self.shared_inbox.as_deref().unwrap_or(&self.inbox)
+    }
+}
+
+/// Expand the `to`/`cc`/`bto`/`bcc` fields of an activity into the inboxes
+/// it must be delivered to. Local followers collections are expanded through
+/// the database, remote actors are fetched to discover their inboxes, the
+/// Public address is skipped and targets sharing an inbox are collapsed.
+/// Direct messages always go to each recipient's personal inbox.
+#[allow(dead_code)]
+pub async fn resolve_recipients(
+    activity: &Value,
+    db: &DatabaseRef,
+    http_client: &dyn HttpClient,
+) -> Vec<InboxTarget> {
+    let sender = activity.get("actor").and_then(|v| v.as_str());
+    let visibility =
+        Visibility::from_addressing(&addresses(activity, "to"), &addresses(activity, "cc"));
+
+    let mut actor_ids = Vec::new();
+    for recipient in addressed_to(activity) {
+        if is_public(&recipient) {
+            continue;
+        }
+
+        match local_followers_owner(&recipient, db).await {
+            Some(owner) => actor_ids.extend(expand_followers(&owner, db).await),
+            None => actor_ids.push(recipient),
+        }
+    }
+
+    let mut seen_actors = HashSet::new();
+    let mut seen_urls = HashSet::new();
+    let mut targets = Vec::new();
+
+    for actor_id in actor_ids {
+        // Never deliver an activity back to its own author
+        if Some(actor_id.as_str()) == sender || !seen_actors.insert(actor_id.clone()) {
+            continue;
+        }
+
+        let Some(mut target) = fetch_inbox_target(&actor_id, http_client).await else {
+            continue;
+        };
+        if visibility == Visibility::Direct {
+            target.shared_inbox = None;
+        }
+
+        if seen_urls.insert(target.delivery_url().to_string()) {
+            targets.push(target);
+        }
+    }
+
+    targets
+}
+
+/// All addresses an activity is sent to, in field order
+fn addressed_to(activity: &Value) -> Vec<String> {
+    ["to", "cc", "bto", "bcc"]
+        .iter()
+        .flat_map(|field| addresses(activity, field))
+        .collect()
+}
+
+/// The addresses in a single addressing field, which may be a string or an array
+fn addresses(activity: &Value, field: &str) -> Vec<String> {
+    match activity.get(field) {
+        Some(Value::String(s)) => vec![s.clone()],
+        Some(Value::Array(arr)) => arr
+            .iter()
+            .filter_map(|v| v.as_str().map(|s| s.to_string()))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+/// If the address is the followers collection of a local actor, return that actor's id
+async fn local_followers_owner(address: &str, db: &DatabaseRef) -> Option<String> {
+    let owner = address.strip_suffix("/followers")?;
+
+    match db.get_actor_by_id(owner).await {
+        Ok(Some(actor)) => Some(actor.id),
+        Ok(None) => {
+            debug!("Skipping followers collection of unknown actor {}", owner);
+            None
+        }
+        Err(e) => {
+            warn!(
+                "Database error while resolving followers of {}: {}",
+                owner, e
+            );
+            None
+        }
+    }
+}
+
+async fn expand_followers(actor_id: &str, db: &DatabaseRef) -> Vec<String> {
+    let mut followers = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        match db
+            .get_followers(actor_id, FOLLOWERS_PAGE_SIZE, offset)
+            .await
+        {
+            Ok(page) => {
+                let count = page.len() as u32;
+                followers.extend(page.into_iter().map(|f| f.follower_id));
+                if count < FOLLOWERS_PAGE_SIZE {
+                    break;
+                }
+                offset += count;
+            }
+            Err(e) => {
+                warn!(
+                    "Database error while expanding followers of {}: {}",
+                    actor_id, e
+                );
+                break;
+            }
+        }
+    }
+
+    followers
+}
+
+/// Whether a value is an absolute http(s) URL with a host
+pub fn is_http_url(value: &str) -> bool {
+    let Some(rest) = value
+        .strip_prefix("https://")
+        .or_else(|| value.strip_prefix("http://"))
+    else {
+        return false;
+    };
+
+    let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+    !host.is_empty() && !value.contains(char::is_whitespace)
+}
+
+/// Find the author of the object a reply points at: local notes are looked
+/// up in the database, anything else is fetched. None if it can't be resolved.
+#[allow(dead_code)]
+pub async fn resolve_reply_author(
+    in_reply_to: &str,
+    urls: &UrlBuilder,
+    db: &DatabaseRef,
+    http_client: &dyn HttpClient,
+) -> Option<String> {
+    if urls.is_local(in_reply_to) {
+        return match db.get_note_by_id(in_reply_to).await {
+            Ok(note) => note.map(|note| note.attributed_to),
+            Err(e) => {
+                warn!("Database error while resolving {}: {}", in_reply_to, e);
+                None
+            }
+        };
+    }
+
+    let document = fetch_document(in_reply_to, http_client).await?;
+    match document.get("attributedTo")? {
+        Value::String(author) => Some(author.clone()),
+        Value::Array(authors) => authors.iter().find_map(|a| a.as_str().map(String::from)),
+        Value::Object(author) => author.get("id")?.as_str().map(String::from),
+        _ => None,
+    }
+}
+
+/// Resolve a Mention tag to the mentioned actor's id: its `href` when it has
+/// one, otherwise the `@user@domain` name, looked up in the database for this
+/// server and through WebFinger for anywhere else
+#[allow(dead_code)]
+pub async fn resolve_mention(
+    mention: &Tag,
+    urls: &UrlBuilder,
+    db: &DatabaseRef,
+    resolver: &WebFingerResolver<'_>,
+) -> Option<String> {
+    if let Some(href) = mention.href.as_deref().filter(|href| is_http_url(href)) {
+        return Some(href.to_string());
+    }
+
+    let local_domain = urls.domain();
+    let acct = mention.name.strip_prefix('@').unwrap_or(&mention.name);
+    let (user, domain) = acct.split_once('@').unwrap_or((acct, &local_domain));
+    if user.is_empty() || domain.is_empty() {
+        return None;
+    }
+
+    if urls.is_local_domain(domain) == Some(true) {
+        return match db.get_actor_by_username(user).await {
+            Ok(actor) => actor.map(|actor| actor.id),
+            Err(e) => {
+                warn!("Database error while resolving @{}: {}", user, e);
+                None
+            }
+        };
+    }
+
+    match resolver.resolve_acct(&format!("{user}@{domain}")).await {
+        Ok(actor_id) => Some(actor_id),
+        Err(e) => {
+            warn!("Could not resolve mention @{}@{}: {:#}", user, domain, e);
+            None
+        }
+    }
+}
+
+/// Fetch an ActivityPub document, logging and swallowing failures
+pub(crate) async fn fetch_document(url: &str, http_client: &dyn HttpClient) -> Option<Value> {
+    fetch_json(url, "application/activity+json", http_client).await
+}
+
+/// Fetch a JSON document with the given Accept header, logging and swallowing failures
+async fn fetch_json(url: &str, accept: &str, http_client: &dyn HttpClient) -> Option<Value> {
+    let request = HttpRequest::new("GET", url).with_header("Accept", accept);
+
+    let response = match http_client.send(request).await {
+        Ok(response) if response.status().is_success() => response,
+        Ok(response) => {
+            warn!("Failed to fetch {}: status {}", url, response.status().0);
+            return None;
+        }
+        Err(e) => {
+            warn!("Failed to fetch {}: {}", url, e);
+            return None;
+        }
+    };
+
+    match response.json() {
+        Ok(document) => Some(document),
+        Err(e) => {
+            warn!("Invalid document at {}: {}", url, e);
+            None
+        }
+    }
+}
+
+/// Fetch an actor document and pull out its inbox endpoints
+pub async fn fetch_inbox_target(
+    actor_id: &str,
+    http_client: &dyn HttpClient,
+) -> Option<InboxTarget> {
+    let document = fetch_document(actor_id, http_client).await?;
+
+    let Some(inbox) = document.get("inbox").and_then(|v| v.as_str()) else {
+        // Collections and other non-actor objects have no inbox
+        debug!("No inbox found for {}", actor_id);
+        return None;
+    };
+
+    Some(InboxTarget {
+        actor_id: actor_id.to_string(),
+        inbox: inbox.to_string(),
+        shared_inbox: document
+            .get("endpoints")
+            .and_then(|e| e.get("sharedInbox"))
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{DbActor, DbFollowRelation, DbNote, MockDatabase, PublishState};
+    use crate::http::client::{HttpResponse, StatusCode};
+    use anyhow::Result;
+    use chrono::Utc;
+    use mockall::predicate::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    const LOCAL_ACTOR: &str = "https://example.com/users/alice";
+
+    // Serves canned actor documents and records which URLs were fetched
+    struct MockHttpClient {
+        documents: HashMap<String, Value>,
+        fetched: Mutex<Vec<String>>,
+    }
+
+    impl MockHttpClient {
+        fn new(documents: Vec<Value>) -> Self {
+            Self {
+                documents: documents
+                    .into_iter()
+                    .map(|doc| (doc["id"].as_str().unwrap().to_string(), doc))
+                    .collect(),
+                fetched: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn fetched(&self) -> Vec<String> {
+            self.fetched.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl HttpClient for MockHttpClient {
+        async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+            self.fetched.lock().unwrap().push(request.url.clone());
+
+            Ok(match self.documents.get(&request.url) {
+                Some(doc) => HttpResponse {
+                    status: StatusCode(200),
+                    headers: HashMap::new(),
+                    body: serde_json::to_vec(doc)?,
+                },
+                None => HttpResponse {
+                    status: StatusCode(404),
+                    headers: HashMap::new(),
+                    body: vec![],
+                },
+            })
+        }
+    }
+
+    fn remote_actor(id: &str, shared_inbox: Option<&str>) -> Value {
+        let mut doc = json!({
+            "id": id,
+            "type": "Person",
+            "inbox": format!("{id}/inbox"),
+        });
+        if let Some(shared) = shared_inbox {
+            doc["endpoints"] = json!({ "sharedInbox": shared });
+        }
+        doc
+    }
+
+    fn create_test_activity(to: Value, cc: Value) -> Value {
+        json!({
+            "id": "https://example.com/activities/1",
+            "type": "Create",
+            "actor": LOCAL_ACTOR,
+            "object": {"type": "Note", "content": "Hello"},
+            "to": to,
+            "cc": cc
+        })
+    }
+
+    fn create_test_db(followers: Vec<&'static str>) -> DatabaseRef {
+        let mut mock = MockDatabase::new();
+        mock.expect_get_actor_by_id().returning(|id| {
+            if id != LOCAL_ACTOR {
+                return Ok(None);
+            }
+            Ok(Some(DbActor {
+                id: id.to_string(),
+                username: "alice".to_string(),
+                name: "Alice".to_string(),
+                summary: None,
+                public_key_pem: "test_key".to_string(),
+                private_key_pem: None,
+                is_admin: false,
+                actor_type: "Person".to_string(),
+                moved_to: None,
+                also_known_as: Vec::new(),
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+            }))
+        });
+        mock.expect_get_followers()
+            .with(eq(LOCAL_ACTOR), always(), eq(0))
+            .returning(move |_, _, _| {
+                Ok(followers
+                    .iter()
+                    .map(|follower| DbFollowRelation {
+                        id: format!("{follower}/follows/1"),
+                        follower_id: follower.to_string(),
+                        following_id: LOCAL_ACTOR.to_string(),
+                        status: "accepted".to_string(),
+                        created_at: Utc::now(),
+                        updated_at: Utc::now(),
+                    })
+                    .collect())
+            });
+        Arc::new(mock)
+    }
+
+    #[tokio::test]
+    async fn test_public_address_is_skipped() {
+        let db = create_test_db(vec![]);
+        let client = MockHttpClient::new(vec![]);
+        let activity = create_test_activity(
+            json!(["https://www.w3.org/ns/activitystreams#Public", "as:Public"]),
+            json!([]),
+        );
+
+        let targets = resolve_recipients(&activity, &db, &client).await;
+
+        assert!(targets.is_empty());
+        assert!(client.fetched().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_remote_actor_is_fetched() {
+        let bob = "https://remote.example/users/bob";
+        let db = create_test_db(vec![]);
+        let client = MockHttpClient::new(vec![remote_actor(bob, None)]);
+        let activity = create_test_activity(json!(bob), json!([]));
+
+        let targets = resolve_recipients(&activity, &db, &client).await;
+
+        assert_eq!(
+            targets,
+            vec![InboxTarget {
+                actor_id: bob.to_string(),
+                inbox: format!("{bob}/inbox"),
+                shared_inbox: None,
+            }]
+        );
+        assert_eq!(targets[0].delivery_url(), format!("{bob}/inbox"));
+    }
+
+    #[tokio::test]
+    async fn test_local_followers_collection_is_expanded() {
+        let bob = "https://remote.example/users/bob";
+        let carol = "https://other.example/users/carol";
+        let db = create_test_db(vec![bob, carol]);
+        let client = MockHttpClient::new(vec![remote_actor(bob, None), remote_actor(carol, None)]);
+        let activity = create_test_activity(
+            json!(["https://www.w3.org/ns/activitystreams#Public"]),
+            json!([format!("{LOCAL_ACTOR}/followers")]),
+        );
+
+        let targets = resolve_recipients(&activity, &db, &client).await;
+        let urls: Vec<_> = targets.iter().map(|t| t.delivery_url()).collect();
+
+        assert_eq!(urls, vec![format!("{bob}/inbox"), format!("{carol}/inbox")]);
+    }
+
+    #[tokio::test]
+    async fn test_shared_inbox_is_deduplicated() {
+        let bob = "https://remote.example/users/bob";
+        let dave = "https://remote.example/users/dave";
+        let shared = "https://remote.example/inbox";
+        let db = create_test_db(vec![bob, dave]);
+        let client = MockHttpClient::new(vec![
+            remote_actor(bob, Some(shared)),
+            remote_actor(dave, Some(shared)),
+        ]);
+        let activity =
+            create_test_activity(json!([format!("{LOCAL_ACTOR}/followers")]), json!([bob]));
+
+        let targets = resolve_recipients(&activity, &db, &client).await;
+
+        assert_eq!(targets.len(), 1);
+        assert_eq!(targets[0].delivery_url(), shared);
+        // bob is addressed twice but only fetched once
+        assert_eq!(client.fetched(), vec![bob.to_string(), dave.to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_direct_message_skips_shared_inbox() {
+        let bob = "https://remote.example/users/bob";
+        let dave = "https://remote.example/users/dave";
+        let shared = "https://remote.example/inbox";
+        let db = create_test_db(vec![]);
+        let client = MockHttpClient::new(vec![
+            remote_actor(bob, Some(shared)),
+            remote_actor(dave, Some(shared)),
+        ]);
+        let activity = create_test_activity(json!([bob]), json!([dave]));
+
+        let targets = resolve_recipients(&activity, &db, &client).await;
+        let urls: Vec<_> = targets.iter().map(|t| t.delivery_url()).collect();
+
+        assert_eq!(urls, vec![format!("{bob}/inbox"), format!("{dave}/inbox")]);
+    }
+
+    #[tokio::test]
+    async fn test_unresolvable_recipients_are_skipped() {
+        let db = create_test_db(vec![]);
+        let client = MockHttpClient::new(vec![json!({
+            "id": "https://remote.example/users/bob/followers",
+            "type": "OrderedCollection"
+        })]);
+        let activity = create_test_activity(
+            json!(["https://gone.example/users/eve"]),
+            json!(["https://remote.example/users/bob/followers", LOCAL_ACTOR]),
+        );
+
+        let targets = resolve_recipients(&activity, &db, &client).await;
+
+        assert!(targets.is_empty());
+        // The sender itself is never fetched
+        assert!(!client.fetched().contains(&LOCAL_ACTOR.to_string()));
+    }
+
+    #[test]
+    fn test_is_http_url() {
+        assert!(is_http_url("https://remote.example/notes/1"));
+        assert!(is_http_url("http://localhost:8080"));
+        assert!(!is_http_url("not a url"));
+        assert!(!is_http_url("https://"));
+        assert!(!is_http_url("ftp://remote.example/notes/1"));
+        assert!(!is_http_url("https://remote.example/has space"));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_reply_author_local() {
+        let mut mock = MockDatabase::new();
+        mock.expect_get_note_by_id()
+            .with(eq("https://example.com/notes/1"))
+            .returning(|id| {
+                Ok(Some(DbNote {
+                    id: id.to_string(),
+                    attributed_to: LOCAL_ACTOR.to_string(),
+                    content: "Parent".to_string(),
+                    to_recipients: vec![],
+                    cc_recipients: vec![],
+                    published: Utc::now(),
+                    in_reply_to: None,
+                    tags: vec![],
+                    attachments: vec![],
+                    visibility: Visibility::Public,
+                    state: PublishState::Published,
+                    pinned: false,
+                    created_at: Utc::now(),
+                    in_reply_to_actor: None,
+                    thread_depth: Some(0),
+                    thread_truncated: false,
+                    sensitive: false,
+                    summary: None,
+                    language: None,
+                    content_map: None,
+                }))
+            });
+        let db: DatabaseRef = Arc::new(mock);
+        let client = MockHttpClient::new(vec![]);
+
+        let author = resolve_reply_author(
+            "https://example.com/notes/1",
+            &UrlBuilder::new("https://example.com", None),
+            &db,
+            &client,
+        )
+        .await;
+
+        assert_eq!(author.as_deref(), Some(LOCAL_ACTOR));
+        assert!(client.fetched().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_reply_author_remote() {
+        let note = "https://remote.example/notes/1";
+        let db = create_test_db(vec![]);
+        let client = MockHttpClient::new(vec![json!({
+            "id": note,
+            "type": "Note",
+            "attributedTo": "https://remote.example/users/bob"
+        })]);
+
+        let urls = UrlBuilder::new("https://example.com", None);
+        let author = resolve_reply_author(note, &urls, &db, &client).await;
+        assert_eq!(author.as_deref(), Some("https://remote.example/users/bob"));
+
+        let missing =
+            resolve_reply_author("https://remote.example/notes/404", &urls, &db, &client).await;
+        assert_eq!(missing, None);
+    }
+}
@@ -0,0 +1,387 @@
+use crate::config::Config;
+use crate::database::DatabaseRef;
+use crate::errors::FederationError;
+use crate::metrics::Metrics;
+use crate::services::inbound_usage::InboundUsage;
+use crate::urls::host_of;
+use async_trait::async_trait;
+use chrono::Utc;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tracing::{info, warn};
+
+/// How long a delivery queue count is trusted before asking the database again
+const QUEUE_DEPTH_TTL: Duration = Duration::from_secs(1);
+
+/// Looks up the addresses a host name points at
+#[async_trait]
+pub trait HostResolver: Send + Sync {
+    async fn lookup(&self, host: &str) -> Vec<IpAddr>;
+}
+
+/// Resolves hosts through the system resolver
+pub struct DnsResolver;
+
+#[async_trait]
+impl HostResolver for DnsResolver {
+    async fn lookup(&self, host: &str) -> Vec<IpAddr> {
+        // `host_of` keeps non-default ports, so only bare hosts need one
+        let target = if host.ends_with(']') || !host.contains(':') {
+            format!("{host}:443")
+        } else {
+            host.to_string()
+        };
+        match tokio::net::lookup_host(target).await {
+            Ok(addrs) => addrs.map(|addr| addr.ip()).collect(),
+            Err(e) => {
+                warn!("Could not resolve {}: {}", host, e);
+                Vec::new()
+            }
+        }
+    }
+}
+
+/// Decides whether the inbox takes on another activity. Beyond either
+/// high-water mark (activities in flight, deliveries queued) remotes get a
+/// 503 with `Retry-After`, except hosts one of our actors follows: their
+/// posts are what our users asked for, so they are always accepted. The same
+/// goes for each sending address's hourly byte quota, beyond which it gets a
+/// 429.
+///
+/// Deliveries aren't signed yet, so a sender's `actor` proves nothing on its
+/// own: usage is counted against the connecting address, and a followed host
+/// is only taken at its word when the connection comes from one of that
+/// host's addresses.
+pub struct InboxBackpressure {
+    processing: Arc<Semaphore>,
+    max_in_flight: usize,
+    max_pending_deliveries: u32,
+    retry_after_secs: u64,
+    queue_depth: Mutex<Option<(Instant, u32)>>,
+    usage: Arc<InboundUsage>,
+    resolver: Arc<dyn HostResolver>,
+    metrics: Arc<Metrics>,
+}
+
+/// A slot in the inbox, released when dropped. Priority senders admitted
+/// past a saturated inbox hold no slot.
+pub struct InboxPermit {
+    permit: Option<OwnedSemaphorePermit>,
+    processing: Arc<Semaphore>,
+    max_in_flight: usize,
+    metrics: Arc<Metrics>,
+}
+
+impl InboxBackpressure {
+    pub fn new(
+        max_in_flight: usize,
+        max_pending_deliveries: u32,
+        retry_after_secs: u64,
+        metrics: Arc<Metrics>,
+    ) -> Self {
+        Self {
+            processing: Arc::new(Semaphore::new(max_in_flight)),
+            max_in_flight,
+            max_pending_deliveries,
+            retry_after_secs,
+            queue_depth: Mutex::new(None),
+            usage: Arc::new(InboundUsage::new(0)),
+            resolver: Arc::new(DnsResolver),
+            metrics,
+        }
+    }
+
+    /// Limit each remote host to `quota_bytes` of activities an hour
+    pub fn with_host_quota(mut self, quota_bytes: u64) -> Self {
+        self.usage = Arc::new(InboundUsage::new(quota_bytes));
+        self
+    }
+
+    /// Check claimed hosts against `resolver` instead of DNS
+    #[allow(dead_code)]
+    pub fn with_resolver(mut self, resolver: Arc<dyn HostResolver>) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    pub fn from_config(config: &Config, metrics: Arc<Metrics>) -> Self {
+        Self::new(
+            config.inbox_max_in_flight,
+            config.inbox_max_pending_deliveries,
+            config.inbox_retry_after_secs,
+            metrics,
+        )
+        .with_host_quota(config.inbox_host_quota_bytes)
+    }
+
+    /// Bytes received from each address, for the admin API and snapshots
+    pub fn usage(&self) -> &Arc<InboundUsage> {
+        &self.usage
+    }
+
+    /// Take a processing slot if one is free, ignoring the delivery queue
+    pub fn try_acquire(&self) -> Option<InboxPermit> {
+        let permit = self.processing.clone().try_acquire_owned().ok()?;
+        let permit = self.permit(Some(permit));
+        self.metrics.set_inbox_in_flight(self.in_flight());
+        Some(permit)
+    }
+
+    /// Activities holding a processing slot
+    pub fn in_flight(&self) -> usize {
+        self.max_in_flight - self.processing.available_permits()
+    }
+
+    /// Admit an activity from `sender` (its `actor`) connecting from `peer`,
+    /// or turn it away with [`FederationError::Overloaded`] when saturated
+    /// and it is not from a host we follow
+    pub async fn admit(
+        &self,
+        db: &DatabaseRef,
+        peer: Option<IpAddr>,
+        sender: Option<&str>,
+    ) -> Result<InboxPermit, FederationError> {
+        if let Some(permit) = self.try_acquire() {
+            if self.pending_deliveries(db).await <= self.max_pending_deliveries {
+                return Ok(permit);
+            }
+        }
+
+        if let Some(host) = self.followed_host(db, peer, sender).await {
+            info!("Inbox saturated, admitting {} as a followed host", host);
+            return Ok(self.permit(None));
+        }
+
+        self.metrics.inc_inbox_rejected();
+        Err(FederationError::Overloaded {
+            retry_after_secs: self.retry_after_secs,
+        })
+    }
+
+    /// Count an activity of `bytes` against the quota of `peer`, the
+    /// address it came from, or turn it away with
+    /// [`FederationError::QuotaExceeded`] when the quota is used up and it is
+    /// not from a host we follow. Followed hosts are still counted so their
+    /// usage shows up.
+    pub async fn charge(
+        &self,
+        db: &DatabaseRef,
+        peer: Option<IpAddr>,
+        sender: Option<&str>,
+        bytes: u64,
+    ) -> Result<(), FederationError> {
+        let Some(address) = peer.map(|peer| peer.to_string()) else {
+            return Ok(());
+        };
+        let now = Utc::now();
+        let retry_after_secs = match self.usage.try_charge(&address, bytes, now) {
+            Ok(_) => return Ok(()),
+            Err(retry_after_secs) => retry_after_secs,
+        };
+
+        if self.followed_host(db, peer, sender).await.is_some() {
+            self.usage.charge(&address, bytes, now);
+            return Ok(());
+        }
+
+        info!("{} is over its inbound quota", address);
+        self.metrics.inc_inbox_quota_rejected();
+        Err(FederationError::QuotaExceeded { retry_after_secs })
+    }
+
+    /// The host of `sender` if one of our actors follows it and `peer` is one
+    /// of its addresses
+    async fn followed_host(
+        &self,
+        db: &DatabaseRef,
+        peer: Option<IpAddr>,
+        sender: Option<&str>,
+    ) -> Option<String> {
+        let peer = peer?;
+        let host = sender.and_then(host_of)?;
+        match db.is_following_host(&host).await {
+            Ok(true) => {}
+            Ok(false) => return None,
+            Err(e) => {
+                warn!("Database error while checking follows of {}: {}", host, e);
+                return None;
+            }
+        }
+
+        if self.resolver.lookup(&host).await.contains(&peer) {
+            return Some(host);
+        }
+        warn!("{} claims to be from {}, which it is not", peer, host);
+        None
+    }
+
+    /// The delivery queue depth, counted at most once per [`QUEUE_DEPTH_TTL`].
+    /// An unanswerable count is taken as empty so the inbox fails open.
+    async fn pending_deliveries(&self, db: &DatabaseRef) -> u32 {
+        if let Some((counted_at, depth)) = *self.queue_depth.lock().unwrap() {
+            if counted_at.elapsed() < QUEUE_DEPTH_TTL {
+                return depth;
+            }
+        }
+
+        let depth = match db.count_pending_deliveries().await {
+            Ok(depth) => depth,
+            Err(e) => {
+                warn!("Database error while counting pending deliveries: {}", e);
+                0
+            }
+        };
+        *self.queue_depth.lock().unwrap() = Some((Instant::now(), depth));
+        self.metrics.set_delivery_queue_pending(depth);
+        depth
+    }
+
+    fn permit(&self, permit: Option<OwnedSemaphorePermit>) -> InboxPermit {
+        InboxPermit {
+            permit,
+            processing: self.processing.clone(),
+            max_in_flight: self.max_in_flight,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+impl Drop for InboxPermit {
+    fn drop(&mut self) {
+        if let Some(permit) = self.permit.take() {
+            drop(permit);
+            self.metrics
+                .set_inbox_in_flight(self.max_in_flight - self.processing.available_permits());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::MockDatabase;
+
+    fn database(pending: u32, followed: bool) -> DatabaseRef {
+        let mut db = MockDatabase::new();
+        db.expect_count_pending_deliveries()
+            .returning(move || Ok(pending));
+        db.expect_is_following_host()
+            .returning(move |_| Ok(followed));
+        Arc::new(db)
+    }
+
+    /// friend.example lives at 192.0.2.1; nothing else resolves
+    struct StaticResolver;
+
+    #[async_trait]
+    impl HostResolver for StaticResolver {
+        async fn lookup(&self, host: &str) -> Vec<IpAddr> {
+            match host {
+                "friend.example" => vec![FRIEND_ADDR],
+                _ => Vec::new(),
+            }
+        }
+    }
+
+    const FRIEND_ADDR: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(192, 0, 2, 1));
+    const STRANGER_ADDR: IpAddr = IpAddr::V4(std::net::Ipv4Addr::new(198, 51, 100, 7));
+
+    fn gauge(metrics: &Metrics, name: &str) -> f64 {
+        metrics
+            .registry()
+            .gather()
+            .iter()
+            .find(|family| family.name() == name)
+            .map(|family| family.get_metric()[0].get_gauge().get_value())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_rejects_once_slots_run_out() {
+        let metrics = Arc::new(Metrics::new());
+        let backpressure = InboxBackpressure::new(1, 100, 15, metrics.clone());
+        let db = database(0, false);
+
+        let held = backpressure
+            .admit(
+                &db,
+                Some(STRANGER_ADDR),
+                Some("https://remote.example/users/bob"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(backpressure.in_flight(), 1);
+        assert_eq!(gauge(&metrics, "feder8_inbox_in_flight"), 1.0);
+
+        let rejected = backpressure
+            .admit(
+                &db,
+                Some(STRANGER_ADDR),
+                Some("https://remote.example/users/bob"),
+            )
+            .await;
+        assert!(matches!(
+            rejected,
+            Err(FederationError::Overloaded {
+                retry_after_secs: 15
+            })
+        ));
+
+        drop(held);
+        assert_eq!(backpressure.in_flight(), 0);
+        assert_eq!(gauge(&metrics, "feder8_inbox_in_flight"), 0.0);
+        assert!(backpressure.admit(&db, None, None).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_rejects_while_delivery_queue_is_deep() {
+        let metrics = Arc::new(Metrics::new());
+        let backpressure = InboxBackpressure::new(8, 100, 15, metrics.clone());
+        let db = database(101, false);
+
+        assert!(backpressure.admit(&db, None, None).await.is_err());
+        assert_eq!(backpressure.in_flight(), 0);
+        assert_eq!(gauge(&metrics, "feder8_delivery_queue_pending"), 101.0);
+    }
+
+    #[tokio::test]
+    async fn test_followed_hosts_bypass_saturation() {
+        let backpressure = InboxBackpressure::new(0, 100, 15, Arc::new(Metrics::new()))
+            .with_resolver(Arc::new(StaticResolver));
+        let db = database(0, true);
+        let carol = Some("https://friend.example/users/carol");
+
+        assert!(backpressure
+            .admit(&db, Some(FRIEND_ADDR), carol)
+            .await
+            .is_ok());
+        // Claiming a followed host from elsewhere, or from nowhere, gets
+        // no priority
+        assert!(backpressure
+            .admit(&db, Some(STRANGER_ADDR), carol)
+            .await
+            .is_err());
+        assert!(backpressure.admit(&db, None, carol).await.is_err());
+        // Without a sender there is no host to prioritise
+        assert!(backpressure
+            .admit(&db, Some(FRIEND_ADDR), None)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_queue_depth_is_cached() {
+        let mut db = MockDatabase::new();
+        db.expect_count_pending_deliveries()
+            .times(1)
+            .returning(|| Ok(0));
+        let db: DatabaseRef = Arc::new(db);
+        let backpressure = InboxBackpressure::new(8, 100, 15, Arc::new(Metrics::new()));
+
+        for _ in 0..3 {
+            assert!(backpressure.admit(&db, None, None).await.is_ok());
+        }
+    }
+}
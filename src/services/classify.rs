@@ -0,0 +1,286 @@
+use crate::config::Config;
+use crate::database::{DatabaseError, DatabaseRef, DbNote, DbNoteLabel};
+use regex::Regex;
+use tracing::{info, warn};
+
+/// What a classifier found in a note, and what it suggests doing about it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Label {
+    pub name: String,
+    /// Content warning to put on the note when it has none
+    pub content_warning: Option<String>,
+    /// Whether the note should be marked sensitive
+    pub sensitive: bool,
+}
+
+/// Looks at a local note before it is stored and federated. Labels are
+/// suggestions: whether they change the note is up to its author's
+/// preferences.
+pub trait ContentClassifier: Send + Sync {
+    fn classify(&self, note: &DbNote) -> Vec<Label>;
+}
+
+/// The default classifier: notes whose content matches a keyword pattern get
+/// that rule's label as content warning, and notes with many attachments
+/// are marked sensitive
+#[derive(Default)]
+pub struct KeywordClassifier {
+    rules: Vec<(String, Regex)>,
+    media_threshold: usize,
+}
+
+impl KeywordClassifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The rules in `classifier_rules` and `classifier_media_threshold`.
+    /// Malformed rules are logged and left out.
+    pub fn from_config(config: &Config) -> Self {
+        let mut classifier = Self::new().with_media_threshold(config.classifier_media_threshold);
+        for rule in &config.classifier_rules {
+            let Some((label, pattern)) = rule.split_once('=') else {
+                warn!("Ignoring classifier rule without a label: {}", rule);
+                continue;
+            };
+            match Regex::new(pattern) {
+                Ok(pattern) => classifier = classifier.with_rule(label.trim(), pattern),
+                Err(e) => warn!("Ignoring classifier rule {}: {}", label.trim(), e),
+            }
+        }
+        classifier
+    }
+
+    /// Label notes whose content matches `pattern` with `label`
+    pub fn with_rule(mut self, label: &str, pattern: Regex) -> Self {
+        self.rules.push((label.to_string(), pattern));
+        self
+    }
+
+    /// Mark notes with at least `threshold` attachments sensitive; 0 never does
+    pub fn with_media_threshold(mut self, threshold: usize) -> Self {
+        self.media_threshold = threshold;
+        self
+    }
+}
+
+impl ContentClassifier for KeywordClassifier {
+    fn classify(&self, note: &DbNote) -> Vec<Label> {
+        let mut labels: Vec<Label> = self
+            .rules
+            .iter()
+            .filter(|(_, pattern)| pattern.is_match(&note.content))
+            .map(|(name, _)| Label {
+                name: name.clone(),
+                content_warning: Some(name.clone()),
+                sensitive: true,
+            })
+            .collect();
+
+        if self.media_threshold > 0 && note.attachments.len() >= self.media_threshold {
+            labels.push(Label {
+                name: "media".to_string(),
+                content_warning: None,
+                sensitive: true,
+            });
+        }
+        labels
+    }
+}
+
+/// Apply `labels` to a note: a content warning naming them unless its author
+/// wrote one, and the sensitive flag if any asks for it
+pub fn apply(labels: &[Label], note: &mut DbNote) {
+    let warnings: Vec<&str> = labels
+        .iter()
+        .filter_map(|label| label.content_warning.as_deref())
+        .collect();
+    if note.summary.is_none() && !warnings.is_empty() {
+        note.summary = Some(warnings.join(", "));
+    }
+    if labels.iter().any(|label| label.sensitive) || note.summary.is_some() {
+        note.sensitive = true;
+    }
+}
+
+/// Classify a local note before it is stored, applying the labels unless
+/// its author opted out. Returns the labels to record once the note is.
+pub async fn classify_note(
+    classifier: &dyn ContentClassifier,
+    db: &DatabaseRef,
+    note: &mut DbNote,
+) -> Result<Vec<DbNoteLabel>, DatabaseError> {
+    let labels = classifier.classify(note);
+    if labels.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let applied = db
+        .get_preferences(&note.attributed_to)
+        .await?
+        .auto_content_warnings;
+    if applied {
+        apply(&labels, note);
+    }
+    info!(
+        "Classified {} as {:?}{}",
+        note.id,
+        labels.iter().map(|label| &label.name).collect::<Vec<_>>(),
+        if applied { "" } else { ", not applied" }
+    );
+
+    Ok(labels
+        .into_iter()
+        .map(|label| DbNoteLabel {
+            label: label.name,
+            applied,
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{DbPreferences, MockDatabase, PublishState};
+    use crate::models::Visibility;
+    use chrono::Utc;
+    use std::sync::Arc;
+
+    fn note(content: &str, attachments: usize) -> DbNote {
+        DbNote {
+            id: "https://example.com/notes/1".to_string(),
+            attributed_to: "https://example.com/users/alice".to_string(),
+            content: content.to_string(),
+            to_recipients: vec![],
+            cc_recipients: vec![],
+            published: Utc::now(),
+            in_reply_to: None,
+            tags: vec![],
+            attachments: vec![serde_json::json!({"type": "Image"}); attachments],
+            visibility: Visibility::Public,
+            state: PublishState::Published,
+            pinned: false,
+            created_at: Utc::now(),
+            in_reply_to_actor: None,
+            thread_depth: Some(0),
+            thread_truncated: false,
+            sensitive: false,
+            summary: None,
+            language: None,
+            content_map: None,
+        }
+    }
+
+    fn classifier() -> KeywordClassifier {
+        KeywordClassifier::from_config(&Config {
+            classifier_rules: vec![
+                r"spoilers=(?i)\bspoil(er|ers)?\b".to_string(),
+                "politics=(?i)election".to_string(),
+                "no label here".to_string(),
+                "broken=(".to_string(),
+            ],
+            classifier_media_threshold: 3,
+            ..Config::default()
+        })
+    }
+
+    fn names(labels: &[Label]) -> Vec<&str> {
+        labels.iter().map(|label| label.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_keyword_and_media_rules() {
+        let classifier = classifier();
+
+        assert!(classifier.classify(&note("<p>Nice day</p>", 2)).is_empty());
+        assert_eq!(
+            names(&classifier.classify(&note("<p>SPOILER: the election</p>", 0))),
+            vec!["spoilers", "politics"]
+        );
+        // Whole words only, as the pattern says
+        assert!(classifier
+            .classify(&note("<p>spoiled milk</p>", 0))
+            .is_empty());
+
+        let labels = classifier.classify(&note("<p>Holiday pictures</p>", 3));
+        assert_eq!(
+            labels,
+            vec![Label {
+                name: "media".to_string(),
+                content_warning: None,
+                sensitive: true,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_apply_keeps_the_authors_content_warning() {
+        let classifier = classifier();
+
+        let mut plain = note("<p>Spoilers about the election</p>", 0);
+        let labels = classifier.classify(&plain);
+        apply(&labels, &mut plain);
+        assert_eq!(plain.summary.as_deref(), Some("spoilers, politics"));
+        assert!(plain.sensitive);
+
+        let mut warned = note("<p>Spoilers!</p>", 0);
+        warned.summary = Some("Film ending".to_string());
+        let labels = classifier.classify(&warned);
+        apply(&labels, &mut warned);
+        assert_eq!(warned.summary.as_deref(), Some("Film ending"));
+
+        let mut media = note("<p>Holiday</p>", 4);
+        let labels = classifier.classify(&media);
+        apply(&labels, &mut media);
+        assert_eq!(media.summary, None);
+        assert!(media.sensitive);
+    }
+
+    fn preferences(auto_content_warnings: bool) -> DatabaseRef {
+        let mut db = MockDatabase::new();
+        db.expect_get_preferences().returning(move |_| {
+            Ok(DbPreferences {
+                auto_content_warnings,
+                ..DbPreferences::default()
+            })
+        });
+        Arc::new(db)
+    }
+
+    #[tokio::test]
+    async fn test_authors_can_opt_out() {
+        let classifier = classifier();
+
+        let mut applied = note("<p>Spoilers</p>", 0);
+        let labels = classify_note(&classifier, &preferences(true), &mut applied)
+            .await
+            .unwrap();
+        assert_eq!(
+            labels,
+            vec![DbNoteLabel {
+                label: "spoilers".to_string(),
+                applied: true
+            }]
+        );
+        assert_eq!(applied.summary.as_deref(), Some("spoilers"));
+
+        // Still recorded, but the note is left as written
+        let mut opted_out = note("<p>Spoilers</p>", 0);
+        let labels = classify_note(&classifier, &preferences(false), &mut opted_out)
+            .await
+            .unwrap();
+        assert!(!labels[0].applied);
+        assert_eq!(opted_out.summary, None);
+        assert!(!opted_out.sensitive);
+    }
+
+    #[tokio::test]
+    async fn test_unlabelled_notes_skip_the_preferences() {
+        let mut quiet = note("<p>Nice day</p>", 0);
+        let db: DatabaseRef = Arc::new(MockDatabase::new());
+        assert!(classify_note(&classifier(), &db, &mut quiet)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -1,24 +1,99 @@
 use crate::config::Config;
+use crate::database::{DatabaseError, DatabaseRef, DeliveryPriority};
 use crate::http::client::HttpClient;
-use anyhow::Result;
+use crate::http::request_id;
+use crate::metrics::Metrics;
+use crate::services::actor_fetch::ActorFetchService;
+use crate::services::delivery_queue;
+use crate::services::log_dedup::LogDedup;
+use crate::services::parse_failures::ParseFailureRecorder;
+use crate::urls::UrlBuilder;
+use anyhow::{bail, Result};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use futures::future::join_all;
 use serde_json::Value;
-use std::collections::HashMap;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use tracing::{error, info, warn};
+use tokio::sync::Semaphore;
+use tracing::{debug, info, instrument};
+
+/// Followers read per page while fanning out
+const FOLLOWERS_PAGE_SIZE: u32 = 100;
+
+/// `Digest` header for an activity's body: its SHA-256, serialized exactly as
+/// `HttpClient::post_with_headers` sends it
+pub fn body_digest(activity: &Value) -> Result<String> {
+    let body = serde_json::to_vec(activity)?;
+    Ok(format!("SHA-256={}", BASE64.encode(Sha256::digest(&body))))
+}
+
+/// What became of a fan-out: inboxes reached now, and those queued for retry
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FanOut {
+    pub delivered: usize,
+    pub queued: usize,
+}
 
 #[allow(dead_code)]
 pub struct DeliveryService {
     client: Arc<dyn HttpClient>,
     config: Config,
+    urls: UrlBuilder,
+    db: DatabaseRef,
+    metrics: Option<Arc<Metrics>>,
+    warnings: Arc<LogDedup>,
 }
 
 #[allow(dead_code)]
 impl DeliveryService {
-    pub fn new(config: Config, client: Arc<dyn HttpClient>) -> Self {
-        Self { client, config }
+    pub fn new(config: Config, client: Arc<dyn HttpClient>, db: DatabaseRef) -> Self {
+        let warnings = Arc::new(LogDedup::from_config(&config));
+        Self {
+            client,
+            urls: UrlBuilder::from_config(&config),
+            config,
+            db,
+            metrics: None,
+            warnings,
+        }
     }
 
+    /// Count each delivery's success or failure
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Where delivery outcomes are counted, when they are
+    pub fn metrics(&self) -> Option<&Arc<Metrics>> {
+        self.metrics.as_ref()
+    }
+
+    /// Share the process-wide dedup of repeated warnings
+    pub fn with_log_dedup(mut self, warnings: Arc<LogDedup>) -> Self {
+        self.warnings = warnings;
+        self
+    }
+
+    #[instrument(skip(self, activity), fields(activity_id = activity.get("id").and_then(|v| v.as_str())))]
     pub async fn deliver_activity(&self, inbox_url: &str, activity: Value) -> Result<()> {
+        let result = self.post_activity(inbox_url, activity).await;
+        if let Some(metrics) = &self.metrics {
+            metrics.inc_delivery(result.is_ok());
+        }
+        if let Err(e) = &result {
+            self.warnings.warn(
+                "delivery_failed",
+                inbox_url,
+                format_args!("Failed to deliver to {}: {:#}", inbox_url, e),
+            );
+        }
+        result
+    }
+
+    async fn post_activity(&self, inbox_url: &str, activity: Value) -> Result<()> {
         info!("Delivering activity to inbox: {}", inbox_url);
 
         let mut headers = HashMap::new();
@@ -30,25 +105,30 @@ impl DeliveryService {
             "User-Agent".to_string(),
             format!("Fediverse-Node/{}", env!("CARGO_PKG_VERSION")),
         );
+        // Lets the receiving server check the body arrived intact
+        headers.insert("Digest".to_string(), body_digest(&activity)?);
+        // Lets the receiving server match its logs to the request behind this
+        if let Some(id) = request_id::current() {
+            headers.insert(request_id::REQUEST_ID_HEADER.to_string(), id);
+        }
 
         let response = self
             .client
             .post_with_headers(inbox_url, headers, &activity)
             .await?;
 
-        if response.status().is_success() {
-            info!("Successfully delivered activity to {}", inbox_url);
-        } else {
-            warn!(
-                "Failed to deliver activity to {}: {}",
+        if !response.status().is_success() {
+            if let Ok(error_text) = response.text() {
+                debug!("Error response from {}: {}", inbox_url, error_text);
+            }
+            bail!(
+                "{} responded with status {}",
                 inbox_url,
                 response.status().0
             );
-            if let Ok(error_text) = response.text() {
-                error!("Error response: {}", error_text);
-            }
         }
 
+        info!("Successfully delivered activity to {}", inbox_url);
         Ok(())
     }
 
@@ -59,18 +139,129 @@ impl DeliveryService {
     ) -> Result<()> {
         info!("Delivering activity to {} followers", followers.len());
 
+        // Failures are logged by deliver_activity
         for follower_inbox in followers {
-            if let Err(e) = self
+            let _ = self
                 .deliver_activity(&follower_inbox, activity.clone())
-                .await
-            {
-                warn!("Failed to deliver to {}: {}", follower_inbox, e);
-            }
+                .await;
         }
 
         Ok(())
     }
 
+    /// Deliver `activity` to every accepted remote follower of `actor_id`,
+    /// once per inbox and at most `delivery_concurrency` at a time. Shared
+    /// inboxes are preferred. Deliveries that fail go to the delivery queue
+    /// to be retried.
+    pub async fn fan_out_activity(
+        &self,
+        activity: Value,
+        actor_id: &str,
+    ) -> Result<FanOut, DatabaseError> {
+        self.fan_out_except(activity, actor_id, &HashSet::new())
+            .await
+    }
+
+    /// `fan_out_activity`, leaving out the inboxes in `skip` that the
+    /// activity was already queued for
+    #[instrument(skip(self, activity, skip), fields(activity_id = activity.get("id").and_then(|v| v.as_str())))]
+    pub async fn fan_out_except(
+        &self,
+        activity: Value,
+        actor_id: &str,
+        skip: &HashSet<String>,
+    ) -> Result<FanOut, DatabaseError> {
+        let mut inboxes = self.follower_inboxes(actor_id).await?;
+        inboxes.retain(|inbox| !skip.contains(inbox));
+        info!("Fanning out activity to {} follower inboxes", inboxes.len());
+
+        let slots = Arc::new(Semaphore::new(self.config.delivery_concurrency.max(1)));
+        let results = join_all(inboxes.iter().map(|inbox| {
+            let slots = slots.clone();
+            let activity = activity.clone();
+            async move {
+                let _slot = slots.acquire_owned().await;
+                self.deliver_activity(inbox, activity).await
+            }
+        }))
+        .await;
+
+        let mut fan_out = FanOut::default();
+        for (inbox, result) in inboxes.iter().zip(results) {
+            match result {
+                Ok(()) => fan_out.delivered += 1,
+                Err(e) => {
+                    info!("Queueing retry to {} after: {}", inbox, e);
+                    delivery_queue::enqueue(
+                        &self.db,
+                        inbox,
+                        activity.clone(),
+                        DeliveryPriority::Broadcast,
+                    )
+                    .await?;
+                    fan_out.queued += 1;
+                }
+            }
+        }
+        Ok(fan_out)
+    }
+
+    /// The distinct inboxes of an actor's accepted remote followers
+    async fn follower_inboxes(&self, actor_id: &str) -> Result<Vec<String>, DatabaseError> {
+        let mut inboxes = Vec::new();
+        let mut seen = HashSet::new();
+        let mut offset = 0;
+
+        loop {
+            let page = self
+                .db
+                .get_followers(actor_id, FOLLOWERS_PAGE_SIZE, offset)
+                .await?;
+            for follow in &page {
+                if follow.status != "accepted" || self.urls.is_local(&follow.follower_id) {
+                    continue;
+                }
+                match self.follower_inbox(&follow.follower_id).await? {
+                    Some(inbox) => {
+                        if seen.insert(inbox.clone()) {
+                            inboxes.push(inbox);
+                        }
+                    }
+                    None => {
+                        self.warnings.warn(
+                            "follower_inbox_missing",
+                            &follow.follower_id,
+                            format_args!("Could not find inbox for {}", follow.follower_id),
+                        );
+                    }
+                }
+            }
+            if (page.len() as u32) < FOLLOWERS_PAGE_SIZE {
+                return Ok(inboxes);
+            }
+            offset += FOLLOWERS_PAGE_SIZE;
+        }
+    }
+
+    /// A follower's shared inbox, or its own, from the remote actor cache
+    async fn follower_inbox(&self, follower_id: &str) -> Result<Option<String>, DatabaseError> {
+        Ok(self
+            .actor_fetch()
+            .find(follower_id)
+            .await?
+            .and_then(|actor| actor.shared_inbox.or(actor.inbox)))
+    }
+
+    /// Remote actors fetched and cached through this service's client
+    pub fn actor_fetch(&self) -> ActorFetchService {
+        ActorFetchService::from_config(
+            &self.config,
+            self.db.clone(),
+            self.client.clone(),
+            ParseFailureRecorder::new(self.db.clone(), self.metrics.clone()),
+        )
+    }
+
     pub async fn deliver_to_public(
         &self,
         activity: Value,
@@ -82,9 +273,7 @@ impl DeliveryService {
         );
 
         for inbox in public_inboxes {
-            if let Err(e) = self.deliver_activity(&inbox, activity.clone()).await {
-                warn!("Failed to deliver to public inbox {}: {}", inbox, e);
-            }
+            let _ = self.deliver_activity(&inbox, activity.clone()).await;
         }
 
         Ok(())
@@ -94,6 +283,7 @@ impl DeliveryService {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::database::MockDatabase;
     use crate::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode};
     use serde_json::json;
     use std::sync::Arc;
@@ -128,6 +318,10 @@ mod tests {
         }
     }
 
+    fn mock_database() -> DatabaseRef {
+        Arc::new(MockDatabase::new())
+    }
+
     fn create_test_config() -> Config {
         Config {
             server_name: "Test Server".to_string(),
@@ -136,6 +330,7 @@ mod tests {
             actor_name: "testuser".to_string(),
             private_key_path: None,
             public_key_path: None,
+            ..Config::default()
         }
     }
 
@@ -159,7 +354,7 @@ mod tests {
     fn test_delivery_service_new() {
         let config = create_test_config();
         let client = Arc::new(MockHttpClient::new(true));
-        let service = DeliveryService::new(config.clone(), client);
+        let service = DeliveryService::new(config.clone(), client, mock_database());
 
         assert_eq!(service.config.server_name, config.server_name);
         assert_eq!(service.config.server_url, config.server_url);
@@ -176,6 +371,7 @@ mod tests {
             actor_name: "alice".to_string(),
             private_key_path: None,
             public_key_path: None,
+            ..Config::default()
         };
 
         let config2 = Config {
@@ -185,12 +381,13 @@ mod tests {
             actor_name: "bob".to_string(),
             private_key_path: Some("/path/to/key".to_string()),
             public_key_path: Some("/path/to/pub".to_string()),
+            ..Config::default()
         };
 
         let client1 = Arc::new(MockHttpClient::new(true));
         let client2 = Arc::new(MockHttpClient::new(true));
-        let service1 = DeliveryService::new(config1.clone(), client1);
-        let service2 = DeliveryService::new(config2.clone(), client2);
+        let service1 = DeliveryService::new(config1.clone(), client1, mock_database());
+        let service2 = DeliveryService::new(config2.clone(), client2, mock_database());
 
         assert_eq!(service1.config.server_name, "Server 1");
         assert_eq!(service1.config.actor_name, "alice");
@@ -207,7 +404,7 @@ mod tests {
     async fn test_deliver_to_followers_empty_list() {
         let config = create_test_config();
         let client = Arc::new(MockHttpClient::new(true));
-        let service = DeliveryService::new(config, client);
+        let service = DeliveryService::new(config, client, mock_database());
         let activity = create_test_activity();
         let followers = vec![];
 
@@ -220,7 +417,7 @@ mod tests {
     async fn test_deliver_to_public_empty_list() {
         let config = create_test_config();
         let client = Arc::new(MockHttpClient::new(true));
-        let service = DeliveryService::new(config, client);
+        let service = DeliveryService::new(config, client, mock_database());
         let activity = create_test_activity();
         let public_inboxes = vec![];
 
@@ -303,6 +500,16 @@ mod tests {
         assert!(public_inboxes.contains(&"https://relay.activitypub.org/inbox".to_string()));
     }
 
+    #[test]
+    fn test_body_digest_is_sha256_of_the_sent_body() {
+        let activity = create_test_activity();
+        let body = serde_json::to_vec(&activity).unwrap();
+        let expected = format!("SHA-256={}", BASE64.encode(Sha256::digest(&body)));
+
+        assert_eq!(body_digest(&activity).unwrap(), expected);
+        assert_ne!(body_digest(&json!({"type": "Delete"})).unwrap(), expected);
+    }
+
     #[test]
     fn test_activity_cloning() {
         let activity = create_test_activity();
@@ -318,7 +525,7 @@ mod tests {
     fn test_delivery_service_config_persistence() {
         let original_config = create_test_config();
         let client = Arc::new(MockHttpClient::new(true));
-        let service = DeliveryService::new(original_config.clone(), client);
+        let service = DeliveryService::new(original_config.clone(), client, mock_database());
 
         // Verify that the service maintains a copy of the config
         assert_eq!(service.config.server_name, original_config.server_name);
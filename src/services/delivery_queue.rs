@@ -0,0 +1,510 @@
+use crate::database::{
+    DatabaseError, DatabaseRef, DbDelivery, DbHostBacklog, DbOldestDelivery, DeliveryPriority,
+};
+use crate::metrics::Metrics;
+use crate::services::actor_fetch::ActorFetchService;
+use crate::services::delivery::DeliveryService;
+use crate::services::log_dedup::LogDedup;
+use crate::urls::UrlBuilder;
+use chrono::{DateTime, Duration, Utc};
+use serde_json::Value;
+use std::collections::HashSet;
+use tracing::{info, warn};
+
+/// Scheduler job name of the delivery worker
+pub const DELIVERY_JOB: &str = "delivery";
+
+/// Scheduler job name of the queue gauges refresh
+pub const QUEUE_STATS_JOB: &str = "delivery-queue-stats";
+
+/// Hosts broken out in the queue gauges and admin stats
+pub const BACKLOG_HOSTS: u32 = 10;
+
+/// Deliveries picked up per worker run
+const DELIVERY_BATCH_SIZE: u32 = 50;
+
+/// Slots of each run's batch held for a priority, so a flood of one can't
+/// hold the others back for good. Slots a priority leaves unused go to the
+/// most urgent with more waiting.
+fn batch_share(priority: DeliveryPriority) -> usize {
+    match priority {
+        DeliveryPriority::Interactive => 25,
+        DeliveryPriority::Direct => 15,
+        DeliveryPriority::Broadcast => 10,
+    }
+}
+
+/// Attempts after which a delivery is dropped
+pub const MAX_DELIVERY_ATTEMPTS: u32 = 8;
+
+/// Delay before the first retry; doubles with every failed attempt
+const BASE_RETRY_DELAY_SECS: i64 = 30;
+const MAX_RETRY_DELAY_SECS: i64 = 6 * 60 * 60;
+
+/// Outcome of a single delivery worker run
+#[derive(Debug, Default, PartialEq)]
+pub struct DeliveryRun {
+    pub delivered: usize,
+    pub retried: usize,
+    pub dropped: usize,
+}
+
+/// Where the delivery queue stands: how much is waiting, for how long, and
+/// for which hosts
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueueBacklog {
+    pub pending: u32,
+    pub by_priority: Vec<(DeliveryPriority, u32)>,
+    pub oldest: Option<DbOldestDelivery>,
+    pub by_host: Vec<DbHostBacklog>,
+}
+
+/// Queue an activity for delivery to a remote inbox
+pub async fn enqueue(
+    db: &DatabaseRef,
+    inbox_url: &str,
+    activity: Value,
+    priority: DeliveryPriority,
+) -> Result<(), DatabaseError> {
+    let now = Utc::now();
+    let delivery = DbDelivery {
+        id: uuid::Uuid::new_v4().to_string(),
+        inbox_url: inbox_url.to_string(),
+        activity,
+        priority,
+        attempts: 0,
+        next_attempt_at: now,
+        last_error: None,
+        created_at: now,
+    };
+
+    info!(
+        "Queued {} delivery {} to {}",
+        priority.as_str(),
+        delivery.id,
+        inbox_url
+    );
+    db.enqueue_delivery(&delivery).await
+}
+
+/// Queue an activity for each remote actor in `actor_ids`, looking up their
+/// inboxes. Local actors read it from the database and are skipped, as is the
+/// activity's own author. With `followers_of`, that actor's accepted
+/// followers are skipped too, as the follower fan-out reaches them. The
+/// actors are addressed directly, so it goes out ahead of fan-out. Returns
+/// the inboxes it was queued for, for the fan-out to leave out.
+pub async fn enqueue_for_actors(
+    db: &DatabaseRef,
+    actor_fetch: &ActorFetchService,
+    urls: &UrlBuilder,
+    activity: &Value,
+    actor_ids: &[String],
+    followers_of: Option<&str>,
+) -> HashSet<String> {
+    let sender = activity.get("actor").and_then(|v| v.as_str());
+    let mut seen = HashSet::new();
+    let mut queued = HashSet::new();
+
+    for actor_id in actor_ids {
+        if urls.is_local(actor_id) || Some(actor_id.as_str()) == sender || !seen.insert(actor_id) {
+            continue;
+        }
+        if let Some(author) = followers_of {
+            match db.get_follow_by_actors(actor_id, author).await {
+                Ok(Some(follow)) if follow.status == "accepted" => continue,
+                Ok(_) => {}
+                // Delivering twice beats not delivering
+                Err(e) => warn!(
+                    "Could not check whether {} follows {}: {}",
+                    actor_id, author, e
+                ),
+            }
+        }
+        let inbox = match actor_fetch.find(actor_id).await {
+            Ok(actor) => actor.and_then(|actor| actor.inbox),
+            Err(e) => {
+                warn!("Database error while fetching actor {}: {}", actor_id, e);
+                continue;
+            }
+        };
+        match inbox {
+            Some(inbox) => {
+                match enqueue(db, &inbox, activity.clone(), DeliveryPriority::Direct).await {
+                    Ok(()) => {
+                        queued.insert(inbox);
+                    }
+                    Err(e) => warn!("Failed to queue delivery to {}: {}", actor_id, e),
+                }
+            }
+            None => warn!("Could not find inbox for {}", actor_id),
+        }
+    }
+    queued
+}
+
+/// Attempt a batch of the deliveries due at `now`, most urgent priority
+/// first and longest waiting first within each, every priority getting at
+/// least its share of the batch. Successful deliveries are removed; failures
+/// are retried with exponential backoff until `MAX_DELIVERY_ATTEMPTS` is
+/// reached.
+pub async fn process_queue(
+    db: &DatabaseRef,
+    service: &DeliveryService,
+    now: DateTime<Utc>,
+) -> Result<DeliveryRun, DatabaseError> {
+    let mut due = Vec::new();
+    for priority in DeliveryPriority::ALL {
+        due.push(
+            db.get_due_deliveries(now, priority, DELIVERY_BATCH_SIZE)
+                .await?,
+        );
+    }
+    let mut run = DeliveryRun::default();
+
+    for delivery in plan_batch(due, DELIVERY_BATCH_SIZE as usize) {
+        let outcome = match service
+            .deliver_activity(&delivery.inbox_url, delivery.activity.clone())
+            .await
+        {
+            Ok(()) => {
+                db.delete_delivery(&delivery.id).await?;
+                run.delivered += 1;
+                "delivered"
+            }
+            Err(e) => {
+                let attempts = delivery.attempts + 1;
+                if attempts >= MAX_DELIVERY_ATTEMPTS {
+                    warn!(
+                        "Giving up on delivery {} to {} after {} attempts: {}",
+                        delivery.id, delivery.inbox_url, attempts, e
+                    );
+                    db.delete_delivery(&delivery.id).await?;
+                    run.dropped += 1;
+                    "dropped"
+                } else {
+                    db.reschedule_delivery(
+                        &delivery.id,
+                        attempts,
+                        now + retry_delay(attempts),
+                        &e.to_string(),
+                    )
+                    .await?;
+                    run.retried += 1;
+                    "retried"
+                }
+            }
+        };
+        if let Some(metrics) = service.metrics() {
+            metrics.inc_delivery_queue_processed(delivery.priority, outcome);
+        }
+    }
+
+    Ok(run)
+}
+
+/// Pick up to `size` of the due deliveries, given for each priority in
+/// `DeliveryPriority::ALL` order: first each priority's share, then what is
+/// left over from the most urgent down
+fn plan_batch(due: Vec<Vec<DbDelivery>>, size: usize) -> Vec<DbDelivery> {
+    let mut take: Vec<usize> = DeliveryPriority::ALL
+        .iter()
+        .zip(&due)
+        .map(|(&priority, waiting)| batch_share(priority).min(waiting.len()))
+        .collect();
+
+    let mut spare = size.saturating_sub(take.iter().sum());
+    for (taken, waiting) in take.iter_mut().zip(&due) {
+        let extra = spare.min(waiting.len() - *taken);
+        *taken += extra;
+        spare -= extra;
+    }
+
+    due.into_iter()
+        .zip(take)
+        .flat_map(|(waiting, taken)| waiting.into_iter().take(taken))
+        .collect()
+}
+
+/// Measure the delivery queue as of `now`
+pub async fn backlog(db: &DatabaseRef, now: DateTime<Utc>) -> Result<QueueBacklog, DatabaseError> {
+    Ok(QueueBacklog {
+        pending: db.count_pending_deliveries().await?,
+        by_priority: db.count_pending_by_priority().await?,
+        oldest: db.oldest_pending_age(now).await?,
+        by_host: db.pending_by_host(BACKLOG_HOSTS).await?,
+    })
+}
+
+/// Measure the queue and publish it as gauges. When the oldest delivery has
+/// waited longer than `max_age`, warn about the inbox holding it up, once per
+/// log window for each host.
+pub async fn report_backlog(
+    db: &DatabaseRef,
+    metrics: &Metrics,
+    warnings: &LogDedup,
+    max_age: Duration,
+    now: DateTime<Utc>,
+) -> Result<QueueBacklog, DatabaseError> {
+    let backlog = backlog(db, now).await?;
+
+    metrics.set_delivery_queue_pending(backlog.pending);
+    metrics.set_delivery_queue_pending_by_priority(backlog.by_priority.iter().copied());
+    metrics.set_delivery_queue_oldest_age(
+        backlog
+            .oldest
+            .as_ref()
+            .map_or(0, |oldest| oldest.age.num_seconds()),
+    );
+    metrics.set_delivery_queue_pending_by_host(
+        backlog
+            .by_host
+            .iter()
+            .map(|host| (host.host.as_str(), host.pending)),
+    );
+
+    if let Some(oldest) = backlog
+        .oldest
+        .as_ref()
+        .filter(|oldest| oldest.age > max_age)
+    {
+        warnings.warn(
+            "delivery_queue_stuck",
+            &oldest.inbox_url,
+            format_args!(
+                "Oldest queued delivery has waited {} minutes; {} is holding up the queue",
+                oldest.age.num_minutes(),
+                oldest.inbox_url
+            ),
+        );
+    }
+
+    Ok(backlog)
+}
+
+fn retry_delay(attempts: u32) -> Duration {
+    let factor = 1i64 << attempts.saturating_sub(1).min(20);
+    Duration::seconds((BASE_RETRY_DELAY_SECS * factor).min(MAX_RETRY_DELAY_SECS))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::database::MockDatabase;
+    use crate::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode};
+    use mockall::predicate::*;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    /// Answers 200 for inboxes on `ok.example`, 500 otherwise
+    struct MockHttpClient;
+
+    #[async_trait::async_trait]
+    impl HttpClient for MockHttpClient {
+        async fn send(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+            let status = if request.url.starts_with("https://ok.example") {
+                200
+            } else {
+                500
+            };
+            Ok(HttpResponse {
+                status: StatusCode(status),
+                headers: HashMap::new(),
+                body: Vec::new(),
+            })
+        }
+    }
+
+    fn queued(id: &str, inbox_url: &str, attempts: u32) -> DbDelivery {
+        DbDelivery {
+            id: id.to_string(),
+            inbox_url: inbox_url.to_string(),
+            activity: json!({"id": "https://example.com/activities/1", "type": "Create"}),
+            priority: DeliveryPriority::Broadcast,
+            attempts,
+            next_attempt_at: Utc::now(),
+            last_error: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    fn service() -> DeliveryService {
+        DeliveryService::new(
+            Config::default(),
+            Arc::new(MockHttpClient),
+            Arc::new(MockDatabase::new()),
+        )
+    }
+
+    #[test]
+    fn test_retry_delay_backs_off() {
+        assert_eq!(retry_delay(1), Duration::seconds(30));
+        assert_eq!(retry_delay(2), Duration::seconds(60));
+        assert_eq!(retry_delay(4), Duration::seconds(240));
+        assert_eq!(retry_delay(30), Duration::seconds(MAX_RETRY_DELAY_SECS));
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_is_due_immediately() {
+        let mut mock = MockDatabase::new();
+        mock.expect_enqueue_delivery()
+            .withf(|d| {
+                d.inbox_url == "https://remote.example/inbox"
+                    && d.priority == DeliveryPriority::Interactive
+                    && d.attempts == 0
+                    && d.next_attempt_at <= Utc::now()
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let db: DatabaseRef = Arc::new(mock);
+        enqueue(
+            &db,
+            "https://remote.example/inbox",
+            json!({"type": "Follow"}),
+            DeliveryPriority::Interactive,
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_process_queue_delivers_retries_and_drops() {
+        let now = Utc::now();
+        let mut mock = MockDatabase::new();
+        mock.expect_get_due_deliveries()
+            .with(
+                eq(now),
+                ne(DeliveryPriority::Broadcast),
+                eq(DELIVERY_BATCH_SIZE),
+            )
+            .returning(|_, _, _| Ok(vec![]));
+        mock.expect_get_due_deliveries()
+            .with(
+                eq(now),
+                eq(DeliveryPriority::Broadcast),
+                eq(DELIVERY_BATCH_SIZE),
+            )
+            .returning(|_, _, _| {
+                Ok(vec![
+                    queued("ok", "https://ok.example/inbox", 0),
+                    queued("retry", "https://down.example/inbox", 1),
+                    queued(
+                        "drop",
+                        "https://down.example/inbox",
+                        MAX_DELIVERY_ATTEMPTS - 1,
+                    ),
+                ])
+            });
+        mock.expect_delete_delivery()
+            .with(eq("ok"))
+            .times(1)
+            .returning(|_| Ok(()));
+        mock.expect_delete_delivery()
+            .with(eq("drop"))
+            .times(1)
+            .returning(|_| Ok(()));
+        mock.expect_reschedule_delivery()
+            .withf(move |id, attempts, next, error| {
+                id == "retry"
+                    && *attempts == 2
+                    && *next == now + Duration::seconds(60)
+                    && error.contains("500")
+            })
+            .times(1)
+            .returning(|_, _, _, _| Ok(()));
+
+        let db: DatabaseRef = Arc::new(mock);
+        let run = process_queue(&db, &service(), now).await.unwrap();
+
+        assert_eq!(
+            run,
+            DeliveryRun {
+                delivered: 1,
+                retried: 1,
+                dropped: 1
+            }
+        );
+    }
+
+    fn waiting(priority: DeliveryPriority, count: usize) -> Vec<DbDelivery> {
+        (0..count)
+            .map(|n| DbDelivery {
+                priority,
+                ..queued(
+                    &format!("{}-{n}", priority.as_str()),
+                    "https://ok.example/inbox",
+                    0,
+                )
+            })
+            .collect()
+    }
+
+    fn ids(batch: &[DbDelivery]) -> Vec<&str> {
+        batch.iter().map(|d| d.id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_plan_batch_drains_by_priority_then_age() {
+        let batch = plan_batch(
+            vec![
+                waiting(DeliveryPriority::Interactive, 2),
+                waiting(DeliveryPriority::Direct, 1),
+                waiting(DeliveryPriority::Broadcast, 2),
+            ],
+            50,
+        );
+        assert_eq!(
+            ids(&batch),
+            vec![
+                "interactive-0",
+                "interactive-1",
+                "direct-0",
+                "broadcast-0",
+                "broadcast-1"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_batch_keeps_a_share_for_broadcast() {
+        let batch = plan_batch(
+            vec![
+                waiting(DeliveryPriority::Interactive, 50),
+                waiting(DeliveryPriority::Direct, 50),
+                waiting(DeliveryPriority::Broadcast, 50),
+            ],
+            50,
+        );
+        let count = |priority| batch.iter().filter(|d| d.priority == priority).count();
+        assert_eq!(batch.len(), 50);
+        assert_eq!(count(DeliveryPriority::Interactive), 25);
+        assert_eq!(count(DeliveryPriority::Direct), 15);
+        assert_eq!(count(DeliveryPriority::Broadcast), 10);
+        assert_eq!(batch[0].id, "interactive-0");
+        assert_eq!(batch[49].id, "broadcast-9");
+    }
+
+    #[test]
+    fn test_plan_batch_gives_unused_shares_to_the_most_urgent() {
+        let batch = plan_batch(
+            vec![
+                waiting(DeliveryPriority::Interactive, 40),
+                vec![],
+                waiting(DeliveryPriority::Broadcast, 40),
+            ],
+            50,
+        );
+        let count = |priority| batch.iter().filter(|d| d.priority == priority).count();
+        assert_eq!(count(DeliveryPriority::Interactive), 40);
+        assert_eq!(count(DeliveryPriority::Broadcast), 10);
+
+        // With nothing more urgent waiting, broadcast gets the whole batch
+        let batch = plan_batch(
+            vec![vec![], vec![], waiting(DeliveryPriority::Broadcast, 60)],
+            50,
+        );
+        assert_eq!(batch.len(), 50);
+    }
+}
@@ -0,0 +1,271 @@
+use crate::config::Config;
+use crate::http::caching;
+use crate::metrics::Metrics;
+use crate::services::events::{EventSubscriber, ObjectEvent};
+use crate::services::scheduler::{Clock, SystemClock};
+use actix_web::http::header::EntityTag;
+use actix_web::web::Bytes;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Serialized actor and note documents, keyed by object id, so serving them
+/// again needs no database call.
+///
+/// Entries live for at most the TTL and are dropped as soon as the event bus
+/// reports a change; past capacity the least recently used goes first. Only
+/// documents anyone may fetch belong here: callers check visibility before
+/// inserting.
+pub struct DocumentCache {
+    clock: Arc<dyn Clock>,
+    capacity: usize,
+    ttl: Duration,
+    entries: Mutex<Entries>,
+    metrics: Option<Arc<Metrics>>,
+}
+
+#[derive(Default)]
+struct Entries {
+    documents: HashMap<String, Entry>,
+    /// Bumped on every use, to find the least recently used entry
+    clock_hand: u64,
+}
+
+struct Entry {
+    document: CachedDocument,
+    /// The actor a note belongs to, so deleting the actor drops its notes
+    owner: Option<String>,
+    stored_at: DateTime<Utc>,
+    last_used: u64,
+}
+
+/// A response body with the validators it is served with
+#[derive(Debug, Clone)]
+pub struct CachedDocument {
+    pub body: Bytes,
+    pub etag: EntityTag,
+    pub last_modified: Option<DateTime<Utc>>,
+}
+
+impl DocumentCache {
+    pub fn new(clock: Arc<dyn Clock>, capacity: usize, ttl: Duration) -> Self {
+        Self {
+            clock,
+            capacity,
+            ttl,
+            entries: Mutex::new(Entries::default()),
+            metrics: None,
+        }
+    }
+
+    /// `document_cache_capacity` entries of `document_cache_ttl_secs` on the
+    /// system clock
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(
+            Arc::new(SystemClock),
+            config.document_cache_capacity,
+            Duration::seconds(config.document_cache_ttl_secs as i64),
+        )
+    }
+
+    /// Count hits and misses
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// The cached document for `id`, unless it is missing or has expired
+    pub fn get(&self, id: &str) -> Option<CachedDocument> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let now = self.clock.now();
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.clock_hand += 1;
+        let clock_hand = entries.clock_hand;
+        let document = match entries.documents.get_mut(id) {
+            Some(entry) if now - entry.stored_at < self.ttl => {
+                entry.last_used = clock_hand;
+                Some(entry.document.clone())
+            }
+            Some(_) => {
+                entries.documents.remove(id);
+                None
+            }
+            None => None,
+        };
+        drop(entries);
+
+        if let Some(metrics) = &self.metrics {
+            metrics.inc_document_cache_lookup(document.is_some());
+        }
+        document
+    }
+
+    /// Cache a freshly serialized document and return it with its ETag.
+    /// `owner` is the actor a note is attributed to.
+    pub fn insert(
+        &self,
+        id: &str,
+        owner: Option<&str>,
+        body: Vec<u8>,
+        last_modified: Option<DateTime<Utc>>,
+    ) -> CachedDocument {
+        let document = CachedDocument {
+            etag: caching::etag_for(&body),
+            body: Bytes::from(body),
+            last_modified,
+        };
+        if self.capacity == 0 {
+            return document;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if !entries.documents.contains_key(id) && entries.documents.len() >= self.capacity {
+            let least_recently_used = entries
+                .documents
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(id, _)| id.clone());
+            if let Some(evicted) = least_recently_used {
+                entries.documents.remove(&evicted);
+            }
+        }
+        entries.clock_hand += 1;
+        let entry = Entry {
+            document: document.clone(),
+            owner: owner.map(str::to_string),
+            stored_at: self.clock.now(),
+            last_used: entries.clock_hand,
+        };
+        entries.documents.insert(id.to_string(), entry);
+        document
+    }
+
+    /// Drop the document for `id`, if cached
+    pub fn invalidate(&self, id: &str) {
+        self.entries.lock().unwrap().documents.remove(id);
+    }
+
+    /// Drop an actor's document along with those of its notes
+    fn invalidate_actor(&self, actor_id: &str) {
+        self.entries
+            .lock()
+            .unwrap()
+            .documents
+            .retain(|id, entry| id != actor_id && entry.owner.as_deref() != Some(actor_id));
+    }
+
+    #[cfg(test)]
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().documents.len()
+    }
+
+    #[cfg(test)]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl EventSubscriber for DocumentCache {
+    fn on_event(&self, event: &ObjectEvent) {
+        // Notes only change by their actor's removal: no handler edits or
+        // deletes a note on its own, so there is no note event to drop one by
+        match event {
+            ObjectEvent::ActorDeleted(id) => self.invalidate_actor(id),
+            ObjectEvent::ActorUpdated(id) => self.invalidate(id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::scheduler::TestClock;
+    use chrono::TimeZone;
+
+    const ALICE: &str = "https://example.com/users/alice";
+
+    fn cache(capacity: usize) -> (Arc<TestClock>, DocumentCache) {
+        let clock = Arc::new(TestClock::new(
+            Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap(),
+        ));
+        let cache = DocumentCache::new(clock.clone(), capacity, Duration::minutes(5));
+        (clock, cache)
+    }
+
+    #[test]
+    fn test_entries_expire_after_the_ttl() {
+        let (clock, cache) = cache(10);
+        let stored = cache.insert(ALICE, None, b"{}".to_vec(), None);
+        assert_eq!(stored.etag, caching::etag_for(b"{}"));
+
+        clock.advance(Duration::minutes(4));
+        assert_eq!(cache.get(ALICE).unwrap().body, Bytes::from_static(b"{}"));
+
+        clock.advance(Duration::minutes(1));
+        assert!(cache.get(ALICE).is_none());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn test_the_least_recently_used_entry_is_evicted() {
+        let (_clock, cache) = cache(2);
+        cache.insert("a", None, b"a".to_vec(), None);
+        cache.insert("b", None, b"b".to_vec(), None);
+        cache.get("a");
+        cache.insert("c", None, b"c".to_vec(), None);
+
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("c").is_some());
+    }
+
+    #[test]
+    fn test_events_invalidate_entries() {
+        let (_clock, cache) = cache(10);
+        let note = "https://example.com/notes/1";
+        let other = "https://example.com/notes/2";
+        cache.insert(ALICE, None, b"{}".to_vec(), None);
+        cache.insert(note, Some(ALICE), b"{}".to_vec(), None);
+        cache.insert(
+            other,
+            Some("https://example.com/users/bob"),
+            b"{}".to_vec(),
+            None,
+        );
+
+        // An updated actor keeps its notes
+        cache.on_event(&ObjectEvent::ActorUpdated(ALICE.to_string()));
+        assert!(cache.get(ALICE).is_none());
+        assert!(cache.get(note).is_some());
+
+        // Deleting an actor takes its notes with it
+        cache.on_event(&ObjectEvent::ActorDeleted(ALICE.to_string()));
+        assert!(cache.get(note).is_none());
+        assert!(cache.get(other).is_some());
+    }
+
+    #[test]
+    fn test_zero_capacity_caches_nothing() {
+        let (_clock, cache) = cache(0);
+        cache.insert(ALICE, None, b"{}".to_vec(), None);
+        assert!(cache.get(ALICE).is_none());
+    }
+
+    #[test]
+    fn test_lookups_are_counted() {
+        let (_clock, cache) = cache(10);
+        let metrics = Arc::new(Metrics::new());
+        let cache = cache.with_metrics(metrics.clone());
+        cache.insert(ALICE, None, b"{}".to_vec(), None);
+        cache.get(ALICE);
+        cache.get(ALICE);
+        cache.get("https://example.com/users/bob");
+
+        let text = metrics.render();
+        assert!(text.contains(r#"feder8_document_cache_lookups_total{result="hit"} 2"#));
+        assert!(text.contains(r#"feder8_document_cache_lookups_total{result="miss"} 1"#));
+    }
+}
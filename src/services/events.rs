@@ -0,0 +1,74 @@
+use std::sync::{Arc, RwLock};
+
+/// A change to one of our actor documents. Notes can't be edited or deleted
+/// on their own yet, so only their actor's removal touches them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ObjectEvent {
+    /// A profile edit or key rotation: anything that changes the actor
+    /// document
+    ActorUpdated(String),
+    /// An actor was removed, and its notes with it
+    ActorDeleted(String),
+}
+
+/// Something that wants to hear about changes, such as a cache to invalidate
+pub trait EventSubscriber: Send + Sync {
+    fn on_event(&self, event: &ObjectEvent);
+}
+
+/// Tells subscribers about changes to our documents. Delivery is synchronous,
+/// so by the time `publish` returns every subscriber has seen the event.
+#[derive(Default)]
+pub struct EventBus {
+    subscribers: RwLock<Vec<Arc<dyn EventSubscriber>>>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&self, subscriber: Arc<dyn EventSubscriber>) {
+        self.subscribers.write().unwrap().push(subscriber);
+    }
+
+    pub fn publish(&self, event: ObjectEvent) {
+        for subscriber in self.subscribers.read().unwrap().iter() {
+            subscriber.on_event(&event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<ObjectEvent>>);
+
+    impl EventSubscriber for Recorder {
+        fn on_event(&self, event: &ObjectEvent) {
+            self.0.lock().unwrap().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn test_every_subscriber_hears_each_event() {
+        let bus = EventBus::new();
+        let first = Arc::new(Recorder::default());
+        let second = Arc::new(Recorder::default());
+        bus.subscribe(first.clone());
+        bus.subscribe(second.clone());
+
+        bus.publish(ObjectEvent::ActorDeleted(
+            "https://example.com/users/alice".into(),
+        ));
+
+        let expected = vec![ObjectEvent::ActorDeleted(
+            "https://example.com/users/alice".into(),
+        )];
+        assert_eq!(*first.0.lock().unwrap(), expected);
+        assert_eq!(*second.0.lock().unwrap(), expected);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::database::{DatabaseError, DatabaseRef};
+use crate::services::scheduler::Clock;
+use chrono::Duration;
+use std::sync::Arc;
+use tracing::info;
+
+/// Scheduler job name for garbage collection
+pub const GC_JOB: &str = "activity-gc";
+
+/// Deletes inbox activities, recorded parse failures and processing traces
+/// once they are past retention. Local actors' activities make up their
+/// outboxes and are kept, as is the latest `Follow` behind each follow
+/// relationship, which Undo and Accept refer back to.
+pub struct GarbageCollector {
+    clock: Arc<dyn Clock>,
+}
+
+impl GarbageCollector {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self { clock }
+    }
+
+    /// Delete activities stored more than `retention_days` days ago; 0 keeps
+    /// everything. Returns the number deleted.
+    pub async fn run(&self, db: &DatabaseRef, retention_days: u32) -> Result<u64, DatabaseError> {
+        if retention_days == 0 {
+            return Ok(0);
+        }
+
+        let cutoff = self.clock.now() - Duration::days(retention_days as i64);
+        let removed = db.delete_activities_older_than(cutoff).await?;
+        if removed > 0 {
+            info!("Deleted {} activities stored before {}", removed, cutoff);
+        }
+        Ok(removed)
+    }
+
+    /// Delete parse failures recorded more than `retention_days` days ago;
+    /// 0 keeps everything. Returns the number deleted.
+    pub async fn prune_parse_failures(
+        &self,
+        db: &DatabaseRef,
+        retention_days: u32,
+    ) -> Result<u64, DatabaseError> {
+        if retention_days == 0 {
+            return Ok(0);
+        }
+
+        let cutoff = self.clock.now() - Duration::days(retention_days as i64);
+        let removed = db.delete_parse_failures_older_than(cutoff).await?;
+        if removed > 0 {
+            info!(
+                "Deleted {} parse failures recorded before {}",
+                removed, cutoff
+            );
+        }
+        Ok(removed)
+    }
+
+    /// Delete processing traces recorded more than `retention_secs` seconds
+    /// ago. Returns the number deleted.
+    pub async fn prune_processing_traces(
+        &self,
+        db: &DatabaseRef,
+        retention_secs: u64,
+    ) -> Result<u64, DatabaseError> {
+        let cutoff = self.clock.now() - Duration::seconds(retention_secs as i64);
+        let removed = db.delete_processing_traces_older_than(cutoff).await?;
+        if removed > 0 {
+            info!(
+                "Deleted {} processing traces recorded before {}",
+                removed, cutoff
+            );
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::MockDatabase;
+    use crate::services::scheduler::TestClock;
+    use chrono::{TimeZone, Utc};
+    use mockall::predicate::*;
+
+    #[tokio::test]
+    async fn test_cutoff_is_retention_days_back() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let mut db = MockDatabase::new();
+        db.expect_delete_activities_older_than()
+            .with(eq(Utc.with_ymd_and_hms(2024, 5, 2, 12, 0, 0).unwrap()))
+            .times(1)
+            .returning(|_| Ok(3));
+        let db: DatabaseRef = Arc::new(db);
+
+        let gc = GarbageCollector::new(Arc::new(TestClock::new(now)));
+        assert_eq!(gc.run(&db, 30).await.unwrap(), 3);
+        // Unlimited retention leaves the database alone
+        assert_eq!(gc.run(&db, 0).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_parse_failure_cutoff() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let mut db = MockDatabase::new();
+        db.expect_delete_parse_failures_older_than()
+            .with(eq(Utc.with_ymd_and_hms(2024, 5, 25, 12, 0, 0).unwrap()))
+            .times(1)
+            .returning(|_| Ok(2));
+        let db: DatabaseRef = Arc::new(db);
+
+        let gc = GarbageCollector::new(Arc::new(TestClock::new(now)));
+        assert_eq!(gc.prune_parse_failures(&db, 7).await.unwrap(), 2);
+        assert_eq!(gc.prune_parse_failures(&db, 0).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_processing_trace_cutoff() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let mut db = MockDatabase::new();
+        db.expect_delete_processing_traces_older_than()
+            .with(eq(Utc.with_ymd_and_hms(2024, 6, 1, 11, 0, 0).unwrap()))
+            .times(1)
+            .returning(|_| Ok(3));
+        let db: DatabaseRef = Arc::new(db);
+
+        let gc = GarbageCollector::new(Arc::new(TestClock::new(now)));
+        assert_eq!(gc.prune_processing_traces(&db, 3600).await.unwrap(), 3);
+    }
+}
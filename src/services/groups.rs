@@ -0,0 +1,115 @@
+//! Group actors. A Group boosts every public note that mentions it, so its
+//! followers see what was posted to the group.
+
+use crate::database::{DatabaseRef, DbActivity, DbActor, PublishState};
+use crate::http::request_id;
+use crate::models::addressing::PUBLIC;
+use crate::models::object::Tag;
+use crate::models::{ContextBuilder, Visibility};
+use crate::services::delivery::DeliveryService;
+use crate::urls::UrlBuilder;
+use serde_json::Value;
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Actor type of a person's account, the default
+pub const PERSON_ACTOR_TYPE: &str = "Person";
+
+/// Actor type of a group
+pub const GROUP_ACTOR_TYPE: &str = "Group";
+
+pub fn is_group_actor(actor: &DbActor) -> bool {
+    actor.actor_type == GROUP_ACTOR_TYPE
+}
+
+/// Whether a note's `tag` property mentions `actor_id`
+pub fn mentions(object: &Value, actor_id: &str) -> bool {
+    Tag::mentions_of(object)
+        .iter()
+        .any(|mention| mention.href.as_deref() == Some(actor_id))
+}
+
+/// Announce a note on behalf of `group` when the note mentions it and may be
+/// shared. The Announce is stored in the group's outbox and fanned out to
+/// its followers in the background.
+pub async fn boost_mention(
+    db: &DatabaseRef,
+    delivery: Arc<DeliveryService>,
+    urls: &UrlBuilder,
+    group: &DbActor,
+    note: &Value,
+    visibility: Visibility,
+) {
+    let Some(note_id) = note.get("id").and_then(|v| v.as_str()) else {
+        return;
+    };
+    // Followers-only and direct notes stay with their audience
+    if !mentions(note, &group.id) || !visibility.is_publicly_visible() {
+        return;
+    }
+
+    let now = chrono::Utc::now();
+    let announce = DbActivity {
+        id: urls.activity(&uuid::Uuid::new_v4().to_string()),
+        actor_id: group.id.clone(),
+        activity_type: "Announce".to_string(),
+        object: Value::String(note_id.to_string()),
+        target: None,
+        to_recipients: vec![PUBLIC.to_string()],
+        cc_recipients: vec![urls.followers(&group.username)],
+        published: now,
+        visibility: Visibility::Public,
+        state: PublishState::Published,
+        created_at: now,
+    };
+    if let Err(e) = db.create_activity(&announce).await {
+        warn!("Database error while storing {}'s boost: {}", group.id, e);
+        return;
+    }
+    info!("{} boosted {}", group.id, note_id);
+
+    let document = announce_document(&announce);
+    let group_id = group.id.clone();
+    tokio::spawn(request_id::propagate(async move {
+        if let Err(e) = delivery.fan_out_activity(document, &group_id).await {
+            warn!(
+                "Database error while fanning out to followers of {}: {}",
+                group_id, e
+            );
+        }
+    }));
+}
+
+fn announce_document(announce: &DbActivity) -> Value {
+    let mut document = serde_json::json!({
+        "id": announce.id,
+        "type": announce.activity_type,
+        "actor": announce.actor_id,
+        "object": announce.object,
+        "to": announce.to_recipients,
+        "cc": announce.cc_recipients,
+        "published": announce.published
+    });
+    document["@context"] = ContextBuilder::for_document(&document).build().into();
+    document
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_mentions_matches_the_href() {
+        let group = "https://example.com/users/hikers";
+        let note = json!({"tag": [
+            {"type": "Hashtag", "name": "#hiking", "href": group},
+            {"type": "Mention", "name": "@hikers", "href": group}
+        ]});
+        assert!(mentions(&note, group));
+
+        let note = json!({"tag": {"type": "Hashtag", "name": "#hikers", "href": group}});
+        assert!(!mentions(&note, group));
+        assert!(!mentions(&json!({}), group));
+    }
+}
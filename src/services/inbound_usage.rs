@@ -0,0 +1,244 @@
+use crate::database::{DatabaseError, DatabaseRef, DbInboundUsage};
+use crate::metrics::Metrics;
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use tracing::info;
+
+/// Scheduler job name for saving the usage snapshot
+pub const SNAPSHOT_JOB: &str = "inbound-usage-snapshot";
+
+/// How far back a host's bytes count against its quota
+pub fn window() -> Duration {
+    Duration::hours(1)
+}
+
+/// Bytes received in each minute, oldest first
+type Buckets = VecDeque<(DateTime<Utc>, u64)>;
+
+/// Bytes each remote host sent the inbox over the last hour, in one-minute
+/// buckets so the window slides without keeping every payload. A quota of 0
+/// leaves usage tracked but never refused. Hosts are whatever usage is keyed
+/// by; the inbox uses the connecting address.
+pub struct InboundUsage {
+    quota_bytes: u64,
+    hosts: Mutex<HashMap<String, Buckets>>,
+}
+
+impl InboundUsage {
+    pub fn new(quota_bytes: u64) -> Self {
+        Self {
+            quota_bytes,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Bytes a host may send per hour; 0 when unlimited
+    pub fn quota_bytes(&self) -> u64 {
+        self.quota_bytes
+    }
+
+    /// Count `bytes` from `host` at `now` if they fit its quota. Otherwise
+    /// nothing is counted and the error says how many seconds until enough
+    /// of the window has slid past for them to fit.
+    pub fn try_charge(&self, host: &str, bytes: u64, now: DateTime<Utc>) -> Result<u64, u64> {
+        let mut hosts = self.hosts.lock().unwrap();
+        let buckets = hosts.entry(host.to_string()).or_default();
+        prune(buckets, now);
+
+        let used: u64 = buckets.iter().map(|(_, bytes)| bytes).sum();
+        if self.quota_bytes == 0 || used.saturating_add(bytes) <= self.quota_bytes {
+            add(buckets, bytes, now);
+            return Ok(used + bytes);
+        }
+        Err(retry_after(buckets, used, bytes, self.quota_bytes, now))
+    }
+
+    /// Count `bytes` from `host` at `now` whatever its quota, for hosts that
+    /// are exempt from it
+    pub fn charge(&self, host: &str, bytes: u64, now: DateTime<Utc>) -> u64 {
+        let mut hosts = self.hosts.lock().unwrap();
+        let buckets = hosts.entry(host.to_string()).or_default();
+        prune(buckets, now);
+        add(buckets, bytes, now);
+        buckets.iter().map(|(_, bytes)| bytes).sum()
+    }
+
+    /// Bytes from each host within the window, most first
+    pub fn by_host(&self, now: DateTime<Utc>) -> Vec<(String, u64)> {
+        let mut hosts = self.hosts.lock().unwrap();
+        hosts.retain(|_, buckets| {
+            prune(buckets, now);
+            !buckets.is_empty()
+        });
+
+        let mut usage: Vec<(String, u64)> = hosts
+            .iter()
+            .map(|(host, buckets)| (host.clone(), buckets.iter().map(|(_, b)| b).sum()))
+            .collect();
+        usage.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        usage
+    }
+
+    /// Every bucket within the window, for saving
+    pub fn snapshot(&self, now: DateTime<Utc>) -> Vec<DbInboundUsage> {
+        let mut hosts = self.hosts.lock().unwrap();
+        let mut rows = Vec::new();
+        hosts.retain(|host, buckets| {
+            prune(buckets, now);
+            rows.extend(buckets.iter().map(|&(minute, bytes)| DbInboundUsage {
+                host: host.clone(),
+                minute,
+                bytes,
+            }));
+            !buckets.is_empty()
+        });
+        rows
+    }
+
+    /// Add saved buckets to what has been counted since
+    pub fn restore(&self, rows: &[DbInboundUsage], now: DateTime<Utc>) {
+        let mut hosts = self.hosts.lock().unwrap();
+        for row in rows {
+            let buckets = hosts.entry(row.host.clone()).or_default();
+            match buckets.iter().position(|&(minute, _)| minute >= row.minute) {
+                Some(i) if buckets[i].0 == row.minute => buckets[i].1 += row.bytes,
+                Some(i) => buckets.insert(i, (row.minute, row.bytes)),
+                None => buckets.push_back((row.minute, row.bytes)),
+            }
+        }
+        hosts.retain(|_, buckets| {
+            prune(buckets, now);
+            !buckets.is_empty()
+        });
+    }
+}
+
+/// Save the usage so far, and publish it as metrics
+pub async fn persist(
+    usage: &InboundUsage,
+    db: &DatabaseRef,
+    metrics: &Metrics,
+    now: DateTime<Utc>,
+) -> Result<(), DatabaseError> {
+    metrics.set_inbox_host_bytes(
+        usage
+            .by_host(now)
+            .iter()
+            .map(|(host, bytes)| (host.as_str(), *bytes)),
+    );
+    db.replace_inbound_usage(&usage.snapshot(now)).await
+}
+
+/// Pick up the usage saved before a restart
+pub async fn restore(
+    usage: &InboundUsage,
+    db: &DatabaseRef,
+    now: DateTime<Utc>,
+) -> Result<usize, DatabaseError> {
+    let rows = db.get_inbound_usage(now - window()).await?;
+    usage.restore(&rows, now);
+    info!("Restored inbound usage of {} host-minutes", rows.len());
+    Ok(rows.len())
+}
+
+fn minute_of(now: DateTime<Utc>) -> DateTime<Utc> {
+    now.duration_trunc(Duration::minutes(1)).unwrap_or(now)
+}
+
+/// Drop buckets that have slid out of the window
+fn prune(buckets: &mut Buckets, now: DateTime<Utc>) {
+    while buckets
+        .front()
+        .is_some_and(|&(minute, _)| minute + window() <= now)
+    {
+        buckets.pop_front();
+    }
+}
+
+fn add(buckets: &mut Buckets, bytes: u64, now: DateTime<Utc>) {
+    let minute = minute_of(now);
+    match buckets.back_mut() {
+        Some((last, total)) if *last == minute => *total += bytes,
+        _ => buckets.push_back((minute, bytes)),
+    }
+}
+
+/// Seconds until the oldest buckets have expired enough for `bytes` more to
+/// fit, or the whole window when they never will
+fn retry_after(buckets: &Buckets, used: u64, bytes: u64, quota: u64, now: DateTime<Utc>) -> u64 {
+    let mut remaining = used;
+    for &(minute, expiring) in buckets {
+        remaining -= expiring;
+        if remaining.saturating_add(bytes) <= quota {
+            return (minute + window() - now).num_seconds().max(1) as u64;
+        }
+    }
+    window().num_seconds() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(minutes: i64, seconds: i64) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap()
+            + Duration::minutes(minutes)
+            + Duration::seconds(seconds)
+    }
+
+    #[test]
+    fn test_quota_slides_with_the_window() {
+        let usage = InboundUsage::new(1000);
+
+        assert_eq!(usage.try_charge("a.example", 400, at(0, 0)), Ok(400));
+        assert_eq!(usage.try_charge("a.example", 400, at(10, 0)), Ok(800));
+        // Until the first minute's bytes expire an hour later
+        assert_eq!(usage.try_charge("a.example", 400, at(30, 0)), Err(30 * 60));
+        assert_eq!(usage.try_charge("a.example", 400, at(59, 30)), Err(30));
+        assert_eq!(usage.try_charge("a.example", 400, at(60, 0)), Ok(800));
+
+        // Other hosts have their own quota
+        assert_eq!(usage.try_charge("b.example", 1000, at(60, 0)), Ok(1000));
+        assert_eq!(
+            usage.by_host(at(60, 0)),
+            vec![
+                ("b.example".to_string(), 1000),
+                ("a.example".to_string(), 800)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_oversized_payloads_wait_the_whole_window() {
+        let usage = InboundUsage::new(100);
+        assert_eq!(usage.try_charge("a.example", 101, at(0, 0)), Err(3600));
+        assert!(usage.by_host(at(0, 0)).is_empty());
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let usage = InboundUsage::new(0);
+        usage.charge("a.example", 10, at(0, 5));
+        usage.charge("a.example", 20, at(0, 50));
+        usage.charge("a.example", 30, at(5, 0));
+        let snapshot = usage.snapshot(at(5, 0));
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[0].minute, at(0, 0));
+        assert_eq!(snapshot[0].bytes, 30);
+
+        let restarted = InboundUsage::new(0);
+        restarted.charge("a.example", 5, at(6, 0));
+        restarted.restore(&snapshot, at(6, 0));
+        assert_eq!(
+            restarted.by_host(at(6, 0)),
+            vec![("a.example".to_string(), 65)]
+        );
+        // Saved minutes still age out
+        assert_eq!(
+            restarted.by_host(at(60, 0)),
+            vec![("a.example".to_string(), 35)]
+        );
+    }
+}
@@ -0,0 +1,93 @@
+use crate::config::Config;
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+
+/// An activity the inbox stored for one of our actors
+#[derive(Debug, Clone, PartialEq)]
+pub struct InboxEvent {
+    /// The local actor whose inbox it arrived in
+    pub actor_id: String,
+    pub activity: Value,
+}
+
+/// Fans activities the inbox stores out to live clients. Each client holds
+/// a receiver and picks out its own actor's events; one that falls more
+/// than the channel's capacity behind misses the oldest ones.
+pub struct InboxStream {
+    sender: broadcast::Sender<Arc<InboxEvent>>,
+    closing: CancellationToken,
+}
+
+impl InboxStream {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity.max(1));
+        Self {
+            sender,
+            closing: CancellationToken::new(),
+        }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(config.stream_channel_capacity)
+    }
+
+    /// Tell connected clients of `actor_id` about `activity`. Nobody
+    /// listening is not an error.
+    pub fn publish(&self, actor_id: &str, activity: Value) {
+        let _ = self.sender.send(Arc::new(InboxEvent {
+            actor_id: actor_id.to_string(),
+            activity,
+        }));
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<InboxEvent>> {
+        self.sender.subscribe()
+    }
+
+    /// Cancelled once the stream is closed; clients end when it is
+    pub fn closing(&self) -> CancellationToken {
+        self.closing.clone()
+    }
+
+    /// End every client's stream, so open connections don't hold up a
+    /// graceful shutdown until it times out
+    pub fn close(&self) {
+        self.closing.cancel();
+    }
+
+    /// Clients connected right now
+    #[allow(dead_code)]
+    pub fn subscribers(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_slow_subscribers_skip_what_they_missed() {
+        let stream = InboxStream::new(2);
+        let mut receiver = stream.subscribe();
+        assert_eq!(stream.subscribers(), 1);
+
+        for n in 0..3 {
+            stream.publish("https://example.com/users/alice", json!({ "n": n }));
+        }
+        assert!(matches!(
+            receiver.recv().await,
+            Err(broadcast::error::RecvError::Lagged(1))
+        ));
+        assert_eq!(receiver.recv().await.unwrap().activity["n"], 1);
+        assert_eq!(receiver.recv().await.unwrap().activity["n"], 2);
+
+        drop(receiver);
+        assert_eq!(stream.subscribers(), 0);
+        // Publishing to nobody is fine
+        stream.publish("https://example.com/users/alice", json!({}));
+    }
+}
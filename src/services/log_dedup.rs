@@ -0,0 +1,221 @@
+use crate::config::Config;
+use crate::metrics::Metrics;
+use crate::services::scheduler::{Clock, SystemClock};
+use crate::urls::host_of;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+use tracing::{debug, warn};
+
+/// Background job that reports warnings suppressed in closed windows
+pub const LOG_SUMMARY_JOB: &str = "log-dedup-summary";
+
+/// Keeps one misbehaving peer from flooding the log with the same warning.
+///
+/// Warnings are keyed by a fixed name for the message and the peer's host.
+/// The first in each window is logged at warn; repeats go to debug and are
+/// counted, and the count is logged once the window closes.
+pub struct LogDedup {
+    clock: Arc<dyn Clock>,
+    window: Duration,
+    windows: Mutex<HashMap<(&'static str, String), Window>>,
+    metrics: Option<Arc<Metrics>>,
+}
+
+struct Window {
+    opened_at: DateTime<Utc>,
+    suppressed: u64,
+}
+
+/// Repeats of one warning about one host that were logged at debug
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Suppressed {
+    pub warning: &'static str,
+    pub host: String,
+    pub count: u64,
+}
+
+impl LogDedup {
+    pub fn new(clock: Arc<dyn Clock>, window: Duration) -> Self {
+        Self {
+            clock,
+            window,
+            windows: Mutex::new(HashMap::new()),
+            metrics: None,
+        }
+    }
+
+    /// Windows of `log_dedup_window_secs` on the system clock
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(
+            Arc::new(SystemClock),
+            Duration::seconds(config.log_dedup_window_secs as i64),
+        )
+    }
+
+    /// Count suppressed repeats per warning
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Log `message` about `peer` (a URL or host) at warn, or at debug if it
+    /// has already been logged for that host this window. Returns whether it
+    /// was logged at warn.
+    pub fn warn(&self, warning: &'static str, peer: &str, message: impl Display) -> bool {
+        let host = host_of(peer).unwrap_or_else(|| peer.to_string());
+        let now = self.clock.now();
+
+        let mut windows = self.windows.lock().unwrap();
+        let Some(window) = windows.get_mut(&(warning, host.clone())) else {
+            windows.insert(
+                (warning, host),
+                Window {
+                    opened_at: now,
+                    suppressed: 0,
+                },
+            );
+            warn!("{}", message);
+            return true;
+        };
+
+        if now - window.opened_at >= self.window {
+            // A new window: report the last one and start over
+            self.report(warning, peer, window.suppressed);
+            *window = Window {
+                opened_at: now,
+                suppressed: 0,
+            };
+            warn!("{}", message);
+            return true;
+        }
+
+        window.suppressed += 1;
+        if let Some(metrics) = &self.metrics {
+            metrics.inc_log_suppressed(warning);
+        }
+        debug!("{}", message);
+        false
+    }
+
+    /// Log and return what was suppressed in windows that have closed, and
+    /// forget them so the next occurrence is logged at warn again
+    pub fn summarize(&self) -> Vec<Suppressed> {
+        let now = self.clock.now();
+        let mut summaries = Vec::new();
+
+        self.windows
+            .lock()
+            .unwrap()
+            .retain(|(warning, host), window| {
+                if now - window.opened_at < self.window {
+                    return true;
+                }
+                if window.suppressed > 0 {
+                    self.report(warning, host, window.suppressed);
+                    summaries.push(Suppressed {
+                        warning,
+                        host: host.clone(),
+                        count: window.suppressed,
+                    });
+                }
+                false
+            });
+        summaries
+    }
+
+    fn report(&self, warning: &str, peer: &str, suppressed: u64) {
+        if suppressed > 0 {
+            warn!(
+                "{} repeated {} more times for {} in the last {} minutes",
+                warning,
+                suppressed,
+                host_of(peer).as_deref().unwrap_or(peer),
+                self.window.num_minutes()
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::scheduler::TestClock;
+    use chrono::TimeZone;
+    use tracing_test::traced_test;
+
+    fn dedup() -> (Arc<TestClock>, LogDedup) {
+        let clock = Arc::new(TestClock::new(
+            Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap(),
+        ));
+        let dedup = LogDedup::new(clock.clone(), Duration::minutes(5));
+        (clock, dedup)
+    }
+
+    #[test]
+    fn test_repeats_within_the_window_are_suppressed() {
+        let (clock, dedup) = dedup();
+
+        assert!(dedup.warn("delivery_failed", "https://bad.example/inbox", "failed"));
+        for _ in 0..3 {
+            clock.advance(Duration::seconds(30));
+            assert!(!dedup.warn("delivery_failed", "https://bad.example/users/a", "failed"));
+        }
+
+        // Another host, or another warning, has its own window
+        assert!(dedup.warn("delivery_failed", "https://other.example/inbox", "failed"));
+        assert!(dedup.warn(
+            "unknown_activity_type",
+            "https://bad.example/users/a",
+            "odd"
+        ));
+
+        clock.advance(Duration::minutes(5));
+        assert!(dedup.warn("delivery_failed", "https://bad.example/inbox", "failed"));
+    }
+
+    #[test]
+    #[traced_test]
+    fn test_summary_reports_closed_windows_once() {
+        let (clock, dedup) = dedup();
+        for _ in 0..4 {
+            dedup.warn("delivery_failed", "https://bad.example/inbox", "failed");
+        }
+        dedup.warn("delivery_failed", "https://quiet.example/inbox", "failed");
+
+        // Nothing has closed yet
+        assert!(dedup.summarize().is_empty());
+
+        clock.advance(Duration::minutes(5));
+        assert_eq!(
+            dedup.summarize(),
+            vec![Suppressed {
+                warning: "delivery_failed",
+                host: "bad.example".to_string(),
+                count: 3,
+            }]
+        );
+        assert!(logs_contain(
+            "delivery_failed repeated 3 more times for bad.example in the last 5 minutes"
+        ));
+        assert!(dedup.summarize().is_empty());
+
+        // The window was forgotten, so the next failure is logged at warn
+        assert!(dedup.warn("delivery_failed", "https://bad.example/inbox", "failed"));
+    }
+
+    #[test]
+    fn test_suppressed_repeats_are_counted_in_metrics() {
+        let (_clock, dedup) = dedup();
+        let metrics = Arc::new(Metrics::new());
+        let dedup = dedup.with_metrics(metrics.clone());
+        for _ in 0..3 {
+            dedup.warn("delivery_failed", "https://bad.example/inbox", "failed");
+        }
+
+        assert!(metrics
+            .render()
+            .contains("feder8_log_suppressed_total{warning=\"delivery_failed\"} 2"));
+    }
+}
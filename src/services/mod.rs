@@ -1 +1,27 @@
+pub mod actor_fetch;
+pub mod actor_profiles;
+pub mod addressing;
+pub mod backpressure;
+pub mod classify;
 pub mod delivery;
+pub mod delivery_queue;
+pub mod document_cache;
+pub mod events;
+pub mod gc;
+pub mod groups;
+pub mod inbound_usage;
+pub mod inbox_stream;
+pub mod keys;
+pub mod log_dedup;
+pub mod parse_failures;
+pub mod pending_accepts;
+pub mod processing_trace;
+pub mod published;
+pub mod rate_limit;
+pub mod relay;
+pub mod scheduled_publishing;
+pub mod scheduler;
+pub mod search;
+pub mod seen_activities;
+pub mod threads;
+pub mod webfinger;
@@ -0,0 +1,245 @@
+use crate::database::{DatabaseRef, DbParseFailure};
+use crate::metrics::Metrics;
+use chrono::Utc;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use serde_path_to_error::Segment;
+use std::sync::Arc;
+use tracing::warn;
+
+/// Largest document stored whole; longer ones are cut short
+pub const MAX_STORED_DOCUMENT_BYTES: usize = 64 * 1024;
+
+/// Longest string kept in a logged value sample
+const MAX_SAMPLE_LEN: usize = 120;
+
+/// A remote document that does not have the shape we deserialize it into
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid {kind} document from {url} at {pointer:?}: {message}")]
+pub struct ParseFailure {
+    pub kind: &'static str,
+    pub url: String,
+    /// JSON pointer to where deserialization stopped; for a missing field,
+    /// the field itself
+    pub pointer: String,
+    pub message: String,
+    pub document: Value,
+}
+
+/// Deserializes remote documents, counting and storing the ones that do not
+/// parse so their shape can be turned into a fixture later
+#[derive(Clone)]
+pub struct ParseFailureRecorder {
+    db: DatabaseRef,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl ParseFailureRecorder {
+    pub fn new(db: DatabaseRef, metrics: Option<Arc<Metrics>>) -> Self {
+        Self { db, metrics }
+    }
+
+    /// Deserialize `document`, fetched from `url` as a `kind`, recording it
+    /// if it doesn't fit
+    pub async fn parse<T: DeserializeOwned>(
+        &self,
+        kind: &'static str,
+        url: &str,
+        document: Value,
+    ) -> Result<T, ParseFailure> {
+        let failure = match parse_document(kind, url, document) {
+            Ok(parsed) => return Ok(parsed),
+            Err(failure) => failure,
+        };
+        self.record(&failure).await;
+        Err(failure)
+    }
+
+    async fn record(&self, failure: &ParseFailure) {
+        if let Some(metrics) = &self.metrics {
+            metrics.inc_parse_failure(failure.kind, &fold_indices(&failure.pointer));
+        }
+
+        let (document, truncated) = cap(failure.document.to_string(), MAX_STORED_DOCUMENT_BYTES);
+        let row = DbParseFailure {
+            id: uuid::Uuid::new_v4().to_string(),
+            kind: failure.kind.to_string(),
+            url: failure.url.clone(),
+            error_path: failure.pointer.clone(),
+            error: failure.message.clone(),
+            document,
+            truncated,
+            created_at: Utc::now(),
+        };
+        if let Err(e) = self.db.create_parse_failure(&row).await {
+            warn!(
+                "Database error while recording parse failure for {}: {}",
+                failure.url, e
+            );
+        }
+    }
+}
+
+/// Deserialize `document`, logging where and on what it failed
+pub fn parse_document<T: DeserializeOwned>(
+    kind: &'static str,
+    url: &str,
+    document: Value,
+) -> Result<T, ParseFailure> {
+    let error = match serde_path_to_error::deserialize(&document) {
+        Ok(parsed) => return Ok(parsed),
+        Err(error) => error,
+    };
+
+    let mut pointer: String = error
+        .path()
+        .iter()
+        .filter_map(|segment| match segment {
+            Segment::Seq { index } => Some(index.to_string()),
+            Segment::Map { key } => Some(key.clone()),
+            Segment::Enum { variant } => Some(variant.clone()),
+            Segment::Unknown => None,
+        })
+        .map(|token| format!("/{}", escape(&token)))
+        .collect();
+    let message = error.into_inner().to_string();
+    if let Some(field) = missing_field(&message) {
+        pointer = format!("{pointer}/{}", escape(field));
+    }
+
+    warn!(
+        "Could not parse {} from {} at {:?} ({}): {}",
+        kind,
+        url,
+        pointer,
+        sample(document.pointer(&pointer)),
+        message
+    );
+    Err(ParseFailure {
+        kind,
+        url: url.to_string(),
+        pointer,
+        message,
+        document,
+    })
+}
+
+/// The field named by serde's "missing field `x`" message
+fn missing_field(message: &str) -> Option<&str> {
+    message
+        .strip_prefix("missing field `")?
+        .split_once('`')
+        .map(|(field, _)| field)
+}
+
+/// Escape a JSON pointer reference token
+fn escape(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+/// The pointer with array indices replaced by `*`, for use as a metric label
+fn fold_indices(pointer: &str) -> String {
+    pointer
+        .split('/')
+        .map(|token| {
+            if !token.is_empty() && token.bytes().all(|b| b.is_ascii_digit()) {
+                "*"
+            } else {
+                token
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// A loggable description of a value that doesn't repeat its contents:
+/// strings become their length, containers their keys or size
+fn sample(value: Option<&Value>) -> String {
+    let sample = match value {
+        None => "missing".to_string(),
+        Some(Value::String(s)) => format!("string of {} chars", s.chars().count()),
+        Some(Value::Array(items)) => format!("array of {} items", items.len()),
+        Some(Value::Object(map)) => format!(
+            "object with keys {}",
+            map.keys()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        Some(other) => other.to_string(),
+    };
+    cap(sample, MAX_SAMPLE_LEN).0
+}
+
+/// `text` cut to at most `limit` bytes on a character boundary, and whether
+/// anything was cut
+fn cap(mut text: String, limit: usize) -> (String, bool) {
+    if text.len() <= limit {
+        return (text, false);
+    }
+    let end = (0..=limit)
+        .rev()
+        .find(|&i| text.is_char_boundary(i))
+        .unwrap_or(0);
+    text.truncate(end);
+    (text, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Doc {
+        name: String,
+        tags: Vec<Tag>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Tag {
+        href: String,
+    }
+
+    fn failure(document: Value) -> ParseFailure {
+        parse_document::<Doc>("doc", "https://remote.example/doc", document).unwrap_err()
+    }
+
+    #[test]
+    fn test_pointer_names_the_failing_value() {
+        let failure = failure(json!({"name": "x", "tags": [{"href": "a"}, {"href": 5}]}));
+        assert_eq!(failure.pointer, "/tags/1/href");
+        assert!(failure.message.starts_with("invalid type: integer `5`"));
+        assert_eq!(fold_indices(&failure.pointer), "/tags/*/href");
+    }
+
+    #[test]
+    fn test_pointer_names_a_missing_field() {
+        assert_eq!(failure(json!({"tags": []})).pointer, "/name");
+        assert_eq!(
+            failure(json!({"name": "x", "tags": [{}]})).pointer,
+            "/tags/0/href"
+        );
+    }
+
+    #[test]
+    fn test_sample_leaves_out_contents() {
+        assert_eq!(sample(Some(&json!("secret"))), "string of 6 chars");
+        assert_eq!(sample(Some(&json!([1, 2]))), "array of 2 items");
+        assert_eq!(
+            sample(Some(&json!({"a": 1, "b": 2}))),
+            "object with keys a, b"
+        );
+        assert_eq!(sample(Some(&json!(5))), "5");
+        assert_eq!(sample(None), "missing");
+    }
+
+    #[test]
+    fn test_cap_respects_char_boundaries() {
+        assert_eq!(cap("short".to_string(), 10), ("short".to_string(), false));
+        assert_eq!(cap("ééé".to_string(), 3), ("é".to_string(), true));
+    }
+}
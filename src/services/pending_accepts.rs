@@ -0,0 +1,226 @@
+use crate::database::{DatabaseError, DatabaseRef, DbFollowRelation, DbPendingAccept};
+use chrono::{Duration, Utc};
+use serde_json::Value;
+use tracing::info;
+
+/// Park an `Accept` or `Undo` that refers to a follow relationship we have
+/// not stored yet, so it can be applied once the follow shows up
+pub async fn park_activity(
+    db: &DatabaseRef,
+    activity_type: &str,
+    follower_id: &str,
+    following_id: &str,
+    activity: &Value,
+    ttl: Duration,
+) -> Result<(), DatabaseError> {
+    let now = Utc::now();
+
+    // Opportunistically drop anything that already expired
+    db.delete_expired_pending_accepts(now).await?;
+
+    let pending = DbPendingAccept {
+        id: uuid::Uuid::new_v4().to_string(),
+        activity_type: activity_type.to_string(),
+        follower_id: follower_id.to_string(),
+        following_id: following_id.to_string(),
+        activity: activity.clone(),
+        created_at: now,
+        expires_at: now + ttl,
+    };
+
+    info!(
+        "Parking {} for unknown follow {} -> {} until {}",
+        activity_type, follower_id, following_id, pending.expires_at
+    );
+    db.create_pending_accept(&pending).await
+}
+
+/// Drop parked activities whose TTL has passed. Run periodically by the
+/// scheduler; returns the number removed.
+pub async fn prune_expired(db: &DatabaseRef) -> Result<u64, DatabaseError> {
+    let removed = db.delete_expired_pending_accepts(Utc::now()).await?;
+    if removed > 0 {
+        info!("Pruned {} expired parked activities", removed);
+    }
+    Ok(removed)
+}
+
+/// Apply any parked activities matching a freshly created follow, in the
+/// order they were received. Returns the number of activities applied.
+pub async fn apply_parked_activities(
+    db: &DatabaseRef,
+    follow: &DbFollowRelation,
+) -> Result<usize, DatabaseError> {
+    let parked = db
+        .get_pending_accepts(&follow.follower_id, &follow.following_id, Utc::now())
+        .await?;
+
+    let mut applied = 0;
+    let mut removed = false;
+    for pending in parked {
+        // Once the follow has been undone, later parked entries are moot
+        if !removed {
+            match pending.activity_type.as_str() {
+                "Accept" => {
+                    db.update_follow_status(&follow.id, "accepted").await?;
+                    applied += 1;
+                }
+                "Undo" => {
+                    db.delete_follow(&follow.id).await?;
+                    removed = true;
+                    applied += 1;
+                }
+                _ => {}
+            }
+            info!(
+                "Applied parked {} to follow {}",
+                pending.activity_type, follow.id
+            );
+        }
+
+        db.delete_pending_accept(&pending.id).await?;
+    }
+
+    Ok(applied)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::MockDatabase;
+    use mockall::predicate::*;
+    use mockall::Sequence;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    fn create_test_follow() -> DbFollowRelation {
+        DbFollowRelation {
+            id: "https://example.com/follows/1".to_string(),
+            follower_id: "https://remote.example/users/bob".to_string(),
+            following_id: "https://example.com/users/alice".to_string(),
+            status: "pending".to_string(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn parked(id: &str, activity_type: &str) -> DbPendingAccept {
+        let follow = create_test_follow();
+        DbPendingAccept {
+            id: id.to_string(),
+            activity_type: activity_type.to_string(),
+            follower_id: follow.follower_id,
+            following_id: follow.following_id,
+            activity: json!({"type": activity_type}),
+            created_at: Utc::now(),
+            expires_at: Utc::now() + Duration::minutes(10),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_park_activity_sets_expiry() {
+        let mut mock = MockDatabase::new();
+        mock.expect_delete_expired_pending_accepts()
+            .times(1)
+            .returning(|_| Ok(0));
+        mock.expect_create_pending_accept()
+            .withf(|pending| {
+                pending.activity_type == "Accept"
+                    && pending.follower_id == "https://example.com/users/alice"
+                    && pending.expires_at - pending.created_at == Duration::seconds(60)
+            })
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let db: DatabaseRef = Arc::new(mock);
+        park_activity(
+            &db,
+            "Accept",
+            "https://example.com/users/alice",
+            "https://remote.example/users/bob",
+            &json!({"type": "Accept"}),
+            Duration::seconds(60),
+        )
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_apply_parked_accept() {
+        let mut mock = MockDatabase::new();
+        mock.expect_get_pending_accepts()
+            .returning(|_, _, _| Ok(vec![parked("p1", "Accept")]));
+        mock.expect_update_follow_status()
+            .with(eq("https://example.com/follows/1"), eq("accepted"))
+            .times(1)
+            .returning(|_, _| Ok(()));
+        mock.expect_delete_pending_accept()
+            .with(eq("p1"))
+            .times(1)
+            .returning(|_| Ok(()));
+
+        let db: DatabaseRef = Arc::new(mock);
+        let applied = apply_parked_activities(&db, &create_test_follow())
+            .await
+            .unwrap();
+
+        assert_eq!(applied, 1);
+    }
+
+    #[tokio::test]
+    async fn test_apply_parked_undo_discards_later_entries() {
+        let mut mock = MockDatabase::new();
+        let mut seq = Sequence::new();
+        mock.expect_get_pending_accepts()
+            .returning(|_, _, _| Ok(vec![parked("p1", "Undo"), parked("p2", "Accept")]));
+        mock.expect_delete_follow()
+            .with(eq("https://example.com/follows/1"))
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| Ok(()));
+        mock.expect_delete_pending_accept()
+            .with(eq("p1"))
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| Ok(()));
+        mock.expect_delete_pending_accept()
+            .with(eq("p2"))
+            .times(1)
+            .in_sequence(&mut seq)
+            .returning(|_| Ok(()));
+        mock.expect_update_follow_status().never();
+
+        let db: DatabaseRef = Arc::new(mock);
+        let applied = apply_parked_activities(&db, &create_test_follow())
+            .await
+            .unwrap();
+
+        assert_eq!(applied, 1);
+    }
+
+    #[tokio::test]
+    async fn test_apply_parked_nothing_pending() {
+        let mut mock = MockDatabase::new();
+        mock.expect_get_pending_accepts()
+            .returning(|_, _, _| Ok(vec![]));
+
+        let db: DatabaseRef = Arc::new(mock);
+        let applied = apply_parked_activities(&db, &create_test_follow())
+            .await
+            .unwrap();
+
+        assert_eq!(applied, 0);
+    }
+
+    #[tokio::test]
+    async fn test_prune_expired() {
+        let mut mock = MockDatabase::new();
+        mock.expect_delete_expired_pending_accepts()
+            .withf(|now| *now <= Utc::now())
+            .times(1)
+            .returning(|_| Ok(3));
+
+        let db: DatabaseRef = Arc::new(mock);
+        assert_eq!(prune_expired(&db).await.unwrap(), 3);
+    }
+}
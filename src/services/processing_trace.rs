@@ -0,0 +1,153 @@
+use crate::database::{DatabaseRef, DbProcessingTrace};
+use chrono::Utc;
+use serde_json::{json, Value};
+use std::time::Instant;
+use tracing::warn;
+
+/// The inbox processing stages a trace can record
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    Parsed,
+    Signature,
+    Dedupe,
+    Audience,
+    StoredNote,
+    StoredActivity,
+    Notifications,
+    Forwarded,
+}
+
+impl Stage {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Stage::Parsed => "parsed",
+            Stage::Signature => "signature",
+            Stage::Dedupe => "dedupe",
+            Stage::Audience => "audience",
+            Stage::StoredNote => "stored_note",
+            Stage::StoredActivity => "stored_activity",
+            Stage::Notifications => "notifications",
+            Stage::Forwarded => "forwarded",
+        }
+    }
+}
+
+struct Recording {
+    activity_id: String,
+    activity_type: String,
+    stages: Vec<Value>,
+    last: Instant,
+}
+
+/// Which stages one inbox activity went through, how each went and how
+/// long it took. Only a sampled share of activities are traced; for the
+/// rest every call is a no-op, so the inbox can trace unconditionally.
+pub struct ProcessingTrace {
+    recording: Option<Recording>,
+}
+
+impl ProcessingTrace {
+    /// Start tracing `activity` with probability `sample_rate`. Activities
+    /// without an id can't be looked up later, so they are never traced.
+    pub fn sample(activity: &Value, sample_rate: f64) -> Self {
+        let activity_id = activity.get("id").and_then(|v| v.as_str());
+        let sampled =
+            sample_rate >= 1.0 || (sample_rate > 0.0 && rand::random::<f64>() < sample_rate);
+        let recording = activity_id.filter(|_| sampled).map(|id| Recording {
+            activity_id: id.to_string(),
+            activity_type: activity
+                .get("type")
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown")
+                .to_string(),
+            stages: Vec::new(),
+            last: Instant::now(),
+        });
+        Self { recording }
+    }
+
+    /// Record that `stage` ended with `outcome`, e.g. `ok` or `skipped`,
+    /// taking the time since the previous stage
+    pub fn record(&mut self, stage: Stage, outcome: &str) {
+        self.push(stage, outcome, None);
+    }
+
+    /// Like `record`, with a note on why it went that way
+    pub fn record_with(&mut self, stage: Stage, outcome: &str, detail: impl ToString) {
+        self.push(stage, outcome, Some(detail.to_string()));
+    }
+
+    fn push(&mut self, stage: Stage, outcome: &str, detail: Option<String>) {
+        let Some(recording) = self.recording.as_mut() else {
+            return;
+        };
+        let now = Instant::now();
+        recording.stages.push(json!({
+            "stage": stage.as_str(),
+            "outcome": outcome,
+            "detail": detail,
+            "duration_us": now.duration_since(recording.last).as_micros() as u64,
+        }));
+        recording.last = now;
+    }
+
+    /// Store the trace, if this activity was sampled. A trace that can't be
+    /// stored is only logged; it must never hold up processing.
+    pub async fn save(self, db: &DatabaseRef) {
+        let Some(recording) = self.recording else {
+            return;
+        };
+        let trace = DbProcessingTrace {
+            activity_id: recording.activity_id,
+            activity_type: recording.activity_type,
+            stages: Value::Array(recording.stages),
+            created_at: Utc::now(),
+        };
+        if let Err(e) = db.save_processing_trace(&trace).await {
+            warn!(
+                "Database error while saving processing trace of {}: {}",
+                trace.activity_id, e
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create() -> Value {
+        json!({"id": "https://remote.example/activities/1", "type": "Create"})
+    }
+
+    #[test]
+    fn test_sample_rate_bounds() {
+        assert!(ProcessingTrace::sample(&create(), 1.0).recording.is_some());
+        assert!(ProcessingTrace::sample(&create(), 0.0).recording.is_none());
+        assert!(ProcessingTrace::sample(&json!({"type": "Create"}), 1.0)
+            .recording
+            .is_none());
+    }
+
+    #[test]
+    fn test_records_stages_in_order() {
+        let mut trace = ProcessingTrace::sample(&create(), 1.0);
+        trace.record(Stage::Parsed, "ok");
+        trace.record_with(Stage::Signature, "skipped", "not verified");
+
+        let recording = trace.recording.unwrap();
+        assert_eq!(recording.activity_type, "Create");
+        assert_eq!(recording.stages[0]["stage"], "parsed");
+        assert_eq!(recording.stages[0]["detail"], Value::Null);
+        assert_eq!(recording.stages[1]["stage"], "signature");
+        assert_eq!(recording.stages[1]["outcome"], "skipped");
+        assert_eq!(recording.stages[1]["detail"], "not verified");
+    }
+
+    #[test]
+    fn test_unsampled_trace_records_nothing() {
+        let mut trace = ProcessingTrace::sample(&create(), 0.0);
+        trace.record(Stage::Parsed, "ok");
+        assert!(trace.recording.is_none());
+    }
+}
@@ -0,0 +1,112 @@
+use crate::services::scheduler::Clock;
+use chrono::{DateTime, Duration, Utc};
+use serde_json::Value;
+
+/// How far ahead of our clock a `published` may be before it is clamped to now
+const MAX_CLOCK_SKEW: Duration = Duration::minutes(5);
+
+/// How far back a `published` may reach, so imports keep their dates
+const MAX_BACKDATE: Duration = Duration::days(365 * 20);
+
+/// Resolve the `published` timestamp for a stored note and its activity.
+/// Missing or unparseable values become now, future values beyond the allowed
+/// skew are clamped to now and very old values are clamped to the backdate limit.
+pub fn resolve_published(client_value: Option<&Value>, clock: &dyn Clock) -> DateTime<Utc> {
+    let now = clock.now();
+    let Some(published) = client_value
+        .and_then(|v| v.as_str())
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|dt| dt.with_timezone(&Utc))
+    else {
+        return now;
+    };
+
+    if published > now + MAX_CLOCK_SKEW {
+        now
+    } else {
+        published.max(now - MAX_BACKDATE)
+    }
+}
+
+/// When a client-supplied `published` lies further in the future than the
+/// allowed skew, the time the post should go out at
+pub fn scheduled_for(client_value: Option<&Value>, clock: &dyn Clock) -> Option<DateTime<Utc>> {
+    let published = client_value
+        .and_then(|v| v.as_str())
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|dt| dt.with_timezone(&Utc))?;
+    (published > clock.now() + MAX_CLOCK_SKEW).then_some(published)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::scheduler::TestClock;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    fn clock() -> TestClock {
+        TestClock::new(Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap())
+    }
+
+    #[test]
+    fn test_missing_or_invalid_is_now() {
+        let clock = clock();
+        assert_eq!(resolve_published(None, &clock), clock.now());
+        assert_eq!(
+            resolve_published(Some(&json!("yesterday")), &clock),
+            clock.now()
+        );
+        assert_eq!(resolve_published(Some(&json!(42)), &clock), clock.now());
+    }
+
+    #[test]
+    fn test_past_values_are_honored() {
+        let clock = clock();
+        let published = resolve_published(Some(&json!("2019-03-04T05:06:07+02:00")), &clock);
+        assert_eq!(
+            published,
+            Utc.with_ymd_and_hms(2019, 3, 4, 3, 6, 7).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_clamping_bounds() {
+        let clock = clock();
+
+        // Small skew is tolerated, anything further ahead is clamped to now
+        let skewed = clock.now() + Duration::minutes(2);
+        assert_eq!(
+            resolve_published(Some(&json!(skewed.to_rfc3339())), &clock),
+            skewed
+        );
+        let future = clock.now() + Duration::hours(1);
+        assert_eq!(
+            resolve_published(Some(&json!(future.to_rfc3339())), &clock),
+            clock.now()
+        );
+
+        assert_eq!(
+            resolve_published(Some(&json!("1970-01-01T00:00:00Z")), &clock),
+            clock.now() - MAX_BACKDATE
+        );
+    }
+
+    #[test]
+    fn test_scheduled_for() {
+        let clock = clock();
+        let later = clock.now() + Duration::hours(1);
+        assert_eq!(
+            scheduled_for(Some(&json!(later.to_rfc3339())), &clock),
+            Some(later)
+        );
+
+        let skewed = clock.now() + Duration::minutes(2);
+        assert_eq!(
+            scheduled_for(Some(&json!(skewed.to_rfc3339())), &clock),
+            None
+        );
+        assert_eq!(scheduled_for(Some(&json!("soon")), &clock), None);
+        assert_eq!(scheduled_for(None, &clock), None);
+    }
+}
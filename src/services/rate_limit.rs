@@ -0,0 +1,235 @@
+use crate::config::Config;
+use crate::services::scheduler::{Clock, SystemClock};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Keys tracked before full buckets are dropped; a full bucket is the same as
+/// no bucket at all
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Most keys tracked at once. Past it new keys share one bucket until
+/// pruning makes room, so a flood of addresses can't grow the map.
+const MAX_KEYS: usize = 100_000;
+
+/// The bucket shared by keys beyond [`MAX_KEYS`]
+const OVERFLOW_KEY: &str = "overflow";
+
+/// Where one key stands against its limit after a request
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimitStatus {
+    pub limit: u32,
+    pub remaining: u32,
+    /// When the bucket will be full again
+    pub reset_at: DateTime<Utc>,
+    /// Seconds until the next request would be allowed, when this one wasn't
+    pub retry_after_secs: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated: DateTime<Utc>,
+}
+
+/// A token bucket per key: each holds `capacity` requests and refills at
+/// `capacity` per minute, so a burst is allowed as long as the average
+/// stays under the limit. A capacity of 0 lets everything through.
+pub struct RateLimiter {
+    capacity: u32,
+    clock: Arc<dyn Clock>,
+    buckets: Mutex<Buckets>,
+}
+
+#[derive(Default)]
+struct Buckets {
+    by_key: HashMap<String, Bucket>,
+    /// When full buckets were last dropped. Every bucket is full a minute
+    /// after its last request, so pruning more often finds little.
+    pruned_at: Option<DateTime<Utc>>,
+}
+
+impl RateLimiter {
+    pub fn new(clock: Arc<dyn Clock>, per_minute: u32) -> Self {
+        Self {
+            capacity: per_minute,
+            clock,
+            buckets: Mutex::new(Buckets::default()),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    /// Take one request from `key`'s bucket if it has one left
+    pub fn check(&self, key: &str) -> RateLimitStatus {
+        let now = self.clock.now();
+        let capacity = f64::from(self.capacity);
+        let mut buckets = self.buckets.lock().unwrap();
+        let due = buckets
+            .pruned_at
+            .is_none_or(|pruned_at| now - pruned_at >= Duration::minutes(1));
+        if buckets.by_key.len() >= PRUNE_THRESHOLD && due {
+            buckets
+                .by_key
+                .retain(|_, bucket| self.refilled(bucket, now) < capacity);
+            buckets.pruned_at = Some(now);
+        }
+
+        let key = if buckets.by_key.len() >= MAX_KEYS && !buckets.by_key.contains_key(key) {
+            OVERFLOW_KEY
+        } else {
+            key
+        };
+        let bucket = buckets.by_key.entry(key.to_string()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated = now;
+
+        let retry_after_secs = if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            None
+        } else {
+            Some(self.seconds_to_refill(1.0 - bucket.tokens).max(1))
+        };
+        RateLimitStatus {
+            limit: self.capacity,
+            remaining: bucket.tokens.floor() as u32,
+            reset_at: now
+                + Duration::seconds(self.seconds_to_refill(capacity - bucket.tokens) as i64),
+            retry_after_secs,
+        }
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: DateTime<Utc>) -> f64 {
+        let elapsed = (now - bucket.updated).num_milliseconds().max(0) as f64 / 1000.0;
+        let per_second = f64::from(self.capacity) / 60.0;
+        (bucket.tokens + elapsed * per_second).min(f64::from(self.capacity))
+    }
+
+    /// Whole seconds until `tokens` more have been added
+    fn seconds_to_refill(&self, tokens: f64) -> u64 {
+        (tokens * 60.0 / f64::from(self.capacity)).ceil().max(0.0) as u64
+    }
+}
+
+/// Whether a client API request spends from the read or the write budget
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestClass {
+    Read,
+    Write,
+}
+
+/// Separate read and write budgets for the client API, so a client stuck
+/// posting can still load its timeline
+pub struct ApiRateLimiter {
+    reads: RateLimiter,
+    writes: RateLimiter,
+}
+
+impl ApiRateLimiter {
+    pub fn new(reads: RateLimiter, writes: RateLimiter) -> Self {
+        Self { reads, writes }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        Self::new(
+            RateLimiter::new(clock.clone(), config.api_read_rate_limit),
+            RateLimiter::new(clock, config.api_write_rate_limit),
+        )
+    }
+
+    /// Spend one request of `class` for `key`, or `None` when that budget is
+    /// unlimited
+    pub fn check(&self, class: RequestClass, key: &str) -> Option<RateLimitStatus> {
+        let limiter = match class {
+            RequestClass::Read => &self.reads,
+            RequestClass::Write => &self.writes,
+        };
+        limiter.is_enabled().then(|| limiter.check(key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::scheduler::TestClock;
+
+    fn allowed(status: &RateLimitStatus) -> bool {
+        status.retry_after_secs.is_none()
+    }
+
+    fn limiter(per_minute: u32) -> (Arc<TestClock>, RateLimiter) {
+        let clock = Arc::new(TestClock::new(Utc::now()));
+        (clock.clone(), RateLimiter::new(clock, per_minute))
+    }
+
+    #[test]
+    fn test_bucket_empties_and_refills() {
+        let (clock, limiter) = limiter(3);
+        for remaining in [2, 1, 0] {
+            let status = limiter.check("a");
+            assert!(allowed(&status));
+            assert_eq!(status.remaining, remaining);
+        }
+
+        let status = limiter.check("a");
+        assert!(!allowed(&status));
+        // One request comes back every 20 seconds
+        assert_eq!(status.retry_after_secs, Some(20));
+        assert_eq!(status.reset_at, clock.now() + Duration::seconds(60));
+
+        clock.advance(Duration::seconds(20));
+        assert!(allowed(&limiter.check("a")));
+        assert!(!allowed(&limiter.check("a")));
+    }
+
+    #[test]
+    fn test_keys_have_their_own_buckets() {
+        let (_, limiter) = limiter(1);
+        assert!(allowed(&limiter.check("a")));
+        assert!(!allowed(&limiter.check("a")));
+        assert!(allowed(&limiter.check("b")));
+    }
+
+    #[test]
+    fn test_idle_buckets_never_exceed_capacity() {
+        let (clock, limiter) = limiter(2);
+        limiter.check("a");
+        clock.advance(Duration::hours(1));
+        assert_eq!(limiter.check("a").remaining, 1);
+    }
+
+    #[test]
+    fn test_keys_past_the_cap_share_a_bucket() {
+        let (clock, limiter) = limiter(1);
+        for n in 0..MAX_KEYS {
+            assert!(allowed(&limiter.check(&n.to_string())));
+        }
+        assert!(allowed(&limiter.check("late")));
+        assert!(!allowed(&limiter.check("later")));
+        // Known keys keep their own buckets
+        assert!(!allowed(&limiter.check("0")));
+
+        // Once the buckets have refilled, pruning makes room again
+        clock.advance(Duration::minutes(1));
+        assert!(allowed(&limiter.check("latest")));
+        assert_eq!(limiter.buckets.lock().unwrap().by_key.len(), 1);
+    }
+
+    #[test]
+    fn test_zero_budget_is_unlimited() {
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let api = ApiRateLimiter::new(
+            RateLimiter::new(clock.clone(), 0),
+            RateLimiter::new(clock, 1),
+        );
+        assert_eq!(api.check(RequestClass::Read, "a"), None);
+        assert!(allowed(&api.check(RequestClass::Write, "a").unwrap()));
+        assert!(!allowed(&api.check(RequestClass::Write, "a").unwrap()));
+    }
+}
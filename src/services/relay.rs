@@ -0,0 +1,319 @@
+use crate::database::{
+    DatabaseError, DatabaseRef, DbActivity, DbRelay, DeliveryPriority, PublishState,
+};
+use crate::http::HttpClient;
+use crate::models::addressing::{normalize_audience, PUBLIC};
+use crate::models::{ContextBuilder, Visibility};
+use crate::services::addressing::fetch_document;
+use crate::services::delivery::DeliveryService;
+use crate::services::delivery_queue;
+use crate::services::published::resolve_published;
+use crate::services::scheduler::SystemClock;
+use crate::services::threads::{self, ThreadPosition};
+use crate::urls::{host_of, UrlBuilder};
+use chrono::Utc;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+/// Subscriptions to ActivityPub relays, which pass on every public post sent
+/// to them. Subscribing sends the relay's inbox a Follow of Public; once the
+/// relay Accepts it, it Announces what it receives to the following actor.
+///
+/// Built per use from the `relays` table, so it always sees the current
+/// subscriptions.
+pub struct RelayService {
+    /// The relays subscribed to, accepted or not
+    relays: Vec<DbRelay>,
+    db: DatabaseRef,
+    delivery: Arc<DeliveryService>,
+}
+
+impl RelayService {
+    pub async fn load(
+        db: DatabaseRef,
+        delivery: Arc<DeliveryService>,
+    ) -> Result<Self, DatabaseError> {
+        let relays = db.list_relays().await?;
+        Ok(Self {
+            relays,
+            db,
+            delivery,
+        })
+    }
+
+    pub fn is_subscribed(&self, relay_url: &str) -> bool {
+        self.relays.iter().any(|relay| relay.inbox_url == relay_url)
+    }
+
+    /// Whether `actor_id` speaks for a relay that has accepted our
+    /// subscription. Relays' actors and inboxes live on the same host, so
+    /// that is what is compared.
+    pub fn is_relay_actor(&self, actor_id: &str) -> bool {
+        let Some(host) = host_of(actor_id) else {
+            return false;
+        };
+        self.relays.iter().any(|relay| {
+            relay.status == "accepted" && host_of(&relay.inbox_url).as_ref() == Some(&host)
+        })
+    }
+
+    /// Ask the relay at `relay_url` to pass its posts on to `actor_id`. The
+    /// Follow is retried from the delivery queue when the relay can't be
+    /// reached right away.
+    pub async fn subscribe(
+        &mut self,
+        relay_url: &str,
+        actor_id: &str,
+        urls: &UrlBuilder,
+    ) -> Result<DbRelay, DatabaseError> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let relay = DbRelay {
+            follow_id: urls.activity(&id),
+            id,
+            inbox_url: relay_url.to_string(),
+            actor_id: actor_id.to_string(),
+            status: "pending".to_string(),
+            created_at: Utc::now(),
+        };
+        self.db.create_relay(&relay).await?;
+        self.relays.push(relay.clone());
+
+        info!("Subscribing {} to relay {}", actor_id, relay_url);
+        let mut follow = follow(&relay);
+        follow["@context"] = ContextBuilder::for_document(&follow).build().into();
+        self.send(&relay.inbox_url, follow).await;
+        Ok(relay)
+    }
+
+    /// Take back the Follow behind `relay` and forget the subscription
+    pub async fn unsubscribe(
+        &mut self,
+        relay: &DbRelay,
+        urls: &UrlBuilder,
+    ) -> Result<(), DatabaseError> {
+        self.db.delete_relay(&relay.id).await?;
+        self.relays.retain(|known| known.id != relay.id);
+
+        info!(
+            "Unsubscribing {} from relay {}",
+            relay.actor_id, relay.inbox_url
+        );
+        let mut undo = json!({
+            "id": urls.activity(&uuid::Uuid::new_v4().to_string()),
+            "type": "Undo",
+            "actor": relay.actor_id,
+            "object": follow(relay),
+        });
+        undo["@context"] = ContextBuilder::for_document(&undo).build().into();
+        self.send(&relay.inbox_url, undo).await;
+        Ok(())
+    }
+
+    /// Mark the subscription whose Follow is `follow_id` accepted, if `sender`
+    /// speaks for that relay. Returns whether there was one, so other Accepts
+    /// are left to the caller.
+    pub async fn accept(&self, follow_id: &str, sender: &str) -> Result<bool, DatabaseError> {
+        let host = host_of(sender);
+        let Some(relay) = self.relays.iter().find(|relay| {
+            relay.follow_id == follow_id && host.is_some() && host_of(&relay.inbox_url) == host
+        }) else {
+            return Ok(false);
+        };
+        self.db.update_relay_status(&relay.id, "accepted").await?;
+        info!("Relay {} accepted the subscription", relay.inbox_url);
+        Ok(true)
+    }
+
+    /// Store what a relay Announced: a Create of a Note, or a bare Note.
+    /// Relays pass on whatever they are sent, so an embedded object is not
+    /// trusted: the announced id is fetched from its own server, ids of ours
+    /// are refused, and the note and its author must live on that server.
+    /// Anything else is ignored.
+    pub async fn receive_announce(
+        &self,
+        announce: &Value,
+        http_client: &dyn HttpClient,
+        urls: &UrlBuilder,
+        max_thread_depth: u32,
+    ) {
+        let relay = announce["actor"].as_str().unwrap_or("unknown");
+        let object_id = match announce.get("object") {
+            Some(Value::String(id)) => Some(id.as_str()),
+            Some(object) => object["id"].as_str(),
+            None => None,
+        };
+        let Some(object_id) = object_id else {
+            warn!("Announce from relay {} has no object id", relay);
+            return;
+        };
+        if urls.is_local(object_id) {
+            warn!(
+                "Ignoring relayed {} from {}, which is ours",
+                object_id, relay
+            );
+            return;
+        }
+        // Re-announces of what we already have cost no fetch, so a relay
+        // can't have us hit the origin server over and over
+        match self.already_stored(object_id).await {
+            Ok(false) => {}
+            Ok(true) => return,
+            Err(e) => {
+                warn!("Database error while checking for {}: {}", object_id, e);
+                return;
+            }
+        }
+        let Some(wrapped) = fetch_document(object_id, http_client).await else {
+            return;
+        };
+        if wrapped["id"].as_str() != Some(object_id) {
+            warn!(
+                "Fetching {} relayed by {} returned {:?} instead",
+                object_id, relay, wrapped["id"]
+            );
+            return;
+        }
+
+        let (create, note) = match wrapped["type"].as_str() {
+            Some("Create") => match wrapped.get("object") {
+                Some(note) if note["type"] == "Note" => (Some(&wrapped), note),
+                _ => {
+                    info!("Ignoring relayed Create of something other than a Note");
+                    return;
+                }
+            },
+            Some("Note") => (None, &wrapped),
+            other => {
+                info!("Ignoring relayed {:?} from {}", other, relay);
+                return;
+            }
+        };
+        let Some(note_id) = note["id"].as_str() else {
+            warn!("Relayed note from {} has no id", relay);
+            return;
+        };
+        let origin = host_of(object_id);
+        let from_origin = |id: Option<&str>| id.is_some_and(|id| host_of(id) == origin);
+        if !from_origin(Some(note_id))
+            || !from_origin(note["attributedTo"].as_str())
+            || create.is_some_and(|create| !from_origin(create["actor"].as_str()))
+        {
+            warn!(
+                "Ignoring relayed {} from {}, whose author is on another server",
+                object_id, relay
+            );
+            return;
+        }
+
+        match self.db.get_note_by_id(note_id).await {
+            Ok(None) => {}
+            Ok(Some(_)) => return,
+            Err(e) => {
+                warn!("Database error while checking for note {}: {}", note_id, e);
+                return;
+            }
+        }
+
+        let published = resolve_published(
+            note.get("published")
+                .or(create.and_then(|c| c.get("published"))),
+            &SystemClock,
+        );
+        let in_reply_to = note["inReplyTo"].as_str();
+        let thread = match threads::position_of(&self.db, in_reply_to, None, max_thread_depth).await
+        {
+            Ok(thread) => thread,
+            Err(e) => {
+                warn!(
+                    "Database error while placing {} in its thread: {}",
+                    note_id, e
+                );
+                ThreadPosition::default()
+            }
+        };
+        let db_note = threads::note_from_object(note, published, thread);
+        if let Err(e) = self.db.create_note(&db_note).await {
+            warn!(
+                "Database error while storing relayed note {}: {}",
+                note_id, e
+            );
+            return;
+        }
+        info!("Stored note {} relayed by {}", note_id, relay);
+
+        let Some(create) = create else {
+            return;
+        };
+        let (Some(id), Some(actor)) = (create["id"].as_str(), create["actor"].as_str()) else {
+            return;
+        };
+        let to = normalize_audience(strings(&create["to"]));
+        let cc = normalize_audience(strings(&create["cc"]));
+        let activity = DbActivity {
+            id: id.to_string(),
+            actor_id: actor.to_string(),
+            activity_type: "Create".to_string(),
+            object: note.clone(),
+            visibility: Visibility::from_addressing(&to, &cc),
+            target: None,
+            to_recipients: to,
+            cc_recipients: cc,
+            published,
+            state: PublishState::Published,
+            created_at: Utc::now(),
+        };
+        if let Err(e) = self.db.create_activity(&activity).await {
+            warn!(
+                "Database error while storing relayed activity {}: {}",
+                id, e
+            );
+        }
+    }
+
+    /// Whether `object_id` is a note or an activity stored here
+    async fn already_stored(&self, object_id: &str) -> Result<bool, DatabaseError> {
+        if self.db.get_note_by_id(object_id).await?.is_some() {
+            return Ok(true);
+        }
+        Ok(self.db.get_activity_by_id(object_id).await?.is_some())
+    }
+
+    /// Deliver to a relay now, queueing the activity for retries if that fails
+    async fn send(&self, inbox: &str, activity: Value) {
+        if self
+            .delivery
+            .deliver_activity(inbox, activity.clone())
+            .await
+            .is_ok()
+        {
+            return;
+        }
+        if let Err(e) =
+            delivery_queue::enqueue(&self.db, inbox, activity, DeliveryPriority::Interactive).await
+        {
+            warn!("Failed to queue delivery to relay {}: {}", inbox, e);
+        }
+    }
+}
+
+/// The Follow of Public that subscribes to a relay
+fn follow(relay: &DbRelay) -> Value {
+    json!({
+        "id": relay.follow_id,
+        "type": "Follow",
+        "actor": relay.actor_id,
+        "object": PUBLIC,
+    })
+}
+
+fn strings(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(s) => vec![s.clone()],
+        Value::Array(arr) => arr
+            .iter()
+            .filter_map(|v| v.as_str().map(String::from))
+            .collect(),
+        _ => vec![],
+    }
+}
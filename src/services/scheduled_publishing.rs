@@ -0,0 +1,68 @@
+use crate::database::{DatabaseError, DatabaseRef};
+use crate::models::addressing::is_public;
+use crate::models::{ContextBuilder, Visibility};
+use crate::services::actor_fetch::ActorFetchService;
+use crate::services::delivery::DeliveryService;
+use crate::services::delivery_queue;
+use crate::urls::UrlBuilder;
+use chrono::{DateTime, Utc};
+use tracing::info;
+
+/// Publish every scheduled activity that is due at `now` and deliver it the
+/// way the outbox would have: queued for the remote actors it addresses and
+/// fanned out to followers unless it is a direct message, each inbox once.
+/// Local-only activities stay here. Returns how many went out.
+pub async fn publish_due(
+    db: &DatabaseRef,
+    actor_fetch: &ActorFetchService,
+    urls: &UrlBuilder,
+    delivery: &DeliveryService,
+    now: DateTime<Utc>,
+) -> Result<usize, DatabaseError> {
+    let due = db.get_due_scheduled_activities(now).await?;
+    let count = due.len();
+
+    for activity in due {
+        db.publish_scheduled_activity(&activity.id).await?;
+        info!("Published scheduled activity {}", activity.id);
+        if !activity.visibility.federates() {
+            continue;
+        }
+
+        let mut created = serde_json::json!({
+            "id": activity.id,
+            "type": activity.activity_type,
+            "actor": activity.actor_id,
+            "object": activity.object,
+            "to": activity.to_recipients,
+            "cc": activity.cc_recipients,
+            "published": activity.published
+        });
+        created["@context"] = ContextBuilder::for_document(&created).build().into();
+
+        let recipients: Vec<String> = activity
+            .to_recipients
+            .iter()
+            .chain(&activity.cc_recipients)
+            .filter(|address| !is_public(address))
+            .cloned()
+            .collect();
+        let to_followers = activity.visibility != Visibility::Direct;
+        let queued = delivery_queue::enqueue_for_actors(
+            db,
+            actor_fetch,
+            urls,
+            &created,
+            &recipients,
+            to_followers.then_some(activity.actor_id.as_str()),
+        )
+        .await;
+        if to_followers {
+            delivery
+                .fan_out_except(created, &activity.actor_id, &queued)
+                .await?;
+        }
+    }
+
+    Ok(count)
+}
@@ -0,0 +1,592 @@
+use crate::metrics::Metrics;
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use serde::Serialize;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, warn};
+
+/// Longest the run loop sleeps before re-checking for due jobs
+const MAX_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// How often `wait_idle` checks whether a job's run has finished
+const IDLE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(10);
+
+/// Source of the current time, so schedules can be driven in tests
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+#[derive(Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Manually advanced clock for tests
+#[allow(dead_code)]
+#[derive(Debug)]
+pub struct TestClock {
+    now: Mutex<DateTime<Utc>>,
+}
+
+#[allow(dead_code)]
+impl TestClock {
+    pub fn new(start: DateTime<Utc>) -> Self {
+        Self {
+            now: Mutex::new(start),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.now.lock().unwrap() += by;
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Schedule {
+    /// Run at a fixed interval
+    Every(Duration),
+    /// Run once a day at the given UTC time
+    #[allow(dead_code)]
+    DailyAt(NaiveTime),
+}
+
+impl Schedule {
+    /// The first run time strictly after `after`
+    pub fn next_after(&self, after: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Schedule::Every(interval) => after + *interval,
+            Schedule::DailyAt(time) => {
+                let today = after.date_naive().and_time(*time).and_utc();
+                if today > after {
+                    today
+                } else {
+                    today + Duration::days(1)
+                }
+            }
+        }
+    }
+
+    fn describe(&self) -> String {
+        match self {
+            Schedule::Every(interval) => format!("every {}s", interval.num_seconds()),
+            Schedule::DailyAt(time) => format!("daily at {} UTC", time.format("%H:%M")),
+        }
+    }
+}
+
+type JobFn =
+    Arc<dyn Fn() -> Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>> + Send + Sync>;
+
+struct Job {
+    name: String,
+    schedule: Schedule,
+    run: JobFn,
+    state: Mutex<JobState>,
+}
+
+#[derive(Debug, Clone)]
+struct JobState {
+    running: bool,
+    paused: bool,
+    next_run: DateTime<Utc>,
+    last_run: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+    runs: u64,
+    failures: u64,
+    skipped_overlaps: u64,
+}
+
+/// Point-in-time view of a registered job, served by the admin API
+#[derive(Debug, Clone, Serialize)]
+pub struct JobStatus {
+    pub name: String,
+    pub schedule: String,
+    pub running: bool,
+    pub paused: bool,
+    pub next_run: DateTime<Utc>,
+    pub last_run: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+    pub runs: u64,
+    pub failures: u64,
+    pub skipped_overlaps: u64,
+}
+
+/// Runs named background jobs on their schedules. A job never overlaps
+/// with itself: if it is still running when it comes due again, that run
+/// is skipped.
+pub struct Scheduler {
+    clock: Arc<dyn Clock>,
+    jobs: Vec<Arc<Job>>,
+    metrics: Option<Arc<Metrics>>,
+}
+
+impl Scheduler {
+    pub fn new(clock: Arc<dyn Clock>) -> Self {
+        Self {
+            clock,
+            jobs: Vec::new(),
+            metrics: None,
+        }
+    }
+
+    /// Publish each job's runs, failures and next and last run times
+    pub fn with_metrics(mut self, metrics: Arc<Metrics>) -> Self {
+        for job in &self.jobs {
+            metrics.set_job_next_run(&job.name, job.state.lock().unwrap().next_run);
+        }
+        self.metrics = Some(metrics);
+        self
+    }
+
+    /// Register a job; its first run is one schedule step from now
+    pub fn register<F, Fut>(&mut self, name: &str, schedule: Schedule, job: F)
+    where
+        F: Fn() -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        let next_run = schedule.next_after(self.clock.now());
+        if let Some(metrics) = &self.metrics {
+            metrics.set_job_next_run(name, next_run);
+        }
+        self.jobs.push(Arc::new(Job {
+            name: name.to_string(),
+            schedule,
+            run: Arc::new(move || Box::pin(job())),
+            state: Mutex::new(JobState {
+                running: false,
+                paused: false,
+                next_run,
+                last_run: None,
+                last_error: None,
+                runs: 0,
+                failures: 0,
+                skipped_overlaps: 0,
+            }),
+        }));
+    }
+
+    pub fn statuses(&self) -> Vec<JobStatus> {
+        self.jobs
+            .iter()
+            .map(|job| {
+                let state = job.state.lock().unwrap().clone();
+                JobStatus {
+                    name: job.name.clone(),
+                    schedule: job.schedule.describe(),
+                    running: state.running,
+                    paused: state.paused,
+                    next_run: state.next_run,
+                    last_run: state.last_run,
+                    last_error: state.last_error,
+                    runs: state.runs,
+                    failures: state.failures,
+                    skipped_overlaps: state.skipped_overlaps,
+                }
+            })
+            .collect()
+    }
+
+    /// Stop starting new runs of `name` until it is resumed; a run already in
+    /// progress carries on. Returns false for an unknown job.
+    pub fn pause(&self, name: &str) -> bool {
+        self.set_paused(name, true)
+    }
+
+    /// Let a paused job run again; if it came due meanwhile it runs on the next tick
+    pub fn resume(&self, name: &str) -> bool {
+        self.set_paused(name, false)
+    }
+
+    fn set_paused(&self, name: &str, paused: bool) -> bool {
+        match self.jobs.iter().find(|job| job.name == name) {
+            Some(job) => {
+                job.state.lock().unwrap().paused = paused;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Wait until no run of `name` is in progress
+    pub async fn wait_idle(&self, name: &str) {
+        let Some(job) = self.jobs.iter().find(|job| job.name == name) else {
+            return;
+        };
+        while job.state.lock().unwrap().running {
+            tokio::time::sleep(IDLE_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Start every job that is due, returning handles for the started runs
+    pub fn tick(&self) -> Vec<JoinHandle<()>> {
+        let now = self.clock.now();
+        let mut started = Vec::new();
+
+        for job in &self.jobs {
+            {
+                let mut state = job.state.lock().unwrap();
+                if state.paused || state.next_run > now {
+                    continue;
+                }
+                state.next_run = job.schedule.next_after(now);
+                if let Some(metrics) = &self.metrics {
+                    metrics.set_job_next_run(&job.name, state.next_run);
+                }
+                if state.running {
+                    state.skipped_overlaps += 1;
+                    warn!("Job {} is still running; skipping this run", job.name);
+                    if let Some(metrics) = &self.metrics {
+                        metrics.inc_job_skipped(&job.name);
+                    }
+                    continue;
+                }
+                state.running = true;
+                state.last_run = Some(now);
+                if let Some(metrics) = &self.metrics {
+                    metrics.set_job_last_run(&job.name, now);
+                }
+            }
+
+            let job = Arc::clone(job);
+            let metrics = self.metrics.clone();
+            started.push(tokio::spawn(async move {
+                let result = (job.run)().await;
+                if let Some(metrics) = &metrics {
+                    metrics.observe_job_run(&job.name, result.is_ok());
+                }
+                let mut state = job.state.lock().unwrap();
+                state.running = false;
+                state.runs += 1;
+                match result {
+                    Ok(()) => state.last_error = None,
+                    Err(e) => {
+                        error!("Job {} failed: {}", job.name, e);
+                        state.failures += 1;
+                        state.last_error = Some(e.to_string());
+                    }
+                }
+            }));
+        }
+
+        started
+    }
+
+    /// Run jobs until `shutdown` is cancelled, then wait for in-flight runs
+    pub async fn run(self: Arc<Self>, shutdown: CancellationToken) {
+        info!("Scheduler started with {} jobs", self.jobs.len());
+        let mut in_flight: Vec<JoinHandle<()>> = Vec::new();
+
+        loop {
+            in_flight.retain(|handle| !handle.is_finished());
+            in_flight.extend(self.tick());
+
+            let wait = self
+                .until_next_run()
+                .to_std()
+                .unwrap_or_default()
+                .min(MAX_POLL_INTERVAL);
+
+            tokio::select! {
+                _ = shutdown.cancelled() => break,
+                _ = tokio::time::sleep(wait) => {}
+            }
+        }
+
+        info!(
+            "Scheduler stopping; waiting for {} running jobs",
+            in_flight.len()
+        );
+        for handle in in_flight {
+            let _ = handle.await;
+        }
+    }
+
+    fn until_next_run(&self) -> Duration {
+        let now = self.clock.now();
+        self.jobs
+            .iter()
+            .map(|job| job.state.lock().unwrap().next_run - now)
+            .min()
+            .unwrap_or_else(|| Duration::seconds(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::sync::Notify;
+
+    fn start() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap()
+    }
+
+    fn counting_job(
+        counter: &Arc<AtomicUsize>,
+    ) -> impl Fn() -> std::future::Ready<anyhow::Result<()>> {
+        let counter = Arc::clone(counter);
+        move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            std::future::ready(Ok(()))
+        }
+    }
+
+    async fn join_all(handles: Vec<JoinHandle<()>>) {
+        for handle in handles {
+            handle.await.unwrap();
+        }
+    }
+
+    #[test]
+    fn test_schedule_next_after() {
+        let every = Schedule::Every(Duration::minutes(5));
+        assert_eq!(every.next_after(start()), start() + Duration::minutes(5));
+
+        let daily = Schedule::DailyAt(NaiveTime::from_hms_opt(3, 30, 0).unwrap());
+        assert_eq!(
+            daily.next_after(start()),
+            Utc.with_ymd_and_hms(2024, 1, 2, 3, 30, 0).unwrap()
+        );
+        let early = Utc.with_ymd_and_hms(2024, 1, 1, 1, 0, 0).unwrap();
+        assert_eq!(
+            daily.next_after(early),
+            Utc.with_ymd_and_hms(2024, 1, 1, 3, 30, 0).unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_jobs_run_when_due() {
+        let clock = Arc::new(TestClock::new(start()));
+        let counter = Arc::new(AtomicUsize::new(0));
+        let mut scheduler = Scheduler::new(clock.clone());
+        scheduler.register(
+            "counter",
+            Schedule::Every(Duration::seconds(10)),
+            counting_job(&counter),
+        );
+
+        assert!(scheduler.tick().is_empty());
+
+        clock.advance(Duration::seconds(10));
+        join_all(scheduler.tick()).await;
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+
+        // Not due again until another interval has passed
+        clock.advance(Duration::seconds(5));
+        assert!(scheduler.tick().is_empty());
+
+        clock.advance(Duration::seconds(5));
+        join_all(scheduler.tick()).await;
+        assert_eq!(counter.load(Ordering::SeqCst), 2);
+
+        let status = &scheduler.statuses()[0];
+        assert_eq!(status.name, "counter");
+        assert_eq!(status.schedule, "every 10s");
+        assert_eq!(status.runs, 2);
+        assert_eq!(status.last_run, Some(start() + Duration::seconds(20)));
+        assert_eq!(status.next_run, start() + Duration::seconds(30));
+    }
+
+    #[tokio::test]
+    async fn test_running_job_is_not_started_again() {
+        let clock = Arc::new(TestClock::new(start()));
+        let release = Arc::new(Notify::new());
+        let started = Arc::new(AtomicUsize::new(0));
+        let mut scheduler = Scheduler::new(clock.clone());
+        {
+            let release = Arc::clone(&release);
+            let started = Arc::clone(&started);
+            scheduler.register("slow", Schedule::Every(Duration::seconds(1)), move || {
+                let release = Arc::clone(&release);
+                started.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    release.notified().await;
+                    Ok(())
+                }
+            });
+        }
+
+        clock.advance(Duration::seconds(1));
+        let first = scheduler.tick();
+        assert_eq!(first.len(), 1);
+        tokio::task::yield_now().await;
+
+        // Due again while the first run is still blocked
+        clock.advance(Duration::seconds(1));
+        assert!(scheduler.tick().is_empty());
+        clock.advance(Duration::seconds(1));
+        assert!(scheduler.tick().is_empty());
+
+        let status = &scheduler.statuses()[0];
+        assert!(status.running);
+        assert_eq!(status.skipped_overlaps, 2);
+        assert_eq!(started.load(Ordering::SeqCst), 1);
+
+        release.notify_one();
+        join_all(first).await;
+        assert!(!scheduler.statuses()[0].running);
+
+        clock.advance(Duration::seconds(1));
+        let second = scheduler.tick();
+        assert_eq!(second.len(), 1);
+        release.notify_one();
+        join_all(second).await;
+        assert_eq!(started.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_paused_job_is_skipped_until_resumed() {
+        let clock = Arc::new(TestClock::new(start()));
+        let counter = Arc::new(AtomicUsize::new(0));
+        let mut scheduler = Scheduler::new(clock.clone());
+        scheduler.register(
+            "counter",
+            Schedule::Every(Duration::seconds(10)),
+            counting_job(&counter),
+        );
+
+        assert!(scheduler.pause("counter"));
+        assert!(!scheduler.pause("missing"));
+        assert!(scheduler.statuses()[0].paused);
+
+        clock.advance(Duration::seconds(10));
+        assert!(scheduler.tick().is_empty());
+
+        assert!(scheduler.resume("counter"));
+        join_all(scheduler.tick()).await;
+        assert_eq!(counter.load(Ordering::SeqCst), 1);
+        assert!(!scheduler.statuses()[0].paused);
+    }
+
+    #[tokio::test]
+    async fn test_wait_idle_waits_for_running_job() {
+        let clock = Arc::new(TestClock::new(start()));
+        let release = Arc::new(Notify::new());
+        let mut scheduler = Scheduler::new(clock.clone());
+        {
+            let release = Arc::clone(&release);
+            scheduler.register("slow", Schedule::Every(Duration::seconds(1)), move || {
+                let release = Arc::clone(&release);
+                async move {
+                    release.notified().await;
+                    Ok(())
+                }
+            });
+        }
+
+        clock.advance(Duration::seconds(1));
+        let running = scheduler.tick();
+        tokio::task::yield_now().await;
+
+        let idle = tokio::time::timeout(
+            std::time::Duration::from_millis(50),
+            scheduler.wait_idle("slow"),
+        );
+        assert!(idle.await.is_err());
+
+        release.notify_one();
+        join_all(running).await;
+        scheduler.wait_idle("slow").await;
+    }
+
+    #[tokio::test]
+    async fn test_failures_are_recorded() {
+        let clock = Arc::new(TestClock::new(start()));
+        let fail = Arc::new(std::sync::atomic::AtomicBool::new(true));
+        let mut scheduler = Scheduler::new(clock.clone());
+        {
+            let fail = Arc::clone(&fail);
+            scheduler.register("flaky", Schedule::Every(Duration::seconds(1)), move || {
+                let fail = fail.load(Ordering::SeqCst);
+                async move {
+                    if fail {
+                        anyhow::bail!("remote unavailable");
+                    }
+                    Ok(())
+                }
+            });
+        }
+
+        clock.advance(Duration::seconds(1));
+        join_all(scheduler.tick()).await;
+        let status = &scheduler.statuses()[0];
+        assert_eq!(status.failures, 1);
+        assert_eq!(status.last_error.as_deref(), Some("remote unavailable"));
+
+        fail.store(false, Ordering::SeqCst);
+        clock.advance(Duration::seconds(1));
+        join_all(scheduler.tick()).await;
+        let status = &scheduler.statuses()[0];
+        assert_eq!(status.runs, 2);
+        assert_eq!(status.failures, 1);
+        assert_eq!(status.last_error, None);
+    }
+
+    #[tokio::test]
+    async fn test_run_stops_on_shutdown() {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let mut scheduler = Scheduler::new(Arc::new(SystemClock));
+        scheduler.register(
+            "fast",
+            Schedule::Every(Duration::milliseconds(10)),
+            counting_job(&counter),
+        );
+
+        let shutdown = CancellationToken::new();
+        let handle = tokio::spawn(Arc::new(scheduler).run(shutdown.clone()));
+
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        shutdown.cancel();
+        tokio::time::timeout(std::time::Duration::from_secs(1), handle)
+            .await
+            .expect("scheduler did not stop")
+            .unwrap();
+
+        assert!(counter.load(Ordering::SeqCst) > 0);
+    }
+
+    #[tokio::test]
+    async fn test_runs_are_published_as_metrics() {
+        let clock = Arc::new(TestClock::new(start()));
+        let metrics = Arc::new(Metrics::new());
+        let mut scheduler = Scheduler::new(clock.clone()).with_metrics(metrics.clone());
+        scheduler.register("flaky", Schedule::Every(Duration::seconds(10)), || async {
+            anyhow::bail!("remote unavailable")
+        });
+
+        let rendered = metrics.render();
+        let next_run = (start() + Duration::seconds(10)).timestamp();
+        assert!(rendered.contains(&format!(
+            "feder8_job_next_run_timestamp_seconds{{job=\"flaky\"}} {next_run}"
+        )));
+
+        clock.advance(Duration::seconds(10));
+        join_all(scheduler.tick()).await;
+
+        let rendered = metrics.render();
+        let last_run = (start() + Duration::seconds(10)).timestamp();
+        assert!(rendered.contains(&format!(
+            "feder8_job_last_run_timestamp_seconds{{job=\"flaky\"}} {last_run}"
+        )));
+        assert!(rendered.contains(&format!(
+            "feder8_job_next_run_timestamp_seconds{{job=\"flaky\"}} {}",
+            last_run + 10
+        )));
+        assert!(rendered.contains("feder8_job_runs_total{job=\"flaky\",outcome=\"failure\"} 1"));
+        assert!(rendered.contains("feder8_job_last_run_failed{job=\"flaky\"} 1"));
+    }
+}
@@ -0,0 +1,48 @@
+use crate::database::{DatabaseError, DatabaseRef, DbActorSummary, DbNote};
+
+/// Full-text search over local notes and actors. The index itself lives in
+/// the database (`notes_fts` on SQLite, kept in step by triggers); this is
+/// the one place handlers go through to query it.
+pub struct SearchService {
+    db: DatabaseRef,
+}
+
+impl SearchService {
+    pub fn new(db: DatabaseRef) -> Self {
+        Self { db }
+    }
+
+    /// Published public notes containing every word of `query`, most
+    /// relevant first. A blank query matches nothing.
+    pub async fn search_notes(
+        &self,
+        query: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        let query = query.trim();
+        if query.is_empty() || limit == 0 {
+            return Ok(vec![]);
+        }
+        self.db.search_notes(query, limit, offset).await
+    }
+
+    /// Local actors whose username or display name contains `query`, paged
+    /// like notes are
+    pub async fn search_accounts(
+        &self,
+        query: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbActorSummary>, DatabaseError> {
+        let query = query.trim();
+        if query.is_empty() || limit == 0 {
+            return Ok(vec![]);
+        }
+        let actors = self
+            .db
+            .search_actors(query, limit.saturating_add(offset))
+            .await?;
+        Ok(actors.into_iter().skip(offset as usize).collect())
+    }
+}
@@ -0,0 +1,193 @@
+use crate::database::{DatabaseError, DatabaseRef};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::RwLock;
+use tracing::info;
+
+/// Target false-positive rate of each filter generation
+const FALSE_POSITIVE_RATE: f64 = 0.01;
+
+/// Fast, approximate memory of recently processed activity ids, flagging
+/// likely relay echoes. It only covers recent ids, so it never stands in for
+/// the database duplicate check.
+///
+/// Backed by two Bloom filter generations: once the current one holds
+/// `capacity` ids it becomes the previous one and a fresh filter takes over,
+/// so the false-positive rate stays bounded while the last `capacity` to
+/// `2 * capacity` ids are remembered. A hit only means "maybe seen" and must
+/// be confirmed against the database.
+pub struct SeenActivities {
+    capacity: usize,
+    generations: RwLock<Generations>,
+}
+
+struct Generations {
+    current: BloomFilter,
+    previous: BloomFilter,
+}
+
+impl SeenActivities {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            capacity,
+            generations: RwLock::new(Generations {
+                current: BloomFilter::new(capacity, FALSE_POSITIVE_RATE),
+                previous: BloomFilter::new(capacity, FALSE_POSITIVE_RATE),
+            }),
+        }
+    }
+
+    /// False if the id has certainly not been remembered; true if it may have
+    pub fn might_contain(&self, activity_id: &str) -> bool {
+        let hashes = BloomFilter::hashes(activity_id);
+        let generations = self.generations.read().unwrap();
+        generations.current.contains(hashes) || generations.previous.contains(hashes)
+    }
+
+    /// Remember an activity id, rotating generations when the current one is full
+    pub fn insert(&self, activity_id: &str) {
+        let hashes = BloomFilter::hashes(activity_id);
+        let full = {
+            let generations = self.generations.read().unwrap();
+            generations.current.insert(hashes) >= self.capacity
+        };
+
+        if full {
+            let mut generations = self.generations.write().unwrap();
+            // Another insert may have rotated while we waited for the lock
+            if generations.current.len() >= self.capacity {
+                let fresh = BloomFilter::new(self.capacity, FALSE_POSITIVE_RATE);
+                generations.previous = std::mem::replace(&mut generations.current, fresh);
+            }
+        }
+    }
+}
+
+/// Seed the filter with the most recently stored activity ids so a restart
+/// does not reopen the door to redeliveries
+pub async fn remember_recent(
+    seen: &SeenActivities,
+    db: &DatabaseRef,
+) -> Result<usize, DatabaseError> {
+    let limit = u32::try_from(seen.capacity).unwrap_or(u32::MAX);
+    let ids = db.get_recent_activity_ids(limit).await?;
+    for id in &ids {
+        seen.insert(id);
+    }
+    info!("Remembered {} recent activity ids", ids.len());
+    Ok(ids.len())
+}
+
+/// Fixed-size Bloom filter over lock-free atomic words
+struct BloomFilter {
+    words: Vec<AtomicU64>,
+    bits: u64,
+    hash_count: u64,
+    len: AtomicUsize,
+}
+
+impl BloomFilter {
+    fn new(capacity: usize, false_positive_rate: f64) -> Self {
+        let ln2 = std::f64::consts::LN_2;
+        let bits = (-(capacity as f64) * false_positive_rate.ln() / (ln2 * ln2)).ceil() as u64;
+        let bits = bits.max(64);
+        let hash_count = ((bits as f64 / capacity as f64) * ln2).round().max(1.0) as u64;
+
+        Self {
+            words: (0..bits.div_ceil(64)).map(|_| AtomicU64::new(0)).collect(),
+            bits,
+            hash_count,
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    /// Two independent hashes, combined into `hash_count` bit positions by
+    /// double hashing
+    fn hashes(key: &str) -> (u64, u64) {
+        let hash = |seed: u64| {
+            let mut hasher = DefaultHasher::new();
+            seed.hash(&mut hasher);
+            key.hash(&mut hasher);
+            hasher.finish()
+        };
+        (hash(0), hash(1) | 1)
+    }
+
+    fn positions(&self, (h1, h2): (u64, u64)) -> impl Iterator<Item = u64> + '_ {
+        (0..self.hash_count).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % self.bits)
+    }
+
+    fn contains(&self, hashes: (u64, u64)) -> bool {
+        self.positions(hashes).all(|bit| {
+            self.words[(bit / 64) as usize].load(Ordering::Relaxed) & (1 << (bit % 64)) != 0
+        })
+    }
+
+    /// Set the key's bits and return the number of keys inserted so far
+    fn insert(&self, hashes: (u64, u64)) -> usize {
+        for bit in self.positions(hashes) {
+            self.words[(bit / 64) as usize].fetch_or(1 << (bit % 64), Ordering::Relaxed);
+        }
+        self.len.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    fn len(&self) -> usize {
+        self.len.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn activity(n: usize) -> String {
+        format!("https://remote.example/activities/{n}")
+    }
+
+    #[test]
+    fn test_remembers_inserted_ids() {
+        let seen = SeenActivities::new(1000);
+        assert!(!seen.might_contain(&activity(1)));
+
+        seen.insert(&activity(1));
+        assert!(seen.might_contain(&activity(1)));
+    }
+
+    #[test]
+    fn test_false_positive_rate_stays_near_target() {
+        let seen = SeenActivities::new(10_000);
+        for n in 0..10_000 {
+            seen.insert(&activity(n));
+        }
+
+        let false_positives = (10_000..110_000)
+            .filter(|n| seen.might_contain(&activity(*n)))
+            .count();
+        assert!(
+            false_positives < 2_000,
+            "{false_positives} false positives in 100000 lookups"
+        );
+    }
+
+    #[test]
+    fn test_rotation_keeps_the_previous_generation() {
+        let seen = SeenActivities::new(100);
+        for n in 0..150 {
+            seen.insert(&activity(n));
+        }
+
+        // Everything from the full generation and the current one is still known
+        assert!((0..150).all(|n| seen.might_contain(&activity(n))));
+
+        // Two further rotations push the first ids out
+        for n in 150..400 {
+            seen.insert(&activity(n));
+        }
+        let remembered = (0..100)
+            .filter(|n| seen.might_contain(&activity(*n)))
+            .count();
+        assert!(remembered < 10, "{remembered} stale ids still remembered");
+    }
+}
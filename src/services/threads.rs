@@ -0,0 +1,265 @@
+use crate::database::{DatabaseError, DatabaseRef, DbNote, PublishState};
+use crate::http::client::HttpClient;
+use crate::models::addressing::normalize_audience;
+use crate::models::object::{Attachment, Note, Tag};
+use crate::models::Visibility;
+use crate::services::addressing::fetch_document;
+use crate::services::published::resolve_published;
+use crate::services::scheduler::SystemClock;
+use crate::urls::UrlBuilder;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use tracing::{info, warn};
+
+/// Where a new note sits in its thread
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ThreadPosition {
+    pub in_reply_to_actor: Option<String>,
+    pub depth: Option<u32>,
+    pub truncated: bool,
+}
+
+/// Place a note replying to `in_reply_to` using the stored parent. Replies to
+/// notes we never stored get an unknown depth and, unless `known_author` is
+/// given, an unknown parent author.
+pub async fn position_of(
+    db: &DatabaseRef,
+    in_reply_to: Option<&str>,
+    known_author: Option<&str>,
+    max_depth: u32,
+) -> Result<ThreadPosition, DatabaseError> {
+    let Some(parent_id) = in_reply_to else {
+        return Ok(ThreadPosition {
+            depth: Some(0),
+            ..ThreadPosition::default()
+        });
+    };
+
+    let parent = db.get_note_by_id(parent_id).await?;
+    let (depth, truncated) = match &parent {
+        Some(parent) => reply_depth(parent.thread_depth, parent.thread_truncated, max_depth),
+        None => (None, false),
+    };
+
+    Ok(ThreadPosition {
+        in_reply_to_actor: parent
+            .map(|parent| parent.attributed_to)
+            .or_else(|| known_author.map(String::from)),
+        depth,
+        truncated,
+    })
+}
+
+/// One level below the parent, held at `max_depth`. Truncation carries down
+/// the thread once the cap has been hit.
+pub fn reply_depth(
+    parent_depth: Option<u32>,
+    parent_truncated: bool,
+    max_depth: u32,
+) -> (Option<u32>, bool) {
+    match parent_depth {
+        Some(depth) if depth >= max_depth => (Some(max_depth), true),
+        Some(depth) => (Some(depth + 1), parent_truncated),
+        None => (None, parent_truncated),
+    }
+}
+
+/// The stored form of a remote Note object, placed at `thread`
+pub fn note_from_object(
+    object: &Value,
+    published: DateTime<Utc>,
+    thread: ThreadPosition,
+) -> DbNote {
+    let id = object
+        .get("id")
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .to_string();
+    let to_recipients = normalize_audience(strings(object, "to"));
+    let cc_recipients = normalize_audience(strings(object, "cc"));
+
+    // Keep the attachments we accept; drop the rest
+    let attachments: Vec<Value> = Attachment::from_object(object)
+        .into_iter()
+        .filter_map(|attachment| match attachment {
+            Ok(attachment) => serde_json::to_value(attachment).ok(),
+            Err(message) => {
+                warn!("Dropping attachment on {}: {}", id, message);
+                None
+            }
+        })
+        .collect();
+
+    DbNote {
+        attributed_to: object
+            .get("attributedTo")
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string(),
+        content: Note::content_of(object),
+        visibility: Visibility::from_addressing(&to_recipients, &cc_recipients),
+        to_recipients,
+        cc_recipients,
+        published,
+        in_reply_to: object
+            .get("inReplyTo")
+            .and_then(|v| v.as_str())
+            .map(String::from),
+        tags: Tag::hashtags_of(object),
+        attachments,
+        state: PublishState::Published,
+        pinned: false,
+        created_at: Utc::now(),
+        in_reply_to_actor: thread.in_reply_to_actor,
+        thread_depth: thread.depth,
+        thread_truncated: thread.truncated,
+        sensitive: Note::sensitive_of(object),
+        summary: Note::summary_of(object),
+        language: Note::language_of(object),
+        content_map: Note::content_map_of(object).map(Value::Object),
+        id,
+    }
+}
+
+/// Fetch and store the remote notes above a reply we are about to store,
+/// walking `inReplyTo` until we reach a note we already have, a local one,
+/// or `max_fetch` fetched notes. They are stored root first so each finds
+/// its parent and gets a thread position. Returns how many were stored.
+pub async fn fetch_ancestors(
+    db: &DatabaseRef,
+    http_client: &dyn HttpClient,
+    urls: &UrlBuilder,
+    in_reply_to: &str,
+    max_fetch: u32,
+    max_depth: u32,
+) -> usize {
+    let mut chain: Vec<Value> = Vec::new();
+    let mut next = Some(in_reply_to.to_string());
+
+    while let Some(id) = next.take() {
+        if chain.len() as u32 >= max_fetch
+            || urls.is_local(&id)
+            || chain.iter().any(|object| object["id"] == id.as_str())
+        {
+            break;
+        }
+        match db.get_note_by_id(&id).await {
+            Ok(None) => {}
+            Ok(Some(_)) => break,
+            Err(e) => {
+                warn!("Database error while looking up {}: {}", id, e);
+                break;
+            }
+        }
+        let Some(object) = fetch_note(&id, http_client).await else {
+            break;
+        };
+        next = object
+            .get("inReplyTo")
+            .and_then(|v| v.as_str())
+            .map(String::from);
+        chain.push(object);
+    }
+
+    let mut stored = 0;
+    for object in chain.iter().rev() {
+        let in_reply_to = object.get("inReplyTo").and_then(|v| v.as_str());
+        let thread = match position_of(db, in_reply_to, None, max_depth).await {
+            Ok(thread) => thread,
+            Err(e) => {
+                warn!("Database error while placing a fetched note: {}", e);
+                ThreadPosition::default()
+            }
+        };
+        let published = resolve_published(object.get("published"), &SystemClock);
+        let note = note_from_object(object, published, thread);
+        match db.create_note(&note).await {
+            Ok(()) => {
+                info!("Stored ancestor {} fetched for a reply", note.id);
+                stored += 1;
+            }
+            Err(e) => warn!("Database error while storing {}: {}", note.id, e),
+        }
+    }
+    stored
+}
+
+/// Fetch a remote Note, refusing anything that is not a Note with the id we
+/// asked for
+async fn fetch_note(id: &str, http_client: &dyn HttpClient) -> Option<Value> {
+    let object = fetch_document(id, http_client).await?;
+    if object.get("type").and_then(|v| v.as_str()) != Some("Note") {
+        warn!("Not fetching thread past {}: not a Note", id);
+        return None;
+    }
+    if object.get("id").and_then(|v| v.as_str()) != Some(id) {
+        warn!("Not fetching thread past {}: served under another id", id);
+        return None;
+    }
+    Some(object)
+}
+
+/// The strings in an addressing field, which may be a string or an array
+fn strings(object: &Value, field: &str) -> Vec<String> {
+    match object.get(field) {
+        Some(Value::String(s)) => vec![s.clone()],
+        Some(Value::Array(arr)) => arr
+            .iter()
+            .filter_map(|v| v.as_str().map(String::from))
+            .collect(),
+        _ => vec![],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reply_depth_counts_down_the_thread() {
+        assert_eq!(reply_depth(Some(0), false, 10), (Some(1), false));
+        assert_eq!(reply_depth(Some(8), false, 10), (Some(9), false));
+        assert_eq!(reply_depth(Some(9), false, 10), (Some(10), false));
+    }
+
+    #[test]
+    fn test_reply_depth_is_capped_and_marked() {
+        assert_eq!(reply_depth(Some(10), false, 10), (Some(10), true));
+        assert_eq!(reply_depth(Some(10), true, 10), (Some(10), true));
+    }
+
+    #[test]
+    fn test_unknown_parent_depth_stays_unknown() {
+        assert_eq!(reply_depth(None, false, 10), (None, false));
+    }
+
+    #[test]
+    fn test_note_from_object_reads_addressing_and_reply() {
+        let object = serde_json::json!({
+            "id": "https://remote.example/notes/2",
+            "type": "Note",
+            "attributedTo": "https://remote.example/users/bob",
+            "content": "hi",
+            "inReplyTo": "https://remote.example/notes/1",
+            "to": "https://www.w3.org/ns/activitystreams#Public",
+            "cc": ["https://remote.example/users/bob/followers"]
+        });
+        let thread = ThreadPosition {
+            depth: Some(1),
+            ..ThreadPosition::default()
+        };
+
+        let note = note_from_object(&object, Utc::now(), thread);
+        assert_eq!(note.id, "https://remote.example/notes/2");
+        assert_eq!(note.attributed_to, "https://remote.example/users/bob");
+        assert_eq!(
+            note.in_reply_to.as_deref(),
+            Some("https://remote.example/notes/1")
+        );
+        assert_eq!(note.to_recipients.len(), 1);
+        assert_eq!(note.cc_recipients.len(), 1);
+        assert_eq!(note.visibility, Visibility::Public);
+        assert_eq!(note.thread_depth, Some(1));
+        assert_eq!(note.state, PublishState::Published);
+    }
+}
@@ -0,0 +1,371 @@
+use crate::config::Config;
+use crate::http::client::{HttpClient, HttpRequest};
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::Value;
+use tracing::{debug, info};
+use url::Url;
+
+/// Discovers actor URLs for `acct:user@domain` handles through WebFinger
+pub struct WebFingerResolver<'a> {
+    http_client: &'a dyn HttpClient,
+    fallback_paths: Vec<String>,
+}
+
+impl<'a> WebFingerResolver<'a> {
+    pub fn new(http_client: &'a dyn HttpClient) -> Self {
+        Self {
+            http_client,
+            fallback_paths: Vec::new(),
+        }
+    }
+
+    /// A resolver with the actor URL fallback as configured
+    pub fn from_config(http_client: &'a dyn HttpClient, config: &Config) -> Self {
+        let resolver = Self::new(http_client);
+        if config.actor_url_fallback {
+            resolver.with_fallback_paths(config.actor_url_fallback_paths.clone())
+        } else {
+            resolver
+        }
+    }
+
+    /// Paths, with `{user}` for the username, tried on the handle's domain
+    /// when WebFinger fails
+    pub fn with_fallback_paths(mut self, paths: Vec<String>) -> Self {
+        self.fallback_paths = paths;
+        self
+    }
+
+    /// Resolve `user@domain` (optionally prefixed with `acct:` or `@`) to the
+    /// actor URL advertised by the `rel=self` link of its JRD, falling back
+    /// to the conventional actor URLs when WebFinger fails
+    pub async fn resolve_acct(&self, acct: &str) -> Result<String> {
+        let (user, domain) = parse_acct(acct).ok_or_else(|| anyhow!("Invalid acct: {acct}"))?;
+
+        let error = match self.webfinger(acct, user, domain).await {
+            Ok(actor_url) => return Ok(actor_url),
+            Err(e) => e,
+        };
+        for path in &self.fallback_paths {
+            match self.fetch_actor(user, domain, path).await {
+                Ok(actor_url) => {
+                    info!("WebFinger failed for {acct}, found actor at {actor_url}");
+                    return Ok(actor_url);
+                }
+                Err(e) => debug!("Actor URL fallback for {acct} via {path}: {e:#}"),
+            }
+        }
+        Err(error)
+    }
+
+    async fn webfinger(&self, acct: &str, user: &str, domain: &str) -> Result<String> {
+        let url = format!("https://{domain}/.well-known/webfinger?resource=acct:{user}@{domain}");
+        let request = HttpRequest::new("GET", &url).with_header("Accept", "application/jrd+json");
+        let response = self
+            .http_client
+            .send(request)
+            .await
+            .with_context(|| format!("WebFinger request to {url} failed"))?;
+        if !response.status().is_success() {
+            bail!(
+                "WebFinger lookup of {acct} returned {}",
+                response.status().0
+            );
+        }
+
+        let jrd: Value = response
+            .json()
+            .with_context(|| format!("Invalid JRD from {url}"))?;
+        self_link(&jrd).ok_or_else(|| anyhow!("No ActivityPub self link for {acct}"))
+    }
+
+    /// Fetch the actor at a fallback path, accepting it only when the
+    /// document really is `user@domain`, so a server cannot answer for
+    /// handles it does not own
+    async fn fetch_actor(&self, user: &str, domain: &str, path: &str) -> Result<String> {
+        let url = format!("https://{domain}{}", path.replace("{user}", user));
+        let request = HttpRequest::new("GET", &url).with_header(
+            "Accept",
+            "application/activity+json, application/ld+json; profile=\"https://www.w3.org/ns/activitystreams\"",
+        );
+        let response = self
+            .http_client
+            .send(request)
+            .await
+            .with_context(|| format!("Request to {url} failed"))?;
+        if !response.status().is_success() {
+            bail!("{url} returned {}", response.status().0);
+        }
+
+        let actor: Value = response
+            .json()
+            .with_context(|| format!("Invalid actor document from {url}"))?;
+        let id = actor
+            .get("id")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| anyhow!("Actor document from {url} has no id"))?;
+        let username = actor
+            .get("preferredUsername")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default();
+        if !username.eq_ignore_ascii_case(user) {
+            bail!("{url} is {username:?}, not {user}");
+        }
+        if !same_host(id, domain) {
+            bail!("{url} has id {id} outside {domain}");
+        }
+        Ok(id.to_string())
+    }
+}
+
+/// Whether a URL lives on the given domain, which may carry a port
+fn same_host(url: &str, domain: &str) -> bool {
+    let (Ok(url), Ok(domain)) = (Url::parse(url), Url::parse(&format!("https://{domain}"))) else {
+        return false;
+    };
+    url.host_str()
+        .zip(domain.host_str())
+        .is_some_and(|(a, b)| a.eq_ignore_ascii_case(b))
+        && url.port_or_known_default() == domain.port_or_known_default()
+}
+
+/// Whether a Follow or mention target looks like an acct handle rather than a URL
+pub fn is_acct(value: &str) -> bool {
+    !value.contains("://") && parse_acct(value).is_some()
+}
+
+/// Split an acct handle into user and domain
+pub fn parse_acct(acct: &str) -> Option<(&str, &str)> {
+    let acct = acct.strip_prefix("acct:").unwrap_or(acct);
+    let acct = acct.strip_prefix('@').unwrap_or(acct);
+    let (user, domain) = acct.split_once('@')?;
+
+    let valid = |part: &str| !part.is_empty() && !part.contains(['@', '/', '?', '#', ' ']);
+    (valid(user) && valid(domain)).then_some((user, domain))
+}
+
+/// The `rel=self` link with an ActivityPub media type
+fn self_link(jrd: &Value) -> Option<String> {
+    jrd.get("links")?
+        .as_array()?
+        .iter()
+        .filter(|link| link.get("rel").and_then(|v| v.as_str()) == Some("self"))
+        .filter(|link| {
+            link.get("type")
+                .and_then(|v| v.as_str())
+                .is_some_and(|t| t.contains("activity+json") || t.contains("ld+json"))
+        })
+        .find_map(|link| link.get("href").and_then(|v| v.as_str()).map(String::from))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::client::{HttpResponse, StatusCode};
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    const JRD_URL: &str =
+        "https://remote.example/.well-known/webfinger?resource=acct:carol@remote.example";
+
+    // Serves one JRD document plus any actor documents, and records the
+    // requested URLs
+    struct MockHttpClient {
+        jrd: Option<Value>,
+        documents: HashMap<String, Value>,
+        requested: Mutex<Vec<String>>,
+    }
+
+    impl MockHttpClient {
+        fn new(jrd: Option<Value>) -> Self {
+            Self {
+                jrd,
+                documents: HashMap::new(),
+                requested: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn with_document(mut self, url: &str, document: Value) -> Self {
+            self.documents.insert(url.to_string(), document);
+            self
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl HttpClient for MockHttpClient {
+        async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+            self.requested.lock().unwrap().push(request.url.clone());
+            let (status, body) = match &self.jrd {
+                Some(jrd) if request.url == JRD_URL => (200, serde_json::to_vec(jrd)?),
+                _ => match self.documents.get(&request.url) {
+                    Some(document) => (200, serde_json::to_vec(document)?),
+                    None => (404, b"Not Found".to_vec()),
+                },
+            };
+            Ok(HttpResponse {
+                status: StatusCode(status),
+                headers: HashMap::new(),
+                body,
+            })
+        }
+    }
+
+    fn carol_jrd() -> Value {
+        json!({
+            "subject": "acct:carol@remote.example",
+            "links": [
+                {"rel": "http://webfinger.net/rel/profile-page", "type": "text/html", "href": "https://remote.example/@carol"},
+                {"rel": "self", "type": "application/activity+json", "href": "https://remote.example/users/carol"}
+            ]
+        })
+    }
+
+    #[tokio::test]
+    async fn test_resolve_acct_returns_self_link() {
+        let client = MockHttpClient::new(Some(carol_jrd()));
+        let resolver = WebFingerResolver::new(&client);
+
+        for acct in [
+            "acct:carol@remote.example",
+            "@carol@remote.example",
+            "carol@remote.example",
+        ] {
+            assert_eq!(
+                resolver.resolve_acct(acct).await.unwrap(),
+                "https://remote.example/users/carol"
+            );
+        }
+        assert!(client
+            .requested
+            .lock()
+            .unwrap()
+            .iter()
+            .all(|url| url == JRD_URL));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_acct_errors() {
+        let client = MockHttpClient::new(None);
+        let resolver = WebFingerResolver::new(&client);
+        assert!(resolver.resolve_acct("carol@remote.example").await.is_err());
+        assert!(resolver.resolve_acct("carol").await.is_err());
+
+        // A JRD without an ActivityPub self link is no use for federation
+        let client = MockHttpClient::new(Some(json!({
+            "subject": "acct:carol@remote.example",
+            "links": [{"rel": "self", "type": "text/html", "href": "https://remote.example/@carol"}]
+        })));
+        let resolver = WebFingerResolver::new(&client);
+        assert!(resolver.resolve_acct("carol@remote.example").await.is_err());
+    }
+
+    fn fallback_paths() -> Vec<String> {
+        ["/users/{user}", "/@{user}", "/accounts/{user}"]
+            .map(String::from)
+            .to_vec()
+    }
+
+    fn actor(id: &str, preferred_username: &str) -> Value {
+        json!({"type": "Person", "id": id, "preferredUsername": preferred_username})
+    }
+
+    #[tokio::test]
+    async fn test_fallback_finds_actor_when_webfinger_fails() {
+        let client = MockHttpClient::new(None).with_document(
+            "https://remote.example/accounts/carol",
+            actor("https://remote.example/accounts/carol", "Carol"),
+        );
+        let resolver = WebFingerResolver::new(&client).with_fallback_paths(fallback_paths());
+
+        assert_eq!(
+            resolver.resolve_acct("carol@remote.example").await.unwrap(),
+            "https://remote.example/accounts/carol"
+        );
+        assert_eq!(
+            *client.requested.lock().unwrap(),
+            vec![
+                JRD_URL,
+                "https://remote.example/users/carol",
+                "https://remote.example/@carol",
+                "https://remote.example/accounts/carol",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fallback_rejects_documents_for_someone_else() {
+        // The URL answers, but with a different user
+        let client = MockHttpClient::new(None).with_document(
+            "https://remote.example/users/carol",
+            actor("https://remote.example/users/mallory", "mallory"),
+        );
+        let resolver = WebFingerResolver::new(&client).with_fallback_paths(fallback_paths());
+        assert!(resolver.resolve_acct("carol@remote.example").await.is_err());
+
+        // The right user, but claiming to live on another host
+        let client = MockHttpClient::new(None).with_document(
+            "https://remote.example/users/carol",
+            actor("https://elsewhere.example/users/carol", "carol"),
+        );
+        let resolver = WebFingerResolver::new(&client).with_fallback_paths(fallback_paths());
+        assert!(resolver.resolve_acct("carol@remote.example").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_fallback_follows_config() {
+        let client = MockHttpClient::new(None).with_document(
+            "https://remote.example/users/carol",
+            actor("https://remote.example/users/carol", "carol"),
+        );
+        let mut config = Config {
+            actor_url_fallback: false,
+            ..Config::default()
+        };
+        let resolver = WebFingerResolver::from_config(&client, &config);
+        assert!(resolver.resolve_acct("carol@remote.example").await.is_err());
+        assert_eq!(*client.requested.lock().unwrap(), vec![JRD_URL]);
+
+        config.actor_url_fallback = true;
+        config.actor_url_fallback_paths = vec!["/users/{user}".to_string()];
+        let resolver = WebFingerResolver::from_config(&client, &config);
+        assert_eq!(
+            resolver.resolve_acct("carol@remote.example").await.unwrap(),
+            "https://remote.example/users/carol"
+        );
+    }
+
+    #[test]
+    fn test_same_host() {
+        assert!(same_host(
+            "https://Remote.Example/users/carol",
+            "remote.example"
+        ));
+        assert!(same_host(
+            "https://remote.example:443/u/carol",
+            "remote.example"
+        ));
+        assert!(same_host(
+            "https://remote.example:8443/u/carol",
+            "remote.example:8443"
+        ));
+        assert!(!same_host(
+            "https://remote.example:8443/u/carol",
+            "remote.example"
+        ));
+        assert!(!same_host(
+            "https://evil.example/users/carol",
+            "remote.example"
+        ));
+        assert!(!same_host("not a url", "remote.example"));
+    }
+
+    #[test]
+    fn test_is_acct() {
+        assert!(is_acct("acct:carol@remote.example"));
+        assert!(is_acct("@carol@remote.example"));
+        assert!(!is_acct("https://remote.example/users/carol"));
+        assert!(!is_acct("carol"));
+        assert!(!is_acct("carol@"));
+    }
+}
@@ -0,0 +1,906 @@
+use crate::database::{
+    Database, DatabaseError, DbActivity, DbActor, DbActorSummary, DbBlock, DbDelivery,
+    DbFollowRelation, DbHostBacklog, DbInboundUsage, DbInstanceStats, DbLike, DbNote, DbNoteLabel,
+    DbOldestDelivery, DbParseFailure, DbPendingAccept, DbPreferences, DbProcessingTrace, DbRelay,
+    DbRemoteActor, DbToken, DeliveryPriority, MockDatabase,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+/// `MockDatabase` without the catch-all defaults of
+/// `create_configured_mock_database`: a call with no matching expectation
+/// panics rather than getting a canned answer. Every call is recorded by
+/// method name, so tests can check the exact sequence a handler made.
+/// Expectations are set through the wrapped mock, e.g.
+/// `db.expect_get_actor_by_username()`.
+#[derive(Default)]
+pub struct StrictMockDatabase {
+    mock: MockDatabase,
+    calls: Mutex<Vec<&'static str>>,
+}
+
+#[allow(dead_code)]
+impl StrictMockDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Names of the methods called so far, in call order
+    pub fn calls(&self) -> Vec<&'static str> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Panic unless exactly `expected` was called, in that order
+    pub fn assert_calls(&self, expected: &[&str]) {
+        assert_eq!(self.calls(), expected, "unexpected database calls");
+    }
+
+    fn called(&self, method: &'static str) -> &MockDatabase {
+        self.calls.lock().unwrap().push(method);
+        &self.mock
+    }
+}
+
+impl Deref for StrictMockDatabase {
+    type Target = MockDatabase;
+
+    fn deref(&self) -> &MockDatabase {
+        &self.mock
+    }
+}
+
+impl DerefMut for StrictMockDatabase {
+    fn deref_mut(&mut self) -> &mut MockDatabase {
+        &mut self.mock
+    }
+}
+
+#[async_trait]
+impl Database for StrictMockDatabase {
+    async fn create_actor(&self, actor: &DbActor) -> Result<(), DatabaseError> {
+        self.called("create_actor").create_actor(actor).await
+    }
+
+    async fn get_actor_by_id(&self, id: &str) -> Result<Option<DbActor>, DatabaseError> {
+        self.called("get_actor_by_id").get_actor_by_id(id).await
+    }
+
+    async fn get_actor_by_username(
+        &self,
+        username: &str,
+    ) -> Result<Option<DbActor>, DatabaseError> {
+        self.called("get_actor_by_username")
+            .get_actor_by_username(username)
+            .await
+    }
+
+    async fn update_actor(&self, actor: &DbActor) -> Result<(), DatabaseError> {
+        self.called("update_actor").update_actor(actor).await
+    }
+
+    async fn touch_actor(&self, id: &str) -> Result<(), DatabaseError> {
+        self.called("touch_actor").touch_actor(id).await
+    }
+
+    async fn update_actor_migration(
+        &self,
+        id: &str,
+        moved_to: Option<String>,
+        also_known_as: &[String],
+    ) -> Result<(), DatabaseError> {
+        self.called("update_actor_migration")
+            .update_actor_migration(id, moved_to, also_known_as)
+            .await
+    }
+
+    async fn delete_actor(&self, id: &str) -> Result<(), DatabaseError> {
+        self.called("delete_actor").delete_actor(id).await
+    }
+
+    async fn list_actors(&self, limit: u32, offset: u32) -> Result<Vec<DbActor>, DatabaseError> {
+        self.called("list_actors").list_actors(limit, offset).await
+    }
+
+    async fn search_actors(
+        &self,
+        query: &str,
+        limit: u32,
+    ) -> Result<Vec<DbActorSummary>, DatabaseError> {
+        self.called("search_actors")
+            .search_actors(query, limit)
+            .await
+    }
+
+    async fn create_activity(&self, activity: &DbActivity) -> Result<(), DatabaseError> {
+        self.called("create_activity")
+            .create_activity(activity)
+            .await
+    }
+
+    async fn get_activity_by_id(&self, id: &str) -> Result<Option<DbActivity>, DatabaseError> {
+        self.called("get_activity_by_id")
+            .get_activity_by_id(id)
+            .await
+    }
+
+    async fn get_recent_activity_ids(&self, limit: u32) -> Result<Vec<String>, DatabaseError> {
+        self.called("get_recent_activity_ids")
+            .get_recent_activity_ids(limit)
+            .await
+    }
+
+    async fn get_activities_by_actor(
+        &self,
+        actor_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.called("get_activities_by_actor")
+            .get_activities_by_actor(actor_id, limit, offset)
+            .await
+    }
+
+    async fn get_public_activities_by_actor(
+        &self,
+        actor_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.called("get_public_activities_by_actor")
+            .get_public_activities_by_actor(actor_id, limit, offset)
+            .await
+    }
+
+    async fn get_activities_by_actor_before(
+        &self,
+        actor_id: &str,
+        before_published: DateTime<Utc>,
+        before_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.called("get_activities_by_actor_before")
+            .get_activities_by_actor_before(actor_id, before_published, before_id, limit)
+            .await
+    }
+
+    async fn get_activities_by_actor_after(
+        &self,
+        actor_id: &str,
+        after_published: DateTime<Utc>,
+        after_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.called("get_activities_by_actor_after")
+            .get_activities_by_actor_after(actor_id, after_published, after_id, limit)
+            .await
+    }
+
+    async fn get_public_activities_by_actor_before(
+        &self,
+        actor_id: &str,
+        before_published: DateTime<Utc>,
+        before_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.called("get_public_activities_by_actor_before")
+            .get_public_activities_by_actor_before(actor_id, before_published, before_id, limit)
+            .await
+    }
+
+    async fn get_public_activities_by_actor_after(
+        &self,
+        actor_id: &str,
+        after_published: DateTime<Utc>,
+        after_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.called("get_public_activities_by_actor_after")
+            .get_public_activities_by_actor_after(actor_id, after_published, after_id, limit)
+            .await
+    }
+
+    async fn get_activities_by_actor_and_types(
+        &self,
+        actor_id: &str,
+        types: &[String],
+        public_only: bool,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.called("get_activities_by_actor_and_types")
+            .get_activities_by_actor_and_types(actor_id, types, public_only, limit, offset)
+            .await
+    }
+
+    async fn count_activities_by_actor_and_types(
+        &self,
+        actor_id: &str,
+        types: &[String],
+        public_only: bool,
+    ) -> Result<u32, DatabaseError> {
+        self.called("count_activities_by_actor_and_types")
+            .count_activities_by_actor_and_types(actor_id, types, public_only)
+            .await
+    }
+
+    async fn get_inbox_activities(
+        &self,
+        actor_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.called("get_inbox_activities")
+            .get_inbox_activities(actor_id, limit, offset)
+            .await
+    }
+
+    async fn count_inbox_activities(&self, actor_id: &str) -> Result<u32, DatabaseError> {
+        self.called("count_inbox_activities")
+            .count_inbox_activities(actor_id)
+            .await
+    }
+
+    async fn get_inbox_activities_before(
+        &self,
+        actor_id: &str,
+        before_published: DateTime<Utc>,
+        before_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.called("get_inbox_activities_before")
+            .get_inbox_activities_before(actor_id, before_published, before_id, limit)
+            .await
+    }
+
+    async fn get_inbox_activities_after(
+        &self,
+        actor_id: &str,
+        after_published: DateTime<Utc>,
+        after_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.called("get_inbox_activities_after")
+            .get_inbox_activities_after(actor_id, after_published, after_id, limit)
+            .await
+    }
+
+    async fn update_activity_object(&self, id: &str, object: &Value) -> Result<(), DatabaseError> {
+        self.called("update_activity_object")
+            .update_activity_object(id, object)
+            .await
+    }
+
+    async fn get_shares(
+        &self,
+        object_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.called("get_shares")
+            .get_shares(object_id, limit, offset)
+            .await
+    }
+
+    async fn count_shares(&self, object_id: &str) -> Result<u32, DatabaseError> {
+        self.called("count_shares").count_shares(object_id).await
+    }
+
+    async fn get_due_scheduled_activities(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<DbActivity>, DatabaseError> {
+        self.called("get_due_scheduled_activities")
+            .get_due_scheduled_activities(now)
+            .await
+    }
+
+    async fn publish_scheduled_activity(&self, id: &str) -> Result<(), DatabaseError> {
+        self.called("publish_scheduled_activity")
+            .publish_scheduled_activity(id)
+            .await
+    }
+
+    async fn delete_activities_older_than(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64, DatabaseError> {
+        self.called("delete_activities_older_than")
+            .delete_activities_older_than(cutoff)
+            .await
+    }
+
+    async fn create_note(&self, note: &DbNote) -> Result<(), DatabaseError> {
+        self.called("create_note").create_note(note).await
+    }
+
+    async fn get_note_by_id(&self, id: &str) -> Result<Option<DbNote>, DatabaseError> {
+        self.called("get_note_by_id").get_note_by_id(id).await
+    }
+
+    async fn get_notes_by_ids(&self, ids: &[String]) -> Result<Vec<DbNote>, DatabaseError> {
+        self.called("get_notes_by_ids").get_notes_by_ids(ids).await
+    }
+
+    async fn get_notes_by_actor(
+        &self,
+        actor_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        self.called("get_notes_by_actor")
+            .get_notes_by_actor(actor_id, limit, offset)
+            .await
+    }
+
+    async fn get_notes_by_actor_before(
+        &self,
+        actor_id: &str,
+        before_published: DateTime<Utc>,
+        before_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        self.called("get_notes_by_actor_before")
+            .get_notes_by_actor_before(actor_id, before_published, before_id, limit)
+            .await
+    }
+
+    async fn get_public_notes_by_actor(
+        &self,
+        actor_id: &str,
+        limit: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        self.called("get_public_notes_by_actor")
+            .get_public_notes_by_actor(actor_id, limit)
+            .await
+    }
+
+    async fn count_notes_by_actor(
+        &self,
+        actor_id: &str,
+        include_private: bool,
+        include_local: bool,
+    ) -> Result<u32, DatabaseError> {
+        self.called("count_notes_by_actor")
+            .count_notes_by_actor(actor_id, include_private, include_local)
+            .await
+    }
+
+    async fn get_notes_by_language(
+        &self,
+        actor_id: &str,
+        language: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        self.called("get_notes_by_language")
+            .get_notes_by_language(actor_id, language, limit, offset)
+            .await
+    }
+
+    async fn get_public_notes(
+        &self,
+        limit: u32,
+        before_id: Option<String>,
+        local_only: bool,
+        hide_sensitive: bool,
+        with_local_visibility: bool,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        self.called("get_public_notes")
+            .get_public_notes(
+                limit,
+                before_id,
+                local_only,
+                hide_sensitive,
+                with_local_visibility,
+            )
+            .await
+    }
+
+    async fn search_notes(
+        &self,
+        query: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        self.called("search_notes")
+            .search_notes(query, limit, offset)
+            .await
+    }
+
+    async fn get_pinned_notes(&self, actor_id: &str) -> Result<Vec<DbNote>, DatabaseError> {
+        self.called("get_pinned_notes")
+            .get_pinned_notes(actor_id)
+            .await
+    }
+
+    async fn set_note_pinned(&self, id: &str, pinned: bool) -> Result<(), DatabaseError> {
+        self.called("set_note_pinned")
+            .set_note_pinned(id, pinned)
+            .await
+    }
+
+    async fn search_notes_by_hashtag(
+        &self,
+        actor_id: &str,
+        hashtag: &str,
+        hide_sensitive: bool,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        self.called("search_notes_by_hashtag")
+            .search_notes_by_hashtag(actor_id, hashtag, hide_sensitive, limit, offset)
+            .await
+    }
+
+    async fn count_notes_by_hashtag(
+        &self,
+        actor_id: &str,
+        hashtag: &str,
+        hide_sensitive: bool,
+    ) -> Result<u32, DatabaseError> {
+        self.called("count_notes_by_hashtag")
+            .count_notes_by_hashtag(actor_id, hashtag, hide_sensitive)
+            .await
+    }
+
+    async fn get_replies(
+        &self,
+        note_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbNote>, DatabaseError> {
+        self.called("get_replies")
+            .get_replies(note_id, limit, offset)
+            .await
+    }
+
+    async fn get_thread(&self, note_id: &str, depth: u32) -> Result<Vec<DbNote>, DatabaseError> {
+        self.called("get_thread").get_thread(note_id, depth).await
+    }
+
+    async fn count_replies(&self, note_id: &str) -> Result<u32, DatabaseError> {
+        self.called("count_replies").count_replies(note_id).await
+    }
+
+    async fn delete_note(&self, id: &str) -> Result<(), DatabaseError> {
+        self.called("delete_note").delete_note(id).await
+    }
+
+    async fn create_follow(&self, follow: &DbFollowRelation) -> Result<(), DatabaseError> {
+        self.called("create_follow").create_follow(follow).await
+    }
+
+    async fn get_follow_by_id(&self, id: &str) -> Result<Option<DbFollowRelation>, DatabaseError> {
+        self.called("get_follow_by_id").get_follow_by_id(id).await
+    }
+
+    async fn get_follow_by_actors(
+        &self,
+        follower_id: &str,
+        following_id: &str,
+    ) -> Result<Option<DbFollowRelation>, DatabaseError> {
+        self.called("get_follow_by_actors")
+            .get_follow_by_actors(follower_id, following_id)
+            .await
+    }
+
+    async fn is_following_host(&self, host: &str) -> Result<bool, DatabaseError> {
+        self.called("is_following_host")
+            .is_following_host(host)
+            .await
+    }
+
+    async fn get_followers(
+        &self,
+        actor_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbFollowRelation>, DatabaseError> {
+        self.called("get_followers")
+            .get_followers(actor_id, limit, offset)
+            .await
+    }
+
+    async fn get_following(
+        &self,
+        actor_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbFollowRelation>, DatabaseError> {
+        self.called("get_following")
+            .get_following(actor_id, limit, offset)
+            .await
+    }
+
+    async fn update_follow_status(
+        &self,
+        follow_id: &str,
+        status: &str,
+    ) -> Result<(), DatabaseError> {
+        self.called("update_follow_status")
+            .update_follow_status(follow_id, status)
+            .await
+    }
+
+    async fn delete_follow(&self, id: &str) -> Result<(), DatabaseError> {
+        self.called("delete_follow").delete_follow(id).await
+    }
+
+    async fn get_pending_follows(
+        &self,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbFollowRelation>, DatabaseError> {
+        self.called("get_pending_follows")
+            .get_pending_follows(limit, offset)
+            .await
+    }
+
+    async fn get_followers_with_profiles(
+        &self,
+        actor_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbActorSummary>, DatabaseError> {
+        self.called("get_followers_with_profiles")
+            .get_followers_with_profiles(actor_id, limit, offset)
+            .await
+    }
+
+    async fn upsert_remote_actor(&self, actor: &DbRemoteActor) -> Result<(), DatabaseError> {
+        self.called("upsert_remote_actor")
+            .upsert_remote_actor(actor)
+            .await
+    }
+
+    async fn get_remote_actor(&self, id: &str) -> Result<Option<DbRemoteActor>, DatabaseError> {
+        self.called("get_remote_actor").get_remote_actor(id).await
+    }
+
+    async fn create_parse_failure(&self, failure: &DbParseFailure) -> Result<(), DatabaseError> {
+        self.called("create_parse_failure")
+            .create_parse_failure(failure)
+            .await
+    }
+
+    async fn get_parse_failures(
+        &self,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbParseFailure>, DatabaseError> {
+        self.called("get_parse_failures")
+            .get_parse_failures(limit, offset)
+            .await
+    }
+
+    async fn get_parse_failure(&self, id: &str) -> Result<Option<DbParseFailure>, DatabaseError> {
+        self.called("get_parse_failure").get_parse_failure(id).await
+    }
+
+    async fn delete_parse_failures_older_than(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64, DatabaseError> {
+        self.called("delete_parse_failures_older_than")
+            .delete_parse_failures_older_than(cutoff)
+            .await
+    }
+
+    async fn save_processing_trace(&self, trace: &DbProcessingTrace) -> Result<(), DatabaseError> {
+        self.called("save_processing_trace")
+            .save_processing_trace(trace)
+            .await
+    }
+
+    async fn get_processing_trace(
+        &self,
+        activity_id: &str,
+    ) -> Result<Option<DbProcessingTrace>, DatabaseError> {
+        self.called("get_processing_trace")
+            .get_processing_trace(activity_id)
+            .await
+    }
+
+    async fn delete_processing_traces_older_than(
+        &self,
+        cutoff: DateTime<Utc>,
+    ) -> Result<u64, DatabaseError> {
+        self.called("delete_processing_traces_older_than")
+            .delete_processing_traces_older_than(cutoff)
+            .await
+    }
+
+    async fn create_block(&self, block: &DbBlock) -> Result<(), DatabaseError> {
+        self.called("create_block").create_block(block).await
+    }
+
+    async fn delete_block(&self, blocker_id: &str, blocked_id: &str) -> Result<(), DatabaseError> {
+        self.called("delete_block")
+            .delete_block(blocker_id, blocked_id)
+            .await
+    }
+
+    async fn is_blocked(&self, blocker_id: &str, blocked_id: &str) -> Result<bool, DatabaseError> {
+        self.called("is_blocked")
+            .is_blocked(blocker_id, blocked_id)
+            .await
+    }
+
+    async fn list_blocks(
+        &self,
+        blocker_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbBlock>, DatabaseError> {
+        self.called("list_blocks")
+            .list_blocks(blocker_id, limit, offset)
+            .await
+    }
+
+    async fn create_relay(&self, relay: &DbRelay) -> Result<(), DatabaseError> {
+        self.called("create_relay").create_relay(relay).await
+    }
+
+    async fn get_relay(&self, id: &str) -> Result<Option<DbRelay>, DatabaseError> {
+        self.called("get_relay").get_relay(id).await
+    }
+
+    async fn list_relays(&self) -> Result<Vec<DbRelay>, DatabaseError> {
+        self.called("list_relays").list_relays().await
+    }
+
+    async fn update_relay_status(&self, id: &str, status: &str) -> Result<(), DatabaseError> {
+        self.called("update_relay_status")
+            .update_relay_status(id, status)
+            .await
+    }
+
+    async fn delete_relay(&self, id: &str) -> Result<(), DatabaseError> {
+        self.called("delete_relay").delete_relay(id).await
+    }
+
+    async fn create_like(&self, like: &DbLike) -> Result<(), DatabaseError> {
+        self.called("create_like").create_like(like).await
+    }
+
+    async fn get_like(
+        &self,
+        actor_id: &str,
+        object_id: &str,
+    ) -> Result<Option<DbLike>, DatabaseError> {
+        self.called("get_like").get_like(actor_id, object_id).await
+    }
+
+    async fn delete_like(&self, actor_id: &str, object_id: &str) -> Result<(), DatabaseError> {
+        self.called("delete_like")
+            .delete_like(actor_id, object_id)
+            .await
+    }
+
+    async fn get_likes(
+        &self,
+        object_id: &str,
+        limit: u32,
+        offset: u32,
+    ) -> Result<Vec<DbLike>, DatabaseError> {
+        self.called("get_likes")
+            .get_likes(object_id, limit, offset)
+            .await
+    }
+
+    async fn count_likes(&self, object_id: &str) -> Result<u32, DatabaseError> {
+        self.called("count_likes").count_likes(object_id).await
+    }
+
+    async fn create_pending_accept(&self, pending: &DbPendingAccept) -> Result<(), DatabaseError> {
+        self.called("create_pending_accept")
+            .create_pending_accept(pending)
+            .await
+    }
+
+    async fn get_pending_accepts(
+        &self,
+        follower_id: &str,
+        following_id: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<DbPendingAccept>, DatabaseError> {
+        self.called("get_pending_accepts")
+            .get_pending_accepts(follower_id, following_id, now)
+            .await
+    }
+
+    async fn delete_pending_accept(&self, id: &str) -> Result<(), DatabaseError> {
+        self.called("delete_pending_accept")
+            .delete_pending_accept(id)
+            .await
+    }
+
+    async fn delete_expired_pending_accepts(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<u64, DatabaseError> {
+        self.called("delete_expired_pending_accepts")
+            .delete_expired_pending_accepts(now)
+            .await
+    }
+
+    async fn enqueue_delivery(&self, delivery: &DbDelivery) -> Result<(), DatabaseError> {
+        self.called("enqueue_delivery")
+            .enqueue_delivery(delivery)
+            .await
+    }
+
+    async fn get_due_deliveries(
+        &self,
+        now: DateTime<Utc>,
+        priority: DeliveryPriority,
+        limit: u32,
+    ) -> Result<Vec<DbDelivery>, DatabaseError> {
+        self.called("get_due_deliveries")
+            .get_due_deliveries(now, priority, limit)
+            .await
+    }
+
+    async fn delete_delivery(&self, id: &str) -> Result<(), DatabaseError> {
+        self.called("delete_delivery").delete_delivery(id).await
+    }
+
+    async fn reschedule_delivery(
+        &self,
+        id: &str,
+        attempts: u32,
+        next_attempt_at: DateTime<Utc>,
+        last_error: &str,
+    ) -> Result<(), DatabaseError> {
+        self.called("reschedule_delivery")
+            .reschedule_delivery(id, attempts, next_attempt_at, last_error)
+            .await
+    }
+
+    async fn count_pending_deliveries(&self) -> Result<u32, DatabaseError> {
+        self.called("count_pending_deliveries")
+            .count_pending_deliveries()
+            .await
+    }
+
+    async fn count_pending_by_priority(
+        &self,
+    ) -> Result<Vec<(DeliveryPriority, u32)>, DatabaseError> {
+        self.called("count_pending_by_priority")
+            .count_pending_by_priority()
+            .await
+    }
+
+    async fn oldest_pending_age(
+        &self,
+        now: DateTime<Utc>,
+    ) -> Result<Option<DbOldestDelivery>, DatabaseError> {
+        self.called("oldest_pending_age")
+            .oldest_pending_age(now)
+            .await
+    }
+
+    async fn pending_by_host(&self, limit: u32) -> Result<Vec<DbHostBacklog>, DatabaseError> {
+        self.called("pending_by_host").pending_by_host(limit).await
+    }
+
+    async fn add_note_labels(
+        &self,
+        note_id: &str,
+        labels: &[DbNoteLabel],
+    ) -> Result<(), DatabaseError> {
+        self.called("add_note_labels")
+            .add_note_labels(note_id, labels)
+            .await
+    }
+
+    async fn get_note_labels(&self, note_id: &str) -> Result<Vec<DbNoteLabel>, DatabaseError> {
+        self.called("get_note_labels")
+            .get_note_labels(note_id)
+            .await
+    }
+
+    async fn replace_inbound_usage(&self, usage: &[DbInboundUsage]) -> Result<(), DatabaseError> {
+        self.called("replace_inbound_usage")
+            .replace_inbound_usage(usage)
+            .await
+    }
+
+    async fn get_inbound_usage(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<DbInboundUsage>, DatabaseError> {
+        self.called("get_inbound_usage")
+            .get_inbound_usage(since)
+            .await
+    }
+
+    async fn create_token(&self, token: &DbToken) -> Result<(), DatabaseError> {
+        self.called("create_token").create_token(token).await
+    }
+
+    async fn validate_token(&self, token: &str) -> Result<Option<DbToken>, DatabaseError> {
+        self.called("validate_token").validate_token(token).await
+    }
+
+    async fn get_preferences(&self, actor_id: &str) -> Result<DbPreferences, DatabaseError> {
+        self.called("get_preferences")
+            .get_preferences(actor_id)
+            .await
+    }
+
+    async fn set_preferences(
+        &self,
+        actor_id: &str,
+        preferences: &DbPreferences,
+    ) -> Result<(), DatabaseError> {
+        self.called("set_preferences")
+            .set_preferences(actor_id, preferences)
+            .await
+    }
+
+    async fn get_actor_outbox_count(&self, actor_id: &str) -> Result<u32, DatabaseError> {
+        self.called("get_actor_outbox_count")
+            .get_actor_outbox_count(actor_id)
+            .await
+    }
+
+    async fn get_actor_public_outbox_count(&self, actor_id: &str) -> Result<u32, DatabaseError> {
+        self.called("get_actor_public_outbox_count")
+            .get_actor_public_outbox_count(actor_id)
+            .await
+    }
+
+    async fn get_actor_inbox_count(&self, actor_id: &str) -> Result<u32, DatabaseError> {
+        self.called("get_actor_inbox_count")
+            .get_actor_inbox_count(actor_id)
+            .await
+    }
+
+    async fn get_actor_followers_count(&self, actor_id: &str) -> Result<u32, DatabaseError> {
+        self.called("get_actor_followers_count")
+            .get_actor_followers_count(actor_id)
+            .await
+    }
+
+    async fn get_actor_following_count(&self, actor_id: &str) -> Result<u32, DatabaseError> {
+        self.called("get_actor_following_count")
+            .get_actor_following_count(actor_id)
+            .await
+    }
+
+    async fn get_instance_stats(&self) -> Result<DbInstanceStats, DatabaseError> {
+        self.called("get_instance_stats").get_instance_stats().await
+    }
+
+    async fn ping(&self) -> Result<(), DatabaseError> {
+        self.called("ping").ping().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_records_calls_in_order() {
+        let mut db = StrictMockDatabase::new();
+        db.expect_get_actor_outbox_count().returning(|_| Ok(1));
+        db.expect_ping().returning(|| Ok(()));
+
+        db.ping().await.unwrap();
+        assert_eq!(db.get_actor_outbox_count("alice").await.unwrap(), 1);
+
+        db.assert_calls(&["ping", "get_actor_outbox_count"]);
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "No matching expectation found")]
+    async fn test_unexpected_calls_panic() {
+        let db = StrictMockDatabase::new();
+        let _ = db.get_actor_inbox_count("alice").await;
+    }
+}
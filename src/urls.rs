@@ -0,0 +1,428 @@
+use crate::config::Config;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use tracing::warn;
+use url::{Host, Url};
+
+/// Builds every URL this server hands out, so ids stay consistent when the
+/// server is deployed under a path prefix such as `https://example.com/fedi`.
+/// Routes are mounted under the same prefix (see [`UrlBuilder::base_path`]).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UrlBuilder {
+    /// Scheme and host, e.g. `https://example.com`
+    origin: String,
+    /// `origin` plus the base path, without a trailing slash
+    base: String,
+}
+
+impl UrlBuilder {
+    /// `server_url` may carry a trailing slash; `base_path` may be given with
+    /// or without its slashes and is ignored when empty
+    pub fn new(server_url: &str, base_path: Option<&str>) -> Self {
+        let origin = server_url.trim_end_matches('/').to_string();
+        let base = match base_path.map(|path| path.trim_matches('/')) {
+            Some(path) if !path.is_empty() => format!("{origin}/{path}"),
+            _ => origin.clone(),
+        };
+        Self { origin, base }
+    }
+
+    pub fn from_config(config: &Config) -> Self {
+        Self::new(&config.server_url, config.base_path.as_deref())
+    }
+
+    /// Root every generated URL starts with
+    pub fn base(&self) -> &str {
+        &self.base
+    }
+
+    /// Path prefix routes are mounted under: empty at the root, otherwise
+    /// e.g. `/fedi`
+    pub fn base_path(&self) -> &str {
+        &self.base[self.origin.len()..]
+    }
+
+    /// Whether `url` points at this server
+    pub fn is_local(&self, url: &str) -> bool {
+        url.strip_prefix(&self.base)
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+
+    /// The `host[:port]` this server's acct: URIs use
+    pub fn domain(&self) -> String {
+        host_of(&self.origin).unwrap_or_else(|| {
+            warn!("SERVER_URL {} is not a valid URL", self.origin);
+            self.origin.clone()
+        })
+    }
+
+    /// Whether `domain`, a `host[:port]` as in an acct: URI, names this
+    /// server. Hosts compare without case, and a missing port is the default
+    /// one for our scheme. Returns `None` when `domain` is malformed.
+    pub fn is_local_domain(&self, domain: &str) -> Option<bool> {
+        if domain.contains(['/', '@', '?', '#', ' ']) {
+            return None;
+        }
+
+        let Ok(server) = Url::parse(&self.origin) else {
+            warn!("SERVER_URL {} is not a valid URL", self.origin);
+            return Some(false);
+        };
+        let candidate = Url::parse(&format!("{}://{}", server.scheme(), domain)).ok()?;
+        let host = candidate.host_str()?;
+
+        Some(
+            server
+                .host_str()
+                .is_some_and(|local| local.eq_ignore_ascii_case(host))
+                && server.port_or_known_default() == candidate.port_or_known_default(),
+        )
+    }
+
+    pub fn actor(&self, username: &str) -> String {
+        format!("{}/users/{username}", self.base)
+    }
+
+    pub fn inbox(&self, username: &str) -> String {
+        format!("{}/inbox", self.actor(username))
+    }
+
+    pub fn outbox(&self, username: &str) -> String {
+        format!("{}/outbox", self.actor(username))
+    }
+
+    pub fn followers(&self, username: &str) -> String {
+        format!("{}/followers", self.actor(username))
+    }
+
+    pub fn following(&self, username: &str) -> String {
+        format!("{}/following", self.actor(username))
+    }
+
+    /// The actor's notes, newest first
+    pub fn statuses(&self, username: &str) -> String {
+        format!("{}/statuses", self.actor(username))
+    }
+
+    /// The actor's pinned posts
+    pub fn featured(&self, username: &str) -> String {
+        format!("{}/collections/featured", self.actor(username))
+    }
+
+    /// The actor's public posts with a hashtag
+    pub fn tag_collection(&self, username: &str, tag: &str) -> String {
+        format!("{}/collections/tags/{tag}", self.actor(username))
+    }
+
+    /// The actor's public posts as an Atom or RSS feed, by `extension`
+    pub fn feed(&self, username: &str, extension: &str) -> String {
+        format!("{}/feed.{extension}", self.actor(username))
+    }
+
+    pub fn note(&self, id: &str) -> String {
+        format!("{}/notes/{id}", self.base)
+    }
+
+    /// The note's replies collection
+    pub fn replies(&self, id: &str) -> String {
+        format!("{}/replies", self.note(id))
+    }
+
+    /// The note's likes collection
+    pub fn likes(&self, id: &str) -> String {
+        format!("{}/likes", self.note(id))
+    }
+
+    /// The note's shares (boosts) collection
+    pub fn shares(&self, id: &str) -> String {
+        format!("{}/shares", self.note(id))
+    }
+
+    pub fn activity(&self, id: &str) -> String {
+        format!("{}/activities/{id}", self.base)
+    }
+
+    pub fn follow(&self, id: &str) -> String {
+        format!("{}/follows/{id}", self.base)
+    }
+
+    pub fn like(&self, id: &str) -> String {
+        format!("{}/likes/{id}", self.base)
+    }
+
+    #[allow(dead_code)]
+    pub fn tag(&self, name: &str) -> String {
+        format!("{}/tags/{name}", self.base)
+    }
+
+    #[allow(dead_code)]
+    pub fn media(&self, id: &str) -> String {
+        format!("{}/media/{id}", self.base)
+    }
+
+    /// `/.well-known` documents always live at the origin root, whatever the
+    /// base path
+    pub fn well_known(&self, path: &str) -> String {
+        format!("{}/.well-known/{path}", self.origin)
+    }
+}
+
+/// The `host[:port]` of an absolute URL, lowercased and without the
+/// scheme's default port. `None` for anything without a host.
+pub fn host_of(url: &str) -> Option<String> {
+    let url = Url::parse(url).ok()?;
+    let host = url.host_str().filter(|host| !host.is_empty())?;
+    let host = host.to_ascii_lowercase();
+    Some(match url.port() {
+        Some(port) => format!("{host}:{port}"),
+        None => host,
+    })
+}
+
+/// Whether `url` is an `https` URL naming a public host, for fetches a
+/// caller asks for by address. Loopback, private, link-local and
+/// unspecified IP literals are refused, as are `localhost` and single-label
+/// names that only resolve inside a local network. Names are not resolved,
+/// so this is a first filter rather than a guarantee.
+pub fn is_public_https_url(url: &str) -> bool {
+    let Ok(url) = Url::parse(url) else {
+        return false;
+    };
+    if url.scheme() != "https" {
+        return false;
+    }
+    match url.host() {
+        Some(Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.').to_ascii_lowercase();
+            domain.contains('.') && domain != "localhost" && !domain.ends_with(".localhost")
+        }
+        Some(Host::Ipv4(ip)) => is_public_ipv4(ip),
+        Some(Host::Ipv6(ip)) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public_ipv4(ip),
+            None => is_public_ipv6(ip),
+        },
+        None => false,
+    }
+}
+
+fn is_public_ipv4(ip: Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    // 100.64.0.0/10 is carrier-grade NAT space
+    let shared = a == 100 && (b & 0xc0) == 64;
+    !(ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_unspecified()
+        || ip.is_broadcast()
+        || shared)
+}
+
+fn is_public_ipv6(ip: Ipv6Addr) -> bool {
+    let first = ip.segments()[0];
+    // fc00::/7 is unique local, fe80::/10 link-local
+    let unique_local = (first & 0xfe00) == 0xfc00;
+    let link_local = (first & 0xffc0) == 0xfe80;
+    !(ip.is_loopback() || ip.is_unspecified() || unique_local || link_local)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_urls_at_the_root() {
+        let urls = UrlBuilder::new("https://example.com/", None);
+
+        assert_eq!(urls.base(), "https://example.com");
+        assert_eq!(urls.base_path(), "");
+        assert_eq!(urls.actor("alice"), "https://example.com/users/alice");
+        assert_eq!(urls.inbox("alice"), "https://example.com/users/alice/inbox");
+        assert_eq!(urls.note("1"), "https://example.com/notes/1");
+        assert_eq!(urls.replies("1"), "https://example.com/notes/1/replies");
+        assert_eq!(urls.likes("1"), "https://example.com/notes/1/likes");
+        assert_eq!(urls.shares("1"), "https://example.com/notes/1/shares");
+        assert_eq!(urls.activity("1"), "https://example.com/activities/1");
+        assert_eq!(urls.follow("1"), "https://example.com/follows/1");
+    }
+
+    #[test]
+    fn test_urls_under_a_base_path() {
+        for base_path in ["fedi", "/fedi", "/fedi/"] {
+            let urls = UrlBuilder::new("https://example.com", Some(base_path));
+
+            assert_eq!(urls.base(), "https://example.com/fedi");
+            assert_eq!(urls.base_path(), "/fedi");
+            assert_eq!(urls.actor("alice"), "https://example.com/fedi/users/alice");
+            assert_eq!(
+                urls.featured("alice"),
+                "https://example.com/fedi/users/alice/collections/featured"
+            );
+            assert_eq!(
+                urls.tag_collection("alice", "rust"),
+                "https://example.com/fedi/users/alice/collections/tags/rust"
+            );
+            assert_eq!(urls.tag("rust"), "https://example.com/fedi/tags/rust");
+            assert_eq!(urls.media("m1"), "https://example.com/fedi/media/m1");
+            assert_eq!(
+                urls.well_known("webfinger"),
+                "https://example.com/.well-known/webfinger"
+            );
+        }
+
+        let urls = UrlBuilder::new("https://example.com", Some("/"));
+        assert_eq!(urls.base(), "https://example.com");
+    }
+
+    #[test]
+    fn test_is_local() {
+        let urls = UrlBuilder::new("https://example.com", Some("fedi"));
+
+        assert!(urls.is_local("https://example.com/fedi/users/alice"));
+        assert!(urls.is_local("https://example.com/fedi"));
+        assert!(!urls.is_local("https://example.com/fediverse/users/alice"));
+        assert!(!urls.is_local("https://example.com/users/alice"));
+        assert!(!urls.is_local("https://remote.example/fedi/users/bob"));
+    }
+
+    #[test]
+    fn test_host_of() {
+        assert_eq!(
+            host_of("https://Remote.Example/users/bob").as_deref(),
+            Some("remote.example")
+        );
+        assert_eq!(
+            host_of("http://remote.example:8080/users/bob").as_deref(),
+            Some("remote.example:8080")
+        );
+        assert_eq!(
+            host_of("https://remote.example:443").as_deref(),
+            Some("remote.example")
+        );
+        assert_eq!(host_of("not a url"), None);
+        assert_eq!(host_of("acct:bob@remote.example"), None);
+    }
+
+    #[test]
+    fn test_domain_keeps_only_non_default_ports() {
+        let domain = |server_url| UrlBuilder::new(server_url, None).domain();
+        assert_eq!(domain("https://Example.COM/"), "example.com");
+        assert_eq!(domain("https://example.com:443"), "example.com");
+        assert_eq!(domain("http://localhost:8080"), "localhost:8080");
+    }
+
+    fn is_local_domain(server_url: &str, domain: &str) -> Option<bool> {
+        UrlBuilder::new(server_url, None).is_local_domain(domain)
+    }
+
+    #[test]
+    fn test_local_domain_ignores_case_and_trailing_slash() {
+        for server_url in ["https://example.com", "https://Example.COM/"] {
+            assert_eq!(
+                is_local_domain(server_url, "example.com"),
+                Some(true),
+                "{server_url}"
+            );
+            assert_eq!(
+                is_local_domain(server_url, "EXAMPLE.com"),
+                Some(true),
+                "{server_url}"
+            );
+        }
+        let urls = UrlBuilder::new("https://example.com", Some("social"));
+        assert_eq!(urls.is_local_domain("example.com"), Some(true));
+    }
+
+    #[test]
+    fn test_local_domain_treats_default_ports_as_equal() {
+        assert_eq!(
+            is_local_domain("https://example.com", "example.com:443"),
+            Some(true)
+        );
+        assert_eq!(
+            is_local_domain("https://example.com:443", "example.com"),
+            Some(true)
+        );
+        assert_eq!(
+            is_local_domain("http://example.com", "example.com:80"),
+            Some(true)
+        );
+        assert_eq!(
+            is_local_domain("http://localhost:8080", "localhost:8080"),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn test_local_domain_rejects_other_hosts_and_ports() {
+        assert_eq!(
+            is_local_domain("https://example.com", "example.org"),
+            Some(false)
+        );
+        assert_eq!(
+            is_local_domain("https://example.com", "sub.example.com"),
+            Some(false)
+        );
+        assert_eq!(
+            is_local_domain("https://example.com", "example.com:8443"),
+            Some(false)
+        );
+        assert_eq!(
+            is_local_domain("http://localhost:8080", "localhost"),
+            Some(false)
+        );
+        assert_eq!(
+            is_local_domain("http://localhost:8080", "localhost:8081"),
+            Some(false)
+        );
+        // Whatever the resource, a broken SERVER_URL matches nothing
+        assert_eq!(is_local_domain("not a url", "example.com"), Some(false));
+    }
+
+    #[test]
+    fn test_local_domain_rejects_malformed_domains() {
+        assert_eq!(
+            is_local_domain("https://example.com", "example.com/users"),
+            None
+        );
+        assert_eq!(
+            is_local_domain("https://example.com", "bob@example.com"),
+            None
+        );
+        assert_eq!(
+            is_local_domain("https://example.com", "example.com:port"),
+            None
+        );
+        assert_eq!(is_local_domain("https://example.com", "exa mple.com"), None);
+    }
+
+    #[test]
+    fn test_only_public_https_urls_are_fetchable() {
+        for url in [
+            "https://remote.example/users/carol",
+            "https://93.184.216.34/actor",
+            "https://[2606:2800:220:1::1]/actor",
+        ] {
+            assert!(is_public_https_url(url), "{url}");
+        }
+        for url in [
+            "http://remote.example/users/carol",
+            "file:///etc/passwd",
+            "https://localhost/admin",
+            "https://api.localhost/",
+            "https://metadata/computeMetadata",
+            "https://127.0.0.1/",
+            "https://10.0.0.5/",
+            "https://172.16.3.4/",
+            "https://192.168.1.1/",
+            "https://169.254.169.254/latest/meta-data",
+            "https://100.64.0.1/",
+            "https://0.0.0.0/",
+            "https://[::1]/",
+            "https://[fd00::1]/",
+            "https://[fe80::1]/",
+            "https://[::ffff:127.0.0.1]/",
+            "not a url",
+        ] {
+            assert!(!is_public_https_url(url), "{url}");
+        }
+    }
+}
@@ -0,0 +1,135 @@
+mod common;
+
+use chrono::Utc;
+use feder8::database::{Database, DbActivity, PublishState};
+use feder8::models::addressing::PUBLIC;
+use feder8::models::Visibility;
+use serde_json::json;
+use sqlx::{Row, SqlitePool};
+use tempfile::TempDir;
+
+const ALICE: &str = "https://example.com/users/alice";
+
+fn activity(id: &str, to: &[&str], cc: &[&str]) -> DbActivity {
+    DbActivity {
+        id: format!("https://remote.example/activities/{id}"),
+        actor_id: "https://remote.example/users/bob".to_string(),
+        activity_type: "Create".to_string(),
+        object: json!({"type": "Note", "content": id}),
+        target: None,
+        to_recipients: to.iter().map(|s| s.to_string()).collect(),
+        cc_recipients: cc.iter().map(|s| s.to_string()).collect(),
+        published: Utc::now(),
+        visibility: Visibility::Public,
+        state: PublishState::Published,
+        created_at: Utc::now(),
+    }
+}
+
+#[tokio::test]
+async fn test_inbox_matches_recipients_exactly() {
+    let dir = TempDir::new().unwrap();
+    let db = common::sqlite_database(&dir).await;
+
+    let followers = format!("{ALICE}/followers");
+    let alicea = format!("{ALICE}a");
+    db.create_activity(&activity("to-alice", &[ALICE], &[PUBLIC]))
+        .await
+        .unwrap();
+    db.create_activity(&activity("cc-alice", &[PUBLIC], &[ALICE]))
+        .await
+        .unwrap();
+    db.create_activity(&activity("both", &[ALICE], &[ALICE]))
+        .await
+        .unwrap();
+    // Their URLs contain alice's id, but they aren't addressed to alice
+    db.create_activity(&activity("followers", &[&followers], &[]))
+        .await
+        .unwrap();
+    db.create_activity(&activity("alicea", &[&alicea], &[]))
+        .await
+        .unwrap();
+
+    let inbox = db.get_inbox_activities(ALICE, 20, 0).await.unwrap();
+    let mut ids: Vec<_> = inbox.iter().map(|a| a.object["content"].clone()).collect();
+    ids.sort_by_key(|id| id.to_string());
+    assert_eq!(
+        ids,
+        vec![json!("both"), json!("cc-alice"), json!("to-alice")]
+    );
+    assert_eq!(db.get_actor_inbox_count(ALICE).await.unwrap(), 3);
+
+    // Recipients still come back in the order they were sent
+    let both = inbox
+        .iter()
+        .find(|a| a.object["content"] == "both")
+        .unwrap();
+    assert_eq!(both.to_recipients, vec![ALICE.to_string()]);
+    assert_eq!(both.cc_recipients, vec![ALICE.to_string()]);
+}
+
+#[tokio::test]
+async fn test_recipients_go_with_their_activity() {
+    let dir = TempDir::new().unwrap();
+    let db = common::sqlite_database(&dir).await;
+    let url = common::sqlite_url(&dir);
+    let pool = SqlitePool::connect(&url).await.unwrap();
+
+    let first = activity("first", &[ALICE], &[PUBLIC]);
+    db.create_activity(&first).await.unwrap();
+    let stored: i64 = sqlx::query("SELECT COUNT(*) FROM activity_recipients WHERE activity_id = ?")
+        .bind(&first.id)
+        .fetch_one(&pool)
+        .await
+        .unwrap()
+        .get(0);
+    assert_eq!(stored, 2);
+
+    // A duplicate activity is refused without leaving stray recipients
+    let mut duplicate = activity("first", &["https://example.com/users/carol"], &[]);
+    duplicate.id = first.id.clone();
+    assert!(db.create_activity(&duplicate).await.is_err());
+    assert_eq!(
+        db.get_inbox_activities("https://example.com/users/carol", 20, 0)
+            .await
+            .unwrap()
+            .len(),
+        0
+    );
+}
+
+#[tokio::test]
+async fn test_inbox_queries_use_the_recipient_index() {
+    let dir = TempDir::new().unwrap();
+    let _db = common::sqlite_database(&dir).await;
+    let url = common::sqlite_url(&dir);
+    let pool = SqlitePool::connect(&url).await.unwrap();
+
+    // The queries behind get_inbox_activities and get_actor_inbox_count
+    for query in [
+        "SELECT id FROM activities WHERE id IN (SELECT activity_id FROM activity_recipients WHERE recipient_url = ?) AND state = 'published' ORDER BY published DESC LIMIT ? OFFSET ?",
+        "SELECT COUNT(*) as count FROM activities WHERE id IN (SELECT activity_id FROM activity_recipients WHERE recipient_url = ?) AND state = 'published'",
+    ] {
+        let explain = format!("EXPLAIN QUERY PLAN {query}");
+        let mut statement = sqlx::query(&explain).bind(ALICE);
+        if query.contains("LIMIT") {
+            statement = statement.bind(20).bind(0);
+        }
+        let plan: Vec<String> = statement
+            .fetch_all(&pool)
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get::<String, _>("detail"))
+            .collect();
+        assert!(
+            plan.iter()
+                .any(|step| step.contains("idx_activity_recipients_recipient_url")),
+            "query doesn't use the recipient index: {plan:?}"
+        );
+        assert!(
+            !plan.iter().any(|step| step == "SCAN activity_recipients"),
+            "query scans every recipient: {plan:?}"
+        );
+    }
+}
@@ -0,0 +1,211 @@
+mod common;
+
+use actix_web::{test, App};
+use chrono::Utc;
+use common::{OfflineHttpClient, ALICE_TOKEN};
+use feder8::auth::hash_token;
+use feder8::config::Config;
+use feder8::database::{DatabaseRef, DbActor, DbToken};
+use feder8::handlers;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ADMIN_TOKEN: &str = "test-admin-token";
+const ALICE_READ_TOKEN: &str = "alice-read-token";
+const BOB_TOKEN: &str = "bob-token";
+
+fn actor_id(username: &str) -> String {
+    format!("https://example.com/users/{username}")
+}
+
+fn local_actor(username: &str) -> DbActor {
+    common::actor(&actor_id(username), username, username)
+}
+
+fn token(id: &str, plaintext: &str, username: &str, scopes: &[&str]) -> DbToken {
+    DbToken {
+        id: id.to_string(),
+        token_hash: hash_token(plaintext),
+        actor_id: actor_id(username),
+        scopes: scopes.iter().map(|s| s.to_string()).collect(),
+        created_at: Utc::now(),
+        expires_at: None,
+    }
+}
+
+/// Alice and Bob, each with a read/write token, plus a read-only one for Alice
+async fn create_test_database() -> (TempDir, DatabaseRef) {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+
+    db.create_actor(&local_actor("bob")).await.unwrap();
+    db.create_token(&token("t2", ALICE_READ_TOKEN, "alice", &["read"]))
+        .await
+        .unwrap();
+    db.create_token(&token("t3", BOB_TOKEN, "bob", &["read", "write"]))
+        .await
+        .unwrap();
+
+    (dir, db)
+}
+
+fn create_test_app(
+    db: &DatabaseRef,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    let config = Config {
+        admin_token: Some(ADMIN_TOKEN.to_string()),
+        ..common::test_config()
+    };
+    common::test_app(db, config, Arc::new(OfflineHttpClient))
+        .service(handlers::outbox::post_outbox)
+        .service(handlers::inbox::get_inbox)
+        .service(handlers::admin::tokens::issue_token)
+}
+
+fn create_note() -> Value {
+    json!({
+        "@context": "https://www.w3.org/ns/activitystreams",
+        "type": "Create",
+        "actor": actor_id("alice"),
+        "object": {
+            "type": "Note",
+            "content": "Hello",
+            "to": ["https://www.w3.org/ns/activitystreams#Public"]
+        }
+    })
+}
+
+fn outbox_post(token: Option<&str>) -> test::TestRequest {
+    let request = test::TestRequest::post()
+        .uri("/users/alice/outbox")
+        .insert_header(("Content-Type", "application/activity+json"))
+        .set_json(create_note());
+    match token {
+        Some(token) => request.insert_header(("Authorization", format!("Bearer {token}"))),
+        None => request,
+    }
+}
+
+#[tokio::test]
+async fn test_outbox_requires_the_actors_write_token() {
+    let (_dir, db) = create_test_database().await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    let cases = [
+        (None, 401),
+        (Some("not-a-token"), 401),
+        (Some(BOB_TOKEN), 403),
+        (Some(ALICE_READ_TOKEN), 403),
+        (Some(ALICE_TOKEN), 201),
+    ];
+    for (token, status) in cases {
+        let resp = test::call_service(&app, outbox_post(token).to_request()).await;
+        assert_eq!(resp.status().as_u16(), status, "{token:?}");
+    }
+
+    let notes = db
+        .get_notes_by_actor(&actor_id("alice"), 10, 0)
+        .await
+        .unwrap();
+    assert_eq!(notes.len(), 1);
+}
+
+#[tokio::test]
+async fn test_inbox_is_readable_by_its_owner_only() {
+    let (_dir, db) = create_test_database().await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/users/alice/inbox")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+
+    let req = test::TestRequest::get()
+        .uri("/users/alice/inbox")
+        .insert_header(("Authorization", format!("Bearer {BOB_TOKEN}")))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 403);
+
+    let req = test::TestRequest::get()
+        .uri("/users/alice/inbox")
+        .insert_header(("Authorization", format!("Bearer {ALICE_READ_TOKEN}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["type"], "OrderedCollection");
+    assert_eq!(body["totalItems"], 0);
+}
+
+#[tokio::test]
+async fn test_admin_issues_working_tokens() {
+    let (_dir, db) = create_test_database().await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/tokens")
+        .insert_header(("Authorization", format!("Bearer {ADMIN_TOKEN}")))
+        .set_json(json!({"username": "alice", "scopes": ["write:statuses"], "expires_in": 3600}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["actor_id"], actor_id("alice"));
+    assert_eq!(body["scopes"], json!(["write:statuses"]));
+    assert!(body["expires_at"].is_string());
+
+    let issued = body["token"].as_str().unwrap();
+    let resp = test::call_service(&app, outbox_post(Some(issued)).to_request()).await;
+    assert_eq!(resp.status(), 201);
+}
+
+#[tokio::test]
+async fn test_issue_token_rejects_bad_requests() {
+    let (_dir, db) = create_test_database().await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    let cases = [
+        (
+            Some(ADMIN_TOKEN),
+            json!({"username": "nobody", "scopes": ["read"]}),
+            404,
+        ),
+        (
+            Some(ADMIN_TOKEN),
+            json!({"username": "alice", "scopes": []}),
+            400,
+        ),
+        (
+            Some(ADMIN_TOKEN),
+            json!({"username": "alice", "scopes": ["read"], "expires_in": 0}),
+            400,
+        ),
+        (
+            Some(ALICE_TOKEN),
+            json!({"username": "alice", "scopes": ["read"]}),
+            403,
+        ),
+        (None, json!({"username": "alice", "scopes": ["read"]}), 401),
+    ];
+    for (token, payload, status) in cases {
+        let mut req = test::TestRequest::post()
+            .uri("/api/admin/tokens")
+            .set_json(&payload);
+        if let Some(token) = token {
+            req = req.insert_header(("Authorization", format!("Bearer {token}")));
+        }
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status().as_u16(), status, "{payload}");
+    }
+}
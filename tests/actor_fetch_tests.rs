@@ -0,0 +1,210 @@
+mod common;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use feder8::database::DatabaseRef;
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode};
+use feder8::services::actor_fetch::ActorFetchService;
+use feder8::services::parse_failures::ParseFailureRecorder;
+use feder8::services::scheduler::TestClock;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+const BOB: &str = "https://remote.example/users/bob";
+const BOB_INBOX: &str = "https://remote.example/users/bob/inbox";
+const BOB_KEY: &str = "-----BEGIN PUBLIC KEY-----\nbob\n-----END PUBLIC KEY-----\n";
+
+fn now() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap()
+}
+
+/// Serves one actor document, recording each request, until told to fail
+struct RemoteServer {
+    document: Mutex<Value>,
+    down: AtomicBool,
+    requests: Mutex<Vec<HttpRequest>>,
+}
+
+impl RemoteServer {
+    fn new(document: Value) -> Arc<Self> {
+        Arc::new(Self {
+            document: Mutex::new(document),
+            down: AtomicBool::new(false),
+            requests: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn request_count(&self) -> usize {
+        self.requests.lock().unwrap().len()
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpClient for RemoteServer {
+    async fn send(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        self.requests.lock().unwrap().push(request);
+        if self.down.load(Ordering::SeqCst) {
+            anyhow::bail!("connection refused");
+        }
+        Ok(HttpResponse {
+            status: StatusCode(200),
+            headers: HashMap::new(),
+            body: serde_json::to_vec(&*self.document.lock().unwrap())?,
+        })
+    }
+}
+
+fn bob(name: &str) -> Value {
+    json!({
+        "id": BOB,
+        "type": "Person",
+        "preferredUsername": "bob",
+        "name": name,
+        "inbox": BOB_INBOX,
+        "endpoints": {"sharedInbox": "https://remote.example/inbox"},
+        "publicKey": {
+            "id": format!("{BOB}#main-key"),
+            "owner": BOB,
+            "publicKeyPem": BOB_KEY
+        }
+    })
+}
+
+fn service(
+    db: &DatabaseRef,
+    server: &Arc<RemoteServer>,
+    clock: &Arc<TestClock>,
+) -> ActorFetchService {
+    ActorFetchService::new(
+        db.clone(),
+        server.clone(),
+        ParseFailureRecorder::new(db.clone(), None),
+        clock.clone(),
+        Duration::hours(1),
+    )
+}
+
+#[tokio::test]
+async fn test_cache_miss_fetches_and_stores_the_actor() {
+    let dir = TempDir::new().unwrap();
+    let db: DatabaseRef = Arc::new(common::sqlite_database(&dir).await);
+    let server = RemoteServer::new(bob("Bob"));
+    let clock = Arc::new(TestClock::new(now()));
+    let fetcher = service(&db, &server, &clock);
+
+    let actor = fetcher.get_or_fetch(BOB).await.unwrap();
+    assert_eq!(actor.id, BOB);
+    assert_eq!(actor.inbox.as_deref(), Some(BOB_INBOX));
+    assert_eq!(
+        actor.shared_inbox.as_deref(),
+        Some("https://remote.example/inbox")
+    );
+    assert_eq!(actor.public_key_pem.as_deref(), Some(BOB_KEY));
+    assert_eq!(actor.fetched_at, now());
+
+    let requests = server.requests.lock().unwrap().clone();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].url, BOB);
+    assert_eq!(
+        requests[0].headers.get("Accept").map(String::as_str),
+        Some("application/activity+json")
+    );
+
+    let stored = db.get_remote_actor(BOB).await.unwrap().unwrap();
+    assert_eq!(stored.public_key_pem.as_deref(), Some(BOB_KEY));
+    assert_eq!(stored.fetched_at, now());
+}
+
+#[tokio::test]
+async fn test_cache_hit_skips_the_fetch() {
+    let dir = TempDir::new().unwrap();
+    let db: DatabaseRef = Arc::new(common::sqlite_database(&dir).await);
+    let server = RemoteServer::new(bob("Bob"));
+    let clock = Arc::new(TestClock::new(now()));
+    let fetcher = service(&db, &server, &clock);
+
+    fetcher.get_or_fetch(BOB).await.unwrap();
+    clock.advance(Duration::minutes(59));
+    *server.document.lock().unwrap() = bob("Robert");
+
+    let actor = fetcher.get_or_fetch(BOB).await.unwrap();
+    assert_eq!(actor.name.as_deref(), Some("Bob"));
+    assert_eq!(server.request_count(), 1);
+}
+
+#[tokio::test]
+async fn test_stale_entries_are_refreshed() {
+    let dir = TempDir::new().unwrap();
+    let db: DatabaseRef = Arc::new(common::sqlite_database(&dir).await);
+    let server = RemoteServer::new(bob("Bob"));
+    let clock = Arc::new(TestClock::new(now()));
+    let fetcher = service(&db, &server, &clock);
+
+    fetcher.get_or_fetch(BOB).await.unwrap();
+    clock.advance(Duration::hours(2));
+    *server.document.lock().unwrap() = bob("Robert");
+
+    let actor = fetcher.get_or_fetch(BOB).await.unwrap();
+    assert_eq!(actor.name.as_deref(), Some("Robert"));
+    assert_eq!(actor.fetched_at, now() + Duration::hours(2));
+    assert_eq!(server.request_count(), 2);
+
+    let stored = db.get_remote_actor(BOB).await.unwrap().unwrap();
+    assert_eq!(stored.name.as_deref(), Some("Robert"));
+}
+
+#[tokio::test]
+async fn test_fetch_failure_falls_back_to_the_stale_copy() {
+    let dir = TempDir::new().unwrap();
+    let db: DatabaseRef = Arc::new(common::sqlite_database(&dir).await);
+    let server = RemoteServer::new(bob("Bob"));
+    let clock = Arc::new(TestClock::new(now()));
+    let fetcher = service(&db, &server, &clock);
+
+    // Nothing cached yet, so the failure is the caller's
+    server.down.store(true, Ordering::SeqCst);
+    assert!(fetcher.get_or_fetch(BOB).await.is_err());
+    assert!(db.get_remote_actor(BOB).await.unwrap().is_none());
+
+    server.down.store(false, Ordering::SeqCst);
+    fetcher.get_or_fetch(BOB).await.unwrap();
+    clock.advance(Duration::hours(2));
+    server.down.store(true, Ordering::SeqCst);
+
+    let actor = fetcher.get_or_fetch(BOB).await.unwrap();
+    assert_eq!(actor.name.as_deref(), Some("Bob"));
+    assert_eq!(actor.fetched_at, now());
+    assert_eq!(server.request_count(), 3);
+}
+
+#[tokio::test]
+async fn test_document_for_another_actor_is_refused() {
+    let dir = TempDir::new().unwrap();
+    let db: DatabaseRef = Arc::new(common::sqlite_database(&dir).await);
+    let mut document = bob("Bob");
+    document["id"] = json!("https://remote.example/users/mallory");
+    let server = RemoteServer::new(document);
+    let clock = Arc::new(TestClock::new(now()));
+    let fetcher = service(&db, &server, &clock);
+
+    assert!(fetcher.get_or_fetch(BOB).await.is_err());
+    assert!(db.get_remote_actor(BOB).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn test_find_gives_none_for_an_unreachable_actor() {
+    let dir = TempDir::new().unwrap();
+    let db: DatabaseRef = Arc::new(common::sqlite_database(&dir).await);
+    let server = RemoteServer::new(bob("Bob"));
+    let clock = Arc::new(TestClock::new(now()));
+    let fetcher = service(&db, &server, &clock);
+
+    server.down.store(true, Ordering::SeqCst);
+    assert!(fetcher.find(BOB).await.unwrap().is_none());
+
+    server.down.store(false, Ordering::SeqCst);
+    let actor = fetcher.find(BOB).await.unwrap().unwrap();
+    assert_eq!(actor.inbox.as_deref(), Some(BOB_INBOX));
+}
@@ -0,0 +1,86 @@
+mod common;
+
+use chrono::{Duration, Utc};
+use feder8::database::{Database, DatabaseError, DbActor, SqliteDatabase};
+use std::time::Duration as StdDuration;
+use tempfile::TempDir;
+
+const ALICE: &str = "https://example.com/users/alice";
+
+fn alice() -> DbActor {
+    common::actor(ALICE, "alice", "Alice")
+}
+
+async fn updated_at(db: &SqliteDatabase) -> chrono::DateTime<Utc> {
+    db.get_actor_by_id(ALICE).await.unwrap().unwrap().updated_at
+}
+
+#[tokio::test]
+async fn test_repeated_updates_advance_updated_at() {
+    let dir = TempDir::new().unwrap();
+    let db = common::sqlite_database(&dir).await;
+    db.create_actor(&alice()).await.unwrap();
+    let mut last = updated_at(&db).await;
+
+    for n in 0..3 {
+        tokio::time::sleep(StdDuration::from_millis(10)).await;
+        let mut renamed = alice();
+        renamed.name = format!("Alice {n}");
+        db.update_actor(&renamed).await.unwrap();
+        let now = updated_at(&db).await;
+        assert!(now > last, "update {n} left updated_at at {now}");
+        last = now;
+
+        tokio::time::sleep(StdDuration::from_millis(10)).await;
+        db.touch_actor(ALICE).await.unwrap();
+        let now = updated_at(&db).await;
+        assert!(now > last, "touch {n} left updated_at at {now}");
+        last = now;
+    }
+    assert_eq!(
+        db.get_actor_by_id(ALICE).await.unwrap().unwrap().name,
+        "Alice 2"
+    );
+}
+
+#[tokio::test]
+async fn test_migration_updates_advance_updated_at() {
+    let dir = TempDir::new().unwrap();
+    let db = common::sqlite_database(&dir).await;
+    db.create_actor(&alice()).await.unwrap();
+    let before = updated_at(&db).await;
+
+    tokio::time::sleep(StdDuration::from_millis(10)).await;
+    db.update_actor_migration(
+        ALICE,
+        Some("https://remote.example/users/alice".to_string()),
+        &[],
+    )
+    .await
+    .unwrap();
+    assert!(updated_at(&db).await > before);
+}
+
+#[tokio::test]
+async fn test_update_ignores_the_callers_updated_at() {
+    let dir = TempDir::new().unwrap();
+    let db = common::sqlite_database(&dir).await;
+    db.create_actor(&alice()).await.unwrap();
+
+    let mut stale = alice();
+    stale.updated_at = Utc::now() - Duration::days(30);
+    let before = Utc::now() - Duration::seconds(1);
+    db.update_actor(&stale).await.unwrap();
+    assert!(updated_at(&db).await > before);
+}
+
+#[tokio::test]
+async fn test_touching_an_unknown_actor_is_not_found() {
+    let dir = TempDir::new().unwrap();
+    let db = common::sqlite_database(&dir).await;
+
+    assert!(matches!(
+        db.touch_actor(ALICE).await,
+        Err(DatabaseError::NotFound)
+    ));
+}
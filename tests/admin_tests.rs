@@ -0,0 +1,535 @@
+mod common;
+
+use actix_web::{http::StatusCode, test, web, App};
+use chrono::{Duration, Utc};
+use common::OfflineHttpClient;
+use feder8::config::Config;
+use feder8::container::Container;
+use feder8::database::{
+    Database, DatabaseRef, DbActor, DbFollowRelation, MockDatabase, SqliteDatabase,
+};
+use feder8::handlers;
+use feder8::services::delivery_queue::DELIVERY_JOB;
+use feder8::services::events::EventBus;
+use feder8::services::keys::KeyManager;
+use feder8::services::scheduler::{Schedule, Scheduler, TestClock};
+use mockall::predicate::*;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ADMIN_TOKEN: &str = "test-admin-token";
+
+fn create_test_app(
+    db: DatabaseRef,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    let config = Config {
+        admin_token: Some(ADMIN_TOKEN.to_string()),
+        ..common::test_config()
+    };
+    common::test_app(&db, config, Arc::new(OfflineHttpClient))
+        .app_data(web::Data::new(KeyManager::with_key_size(1024)))
+        .app_data(web::Data::new(EventBus::new()))
+        .service(handlers::admin::actors::list_actors)
+        .service(handlers::admin::actors::create_actor)
+        .service(handlers::admin::actors::delete_actor)
+        .service(handlers::admin::follows::list_pending_follows)
+        .service(handlers::admin::follows::accept_follow)
+        .service(handlers::admin::follows::reject_follow)
+}
+
+fn test_actor(username: &str) -> DbActor {
+    DbActor {
+        private_key_pem: Some("test_private_key".to_string()),
+        ..common::actor(
+            &format!("https://example.com/users/{username}"),
+            username,
+            &format!("Test User {username}"),
+        )
+    }
+}
+
+fn pending_follow(id: &str) -> DbFollowRelation {
+    DbFollowRelation {
+        id: id.to_string(),
+        follower_id: "https://remote.example/users/bob".to_string(),
+        following_id: "https://example.com/users/alice".to_string(),
+        status: "pending".to_string(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    }
+}
+
+fn bearer() -> (&'static str, String) {
+    ("Authorization", format!("Bearer {ADMIN_TOKEN}"))
+}
+
+#[actix_web::test]
+async fn test_admin_requires_token() {
+    let mut mock = MockDatabase::new();
+    mock.expect_validate_token().returning(|_| Ok(None));
+    let db: DatabaseRef = Arc::new(mock);
+    let app = test::init_service(create_test_app(db)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/actors")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/actors")
+        .insert_header(("Authorization", "Bearer wrong-token"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Unauthorized");
+}
+
+#[actix_web::test]
+async fn test_admin_disabled_without_configured_token() {
+    let mut mock = MockDatabase::new();
+    mock.expect_validate_token().returning(|_| Ok(None));
+    let db: DatabaseRef = Arc::new(mock);
+    let app = test::init_service(
+        common::test_app(&db, common::test_config(), Arc::new(OfflineHttpClient))
+            .service(handlers::admin::actors::list_actors),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/actors")
+        .insert_header(bearer())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_admin_list_actors_with_pagination() {
+    let mut mock = MockDatabase::new();
+    mock.expect_list_actors()
+        .with(eq(2), eq(4))
+        .times(1)
+        .returning(|_, _| Ok(vec![test_actor("alice"), test_actor("bob")]));
+
+    let db: DatabaseRef = Arc::new(mock);
+    let app = test::init_service(create_test_app(db)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/actors?limit=2&offset=4")
+        .insert_header(bearer())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let body: Value = test::read_body_json(resp).await;
+    let actors = body["actors"].as_array().unwrap();
+    assert_eq!(actors.len(), 2);
+    assert_eq!(actors[0]["username"], "alice");
+    assert!(actors[0].get("private_key_pem").is_none());
+    assert_eq!(body["limit"], 2);
+    assert_eq!(body["offset"], 4);
+}
+
+#[actix_web::test]
+async fn test_admin_create_actor_generates_keys() {
+    let mut mock = MockDatabase::new();
+    mock.expect_get_actor_by_username()
+        .with(eq("carol"))
+        .returning(|_| Ok(None));
+    mock.expect_create_actor()
+        .withf(|actor| {
+            actor.id == "https://example.com/users/carol"
+                && actor.public_key_pem.contains("BEGIN PUBLIC KEY")
+                && actor
+                    .private_key_pem
+                    .as_deref()
+                    .is_some_and(|pem| pem.contains("BEGIN PRIVATE KEY"))
+        })
+        .times(1)
+        .returning(|_| Ok(()));
+
+    let db: DatabaseRef = Arc::new(mock);
+    let app = test::init_service(create_test_app(db)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/actors")
+        .insert_header(bearer())
+        .set_json(json!({"username": "carol", "name": "Carol"}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["id"], "https://example.com/users/carol");
+    assert_eq!(body["name"], "Carol");
+    assert!(body["public_key_pem"]
+        .as_str()
+        .unwrap()
+        .contains("BEGIN PUBLIC KEY"));
+    assert!(body.get("private_key_pem").is_none());
+}
+
+#[actix_web::test]
+async fn test_admin_create_actor_conflict() {
+    let mut mock = MockDatabase::new();
+    mock.expect_get_actor_by_username()
+        .with(eq("alice"))
+        .returning(|username| Ok(Some(test_actor(username))));
+    mock.expect_create_actor().never();
+
+    let db: DatabaseRef = Arc::new(mock);
+    let app = test::init_service(create_test_app(db)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/actors")
+        .insert_header(bearer())
+        .set_json(json!({"username": "alice"}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+}
+
+#[actix_web::test]
+async fn test_admin_create_actor_invalid_username() {
+    let db: DatabaseRef = Arc::new(MockDatabase::new());
+    let app = test::init_service(create_test_app(db)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/actors")
+        .insert_header(bearer())
+        .set_json(json!({"username": "Not Valid!"}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn test_admin_create_group_actor() {
+    let mut mock = MockDatabase::new();
+    mock.expect_get_actor_by_username().returning(|_| Ok(None));
+    mock.expect_create_actor()
+        .withf(|actor| actor.actor_type == "Group")
+        .times(1)
+        .returning(|_| Ok(()));
+
+    let db: DatabaseRef = Arc::new(mock);
+    let app = test::init_service(create_test_app(db)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/actors")
+        .insert_header(bearer())
+        .set_json(json!({"username": "hikers", "type": "Group"}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["type"], "Group");
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/actors")
+        .insert_header(bearer())
+        .set_json(json!({"username": "bots", "type": "Service"}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn test_admin_delete_actor() {
+    let mut mock = MockDatabase::new();
+    mock.expect_get_actor_by_id()
+        .with(eq("https://example.com/users/alice"))
+        .returning(|_| Ok(Some(test_actor("alice"))));
+    mock.expect_delete_actor()
+        .with(eq("https://example.com/users/alice"))
+        .times(1)
+        .returning(|_| Ok(()));
+
+    let db: DatabaseRef = Arc::new(mock);
+    let app = test::init_service(create_test_app(db)).await;
+
+    let req = test::TestRequest::delete()
+        .uri("/api/admin/actors/https%3A%2F%2Fexample.com%2Fusers%2Falice")
+        .insert_header(bearer())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+}
+
+#[actix_web::test]
+async fn test_admin_delete_unknown_actor() {
+    let mut mock = MockDatabase::new();
+    mock.expect_get_actor_by_id().returning(|_| Ok(None));
+    mock.expect_delete_actor().never();
+
+    let db: DatabaseRef = Arc::new(mock);
+    let app = test::init_service(create_test_app(db)).await;
+
+    let req = test::TestRequest::delete()
+        .uri("/api/admin/actors/https%3A%2F%2Fexample.com%2Fusers%2Fghost")
+        .insert_header(bearer())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_admin_list_pending_follows() {
+    let mut mock = MockDatabase::new();
+    mock.expect_get_pending_follows()
+        .with(eq(20), eq(0))
+        .returning(|_, _| Ok(vec![pending_follow("https://example.com/follows/1")]));
+
+    let db: DatabaseRef = Arc::new(mock);
+    let app = test::init_service(create_test_app(db)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/follows")
+        .insert_header(bearer())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["follows"].as_array().unwrap().len(), 1);
+    assert_eq!(body["follows"][0]["status"], "pending");
+}
+
+#[actix_web::test]
+async fn test_admin_accept_follow() {
+    let mut mock = MockDatabase::new();
+    mock.expect_get_follow_by_id()
+        .with(eq("https://example.com/follows/abc"))
+        .returning(|id| Ok(Some(pending_follow(id))));
+    mock.expect_update_follow_status()
+        .with(eq("https://example.com/follows/abc"), eq("accepted"))
+        .times(1)
+        .returning(|_, _| Ok(()));
+
+    let db: DatabaseRef = Arc::new(mock);
+    let app = test::init_service(create_test_app(db)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/follows/abc/accept")
+        .insert_header(bearer())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], "accepted");
+}
+
+#[actix_web::test]
+async fn test_admin_reject_follow() {
+    let mut mock = MockDatabase::new();
+    mock.expect_get_follow_by_id()
+        .returning(|id| Ok(Some(pending_follow(id))));
+    mock.expect_update_follow_status()
+        .with(eq("https://example.com/follows/abc"), eq("rejected"))
+        .times(1)
+        .returning(|_, _| Ok(()));
+
+    let db: DatabaseRef = Arc::new(mock);
+    let app = test::init_service(create_test_app(db)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/follows/abc/reject")
+        .insert_header(bearer())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_admin_accept_unknown_follow() {
+    let mut mock = MockDatabase::new();
+    mock.expect_get_follow_by_id().returning(|_| Ok(None));
+    mock.expect_update_follow_status().never();
+
+    let db: DatabaseRef = Arc::new(mock);
+    let app = test::init_service(create_test_app(db)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/follows/missing/accept")
+        .insert_header(bearer())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_admin_list_jobs() {
+    let clock = Arc::new(TestClock::new(Utc::now()));
+    let mut scheduler = Scheduler::new(clock.clone());
+    scheduler.register("flaky", Schedule::Every(Duration::seconds(5)), || async {
+        anyhow::bail!("remote unavailable")
+    });
+    clock.advance(Duration::seconds(5));
+    for handle in scheduler.tick() {
+        handle.await.unwrap();
+    }
+
+    let db: DatabaseRef = Arc::new(MockDatabase::new());
+    let app = test::init_service(
+        create_test_app(db)
+            .app_data(web::Data::new(scheduler))
+            .service(handlers::admin::jobs::list_jobs),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/jobs")
+        .insert_header(bearer())
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let body: Value = test::read_body_json(resp).await;
+    let job = &body["jobs"][0];
+    assert_eq!(job["name"], "flaky");
+    assert_eq!(job["schedule"], "every 5s");
+    assert_eq!(job["running"], false);
+    assert_eq!(job["runs"], 1);
+    assert_eq!(job["failures"], 1);
+    assert_eq!(job["last_error"], "remote unavailable");
+    assert!(job["last_run"].is_string());
+    assert!(job["next_run"].is_string());
+}
+
+/// App whose database reads go through a container, so swaps are visible
+fn create_swap_test_app(
+    old: DatabaseRef,
+) -> (
+    Arc<Scheduler>,
+    App<
+        impl actix_web::dev::ServiceFactory<
+            actix_web::dev::ServiceRequest,
+            Config = (),
+            Response = actix_web::dev::ServiceResponse,
+            Error = actix_web::Error,
+            InitError = (),
+        >,
+    >,
+) {
+    let mut scheduler = Scheduler::new(Arc::new(TestClock::new(Utc::now())));
+    scheduler.register(
+        DELIVERY_JOB,
+        Schedule::Every(Duration::seconds(5)),
+        || async { Ok(()) },
+    );
+    let scheduler = Arc::new(scheduler);
+
+    let container = Container::new(Config::default(), old);
+    let app = create_test_app(container.database().clone())
+        .app_data(web::Data::new(container))
+        .app_data(web::Data::from(scheduler.clone()))
+        .service(handlers::admin::database::swap_database);
+    (scheduler, app)
+}
+
+fn list_actors_request() -> test::TestRequest {
+    test::TestRequest::get()
+        .uri("/api/admin/actors")
+        .insert_header(bearer())
+}
+
+fn usernames(body: &Value) -> Vec<&str> {
+    body["actors"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|actor| actor["username"].as_str().unwrap())
+        .collect()
+}
+
+fn database_listing(username: &'static str) -> DatabaseRef {
+    let mut mock = MockDatabase::new();
+    mock.expect_list_actors()
+        .returning(move |_, _| Ok(vec![test_actor(username)]));
+    Arc::new(mock)
+}
+
+#[actix_web::test]
+async fn test_admin_swap_database_repoints_reads() {
+    let (scheduler, app) = create_swap_test_app(database_listing("old"));
+    let app = test::init_service(app).await;
+
+    let body: Value = test::call_and_read_body_json(&app, list_actors_request().to_request()).await;
+    assert_eq!(usernames(&body), vec!["old"]);
+
+    let dir = TempDir::new().unwrap();
+    let url = format!("sqlite://{}?mode=rwc", dir.path().join("new.db").display());
+    let new_db = SqliteDatabase::new(&url).await.unwrap();
+    new_db.run_migrations().await.unwrap();
+    new_db.create_actor(&test_actor("new")).await.unwrap();
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/database/swap")
+        .insert_header(bearer())
+        .set_json(json!({ "database_url": url }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let body: Value = test::call_and_read_body_json(&app, list_actors_request().to_request()).await;
+    assert_eq!(usernames(&body), vec!["new"]);
+    assert!(!scheduler.statuses()[0].paused);
+}
+
+#[actix_web::test]
+async fn test_admin_swap_database_migrates_a_fresh_target() {
+    let (_scheduler, app) = create_swap_test_app(database_listing("old"));
+    let app = test::init_service(app).await;
+
+    let dir = TempDir::new().unwrap();
+    let url = format!(
+        "sqlite://{}?mode=rwc",
+        dir.path().join("fresh.db").display()
+    );
+    let req = test::TestRequest::post()
+        .uri("/api/admin/database/swap")
+        .insert_header(bearer())
+        .set_json(json!({ "database_url": url }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let body: Value = test::call_and_read_body_json(&app, list_actors_request().to_request()).await;
+    assert!(usernames(&body).is_empty());
+}
+
+#[actix_web::test]
+async fn test_admin_swap_database_rejects_unreachable_target() {
+    let (_scheduler, app) = create_swap_test_app(database_listing("old"));
+    let app = test::init_service(app).await;
+
+    let req = test::TestRequest::post()
+        .uri("/api/admin/database/swap")
+        .insert_header(bearer())
+        .set_json(json!({ "database_url": "sqlite:///nonexistent/dir/feder8.db" }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "bad_request");
+    assert!(body["error"]
+        .as_str()
+        .unwrap()
+        .starts_with("New database is not reachable"));
+
+    let body: Value = test::call_and_read_body_json(&app, list_actors_request().to_request()).await;
+    assert_eq!(usernames(&body), vec!["old"]);
+}
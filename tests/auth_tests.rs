@@ -0,0 +1,255 @@
+mod common;
+
+use actix_web::{http::StatusCode, test, web, App, HttpResponse};
+use chrono::Utc;
+use common::OfflineHttpClient;
+use feder8::auth::{hash_token, AuthContext, RequireScope, ADMIN_SCOPE};
+use feder8::database::{DatabaseRef, DbActor, DbToken, MockDatabase};
+use feder8::handlers;
+use mockall::predicate::*;
+use serde_json::Value;
+use std::sync::Arc;
+
+const ACTOR_ID: &str = "https://example.com/users/alice";
+
+async fn whoami(auth: AuthContext) -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({
+        "actor_id": auth.actor_id,
+        "token_id": auth.token_id,
+    }))
+}
+
+fn create_test_app(
+    db: DatabaseRef,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    common::test_app(&db, common::test_config(), Arc::new(OfflineHttpClient))
+        .service(
+            web::resource("/statuses")
+                .wrap(RequireScope("read:statuses"))
+                .route(web::get().to(whoami)),
+        )
+        .service(
+            web::resource("/statuses/new")
+                .wrap(RequireScope("write:statuses"))
+                .route(web::post().to(whoami)),
+        )
+        .service(
+            web::resource("/follows")
+                .wrap(RequireScope("follow"))
+                .route(web::post().to(whoami)),
+        )
+        .service(
+            web::resource("/admin/whoami")
+                .wrap(RequireScope(ADMIN_SCOPE))
+                .route(web::get().to(whoami)),
+        )
+        .service(handlers::admin::actors::list_actors)
+}
+
+fn test_actor(is_admin: bool) -> DbActor {
+    DbActor {
+        is_admin,
+        ..common::actor(ACTOR_ID, "alice", "Alice")
+    }
+}
+
+/// Mock that accepts `token` with the given scopes
+fn mock_with_token(token: &'static str, scopes: &[&str]) -> MockDatabase {
+    let scopes: Vec<String> = scopes.iter().map(|s| s.to_string()).collect();
+    let mut mock = MockDatabase::new();
+    mock.expect_validate_token().returning(move |provided| {
+        if provided != token {
+            return Ok(None);
+        }
+        Ok(Some(DbToken {
+            id: "token-1".to_string(),
+            token_hash: hash_token(token),
+            actor_id: ACTOR_ID.to_string(),
+            scopes: scopes.clone(),
+            created_at: Utc::now(),
+            expires_at: None,
+        }))
+    });
+    mock
+}
+
+fn bearer(token: &str) -> (&'static str, String) {
+    ("Authorization", format!("Bearer {token}"))
+}
+
+#[actix_web::test]
+async fn test_missing_or_invalid_token_is_unauthorized() {
+    let db: DatabaseRef = Arc::new(mock_with_token("good", &["read"]));
+    let app = test::init_service(create_test_app(db)).await;
+
+    let req = test::TestRequest::get().uri("/statuses").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(resp.headers().get("WWW-Authenticate").unwrap(), "Bearer");
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "unauthorized");
+
+    let req = test::TestRequest::get()
+        .uri("/statuses")
+        .insert_header(bearer("bad"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[actix_web::test]
+async fn test_read_scope() {
+    let db: DatabaseRef = Arc::new(mock_with_token("reader", &["read:statuses"]));
+    let app = test::init_service(create_test_app(db)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/statuses")
+        .insert_header(bearer("reader"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["actor_id"], ACTOR_ID);
+    assert_eq!(body["token_id"], "token-1");
+
+    let req = test::TestRequest::post()
+        .uri("/statuses/new")
+        .insert_header(bearer("reader"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Missing required scope: write:statuses");
+    assert_eq!(body["code"], "insufficient_scope");
+    assert_eq!(body["required_scope"], "write:statuses");
+}
+
+#[actix_web::test]
+async fn test_write_class_grants_write_scopes() {
+    let db: DatabaseRef = Arc::new(mock_with_token("writer", &["write"]));
+    let app = test::init_service(create_test_app(db)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/statuses/new")
+        .insert_header(bearer("writer"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get()
+        .uri("/statuses")
+        .insert_header(bearer("writer"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn test_follow_scope() {
+    let db: DatabaseRef = Arc::new(mock_with_token("follower", &["follow"]));
+    let app = test::init_service(create_test_app(db)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/follows")
+        .insert_header(bearer("follower"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let req = test::TestRequest::post()
+        .uri("/statuses/new")
+        .insert_header(bearer("follower"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn test_admin_scope_requires_admin_actor() {
+    let mut mock = mock_with_token("admin", &[ADMIN_SCOPE]);
+    mock.expect_get_actor_by_id()
+        .with(eq(ACTOR_ID))
+        .returning(|_| Ok(Some(test_actor(false))));
+    mock.expect_list_actors().never();
+
+    let db: DatabaseRef = Arc::new(mock);
+    let app = test::init_service(create_test_app(db)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/admin/whoami")
+        .insert_header(bearer("admin"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/actors")
+        .insert_header(bearer("admin"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Actor is not an administrator");
+    assert_eq!(body["code"], "forbidden");
+}
+
+#[actix_web::test]
+async fn test_admin_actor_without_admin_scope_is_forbidden() {
+    let mut mock = mock_with_token("user", &["read", "write"]);
+    mock.expect_get_actor_by_id().never();
+    mock.expect_list_actors().never();
+
+    let db: DatabaseRef = Arc::new(mock);
+    let app = test::init_service(create_test_app(db)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/actors")
+        .insert_header(bearer("user"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["required_scope"], ADMIN_SCOPE);
+}
+
+#[actix_web::test]
+async fn test_admin_scope_with_admin_actor() {
+    let mut mock = mock_with_token("admin", &[ADMIN_SCOPE]);
+    mock.expect_get_actor_by_id()
+        .with(eq(ACTOR_ID))
+        .returning(|_| Ok(Some(test_actor(true))));
+    mock.expect_list_actors()
+        .returning(|_, _| Ok(vec![test_actor(true)]));
+
+    let db: DatabaseRef = Arc::new(mock);
+    let app = test::init_service(create_test_app(db)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/admin/whoami")
+        .insert_header(bearer("admin"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/actors")
+        .insert_header(bearer("admin"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["actors"][0]["username"], "alice");
+}
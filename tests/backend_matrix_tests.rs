@@ -0,0 +1,500 @@
+//! The handler suite against real storage. Each scenario drives the handlers
+//! over HTTP and runs once per backend, so the SQL behind them is exercised
+//! the way requests use it. Error injection stays with `MockDatabase` in
+//! `handler_integration_tests.rs`.
+
+mod common;
+
+use actix_web::{test, App};
+use chrono::{DateTime, Duration, Utc};
+use common::{OfflineHttpClient, ALICE_TOKEN};
+use feder8::config::Config;
+use feder8::database::{DatabaseRef, DbActivity, DbFollowRelation, DbNote, PublishState};
+use feder8::handlers;
+use feder8::models::addressing::PUBLIC;
+use feder8::models::Visibility;
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+const ALICE: &str = "https://example.com/users/alice";
+const BOB: &str = "https://example.com/users/bob";
+const CAROL: &str = "https://remote.example/users/carol";
+const DAVE: &str = "https://remote.example/users/dave";
+const ADMIN_TOKEN: &str = "admin-token";
+
+/// Alice and Bob, with a read and write token for Alice
+async fn seed(db: &DatabaseRef) {
+    common::seed_alice(db).await;
+    db.create_actor(&common::actor(BOB, "bob", "bob"))
+        .await
+        .unwrap();
+}
+
+common::backend_matrix!(
+    actor_documents,
+    outbox_round_trip,
+    direct_notes_stay_private,
+    inbox_matches_recipients,
+    followers_list_accepted_follows,
+    likes_are_counted_and_undone,
+    hashtags_and_timelines,
+    deleted_actors_take_their_notes_activities_and_follows,
+    listings_page_by_cursor,
+);
+
+fn create_test_app(
+    db: &DatabaseRef,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    let config = Config {
+        admin_token: Some(ADMIN_TOKEN.to_string()),
+        interaction_collections_count_only: false,
+        ..common::test_config()
+    };
+    common::test_app(db, config, Arc::new(OfflineHttpClient))
+        .service(handlers::actor::get_actor)
+        .service(handlers::inbox::get_inbox)
+        .service(handlers::inbox::inbox)
+        .service(handlers::outbox::get_outbox)
+        .service(handlers::outbox::post_outbox)
+        .service(handlers::note::get_note)
+        .service(handlers::note::get_statuses)
+        .service(handlers::collections::get_tag_collection)
+        .service(handlers::collections::get_likes)
+        .service(handlers::api::accounts::get_followers)
+        .service(handlers::api::statuses::get_status)
+        .service(handlers::api::timelines::get_public_timeline)
+        .service(handlers::admin::follows::accept_follow)
+}
+
+/// Status and JSON body (`Null` when there is none) of a request
+async fn call(db: &DatabaseRef, req: test::TestRequest) -> (u16, Value) {
+    let app = test::init_service(create_test_app(db)).await;
+    let resp = test::call_service(&app, req.to_request()).await;
+    let status = resp.status().as_u16();
+    let body = test::read_body(resp).await;
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+fn get_request(uri: &str) -> test::TestRequest {
+    test::TestRequest::get()
+        .uri(uri)
+        .insert_header(("Accept", "application/activity+json"))
+}
+
+/// An anonymous GET, seeing what the public sees
+async fn get(db: &DatabaseRef, uri: &str) -> (u16, Value) {
+    call(db, get_request(uri)).await
+}
+
+/// A GET with Alice's token
+async fn get_as_alice(db: &DatabaseRef, uri: &str) -> (u16, Value) {
+    let req = get_request(uri).insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")));
+    call(db, req).await
+}
+
+/// Post a note to Alice's outbox, returning the stored Create
+async fn post_note(db: &DatabaseRef, object: Value) -> Value {
+    let to = object["to"].clone();
+    let req = test::TestRequest::post()
+        .uri("/users/alice/outbox")
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+        .set_json(json!({"type": "Create", "actor": ALICE, "to": to, "object": object}));
+    let (status, create) = call(db, req).await;
+    assert_eq!(status, 201);
+    create
+}
+
+async fn post_inbox(db: &DatabaseRef, username: &str, activity: Value) {
+    let req = test::TestRequest::post()
+        .uri(&format!("/users/{username}/inbox"))
+        .set_json(activity);
+    assert_eq!(call(db, req).await.0, 202);
+}
+
+/// A remote Create of note `n` by Carol, addressed to `to`
+fn remote_create(n: u32, to: &str) -> Value {
+    json!({
+        "id": format!("{CAROL}/activities/{n}"),
+        "type": "Create",
+        "actor": CAROL,
+        "to": [to],
+        "object": {
+            "id": format!("{CAROL}/notes/{n}"),
+            "type": "Note",
+            "attributedTo": CAROL,
+            "content": format!("remote {n}"),
+            "to": [to]
+        }
+    })
+}
+
+fn last_segment(id: &Value) -> &str {
+    id.as_str().unwrap().rsplit('/').next().unwrap()
+}
+
+fn ids(items: &Value) -> Vec<&str> {
+    items
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["id"].as_str().unwrap())
+        .collect()
+}
+
+async fn actor_documents(db: &DatabaseRef) {
+    let (status, actor) = get(db, "/users/alice").await;
+    assert_eq!(status, 200);
+    assert_eq!(actor["id"], ALICE);
+    assert_eq!(actor["type"], "Person");
+    assert_eq!(actor["inbox"], format!("{ALICE}/inbox"));
+
+    assert_eq!(get(db, "/users/nobody").await.0, 404);
+}
+
+async fn outbox_round_trip(db: &DatabaseRef) {
+    let create = post_note(
+        db,
+        json!({"type": "Note", "content": "Hello", "to": [PUBLIC]}),
+    )
+    .await;
+    let note_id = &create["object"]["id"];
+    let uuid = last_segment(note_id);
+
+    let (_, outbox) = get(db, "/users/alice/outbox").await;
+    assert_eq!(outbox["totalItems"], 1);
+    let (_, page) = get(db, "/users/alice/outbox?page=true").await;
+    assert_eq!(page["orderedItems"][0]["id"], create["id"]);
+
+    let (status, note) = get(db, &format!("/notes/{uuid}")).await;
+    assert_eq!(status, 200);
+    assert_eq!(note["content"], "Hello");
+
+    let (_, statuses) = get(db, "/users/alice/statuses").await;
+    assert_eq!(
+        ids(&statuses["orderedItems"]),
+        vec![note_id.as_str().unwrap()]
+    );
+
+    let (status, api_status) = get(db, &format!("/api/v1/statuses/{uuid}")).await;
+    assert_eq!(status, 200);
+    assert_eq!(api_status["content"], "Hello");
+}
+
+async fn direct_notes_stay_private(db: &DatabaseRef) {
+    let create = post_note(db, json!({"type": "Note", "content": "Psst", "to": [DAVE]})).await;
+    let uuid = last_segment(&create["object"]["id"]);
+
+    // Hidden from the public outbox count and the note route, but not from
+    // the author's own token
+    let (_, outbox) = get(db, "/users/alice/outbox").await;
+    assert_eq!(outbox["totalItems"], 0);
+    assert_eq!(get(db, &format!("/notes/{uuid}")).await.0, 404);
+    let (_, statuses) = get(db, "/users/alice/statuses").await;
+    assert_eq!(statuses["totalItems"], 0);
+    let (_, statuses) = get_as_alice(db, "/users/alice/statuses").await;
+    assert_eq!(statuses["totalItems"], 1);
+}
+
+async fn inbox_matches_recipients(db: &DatabaseRef) {
+    post_inbox(db, "alice", remote_create(1, ALICE)).await;
+    post_inbox(db, "bob", remote_create(2, BOB)).await;
+    // A redelivery is stored once
+    post_inbox(db, "alice", remote_create(1, ALICE)).await;
+
+    let (status, inbox) = get_as_alice(db, "/users/alice/inbox").await;
+    assert_eq!(status, 200);
+    assert_eq!(inbox["totalItems"], 1);
+    assert_eq!(
+        inbox["orderedItems"][0]["id"],
+        format!("{CAROL}/activities/1")
+    );
+
+    // Bob's note is stored all the same
+    let stored = db
+        .get_note_by_id(&format!("{CAROL}/notes/2"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(stored.content, "remote 2");
+}
+
+async fn followers_list_accepted_follows(db: &DatabaseRef) {
+    let follow = |n: u32, actor: &str| {
+        json!({
+            "id": format!("{actor}/follows/{n}"),
+            "type": "Follow",
+            "actor": actor,
+            "object": ALICE
+        })
+    };
+    post_inbox(db, "alice", follow(1, CAROL)).await;
+    post_inbox(db, "alice", follow(2, DAVE)).await;
+
+    // Pending follows aren't listed until accepted
+    let (_, followers) = get(db, "/api/v1/accounts/alice/followers").await;
+    assert!(followers.as_array().unwrap().is_empty());
+
+    let pending = db
+        .get_follow_by_actors(CAROL, ALICE)
+        .await
+        .unwrap()
+        .unwrap();
+    let req = test::TestRequest::post()
+        .uri(&format!(
+            "/api/admin/follows/{}/accept",
+            last_segment(&json!(pending.id))
+        ))
+        .insert_header(("Authorization", format!("Bearer {ADMIN_TOKEN}")));
+    assert_eq!(call(db, req).await.0, 200);
+
+    let (_, followers) = get(db, "/api/v1/accounts/alice/followers").await;
+    assert_eq!(ids(&followers), vec![CAROL]);
+
+    post_inbox(
+        db,
+        "alice",
+        json!({
+            "id": format!("{CAROL}/undo/1"),
+            "type": "Undo",
+            "actor": CAROL,
+            "object": follow(1, CAROL)
+        }),
+    )
+    .await;
+    let (_, followers) = get(db, "/api/v1/accounts/alice/followers").await;
+    assert!(followers.as_array().unwrap().is_empty());
+}
+
+async fn likes_are_counted_and_undone(db: &DatabaseRef) {
+    let create = post_note(
+        db,
+        json!({"type": "Note", "content": "Like me", "to": [PUBLIC]}),
+    )
+    .await;
+    let note_id = create["object"]["id"].as_str().unwrap().to_string();
+    let likes = format!("/notes/{}/likes", last_segment(&create["object"]["id"]));
+    let like = json!({
+        "id": format!("{CAROL}/likes/1"),
+        "type": "Like",
+        "actor": CAROL,
+        "object": note_id
+    });
+
+    post_inbox(db, "alice", like.clone()).await;
+    let (_, collection) = get(db, &likes).await;
+    assert_eq!(collection["totalItems"], 1);
+
+    post_inbox(
+        db,
+        "alice",
+        json!({
+            "id": format!("{CAROL}/undo/1"),
+            "type": "Undo",
+            "actor": CAROL,
+            "object": like
+        }),
+    )
+    .await;
+    let (_, collection) = get(db, &likes).await;
+    assert_eq!(collection["totalItems"], 0);
+}
+
+async fn hashtags_and_timelines(db: &DatabaseRef) {
+    for (content, tag) in [("about rust", "#Rust"), ("about cats", "#cats")] {
+        post_note(
+            db,
+            json!({
+                "type": "Note",
+                "content": content,
+                "tag": [{"type": "Hashtag", "name": tag}],
+                "to": [PUBLIC]
+            }),
+        )
+        .await;
+    }
+    post_inbox(db, "alice", remote_create(1, PUBLIC)).await;
+
+    let (_, collection) = get(db, "/users/alice/collections/tags/rust").await;
+    assert_eq!(collection["totalItems"], 1);
+    assert_eq!(collection["orderedItems"][0]["content"], "about rust");
+
+    let (_, timeline) = get(db, "/api/timelines/public").await;
+    assert_eq!(timeline.as_array().unwrap().len(), 3);
+    let (_, timeline) = get(db, "/api/timelines/public?local=true").await;
+    assert_eq!(timeline.as_array().unwrap().len(), 2);
+}
+
+async fn deleted_actors_take_their_notes_activities_and_follows(db: &DatabaseRef) {
+    let create = post_note(
+        db,
+        json!({"type": "Note", "content": "Bye", "to": [PUBLIC]}),
+    )
+    .await;
+    let note_id = create["object"]["id"].as_str().unwrap();
+    post_inbox(db, "alice", remote_create(1, ALICE)).await;
+    for (n, (follower, following)) in [(ALICE, CAROL), (CAROL, ALICE), (DAVE, CAROL)]
+        .into_iter()
+        .enumerate()
+    {
+        db.create_follow(&DbFollowRelation {
+            id: format!("https://example.com/follows/{n}"),
+            follower_id: follower.to_string(),
+            following_id: following.to_string(),
+            status: "accepted".to_string(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        })
+        .await
+        .unwrap();
+    }
+
+    db.delete_actor(ALICE).await.unwrap();
+    assert!(db
+        .get_activities_by_actor(ALICE, 20, 0)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(db.get_note_by_id(note_id).await.unwrap().is_none());
+    assert!(db
+        .get_follow_by_actors(ALICE, CAROL)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(db
+        .get_follow_by_actors(CAROL, ALICE)
+        .await
+        .unwrap()
+        .is_none());
+    // Remote actors' rows have no actor row to go with
+    assert_eq!(
+        db.get_activities_by_actor(CAROL, 20, 0)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+    assert!(db
+        .get_note_by_id(&format!("{CAROL}/notes/1"))
+        .await
+        .unwrap()
+        .is_some());
+    assert!(db
+        .get_follow_by_actors(DAVE, CAROL)
+        .await
+        .unwrap()
+        .is_some());
+}
+
+/// Activity `n` by `actor_id` to `to`, published `n` seconds after `start`
+fn stored_activity(n: i64, actor_id: &str, to: &str, start: DateTime<Utc>) -> DbActivity {
+    DbActivity {
+        id: format!("{actor_id}/activities/{n}"),
+        actor_id: actor_id.to_string(),
+        activity_type: "Create".to_string(),
+        object: json!({"type": "Note", "content": format!("activity {n}")}),
+        target: None,
+        to_recipients: vec![to.to_string()],
+        cc_recipients: vec![],
+        published: start + Duration::seconds(n),
+        visibility: Visibility::Public,
+        state: PublishState::Published,
+        created_at: start + Duration::seconds(n),
+    }
+}
+
+fn activity_ids(actor_id: &str, ns: impl IntoIterator<Item = i64>) -> Vec<String> {
+    ns.into_iter()
+        .map(|n| format!("{actor_id}/activities/{n}"))
+        .collect()
+}
+
+async fn listings_page_by_cursor(db: &DatabaseRef) {
+    let start = Utc::now() - Duration::hours(1);
+    let store = |n: i64| async move {
+        db.create_activity(&stored_activity(n, ALICE, PUBLIC, start))
+            .await
+            .unwrap();
+        db.create_activity(&stored_activity(n, CAROL, ALICE, start))
+            .await
+            .unwrap();
+    };
+    for n in 1..=25 {
+        store(n).await;
+    }
+    let (_, outbox) = get(db, "/users/alice/outbox").await;
+    let next = outbox["first"]["next"].as_str().unwrap().to_string();
+    let next = next.strip_prefix("https://example.com").unwrap();
+
+    // Pages carry on from where the last one stopped, whatever arrives
+    for n in 26..=30 {
+        store(n).await;
+    }
+    let (_, page) = get(db, next).await;
+    assert_eq!(
+        ids(&page["orderedItems"]),
+        activity_ids(ALICE, (1..=5).rev())
+    );
+    let (_, page) = get_as_alice(db, next).await;
+    assert_eq!(
+        ids(&page["orderedItems"]),
+        activity_ids(ALICE, (1..=5).rev())
+    );
+    let min_id = format!("{ALICE}/activities/5")
+        .replace(':', "%3A")
+        .replace('/', "%2F");
+    let (_, page) = get_as_alice(db, &format!("/users/alice/outbox?min_id={min_id}")).await;
+    assert_eq!(
+        ids(&page["orderedItems"]),
+        activity_ids(ALICE, (6..=25).rev())
+    );
+
+    let max_id = format!("{CAROL}/activities/6")
+        .replace(':', "%3A")
+        .replace('/', "%2F");
+    let (status, inbox) =
+        get_as_alice(db, &format!("/users/alice/inbox?limit=3&max_id={max_id}")).await;
+    assert_eq!(status, 200);
+    assert_eq!(ids(&inbox["orderedItems"]), activity_ids(CAROL, [5, 4, 3]));
+    let (_, inbox) = get_as_alice(db, &format!("/users/alice/inbox?limit=3&min_id={max_id}")).await;
+    assert_eq!(ids(&inbox["orderedItems"]), activity_ids(CAROL, [9, 8, 7]));
+
+    for n in 1..=3 {
+        db.create_note(&DbNote {
+            id: format!("https://example.com/notes/{n}"),
+            attributed_to: ALICE.to_string(),
+            content: format!("note {n}"),
+            to_recipients: vec![PUBLIC.to_string()],
+            cc_recipients: vec![],
+            published: start + Duration::seconds(n),
+            in_reply_to: None,
+            tags: vec![],
+            attachments: vec![],
+            visibility: Visibility::Public,
+            state: PublishState::Published,
+            pinned: false,
+            created_at: start + Duration::seconds(n),
+            in_reply_to_actor: None,
+            thread_depth: Some(0),
+            thread_truncated: false,
+            sensitive: false,
+            summary: None,
+            language: None,
+            content_map: None,
+        })
+        .await
+        .unwrap();
+    }
+    let (_, statuses) = get(db, "/users/alice/statuses?max_id=3").await;
+    assert_eq!(
+        ids(&statuses["orderedItems"]),
+        vec!["https://example.com/notes/2", "https://example.com/notes/1"]
+    );
+}
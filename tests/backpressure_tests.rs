@@ -0,0 +1,425 @@
+mod common;
+
+use actix_web::{test, web, App};
+use chrono::Utc;
+use common::OfflineHttpClient;
+use feder8::config::Config;
+use feder8::database::{Database, DatabaseRef, DbDelivery, DbFollowRelation, DeliveryPriority};
+use feder8::handlers;
+use feder8::metrics::Metrics;
+use feder8::models::addressing::PUBLIC;
+use feder8::services::backpressure::{HostResolver, InboxBackpressure};
+use feder8::services::inbound_usage::{self, InboundUsage};
+use serde_json::{json, Value};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ALICE: &str = "https://example.com/users/alice";
+const FRIEND: &str = "https://friend.example/users/carol";
+const STRANGER: &str = "https://stranger.example/users/dave";
+const FRIEND_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+const STRANGER_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 7));
+const OTHER_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::new(198, 51, 100, 8));
+/// Reverse proxy the server trusts to say who it forwards for
+const PROXY_ADDR: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+
+/// friend.example lives at `FRIEND_ADDR`; nothing else resolves
+struct StaticResolver;
+
+#[async_trait::async_trait]
+impl HostResolver for StaticResolver {
+    async fn lookup(&self, host: &str) -> Vec<IpAddr> {
+        match host {
+            "friend.example" => vec![FRIEND_ADDR],
+            _ => Vec::new(),
+        }
+    }
+}
+
+fn backpressure(
+    max_in_flight: usize,
+    max_pending: u32,
+    metrics: Arc<Metrics>,
+) -> InboxBackpressure {
+    InboxBackpressure::new(max_in_flight, max_pending, 30, metrics)
+        .with_resolver(Arc::new(StaticResolver))
+}
+
+/// Alice, who follows everyone on friend.example
+async fn create_test_database(dir: &TempDir) -> DatabaseRef {
+    let db = common::sqlite_database(dir).await;
+    db.create_actor(&common::actor(ALICE, "alice", "Alice"))
+        .await
+        .unwrap();
+    db.create_follow(&DbFollowRelation {
+        id: "https://example.com/follows/1".to_string(),
+        follower_id: ALICE.to_string(),
+        following_id: FRIEND.to_string(),
+        status: "accepted".to_string(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    })
+    .await
+    .unwrap();
+    Arc::new(db)
+}
+
+fn create_test_app(
+    db: &DatabaseRef,
+    backpressure: Arc<InboxBackpressure>,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    let config = Config {
+        admin_token: Some("secret".to_string()),
+        trusted_proxies: vec![PROXY_ADDR],
+        ..common::test_config()
+    };
+    common::test_app(db, config, Arc::new(OfflineHttpClient))
+        .app_data(web::Data::from(backpressure))
+        .service(handlers::inbox::inbox)
+        .service(handlers::admin::hosts::list_hosts)
+}
+
+fn create_note(actor: &str, n: u32) -> Value {
+    let host = actor.split('/').nth(2).unwrap();
+    json!({
+        "id": format!("https://{host}/activities/{n}"),
+        "type": "Create",
+        "actor": actor,
+        "to": [PUBLIC],
+        "object": {
+            "id": format!("https://{host}/notes/{n}"),
+            "type": "Note",
+            "attributedTo": actor,
+            "content": "hello",
+            "to": [PUBLIC]
+        }
+    })
+}
+
+/// POST `activity` from the address its actor's server lives at
+fn post_inbox(activity: Value) -> test::TestRequest {
+    let address = match activity["actor"].as_str() {
+        Some(FRIEND) => FRIEND_ADDR,
+        Some(STRANGER) => STRANGER_ADDR,
+        _ => OTHER_ADDR,
+    };
+    post_inbox_from(address, activity)
+}
+
+fn post_inbox_from(address: IpAddr, activity: Value) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/users/alice/inbox")
+        .peer_addr(SocketAddr::new(address, 40000))
+        .set_json(activity)
+}
+
+fn counter(metrics: &Metrics, name: &str) -> f64 {
+    metrics
+        .registry()
+        .gather()
+        .iter()
+        .find(|family| family.name() == name)
+        .map(|family| family.get_metric()[0].get_counter().get_value())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_saturated_inbox_asks_strangers_to_retry_but_admits_followed_hosts() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let metrics = Arc::new(Metrics::new());
+    let backpressure = Arc::new(backpressure(2, 100, metrics.clone()));
+    let app = test::init_service(create_test_app(&db, backpressure.clone())).await;
+
+    // A stub processor sits on every slot
+    let stuck: Vec<_> = (0..2)
+        .map(|_| backpressure.try_acquire().unwrap())
+        .collect();
+    assert_eq!(backpressure.in_flight(), 2);
+
+    let resp = test::call_service(&app, post_inbox(create_note(STRANGER, 1)).to_request()).await;
+    assert_eq!(resp.status(), 503);
+    assert_eq!(resp.headers().get("Retry-After").unwrap(), "30");
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "overloaded");
+    assert!(db
+        .get_note_by_id("https://stranger.example/notes/1")
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(counter(&metrics, "feder8_inbox_rejected_total"), 1.0);
+
+    // Claiming to be a followed host from elsewhere doesn't get anyone in
+    let impostor = post_inbox_from(STRANGER_ADDR, create_note(FRIEND, 2));
+    let resp = test::call_service(&app, impostor.to_request()).await;
+    assert_eq!(resp.status(), 503);
+
+    // Hosts we follow are a priority class and always get in
+    let resp = test::call_service(&app, post_inbox(create_note(FRIEND, 1)).to_request()).await;
+    assert_eq!(resp.status(), 202);
+    assert!(db
+        .get_note_by_id("https://friend.example/notes/1")
+        .await
+        .unwrap()
+        .is_some());
+
+    // Once the processor catches up, strangers are welcome again
+    drop(stuck);
+    assert_eq!(backpressure.in_flight(), 0);
+    let resp = test::call_service(&app, post_inbox(create_note(STRANGER, 2)).to_request()).await;
+    assert_eq!(resp.status(), 202);
+    assert_eq!(backpressure.in_flight(), 0);
+}
+
+#[tokio::test]
+async fn test_deep_delivery_queue_pushes_back() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    for n in 0..3 {
+        db.enqueue_delivery(&DbDelivery {
+            id: format!("delivery-{n}"),
+            inbox_url: "https://remote.example/inbox".to_string(),
+            activity: json!({"type": "Create"}),
+            priority: DeliveryPriority::Broadcast,
+            attempts: 0,
+            next_attempt_at: Utc::now(),
+            last_error: None,
+            created_at: Utc::now(),
+        })
+        .await
+        .unwrap();
+    }
+    let backpressure = Arc::new(backpressure(8, 2, Arc::new(Metrics::new())));
+    let app = test::init_service(create_test_app(&db, backpressure)).await;
+
+    let resp = test::call_service(&app, post_inbox(create_note(STRANGER, 1)).to_request()).await;
+    assert_eq!(resp.status(), 503);
+    assert_eq!(resp.headers().get("Retry-After").unwrap(), "30");
+
+    let resp = test::call_service(&app, post_inbox(create_note(FRIEND, 1)).to_request()).await;
+    assert_eq!(resp.status(), 202);
+}
+
+#[tokio::test]
+async fn test_hosts_over_their_quota_are_asked_to_retry() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    // Every payload is the same size, and three of them fit the quota
+    let size = serde_json::to_vec(&create_note(STRANGER, 1)).unwrap().len() as u64;
+    let metrics = Arc::new(Metrics::new());
+    let backpressure =
+        Arc::new(backpressure(8, 100, metrics.clone()).with_host_quota(3 * size + size / 2));
+    let app = test::init_service(create_test_app(&db, backpressure)).await;
+
+    for n in 1..=3 {
+        let resp =
+            test::call_service(&app, post_inbox(create_note(STRANGER, n)).to_request()).await;
+        assert_eq!(resp.status(), 202, "payload {n}");
+    }
+
+    let resp = test::call_service(&app, post_inbox(create_note(STRANGER, 4)).to_request()).await;
+    assert_eq!(resp.status(), 429);
+    let retry_after: u64 = resp
+        .headers()
+        .get("Retry-After")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    // Until the first payload's minute leaves the hour-long window
+    assert!((3540..=3600).contains(&retry_after), "{retry_after}");
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "quota_exceeded");
+    assert!(db
+        .get_note_by_id("https://stranger.example/notes/4")
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(counter(&metrics, "feder8_inbox_quota_rejected_total"), 1.0);
+
+    // Claiming to be from a followed host is no way around the quota
+    let impostor = post_inbox_from(STRANGER_ADDR, create_note(FRIEND, 1));
+    let resp = test::call_service(&app, impostor.to_request()).await;
+    assert_eq!(resp.status(), 429);
+
+    // Rejected payloads aren't counted, and other addresses have their own
+    // quota
+    let other = create_note("https://other.example/users/erin", 1);
+    let other_size = serde_json::to_vec(&other).unwrap().len() as u64;
+    let resp = test::call_service(&app, post_inbox(other).to_request()).await;
+    assert_eq!(resp.status(), 202);
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/api/admin/hosts")
+            .insert_header(("Authorization", "Bearer secret"))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["quota_bytes"], 3 * size + size / 2);
+    assert_eq!(body["window_secs"], 3600);
+    assert_eq!(body["hosts"][0]["host"], "198.51.100.7");
+    assert_eq!(body["hosts"][0]["bytes"], 3 * size);
+    assert_eq!(body["hosts"][0]["remaining"], size / 2);
+    assert_eq!(body["hosts"][1]["host"], "198.51.100.8");
+    assert_eq!(body["hosts"][1]["bytes"], other_size);
+}
+
+#[tokio::test]
+async fn test_followed_hosts_are_exempt_from_the_quota_but_counted() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let size = serde_json::to_vec(&create_note(FRIEND, 1)).unwrap().len() as u64;
+    let metrics = Arc::new(Metrics::new());
+    let backpressure = Arc::new(backpressure(8, 100, metrics.clone()).with_host_quota(size));
+    let app = test::init_service(create_test_app(&db, backpressure.clone())).await;
+
+    for n in 1..=4 {
+        let resp = test::call_service(&app, post_inbox(create_note(FRIEND, n)).to_request()).await;
+        assert_eq!(resp.status(), 202, "payload {n}");
+    }
+    assert_eq!(counter(&metrics, "feder8_inbox_quota_rejected_total"), 0.0);
+    assert_eq!(
+        backpressure.usage().by_host(Utc::now()),
+        vec![("192.0.2.1".to_string(), 4 * size)]
+    );
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/api/admin/hosts")
+            .insert_header(("Authorization", "Bearer secret"))
+            .to_request(),
+    )
+    .await;
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["hosts"][0]["remaining"], 0);
+}
+
+#[tokio::test]
+async fn test_quotas_count_the_address_a_trusted_proxy_forwards_for() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let size = serde_json::to_vec(&create_note(STRANGER, 1)).unwrap().len() as u64;
+    let backpressure =
+        Arc::new(backpressure(8, 100, Arc::new(Metrics::new())).with_host_quota(size));
+    let app = test::init_service(create_test_app(&db, backpressure.clone())).await;
+    let forwarded = |from: IpAddr, client: IpAddr, activity: Value| {
+        post_inbox_from(from, activity).insert_header(("X-Forwarded-For", client.to_string()))
+    };
+
+    // Behind the proxy each client has its own quota, and followed hosts are
+    // recognized by their own address
+    let resp = test::call_service(
+        &app,
+        forwarded(PROXY_ADDR, STRANGER_ADDR, create_note(STRANGER, 1)).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 202);
+    for n in 1..=2 {
+        let resp = test::call_service(
+            &app,
+            forwarded(PROXY_ADDR, FRIEND_ADDR, create_note(FRIEND, n)).to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), 202, "payload {n}");
+    }
+    let resp = test::call_service(
+        &app,
+        forwarded(PROXY_ADDR, STRANGER_ADDR, create_note(STRANGER, 2)).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 429);
+
+    // Anyone else forwarding for a followed host is not believed
+    let resp = test::call_service(
+        &app,
+        forwarded(STRANGER_ADDR, FRIEND_ADDR, create_note(FRIEND, 3)).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), 429);
+
+    let hosts: Vec<String> = backpressure
+        .usage()
+        .by_host(Utc::now())
+        .into_iter()
+        .map(|(host, _)| host)
+        .collect();
+    assert_eq!(
+        hosts,
+        vec![FRIEND_ADDR.to_string(), STRANGER_ADDR.to_string()]
+    );
+}
+
+#[tokio::test]
+async fn test_inbound_usage_survives_a_restart() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let metrics = Metrics::new();
+    let now = Utc::now();
+
+    let usage = InboundUsage::new(1000);
+    usage.charge("stranger.example", 600, now - chrono::Duration::minutes(90));
+    usage.charge("stranger.example", 700, now - chrono::Duration::minutes(30));
+    usage.charge("friend.example", 50, now);
+    inbound_usage::persist(&usage, &db, &metrics, now)
+        .await
+        .unwrap();
+
+    // Saving again replaces what was saved
+    inbound_usage::persist(&usage, &db, &metrics, now)
+        .await
+        .unwrap();
+
+    let restarted = InboundUsage::new(1000);
+    assert_eq!(
+        inbound_usage::restore(&restarted, &db, now).await.unwrap(),
+        2
+    );
+    assert_eq!(
+        restarted.by_host(now),
+        vec![
+            ("stranger.example".to_string(), 700),
+            ("friend.example".to_string(), 50)
+        ]
+    );
+    assert!(restarted.try_charge("stranger.example", 400, now).is_err());
+}
+
+#[tokio::test]
+async fn test_is_following_host_matches_accepted_follows_of_local_actors() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    db.create_follow(&DbFollowRelation {
+        id: "https://example.com/follows/2".to_string(),
+        follower_id: ALICE.to_string(),
+        following_id: "https://pending.example/users/erin".to_string(),
+        status: "pending".to_string(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    })
+    .await
+    .unwrap();
+
+    assert!(db.is_following_host("friend.example").await.unwrap());
+    assert!(!db.is_following_host("pending.example").await.unwrap());
+    assert!(!db.is_following_host("stranger.example").await.unwrap());
+    // Host names match whole, not as prefixes
+    assert!(!db.is_following_host("friend.exam").await.unwrap());
+    // and `%` or `_` in them are not wildcards
+    assert!(!db.is_following_host("friend_example").await.unwrap());
+    assert!(!db.is_following_host("%").await.unwrap());
+}
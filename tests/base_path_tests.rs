@@ -0,0 +1,234 @@
+mod common;
+
+use actix_web::{test, web, App};
+use common::OfflineHttpClient;
+use feder8::config::Config;
+use feder8::database::DatabaseRef;
+use feder8::handlers;
+use feder8::models::addressing::PUBLIC;
+use feder8::services::keys::KeyManager;
+use feder8::urls::UrlBuilder;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ADMIN_TOKEN: &str = "test-admin-token";
+const ORIGIN: &str = "https://example.com";
+const BASE: &str = "https://example.com/fedi";
+const ALICE: &str = "https://example.com/fedi/users/alice";
+
+/// Every handler that hands out URLs, for a node deployed under `/fedi`, with
+/// routes mounted the way `main` mounts them
+fn create_test_app(
+    db: &DatabaseRef,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    let config = Config {
+        base_path: Some("/fedi".to_string()),
+        admin_token: Some(ADMIN_TOKEN.to_string()),
+        ..common::test_config()
+    };
+    let urls = UrlBuilder::from_config(&config);
+    common::test_app(db, config, Arc::new(OfflineHttpClient))
+        .app_data(web::Data::new(KeyManager::with_key_size(1024)))
+        .service(handlers::webfinger::webfinger)
+        .service(handlers::host_meta::host_meta_json)
+        .service(
+            web::scope(urls.base_path())
+                .service(handlers::admin::actors::create_actor)
+                .service(handlers::admin::tokens::issue_token)
+                .service(handlers::actor::get_actor)
+                .service(handlers::inbox::get_inbox)
+                .service(handlers::outbox::get_outbox)
+                .service(handlers::outbox::post_outbox)
+                .service(handlers::collections::get_featured)
+                .service(handlers::collections::get_tag_collection)
+                .service(handlers::collections::get_replies)
+                .service(handlers::api::statuses::get_status),
+        )
+}
+
+fn under_base(url: &Value) -> bool {
+    url.as_str()
+        .is_some_and(|url| url.starts_with(&format!("{BASE}/")))
+}
+
+/// The request path that dereferences one of our own URLs
+fn path_of(url: &str) -> String {
+    url.strip_prefix(ORIGIN)
+        .unwrap_or_else(|| panic!("{url} is not on {ORIGIN}"))
+        .to_string()
+}
+
+#[tokio::test]
+async fn test_urls_are_consistent_under_a_base_path() {
+    let dir = TempDir::new().unwrap();
+    let db: DatabaseRef = Arc::new(common::sqlite_database(&dir).await);
+    let app = test::init_service(create_test_app(&db)).await;
+    let admin = ("Authorization", format!("Bearer {ADMIN_TOKEN}"));
+
+    // Actors are created with ids under the base path
+    let req = test::TestRequest::post()
+        .uri("/fedi/api/admin/actors")
+        .insert_header(admin.clone())
+        .set_json(json!({"username": "alice"}))
+        .to_request();
+    let created: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(created["id"], ALICE);
+
+    let req = test::TestRequest::post()
+        .uri("/fedi/api/admin/tokens")
+        .insert_header(admin)
+        .set_json(json!({"username": "alice", "scopes": ["read", "write"]}))
+        .to_request();
+    let issued: Value = test::call_and_read_body_json(&app, req).await;
+    let alice = (
+        "Authorization",
+        format!("Bearer {}", issued["token"].as_str().unwrap()),
+    );
+
+    // The actor document points everywhere under the base path
+    let req = test::TestRequest::get()
+        .uri("/fedi/users/alice")
+        .insert_header(("Accept", "application/activity+json"))
+        .to_request();
+    let actor: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(actor["id"], ALICE);
+    assert_eq!(actor["inbox"], format!("{ALICE}/inbox"));
+    assert_eq!(actor["outbox"], format!("{ALICE}/outbox"));
+    assert_eq!(actor["followers"], format!("{ALICE}/followers"));
+    assert_eq!(actor["following"], format!("{ALICE}/following"));
+    assert_eq!(actor["featured"], format!("{ALICE}/collections/featured"));
+    assert_eq!(actor["publicKey"]["id"], format!("{ALICE}#main-key"));
+
+    // Discovery resolves to the same actor, while /.well-known stays at the root
+    let req = test::TestRequest::get()
+        .uri("/.well-known/webfinger?resource=acct:alice@example.com")
+        .to_request();
+    let jrd: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(jrd["links"][0]["href"], ALICE);
+
+    let req = test::TestRequest::get()
+        .uri("/.well-known/host-meta.json")
+        .to_request();
+    let host_meta: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(
+        host_meta["links"][0]["template"],
+        "https://example.com/.well-known/webfinger?resource={uri}"
+    );
+
+    // Posted activities and notes get ids under the base path
+    let req = test::TestRequest::post()
+        .uri("/fedi/users/alice/outbox")
+        .insert_header(alice.clone())
+        .set_json(json!({
+            "type": "Create",
+            "actor": ALICE,
+            "object": {
+                "type": "Note",
+                "content": "hello from a subpath",
+                "tag": [{"type": "Hashtag", "name": "#rust"}],
+                "to": [PUBLIC]
+            },
+            "to": [PUBLIC]
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let create: Value = test::read_body_json(resp).await;
+    assert!(under_base(&create["id"]), "{}", create["id"]);
+    let note_id = create["object"]["id"].as_str().unwrap().to_string();
+    assert!(note_id.starts_with(&format!("{BASE}/notes/")), "{note_id}");
+
+    // The featured collection id the actor advertises is the pin target
+    let req = test::TestRequest::post()
+        .uri("/fedi/users/alice/outbox")
+        .insert_header(alice.clone())
+        .set_json(json!({
+            "type": "Add",
+            "actor": ALICE,
+            "object": note_id,
+            "target": actor["featured"]
+        }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 201);
+
+    // Collections carry their own ids under the base path
+    for id in [
+        format!("{ALICE}/outbox"),
+        format!("{ALICE}/collections/featured"),
+        format!("{ALICE}/collections/tags/rust"),
+        format!("{note_id}/replies"),
+    ] {
+        let req = test::TestRequest::get().uri(&path_of(&id)).to_request();
+        let collection: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(collection["id"], id);
+    }
+
+    let req = test::TestRequest::get()
+        .uri("/fedi/users/alice/inbox")
+        .insert_header(alice)
+        .to_request();
+    let inbox: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(inbox["id"], format!("{ALICE}/inbox"));
+
+    // And the Mastodon API finds the note by the last segment of that id
+    let short_id = note_id.rsplit('/').next().unwrap();
+    let req = test::TestRequest::get()
+        .uri(&format!("/fedi/api/v1/statuses/{short_id}"))
+        .to_request();
+    let status: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(status["uri"], note_id);
+}
+
+#[tokio::test]
+async fn test_discovery_chain_follows_routed_ids() {
+    let dir = TempDir::new().unwrap();
+    let db: DatabaseRef = Arc::new(common::sqlite_database(&dir).await);
+    let app = test::init_service(create_test_app(&db)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/fedi/api/admin/actors")
+        .insert_header(("Authorization", format!("Bearer {ADMIN_TOKEN}")))
+        .set_json(json!({"username": "alice"}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 201);
+
+    // Webfinger answers at the root...
+    let req = test::TestRequest::get()
+        .uri("/.well-known/webfinger?resource=acct:alice@example.com")
+        .to_request();
+    let jrd: Value = test::call_and_read_body_json(&app, req).await;
+
+    // ...and every id from there on resolves under the base path
+    let req = test::TestRequest::get()
+        .uri(&path_of(jrd["links"][0]["href"].as_str().unwrap()))
+        .insert_header(("Accept", "application/activity+json"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let actor: Value = test::read_body_json(resp).await;
+    assert_eq!(actor["id"], ALICE);
+
+    let req = test::TestRequest::get()
+        .uri(&path_of(actor["outbox"].as_str().unwrap()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let outbox: Value = test::read_body_json(resp).await;
+    assert_eq!(outbox["id"], actor["outbox"]);
+    assert_eq!(outbox["type"], "OrderedCollection");
+
+    // Nothing is served outside the base path
+    for uri in ["/users/alice", "/users/alice/outbox"] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404, "{uri}");
+    }
+}
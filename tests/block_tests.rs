@@ -0,0 +1,213 @@
+mod common;
+
+use actix_web::{http::StatusCode, test};
+use chrono::Utc;
+use common::ALICE_TOKEN;
+use feder8::database::{DatabaseRef, DbFollowRelation, DeliveryPriority};
+use feder8::handlers;
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode as ClientStatus};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ALICE: &str = "https://example.com/users/alice";
+const BOB: &str = "https://remote.example/users/bob";
+const BOB_INBOX: &str = "https://remote.example/users/bob/inbox";
+const CAROL: &str = "https://remote.example/users/carol";
+
+/// Serves Bob's actor document so his inbox can be found
+struct RemoteActors;
+
+#[async_trait::async_trait]
+impl HttpClient for RemoteActors {
+    async fn send(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        let (status, body) = if request.url == BOB {
+            let actor = json!({
+                "id": BOB,
+                "type": "Person",
+                "preferredUsername": "bob",
+                "inbox": BOB_INBOX
+            });
+            (200, serde_json::to_vec(&actor)?)
+        } else {
+            (404, Vec::new())
+        };
+        Ok(HttpResponse {
+            status: ClientStatus(status),
+            headers: HashMap::new(),
+            body,
+        })
+    }
+}
+
+async fn follow(db: &DatabaseRef, follower: &str, following: &str) {
+    db.create_follow(&DbFollowRelation {
+        id: format!("{follower}/follows/{}", uuid::Uuid::new_v4()),
+        follower_id: follower.to_string(),
+        following_id: following.to_string(),
+        status: "accepted".to_string(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    })
+    .await
+    .unwrap();
+}
+
+macro_rules! block_app {
+    ($db:expr) => {{
+        test::init_service(
+            common::test_app(&$db, common::test_config(), Arc::new(RemoteActors))
+                .service(handlers::inbox::inbox)
+                .service(handlers::outbox::post_outbox),
+        )
+        .await
+    }};
+}
+
+fn post_outbox(activity: Value) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/users/alice/outbox")
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+        .set_json(activity)
+}
+
+fn post_inbox(activity: Value) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/users/alice/inbox")
+        .set_json(activity)
+}
+
+fn block(target: &str) -> Value {
+    json!({"type": "Block", "actor": ALICE, "object": target})
+}
+
+fn follow_from(actor: &str) -> Value {
+    json!({
+        "id": format!("{actor}/follows/{}", uuid::Uuid::new_v4()),
+        "type": "Follow",
+        "actor": actor,
+        "object": ALICE
+    })
+}
+
+async fn queued_deliveries(db: &DatabaseRef) -> Vec<(String, Value)> {
+    db.get_due_deliveries(Utc::now(), DeliveryPriority::Interactive, 100)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|d| (d.inbox_url, d.activity))
+        .collect()
+}
+
+#[actix_web::test]
+async fn test_block_is_stored_delivered_and_ends_follows() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    follow(&db, BOB, ALICE).await;
+    follow(&db, ALICE, BOB).await;
+    let app = block_app!(db);
+
+    let resp = test::call_service(&app, post_outbox(block(BOB)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let created: Value = test::read_body_json(resp).await;
+    assert_eq!(created["type"], "Block");
+    assert_eq!(created["object"], BOB);
+    assert_eq!(created["to"], json!([BOB]));
+
+    assert!(db.is_blocked(ALICE, BOB).await.unwrap());
+    assert!(!db.is_blocked(BOB, ALICE).await.unwrap());
+    let blocks = db.list_blocks(ALICE, 10, 0).await.unwrap();
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(blocks[0].id, created["id"].as_str().unwrap());
+
+    assert!(db.get_follow_by_actors(BOB, ALICE).await.unwrap().is_none());
+    assert!(db.get_follow_by_actors(ALICE, BOB).await.unwrap().is_none());
+
+    let deliveries = queued_deliveries(&db).await;
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].0, BOB_INBOX);
+    assert_eq!(deliveries[0].1["type"], "Block");
+
+    // Blocking twice is a conflict, blocking yourself a mistake
+    let resp = test::call_service(&app, post_outbox(block(BOB)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let resp = test::call_service(&app, post_outbox(block(ALICE)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+#[actix_web::test]
+async fn test_inbox_refuses_blocked_actors() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = block_app!(db);
+
+    let resp = test::call_service(&app, post_outbox(block(BOB)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let resp = test::call_service(&app, post_inbox(follow_from(BOB)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    assert!(db.get_follow_by_actors(BOB, ALICE).await.unwrap().is_none());
+
+    // Other actors are unaffected
+    let resp = test::call_service(&app, post_inbox(follow_from(CAROL)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    assert!(db
+        .get_follow_by_actors(CAROL, ALICE)
+        .await
+        .unwrap()
+        .is_some());
+}
+
+#[actix_web::test]
+async fn test_undo_block_lets_the_actor_back_in() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = block_app!(db);
+
+    let resp = test::call_service(&app, post_outbox(block(BOB)).to_request()).await;
+    let blocked: Value = test::read_body_json(resp).await;
+
+    let undo = json!({"type": "Undo", "actor": ALICE, "object": blocked});
+    let resp = test::call_service(&app, post_outbox(undo.clone()).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let undone: Value = test::read_body_json(resp).await;
+    assert_eq!(undone["object"]["type"], "Block");
+    assert_eq!(undone["object"]["id"], blocked["id"]);
+    assert!(!db.is_blocked(ALICE, BOB).await.unwrap());
+
+    let deliveries = queued_deliveries(&db).await;
+    assert!(deliveries
+        .iter()
+        .any(|(inbox, activity)| inbox == BOB_INBOX && activity["type"] == "Undo"));
+
+    let resp = test::call_service(&app, post_inbox(follow_from(BOB)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+
+    // There is nothing left to undo
+    let resp = test::call_service(&app, post_outbox(undo).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_remote_block_removes_follows() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    follow(&db, BOB, ALICE).await;
+    follow(&db, ALICE, BOB).await;
+    let app = block_app!(db);
+
+    let remote_block = json!({
+        "id": format!("{BOB}/blocks/1"),
+        "type": "Block",
+        "actor": BOB,
+        "object": ALICE
+    });
+    let resp = test::call_service(&app, post_inbox(remote_block).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+
+    assert!(db.get_follow_by_actors(BOB, ALICE).await.unwrap().is_none());
+    assert!(db.get_follow_by_actors(ALICE, BOB).await.unwrap().is_none());
+    // Bob's block is his server's to enforce; Alice blocks no one
+    assert!(db.list_blocks(ALICE, 10, 0).await.unwrap().is_empty());
+}
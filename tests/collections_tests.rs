@@ -0,0 +1,584 @@
+mod common;
+
+use actix_web::{test, App};
+use chrono::Utc;
+use common::{OfflineHttpClient, ALICE_TOKEN};
+use feder8::config::Config;
+use feder8::database::{
+    DatabaseRef, DbActivity, DbActor, DbLike, DbNote, MockDatabase, PublishState,
+};
+use feder8::handlers;
+use feder8::models::addressing::PUBLIC;
+use feder8::models::Visibility;
+use mockall::predicate::*;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ACTOR_ID: &str = "https://example.com/users/alice";
+
+fn create_test_app(
+    db: &DatabaseRef,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    create_test_app_with_config(db, common::test_config())
+}
+
+fn create_test_app_with_config(
+    db: &DatabaseRef,
+    config: Config,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    common::test_app(db, config, Arc::new(OfflineHttpClient))
+        .service(handlers::outbox::post_outbox)
+        .service(handlers::collections::get_featured)
+        .service(handlers::collections::get_tag_collection)
+        .service(handlers::collections::get_replies)
+        .service(handlers::collections::get_status_replies)
+        .service(handlers::api::statuses::get_status)
+        .service(handlers::collections::get_likes)
+        .service(handlers::collections::get_shares)
+}
+
+fn alice() -> DbActor {
+    common::actor(ACTOR_ID, "alice", "Alice")
+}
+
+fn pinned_note(id: &str, visibility: Visibility) -> DbNote {
+    DbNote {
+        id: id.to_string(),
+        attributed_to: ACTOR_ID.to_string(),
+        content: format!("Pinned {id}"),
+        to_recipients: vec![PUBLIC.to_string()],
+        cc_recipients: vec![],
+        published: Utc::now(),
+        in_reply_to: None,
+        tags: vec![],
+        attachments: vec![],
+        visibility,
+        state: PublishState::Published,
+        pinned: true,
+        created_at: Utc::now(),
+        in_reply_to_actor: None,
+        thread_depth: Some(0),
+        thread_truncated: false,
+        sensitive: false,
+        summary: None,
+        language: None,
+        content_map: None,
+    }
+}
+
+async fn get_json(db: &DatabaseRef, uri: &str) -> (u16, Value) {
+    get_json_with_config(db, common::test_config(), uri).await
+}
+
+async fn get_json_with_config(db: &DatabaseRef, config: Config, uri: &str) -> (u16, Value) {
+    let app = test::init_service(create_test_app_with_config(db, config)).await;
+    let req = test::TestRequest::get()
+        .uri(uri)
+        .insert_header(("Accept", "application/activity+json"))
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    let status = resp.status().as_u16();
+    (status, test::read_body_json(resp).await)
+}
+
+#[tokio::test]
+async fn test_featured_collection_lists_pinned_notes() {
+    let mut mock = MockDatabase::new();
+    mock.expect_get_actor_by_username()
+        .with(eq("alice"))
+        .returning(|_| Ok(Some(alice())));
+    mock.expect_get_pinned_notes()
+        .with(eq(ACTOR_ID))
+        .returning(|_| {
+            Ok(vec![
+                pinned_note("https://example.com/notes/1", Visibility::Public),
+                pinned_note("https://example.com/notes/2", Visibility::Followers),
+            ])
+        });
+    let db: DatabaseRef = Arc::new(mock);
+
+    let (status, body) = get_json(&db, "/users/alice/collections/featured").await;
+
+    assert_eq!(status, 200);
+    assert_eq!(body["type"], "OrderedCollection");
+    assert_eq!(body["id"], format!("{ACTOR_ID}/collections/featured"));
+    // Followers-only pins stay hidden from the public collection
+    assert_eq!(body["totalItems"], 1);
+    assert_eq!(body["orderedItems"][0]["id"], "https://example.com/notes/1");
+    assert_eq!(body["orderedItems"][0]["type"], "Note");
+}
+
+#[tokio::test]
+async fn test_featured_collection_unknown_actor() {
+    let mut mock = MockDatabase::new();
+    mock.expect_get_actor_by_username().returning(|_| Ok(None));
+    let db: DatabaseRef = Arc::new(mock);
+
+    let (status, body) = get_json(&db, "/users/nobody/collections/featured").await;
+
+    assert_eq!(status, 404);
+    assert_eq!(body["error"], "Actor not found");
+}
+
+#[tokio::test]
+async fn test_tag_collection_lists_tagged_notes() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+
+    let app = test::init_service(create_test_app(&db)).await;
+    for (content, tags) in [
+        ("about rust", json!([{"type": "Hashtag", "name": "#Rust"}])),
+        ("about cats", json!([{"type": "Hashtag", "name": "#cats"}])),
+        (
+            "about both",
+            json!([
+                {"type": "Hashtag", "name": "#cats"},
+                {"type": "Hashtag", "name": "#rust"}
+            ]),
+        ),
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/users/alice/outbox")
+            .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+            .set_json(json!({
+                "type": "Create",
+                "actor": ACTOR_ID,
+                "object": {"type": "Note", "content": content, "tag": tags, "to": [PUBLIC]},
+                "to": [PUBLIC]
+            }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 201);
+    }
+
+    let (status, body) = get_json(&db, "/users/alice/collections/tags/RUST").await;
+
+    assert_eq!(status, 200);
+    assert_eq!(body["id"], format!("{ACTOR_ID}/collections/tags/rust"));
+    assert_eq!(body["totalItems"], 2);
+    let mut contents: Vec<_> = body["orderedItems"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|note| note["content"].as_str().unwrap())
+        .collect();
+    contents.sort();
+    assert_eq!(contents, vec!["about both", "about rust"]);
+
+    let (_, body) = get_json(&db, "/users/alice/collections/tags/unused").await;
+    assert_eq!(body["totalItems"], 0);
+}
+
+#[tokio::test]
+async fn test_tag_collection_can_hide_sensitive_notes() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+
+    for (content, summary) in [("plain", None), ("warned", Some("CW: rust"))] {
+        let (status, _) = post_outbox(
+            &db,
+            json!({
+                "type": "Create",
+                "actor": ACTOR_ID,
+                "object": {
+                    "type": "Note",
+                    "content": content,
+                    "summary": summary,
+                    "tag": [{"type": "Hashtag", "name": "#rust"}],
+                    "to": [PUBLIC]
+                },
+                "to": [PUBLIC]
+            }),
+        )
+        .await;
+        assert_eq!(status, 201);
+    }
+
+    let (_, body) = get_json(&db, "/users/alice/collections/tags/rust").await;
+    assert_eq!(body["totalItems"], 2);
+
+    let (_, body) = get_json(
+        &db,
+        "/users/alice/collections/tags/rust?hide_sensitive=true",
+    )
+    .await;
+    assert_eq!(body["totalItems"], 1);
+    assert_eq!(body["orderedItems"][0]["content"], "plain");
+}
+
+async fn post_outbox(db: &DatabaseRef, activity: Value) -> (u16, Value) {
+    let app = test::init_service(create_test_app(db)).await;
+    let req = test::TestRequest::post()
+        .uri("/users/alice/outbox")
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+        .set_json(activity)
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    let status = resp.status().as_u16();
+    (status, test::read_body_json(resp).await)
+}
+
+#[tokio::test]
+async fn test_pin_and_unpin_via_outbox() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let featured = format!("{ACTOR_ID}/collections/featured");
+
+    let (status, created) = post_outbox(
+        &db,
+        json!({
+            "type": "Create",
+            "actor": ACTOR_ID,
+            "object": {"type": "Note", "content": "pin me", "to": [PUBLIC]},
+            "to": [PUBLIC]
+        }),
+    )
+    .await;
+    assert_eq!(status, 201);
+    let note_id = created["object"]["id"].as_str().unwrap().to_string();
+
+    let (status, added) = post_outbox(
+        &db,
+        json!({"type": "Add", "actor": ACTOR_ID, "object": note_id, "target": featured}),
+    )
+    .await;
+    assert_eq!(status, 201);
+    assert_eq!(added["type"], "Add");
+    assert_eq!(added["target"], featured);
+
+    let (_, body) = get_json(&db, "/users/alice/collections/featured").await;
+    assert_eq!(body["totalItems"], 1);
+    assert_eq!(body["orderedItems"][0]["id"], note_id);
+
+    let (status, _) = post_outbox(
+        &db,
+        json!({
+            "type": "Remove",
+            "actor": ACTOR_ID,
+            "object": {"id": note_id, "type": "Note"},
+            "target": {"id": featured, "type": "OrderedCollection"}
+        }),
+    )
+    .await;
+    assert_eq!(status, 201);
+
+    let (_, body) = get_json(&db, "/users/alice/collections/featured").await;
+    assert_eq!(body["totalItems"], 0);
+}
+
+#[tokio::test]
+async fn test_pin_rejects_other_targets_and_unknown_notes() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+
+    let (status, _) = post_outbox(
+        &db,
+        json!({
+            "type": "Add",
+            "actor": ACTOR_ID,
+            "object": "https://example.com/notes/1",
+            "target": "https://example.com/users/bob/collections/featured"
+        }),
+    )
+    .await;
+    assert_eq!(status, 422);
+
+    let (status, body) = post_outbox(
+        &db,
+        json!({
+            "type": "Add",
+            "actor": ACTOR_ID,
+            "object": "https://example.com/notes/missing",
+            "target": format!("{ACTOR_ID}/collections/featured")
+        }),
+    )
+    .await;
+    assert_eq!(status, 404);
+    assert_eq!(body["error"], "Note not found");
+}
+
+/// Post a note as Alice and return its id
+async fn post_note(db: &DatabaseRef, content: &str, in_reply_to: Option<&str>, to: &str) -> String {
+    let mut note = json!({"type": "Note", "content": content, "to": [to]});
+    if let Some(parent) = in_reply_to {
+        note["inReplyTo"] = json!(parent);
+    }
+    let (status, body) = post_outbox(
+        db,
+        json!({"type": "Create", "actor": ACTOR_ID, "object": note, "to": [to]}),
+    )
+    .await;
+    assert_eq!(status, 201);
+    body["object"]["id"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_replies_collection() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let followers = format!("{ACTOR_ID}/followers");
+
+    let parent = post_note(&db, "parent", None, PUBLIC).await;
+    let first = post_note(&db, "first reply", Some(&parent), PUBLIC).await;
+    let second = post_note(&db, "second reply", Some(&parent), PUBLIC).await;
+    // Neither a followers-only reply nor a reply to a reply belongs here
+    post_note(&db, "hidden reply", Some(&parent), &followers).await;
+    post_note(&db, "nested reply", Some(&first), PUBLIC).await;
+
+    // Notes link their replies collection, which is dereferenceable
+    let (_, outbox) = post_outbox(
+        &db,
+        json!({"type": "Create", "actor": ACTOR_ID, "object": {"type": "Note", "content": "x", "to": [PUBLIC]}, "to": [PUBLIC]}),
+    )
+    .await;
+    let replies = outbox["object"]["replies"].as_str().unwrap();
+    assert_eq!(
+        replies,
+        format!("{}/replies", outbox["object"]["id"].as_str().unwrap())
+    );
+
+    let path = format!(
+        "{}/replies",
+        parent.strip_prefix("https://example.com").unwrap()
+    );
+    let (status, body) = get_json(&db, &path).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["type"], "OrderedCollection");
+    assert_eq!(body["id"], format!("{parent}/replies"));
+    assert_eq!(body["totalItems"], 2);
+
+    let page = &body["first"];
+    assert_eq!(page["type"], "OrderedCollectionPage");
+    assert_eq!(page["partOf"], format!("{parent}/replies"));
+    let ids: Vec<&str> = page["orderedItems"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|note| note["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ids, vec![first.as_str(), second.as_str()]);
+    assert_eq!(page["orderedItems"][0]["inReplyTo"], parent);
+    assert_eq!(
+        page["orderedItems"][0]["replies"],
+        format!("{first}/replies")
+    );
+
+    // The page the collection points at can be fetched on its own
+    let page_path = page["id"]
+        .as_str()
+        .unwrap()
+        .strip_prefix("https://example.com")
+        .unwrap()
+        .to_string();
+    let (status, page) = get_json(&db, &page_path).await;
+    assert_eq!(status, 200);
+    assert_eq!(page["type"], "OrderedCollectionPage");
+    assert!(page["@context"].is_array());
+    assert_eq!(page["orderedItems"].as_array().unwrap().len(), 2);
+    assert!(page.get("next").is_none());
+
+    let path = format!(
+        "{}/replies",
+        first.strip_prefix("https://example.com").unwrap()
+    );
+    let (_, body) = get_json(&db, &path).await;
+    assert_eq!(body["totalItems"], 1);
+
+    let (status, _) = get_json(&db, "/notes/missing/replies").await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn test_replies_under_the_authors_statuses() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    db.create_actor(&DbActor {
+        id: "https://example.com/users/bob".to_string(),
+        username: "bob".to_string(),
+        ..alice()
+    })
+    .await
+    .unwrap();
+
+    let parent = post_note(&db, "parent", None, PUBLIC).await;
+    let first = post_note(&db, "first reply", Some(&parent), PUBLIC).await;
+    let second = post_note(&db, "second reply", Some(&parent), PUBLIC).await;
+    let id = parent.rsplit('/').next().unwrap();
+
+    let (status, body) = get_json(&db, &format!("/users/alice/statuses/{id}/replies")).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["type"], "OrderedCollection");
+    assert_eq!(body["id"], format!("{parent}/replies"));
+    assert_eq!(body["totalItems"], 2);
+    let ids: Vec<&str> = body["first"]["orderedItems"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|note| note["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(ids, vec![first.as_str(), second.as_str()]);
+
+    // The status counts them too
+    let (status, body) = get_json(&db, &format!("/api/v1/statuses/{id}")).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["replies_count"], 2);
+    let first_id = first.rsplit('/').next().unwrap();
+    let (_, body) = get_json(&db, &format!("/api/v1/statuses/{first_id}")).await;
+    assert_eq!(body["replies_count"], 0);
+
+    // Only under the note's own author
+    for uri in [
+        format!("/users/bob/statuses/{id}/replies"),
+        format!("/users/nobody/statuses/{id}/replies"),
+        "/users/alice/statuses/missing/replies".to_string(),
+    ] {
+        let (status, _) = get_json(&db, &uri).await;
+        assert_eq!(status, 404, "{uri}");
+    }
+}
+
+/// Alice's note liked by two remote actors and boosted by Alice herself,
+/// plus a followers-only boost that must not count
+async fn liked_and_shared_note(db: &DatabaseRef) -> String {
+    let note = post_note(db, "popular", None, PUBLIC).await;
+    for (n, liker) in [
+        "https://remote.example/users/bob",
+        "https://remote.example/users/carol",
+    ]
+    .iter()
+    .enumerate()
+    {
+        db.create_like(&DbLike {
+            id: format!("https://remote.example/likes/{n}"),
+            actor_id: liker.to_string(),
+            object_id: note.clone(),
+            created_at: Utc::now(),
+        })
+        .await
+        .unwrap();
+    }
+    for (n, visibility) in [Visibility::Public, Visibility::Followers]
+        .into_iter()
+        .enumerate()
+    {
+        db.create_activity(&DbActivity {
+            id: format!("https://example.com/activities/announce-{n}"),
+            actor_id: ACTOR_ID.to_string(),
+            activity_type: "Announce".to_string(),
+            object: json!(note),
+            target: None,
+            to_recipients: vec![PUBLIC.to_string()],
+            cc_recipients: vec![],
+            published: Utc::now(),
+            visibility,
+            state: PublishState::Published,
+            created_at: Utc::now(),
+        })
+        .await
+        .unwrap();
+    }
+    note
+}
+
+#[tokio::test]
+async fn test_likes_and_shares_collections_count_only() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let note = liked_and_shared_note(&db).await;
+    let path = note.strip_prefix("https://example.com").unwrap();
+
+    // Notes advertise both collections
+    let (_, outbox) = post_outbox(
+        &db,
+        json!({"type": "Create", "actor": ACTOR_ID, "object": {"type": "Note", "content": "x", "to": [PUBLIC]}, "to": [PUBLIC]}),
+    )
+    .await;
+    let id = outbox["object"]["id"].as_str().unwrap();
+    assert_eq!(outbox["object"]["likes"], format!("{id}/likes"));
+    assert_eq!(outbox["object"]["shares"], format!("{id}/shares"));
+
+    let config = Config {
+        interaction_collections_count_only: true,
+        ..common::test_config()
+    };
+    for (collection, total) in [("likes", 2), ("shares", 1)] {
+        let (status, body) =
+            get_json_with_config(&db, config.clone(), &format!("{path}/{collection}")).await;
+        assert_eq!(status, 200, "{collection}");
+        assert_eq!(body["type"], "OrderedCollection");
+        assert_eq!(body["id"], format!("{note}/{collection}"));
+        assert_eq!(body["totalItems"], total, "{collection}");
+        // Who liked or shared stays private
+        assert!(body.get("first").is_none(), "{collection}");
+        assert!(body.get("orderedItems").is_none(), "{collection}");
+    }
+}
+
+#[tokio::test]
+async fn test_likes_and_shares_collections_with_items() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let note = liked_and_shared_note(&db).await;
+    let path = note.strip_prefix("https://example.com").unwrap();
+    let config = Config {
+        interaction_collections_count_only: false,
+        ..common::test_config()
+    };
+
+    let (status, body) = get_json_with_config(&db, config.clone(), &format!("{path}/likes")).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["totalItems"], 2);
+    assert_eq!(body["first"]["partOf"], format!("{note}/likes"));
+    assert_eq!(
+        body["first"]["orderedItems"],
+        json!([
+            "https://remote.example/likes/0",
+            "https://remote.example/likes/1"
+        ])
+    );
+
+    let (status, body) = get_json_with_config(&db, config, &format!("{path}/shares")).await;
+    assert_eq!(status, 200);
+    assert_eq!(body["totalItems"], 1);
+    assert_eq!(
+        body["first"]["orderedItems"],
+        json!(["https://example.com/activities/announce-0"])
+    );
+}
+
+#[tokio::test]
+async fn test_likes_and_shares_of_hidden_or_missing_notes_are_not_found() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let hidden = post_note(&db, "secret", None, &format!("{ACTOR_ID}/followers")).await;
+    let hidden = hidden.strip_prefix("https://example.com").unwrap();
+
+    for path in [
+        "/notes/missing/likes".to_string(),
+        "/notes/missing/shares".to_string(),
+        format!("{hidden}/likes"),
+        format!("{hidden}/shares"),
+    ] {
+        let (status, body) = get_json(&db, &path).await;
+        assert_eq!(status, 404, "{path}");
+        assert_eq!(body["error"], "Note not found");
+    }
+}
@@ -0,0 +1,279 @@
+//! Fixtures shared by the integration tests. Each test binary uses only
+//! some of them.
+#![allow(dead_code)]
+
+use actix_web::{web, App};
+use chrono::Utc;
+use feder8::auth::hash_token;
+use feder8::config::Config;
+use feder8::database::{DatabaseRef, DbActor, DbToken, SqliteDatabase};
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse};
+use feder8::services::backpressure::InboxBackpressure;
+use feder8::services::delivery::DeliveryService;
+use feder8::services::document_cache::DocumentCache;
+use feder8::services::log_dedup::LogDedup;
+use feder8::services::seen_activities::SeenActivities;
+use feder8::urls::UrlBuilder;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+pub const ALICE: &str = "https://example.com/users/alice";
+/// Bearer token of Alice in a `seeded_database`
+pub const ALICE_TOKEN: &str = "alice-token";
+
+/// URL of a SQLite database file in `dir`
+pub fn sqlite_url(dir: &TempDir) -> String {
+    format!("sqlite://{}?mode=rwc", dir.path().join("test.db").display())
+}
+
+/// A migrated, empty SQLite database in `dir`
+pub async fn sqlite_database(dir: &TempDir) -> SqliteDatabase {
+    let db = SqliteDatabase::new(&sqlite_url(dir)).await.unwrap();
+    db.run_migrations().await.unwrap();
+    db
+}
+
+/// A local Person with a placeholder key and no private key, created now
+pub fn actor(id: &str, username: &str, name: &str) -> DbActor {
+    DbActor {
+        id: id.to_string(),
+        username: username.to_string(),
+        name: name.to_string(),
+        summary: None,
+        public_key_pem: "test_key".to_string(),
+        private_key_pem: None,
+        is_admin: false,
+        actor_type: "Person".to_string(),
+        moved_to: None,
+        also_known_as: Vec::new(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    }
+}
+
+/// Alice's read and write token, stored as `ALICE_TOKEN` hashes
+pub fn alice_token() -> DbToken {
+    DbToken {
+        id: "token-1".to_string(),
+        token_hash: hash_token(ALICE_TOKEN),
+        actor_id: ALICE.to_string(),
+        scopes: vec!["read".to_string(), "write".to_string()],
+        created_at: Utc::now(),
+        expires_at: None,
+    }
+}
+
+/// Store Alice and her token in `db`
+pub async fn seed_alice(db: &DatabaseRef) {
+    db.create_actor(&actor(ALICE, "alice", "Alice"))
+        .await
+        .unwrap();
+    db.create_token(&alice_token()).await.unwrap();
+}
+
+/// A migrated SQLite database in `dir` holding Alice and her token
+pub async fn seeded_database(dir: &TempDir) -> DatabaseRef {
+    let db: DatabaseRef = Arc::new(sqlite_database(dir).await);
+    seed_alice(&db).await;
+    db
+}
+
+/// An app serving nothing yet, with everything the inbox and outbox
+/// handlers need built from `config` and reaching other servers through
+/// `http_client`. Tests add the handlers they call, and any `app_data` they
+/// add replaces what is here.
+pub fn test_app(
+    db: &DatabaseRef,
+    config: Config,
+    http_client: Arc<dyn HttpClient>,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    App::new()
+        .app_data(web::Data::new(UrlBuilder::from_config(&config)))
+        .app_data(web::Data::new(DeliveryService::new(
+            config.clone(),
+            http_client.clone(),
+            db.clone(),
+        )))
+        .app_data(web::Data::new(DocumentCache::from_config(&config)))
+        .app_data(web::Data::new(SeenActivities::new(1000)))
+        .app_data(web::Data::new(InboxBackpressure::from_config(
+            &config,
+            Arc::default(),
+        )))
+        .app_data(web::Data::new(LogDedup::from_config(&config)))
+        .app_data(web::Data::new(config))
+        .app_data(web::Data::new(db.clone()))
+        .app_data(web::Data::from(http_client))
+}
+
+/// `Config` for a server at https://example.com
+pub fn test_config() -> Config {
+    Config {
+        server_url: "https://example.com".to_string(),
+        ..Config::default()
+    }
+}
+
+/// Refuses every request, for tests that must not reach the network
+pub struct OfflineHttpClient;
+
+#[async_trait::async_trait]
+impl HttpClient for OfflineHttpClient {
+    async fn send(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        anyhow::bail!("unexpected request to {}", request.url)
+    }
+}
+
+/// Storage a backend-matrix scenario runs against
+pub enum Backend {
+    InMemory,
+    TempFile,
+    /// A schema of its own on the server at `PG_TEST_URL`
+    #[cfg(feature = "postgres")]
+    Postgres,
+}
+
+/// An open, migrated and empty backend, keeping any files alive as long as
+/// it is
+pub struct TestBackend {
+    pub db: DatabaseRef,
+    _dir: Option<TempDir>,
+    /// The Postgres schema to drop once the scenario is done
+    #[cfg(feature = "postgres")]
+    schema: Option<String>,
+}
+
+async fn open_sqlite(url: &str) -> DatabaseRef {
+    let db = SqliteDatabase::new(url).await.unwrap();
+    db.run_migrations().await.unwrap();
+    Arc::new(db)
+}
+
+/// Postgres at `PG_TEST_URL`, e.g. a throwaway container:
+/// `docker run -e POSTGRES_HOST_AUTH_METHOD=trust -p 5432:5432 postgres`
+/// and `PG_TEST_URL=postgres://postgres@localhost:5432/postgres`
+#[cfg(feature = "postgres")]
+pub fn pg_test_url() -> Option<String> {
+    std::env::var("PG_TEST_URL")
+        .ok()
+        .filter(|url| !url.is_empty())
+}
+
+/// A fresh schema on the test server, migrated, so scenarios don't see
+/// each other's rows
+#[cfg(feature = "postgres")]
+async fn open_postgres(base_url: &str) -> (DatabaseRef, String) {
+    use feder8::postgres_database::PostgresDatabase;
+
+    let schema = format!("feder8_test_{}", uuid::Uuid::new_v4().simple());
+    let admin = sqlx::PgPool::connect(base_url).await.unwrap();
+    sqlx::query(&format!("CREATE SCHEMA {schema}"))
+        .execute(&admin)
+        .await
+        .unwrap();
+    admin.close().await;
+
+    let separator = if base_url.contains('?') { '&' } else { '?' };
+    let url = format!("{base_url}{separator}options[search_path]={schema}");
+    let db = PostgresDatabase::new(&url).await.unwrap();
+    db.run_migrations().await.unwrap();
+    (Arc::new(db), schema)
+}
+
+#[cfg(feature = "postgres")]
+impl TestBackend {
+    /// Drop the scenario's Postgres schema
+    pub async fn close(self) {
+        if let (Some(schema), Some(url)) = (self.schema, pg_test_url()) {
+            let admin = sqlx::PgPool::connect(&url).await.unwrap();
+            sqlx::query(&format!("DROP SCHEMA {schema} CASCADE"))
+                .execute(&admin)
+                .await
+                .unwrap();
+            admin.close().await;
+        }
+    }
+}
+
+impl Backend {
+    pub async fn open(self) -> TestBackend {
+        #[cfg(feature = "postgres")]
+        let mut schema = None;
+        let (db, dir) = match self {
+            Backend::InMemory => (open_sqlite("sqlite::memory:").await, None),
+            Backend::TempFile => {
+                let dir = TempDir::new().unwrap();
+                (open_sqlite(&sqlite_url(&dir)).await, Some(dir))
+            }
+            #[cfg(feature = "postgres")]
+            Backend::Postgres => {
+                let url = pg_test_url().expect("PG_TEST_URL is not set");
+                let (db, name) = open_postgres(&url).await;
+                schema = Some(name);
+                (db, None)
+            }
+        };
+        TestBackend {
+            db,
+            _dir: dir,
+            #[cfg(feature = "postgres")]
+            schema,
+        }
+    }
+}
+
+/// Runs each scenario against every backend, in a module per backend. The
+/// calling suite provides `seed`, run on each fresh backend first.
+#[allow(unused_macros)]
+macro_rules! backend_matrix {
+    ($($scenario:ident),* $(,)?) => {
+        mod in_memory {
+            $(
+                #[actix_web::test]
+                async fn $scenario() {
+                    let backend = crate::common::Backend::InMemory.open().await;
+                    super::seed(&backend.db).await;
+                    super::$scenario(&backend.db).await;
+                }
+            )*
+        }
+
+        mod temp_file {
+            $(
+                #[actix_web::test]
+                async fn $scenario() {
+                    let backend = crate::common::Backend::TempFile.open().await;
+                    super::seed(&backend.db).await;
+                    super::$scenario(&backend.db).await;
+                }
+            )*
+        }
+
+        #[cfg(feature = "postgres")]
+        mod postgres {
+            $(
+                #[actix_web::test]
+                async fn $scenario() {
+                    if crate::common::pg_test_url().is_none() {
+                        eprintln!("PG_TEST_URL is not set; skipping");
+                        return;
+                    }
+                    let backend = crate::common::Backend::Postgres.open().await;
+                    super::seed(&backend.db).await;
+                    super::$scenario(&backend.db).await;
+                    backend.close().await;
+                }
+            )*
+        }
+    };
+}
+#[allow(unused_imports)]
+pub(crate) use backend_matrix;
@@ -0,0 +1,473 @@
+//! The ActivityPub server conformance checklist as executable assertions
+//! against an in-process node. Each test is named for the spec item it
+//! checks. Items we do not implement yet are `#[ignore]`d with their spec
+//! reference so `cargo test -- --ignored` lists what is left to do.
+
+mod common;
+
+use actix_web::http::header;
+use actix_web::{test, App};
+use chrono::Utc;
+use common::ALICE_TOKEN;
+use feder8::database::{DatabaseRef, DbDelivery, DeliveryPriority};
+use feder8::handlers;
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode};
+use feder8::models::addressing::PUBLIC;
+use feder8::services::addressing;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ALICE: &str = "https://example.com/users/alice";
+const BOB: &str = "https://remote.example/users/bob";
+const CAROL: &str = "https://remote.example/users/carol";
+const BOB_INBOX: &str = "https://remote.example/users/bob/inbox";
+const SHARED_INBOX: &str = "https://remote.example/inbox";
+const BOB_NOTE: &str = "https://remote.example/notes/1";
+
+/// The remote server: Bob and Carol share an inbox, and Bob has written a note
+struct RemoteServer;
+
+#[async_trait::async_trait]
+impl HttpClient for RemoteServer {
+    async fn send(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        let document = match request.url.as_str() {
+            BOB | CAROL => json!({
+                "id": request.url,
+                "type": "Person",
+                "preferredUsername": request.url.rsplit('/').next(),
+                "inbox": format!("{}/inbox", request.url),
+                "endpoints": {"sharedInbox": SHARED_INBOX}
+            }),
+            BOB_NOTE => json!({"id": BOB_NOTE, "type": "Note", "attributedTo": BOB}),
+            url => anyhow::bail!("unexpected request to {}", url),
+        };
+        Ok(HttpResponse {
+            status: StatusCode(200),
+            headers: HashMap::new(),
+            body: serde_json::to_vec(&document)?,
+        })
+    }
+}
+
+fn create_test_app(
+    db: &DatabaseRef,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    common::test_app(db, common::test_config(), Arc::new(RemoteServer))
+        .service(handlers::actor::get_actor)
+        .service(handlers::inbox::inbox)
+        .service(handlers::outbox::get_outbox)
+        .service(handlers::outbox::post_outbox)
+        .service(handlers::collections::get_featured)
+        .service(handlers::collections::get_replies)
+}
+
+fn post_outbox(activity: Value) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/users/alice/outbox")
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+        .set_json(activity)
+}
+
+fn post_inbox(activity: Value) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/users/alice/inbox")
+        .set_json(activity)
+}
+
+fn public_note(content: &str) -> Value {
+    json!({"type": "Note", "content": content, "to": [PUBLIC]})
+}
+
+/// Deliveries waiting, whatever their priority
+async fn due_deliveries(db: &DatabaseRef) -> Vec<DbDelivery> {
+    let mut due = Vec::new();
+    for priority in DeliveryPriority::ALL {
+        due.extend(
+            db.get_due_deliveries(Utc::now(), priority, 100)
+                .await
+                .unwrap(),
+        );
+    }
+    due
+}
+
+/// Inboxes with a delivery waiting, and the activity types sent to each
+async fn queued_deliveries(db: &DatabaseRef) -> Vec<(String, String)> {
+    due_deliveries(db)
+        .await
+        .into_iter()
+        .map(|delivery| {
+            let activity_type = delivery.activity["type"].as_str().unwrap().to_string();
+            (delivery.inbox_url, activity_type)
+        })
+        .collect()
+}
+
+// Section 6: client to server interactions
+
+/// §6.2.1: a bare object posted to the outbox MUST be wrapped in a Create
+#[tokio::test]
+#[ignore = "ActivityPub §6.2.1: the outbox requires an activity and rejects bare objects"]
+async fn outbox_must_wrap_bare_objects_in_create() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    let resp = test::call_service(&app, post_outbox(public_note("bare")).to_request()).await;
+    assert_eq!(resp.status(), 201);
+    let activity: Value = test::read_body_json(resp).await;
+    assert_eq!(activity["type"], "Create");
+    assert_eq!(activity["object"]["type"], "Note");
+    assert_eq!(activity["object"]["content"], "bare");
+}
+
+/// §6: the server MUST respond 201 Created with the new activity's id in Location
+#[tokio::test]
+async fn outbox_must_respond_created_with_location() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    let activity =
+        json!({"type": "Create", "actor": ALICE, "object": public_note("hi"), "to": [PUBLIC]});
+    let resp = test::call_service(&app, post_outbox(activity).to_request()).await;
+    assert_eq!(resp.status(), 201);
+    let location = resp
+        .headers()
+        .get(header::LOCATION)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string();
+    let created: Value = test::read_body_json(resp).await;
+    assert_eq!(created["id"], location.as_str());
+}
+
+/// §6: the server MUST assign new ids to the activity and its object,
+/// ignoring any the client supplied
+#[tokio::test]
+async fn outbox_must_assign_ids() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    let mut note = public_note("hi");
+    note["id"] = json!("https://elsewhere.example/notes/forged");
+    let activity = json!({
+        "id": "https://elsewhere.example/activities/forged",
+        "type": "Create",
+        "actor": ALICE,
+        "object": note,
+        "to": [PUBLIC]
+    });
+    let resp = test::call_service(&app, post_outbox(activity).to_request()).await;
+    let created: Value = test::read_body_json(resp).await;
+
+    let activity_id = created["id"].as_str().unwrap();
+    let note_id = created["object"]["id"].as_str().unwrap();
+    assert!(
+        activity_id.starts_with("https://example.com/"),
+        "{activity_id}"
+    );
+    assert!(note_id.starts_with("https://example.com/"), "{note_id}");
+    assert!(db.get_note_by_id(note_id).await.unwrap().is_some());
+}
+
+/// §6: posts to the outbox MUST be authenticated as the outbox's owner
+#[tokio::test]
+async fn outbox_must_require_authentication() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/users/alice/outbox")
+        .set_json(json!({"type": "Create", "actor": ALICE, "object": public_note("hi")}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+}
+
+/// §6.4: deleting an object SHOULD replace it with a Tombstone
+#[tokio::test]
+#[ignore = "ActivityPub §6.4: the outbox does not support Delete yet"]
+async fn outbox_delete_should_leave_tombstone() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    let activity =
+        json!({"type": "Create", "actor": ALICE, "object": public_note("hi"), "to": [PUBLIC]});
+    let resp = test::call_service(&app, post_outbox(activity).to_request()).await;
+    let created: Value = test::read_body_json(resp).await;
+    let note_id = created["object"]["id"].as_str().unwrap().to_string();
+
+    let delete = json!({"type": "Delete", "actor": ALICE, "object": note_id, "to": [PUBLIC]});
+    let resp = test::call_service(&app, post_outbox(delete).to_request()).await;
+    assert_eq!(resp.status(), 201);
+
+    let outbox: Value = test::call_and_read_body_json(
+        &app,
+        test::TestRequest::get()
+            .uri("/users/alice/outbox?page=true")
+            .to_request(),
+    )
+    .await;
+    let create = outbox["orderedItems"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|activity| activity["type"] == "Create")
+        .unwrap();
+    assert_eq!(create["object"]["type"], "Tombstone");
+    assert_eq!(create["object"]["formerType"], "Note");
+}
+
+// Section 7: server to server interactions
+
+/// §7.1: delivery MUST NOT target the Public collection
+#[tokio::test]
+async fn delivery_must_skip_public_collection() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let activity = json!({"actor": ALICE, "to": [PUBLIC, "as:Public", "Public"], "cc": [BOB]});
+
+    let targets = addressing::resolve_recipients(&activity, &db, &RemoteServer).await;
+    let actors: Vec<&str> = targets.iter().map(|t| t.actor_id.as_str()).collect();
+    assert_eq!(actors, vec![BOB]);
+}
+
+/// §7.1: the server MUST NOT deliver an activity to its own actor
+#[tokio::test]
+async fn delivery_must_skip_the_author() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let activity = json!({"actor": BOB, "to": [BOB, CAROL]});
+
+    let targets = addressing::resolve_recipients(&activity, &db, &RemoteServer).await;
+    let actors: Vec<&str> = targets.iter().map(|t| t.actor_id.as_str()).collect();
+    assert_eq!(actors, vec![CAROL]);
+}
+
+/// §7.1: recipients MUST be de-duplicated, and §7.1.3 lets public
+/// activities collapse onto a shared inbox
+#[tokio::test]
+async fn delivery_must_deduplicate_recipients_and_shared_inboxes() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let activity = json!({"actor": ALICE, "to": [PUBLIC, BOB], "cc": [BOB, CAROL], "bcc": [CAROL]});
+
+    let targets = addressing::resolve_recipients(&activity, &db, &RemoteServer).await;
+    let urls: Vec<&str> = targets.iter().map(|t| t.delivery_url()).collect();
+    assert_eq!(urls, vec![SHARED_INBOX]);
+}
+
+/// §7.1: bto and bcc MUST be removed before an activity is delivered
+#[tokio::test]
+#[ignore = "ActivityPub §7.1: bto and bcc are not stripped from delivered activities"]
+async fn delivery_must_strip_bto_and_bcc() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    let activity = json!({
+        "type": "Create",
+        "actor": ALICE,
+        "object": public_note("hi"),
+        "to": [PUBLIC],
+        "cc": [BOB],
+        "bcc": [CAROL]
+    });
+    test::call_service(&app, post_outbox(activity).to_request()).await;
+
+    let deliveries = due_deliveries(&db).await;
+    assert!(!deliveries.is_empty());
+    for delivery in deliveries {
+        assert!(delivery.activity.get("bcc").is_none());
+        assert!(delivery.activity.get("bto").is_none());
+    }
+}
+
+/// §7.1.1: a reply is delivered to the author of the object it replies to
+#[tokio::test]
+async fn delivery_of_reply_must_reach_parent_author() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    let mut note = public_note("reply");
+    note["inReplyTo"] = json!(BOB_NOTE);
+    let activity = json!({"type": "Create", "actor": ALICE, "object": note, "to": [PUBLIC]});
+    let resp = test::call_service(&app, post_outbox(activity).to_request()).await;
+    let created: Value = test::read_body_json(resp).await;
+
+    assert!(created["cc"].as_array().unwrap().contains(&json!(BOB)));
+    assert_eq!(
+        queued_deliveries(&db).await,
+        vec![(BOB_INBOX.to_string(), "Create".to_string())]
+    );
+}
+
+/// §7: the server MUST de-duplicate activities delivered more than once
+#[tokio::test]
+async fn inbox_must_deduplicate_redelivered_activities() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    let create = |content: &str| {
+        json!({
+            "id": "https://remote.example/activities/1",
+            "type": "Create",
+            "actor": BOB,
+            "to": [PUBLIC],
+            "object": {
+                "id": "https://remote.example/notes/2",
+                "type": "Note",
+                "attributedTo": BOB,
+                "content": content,
+                "to": [PUBLIC]
+            }
+        })
+    };
+    for content in ["first", "second"] {
+        let resp = test::call_service(&app, post_inbox(create(content)).to_request()).await;
+        assert_eq!(resp.status(), 202);
+    }
+
+    let note = db
+        .get_note_by_id("https://remote.example/notes/2")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(note.content, "first");
+}
+
+/// §7.5: a Follow SHOULD be answered with an Accept or Reject
+#[tokio::test]
+#[ignore = "ActivityPub §7.5: Follows wait for manual approval and no Accept is sent"]
+async fn inbox_follow_should_be_answered() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    let follow = json!({
+        "id": "https://remote.example/follows/1",
+        "type": "Follow",
+        "actor": BOB,
+        "object": ALICE
+    });
+    let resp = test::call_service(&app, post_inbox(follow).to_request()).await;
+    assert_eq!(resp.status(), 202);
+
+    let answers: Vec<String> = queued_deliveries(&db)
+        .await
+        .into_iter()
+        .filter(|(inbox, _)| inbox == BOB_INBOX || inbox == SHARED_INBOX)
+        .map(|(_, activity_type)| activity_type)
+        .collect();
+    assert_eq!(answers, vec!["Accept"]);
+}
+
+/// §7.5: a Follow is recorded against the followed actor
+#[tokio::test]
+async fn inbox_follow_must_be_recorded() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    let follow = json!({
+        "id": "https://remote.example/follows/1",
+        "type": "Follow",
+        "actor": BOB,
+        "object": ALICE
+    });
+    let resp = test::call_service(&app, post_inbox(follow).to_request()).await;
+    assert_eq!(resp.status(), 202);
+
+    let follow = db.get_follow_by_actors(BOB, ALICE).await.unwrap().unwrap();
+    assert_eq!(follow.status, "pending");
+}
+
+/// §7.7: an Accept of one of our Follows MUST add the actor to following
+#[tokio::test]
+async fn inbox_accept_must_complete_follow() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    let follow = json!({"type": "Follow", "actor": ALICE, "object": BOB, "to": [BOB]});
+    let resp = test::call_service(&app, post_outbox(follow).to_request()).await;
+    assert_eq!(resp.status(), 201);
+    let follow: Value = test::read_body_json(resp).await;
+    assert_eq!(
+        queued_deliveries(&db).await,
+        vec![(BOB_INBOX.to_string(), "Follow".to_string())]
+    );
+
+    let accept = json!({
+        "id": "https://remote.example/accepts/1",
+        "type": "Accept",
+        "actor": BOB,
+        "object": follow
+    });
+    let resp = test::call_service(&app, post_inbox(accept).to_request()).await;
+    assert_eq!(resp.status(), 202);
+
+    let following = db.get_following(ALICE, 10, 0).await.unwrap();
+    assert_eq!(following.len(), 1);
+    assert_eq!(following[0].following_id, BOB);
+    assert_eq!(following[0].status, "accepted");
+}
+
+// Section 3: objects and collections
+
+/// §3.2: objects and collections MUST be served as ActivityStreams, honouring
+/// the client's choice between the two media types
+#[tokio::test]
+async fn collections_must_be_served_as_activity_streams() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    let activity =
+        json!({"type": "Create", "actor": ALICE, "object": public_note("hi"), "to": [PUBLIC]});
+    let resp = test::call_service(&app, post_outbox(activity).to_request()).await;
+    let created: Value = test::read_body_json(resp).await;
+    let replies = created["object"]["replies"].as_str().unwrap();
+    let replies = replies.trim_start_matches("https://example.com");
+
+    let ld_json = "application/ld+json; profile=\"https://www.w3.org/ns/activitystreams\"";
+    for uri in [
+        "/users/alice",
+        "/users/alice/outbox",
+        "/users/alice/collections/featured",
+        replies,
+    ] {
+        for (accept, expected) in [
+            ("application/activity+json", "application/activity+json"),
+            (ld_json, ld_json),
+        ] {
+            let req = test::TestRequest::get()
+                .uri(uri)
+                .insert_header((header::ACCEPT, accept))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 200, "{uri}");
+            assert_eq!(
+                resp.headers().get(header::CONTENT_TYPE).unwrap(),
+                expected,
+                "{uri} as {accept}"
+            );
+        }
+    }
+}
@@ -0,0 +1,339 @@
+mod common;
+
+use actix_web::{test, web, App};
+use chrono::Utc;
+use common::{OfflineHttpClient, ALICE_TOKEN};
+use feder8::config::Config;
+use feder8::database::{DatabaseRef, DbNote, DbNoteLabel, PublishState};
+use feder8::handlers;
+use feder8::models::addressing::PUBLIC;
+use feder8::models::Visibility;
+use feder8::services::classify::{ContentClassifier, KeywordClassifier};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ALICE: &str = "https://example.com/users/alice";
+const BOB: &str = "https://remote.example/users/bob";
+
+fn create_test_app(
+    db: &DatabaseRef,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    let config = Config {
+        classifier_rules: vec![r"murder=(?i)\bmurder\b".to_string()],
+        ..common::test_config()
+    };
+    let classifier: Arc<dyn ContentClassifier> = Arc::new(KeywordClassifier::from_config(&config));
+    common::test_app(db, config, Arc::new(OfflineHttpClient))
+        .app_data(web::Data::from(classifier))
+        .service(handlers::outbox::post_outbox)
+        .service(handlers::outbox::get_outbox)
+        .service(handlers::inbox::inbox)
+        .service(handlers::api::statuses::get_status)
+        .service(handlers::api::preferences::get_preferences)
+        .service(handlers::api::preferences::update_preferences)
+}
+
+fn note(id: &str, sensitive: bool, summary: Option<&str>) -> DbNote {
+    DbNote {
+        id: id.to_string(),
+        attributed_to: ALICE.to_string(),
+        content: "The butler did it".to_string(),
+        to_recipients: vec![PUBLIC.to_string()],
+        cc_recipients: vec![],
+        published: Utc::now(),
+        in_reply_to: None,
+        tags: vec![],
+        attachments: vec![],
+        visibility: Visibility::Public,
+        state: PublishState::Published,
+        pinned: false,
+        created_at: Utc::now(),
+        in_reply_to_actor: None,
+        thread_depth: Some(0),
+        thread_truncated: false,
+        sensitive,
+        summary: summary.map(String::from),
+        language: None,
+        content_map: None,
+    }
+}
+
+#[tokio::test]
+async fn test_content_warning_round_trips_through_the_database() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+
+    let warned = note(
+        "https://example.com/notes/1",
+        true,
+        Some("Spoilers: mystery novel"),
+    );
+    let plain = note("https://example.com/notes/2", false, None);
+    db.create_note(&warned).await.unwrap();
+    db.create_note(&plain).await.unwrap();
+
+    let fetched = db.get_note_by_id(&warned.id).await.unwrap().unwrap();
+    assert!(fetched.sensitive);
+    assert_eq!(fetched.summary.as_deref(), Some("Spoilers: mystery novel"));
+
+    let fetched = db.get_note_by_id(&plain.id).await.unwrap().unwrap();
+    assert!(!fetched.sensitive);
+    assert_eq!(fetched.summary, None);
+
+    let notes = db.get_notes_by_actor(ALICE, 10, 0).await.unwrap();
+    let listed = notes.iter().find(|n| n.id == warned.id).unwrap();
+    assert!(listed.sensitive);
+    assert_eq!(listed.summary, warned.summary);
+}
+
+#[tokio::test]
+async fn test_outbox_stores_and_serves_content_warnings() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/users/alice/outbox")
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+        .set_json(json!({
+            "type": "Create",
+            "actor": ALICE,
+            "to": [PUBLIC],
+            "object": {
+                "type": "Note",
+                "content": "The butler did it",
+                "sensitive": true,
+                "summary": "Spoilers",
+                "to": [PUBLIC]
+            }
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let create: Value = test::read_body_json(resp).await;
+    assert_eq!(create["object"]["sensitive"], true);
+    assert_eq!(create["object"]["summary"], "Spoilers");
+
+    let note_id = create["object"]["id"].as_str().unwrap();
+    let stored = db.get_note_by_id(note_id).await.unwrap().unwrap();
+    assert!(stored.sensitive);
+    assert_eq!(stored.summary.as_deref(), Some("Spoilers"));
+
+    let req = test::TestRequest::get()
+        .uri("/users/alice/outbox?page=true")
+        .to_request();
+    let page: Value = test::call_and_read_body_json(&app, req).await;
+    let object = &page["orderedItems"][0]["object"];
+    assert_eq!(object["sensitive"], true);
+    assert_eq!(object["summary"], "Spoilers");
+
+    let status_id = note_id.rsplit('/').next().unwrap();
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/statuses/{status_id}"))
+        .to_request();
+    let status: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(status["sensitive"], true);
+    assert_eq!(status["spoiler_text"], "Spoilers");
+}
+
+#[tokio::test]
+async fn test_content_warnings_mark_outgoing_notes_sensitive() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    // A content warning without the flag, and a blank one, which is dropped
+    for (summary, sensitive) in [(json!("Spoilers"), true), (json!("  "), false)] {
+        let req = test::TestRequest::post()
+            .uri("/users/alice/outbox")
+            .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+            .set_json(json!({
+                "type": "Create",
+                "actor": ALICE,
+                "to": [PUBLIC],
+                "object": {
+                    "type": "Note",
+                    "content": "The butler did it",
+                    "summary": summary,
+                    "to": [PUBLIC]
+                }
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 201);
+        let create: Value = test::read_body_json(resp).await;
+        assert_eq!(
+            create["object"].get("sensitive").is_some(),
+            sensitive,
+            "{summary}"
+        );
+
+        let note_id = create["object"]["id"].as_str().unwrap();
+        let stored = db.get_note_by_id(note_id).await.unwrap().unwrap();
+        assert_eq!(stored.sensitive, sensitive, "{summary}");
+    }
+}
+
+#[tokio::test]
+async fn test_expand_sensitive_preference() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    let get = || {
+        test::TestRequest::get()
+            .uri("/api/v1/preferences")
+            .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+            .to_request()
+    };
+    let preferences: Value = test::call_and_read_body_json(&app, get()).await;
+    assert_eq!(preferences["reading:expand:spoilers"], false);
+    assert_eq!(preferences["reading:expand:media"], "default");
+    assert_eq!(preferences["posting:auto_content_warnings"], true);
+
+    let req = test::TestRequest::patch()
+        .uri("/api/v1/preferences")
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+        .set_json(json!({"expand_sensitive": true}))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 200);
+
+    let preferences: Value = test::call_and_read_body_json(&app, get()).await;
+    assert_eq!(preferences["reading:expand:spoilers"], true);
+    assert_eq!(preferences["reading:expand:media"], "show_all");
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/preferences")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+}
+
+/// Alice posting a public note with `content`
+fn post_note(content: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/users/alice/outbox")
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+        .set_json(json!({
+            "type": "Create",
+            "actor": ALICE,
+            "to": [PUBLIC],
+            "object": {"type": "Note", "content": content, "to": [PUBLIC]}
+        }))
+}
+
+#[tokio::test]
+async fn test_classifiers_warn_about_matching_notes_unless_opted_out() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    let create: Value =
+        test::call_and_read_body_json(&app, post_note("Murder at the vicarage").to_request()).await;
+    assert_eq!(create["object"]["summary"], "murder");
+    assert_eq!(create["object"]["sensitive"], true);
+    let note_id = create["object"]["id"].as_str().unwrap();
+    let stored = db.get_note_by_id(note_id).await.unwrap().unwrap();
+    assert_eq!(stored.summary.as_deref(), Some("murder"));
+    assert!(stored.sensitive);
+    assert_eq!(
+        db.get_note_labels(note_id).await.unwrap(),
+        vec![DbNoteLabel {
+            label: "murder".to_string(),
+            applied: true
+        }]
+    );
+
+    // Notes nothing matched are left alone, with nothing recorded
+    let create: Value =
+        test::call_and_read_body_json(&app, post_note("Tea at the vicarage").to_request()).await;
+    assert!(create["object"].get("summary").is_none());
+    let note_id = create["object"]["id"].as_str().unwrap();
+    assert!(db.get_note_labels(note_id).await.unwrap().is_empty());
+
+    let req = test::TestRequest::patch()
+        .uri("/api/v1/preferences")
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+        .set_json(json!({"auto_content_warnings": false}))
+        .to_request();
+    let preferences: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(preferences["posting:auto_content_warnings"], false);
+
+    // The label is still recorded for audit, but the note goes out as written
+    let create: Value = test::call_and_read_body_json(
+        &app,
+        post_note("Murder at the vicarage, again").to_request(),
+    )
+    .await;
+    assert!(create["object"].get("summary").is_none());
+    assert!(create["object"].get("sensitive").is_none());
+    let note_id = create["object"]["id"].as_str().unwrap();
+    assert!(!db.get_note_by_id(note_id).await.unwrap().unwrap().sensitive);
+    assert_eq!(
+        db.get_note_labels(note_id).await.unwrap(),
+        vec![DbNoteLabel {
+            label: "murder".to_string(),
+            applied: false
+        }]
+    );
+}
+
+#[tokio::test]
+async fn test_inbox_parses_content_warnings() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    for (id, sensitive, summary) in [
+        (
+            "https://remote.example/notes/1",
+            json!(true),
+            json!("CW: food"),
+        ),
+        ("https://remote.example/notes/2", json!(null), json!("   ")),
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/users/alice/inbox")
+            .set_json(json!({
+                "id": format!("{id}/activity"),
+                "type": "Create",
+                "actor": BOB,
+                "to": [PUBLIC],
+                "object": {
+                    "id": id,
+                    "type": "Note",
+                    "attributedTo": BOB,
+                    "content": "lunch",
+                    "sensitive": sensitive,
+                    "summary": summary,
+                    "to": [PUBLIC]
+                }
+            }))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 202);
+    }
+
+    let warned = db
+        .get_note_by_id("https://remote.example/notes/1")
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(warned.sensitive);
+    assert_eq!(warned.summary.as_deref(), Some("CW: food"));
+
+    let plain = db
+        .get_note_by_id("https://remote.example/notes/2")
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(!plain.sensitive);
+    assert_eq!(plain.summary, None);
+}
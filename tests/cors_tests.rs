@@ -0,0 +1,188 @@
+mod common;
+
+use actix_web::http::{header, Method};
+use actix_web::{test, App};
+use common::OfflineHttpClient;
+use feder8::config::Config;
+use feder8::database::{create_configured_mock_database, DatabaseRef};
+use feder8::{handlers, http};
+use std::sync::Arc;
+
+fn create_test_app(
+    allowed_origins: &[&str],
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse<impl actix_web::body::MessageBody>,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    let allowed_origins: Vec<String> = allowed_origins.iter().map(|o| o.to_string()).collect();
+    let db: DatabaseRef = Arc::new(create_configured_mock_database());
+    common::test_app(&db, Config::default(), Arc::new(OfflineHttpClient))
+        .wrap(http::cors(&allowed_origins))
+        .service(handlers::outbox::get_outbox)
+        .service(handlers::cors::preflight)
+}
+
+fn preflight(origin: &str) -> test::TestRequest {
+    test::TestRequest::default()
+        .method(Method::OPTIONS)
+        .uri("/users/testuser/outbox")
+        .insert_header((header::ORIGIN, origin))
+        .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, "GET"))
+        .insert_header((header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization"))
+}
+
+#[tokio::test]
+async fn test_preflight_allows_configured_origin() {
+    let app = test::init_service(create_test_app(&["https://app.example"])).await;
+
+    let resp = test::call_service(&app, preflight("https://app.example").to_request()).await;
+
+    assert!(resp.status().is_success());
+    assert_eq!(
+        resp.headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .unwrap(),
+        "https://app.example"
+    );
+    let methods = resp
+        .headers()
+        .get(header::ACCESS_CONTROL_ALLOW_METHODS)
+        .unwrap()
+        .to_str()
+        .unwrap();
+    for method in ["GET", "POST", "PATCH", "DELETE", "OPTIONS"] {
+        assert!(methods.contains(method), "{method} missing from {methods}");
+    }
+}
+
+#[tokio::test]
+async fn test_preflight_rejects_unknown_origin() {
+    let app = test::init_service(create_test_app(&["https://app.example"])).await;
+
+    let resp = test::call_service(&app, preflight("https://evil.example").to_request()).await;
+
+    assert!(resp
+        .headers()
+        .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_wildcard_allows_any_origin_and_exposes_headers() {
+    let app = test::init_service(create_test_app(&["*"])).await;
+
+    let resp = test::call_service(&app, preflight("https://client.example").to_request()).await;
+    assert_eq!(
+        resp.headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .unwrap(),
+        "https://client.example"
+    );
+
+    let req = test::TestRequest::get()
+        .uri("/users/testuser/outbox")
+        .insert_header((header::ORIGIN, "https://client.example"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let exposed = resp
+        .headers()
+        .get(header::ACCESS_CONTROL_EXPOSE_HEADERS)
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_lowercase();
+    assert!(exposed.contains("link"));
+    assert!(exposed.contains("x-ratelimit-remaining"));
+}
+
+#[tokio::test]
+async fn test_plain_options_request_is_answered() {
+    let app = test::init_service(create_test_app(&["*"])).await;
+
+    let req = test::TestRequest::default()
+        .method(Method::OPTIONS)
+        .uri("/users/testuser/outbox")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 204);
+    assert_eq!(
+        resp.headers().get(header::ALLOW).unwrap(),
+        "GET, POST, PATCH, DELETE, OPTIONS"
+    );
+}
+
+fn preflight_for(origin: &str, method: &str, path: &str) -> test::TestRequest {
+    test::TestRequest::default()
+        .method(Method::OPTIONS)
+        .uri(path)
+        .insert_header((header::ORIGIN, origin))
+        .insert_header((header::ACCESS_CONTROL_REQUEST_METHOD, method))
+}
+
+fn allowed_origin<B>(resp: &actix_web::dev::ServiceResponse<B>) -> Option<String> {
+    resp.headers()
+        .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        .map(|v| v.to_str().unwrap().to_string())
+}
+
+#[tokio::test]
+async fn test_wildcard_only_allows_reads() {
+    let app = test::init_service(create_test_app(&["*"])).await;
+    let origin = "https://client.example";
+
+    for path in [
+        "/.well-known/webfinger?resource=acct:testuser@example.com",
+        "/users/testuser",
+        "/users/testuser/outbox",
+    ] {
+        assert_eq!(
+            allowed_origin(
+                &test::call_service(&app, preflight_for(origin, "GET", path).to_request()).await
+            )
+            .as_deref(),
+            Some(origin),
+            "{path}"
+        );
+    }
+
+    // Writes and inboxes need the origin listed
+    for (method, path) in [
+        ("POST", "/users/testuser/outbox"),
+        ("GET", "/users/testuser/inbox"),
+        ("POST", "/users/testuser/inbox"),
+    ] {
+        assert_eq!(
+            allowed_origin(
+                &test::call_service(&app, preflight_for(origin, method, path).to_request()).await
+            ),
+            None,
+            "{method} {path}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_listed_origins_may_write() {
+    let app = test::init_service(create_test_app(&["*", "https://app.example"])).await;
+
+    for (method, path) in [
+        ("POST", "/users/testuser/outbox"),
+        ("PATCH", "/api/v1/preferences"),
+        ("GET", "/users/testuser/inbox"),
+    ] {
+        let req = preflight_for("https://app.example", method, path)
+            .insert_header((header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization"));
+        assert_eq!(
+            allowed_origin(&test::call_service(&app, req.to_request()).await).as_deref(),
+            Some("https://app.example"),
+            "{method} {path}"
+        );
+    }
+}
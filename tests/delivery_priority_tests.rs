@@ -0,0 +1,187 @@
+mod common;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use feder8::config::Config;
+use feder8::database::{DatabaseRef, DbDelivery, DeliveryPriority};
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode};
+use feder8::metrics::Metrics;
+use feder8::services::delivery::DeliveryService;
+use feder8::services::delivery_queue::{self, DeliveryRun};
+use feder8::services::log_dedup::LogDedup;
+use feder8::services::scheduler::TestClock;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+fn now() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap()
+}
+
+/// Accepts everything, remembering the inboxes posted to in order
+#[derive(Default)]
+struct RecordingHttpClient {
+    posted: Mutex<Vec<String>>,
+}
+
+#[async_trait::async_trait]
+impl HttpClient for RecordingHttpClient {
+    async fn send(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        self.posted.lock().unwrap().push(request.url);
+        Ok(HttpResponse {
+            status: StatusCode(202),
+            headers: HashMap::new(),
+            body: Vec::new(),
+        })
+    }
+}
+
+/// `count` deliveries of `priority` to `host`, queued `minutes` ago, oldest
+/// first
+async fn enqueue(
+    db: &DatabaseRef,
+    priority: DeliveryPriority,
+    host: &str,
+    count: usize,
+    minutes: i64,
+) {
+    for n in 0..count {
+        let queued_at = now() - Duration::minutes(minutes) + Duration::seconds(n as i64);
+        db.enqueue_delivery(&DbDelivery {
+            id: format!("{}-{n}", priority.as_str()),
+            inbox_url: format!("https://{host}/inbox/{n}"),
+            activity: json!({ "type": "Create" }),
+            priority,
+            attempts: 0,
+            next_attempt_at: queued_at,
+            last_error: None,
+            created_at: queued_at,
+        })
+        .await
+        .unwrap();
+    }
+}
+
+fn service(
+    db: &DatabaseRef,
+    client: Arc<RecordingHttpClient>,
+    metrics: Arc<Metrics>,
+) -> DeliveryService {
+    DeliveryService::new(Config::default(), client, db.clone()).with_metrics(metrics)
+}
+
+#[tokio::test]
+async fn test_interactive_deliveries_jump_the_fan_out() {
+    let dir = TempDir::new().unwrap();
+    let db: DatabaseRef = Arc::new(common::sqlite_database(&dir).await);
+    // A big post's fan-out was queued first, then a follow handshake
+    enqueue(&db, DeliveryPriority::Broadcast, "followers.example", 5, 30).await;
+    enqueue(&db, DeliveryPriority::Direct, "mentioned.example", 2, 20).await;
+    enqueue(
+        &db,
+        DeliveryPriority::Interactive,
+        "handshake.example",
+        2,
+        10,
+    )
+    .await;
+    let client = Arc::new(RecordingHttpClient::default());
+
+    let run = delivery_queue::process_queue(
+        &db,
+        &service(&db, client.clone(), Arc::new(Metrics::new())),
+        now(),
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(run.delivered, 9);
+    let posted = client.posted.lock().unwrap().clone();
+    assert_eq!(
+        posted[..4],
+        [
+            "https://handshake.example/inbox/0",
+            "https://handshake.example/inbox/1",
+            "https://mentioned.example/inbox/0",
+            "https://mentioned.example/inbox/1",
+        ]
+    );
+    assert_eq!(posted[4], "https://followers.example/inbox/0");
+}
+
+#[tokio::test]
+async fn test_broadcast_keeps_its_share_under_load() {
+    let dir = TempDir::new().unwrap();
+    let db: DatabaseRef = Arc::new(common::sqlite_database(&dir).await);
+    enqueue(
+        &db,
+        DeliveryPriority::Broadcast,
+        "followers.example",
+        60,
+        30,
+    )
+    .await;
+    enqueue(
+        &db,
+        DeliveryPriority::Interactive,
+        "handshake.example",
+        60,
+        10,
+    )
+    .await;
+    let client = Arc::new(RecordingHttpClient::default());
+    let metrics = Arc::new(Metrics::new());
+    let service = service(&db, client.clone(), metrics.clone());
+
+    let run = delivery_queue::process_queue(&db, &service, now())
+        .await
+        .unwrap();
+
+    // Interactive takes its own share and the unused direct one; broadcast
+    // still gets through
+    assert_eq!(
+        run,
+        DeliveryRun {
+            delivered: 50,
+            retried: 0,
+            dropped: 0
+        }
+    );
+    let posted = client.posted.lock().unwrap().clone();
+    let broadcast = posted
+        .iter()
+        .filter(|url| url.starts_with("https://followers.example"))
+        .count();
+    assert_eq!(broadcast, 10);
+    assert!(posted[..40]
+        .iter()
+        .all(|url| url.starts_with("https://handshake.example")));
+
+    let text = metrics.render();
+    assert!(text.contains(
+        r#"feder8_delivery_queue_processed_total{outcome="delivered",priority="interactive"} 40"#
+    ));
+    assert!(text.contains(
+        r#"feder8_delivery_queue_processed_total{outcome="delivered",priority="broadcast"} 10"#
+    ));
+
+    // What is left is reported by priority
+    let warnings = LogDedup::new(Arc::new(TestClock::new(now())), Duration::minutes(5));
+    let backlog =
+        delivery_queue::report_backlog(&db, &metrics, &warnings, Duration::hours(1), now())
+            .await
+            .unwrap();
+    assert_eq!(
+        backlog.by_priority,
+        vec![
+            (DeliveryPriority::Interactive, 20),
+            (DeliveryPriority::Broadcast, 50)
+        ]
+    );
+    let text = metrics.render();
+    assert!(
+        text.contains(r#"feder8_delivery_queue_pending_by_priority{priority="interactive"} 20"#)
+    );
+    assert!(text.contains(r#"feder8_delivery_queue_pending_by_priority{priority="direct"} 0"#));
+    assert!(text.contains(r#"feder8_delivery_queue_pending_by_priority{priority="broadcast"} 50"#));
+}
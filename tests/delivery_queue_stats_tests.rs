@@ -0,0 +1,179 @@
+mod common;
+
+use actix_web::test;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use common::OfflineHttpClient;
+use feder8::config::Config;
+use feder8::database::{DatabaseRef, DbDelivery, DeliveryPriority};
+use feder8::handlers;
+use feder8::metrics::Metrics;
+use feder8::services::delivery_queue;
+use feder8::services::log_dedup::LogDedup;
+use feder8::services::scheduler::TestClock;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ADMIN_TOKEN: &str = "test-admin-token";
+
+fn now() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap()
+}
+
+/// A delivery to `inbox_url` that was queued `minutes` ago
+async fn enqueue_aged(db: &DatabaseRef, id: &str, inbox_url: &str, minutes: i64) {
+    db.enqueue_delivery(&DbDelivery {
+        id: id.to_string(),
+        inbox_url: inbox_url.to_string(),
+        activity: json!({ "type": "Create" }),
+        priority: DeliveryPriority::Broadcast,
+        attempts: 3,
+        next_attempt_at: now() + Duration::minutes(5),
+        last_error: Some("HTTP 503".to_string()),
+        created_at: now() - Duration::minutes(minutes),
+    })
+    .await
+    .unwrap();
+}
+
+async fn seed_backlog(db: &DatabaseRef) {
+    enqueue_aged(db, "d1", "https://slow.example/inbox", 120).await;
+    enqueue_aged(db, "d2", "https://slow.example/users/bob/inbox", 90).await;
+    enqueue_aged(db, "d3", "https://slow.example/inbox", 10).await;
+    enqueue_aged(db, "d4", "https://fine.example/inbox", 1).await;
+}
+
+fn warnings() -> LogDedup {
+    LogDedup::new(Arc::new(TestClock::new(now())), Duration::minutes(5))
+}
+
+#[tokio::test]
+async fn test_backlog_is_exported_as_gauges() {
+    let dir = TempDir::new().unwrap();
+    let db: DatabaseRef = Arc::new(common::sqlite_database(&dir).await);
+    seed_backlog(&db).await;
+    let metrics = Metrics::new();
+
+    let backlog =
+        delivery_queue::report_backlog(&db, &metrics, &warnings(), Duration::hours(1), now())
+            .await
+            .unwrap();
+
+    assert_eq!(backlog.pending, 4);
+    let oldest = backlog.oldest.unwrap();
+    assert_eq!(oldest.inbox_url, "https://slow.example/inbox");
+    assert_eq!(oldest.age, Duration::minutes(120));
+    assert_eq!(backlog.by_host[0].host, "slow.example");
+    assert_eq!(backlog.by_host[0].pending, 3);
+
+    let text = metrics.render();
+    assert!(text.contains("feder8_delivery_queue_pending 4"));
+    assert!(text.contains("feder8_delivery_queue_oldest_age_seconds 7200"));
+    assert!(text.contains(r#"feder8_delivery_queue_pending_by_host{host="slow.example"} 3"#));
+    assert!(text.contains(r#"feder8_delivery_queue_pending_by_host{host="fine.example"} 1"#));
+}
+
+#[tokio::test]
+async fn test_drained_hosts_drop_out_of_the_gauges() {
+    let dir = TempDir::new().unwrap();
+    let db: DatabaseRef = Arc::new(common::sqlite_database(&dir).await);
+    seed_backlog(&db).await;
+    let metrics = Metrics::new();
+    let warnings = warnings();
+
+    delivery_queue::report_backlog(&db, &metrics, &warnings, Duration::hours(1), now())
+        .await
+        .unwrap();
+    db.delete_delivery("d4").await.unwrap();
+    delivery_queue::report_backlog(&db, &metrics, &warnings, Duration::hours(1), now())
+        .await
+        .unwrap();
+
+    let text = metrics.render();
+    assert!(text.contains("feder8_delivery_queue_pending 3"));
+    assert!(!text.contains("fine.example"));
+}
+
+#[tokio::test]
+#[tracing_test::traced_test]
+async fn test_a_stuck_queue_is_warned_about_once_per_window() {
+    let dir = TempDir::new().unwrap();
+    let db: DatabaseRef = Arc::new(common::sqlite_database(&dir).await);
+    seed_backlog(&db).await;
+    let metrics = Metrics::new();
+    let warnings = warnings();
+
+    for _ in 0..3 {
+        delivery_queue::report_backlog(&db, &metrics, &warnings, Duration::hours(1), now())
+            .await
+            .unwrap();
+    }
+
+    logs_assert(|lines: &[&str]| {
+        let warned: Vec<_> = lines
+            .iter()
+            .filter(|line| line.contains(" WARN ") && line.contains("holding up the queue"))
+            .collect();
+        match warned.as_slice() {
+            [line]
+                if line.contains("https://slow.example/inbox")
+                    && line.contains("waited 120 minutes") =>
+            {
+                Ok(())
+            }
+            _ => Err(format!("expected one stuck-queue warning, got {warned:?}")),
+        }
+    });
+}
+
+#[tokio::test]
+#[tracing_test::traced_test]
+async fn test_a_queue_within_the_threshold_is_not_warned_about() {
+    let dir = TempDir::new().unwrap();
+    let db: DatabaseRef = Arc::new(common::sqlite_database(&dir).await);
+    seed_backlog(&db).await;
+
+    delivery_queue::report_backlog(&db, &Metrics::new(), &warnings(), Duration::hours(3), now())
+        .await
+        .unwrap();
+
+    assert!(!logs_contain("holding up the queue"));
+}
+
+#[actix_web::test]
+async fn test_admin_stats_reports_the_backlog() {
+    let dir = TempDir::new().unwrap();
+    let db: DatabaseRef = Arc::new(common::sqlite_database(&dir).await);
+    seed_backlog(&db).await;
+    let config = Config {
+        admin_token: Some(ADMIN_TOKEN.to_string()),
+        ..common::test_config()
+    };
+    let app = test::init_service(
+        common::test_app(&db, config, Arc::new(OfflineHttpClient))
+            .service(handlers::admin::stats::get_stats),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/stats")
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 401);
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/stats")
+        .insert_header(("Authorization", format!("Bearer {ADMIN_TOKEN}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let stats: Value = test::read_body_json(resp).await;
+
+    let queue = &stats["delivery_queue"];
+    assert_eq!(queue["pending"], 4);
+    assert_eq!(queue["oldest_inbox"], "https://slow.example/inbox");
+    // Measured against the wall clock, so at least the seeded two hours
+    assert!(queue["oldest_age_secs"].as_i64().unwrap() >= 7200);
+    assert_eq!(queue["by_host"][0]["host"], "slow.example");
+    assert_eq!(queue["by_host"][0]["pending"], 3);
+    assert_eq!(queue["by_host"][1]["host"], "fine.example");
+}
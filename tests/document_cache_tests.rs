@@ -0,0 +1,241 @@
+mod common;
+
+use actix_web::{test, web, App};
+use chrono::Utc;
+use feder8::config::Config;
+use feder8::database::{DatabaseRef, DbActor, DbNote, MockDatabase, PublishState};
+use feder8::handlers;
+use feder8::http::ReqwestClient;
+use feder8::models::addressing::PUBLIC;
+use feder8::models::Visibility;
+use feder8::services::document_cache::DocumentCache;
+use feder8::services::events::{EventBus, ObjectEvent};
+use serde_json::Value;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ALICE: &str = "https://example.com/users/alice";
+const ADMIN_TOKEN: &str = "test-admin-token";
+
+fn alice() -> DbActor {
+    common::actor(ALICE, "alice", "Alice")
+}
+
+fn note(n: u32, visibility: Visibility) -> DbNote {
+    let (to, cc) = match visibility {
+        Visibility::Public => (vec![PUBLIC.to_string()], vec![]),
+        Visibility::Unlisted => (vec![], vec![PUBLIC.to_string()]),
+        Visibility::Followers | Visibility::Local => (vec![format!("{ALICE}/followers")], vec![]),
+        Visibility::Direct => (vec!["https://remote.example/users/bob".to_string()], vec![]),
+    };
+    DbNote {
+        id: format!("https://example.com/notes/{n}"),
+        attributed_to: ALICE.to_string(),
+        content: format!("note {n}"),
+        to_recipients: to,
+        cc_recipients: cc,
+        published: Utc::now(),
+        in_reply_to: None,
+        tags: vec![],
+        attachments: vec![],
+        visibility,
+        state: PublishState::Published,
+        pinned: false,
+        created_at: Utc::now(),
+        in_reply_to_actor: None,
+        thread_depth: Some(0),
+        thread_truncated: false,
+        sensitive: false,
+        summary: None,
+        language: None,
+        content_map: None,
+    }
+}
+
+/// A cache subscribed to a bus, as the container wires them
+fn cache_and_bus() -> (Arc<DocumentCache>, Arc<EventBus>) {
+    let cache = Arc::new(DocumentCache::from_config(&Config::default()));
+    let bus = Arc::new(EventBus::new());
+    bus.subscribe(cache.clone());
+    (cache, bus)
+}
+
+fn create_test_app(
+    db: DatabaseRef,
+    cache: Arc<DocumentCache>,
+    bus: Arc<EventBus>,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    let config = Config {
+        admin_token: Some(ADMIN_TOKEN.to_string()),
+        ..common::test_config()
+    };
+    common::test_app(&db, config, Arc::new(ReqwestClient::new()))
+        .app_data(web::Data::from(cache))
+        .app_data(web::Data::from(bus))
+        .service(handlers::actor::get_actor)
+        .service(handlers::note::get_note)
+        .service(handlers::admin::actors::delete_actor)
+}
+
+fn get(uri: &str) -> test::TestRequest {
+    test::TestRequest::get()
+        .uri(uri)
+        .insert_header(("Accept", "application/activity+json"))
+}
+
+#[actix_web::test]
+async fn test_cache_hits_skip_the_database() {
+    let mut mock = MockDatabase::new();
+    mock.expect_get_actor_by_username()
+        .times(1)
+        .returning(|_| Ok(Some(alice())));
+    let (cache, bus) = cache_and_bus();
+    let app = test::init_service(create_test_app(Arc::new(mock), cache, bus)).await;
+
+    let first = test::call_service(&app, get("/users/alice").to_request()).await;
+    assert_eq!(first.status(), 200);
+    let etag = first.headers().get("etag").unwrap().clone();
+    let first: Value = test::read_body_json(first).await;
+
+    let second = test::call_service(&app, get("/users/alice").to_request()).await;
+    assert_eq!(second.status(), 200);
+    assert_eq!(second.headers().get("etag").unwrap(), &etag);
+    assert_eq!(
+        second.headers().get("content-type").unwrap(),
+        "application/activity+json"
+    );
+    let second: Value = test::read_body_json(second).await;
+    assert_eq!(first, second);
+
+    // Conditional requests are answered from the cache too
+    let req = get("/users/alice")
+        .insert_header(("If-None-Match", etag))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 304);
+}
+
+#[actix_web::test]
+async fn test_an_update_event_invalidates_the_actor() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let (cache, bus) = cache_and_bus();
+    let app = test::init_service(create_test_app(db.clone(), cache, bus.clone())).await;
+
+    let actor: Value =
+        test::read_body_json(test::call_service(&app, get("/users/alice").to_request()).await)
+            .await;
+    assert_eq!(actor["name"], "Alice");
+
+    let mut renamed = alice();
+    renamed.name = "Alice Liddell".to_string();
+    db.update_actor(&renamed).await.unwrap();
+
+    // Until the change is announced the cached document is served
+    let actor: Value =
+        test::read_body_json(test::call_service(&app, get("/users/alice").to_request()).await)
+            .await;
+    assert_eq!(actor["name"], "Alice");
+
+    bus.publish(ObjectEvent::ActorUpdated(ALICE.to_string()));
+    let actor: Value =
+        test::read_body_json(test::call_service(&app, get("/users/alice").to_request()).await)
+            .await;
+    assert_eq!(actor["name"], "Alice Liddell");
+}
+
+#[actix_web::test]
+async fn test_deleting_an_actor_drops_it_and_its_notes() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    db.create_note(&note(1, Visibility::Public)).await.unwrap();
+    let (cache, bus) = cache_and_bus();
+    let app = test::init_service(create_test_app(db, cache.clone(), bus)).await;
+
+    for uri in ["/users/alice", "/notes/1"] {
+        assert_eq!(
+            test::call_service(&app, get(uri).to_request())
+                .await
+                .status(),
+            200
+        );
+    }
+    assert!(cache.get(ALICE).is_some());
+    assert!(cache.get("https://example.com/notes/1").is_some());
+
+    let req = test::TestRequest::delete()
+        .uri("/api/admin/actors/https%3A%2F%2Fexample.com%2Fusers%2Falice")
+        .insert_header(("Authorization", format!("Bearer {ADMIN_TOKEN}")))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 204);
+
+    assert!(cache.get(ALICE).is_none());
+    assert!(cache.get("https://example.com/notes/1").is_none());
+    let resp = test::call_service(&app, get("/users/alice").to_request()).await;
+    assert_eq!(resp.status(), 404);
+}
+
+#[actix_web::test]
+async fn test_restricted_notes_are_never_cached() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    for note in [
+        note(1, Visibility::Public),
+        note(2, Visibility::Unlisted),
+        note(3, Visibility::Followers),
+        note(4, Visibility::Direct),
+    ] {
+        db.create_note(&note).await.unwrap();
+    }
+    let (cache, bus) = cache_and_bus();
+    let app = test::init_service(create_test_app(db, cache.clone(), bus)).await;
+
+    for (uri, status) in [
+        ("/notes/1", 200),
+        ("/notes/2", 200),
+        ("/notes/3", 404),
+        ("/notes/4", 404),
+    ] {
+        let resp = test::call_service(&app, get(uri).to_request()).await;
+        assert_eq!(resp.status(), status, "{uri}");
+    }
+
+    assert!(cache.get("https://example.com/notes/1").is_some());
+    assert!(cache.get("https://example.com/notes/2").is_some());
+    assert!(cache.get("https://example.com/notes/3").is_none());
+    assert!(cache.get("https://example.com/notes/4").is_none());
+}
+
+#[actix_web::test]
+async fn test_html_pages_bypass_the_cache() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    db.create_note(&note(1, Visibility::Public)).await.unwrap();
+    let (cache, bus) = cache_and_bus();
+    let app = test::init_service(create_test_app(db, cache.clone(), bus)).await;
+
+    for uri in ["/users/alice", "/notes/1"] {
+        let req = test::TestRequest::get()
+            .uri(uri)
+            .insert_header(("Accept", "text/html"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert!(resp
+            .headers()
+            .get("content-type")
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("text/html"));
+    }
+    assert!(cache.get(ALICE).is_none());
+    assert!(cache.get("https://example.com/notes/1").is_none());
+}
@@ -0,0 +1,429 @@
+mod common;
+
+use actix_web::{test, web};
+use chrono::{Duration, Utc};
+use common::ALICE_TOKEN;
+use feder8::config::Config;
+use feder8::database::{DatabaseRef, DbFollowRelation, DbRemoteActor, DeliveryPriority};
+use feder8::handlers;
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode};
+use feder8::models::addressing::PUBLIC;
+use feder8::services::delivery::{DeliveryService, FanOut};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+const ALICE: &str = "https://example.com/users/alice";
+const SHARED_INBOX: &str = "https://remote.example/inbox";
+
+/// Serves remote actor documents and records every request made
+#[derive(Default)]
+struct FederationClient {
+    actors: HashMap<String, Value>,
+    /// Inboxes that answer deliveries with 503
+    down: HashSet<String>,
+    requests: Mutex<Vec<(String, String)>>,
+}
+
+impl FederationClient {
+    fn with_actor(mut self, id: &str, inbox: &str, shared_inbox: Option<&str>) -> Self {
+        let mut actor = json!({
+            "id": id,
+            "type": "Person",
+            "preferredUsername": id.rsplit('/').next().unwrap(),
+            "inbox": inbox,
+        });
+        if let Some(shared_inbox) = shared_inbox {
+            actor["endpoints"] = json!({ "sharedInbox": shared_inbox });
+        }
+        self.actors.insert(id.to_string(), actor);
+        self
+    }
+
+    fn with_inbox_down(mut self, inbox: &str) -> Self {
+        self.down.insert(inbox.to_string());
+        self
+    }
+
+    fn requests(&self, method: &str) -> Vec<String> {
+        let requests = self.requests.lock().unwrap();
+        requests
+            .iter()
+            .filter(|(m, _)| m == method)
+            .map(|(_, url)| url.clone())
+            .collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpClient for FederationClient {
+    async fn send(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        self.requests
+            .lock()
+            .unwrap()
+            .push((request.method.clone(), request.url.clone()));
+
+        let (status, body) = match request.method.as_str() {
+            "GET" => match self.actors.get(&request.url) {
+                Some(actor) => (200, serde_json::to_vec(actor)?),
+                None => (404, Vec::new()),
+            },
+            _ if self.down.contains(&request.url) => (503, Vec::new()),
+            _ => (202, Vec::new()),
+        };
+        Ok(HttpResponse {
+            status: StatusCode(status),
+            headers: HashMap::new(),
+            body,
+        })
+    }
+}
+
+async fn follow(db: &DatabaseRef, follower_id: &str, status: &str) {
+    db.create_follow(&DbFollowRelation {
+        id: format!("{follower_id}/follows/alice"),
+        follower_id: follower_id.to_string(),
+        following_id: ALICE.to_string(),
+        status: status.to_string(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    })
+    .await
+    .unwrap();
+}
+
+async fn cache_actor(db: &DatabaseRef, id: &str, inbox: &str, shared_inbox: Option<&str>) {
+    db.upsert_remote_actor(&DbRemoteActor {
+        id: id.to_string(),
+        username: id.rsplit('/').next().unwrap().to_string(),
+        name: None,
+        avatar_url: None,
+        inbox: Some(inbox.to_string()),
+        shared_inbox: shared_inbox.map(String::from),
+        public_key_pem: None,
+        fetched_at: Utc::now(),
+    })
+    .await
+    .unwrap();
+}
+
+fn delivery(db: &DatabaseRef, client: Arc<FederationClient>) -> DeliveryService {
+    let config = Config {
+        delivery_concurrency: 2,
+        ..common::test_config()
+    };
+    DeliveryService::new(config, client, db.clone())
+}
+
+fn create_activity() -> Value {
+    json!({
+        "id": "https://example.com/activities/1",
+        "type": "Create",
+        "actor": ALICE,
+        "to": [PUBLIC],
+        "object": {
+            "id": "https://example.com/notes/1",
+            "type": "Note",
+            "attributedTo": ALICE,
+            "content": "Hello followers"
+        }
+    })
+}
+
+#[tokio::test]
+async fn test_followers_on_a_shared_inbox_get_one_delivery() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let client = Arc::new(
+        FederationClient::default()
+            .with_actor(
+                "https://remote.example/users/bob",
+                "https://remote.example/users/bob/inbox",
+                Some(SHARED_INBOX),
+            )
+            .with_actor(
+                "https://remote.example/users/carol",
+                "https://remote.example/users/carol/inbox",
+                Some(SHARED_INBOX),
+            )
+            .with_actor(
+                "https://other.example/users/dave",
+                "https://other.example/users/dave/inbox",
+                None,
+            ),
+    );
+    for follower in [
+        "https://remote.example/users/bob",
+        "https://remote.example/users/carol",
+        "https://other.example/users/dave",
+    ] {
+        follow(&db, follower, "accepted").await;
+    }
+
+    let fan_out = delivery(&db, client.clone())
+        .fan_out_activity(create_activity(), ALICE)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        fan_out,
+        FanOut {
+            delivered: 2,
+            queued: 0
+        }
+    );
+    let mut posted = client.requests("POST");
+    posted.sort();
+    assert_eq!(
+        posted,
+        vec!["https://other.example/users/dave/inbox", SHARED_INBOX]
+    );
+
+    // What was fetched is cached for the next fan-out
+    let bob = db
+        .get_remote_actor("https://remote.example/users/bob")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(bob.shared_inbox.as_deref(), Some(SHARED_INBOX));
+    assert_eq!(
+        bob.inbox.as_deref(),
+        Some("https://remote.example/users/bob/inbox")
+    );
+}
+
+#[tokio::test]
+async fn test_cached_inboxes_are_used_without_fetching() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let client = Arc::new(FederationClient::default());
+    for follower in [
+        "https://remote.example/users/bob",
+        "https://remote.example/users/carol",
+    ] {
+        cache_actor(
+            &db,
+            follower,
+            &format!("{follower}/inbox"),
+            Some(SHARED_INBOX),
+        )
+        .await;
+        follow(&db, follower, "accepted").await;
+    }
+
+    delivery(&db, client.clone())
+        .fan_out_activity(create_activity(), ALICE)
+        .await
+        .unwrap();
+
+    assert!(client.requests("GET").is_empty());
+    assert_eq!(client.requests("POST"), vec![SHARED_INBOX]);
+}
+
+#[tokio::test]
+async fn test_pending_and_local_followers_are_skipped() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let client = Arc::new(FederationClient::default());
+    cache_actor(
+        &db,
+        "https://remote.example/users/bob",
+        "https://remote.example/users/bob/inbox",
+        None,
+    )
+    .await;
+    follow(&db, "https://remote.example/users/bob", "pending").await;
+    follow(&db, "https://example.com/users/carol", "accepted").await;
+
+    let fan_out = delivery(&db, client.clone())
+        .fan_out_activity(create_activity(), ALICE)
+        .await
+        .unwrap();
+
+    assert_eq!(fan_out, FanOut::default());
+    assert!(client.requests("GET").is_empty());
+    assert!(client.requests("POST").is_empty());
+}
+
+#[tokio::test]
+async fn test_failed_deliveries_are_queued_for_retry() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let client = Arc::new(FederationClient::default().with_inbox_down(SHARED_INBOX));
+    cache_actor(
+        &db,
+        "https://remote.example/users/bob",
+        "https://remote.example/users/bob/inbox",
+        Some(SHARED_INBOX),
+    )
+    .await;
+    cache_actor(
+        &db,
+        "https://other.example/users/dave",
+        "https://other.example/users/dave/inbox",
+        None,
+    )
+    .await;
+    follow(&db, "https://remote.example/users/bob", "accepted").await;
+    follow(&db, "https://other.example/users/dave", "accepted").await;
+
+    let fan_out = delivery(&db, client)
+        .fan_out_activity(create_activity(), ALICE)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        fan_out,
+        FanOut {
+            delivered: 1,
+            queued: 1
+        }
+    );
+    let queued = db
+        .get_due_deliveries(
+            Utc::now() + Duration::days(1),
+            DeliveryPriority::Broadcast,
+            10,
+        )
+        .await
+        .unwrap();
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].inbox_url, SHARED_INBOX);
+    assert_eq!(queued[0].activity["id"], "https://example.com/activities/1");
+}
+
+#[tokio::test]
+async fn test_fan_out_pages_through_every_follower() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let client = Arc::new(FederationClient::default());
+    for i in 0..250 {
+        let follower = format!("https://node{i}.example/users/bob");
+        cache_actor(&db, &follower, &format!("{follower}/inbox"), None).await;
+        follow(&db, &follower, "accepted").await;
+    }
+
+    let fan_out = delivery(&db, client.clone())
+        .fan_out_activity(create_activity(), ALICE)
+        .await
+        .unwrap();
+
+    assert_eq!(fan_out.delivered, 250);
+    let posted: HashSet<_> = client.requests("POST").into_iter().collect();
+    assert_eq!(posted.len(), 250);
+}
+
+#[actix_web::test]
+async fn test_outbox_create_fans_out_to_followers() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let client = Arc::new(FederationClient::default());
+    for follower in [
+        "https://remote.example/users/bob",
+        "https://remote.example/users/carol",
+    ] {
+        cache_actor(
+            &db,
+            follower,
+            &format!("{follower}/inbox"),
+            Some(SHARED_INBOX),
+        )
+        .await;
+        follow(&db, follower, "accepted").await;
+    }
+
+    let app = test::init_service(
+        common::test_app(&db, common::test_config(), client.clone())
+            .app_data(web::Data::new(delivery(&db, client.clone())))
+            .service(handlers::outbox::post_outbox),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/users/alice/outbox")
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+        .set_json(json!({
+            "type": "Create",
+            "actor": ALICE,
+            "to": [PUBLIC],
+            "cc": [format!("{ALICE}/followers")],
+            "object": {
+                "type": "Note",
+                "content": "Hello followers",
+                "to": [PUBLIC],
+                "cc": [format!("{ALICE}/followers")]
+            }
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+
+    // Fan-out runs in the background once the activity is stored
+    for _ in 0..100 {
+        if !client.requests("POST").is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert_eq!(client.requests("POST"), vec![SHARED_INBOX]);
+}
+
+#[actix_web::test]
+async fn test_outbox_create_reaches_a_mentioned_follower_once() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let bob = "https://remote.example/users/bob";
+    let dave = "https://other.example/users/dave";
+    let client = Arc::new(
+        FederationClient::default()
+            .with_actor(bob, &format!("{bob}/inbox"), None)
+            .with_actor(dave, &format!("{dave}/inbox"), None),
+    );
+    cache_actor(&db, bob, &format!("{bob}/inbox"), None).await;
+    follow(&db, bob, "accepted").await;
+
+    let app = test::init_service(
+        common::test_app(&db, common::test_config(), client.clone())
+            .app_data(web::Data::new(delivery(&db, client.clone())))
+            .service(handlers::outbox::post_outbox),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/users/alice/outbox")
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+        .set_json(json!({
+            "type": "Create",
+            "actor": ALICE,
+            "to": [PUBLIC],
+            "object": {
+                "type": "Note",
+                "content": "Hi @bob and @dave",
+                "tag": [
+                    {"type": "Mention", "name": "@bob@remote.example", "href": bob},
+                    {"type": "Mention", "name": "@dave@other.example", "href": dave}
+                ]
+            }
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+
+    // Dave only gets it because he is mentioned; Bob follows, so the
+    // fan-out covers him
+    let direct = db
+        .get_due_deliveries(Utc::now() + Duration::days(1), DeliveryPriority::Direct, 10)
+        .await
+        .unwrap();
+    let direct: Vec<_> = direct.iter().map(|d| d.inbox_url.as_str()).collect();
+    assert_eq!(direct, vec![format!("{dave}/inbox")]);
+
+    for _ in 0..100 {
+        if !client.requests("POST").is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert_eq!(client.requests("POST"), vec![format!("{bob}/inbox")]);
+}
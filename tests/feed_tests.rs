@@ -0,0 +1,228 @@
+mod common;
+
+use actix_web::test;
+use chrono::{Duration, TimeZone, Utc};
+use common::OfflineHttpClient;
+use feder8::database::{Database, DatabaseRef, DbActor, DbNote, PublishState};
+use feder8::handlers;
+use feder8::models::addressing::PUBLIC;
+use feder8::models::Visibility;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ALICE: &str = "https://example.com/users/alice";
+const ATOM: &str = "http://www.w3.org/2005/Atom";
+
+fn at(n: i64) -> chrono::DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap() + Duration::minutes(n)
+}
+
+async fn create_test_database(dir: &TempDir) -> DatabaseRef {
+    let db = common::sqlite_database(dir).await;
+    db.create_actor(&DbActor {
+        summary: Some("Tinkerer <3".to_string()),
+        created_at: at(0),
+        updated_at: at(0),
+        ..common::actor(ALICE, "alice", "Alice & Co")
+    })
+    .await
+    .unwrap();
+    Arc::new(db)
+}
+
+/// Alice's note `n`, published `n` minutes after a fixed start
+fn note(n: i64, content: &str, visibility: Visibility) -> DbNote {
+    DbNote {
+        id: format!("https://example.com/notes/{n}"),
+        attributed_to: ALICE.to_string(),
+        content: content.to_string(),
+        to_recipients: vec![PUBLIC.to_string()],
+        cc_recipients: vec![],
+        published: at(n),
+        in_reply_to: None,
+        tags: vec![],
+        attachments: vec![],
+        visibility,
+        state: PublishState::Published,
+        pinned: false,
+        created_at: at(n),
+        in_reply_to_actor: None,
+        thread_depth: Some(0),
+        thread_truncated: false,
+        sensitive: false,
+        summary: None,
+        language: None,
+        content_map: None,
+    }
+}
+
+/// Status, content type and body of a GET of `uri`
+async fn get(db: &DatabaseRef, uri: &str) -> (u16, String, String) {
+    let app = test::init_service(
+        common::test_app(db, common::test_config(), Arc::new(OfflineHttpClient))
+            .service(handlers::feed::get_atom_feed)
+            .service(handlers::feed::get_rss_feed),
+    )
+    .await;
+
+    let req = test::TestRequest::get().uri(uri).to_request();
+    let resp = test::call_service(&app, req).await;
+    let status = resp.status().as_u16();
+    let content_type = resp
+        .headers()
+        .get("content-type")
+        .map(|value| value.to_str().unwrap().to_string())
+        .unwrap_or_default();
+    let body = String::from_utf8(test::read_body(resp).await.to_vec()).unwrap();
+    (status, content_type, body)
+}
+
+/// Text of the first child of `node` called `name`
+fn child_text<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> &'a str {
+    node.children()
+        .find(|child| child.tag_name().name() == name)
+        .and_then(|child| child.text())
+        .unwrap_or_default()
+}
+
+async fn seed(db: &DatabaseRef) {
+    let mut warned = note(3, "<p>Spoilers</p>", Visibility::Public);
+    warned.summary = Some("Film ending".to_string());
+    let mut scheduled = note(5, "<p>Later</p>", Visibility::Public);
+    scheduled.state = PublishState::Scheduled;
+    for note in [
+        note(1, "<p>Fish &amp; chips &lt;3</p>", Visibility::Public),
+        note(2, "<p>Just followers</p>", Visibility::Followers),
+        warned,
+        note(4, "<p>Psst</p>", Visibility::Direct),
+        scheduled,
+    ] {
+        db.create_note(&note).await.unwrap();
+    }
+}
+
+#[actix_web::test]
+async fn test_atom_feed_lists_public_notes() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    seed(&db).await;
+
+    let (status, content_type, body) = get(&db, "/users/alice/feed.atom").await;
+    assert_eq!(status, 200);
+    assert_eq!(content_type, "application/atom+xml; charset=utf-8");
+
+    let doc = roxmltree::Document::parse(&body).unwrap();
+    let feed = doc.root_element();
+    assert_eq!(feed.tag_name().namespace(), Some(ATOM));
+    assert_eq!(child_text(feed, "title"), "Alice & Co (@alice)");
+    assert_eq!(child_text(feed, "subtitle"), "Tinkerer <3");
+    assert_eq!(child_text(feed, "updated"), at(3).to_rfc3339());
+
+    let entries: Vec<_> = feed
+        .children()
+        .filter(|node| node.tag_name().name() == "entry")
+        .collect();
+    assert_eq!(entries.len(), 2);
+
+    // Newest first, a content warning standing in for the title
+    assert_eq!(child_text(entries[0], "id"), "https://example.com/notes/3");
+    assert_eq!(child_text(entries[0], "title"), "Film ending");
+    assert_eq!(child_text(entries[0], "published"), at(3).to_rfc3339());
+
+    // The content's own entities survive as HTML for the reader to render
+    assert_eq!(child_text(entries[1], "title"), "Fish & chips <3");
+    assert_eq!(
+        child_text(entries[1], "content"),
+        "<p>Fish &amp; chips &lt;3</p>"
+    );
+    assert_eq!(child_text(entries[1], "updated"), at(1).to_rfc3339());
+}
+
+#[actix_web::test]
+async fn test_rss_feed_lists_public_notes() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    seed(&db).await;
+
+    let (status, content_type, body) = get(&db, "/users/alice/feed.rss").await;
+    assert_eq!(status, 200);
+    assert_eq!(content_type, "application/rss+xml; charset=utf-8");
+
+    let doc = roxmltree::Document::parse(&body).unwrap();
+    let channel = doc
+        .root_element()
+        .children()
+        .find(|node| node.tag_name().name() == "channel")
+        .unwrap();
+    assert_eq!(child_text(channel, "title"), "Alice & Co (@alice)");
+    assert_eq!(child_text(channel, "link"), ALICE);
+    assert_eq!(child_text(channel, "lastBuildDate"), at(3).to_rfc2822());
+
+    let items: Vec<_> = channel
+        .children()
+        .filter(|node| node.tag_name().name() == "item")
+        .collect();
+    assert_eq!(items.len(), 2);
+    assert_eq!(child_text(items[0], "guid"), "https://example.com/notes/3");
+    assert_eq!(child_text(items[1], "pubDate"), at(1).to_rfc2822());
+    assert_eq!(
+        child_text(items[1], "description"),
+        "<p>Fish &amp; chips &lt;3</p>"
+    );
+}
+
+#[actix_web::test]
+async fn test_feeds_without_notes() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+
+    let (status, _, body) = get(&db, "/users/alice/feed.atom").await;
+    assert_eq!(status, 200);
+    let doc = roxmltree::Document::parse(&body).unwrap();
+    let feed = doc.root_element();
+    assert!(!feed
+        .children()
+        .any(|node| node.tag_name().name() == "entry"));
+    // Still dated, by the profile
+    assert_eq!(child_text(feed, "updated"), at(0).to_rfc3339());
+
+    let (status, _, body) = get(&db, "/users/alice/feed.rss").await;
+    assert_eq!(status, 200);
+    let doc = roxmltree::Document::parse(&body).unwrap();
+    assert!(!doc.descendants().any(|node| node.has_tag_name("item")));
+}
+
+#[actix_web::test]
+async fn test_newer_private_notes_dont_crowd_out_public_ones() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    db.create_note(&note(1, "<p>Hello world</p>", Visibility::Public))
+        .await
+        .unwrap();
+    for n in 2..=30 {
+        db.create_note(&note(n, "<p>Just followers</p>", Visibility::Followers))
+            .await
+            .unwrap();
+    }
+
+    let (status, _, body) = get(&db, "/users/alice/feed.atom").await;
+    assert_eq!(status, 200);
+    let doc = roxmltree::Document::parse(&body).unwrap();
+    let ids: Vec<_> = doc
+        .root_element()
+        .children()
+        .filter(|node| node.tag_name().name() == "entry")
+        .map(|entry| child_text(entry, "id"))
+        .collect();
+    assert_eq!(ids, vec!["https://example.com/notes/1"]);
+}
+
+#[actix_web::test]
+async fn test_feeds_of_unknown_actors() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+
+    for uri in ["/users/nobody/feed.atom", "/users/nobody/feed.rss"] {
+        assert_eq!(get(&db, uri).await.0, 404, "{uri}");
+    }
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use chrono::Utc;
+use feder8::database::{Database, DbFollowRelation};
+use tempfile::TempDir;
+
+const ALICE: &str = "https://example.com/users/alice";
+const BOB: &str = "https://remote.example/users/bob";
+const CAROL: &str = "https://remote.example/users/carol";
+
+fn follow(n: u32, follower_id: &str, following_id: &str) -> DbFollowRelation {
+    DbFollowRelation {
+        id: format!("{follower_id}/follows/{n}"),
+        follower_id: follower_id.to_string(),
+        following_id: following_id.to_string(),
+        status: "pending".to_string(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    }
+}
+
+#[tokio::test]
+async fn test_follow_found_by_actor_pair() {
+    let dir = TempDir::new().unwrap();
+    let db = common::sqlite_database(&dir).await;
+    db.create_follow(&follow(1, BOB, ALICE)).await.unwrap();
+    db.create_follow(&follow(2, CAROL, ALICE)).await.unwrap();
+
+    let found = db.get_follow_by_actors(BOB, ALICE).await.unwrap().unwrap();
+    assert_eq!(found.id, format!("{BOB}/follows/1"));
+    assert_eq!(found.status, "pending");
+
+    // The pair is directed: bob following alice says nothing about alice following bob
+    assert!(db.get_follow_by_actors(ALICE, BOB).await.unwrap().is_none());
+    assert!(db
+        .get_follow_by_actors(BOB, "https://example.com/users/dave")
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_actor_pair_follows_at_most_once() {
+    let dir = TempDir::new().unwrap();
+    let db = common::sqlite_database(&dir).await;
+    db.create_follow(&follow(1, BOB, ALICE)).await.unwrap();
+
+    // A second Follow for the same pair under a new id is refused
+    assert!(db.create_follow(&follow(2, BOB, ALICE)).await.is_err());
+    let found = db.get_follow_by_actors(BOB, ALICE).await.unwrap().unwrap();
+    assert_eq!(found.id, format!("{BOB}/follows/1"));
+
+    // The lookup reflects status changes and deletion
+    db.update_follow_status(&found.id, "accepted")
+        .await
+        .unwrap();
+    let accepted = db.get_follow_by_actors(BOB, ALICE).await.unwrap().unwrap();
+    assert_eq!(accepted.status, "accepted");
+    db.delete_follow(&found.id).await.unwrap();
+    assert!(db.get_follow_by_actors(BOB, ALICE).await.unwrap().is_none());
+}
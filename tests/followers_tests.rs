@@ -0,0 +1,215 @@
+mod common;
+
+use actix_web::{test, App};
+use chrono::{Duration, Utc};
+use feder8::database::{Database, DatabaseRef, DbFollowRelation, DbRemoteActor};
+use feder8::handlers;
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ALICE: &str = "https://example.com/users/alice";
+const DAVE: &str = "https://example.com/users/dave";
+const BOB: &str = "https://remote.example/users/bob";
+const CAROL: &str = "https://other.example/users/carol";
+
+// Serves Carol's actor document and nothing else
+struct RemoteActorClient;
+
+#[async_trait::async_trait]
+impl HttpClient for RemoteActorClient {
+    async fn send(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        anyhow::ensure!(
+            request.url == CAROL,
+            "unexpected request to {}",
+            request.url
+        );
+        Ok(HttpResponse {
+            status: StatusCode(200),
+            headers: HashMap::new(),
+            body: serde_json::to_vec(&json!({
+                "id": CAROL,
+                "type": "Person",
+                "preferredUsername": "carol",
+                "name": "Carol",
+                "icon": {"type": "Image", "url": "https://other.example/carol.png"}
+            }))?,
+        })
+    }
+}
+
+/// Alice is followed by Dave (local), Bob (remote, cached) and Carol (remote,
+/// never fetched), in that order
+async fn create_test_database() -> (TempDir, DatabaseRef) {
+    let dir = TempDir::new().unwrap();
+    let db = common::sqlite_database(&dir).await;
+
+    db.create_actor(&common::actor(ALICE, "alice", "Alice"))
+        .await
+        .unwrap();
+    db.create_actor(&common::actor(DAVE, "dave", "Dave"))
+        .await
+        .unwrap();
+    db.upsert_remote_actor(&DbRemoteActor {
+        id: BOB.to_string(),
+        username: "bob".to_string(),
+        name: Some("Bob".to_string()),
+        avatar_url: Some("https://remote.example/bob.png".to_string()),
+        inbox: None,
+        shared_inbox: None,
+        public_key_pem: None,
+        fetched_at: Utc::now(),
+    })
+    .await
+    .unwrap();
+
+    let start = Utc::now() - Duration::minutes(10);
+    for (i, follower) in [DAVE, BOB, CAROL].into_iter().enumerate() {
+        let at = start + Duration::minutes(i as i64);
+        db.create_follow(&DbFollowRelation {
+            id: format!("follow-{i}"),
+            follower_id: follower.to_string(),
+            following_id: ALICE.to_string(),
+            status: "accepted".to_string(),
+            created_at: at,
+            updated_at: at,
+        })
+        .await
+        .unwrap();
+    }
+
+    (dir, Arc::new(db))
+}
+
+fn create_test_app(
+    db: DatabaseRef,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    common::test_app(&db, common::test_config(), Arc::new(RemoteActorClient))
+        .service(handlers::actor::get_actor)
+        .service(handlers::api::accounts::get_followers)
+}
+
+/// Wait for the background profile fetch to land in the cache
+async fn wait_for_cached(db: &DatabaseRef, id: &str) -> DbRemoteActor {
+    for _ in 0..50 {
+        if let Some(actor) = db.get_remote_actor(id).await.unwrap() {
+            return actor;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    panic!("{id} was never cached");
+}
+
+#[tokio::test]
+async fn test_followers_with_profiles_joins_local_and_cached_actors() {
+    let (_dir, db) = create_test_database().await;
+
+    let followers = db.get_followers_with_profiles(ALICE, 10, 0).await.unwrap();
+
+    let ids: Vec<&str> = followers.iter().map(|f| f.id.as_str()).collect();
+    assert_eq!(ids, vec![CAROL, BOB, DAVE]);
+
+    assert!(!followers[0].local);
+    assert_eq!(followers[0].username, None);
+    assert_eq!(followers[0].name, None);
+
+    assert!(!followers[1].local);
+    assert_eq!(followers[1].username.as_deref(), Some("bob"));
+    assert_eq!(
+        followers[1].avatar_url.as_deref(),
+        Some("https://remote.example/bob.png")
+    );
+
+    assert!(followers[2].local);
+    assert_eq!(followers[2].username.as_deref(), Some("dave"));
+    assert_eq!(followers[2].name.as_deref(), Some("Dave"));
+
+    let page = db.get_followers_with_profiles(ALICE, 1, 1).await.unwrap();
+    assert_eq!(page.len(), 1);
+    assert_eq!(page[0].id, BOB);
+}
+
+#[tokio::test]
+async fn test_followers_api_renders_accounts_and_fetches_uncached_profiles() {
+    let (_dir, db) = create_test_database().await;
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/accounts/alice/followers")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let accounts: Vec<Value> = test::read_body_json(resp).await;
+    assert_eq!(accounts.len(), 3);
+
+    // Carol is listed straight away, from her id alone
+    assert_eq!(accounts[0]["id"], CAROL);
+    assert_eq!(accounts[0]["acct"], "carol@other.example");
+    assert_eq!(accounts[0]["display_name"], "");
+
+    assert_eq!(accounts[1]["acct"], "bob@remote.example");
+    assert_eq!(accounts[1]["display_name"], "Bob");
+    assert_eq!(accounts[1]["avatar"], "https://remote.example/bob.png");
+
+    assert_eq!(accounts[2]["acct"], "dave");
+    assert_eq!(accounts[2]["display_name"], "Dave");
+
+    let carol = wait_for_cached(&db, CAROL).await;
+    assert_eq!(carol.username, "carol");
+    assert_eq!(carol.name.as_deref(), Some("Carol"));
+    assert_eq!(
+        carol.avatar_url.as_deref(),
+        Some("https://other.example/carol.png")
+    );
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/accounts/alice/followers?limit=1")
+        .to_request();
+    let accounts: Vec<Value> = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(accounts.len(), 1);
+    assert_eq!(accounts[0]["display_name"], "Carol");
+}
+
+#[tokio::test]
+async fn test_followers_api_unknown_account() {
+    let (_dir, db) = create_test_database().await;
+    let app = test::init_service(create_test_app(db)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/accounts/nobody/followers")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+}
+
+#[tokio::test]
+async fn test_html_profile_lists_followers() {
+    let (_dir, db) = create_test_database().await;
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    let req = test::TestRequest::get()
+        .uri("/users/alice")
+        .insert_header(("Accept", "text/html"))
+        .to_request();
+    let body = test::call_and_read_body(&app, req).await;
+    let html = std::str::from_utf8(&body).unwrap();
+
+    assert!(html.contains("<h2>Followers</h2>"));
+    assert!(html.contains("Dave @dave"));
+    assert!(html
+        .contains("<img src=\"https://remote.example/bob.png\" alt=\"\"> Bob @bob@remote.example"));
+    assert!(html.contains(&format!("<a href=\"{CAROL}\">@carol@other.example</a>")));
+
+    wait_for_cached(&db, CAROL).await;
+}
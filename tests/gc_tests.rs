@@ -0,0 +1,188 @@
+mod common;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use feder8::database::{
+    Database, DatabaseRef, DbActivity, DbActor, DbFollowRelation, PublishState,
+};
+use feder8::models::Visibility;
+use feder8::services::gc::GarbageCollector;
+use feder8::services::scheduler::TestClock;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ALICE: &str = "https://example.com/users/alice";
+const BOB: &str = "https://remote.example/users/bob";
+
+fn now() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap()
+}
+
+async fn create_test_database(dir: &TempDir) -> DatabaseRef {
+    let db = common::sqlite_database(dir).await;
+    db.create_actor(&DbActor {
+        created_at: now(),
+        updated_at: now(),
+        ..common::actor(ALICE, "alice", "Alice")
+    })
+    .await
+    .unwrap();
+    Arc::new(db)
+}
+
+/// An activity stored `days` days ago
+async fn store(
+    db: &DatabaseRef,
+    id: &str,
+    actor_id: &str,
+    activity_type: &str,
+    object: Value,
+    days: i64,
+) {
+    let stored = now() - Duration::days(days);
+    db.create_activity(&DbActivity {
+        id: id.to_string(),
+        actor_id: actor_id.to_string(),
+        activity_type: activity_type.to_string(),
+        object,
+        target: None,
+        to_recipients: vec![ALICE.to_string()],
+        cc_recipients: vec![],
+        published: stored,
+        visibility: Visibility::Direct,
+        state: PublishState::Published,
+        created_at: stored,
+    })
+    .await
+    .unwrap();
+}
+
+async fn exists(db: &DatabaseRef, id: &str) -> bool {
+    db.get_activity_by_id(id).await.unwrap().is_some()
+}
+
+fn collector() -> GarbageCollector {
+    GarbageCollector::new(Arc::new(TestClock::new(now())))
+}
+
+#[tokio::test]
+async fn test_activities_past_retention_are_deleted() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let note = json!({"type": "Note", "content": "Hi"});
+    store(
+        &db,
+        "https://remote.example/a/old",
+        BOB,
+        "Create",
+        note.clone(),
+        45,
+    )
+    .await;
+    store(
+        &db,
+        "https://remote.example/a/older",
+        BOB,
+        "Like",
+        json!("https://example.com/notes/1"),
+        200,
+    )
+    .await;
+    store(
+        &db,
+        "https://remote.example/a/recent",
+        BOB,
+        "Create",
+        note.clone(),
+        29,
+    )
+    .await;
+    store(
+        &db,
+        "https://remote.example/a/today",
+        BOB,
+        "Create",
+        note.clone(),
+        0,
+    )
+    .await;
+
+    assert_eq!(collector().run(&db, 30).await.unwrap(), 2);
+
+    assert!(!exists(&db, "https://remote.example/a/old").await);
+    assert!(!exists(&db, "https://remote.example/a/older").await);
+    assert!(exists(&db, "https://remote.example/a/recent").await);
+    assert!(exists(&db, "https://remote.example/a/today").await);
+
+    // Nothing left to collect
+    assert_eq!(collector().run(&db, 30).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_outboxes_and_follows_are_kept() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    store(
+        &db,
+        "https://example.com/a/post",
+        ALICE,
+        "Create",
+        json!({"type": "Note"}),
+        400,
+    )
+    .await;
+
+    // Bob followed Alice twice; only the latest Follow still matters
+    store(
+        &db,
+        "https://remote.example/a/follow-1",
+        BOB,
+        "Follow",
+        json!(ALICE),
+        300,
+    )
+    .await;
+    store(
+        &db,
+        "https://remote.example/a/follow-2",
+        BOB,
+        "Follow",
+        json!(ALICE),
+        200,
+    )
+    .await;
+    db.create_follow(&DbFollowRelation {
+        id: "https://remote.example/follows/bob".to_string(),
+        follower_id: BOB.to_string(),
+        following_id: ALICE.to_string(),
+        status: "accepted".to_string(),
+        created_at: now(),
+        updated_at: now(),
+    })
+    .await
+    .unwrap();
+
+    assert_eq!(collector().run(&db, 30).await.unwrap(), 1);
+
+    assert!(exists(&db, "https://example.com/a/post").await);
+    assert!(!exists(&db, "https://remote.example/a/follow-1").await);
+    assert!(exists(&db, "https://remote.example/a/follow-2").await);
+}
+
+#[tokio::test]
+async fn test_zero_retention_keeps_everything() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    store(
+        &db,
+        "https://remote.example/a/old",
+        BOB,
+        "Create",
+        json!({"type": "Note"}),
+        1000,
+    )
+    .await;
+
+    assert_eq!(collector().run(&db, 0).await.unwrap(), 0);
+    assert!(exists(&db, "https://remote.example/a/old").await);
+}
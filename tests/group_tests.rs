@@ -0,0 +1,251 @@
+mod common;
+
+use actix_web::{test, App};
+use chrono::Utc;
+use feder8::database::{Database, DatabaseRef, DbActor, DbFollowRelation, DbRemoteActor};
+use feder8::handlers;
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode};
+use feder8::models::addressing::PUBLIC;
+use feder8::services::groups::is_group_actor;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+const HIKERS: &str = "https://example.com/users/hikers";
+const ALICE: &str = "https://example.com/users/alice";
+const BOB: &str = "https://remote.example/users/bob";
+const CAROL: &str = "https://remote.example/users/carol";
+const BOB_INBOX: &str = "https://remote.example/users/bob/inbox";
+
+/// Accepts every delivery and records what was posted where
+#[derive(Default)]
+struct RecordingClient {
+    deliveries: Mutex<Vec<(String, Value)>>,
+}
+
+impl RecordingClient {
+    fn deliveries(&self) -> Vec<(String, Value)> {
+        self.deliveries.lock().unwrap().clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpClient for RecordingClient {
+    async fn send(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        if request.method == "POST" {
+            let body = serde_json::from_slice(request.body.as_deref().unwrap_or_default())?;
+            self.deliveries
+                .lock()
+                .unwrap()
+                .push((request.url.clone(), body));
+        }
+        Ok(HttpResponse {
+            status: StatusCode(202),
+            headers: HashMap::new(),
+            body: Vec::new(),
+        })
+    }
+}
+
+fn local_actor(username: &str, actor_type: &str) -> DbActor {
+    DbActor {
+        actor_type: actor_type.to_string(),
+        ..common::actor(
+            &format!("https://example.com/users/{username}"),
+            username,
+            username,
+        )
+    }
+}
+
+/// The hikers Group and Alice, a person, each followed by Bob
+async fn create_test_database(dir: &TempDir) -> DatabaseRef {
+    let db = common::sqlite_database(dir).await;
+    db.create_actor(&local_actor("hikers", "Group"))
+        .await
+        .unwrap();
+    db.create_actor(&local_actor("alice", "Person"))
+        .await
+        .unwrap();
+    for following in [HIKERS, ALICE] {
+        db.create_follow(&DbFollowRelation {
+            id: format!("{BOB}/follows/{}", following.rsplit('/').next().unwrap()),
+            follower_id: BOB.to_string(),
+            following_id: following.to_string(),
+            status: "accepted".to_string(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        })
+        .await
+        .unwrap();
+    }
+    db.upsert_remote_actor(&DbRemoteActor {
+        id: BOB.to_string(),
+        username: "bob".to_string(),
+        name: None,
+        avatar_url: None,
+        inbox: Some(BOB_INBOX.to_string()),
+        shared_inbox: None,
+        public_key_pem: None,
+        fetched_at: Utc::now(),
+    })
+    .await
+    .unwrap();
+    Arc::new(db)
+}
+
+fn create_test_app(
+    db: &DatabaseRef,
+    client: Arc<RecordingClient>,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    common::test_app(db, common::test_config(), client)
+        .service(handlers::inbox::inbox)
+        .service(handlers::actor::get_actor)
+}
+
+/// A Create from Carol of note `n`, addressed like `to`, mentioning `mentioned`
+fn create(n: u32, to: &str, mentioned: &[&str]) -> Value {
+    let tags: Vec<Value> = mentioned
+        .iter()
+        .map(|href| json!({"type": "Mention", "name": "@someone", "href": href}))
+        .collect();
+    let mut recipients = vec![to.to_string()];
+    recipients.extend(mentioned.iter().map(|href| href.to_string()));
+    json!({
+        "id": format!("{CAROL}/activities/{n}"),
+        "type": "Create",
+        "actor": CAROL,
+        "to": recipients,
+        "object": {
+            "id": format!("{CAROL}/notes/{n}"),
+            "type": "Note",
+            "attributedTo": CAROL,
+            "content": format!("note {n}"),
+            "to": recipients,
+            "tag": tags
+        }
+    })
+}
+
+async fn post_to_inbox(
+    db: &DatabaseRef,
+    client: Arc<RecordingClient>,
+    inbox: &str,
+    activity: Value,
+) {
+    let app = test::init_service(create_test_app(db, client)).await;
+    let req = test::TestRequest::post()
+        .uri(inbox)
+        .set_json(activity)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 202);
+}
+
+async fn announces(db: &DatabaseRef, actor_id: &str) -> Vec<String> {
+    db.get_activities_by_actor(actor_id, 20, 0)
+        .await
+        .unwrap()
+        .into_iter()
+        .filter(|activity| activity.activity_type == "Announce")
+        .map(|activity| activity.object.as_str().unwrap().to_string())
+        .collect()
+}
+
+#[actix_web::test]
+async fn test_group_boosts_notes_that_mention_it() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let client = Arc::new(RecordingClient::default());
+
+    post_to_inbox(
+        &db,
+        client.clone(),
+        "/users/hikers/inbox",
+        create(1, PUBLIC, &[HIKERS]),
+    )
+    .await;
+
+    let note_id = format!("{CAROL}/notes/1");
+    assert_eq!(announces(&db, HIKERS).await, vec![note_id.clone()]);
+
+    // Followers get the boost in the background
+    for _ in 0..100 {
+        if !client.deliveries().is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    let deliveries = client.deliveries();
+    assert_eq!(deliveries.len(), 1);
+    let (inbox, announce) = &deliveries[0];
+    assert_eq!(inbox, BOB_INBOX);
+    assert_eq!(announce["type"], "Announce");
+    assert_eq!(announce["actor"], HIKERS);
+    assert_eq!(announce["object"], note_id);
+    assert_eq!(announce["to"], json!([PUBLIC]));
+    assert_eq!(announce["cc"], json!([format!("{HIKERS}/followers")]));
+}
+
+#[actix_web::test]
+async fn test_group_only_boosts_public_mentions() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let client = Arc::new(RecordingClient::default());
+
+    // Delivered to the group without mentioning it, and a direct mention
+    for activity in [create(1, PUBLIC, &[]), create(2, HIKERS, &[HIKERS])] {
+        post_to_inbox(&db, client.clone(), "/users/hikers/inbox", activity).await;
+    }
+
+    assert!(announces(&db, HIKERS).await.is_empty());
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert!(client.deliveries().is_empty());
+}
+
+#[actix_web::test]
+async fn test_people_do_not_boost_mentions() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let client = Arc::new(RecordingClient::default());
+
+    post_to_inbox(
+        &db,
+        client.clone(),
+        "/users/alice/inbox",
+        create(1, PUBLIC, &[ALICE]),
+    )
+    .await;
+
+    assert!(announces(&db, ALICE).await.is_empty());
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    assert!(client.deliveries().is_empty());
+}
+
+#[actix_web::test]
+async fn test_group_actor_document() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let group = db.get_actor_by_id(HIKERS).await.unwrap().unwrap();
+    assert!(is_group_actor(&group));
+    let alice = db.get_actor_by_id(ALICE).await.unwrap().unwrap();
+    assert!(!is_group_actor(&alice));
+
+    let app = test::init_service(create_test_app(&db, Arc::new(RecordingClient::default()))).await;
+    for (uri, actor_type) in [("/users/hikers", "Group"), ("/users/alice", "Person")] {
+        let req = test::TestRequest::get()
+            .uri(uri)
+            .insert_header(("Accept", "application/activity+json"))
+            .to_request();
+        let actor: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(actor["type"], actor_type, "{uri}");
+    }
+}
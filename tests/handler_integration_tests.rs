@@ -1,13 +1,194 @@
+//! Handlers driven over HTTP. Most tests are scenarios run once per real
+//! backend by `backend_matrix!`, so what they assert is what storage does.
+//! Tests that inject database errors or pin the exact queries a handler
+//! makes stay on `MockDatabase`.
+
+mod common;
+
 use actix_web::{test, web, App};
-use chrono::Utc;
+use chrono::{DateTime, Duration, SubsecRound, TimeZone, Utc};
+use feder8::auth::hash_token;
 use feder8::config::Config;
-use feder8::database::{DatabaseRef, DbActivity, DbActor, MockDatabase};
+use feder8::database::{
+    DatabaseRef, DbActivity, DbActor, DbDelivery, DbFollowRelation, DbNote, DbToken,
+    DeliveryPriority, MockDatabase, PublishState, StrictMockDatabase,
+};
 use feder8::handlers;
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode};
+use feder8::http::json_config;
+use feder8::models::Visibility;
+use feder8::services::delivery::DeliveryService;
+use feder8::services::delivery_queue;
+use feder8::services::seen_activities::SeenActivities;
+use feder8::urls::UrlBuilder;
 use mockall::predicate::*;
-use serde_json::json;
-use std::sync::Arc;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+// HTTP client serving canned documents and recording what gets POSTed
+#[derive(Default)]
+struct MockHttpClient {
+    documents: HashMap<String, Value>,
+    posted: Mutex<Vec<(String, Value)>>,
+}
+
+impl MockHttpClient {
+    fn with_documents(documents: Vec<Value>) -> Self {
+        Self {
+            documents: documents
+                .into_iter()
+                .map(|doc| (doc["id"].as_str().unwrap().to_string(), doc))
+                .collect(),
+            posted: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn posted(&self) -> Vec<(String, Value)> {
+        self.posted.lock().unwrap().clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpClient for MockHttpClient {
+    async fn send(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        if request.method == "POST" {
+            let body = serde_json::from_slice(request.body.as_deref().unwrap_or_default())?;
+            self.posted.lock().unwrap().push((request.url, body));
+            return Ok(HttpResponse {
+                status: StatusCode(202),
+                headers: HashMap::new(),
+                body: vec![],
+            });
+        }
+
+        Ok(match self.documents.get(&request.url) {
+            Some(doc) => HttpResponse {
+                status: StatusCode(200),
+                headers: HashMap::new(),
+                body: serde_json::to_vec(doc)?,
+            },
+            None => HttpResponse {
+                status: StatusCode(404),
+                headers: HashMap::new(),
+                body: vec![],
+            },
+        })
+    }
+}
+
+/// Local actors testuser, alice and bob, each with a read and write token
+/// `token-{username}`
+async fn seed(db: &DatabaseRef) {
+    for (username, name) in [
+        ("testuser", "Test User"),
+        ("alice", "Alice"),
+        ("bob", "Bob"),
+    ] {
+        let id = format!("https://example.com/users/{username}");
+        db.create_actor(&common::actor(&id, username, name))
+            .await
+            .unwrap();
+        db.create_token(&DbToken {
+            id: format!("token-id-{username}"),
+            token_hash: hash_token(&format!("token-{username}")),
+            actor_id: id,
+            scopes: vec!["read".to_string(), "write".to_string()],
+            created_at: Utc::now(),
+            expires_at: None,
+        })
+        .await
+        .unwrap();
+    }
+}
+
+common::backend_matrix!(
+    get_actor_handler_success,
+    get_actor_handler_not_found,
+    get_actor_handler_html_profile,
+    get_actor_handler_json_for_activitypub_clients,
+    get_note_html_and_json_from_the_same_route,
+    get_note_hides_private_and_missing_notes,
+    get_actor_conditional_requests,
+    get_outbox_conditional_requests,
+    get_outbox_handler_success,
+    get_outbox_embeds_first_page,
+    get_outbox_serves_later_pages,
+    get_outbox_legacy_shape,
+    get_outbox_handler_actor_not_found,
+    get_outbox_inlines_announced_notes,
+    post_outbox_handler_create_note,
+    post_outbox_handler_actor_not_found,
+    post_outbox_reply_to_local_note,
+    post_outbox_reply_to_remote_note,
+    post_outbox_reply_to_nonexistent_note,
+    post_outbox_mention_remote_actor,
+    post_outbox_mention_local_actor,
+    post_outbox_note_and_activity_share_published,
+    post_outbox_attachments_round_trip,
+    post_outbox_rejects_disallowed_attachment,
+    inbox_create_keeps_allowed_attachments,
+    inbox_handler_create_note,
+    inbox_handler_stores_redelivered_activity_once,
+    inbox_handler_repeated_follow,
+    inbox_handler_follow_activity,
+    inbox_handler_accept_activity,
+    inbox_handler_accept_from_third_party,
+    inbox_handler_actor_not_found,
+    complete_activity_flow,
+    post_outbox_follow_remote_actor,
+    post_outbox_follow_acct_handle,
+    post_outbox_follow_unknown_acct_handle,
+    post_outbox_follow_invalid_object,
+    post_outbox_follow_already_following,
+    post_outbox_undo_follow,
+    post_outbox_undo_follow_not_following,
+);
+
+/// Accept `token-{username}` as a bearer token of that local actor
+fn accept_test_tokens(mock: &mut MockDatabase) {
+    mock.expect_validate_token().returning(|token| {
+        Ok(token.strip_prefix("token-").map(|username| DbToken {
+            id: format!("token-id-{username}"),
+            token_hash: String::new(),
+            actor_id: format!("https://example.com/users/{username}"),
+            scopes: vec!["read".to_string(), "write".to_string()],
+            created_at: Utc::now(),
+            expires_at: None,
+        }))
+    });
+}
+
+/// An empty delivery queue, so the inbox has no reason to push back
+fn idle_delivery_queue(mock: &mut MockDatabase) {
+    mock.expect_count_pending_deliveries().returning(|| Ok(0));
+}
+
+/// Every queued delivery, of any priority
+async fn queued(db: &DatabaseRef) -> Vec<DbDelivery> {
+    let mut queued = Vec::new();
+    for priority in [
+        DeliveryPriority::Interactive,
+        DeliveryPriority::Direct,
+        DeliveryPriority::Broadcast,
+    ] {
+        queued.extend(
+            db.get_due_deliveries(Utc::now() + Duration::hours(1), priority, 100)
+                .await
+                .unwrap(),
+        );
+    }
+    queued
+}
+
+/// A POST to `username`'s outbox, authorized as them
+fn outbox_post(username: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri(&format!("/users/{username}/outbox"))
+        .insert_header(("Authorization", format!("Bearer token-{username}")))
+}
 
-// Helper function to create a test app with mock database
+// Helper function to create a test app over a database
 fn create_test_app(
     db: DatabaseRef,
 ) -> App<
@@ -19,40 +200,32 @@ fn create_test_app(
         InitError = (),
     >,
 > {
-    let config = Config {
-        server_url: "https://example.com".to_string(),
-        ..Config::default()
-    };
-    App::new()
-        .app_data(web::Data::new(config))
-        .app_data(web::Data::new(db))
+    create_test_app_with_client(db, Arc::new(MockHttpClient::default()))
+}
+
+fn create_test_app_with_client(
+    db: DatabaseRef,
+    http_client: Arc<dyn HttpClient>,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    common::test_app(&db, common::test_config(), http_client)
+        .app_data(json_config())
         .service(handlers::actor::get_actor)
+        .service(handlers::note::get_note)
         .service(handlers::outbox::get_outbox)
         .service(handlers::outbox::post_outbox)
         .service(handlers::inbox::inbox)
 }
 
-#[tokio::test]
-async fn test_get_actor_handler_success() {
-    let mut mock = MockDatabase::new();
-
-    mock.expect_get_actor_by_username()
-        .with(eq("testuser"))
-        .returning(|_| {
-            Ok(Some(DbActor {
-                id: "https://example.com/users/testuser".to_string(),
-                username: "testuser".to_string(),
-                name: "Test User".to_string(),
-                summary: Some("A test user".to_string()),
-                public_key_pem: "-----BEGIN PUBLIC KEY-----\nMIIBIjANBgkqhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEA...\n-----END PUBLIC KEY-----".to_string(),
-                private_key_pem: None,
-                created_at: Utc::now(),
-                updated_at: Utc::now(),
-            }))
-        });
-
-    let db: DatabaseRef = Arc::new(mock);
-    let app = test::init_service(create_test_app(db)).await;
+async fn get_actor_handler_success(db: &DatabaseRef) {
+    let app = test::init_service(create_test_app(db.clone())).await;
 
     let req = test::TestRequest::get()
         .uri("/users/testuser")
@@ -68,16 +241,8 @@ async fn test_get_actor_handler_success() {
     assert_eq!(body["name"], "Test User");
 }
 
-#[tokio::test]
-async fn test_get_actor_handler_not_found() {
-    let mut mock = MockDatabase::new();
-
-    mock.expect_get_actor_by_username()
-        .with(eq("nonexistent"))
-        .returning(|_| Ok(None));
-
-    let db: DatabaseRef = Arc::new(mock);
-    let app = test::init_service(create_test_app(db)).await;
+async fn get_actor_handler_not_found(db: &DatabaseRef) {
+    let app = test::init_service(create_test_app(db.clone())).await;
 
     let req = test::TestRequest::get()
         .uri("/users/nonexistent")
@@ -118,52 +283,285 @@ async fn test_get_actor_handler_database_error() {
     assert_eq!(body["error"], "Internal server error");
 }
 
-#[tokio::test]
-async fn test_get_outbox_handler_success() {
-    let mut mock = MockDatabase::new();
+/// testuser's profile, with one note of each visibility and a scheduled one
+async fn seed_profile(db: &DatabaseRef) {
+    db.update_actor(&DbActor {
+        summary: Some("Posting about Rust & ActivityPub".to_string()),
+        ..common::actor(
+            "https://example.com/users/testuser",
+            "testuser",
+            "Test <User>",
+        )
+    })
+    .await
+    .unwrap();
+
+    for (name, visibility, state) in [
+        ("public", Visibility::Public, PublishState::Published),
+        ("unlisted", Visibility::Unlisted, PublishState::Published),
+        ("direct", Visibility::Direct, PublishState::Published),
+        ("scheduled", Visibility::Public, PublishState::Scheduled),
+    ] {
+        db.create_note(&profile_note(name, visibility, state))
+            .await
+            .unwrap();
+    }
+}
 
-    let actor_id = "https://example.com/users/testuser".to_string();
-    let activity_id = "https://example.com/activities/1".to_string();
-    let actor_id_clone1 = actor_id.clone();
-    let actor_id_clone2 = actor_id.clone();
-    let activity_id_clone = activity_id.clone();
+/// A public note published on 2024-05-01
+fn note(id: &str, attributed_to: &str, content: &str) -> DbNote {
+    DbNote {
+        id: id.to_string(),
+        attributed_to: attributed_to.to_string(),
+        content: content.to_string(),
+        to_recipients: vec!["https://www.w3.org/ns/activitystreams#Public".to_string()],
+        cc_recipients: vec![],
+        published: Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap(),
+        in_reply_to: None,
+        tags: vec![],
+        attachments: vec![],
+        visibility: Visibility::Public,
+        state: PublishState::Published,
+        pinned: false,
+        created_at: Utc::now(),
+        in_reply_to_actor: None,
+        thread_depth: Some(0),
+        thread_truncated: false,
+        sensitive: false,
+        summary: None,
+        language: None,
+        content_map: None,
+    }
+}
 
-    mock.expect_get_actor_by_username()
-        .with(eq("testuser"))
-        .returning(move |_| {
-            Ok(Some(DbActor {
-                id: actor_id_clone1.clone(),
-                username: "testuser".to_string(),
-                name: "Test User".to_string(),
-                summary: None,
-                public_key_pem: "test_key".to_string(),
-                private_key_pem: None,
-                created_at: Utc::now(),
-                updated_at: Utc::now(),
-            }))
-        });
+/// A note by testuser whose content is `<em>{name}</em>`
+fn profile_note(name: &str, visibility: Visibility, state: PublishState) -> DbNote {
+    DbNote {
+        visibility,
+        state,
+        summary: Some("cw".to_string()),
+        ..note(
+            &format!("https://example.com/notes/{name}"),
+            "https://example.com/users/testuser",
+            &format!("<em>{name}</em>"),
+        )
+    }
+}
 
-    mock.expect_get_actor_outbox_count()
-        .with(eq(actor_id.clone()))
-        .returning(|_| Ok(2));
-
-    mock.expect_get_activities_by_actor()
-        .with(eq(actor_id.clone()), eq(20), eq(0))
-        .returning(move |_, _, _| {
-            Ok(vec![DbActivity {
-                id: activity_id_clone.clone(),
-                actor_id: actor_id_clone2.clone(),
-                activity_type: "Create".to_string(),
-                object: json!({"type": "Note", "content": "Hello, world!"}),
-                to_recipients: vec!["https://www.w3.org/ns/activitystreams#Public".to_string()],
-                cc_recipients: vec![],
-                published: Utc::now(),
-                created_at: Utc::now(),
-            }])
-        });
+async fn get_actor_handler_html_profile(db: &DatabaseRef) {
+    seed_profile(db).await;
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    for accept in [
+        None,
+        Some("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"),
+    ] {
+        let mut req = test::TestRequest::get().uri("/users/testuser");
+        if let Some(accept) = accept {
+            req = req.insert_header(("Accept", accept));
+        }
 
-    let db: DatabaseRef = Arc::new(mock);
-    let app = test::init_service(create_test_app(db)).await;
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(
+            resp.headers().get("Content-Type").unwrap(),
+            "text/html; charset=utf-8"
+        );
+
+        let body = test::read_body(resp).await;
+        let html = std::str::from_utf8(&body).unwrap();
+        assert!(html.contains("<h1>Test &lt;User&gt;</h1>"));
+        assert!(html.contains("@testuser"));
+        assert!(html.contains("Posting about Rust &amp; ActivityPub"));
+        assert!(html.contains("href=\"https://example.com/users/testuser/outbox\""));
+        // Only published public notes are listed, escaped
+        assert!(html.contains("&lt;em&gt;public&lt;/em&gt;"));
+        assert!(html.contains("<p class=\"summary\">cw</p>"));
+        assert!(!html.contains("unlisted"));
+        assert!(!html.contains("direct"));
+        assert!(!html.contains("scheduled"));
+    }
+}
+
+async fn get_actor_handler_json_for_activitypub_clients(db: &DatabaseRef) {
+    seed_profile(db).await;
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    let ld_json = "application/ld+json; profile=\"https://www.w3.org/ns/activitystreams\"";
+    for (accept, expected) in [
+        ("application/activity+json", "application/activity+json"),
+        (ld_json, ld_json),
+        (
+            "application/activity+json, text/html;q=0.1",
+            "application/activity+json",
+        ),
+    ] {
+        let req = test::TestRequest::get()
+            .uri("/users/testuser")
+            .insert_header(("Accept", accept))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 200);
+        assert_eq!(resp.headers().get("Content-Type").unwrap(), expected);
+
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["preferredUsername"], "testuser");
+    }
+}
+
+async fn get_note_html_and_json_from_the_same_route(db: &DatabaseRef) {
+    seed_profile(db).await;
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    let req = test::TestRequest::get()
+        .uri("/notes/public")
+        .insert_header(("Accept", "text/html"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers().get("Content-Type").unwrap(),
+        "text/html; charset=utf-8"
+    );
+    assert_eq!(resp.headers().get("Vary").unwrap(), "Accept");
+    let body = test::read_body(resp).await;
+    let html = std::str::from_utf8(&body).unwrap();
+    assert!(html.contains("<title>Test &lt;User&gt; (@testuser)</title>"));
+    assert!(html.contains("&lt;em&gt;public&lt;/em&gt;"));
+    assert!(html.contains(
+        "<link rel=\"alternate\" type=\"application/activity+json\" href=\"https://example.com/notes/public\">"
+    ));
+
+    let req = test::TestRequest::get()
+        .uri("/notes/public")
+        .insert_header(("Accept", "application/activity+json"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers().get("Content-Type").unwrap(),
+        "application/activity+json"
+    );
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["@context"][0], "https://www.w3.org/ns/activitystreams");
+    assert_eq!(body["id"], "https://example.com/notes/public");
+    assert_eq!(body["type"], "Note");
+    assert_eq!(body["content"], "<em>public</em>");
+    assert_eq!(body["replies"], "https://example.com/notes/public/replies");
+}
+
+async fn get_note_hides_private_and_missing_notes(db: &DatabaseRef) {
+    seed_profile(db).await;
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    for uri in ["/notes/direct", "/notes/missing"] {
+        for accept in ["text/html", "application/activity+json"] {
+            let req = test::TestRequest::get()
+                .uri(uri)
+                .insert_header(("Accept", accept))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), 404, "{uri} as {accept}");
+        }
+    }
+}
+
+async fn get_actor_conditional_requests(db: &DatabaseRef) {
+    // Updates stamp the database clock, so this actor is created already dated
+    let updated_at = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+    db.create_actor(&DbActor {
+        created_at: updated_at,
+        updated_at,
+        ..common::actor("https://example.com/users/dated", "dated", "Dated")
+    })
+    .await
+    .unwrap();
+    let app = test::init_service(create_test_app(db.clone())).await;
+    let get = || {
+        test::TestRequest::get()
+            .uri("/users/dated")
+            .insert_header(("Accept", "application/activity+json"))
+    };
+
+    let resp = test::call_service(&app, get().to_request()).await;
+    assert_eq!(resp.status(), 200);
+    let etag = resp.headers().get("etag").unwrap().clone();
+    let last_modified = resp.headers().get("last-modified").unwrap().clone();
+    assert_eq!(last_modified, "Wed, 01 May 2024 12:00:00 GMT");
+
+    let req = get()
+        .insert_header(("If-None-Match", etag.clone()))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 304);
+    assert_eq!(resp.headers().get("etag").unwrap(), &etag);
+    assert!(test::read_body(resp).await.is_empty());
+
+    let req = get()
+        .insert_header(("If-Modified-Since", last_modified))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 304);
+
+    // A stale validator gets the full document
+    let req = get()
+        .insert_header(("If-None-Match", "\"stale\""))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+}
+
+/// A public Create of a plain note by testuser
+fn outbox_activity(n: i64, published: DateTime<Utc>) -> DbActivity {
+    DbActivity {
+        id: format!("https://example.com/activities/{n}"),
+        actor_id: "https://example.com/users/testuser".to_string(),
+        activity_type: "Create".to_string(),
+        object: json!({"type": "Note", "content": "Hello"}),
+        target: None,
+        to_recipients: vec!["https://www.w3.org/ns/activitystreams#Public".to_string()],
+        cc_recipients: vec![],
+        published,
+        visibility: Visibility::Public,
+        state: PublishState::Published,
+        created_at: published,
+    }
+}
+
+async fn get_outbox_conditional_requests(db: &DatabaseRef) {
+    let published = Utc.with_ymd_and_hms(2024, 5, 1, 12, 0, 0).unwrap();
+    db.create_activity(&outbox_activity(1, published))
+        .await
+        .unwrap();
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    let req = test::TestRequest::get()
+        .uri("/users/testuser/outbox")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers().get("last-modified").unwrap(),
+        "Wed, 01 May 2024 12:00:00 GMT"
+    );
+    let etag = resp.headers().get("etag").unwrap().clone();
+
+    let req = test::TestRequest::get()
+        .uri("/users/testuser/outbox")
+        .insert_header(("If-None-Match", etag))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 304);
+}
+
+async fn get_outbox_handler_success(db: &DatabaseRef) {
+    for n in 1..=2 {
+        db.create_activity(&outbox_activity(n, Utc::now()))
+            .await
+            .unwrap();
+    }
+    let app = test::init_service(create_test_app(db.clone())).await;
 
     let req = test::TestRequest::get()
         .uri("/users/testuser/outbox")
@@ -176,20 +574,113 @@ async fn test_get_outbox_handler_success() {
     let body: serde_json::Value = test::read_body_json(resp).await;
     assert_eq!(body["type"], "OrderedCollection");
     assert_eq!(body["totalItems"], 2);
-    assert_eq!(body["orderedItems"].as_array().unwrap().len(), 1);
-    assert_eq!(body["orderedItems"][0]["type"], "Create");
+    assert_eq!(body["first"]["orderedItems"].as_array().unwrap().len(), 2);
+    assert_eq!(body["first"]["orderedItems"][0]["type"], "Create");
 }
 
-#[tokio::test]
-async fn test_get_outbox_handler_actor_not_found() {
-    let mut mock = MockDatabase::new();
+// Outbox of 25 activities, a minute apart from 2024-01-01, newest last
+async fn seed_outbox_snapshot(db: &DatabaseRef) {
+    let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+    for n in 0..25 {
+        db.create_activity(&outbox_activity(n, start + Duration::minutes(n)))
+            .await
+            .unwrap();
+    }
+}
 
-    mock.expect_get_actor_by_username()
-        .with(eq("nonexistent"))
-        .returning(|_| Ok(None));
+async fn get_outbox_snapshot(db: &DatabaseRef, outbox_legacy_shape: bool, uri: &str) -> Value {
+    seed_outbox_snapshot(db).await;
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(Config {
+                server_url: "https://example.com".to_string(),
+                outbox_legacy_shape,
+                ..Config::default()
+            }))
+            .app_data(web::Data::new(UrlBuilder::new("https://example.com", None)))
+            .app_data(web::Data::new(db.clone()))
+            .service(handlers::outbox::get_outbox),
+    )
+    .await;
+    let req = test::TestRequest::get().uri(uri).to_request();
+    test::call_and_read_body_json(&app, req).await
+}
 
-    let db: DatabaseRef = Arc::new(mock);
-    let app = test::init_service(create_test_app(db)).await;
+/// The snapshot activities `from..to`, newest first as the outbox lists them
+fn snapshot_activities(from: i64, to: i64) -> Vec<Value> {
+    (from..to)
+        .rev()
+        .map(|n| {
+            json!({
+                "id": format!("https://example.com/activities/{n}"),
+                "type": "Create",
+                "actor": "https://example.com/users/testuser",
+                "object": {"type": "Note", "content": "Hello"},
+                "to": ["https://www.w3.org/ns/activitystreams#Public"],
+                "cc": [],
+                "published": format!("2024-01-01T00:{n:02}:00Z")
+            })
+        })
+        .collect()
+}
+
+async fn get_outbox_embeds_first_page(db: &DatabaseRef) {
+    let body = get_outbox_snapshot(db, false, "/users/testuser/outbox").await;
+
+    assert_eq!(
+        body,
+        json!({
+            "@context": ["https://www.w3.org/ns/activitystreams"],
+            "id": "https://example.com/users/testuser/outbox",
+            "type": "OrderedCollection",
+            "totalItems": 25,
+            "first": {
+                "id": "https://example.com/users/testuser/outbox?page=true",
+                "type": "OrderedCollectionPage",
+                "partOf": "https://example.com/users/testuser/outbox",
+                "next": "https://example.com/users/testuser/outbox?page=true&max_id=https%3A%2F%2Fexample.com%2Factivities%2F5",
+                "orderedItems": snapshot_activities(5, 25)
+            },
+            "last": "https://example.com/users/testuser/outbox?page=true&offset=20"
+        })
+    );
+}
+
+async fn get_outbox_serves_later_pages(db: &DatabaseRef) {
+    let body = get_outbox_snapshot(db, false, "/users/testuser/outbox?page=true&offset=20").await;
+
+    assert_eq!(
+        body,
+        json!({
+            "@context": ["https://www.w3.org/ns/activitystreams"],
+            "id": "https://example.com/users/testuser/outbox?page=true&offset=20",
+            "type": "OrderedCollectionPage",
+            "partOf": "https://example.com/users/testuser/outbox",
+            "prev": "https://example.com/users/testuser/outbox?page=true",
+            "orderedItems": snapshot_activities(0, 5)
+        })
+    );
+}
+
+async fn get_outbox_legacy_shape(db: &DatabaseRef) {
+    let body = get_outbox_snapshot(db, true, "/users/testuser/outbox").await;
+
+    assert_eq!(
+        body,
+        json!({
+            "@context": ["https://www.w3.org/ns/activitystreams"],
+            "id": "https://example.com/users/testuser/outbox",
+            "type": "OrderedCollection",
+            "totalItems": 25,
+            "first": "https://example.com/users/testuser/outbox?page=true",
+            "last": "https://example.com/users/testuser/outbox?page=true",
+            "orderedItems": snapshot_activities(5, 25)
+        })
+    );
+}
+
+async fn get_outbox_handler_actor_not_found(db: &DatabaseRef) {
+    let app = test::init_service(create_test_app(db.clone())).await;
 
     let req = test::TestRequest::get()
         .uri("/users/nonexistent/outbox")
@@ -203,33 +694,58 @@ async fn test_get_outbox_handler_actor_not_found() {
     assert_eq!(body["error"], "Actor not found");
 }
 
-#[tokio::test]
-async fn test_post_outbox_handler_create_note() {
-    let mut mock = MockDatabase::new();
+async fn get_outbox_inlines_announced_notes(db: &DatabaseRef) {
+    let local_note = "https://example.com/notes/local";
+    let remote_note = "https://remote.example/notes/unknown";
+
+    db.create_note(&note(
+        local_note,
+        "https://example.com/users/bob",
+        "Boosted!",
+    ))
+    .await
+    .unwrap();
+
+    let now = Utc::now();
+    let announce = |n: i64, object: &str| DbActivity {
+        activity_type: "Announce".to_string(),
+        object: json!(object),
+        // Listed newest first, in the order they are numbered
+        ..outbox_activity(n, now - Duration::minutes(n))
+    };
+    for activity in [
+        announce(1, local_note),
+        announce(2, remote_note),
+        outbox_activity(3, now - Duration::minutes(3)),
+    ] {
+        db.create_activity(&activity).await.unwrap();
+    }
 
-    let actor_id = "https://example.com/users/testuser".to_string();
+    let app = test::init_service(create_test_app(db.clone())).await;
 
-    mock.expect_get_actor_by_username()
-        .with(eq("testuser"))
-        .returning(move |_| {
-            Ok(Some(DbActor {
-                id: actor_id.clone(),
-                username: "testuser".to_string(),
-                name: "Test User".to_string(),
-                summary: None,
-                public_key_pem: "test_key".to_string(),
-                private_key_pem: None,
-                created_at: Utc::now(),
-                updated_at: Utc::now(),
-            }))
-        });
+    let req = test::TestRequest::get()
+        .uri("/users/testuser/outbox")
+        .insert_header(("Accept", "application/activity+json"))
+        .to_request();
 
-    mock.expect_create_note().returning(|_| Ok(()));
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
 
-    mock.expect_create_activity().returning(|_| Ok(()));
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    let items = body["first"]["orderedItems"].as_array().unwrap();
+    assert_eq!(items[0]["object"]["id"], local_note);
+    assert_eq!(items[0]["object"]["type"], "Note");
+    assert_eq!(items[0]["object"]["content"], "Boosted!");
+    assert_eq!(
+        items[0]["object"]["attributedTo"],
+        "https://example.com/users/bob"
+    );
+    assert_eq!(items[1]["object"], remote_note);
+    assert_eq!(items[2]["object"]["content"], "Hello");
+}
 
-    let db: DatabaseRef = Arc::new(mock);
-    let app = test::init_service(create_test_app(db)).await;
+async fn post_outbox_handler_create_note(db: &DatabaseRef) {
+    let app = test::init_service(create_test_app(db.clone())).await;
 
     let create_activity = json!({
         "type": "Create",
@@ -243,14 +759,18 @@ async fn test_post_outbox_handler_create_note() {
         "cc": []
     });
 
-    let req = test::TestRequest::post()
-        .uri("/users/testuser/outbox")
+    let req = outbox_post("testuser")
         .insert_header(("Content-Type", "application/activity+json"))
         .set_json(&create_activity)
         .to_request();
 
     let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), 201);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "application/activity+json"
+    );
+    let location = resp.headers().get("location").unwrap().clone();
 
     let body: serde_json::Value = test::read_body_json(resp).await;
     assert_eq!(body["type"], "Create");
@@ -259,121 +779,762 @@ async fn test_post_outbox_handler_create_note() {
         .as_str()
         .unwrap()
         .starts_with("https://example.com/activities/"));
+    assert_eq!(location, body["id"].as_str().unwrap());
+    assert_eq!(
+        body["@context"],
+        json!(["https://www.w3.org/ns/activitystreams"])
+    );
+
+    let activity = db
+        .get_activity_by_id(body["id"].as_str().unwrap())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(activity.activity_type, "Create");
+    let note = db
+        .get_note_by_id(body["object"]["id"].as_str().unwrap())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(note.attributed_to, "https://example.com/users/testuser");
+    assert_eq!(body["object"]["content"], note.content);
+}
+
+async fn post_outbox_handler_actor_not_found(db: &DatabaseRef) {
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    let create_activity = json!({
+        "type": "Create",
+        "object": {
+            "type": "Note",
+            "content": "Hello!"
+        }
+    });
+
+    // Nobody holds a token for a missing actor, so this one is testuser's
+    let req = outbox_post("testuser")
+        .uri("/users/nonexistent/outbox")
+        .insert_header(("Content-Type", "application/activity+json"))
+        .set_json(&create_activity)
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Actor not found");
+}
+
+fn create_reply(in_reply_to: &str) -> Value {
+    json!({
+        "type": "Create",
+        "object": {
+            "type": "Note",
+            "content": "I agree!",
+            "inReplyTo": in_reply_to
+        },
+        "to": ["https://www.w3.org/ns/activitystreams#Public"],
+        "cc": ["https://example.com/users/testuser/followers"]
+    })
+}
+
+/// The stored note `body` created
+async fn created_note(db: &DatabaseRef, body: &Value) -> DbNote {
+    db.get_note_by_id(body["object"]["id"].as_str().unwrap())
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+async fn post_outbox_reply_to_local_note(db: &DatabaseRef) {
+    let parent_id = "https://example.com/notes/parent";
+    let parent_author = "https://example.com/users/bob";
+    db.create_note(&note(parent_id, parent_author, "Hot take"))
+        .await
+        .unwrap();
+
+    let client = Arc::new(MockHttpClient::default());
+    let app = test::init_service(create_test_app_with_client(db.clone(), client.clone())).await;
+
+    let req = outbox_post("testuser")
+        .set_json(create_reply(parent_id))
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert!(body["cc"]
+        .as_array()
+        .unwrap()
+        .contains(&json!(parent_author)));
+
+    let reply = created_note(db, &body).await;
+    assert_eq!(reply.in_reply_to.as_deref(), Some(parent_id));
+    assert!(reply.cc_recipients.contains(&parent_author.to_string()));
+    assert_eq!(reply.in_reply_to_actor.as_deref(), Some(parent_author));
+    assert_eq!(reply.thread_depth, Some(1));
+    // Local authors don't need a network delivery
+    assert!(queued(db).await.is_empty());
+    assert!(client.posted().is_empty());
+}
+
+async fn post_outbox_reply_to_remote_note(db: &DatabaseRef) {
+    let parent_id = "https://remote.example/notes/1";
+    let parent_author = "https://remote.example/users/carol";
+
+    let client = Arc::new(MockHttpClient::with_documents(vec![
+        json!({
+            "id": parent_id,
+            "type": "Note",
+            "attributedTo": parent_author,
+            "content": "Hot take"
+        }),
+        json!({
+            "id": parent_author,
+            "type": "Person",
+            "preferredUsername": "carol",
+            "inbox": format!("{parent_author}/inbox")
+        }),
+    ]));
+    let app = test::init_service(create_test_app_with_client(db.clone(), client.clone())).await;
+
+    let req = outbox_post("testuser")
+        .set_json(create_reply(parent_id))
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert!(body["cc"]
+        .as_array()
+        .unwrap()
+        .contains(&json!(parent_author)));
+    assert_eq!(body["object"]["inReplyTo"], parent_id);
+
+    // Never stored here, so its depth in the thread is unknown
+    let reply = created_note(db, &body).await;
+    assert_eq!(reply.in_reply_to_actor.as_deref(), Some(parent_author));
+    assert!(reply.thread_depth.is_none());
+
+    // Not a follower, so delivered directly rather than by fan-out, and
+    // through the queue rather than inline
+    assert!(client.posted().is_empty());
+    let queued = queued(db).await;
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].inbox_url, format!("{parent_author}/inbox"));
+    assert_eq!(queued[0].activity["type"], "Create");
+    assert_eq!(queued[0].activity["id"], body["id"]);
+}
+
+async fn post_outbox_reply_to_nonexistent_note(db: &DatabaseRef) {
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    for in_reply_to in [
+        "https://remote.example/notes/missing",
+        "https://example.com/notes/missing",
+        "not a url",
+    ] {
+        let req = outbox_post("testuser")
+            .set_json(create_reply(in_reply_to))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400, "inReplyTo {in_reply_to}");
+    }
+
+    let notes = db
+        .get_notes_by_actor("https://example.com/users/testuser", 20, 0)
+        .await
+        .unwrap();
+    assert!(notes.is_empty());
+}
+
+fn create_mention(tag: Value) -> Value {
+    json!({
+        "type": "Create",
+        "object": {
+            "type": "Note",
+            "content": "Have you seen this?",
+            "tag": [tag]
+        },
+        "to": ["https://www.w3.org/ns/activitystreams#Public"]
+    })
+}
+
+async fn post_outbox_mention_remote_actor(db: &DatabaseRef) {
+    let mentioned = "https://remote.example/users/carol";
+
+    // The mention only carries an acct name, so it is resolved through WebFinger
+    let mut client = MockHttpClient::with_documents(vec![json!({
+        "id": mentioned,
+        "type": "Person",
+        "preferredUsername": "carol",
+        "inbox": format!("{mentioned}/inbox")
+    })]);
+    client.documents.insert(
+        "https://remote.example/.well-known/webfinger?resource=acct:carol@remote.example"
+            .to_string(),
+        json!({
+            "subject": "acct:carol@remote.example",
+            "links": [
+                {"rel": "http://webfinger.net/rel/profile-page", "type": "text/html", "href": "https://remote.example/@carol"},
+                {"rel": "self", "type": "application/activity+json", "href": mentioned}
+            ]
+        }),
+    );
+    let client = Arc::new(client);
+    let app = test::init_service(create_test_app_with_client(db.clone(), client.clone())).await;
+
+    let req = outbox_post("testuser")
+        .set_json(create_mention(
+            json!({"type": "Mention", "name": "@carol@remote.example"}),
+        ))
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["cc"], json!([mentioned]));
+
+    let note = created_note(db, &body).await;
+    assert_eq!(note.cc_recipients, vec![mentioned.to_string()]);
+    let activity = db
+        .get_activity_by_id(body["id"].as_str().unwrap())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(activity.cc_recipients, vec![mentioned.to_string()]);
+
+    // Not a follower, so delivered directly rather than by fan-out
+    let queued = queued(db).await;
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].inbox_url, format!("{mentioned}/inbox"));
+    assert_eq!(queued[0].activity["id"], body["id"]);
+}
+
+async fn post_outbox_mention_local_actor(db: &DatabaseRef) {
+    let mentioned = "https://example.com/users/bob";
+
+    let client = Arc::new(MockHttpClient::default());
+    let app = test::init_service(create_test_app_with_client(db.clone(), client.clone())).await;
+
+    let req = outbox_post("testuser")
+        .set_json(create_mention(
+            json!({"type": "Mention", "name": "@bob", "href": mentioned}),
+        ))
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+
+    let body: Value = test::read_body_json(resp).await;
+    // Addressing the local actor is enough to land in their inbox collection
+    assert_eq!(body["cc"], json!([mentioned]));
+    let activity = db
+        .get_activity_by_id(body["id"].as_str().unwrap())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(activity.cc_recipients, vec![mentioned.to_string()]);
+    assert!(queued(db).await.is_empty());
+    assert!(client.posted().is_empty());
+}
+
+async fn post_outbox_note_and_activity_share_published(db: &DatabaseRef) {
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    let imported = "2019-03-04T05:06:07Z";
+    for (published, expected) in [
+        (Some(imported), Some(imported)),
+        // Future dates schedule the note for then
+        (Some("2999-01-01T00:00:00Z"), Some("2999-01-01T00:00:00Z")),
+        (None, None),
+    ] {
+        let mut object = json!({"type": "Note", "content": "Imported"});
+        if let Some(published) = published {
+            object["published"] = json!(published);
+        }
+        // Storage may keep less than nanosecond precision
+        let before = Utc::now().trunc_subsecs(0);
+        let req = outbox_post("testuser")
+            .set_json(json!({
+                "type": "Create",
+                "object": object,
+                "to": ["https://www.w3.org/ns/activitystreams#Public"]
+            }))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 201);
+        let body: Value = test::read_body_json(resp).await;
+
+        let note = created_note(db, &body).await;
+        let activity = db
+            .get_activity_by_id(body["id"].as_str().unwrap())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(note.published, activity.published);
+        assert_eq!(body["published"], body["object"]["published"]);
+        match expected {
+            Some(expected) => assert_eq!(
+                note.published,
+                expected.parse::<chrono::DateTime<Utc>>().unwrap()
+            ),
+            None => assert!(note.published >= before && note.published <= Utc::now()),
+        }
+    }
+}
+
+fn image_attachments() -> Value {
+    json!([
+        {
+            "type": "Image",
+            "mediaType": "image/png",
+            "url": "https://example.com/media/1.png",
+            "name": "First",
+            "blurhash": "LEHV6nWB2yk8pyo0adR*.7kCMdnj"
+        },
+        {
+            "type": "Image",
+            "mediaType": "image/jpeg",
+            "url": "https://example.com/media/2.jpg"
+        }
+    ])
+}
+
+async fn post_outbox_attachments_round_trip(db: &DatabaseRef) {
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    let req = outbox_post("testuser")
+        .set_json(json!({
+            "type": "Create",
+            "object": {
+                "type": "Note",
+                "content": "Two pictures",
+                "attachment": image_attachments()
+            },
+            "to": ["https://www.w3.org/ns/activitystreams#Public"]
+        }))
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["object"]["attachment"], image_attachments());
+
+    let note = created_note(db, &body).await;
+    assert_eq!(Value::Array(note.attachments), image_attachments());
+
+    let req = test::TestRequest::get()
+        .uri("/users/testuser/outbox")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(
+        body["first"]["orderedItems"][0]["object"]["attachment"],
+        image_attachments()
+    );
+}
+
+async fn post_outbox_rejects_disallowed_attachment(db: &DatabaseRef) {
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    let req = outbox_post("testuser")
+        .set_json(json!({
+            "type": "Create",
+            "object": {
+                "type": "Note",
+                "content": "Totally safe",
+                "attachment": {
+                    "type": "Document",
+                    "mediaType": "application/x-msdownload",
+                    "url": "https://example.com/media/setup.exe"
+                }
+            }
+        }))
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 400);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(
+        body["error"],
+        "Unsupported attachment mediaType: application/x-msdownload"
+    );
+
+    let notes = db
+        .get_notes_by_actor("https://example.com/users/testuser", 20, 0)
+        .await
+        .unwrap();
+    assert!(notes.is_empty());
+}
+
+async fn inbox_create_keeps_allowed_attachments(db: &DatabaseRef) {
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    let mut attachments = image_attachments();
+    attachments.as_array_mut().unwrap().push(json!({
+        "type": "Document",
+        "mediaType": "application/x-msdownload",
+        "url": "https://remote.example/media/setup.exe"
+    }));
+
+    let req = test::TestRequest::post()
+        .uri("/users/testuser/inbox")
+        .set_json(json!({
+            "id": "https://remote.example/activities/1",
+            "type": "Create",
+            "actor": "https://remote.example/users/alice",
+            "object": {
+                "id": "https://remote.example/notes/1",
+                "type": "Note",
+                "attributedTo": "https://remote.example/users/alice",
+                "content": "Pictures!",
+                "attachment": attachments
+            }
+        }))
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 202);
+
+    let note = db
+        .get_note_by_id("https://remote.example/notes/1")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(Value::Array(note.attachments), image_attachments());
+}
+
+fn remote_create() -> Value {
+    json!({
+        "id": "https://remote.example/activities/1",
+        "type": "Create",
+        "actor": "https://remote.example/users/alice",
+        "object": {
+            "id": "https://remote.example/notes/1",
+            "type": "Note",
+            "attributedTo": "https://remote.example/users/alice",
+            "content": "Hello from remote server!",
+            "to": ["https://example.com/users/testuser"],
+            "published": "2023-01-01T00:00:00Z"
+        },
+        "to": ["https://example.com/users/testuser"],
+        "published": "2023-01-01T00:00:00Z"
+    })
+}
+
+async fn inbox_handler_create_note(db: &DatabaseRef) {
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    let req = test::TestRequest::post()
+        .uri("/users/testuser/inbox")
+        .insert_header(("Content-Type", "application/activity+json"))
+        .set_json(remote_create())
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 202); // Accepted
+
+    let note = db
+        .get_note_by_id("https://remote.example/notes/1")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(note.attributed_to, "https://remote.example/users/alice");
+    assert_eq!(note.content, "Hello from remote server!");
+    let activity = db
+        .get_activity_by_id("https://remote.example/activities/1")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(activity.activity_type, "Create");
 }
 
 #[tokio::test]
-async fn test_post_outbox_handler_actor_not_found() {
+async fn test_inbox_handler_create_note() {
+    let mut mock = StrictMockDatabase::new();
+    idle_delivery_queue(&mut mock);
+    mock.expect_is_blocked().returning(|_, _| Ok(false));
+    mock.expect_get_activity_by_id().returning(|_| Ok(None));
+
+    let actor_id = "https://example.com/users/testuser".to_string();
+
+    mock.expect_get_actor_by_username()
+        .with(eq("testuser"))
+        .returning(move |_| Ok(Some(common::actor(&actor_id, "testuser", "Test User"))));
+
+    mock.expect_get_note_by_id().returning(|_| Ok(None)); // Note doesn't exist yet
+
+    mock.expect_create_note().returning(|_| Ok(()));
+
+    mock.expect_create_activity().returning(|_| Ok(()));
+
+    let mock = Arc::new(mock);
+    let app = test::init_service(create_test_app(mock.clone())).await;
+
+    let create_activity = json!({
+        "id": "https://remote.example/activities/1",
+        "type": "Create",
+        "actor": "https://remote.example/users/alice",
+        "object": {
+            "id": "https://remote.example/notes/1",
+            "type": "Note",
+            "attributedTo": "https://remote.example/users/alice",
+            "content": "Hello from remote server!",
+            "to": ["https://example.com/users/testuser"],
+            "published": "2023-01-01T00:00:00Z"
+        },
+        "to": ["https://example.com/users/testuser"],
+        "published": "2023-01-01T00:00:00Z"
+    });
+
+    let req = test::TestRequest::post()
+        .uri("/users/testuser/inbox")
+        .insert_header(("Content-Type", "application/activity+json"))
+        .set_json(&create_activity)
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 202); // Accepted
+    mock.assert_calls(&[
+        "get_actor_by_username",
+        "is_blocked",
+        "count_pending_deliveries",
+        "get_activity_by_id",
+        "get_note_by_id",
+        "create_note",
+        "create_activity",
+    ]);
+}
+
+async fn inbox_handler_stores_redelivered_activity_once(db: &DatabaseRef) {
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    for _ in 0..2 {
+        let req = test::TestRequest::post()
+            .uri("/users/testuser/inbox")
+            .set_json(remote_create())
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 202);
+    }
+
+    let activities = db
+        .get_inbox_activities("https://example.com/users/testuser", 20, 0)
+        .await
+        .unwrap();
+    assert_eq!(activities.len(), 1);
+}
+
+fn create_reply_test_db() -> MockDatabase {
     let mut mock = MockDatabase::new();
+    idle_delivery_queue(&mut mock);
+    mock.expect_is_blocked().returning(|_, _| Ok(false));
+    // Remote actors are fetched, as none are cached
+    mock.expect_get_remote_actor().returning(|_| Ok(None));
+    mock.expect_upsert_remote_actor().returning(|_| Ok(()));
 
     mock.expect_get_actor_by_username()
-        .with(eq("nonexistent"))
-        .returning(|_| Ok(None));
+        .with(eq("testuser"))
+        .returning(|_| {
+            Ok(Some(common::actor(
+                "https://example.com/users/testuser",
+                "testuser",
+                "Test User",
+            )))
+        });
 
-    let db: DatabaseRef = Arc::new(mock);
-    let app = test::init_service(create_test_app(db)).await;
+    mock
+}
+
+/// Activities are stored and found again by id; `notes` notes are created
+fn create_redelivery_test_db(notes: usize) -> MockDatabase {
+    let mut mock = create_reply_test_db();
+    let stored = Arc::new(Mutex::new(Vec::<DbActivity>::new()));
+
+    mock.expect_get_note_by_id().returning(|_| Ok(None));
+    mock.expect_create_note().times(notes).returning(|_| Ok(()));
+    {
+        let stored = stored.clone();
+        mock.expect_create_activity()
+            .times(notes)
+            .returning(move |activity| {
+                stored.lock().unwrap().push(activity.clone());
+                Ok(())
+            });
+    }
+    mock.expect_get_activity_by_id().returning(move |id| {
+        Ok(stored
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|activity| activity.id == id)
+            .cloned())
+    });
+    mock
+}
 
-    let create_activity = json!({
+fn relayed_create(n: u32) -> Value {
+    json!({
+        "id": format!("https://remote.example/activities/{n}"),
         "type": "Create",
+        "actor": "https://remote.example/users/alice",
         "object": {
+            "id": format!("https://remote.example/notes/{n}"),
             "type": "Note",
-            "content": "Hello!"
-        }
-    });
-
-    let req = test::TestRequest::post()
-        .uri("/users/nonexistent/outbox")
-        .insert_header(("Content-Type", "application/activity+json"))
-        .set_json(&create_activity)
-        .to_request();
+            "attributedTo": "https://remote.example/users/alice",
+            "content": "Relayed twice",
+            "to": ["https://example.com/users/testuser"]
+        },
+        "to": ["https://example.com/users/testuser"]
+    })
+}
 
-    let resp = test::call_service(&app, req).await;
-    assert_eq!(resp.status(), 404);
+#[tokio::test]
+async fn test_inbox_handler_skips_redelivered_activity() {
+    let db: DatabaseRef = Arc::new(create_redelivery_test_db(1));
+    let app = test::init_service(create_test_app(db)).await;
 
-    let body: serde_json::Value = test::read_body_json(resp).await;
-    assert_eq!(body["error"], "Actor not found");
+    for _ in 0..2 {
+        let req = test::TestRequest::post()
+            .uri("/users/testuser/inbox")
+            .set_json(relayed_create(1))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 202);
+    }
 }
 
 #[tokio::test]
-async fn test_inbox_handler_create_note() {
-    let mut mock = MockDatabase::new();
+async fn test_inbox_handler_skips_replays_older_than_the_filter() {
+    let db: DatabaseRef = Arc::new(create_redelivery_test_db(3));
+    // Remembering one id at a time, the filter has forgotten the first by
+    // the time it is replayed
+    let app =
+        test::init_service(create_test_app(db).app_data(web::Data::new(SeenActivities::new(1))))
+            .await;
+
+    for n in [1, 2, 3, 1] {
+        let req = test::TestRequest::post()
+            .uri("/users/testuser/inbox")
+            .set_json(relayed_create(n))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 202);
+    }
+}
 
-    let actor_id = "https://example.com/users/testuser".to_string();
+async fn inbox_handler_repeated_follow(db: &DatabaseRef) {
+    let app = test::init_service(create_test_app(db.clone())).await;
 
-    mock.expect_get_actor_by_username()
-        .with(eq("testuser"))
-        .returning(move |_| {
-            Ok(Some(DbActor {
-                id: actor_id.clone(),
-                username: "testuser".to_string(),
-                name: "Test User".to_string(),
-                summary: None,
-                public_key_pem: "test_key".to_string(),
-                private_key_pem: None,
-                created_at: Utc::now(),
-                updated_at: Utc::now(),
-            }))
-        });
+    let follow = json!({
+        "id": "https://remote.example/activities/follow-1",
+        "type": "Follow",
+        "actor": "https://remote.example/users/alice",
+        "object": "https://example.com/users/testuser"
+    });
 
-    mock.expect_get_note_by_id().returning(|_| Ok(None)); // Note doesn't exist yet
+    for _ in 0..2 {
+        let req = test::TestRequest::post()
+            .uri("/users/testuser/inbox")
+            .set_json(&follow)
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 202);
+    }
+
+    // The second is turned away by the follow pair already stored
+    let follow = db
+        .get_follow_by_actors(
+            "https://remote.example/users/alice",
+            "https://example.com/users/testuser",
+        )
+        .await
+        .unwrap();
+    assert!(follow.is_some());
+}
 
-    mock.expect_create_note().returning(|_| Ok(()));
+#[tokio::test]
+async fn test_inbox_handler_filter_hits_fall_through_to_database() {
+    let mut mock = create_reply_test_db();
 
-    mock.expect_create_activity().returning(|_| Ok(()));
+    // Follows are not stored as activities, so the exact check never finds them
+    mock.expect_get_activity_by_id().returning(|_| Ok(None));
+    mock.expect_create_follow().times(2).returning(|_| Ok(()));
+    mock.expect_get_pending_accepts()
+        .returning(|_, _, _| Ok(vec![]));
 
     let db: DatabaseRef = Arc::new(mock);
     let app = test::init_service(create_test_app(db)).await;
 
-    let create_activity = json!({
-        "id": "https://remote.example/activities/1",
-        "type": "Create",
+    let follow = json!({
+        "id": "https://remote.example/activities/follow-1",
+        "type": "Follow",
         "actor": "https://remote.example/users/alice",
-        "object": {
-            "id": "https://remote.example/notes/1",
-            "type": "Note",
-            "attributedTo": "https://remote.example/users/alice",
-            "content": "Hello from remote server!",
-            "to": ["https://example.com/users/testuser"],
-            "published": "2023-01-01T00:00:00Z"
-        },
-        "to": ["https://example.com/users/testuser"],
-        "published": "2023-01-01T00:00:00Z"
+        "object": "https://example.com/users/testuser"
+    });
+
+    for _ in 0..2 {
+        let req = test::TestRequest::post()
+            .uri("/users/testuser/inbox")
+            .set_json(&follow)
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 202);
+    }
+}
+
+async fn inbox_handler_follow_activity(db: &DatabaseRef) {
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    let follow_activity = json!({
+        "id": "https://remote.example/activities/follow/1",
+        "type": "Follow",
+        "actor": "https://remote.example/users/alice",
+        "object": "https://example.com/users/testuser"
     });
 
     let req = test::TestRequest::post()
         .uri("/users/testuser/inbox")
         .insert_header(("Content-Type", "application/activity+json"))
-        .set_json(&create_activity)
+        .set_json(&follow_activity)
         .to_request();
 
     let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), 202); // Accepted
+
+    // Stored under an id of our own rather than the activity's
+    let follow = db
+        .get_follow_by_actors(
+            "https://remote.example/users/alice",
+            "https://example.com/users/testuser",
+        )
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(follow.status, "pending");
 }
 
 #[tokio::test]
 async fn test_inbox_handler_follow_activity() {
-    let mut mock = MockDatabase::new();
+    let mut mock = StrictMockDatabase::new();
+    idle_delivery_queue(&mut mock);
+    mock.expect_is_blocked().returning(|_, _| Ok(false));
+    mock.expect_get_activity_by_id().returning(|_| Ok(None));
 
     let actor_id = "https://example.com/users/testuser".to_string();
 
     mock.expect_get_actor_by_username()
         .with(eq("testuser"))
-        .returning(move |_| {
-            Ok(Some(DbActor {
-                id: actor_id.clone(),
-                username: "testuser".to_string(),
-                name: "Test User".to_string(),
-                summary: None,
-                public_key_pem: "test_key".to_string(),
-                private_key_pem: None,
-                created_at: Utc::now(),
-                updated_at: Utc::now(),
-            }))
-        });
+        .returning(move |_| Ok(Some(common::actor(&actor_id, "testuser", "Test User"))));
 
     mock.expect_create_follow().returning(|_| Ok(()));
+    mock.expect_get_pending_accepts()
+        .returning(|_, _, _| Ok(vec![]));
 
-    let db: DatabaseRef = Arc::new(mock);
-    let app = test::init_service(create_test_app(db)).await;
+    let mock = Arc::new(mock);
+    let app = test::init_service(create_test_app(mock.clone())).await;
 
     let follow_activity = json!({
         "id": "https://remote.example/activities/follow/1",
@@ -390,24 +1551,125 @@ async fn test_inbox_handler_follow_activity() {
 
     let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), 202); // Accepted
+    mock.assert_calls(&[
+        "get_actor_by_username",
+        "is_blocked",
+        "count_pending_deliveries",
+        "get_activity_by_id",
+        "create_follow",
+        "get_pending_accepts",
+    ]);
+}
+
+async fn inbox_handler_accept_activity(db: &DatabaseRef) {
+    let follow_id = "https://remote.example/activities/follow/1";
+    db.create_follow(&DbFollowRelation {
+        id: follow_id.to_string(),
+        follower_id: "https://example.com/users/testuser".to_string(),
+        following_id: "https://remote.example/users/alice".to_string(),
+        status: "pending".to_string(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    })
+    .await
+    .unwrap();
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    let accept_activity = json!({
+        "id": "https://remote.example/activities/accept/1",
+        "type": "Accept",
+        "actor": "https://remote.example/users/alice",
+        "object": {
+            "id": follow_id,
+            "type": "Follow",
+            "actor": "https://example.com/users/testuser",
+            "object": "https://remote.example/users/alice"
+        }
+    });
+
+    let req = test::TestRequest::post()
+        .uri("/users/testuser/inbox")
+        .insert_header(("Content-Type", "application/activity+json"))
+        .set_json(&accept_activity)
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 202); // Accepted
+
+    let follow = db.get_follow_by_id(follow_id).await.unwrap().unwrap();
+    assert_eq!(follow.status, "accepted");
+}
+
+async fn inbox_handler_accept_from_third_party(db: &DatabaseRef) {
+    let follow_id = "https://remote.example/activities/follow/1";
+    db.create_follow(&DbFollowRelation {
+        id: follow_id.to_string(),
+        follower_id: "https://example.com/users/testuser".to_string(),
+        following_id: "https://remote.example/users/alice".to_string(),
+        status: "pending".to_string(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    })
+    .await
+    .unwrap();
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    // Someone other than the followed actor, or sent to someone other than
+    // the follower, cannot accept the follow
+    for (n, (inbox, actor)) in [
+        ("testuser", "https://other.example/users/mallory"),
+        ("bob", "https://remote.example/users/alice"),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let accept_activity = json!({
+            "id": format!("https://other.example/activities/accept/{n}"),
+            "type": "Accept",
+            "actor": actor,
+            "object": {
+                "id": follow_id,
+                "type": "Follow",
+                "actor": "https://example.com/users/testuser",
+                "object": "https://remote.example/users/alice"
+            }
+        });
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/users/{inbox}/inbox"))
+            .insert_header(("Content-Type", "application/activity+json"))
+            .set_json(&accept_activity)
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 403, "Accept by {actor} to {inbox}");
+    }
+
+    let follow = db.get_follow_by_id(follow_id).await.unwrap().unwrap();
+    assert_eq!(follow.status, "pending");
 }
 
 #[tokio::test]
 async fn test_inbox_handler_accept_activity() {
-    let mut mock = MockDatabase::new();
+    let mut mock = StrictMockDatabase::new();
+    idle_delivery_queue(&mut mock);
+    mock.expect_is_blocked().returning(|_, _| Ok(false));
+    mock.expect_get_activity_by_id().returning(|_| Ok(None));
 
     let actor_id = "https://example.com/users/testuser".to_string();
 
     mock.expect_get_actor_by_username()
         .with(eq("testuser"))
-        .returning(move |_| {
-            Ok(Some(DbActor {
-                id: actor_id.clone(),
-                username: "testuser".to_string(),
-                name: "Test User".to_string(),
-                summary: None,
-                public_key_pem: "test_key".to_string(),
-                private_key_pem: None,
+        .returning(move |_| Ok(Some(common::actor(&actor_id, "testuser", "Test User"))));
+
+    mock.expect_get_follow_by_id()
+        .with(eq("https://remote.example/activities/follow/1"))
+        .returning(|id| {
+            Ok(Some(DbFollowRelation {
+                id: id.to_string(),
+                follower_id: "https://example.com/users/testuser".to_string(),
+                following_id: "https://remote.example/users/alice".to_string(),
+                status: "pending".to_string(),
                 created_at: Utc::now(),
                 updated_at: Utc::now(),
             }))
@@ -420,8 +1682,8 @@ async fn test_inbox_handler_accept_activity() {
         )
         .returning(|_, _| Ok(()));
 
-    let db: DatabaseRef = Arc::new(mock);
-    let app = test::init_service(create_test_app(db)).await;
+    let mock = Arc::new(mock);
+    let app = test::init_service(create_test_app(mock.clone())).await;
 
     let accept_activity = json!({
         "id": "https://remote.example/activities/accept/1",
@@ -443,18 +1705,18 @@ async fn test_inbox_handler_accept_activity() {
 
     let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), 202); // Accepted
+    mock.assert_calls(&[
+        "get_actor_by_username",
+        "is_blocked",
+        "count_pending_deliveries",
+        "get_activity_by_id",
+        "get_follow_by_id",
+        "update_follow_status",
+    ]);
 }
 
-#[tokio::test]
-async fn test_inbox_handler_actor_not_found() {
-    let mut mock = MockDatabase::new();
-
-    mock.expect_get_actor_by_username()
-        .with(eq("nonexistent"))
-        .returning(|_| Ok(None));
-
-    let db: DatabaseRef = Arc::new(mock);
-    let app = test::init_service(create_test_app(db)).await;
+async fn inbox_handler_actor_not_found(db: &DatabaseRef) {
+    let app = test::init_service(create_test_app(db.clone())).await;
 
     let create_activity = json!({
         "type": "Create",
@@ -477,68 +1739,43 @@ async fn test_inbox_handler_actor_not_found() {
     assert_eq!(body["error"], "Actor not found");
 }
 
-// Integration test that simulates a complete flow
 #[tokio::test]
-async fn test_complete_activity_flow() {
-    let mut mock = MockDatabase::new();
-
-    let actor_id = "https://example.com/users/alice".to_string();
-    let follower_id = "https://example.com/users/bob".to_string();
+async fn test_inbox_handler_actor_not_found() {
+    let mut mock = StrictMockDatabase::new();
 
-    // Setup expectations for the complete flow
     mock.expect_get_actor_by_username()
-        .with(eq("alice"))
-        .returning(move |_| {
-            Ok(Some(DbActor {
-                id: actor_id.clone(),
-                username: "alice".to_string(),
-                name: "Alice".to_string(),
-                summary: Some("Alice's profile".to_string()),
-                public_key_pem: "alice_key".to_string(),
-                private_key_pem: None,
-                created_at: Utc::now(),
-                updated_at: Utc::now(),
-            }))
-        });
+        .with(eq("nonexistent"))
+        .returning(|_| Ok(None));
 
-    mock.expect_get_actor_by_username()
-        .with(eq("bob"))
-        .returning(move |_| {
-            Ok(Some(DbActor {
-                id: follower_id.clone(),
-                username: "bob".to_string(),
-                name: "Bob".to_string(),
-                summary: Some("Bob's profile".to_string()),
-                public_key_pem: "bob_key".to_string(),
-                private_key_pem: None,
-                created_at: Utc::now(),
-                updated_at: Utc::now(),
-            }))
-        });
+    let mock = Arc::new(mock);
+    let app = test::init_service(create_test_app(mock.clone())).await;
 
-    mock.expect_create_note().returning(|_| Ok(()));
+    let create_activity = json!({
+        "type": "Create",
+        "object": {
+            "type": "Note",
+            "content": "Hello!"
+        }
+    });
 
-    mock.expect_create_activity().returning(|_| Ok(()));
+    let req = test::TestRequest::post()
+        .uri("/users/nonexistent/inbox")
+        .insert_header(("Content-Type", "application/activity+json"))
+        .set_json(&create_activity)
+        .to_request();
 
-    mock.expect_create_follow().returning(|_| Ok(()));
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
 
-    mock.expect_get_actor_outbox_count().returning(|_| Ok(1));
-
-    mock.expect_get_activities_by_actor().returning(|_, _, _| {
-        Ok(vec![DbActivity {
-            id: "https://example.com/activities/1".to_string(),
-            actor_id: "https://example.com/users/alice".to_string(),
-            activity_type: "Create".to_string(),
-            object: json!({"type": "Note", "content": "Hello, world!"}),
-            to_recipients: vec!["https://www.w3.org/ns/activitystreams#Public".to_string()],
-            cc_recipients: vec![],
-            published: Utc::now(),
-            created_at: Utc::now(),
-        }])
-    });
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Actor not found");
+    // Turned away before anything else is looked at
+    mock.assert_calls(&["get_actor_by_username"]);
+}
 
-    let db: DatabaseRef = Arc::new(mock);
-    let app = test::init_service(create_test_app(db)).await;
+// Integration test that simulates a complete flow
+async fn complete_activity_flow(db: &DatabaseRef) {
+    let app = test::init_service(create_test_app(db.clone())).await;
 
     // 1. Alice creates a note
     let create_activity = json!({
@@ -551,8 +1788,7 @@ async fn test_complete_activity_flow() {
         "to": ["https://www.w3.org/ns/activitystreams#Public"]
     });
 
-    let req = test::TestRequest::post()
-        .uri("/users/alice/outbox")
+    let req = outbox_post("alice")
         .insert_header(("Content-Type", "application/activity+json"))
         .set_json(&create_activity)
         .to_request();
@@ -576,6 +1812,14 @@ async fn test_complete_activity_flow() {
 
     let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), 202);
+    let follow = db
+        .get_follow_by_actors(
+            "https://example.com/users/bob",
+            "https://example.com/users/alice",
+        )
+        .await
+        .unwrap();
+    assert!(follow.is_some());
 
     // 3. Check Alice's outbox
     let req = test::TestRequest::get()
@@ -589,12 +1833,13 @@ async fn test_complete_activity_flow() {
     let body: serde_json::Value = test::read_body_json(resp).await;
     assert_eq!(body["type"], "OrderedCollection");
     assert_eq!(body["totalItems"], 1);
-    assert_eq!(body["orderedItems"][0]["type"], "Create");
+    assert_eq!(body["first"]["orderedItems"][0]["type"], "Create");
 }
 
 #[tokio::test]
 async fn test_error_handling_in_handlers() {
     let mut mock = MockDatabase::new();
+    accept_test_tokens(&mut mock);
 
     // Test database error in get_actor
     mock.expect_get_actor_by_username()
@@ -609,16 +1854,11 @@ async fn test_error_handling_in_handlers() {
     mock.expect_get_actor_by_username()
         .with(eq("note_error"))
         .returning(|_| {
-            Ok(Some(DbActor {
-                id: "https://example.com/users/note_error".to_string(),
-                username: "note_error".to_string(),
-                name: "Error User".to_string(),
-                summary: None,
-                public_key_pem: "test_key".to_string(),
-                private_key_pem: None,
-                created_at: Utc::now(),
-                updated_at: Utc::now(),
-            }))
+            Ok(Some(common::actor(
+                "https://example.com/users/note_error",
+                "note_error",
+                "Error User",
+            )))
         });
 
     mock.expect_create_note().returning(|_| {
@@ -647,8 +1887,7 @@ async fn test_error_handling_in_handlers() {
         }
     });
 
-    let req = test::TestRequest::post()
-        .uri("/users/note_error/outbox")
+    let req = outbox_post("note_error")
         .insert_header(("Content-Type", "application/activity+json"))
         .set_json(&create_activity)
         .to_request();
@@ -656,3 +1895,276 @@ async fn test_error_handling_in_handlers() {
     let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), 500);
 }
+
+#[tokio::test]
+#[tracing_test::traced_test]
+async fn test_inbox_handler_emits_span_with_activity_type() {
+    let mut mock = MockDatabase::new();
+
+    mock.expect_get_actor_by_username()
+        .with(eq("testuser"))
+        .returning(|_| Ok(None));
+
+    let db: DatabaseRef = Arc::new(mock);
+    let app = test::init_service(create_test_app(db)).await;
+
+    let req = test::TestRequest::post()
+        .uri("/users/testuser/inbox")
+        .insert_header(("Content-Type", "application/activity+json"))
+        .set_json(json!({
+            "type": "Follow",
+            "actor": "https://remote.example/users/alice",
+            "object": "https://example.com/users/testuser"
+        }))
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+
+    assert!(logs_contain("inbox{"));
+    assert!(logs_contain("activity_type=Follow"));
+}
+
+fn follow_activity(object: Value) -> Value {
+    json!({
+        "type": "Follow",
+        "actor": "https://example.com/users/testuser",
+        "object": object
+    })
+}
+
+/// testuser's accepted follow of `target`
+async fn seed_follow(db: &DatabaseRef, id: &str, target: &str) {
+    db.create_follow(&DbFollowRelation {
+        id: id.to_string(),
+        follower_id: "https://example.com/users/testuser".to_string(),
+        following_id: target.to_string(),
+        status: "accepted".to_string(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    })
+    .await
+    .unwrap();
+}
+
+async fn post_outbox_follow_remote_actor(db: &DatabaseRef) {
+    let target = "https://remote.example/users/carol";
+    let client = Arc::new(MockHttpClient::with_documents(vec![json!({
+        "id": target,
+        "type": "Person",
+        "preferredUsername": "carol",
+        "inbox": format!("{target}/inbox")
+    })]));
+    let app = test::init_service(create_test_app_with_client(db.clone(), client.clone())).await;
+
+    let req = outbox_post("testuser")
+        .set_json(follow_activity(json!(target)))
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let location = resp.headers().get("location").unwrap().clone();
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(location, body["id"].as_str().unwrap());
+    assert_eq!(body["type"], "Follow");
+    assert_eq!(body["actor"], "https://example.com/users/testuser");
+    assert_eq!(body["object"], target);
+    assert!(body["id"]
+        .as_str()
+        .unwrap()
+        .starts_with("https://example.com/activities/"));
+
+    let follow = db
+        .get_follow_by_actors("https://example.com/users/testuser", target)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(follow.status, "pending");
+    let activity = db
+        .get_activity_by_id(body["id"].as_str().unwrap())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(activity.activity_type, "Follow");
+
+    // The delivery worker sends the queued Follow to the target's inbox
+    let queued = queued(db).await;
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].priority, DeliveryPriority::Interactive);
+    let service = DeliveryService::new(Config::default(), client.clone(), db.clone());
+
+    let run = delivery_queue::process_queue(db, &service, Utc::now())
+        .await
+        .unwrap();
+
+    assert_eq!(run.delivered, 1);
+    let posted = client.posted();
+    assert_eq!(posted.len(), 1);
+    assert_eq!(posted[0].0, format!("{target}/inbox"));
+    assert_eq!(posted[0].1["type"], "Follow");
+    assert_eq!(posted[0].1["id"], body["id"]);
+}
+
+async fn post_outbox_follow_acct_handle(db: &DatabaseRef) {
+    let target = "https://remote.example/users/carol";
+
+    // The handle is discovered through WebFinger before following
+    let mut client = MockHttpClient::with_documents(vec![json!({
+        "id": target,
+        "type": "Person",
+        "preferredUsername": "carol",
+        "inbox": format!("{target}/inbox")
+    })]);
+    client.documents.insert(
+        "https://remote.example/.well-known/webfinger?resource=acct:carol@remote.example"
+            .to_string(),
+        json!({
+            "subject": "acct:carol@remote.example",
+            "links": [{"rel": "self", "type": "application/activity+json", "href": target}]
+        }),
+    );
+    let app = test::init_service(create_test_app_with_client(db.clone(), Arc::new(client))).await;
+
+    let req = outbox_post("testuser")
+        .set_json(follow_activity(json!("acct:carol@remote.example")))
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["object"], target);
+
+    let follow = db
+        .get_follow_by_actors("https://example.com/users/testuser", target)
+        .await
+        .unwrap();
+    assert!(follow.is_some());
+    let queued = queued(db).await;
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].inbox_url, format!("{target}/inbox"));
+}
+
+async fn post_outbox_follow_unknown_acct_handle(db: &DatabaseRef) {
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    let req = outbox_post("testuser")
+        .set_json(follow_activity(json!("@nobody@remote.example")))
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+
+    let following = db
+        .get_actor_following_count("https://example.com/users/testuser")
+        .await
+        .unwrap();
+    assert_eq!(following, 0);
+}
+
+async fn post_outbox_follow_invalid_object(db: &DatabaseRef) {
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    for object in [
+        json!("http://remote.example/users/carol"),
+        json!("not a url"),
+        json!({"type": "Person"}),
+        json!("https://example.com/users/testuser"),
+    ] {
+        let req = outbox_post("testuser")
+            .set_json(follow_activity(object.clone()))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 400, "object {object}");
+    }
+
+    let following = db
+        .get_actor_following_count("https://example.com/users/testuser")
+        .await
+        .unwrap();
+    assert_eq!(following, 0);
+}
+
+async fn post_outbox_follow_already_following(db: &DatabaseRef) {
+    let target = "https://remote.example/users/carol";
+    seed_follow(db, "https://example.com/activities/1", target).await;
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    let req = outbox_post("testuser")
+        .set_json(follow_activity(json!({"id": target, "type": "Person"})))
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 409);
+    assert!(queued(db).await.is_empty());
+}
+
+fn undo_follow(target: &str) -> Value {
+    json!({
+        "type": "Undo",
+        "actor": "https://example.com/users/testuser",
+        "object": {
+            "type": "Follow",
+            "actor": "https://example.com/users/testuser",
+            "object": target
+        }
+    })
+}
+
+async fn post_outbox_undo_follow(db: &DatabaseRef) {
+    let target = "https://remote.example/users/carol";
+    let follow_id = "https://example.com/activities/follow-1";
+    seed_follow(db, follow_id, target).await;
+
+    let client = Arc::new(MockHttpClient::with_documents(vec![json!({
+        "id": target,
+        "type": "Person",
+        "preferredUsername": "carol",
+        "inbox": format!("{target}/inbox")
+    })]));
+    let app = test::init_service(create_test_app_with_client(db.clone(), client)).await;
+
+    let req = outbox_post("testuser")
+        .set_json(undo_follow(target))
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let location = resp.headers().get("location").unwrap().clone();
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(location, body["id"].as_str().unwrap());
+    assert_eq!(body["type"], "Undo");
+    assert_eq!(body["object"]["id"], follow_id);
+    assert_eq!(body["object"]["object"], target);
+
+    assert!(db.get_follow_by_id(follow_id).await.unwrap().is_none());
+    let activity = db
+        .get_activity_by_id(body["id"].as_str().unwrap())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(activity.activity_type, "Undo");
+
+    let queued = queued(db).await;
+    assert_eq!(queued.len(), 1);
+    assert_eq!(queued[0].inbox_url, format!("{target}/inbox"));
+    assert_eq!(queued[0].activity["id"], body["id"]);
+}
+
+async fn post_outbox_undo_follow_not_following(db: &DatabaseRef) {
+    let app = test::init_service(create_test_app(db.clone())).await;
+
+    let req = outbox_post("testuser")
+        .set_json(undo_follow("https://remote.example/users/carol"))
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 404);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Follow not found");
+    assert!(queued(db).await.is_empty());
+}
@@ -0,0 +1,156 @@
+mod common;
+
+use actix_web::{test, web, App};
+use common::OfflineHttpClient;
+use feder8::config::Config;
+use feder8::database::{DatabaseError, DatabaseRef, MockDatabase};
+use feder8::handlers;
+use feder8::health::{DatabaseHealth, ServiceUnavailable};
+use feder8::metered_database::MeteredDatabase;
+use feder8::metrics::Metrics;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+
+const RETRY_AFTER_SECS: u64 = 7;
+
+/// A database whose actor lookups always fail with `error`, tracked by a
+/// fresh health monitor
+fn failing_database(error: fn() -> DatabaseError) -> (DatabaseRef, Arc<DatabaseHealth>) {
+    let mut mock = MockDatabase::new();
+    mock.expect_get_actor_by_username()
+        .returning(move |_| Err(error()));
+    mock.expect_ping().returning(|| Ok(()));
+
+    let health = Arc::new(DatabaseHealth::new(0.5, Arc::new(Metrics::new())));
+    let db = MeteredDatabase::unmetered(Arc::new(mock), Duration::from_secs(1))
+        .with_health(health.clone());
+    (Arc::new(db), health)
+}
+
+fn create_test_app(
+    db: DatabaseRef,
+    health: Arc<DatabaseHealth>,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse<impl actix_web::body::MessageBody>,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    common::test_app(&db, Config::default(), Arc::new(OfflineHttpClient))
+        .wrap(ServiceUnavailable::new(RETRY_AFTER_SECS))
+        .app_data(web::Data::from(health))
+        .service(handlers::outbox::get_outbox)
+        .service(handlers::health::healthz)
+        .service(handlers::health::readyz)
+}
+
+#[tokio::test]
+async fn test_unavailable_database_returns_503_with_retry_after() {
+    let (db, health) = failing_database(|| DatabaseError::Connection("pool timed out".into()));
+    let app = test::init_service(create_test_app(db, health)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/users/alice/outbox")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 503);
+    assert_eq!(
+        resp.headers().get("Retry-After").unwrap(),
+        &RETRY_AFTER_SECS.to_string()
+    );
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Service temporarily unavailable");
+    assert_eq!(body["code"], "service_unavailable");
+}
+
+#[tokio::test]
+async fn test_query_errors_stay_500() {
+    let (db, health) = failing_database(|| DatabaseError::Query("no such column".into()));
+    let app = test::init_service(create_test_app(db, health.clone())).await;
+
+    let req = test::TestRequest::get()
+        .uri("/users/alice/outbox")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 500);
+    assert!(resp.headers().get("Retry-After").is_none());
+    assert_eq!(health.failure_rate(), 0.0);
+}
+
+#[tokio::test]
+async fn test_readiness_flips_when_failures_exceed_budget() {
+    let (db, health) = failing_database(|| DatabaseError::Connection("database is locked".into()));
+    let app = test::init_service(create_test_app(db, health)).await;
+
+    let req = test::TestRequest::get().uri("/readyz").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    for _ in 0..10 {
+        let req = test::TestRequest::get()
+            .uri("/users/alice/outbox")
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 503);
+    }
+
+    let req = test::TestRequest::get().uri("/readyz").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 503);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], "not ready");
+    assert_eq!(body["checks"]["database"]["status"], "ok");
+    assert_eq!(body["checks"]["error_budget"]["status"], "error");
+}
+
+#[tokio::test]
+async fn test_readiness_reports_each_check() {
+    let (db, health) = failing_database(|| DatabaseError::Query("unused".into()));
+    let app = test::init_service(create_test_app(db, health)).await;
+
+    let req = test::TestRequest::get().uri("/readyz").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["status"], "ready");
+    assert_eq!(body["checks"]["database"]["status"], "ok");
+    assert_eq!(body["checks"]["error_budget"]["status"], "ok");
+    assert_eq!(body["checks"]["error_budget"]["failure_rate"], 0.0);
+}
+
+#[tokio::test]
+async fn test_readiness_fails_when_ping_fails() {
+    let mut mock = MockDatabase::new();
+    mock.expect_ping().returning(|| {
+        Err(DatabaseError::Connection(
+            "unable to open database file".into(),
+        ))
+    });
+    let health = Arc::new(DatabaseHealth::new(0.5, Arc::new(Metrics::new())));
+    let app = test::init_service(create_test_app(Arc::new(mock), health)).await;
+
+    let req = test::TestRequest::get().uri("/readyz").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 503);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["checks"]["database"]["status"], "error");
+    // Unauthenticated callers don't get to see why
+    assert!(!body.to_string().contains("unable to open database file"));
+
+    // Liveness doesn't depend on the database
+    let req = test::TestRequest::get().uri("/healthz").to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+}
+
+#[tokio::test]
+async fn test_pool_timeouts_count_as_unavailable() {
+    assert!(DatabaseError::from(sqlx::Error::PoolTimedOut).is_unavailable());
+    assert!(DatabaseError::from(sqlx::Error::PoolClosed).is_unavailable());
+    assert!(!DatabaseError::from(sqlx::Error::RowNotFound).is_unavailable());
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use actix_web::test;
+use chrono::Utc;
+use common::OfflineHttpClient;
+use feder8::config::Config;
+use feder8::database::{Database, DatabaseRef, DbActor, DbNote, DbRemoteActor, PublishState};
+use feder8::handlers;
+use feder8::models::Visibility;
+use serde_json::Value;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+fn local_actor(username: &str) -> DbActor {
+    common::actor(
+        &format!("https://example.com/users/{username}"),
+        username,
+        username,
+    )
+}
+
+fn note(id: &str, author: &str, state: PublishState) -> DbNote {
+    DbNote {
+        id: id.to_string(),
+        attributed_to: author.to_string(),
+        content: "Hello".to_string(),
+        to_recipients: vec![],
+        cc_recipients: vec![],
+        published: Utc::now(),
+        in_reply_to: None,
+        tags: vec![],
+        attachments: vec![],
+        visibility: Visibility::Public,
+        state,
+        pinned: false,
+        created_at: Utc::now(),
+        in_reply_to_actor: None,
+        thread_depth: Some(0),
+        thread_truncated: false,
+        sensitive: false,
+        summary: None,
+        language: None,
+        content_map: None,
+    }
+}
+
+fn remote_actor(id: &str) -> DbRemoteActor {
+    DbRemoteActor {
+        id: id.to_string(),
+        username: id.rsplit('/').next().unwrap().to_string(),
+        name: None,
+        avatar_url: None,
+        inbox: None,
+        shared_inbox: None,
+        public_key_pem: None,
+        fetched_at: Utc::now(),
+    }
+}
+
+/// Two local actors, two published local notes plus a scheduled one and a
+/// remote one, and remote actors on two hosts
+async fn create_test_database() -> (TempDir, DatabaseRef) {
+    let dir = TempDir::new().unwrap();
+    let db = common::sqlite_database(&dir).await;
+
+    let alice = local_actor("alice");
+    db.create_actor(&alice).await.unwrap();
+    db.create_actor(&local_actor("bob")).await.unwrap();
+
+    for (id, author, state) in [
+        (
+            "https://example.com/notes/1",
+            alice.id.as_str(),
+            PublishState::Published,
+        ),
+        (
+            "https://example.com/notes/2",
+            alice.id.as_str(),
+            PublishState::Published,
+        ),
+        (
+            "https://example.com/notes/3",
+            alice.id.as_str(),
+            PublishState::Scheduled,
+        ),
+        (
+            "https://remote.example/notes/1",
+            "https://remote.example/users/carol",
+            PublishState::Published,
+        ),
+    ] {
+        db.create_note(&note(id, author, state)).await.unwrap();
+    }
+
+    for id in [
+        "https://remote.example/users/carol",
+        "https://remote.example/users/dan",
+        "https://other.example:8443/users/erin",
+    ] {
+        db.upsert_remote_actor(&remote_actor(id)).await.unwrap();
+    }
+
+    (dir, Arc::new(db))
+}
+
+#[tokio::test]
+async fn test_instance_metadata() {
+    let (_dir, db) = create_test_database().await;
+    let config = Config {
+        server_name: "Test Node".to_string(),
+        max_note_length: 1000,
+        instance_description: "A node for tests".to_string(),
+        contact_email: Some("admin@example.com".to_string()),
+        ..common::test_config()
+    };
+    let app = test::init_service(
+        common::test_app(&db, config, Arc::new(OfflineHttpClient))
+            .service(handlers::api::instance::get_instance),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/v1/instance")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200);
+
+    let body: Value = test::read_body_json(resp).await;
+    for key in [
+        "uri",
+        "title",
+        "short_description",
+        "description",
+        "email",
+        "version",
+        "urls",
+        "stats",
+        "languages",
+        "registrations",
+        "max_toot_chars",
+        "configuration",
+    ] {
+        assert!(body.get(key).is_some(), "missing {key}");
+    }
+
+    assert_eq!(body["uri"], "example.com");
+    assert_eq!(body["title"], "Test Node");
+    assert_eq!(body["description"], "A node for tests");
+    assert_eq!(body["email"], "admin@example.com");
+    assert!(body["version"]
+        .as_str()
+        .unwrap()
+        .contains("(compatible; feder8"));
+    assert_eq!(body["max_toot_chars"], 1000);
+    assert_eq!(body["configuration"]["statuses"]["max_characters"], 1000);
+
+    assert_eq!(body["stats"]["user_count"], 2);
+    assert_eq!(body["stats"]["status_count"], 2);
+    assert_eq!(body["stats"]["domain_count"], 2);
+}
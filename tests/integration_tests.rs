@@ -1,13 +1,38 @@
+mod common;
+
 use actix_web::{http::StatusCode, test, web, App};
+use chrono::Utc;
+use feder8::services::document_cache::DocumentCache;
+use feder8::services::log_dedup::LogDedup;
+use feder8::urls::UrlBuilder;
 use feder8::{
+    capabilities::Capabilities,
     config::Config,
-    database::{create_configured_mock_database, DatabaseRef},
+    database::{
+        create_configured_mock_database, create_configured_mock_database_with_token, DatabaseRef,
+        DbActor, DbToken, MockDatabase,
+    },
     handlers,
+    http::{json_config, json_errors::MAX_JSON_PAYLOAD, HttpClient, ReqwestClient},
     models::Actor,
+    services::{
+        backpressure::InboxBackpressure, delivery::DeliveryService, seen_activities::SeenActivities,
+    },
 };
 use serde_json::{json, Value};
 use std::sync::Arc;
 
+/// Alice as the configured mock database knows her
+const ALICE: &str = "https://example.com/users/alice";
+const ALICE_TOKEN: &str = "alice-token";
+
+/// A POST to Alice's outbox, authorized as her
+fn outbox_post() -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/users/alice/outbox")
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+}
+
 fn create_test_config() -> Config {
     Config {
         server_name: "Test Server".to_string(),
@@ -16,15 +41,35 @@ fn create_test_config() -> Config {
         actor_name: "testuser".to_string(),
         private_key_path: None,
         public_key_path: None,
+        ..Config::default()
     }
 }
 
+fn create_test_http_client() -> web::Data<dyn HttpClient> {
+    let client: Arc<dyn HttpClient> = Arc::new(ReqwestClient::new());
+    web::Data::from(client)
+}
+
+/// Fan-out for an actor nobody follows
+fn create_test_delivery() -> web::Data<DeliveryService> {
+    let mut mock = MockDatabase::new();
+    mock.expect_get_followers().returning(|_, _, _| Ok(vec![]));
+    web::Data::new(DeliveryService::new(
+        create_test_config(),
+        Arc::new(ReqwestClient::new()),
+        Arc::new(mock),
+    ))
+}
+
 #[actix_web::test]
 async fn test_webfinger_valid_request() {
     let config = create_test_config();
+    let db: DatabaseRef = Arc::new(create_configured_mock_database());
     let app = test::init_service(
         App::new()
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
             .app_data(web::Data::new(config))
+            .app_data(web::Data::new(db))
             .service(handlers::webfinger::webfinger),
     )
     .await;
@@ -53,9 +98,12 @@ async fn test_webfinger_valid_request() {
 #[actix_web::test]
 async fn test_webfinger_invalid_domain() {
     let config = create_test_config();
+    let db: DatabaseRef = Arc::new(create_configured_mock_database());
     let app = test::init_service(
         App::new()
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
             .app_data(web::Data::new(config))
+            .app_data(web::Data::new(db))
             .service(handlers::webfinger::webfinger),
     )
     .await;
@@ -71,9 +119,12 @@ async fn test_webfinger_invalid_domain() {
 #[actix_web::test]
 async fn test_webfinger_malformed_resource() {
     let config = create_test_config();
+    let db: DatabaseRef = Arc::new(create_configured_mock_database());
     let app = test::init_service(
         App::new()
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
             .app_data(web::Data::new(config))
+            .app_data(web::Data::new(db))
             .service(handlers::webfinger::webfinger),
     )
     .await;
@@ -83,15 +134,331 @@ async fn test_webfinger_malformed_resource() {
         .to_request();
 
     let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "bad_request");
+}
+
+#[actix_web::test]
+async fn test_webfinger_acct_domain_variants() {
+    let config = Config {
+        server_url: "https://Test.Example.com/".to_string(),
+        ..create_test_config()
+    };
+    let db: DatabaseRef = Arc::new(create_configured_mock_database());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(db))
+            .service(handlers::webfinger::webfinger),
+    )
+    .await;
+
+    for (resource, status) in [
+        ("acct:testuser@test.example.com", StatusCode::OK),
+        ("acct:testuser@TEST.EXAMPLE.COM", StatusCode::OK),
+        ("acct:testuser@test.example.com:443", StatusCode::OK),
+        ("ACCT:testuser@test.example.com", StatusCode::OK),
+        ("acct%3Atestuser%40test.example.com", StatusCode::OK),
+        // Decoded once, like any other query parameter
+        (
+            "acct%3Atestuser%2540test.example.com",
+            StatusCode::BAD_REQUEST,
+        ),
+        ("acct:testuser@test.example.com:8443", StatusCode::NOT_FOUND),
+        ("acct:testuser@example.com", StatusCode::NOT_FOUND),
+        (
+            "acct:testuser@test.example.com/users",
+            StatusCode::BAD_REQUEST,
+        ),
+        ("acct:@test.example.com", StatusCode::BAD_REQUEST),
+    ] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/.well-known/webfinger?resource={resource}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), status, "{resource}");
+    }
+}
+
+/// Mock database that knows only the actor stored as
+/// `https://test.example.com/users/alice`
+fn create_webfinger_test_db() -> DatabaseRef {
+    fn alice() -> DbActor {
+        common::actor("https://test.example.com/users/alice", "alice", "Alice")
+    }
+
+    let mut mock = MockDatabase::new();
+    mock.expect_get_actor_by_id()
+        .returning(|id| Ok((id == "https://test.example.com/users/alice").then(alice)));
+    mock.expect_get_actor_by_username()
+        .returning(|username| Ok((username == "alice").then(alice)));
+    Arc::new(mock)
+}
+
+#[actix_web::test]
+async fn test_webfinger_acct_only_for_existing_actors() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_config()))
+            .app_data(web::Data::new(UrlBuilder::from_config(
+                &create_test_config(),
+            )))
+            .app_data(web::Data::new(create_webfinger_test_db()))
+            .service(handlers::webfinger::webfinger),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/.well-known/webfinger?resource=acct:alice@test.example.com")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(
+        body["links"][0]["href"],
+        "https://test.example.com/users/alice"
+    );
+
+    // No actor, no answer a remote server would then 404 on
+    let req = test::TestRequest::get()
+        .uri("/.well-known/webfinger?resource=acct:bob@test.example.com")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), StatusCode::NOT_FOUND);
 }
 
+#[actix_web::test]
+async fn test_webfinger_https_resource() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_config()))
+            .app_data(web::Data::new(UrlBuilder::from_config(
+                &create_test_config(),
+            )))
+            .app_data(web::Data::new(create_webfinger_test_db()))
+            .service(handlers::webfinger::webfinger),
+    )
+    .await;
+
+    for resource in [
+        "https://test.example.com/users/alice",
+        "https://TEST.Example.com/users/alice/",
+    ] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/.well-known/webfinger?resource={resource}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK, "{resource}");
+
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["subject"], "acct:alice@test.example.com");
+        assert_eq!(
+            body["aliases"],
+            json!([
+                "acct:alice@test.example.com",
+                "https://test.example.com/users/alice"
+            ])
+        );
+        let self_link = body["links"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|link| link["rel"] == "self")
+            .unwrap();
+        assert_eq!(self_link["href"], "https://test.example.com/users/alice");
+        assert_eq!(self_link["type"], "application/activity+json");
+    }
+}
+
+#[actix_web::test]
+async fn test_webfinger_acct_resource_has_aliases() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_config()))
+            .app_data(web::Data::new(UrlBuilder::from_config(
+                &create_test_config(),
+            )))
+            .app_data(web::Data::new(create_webfinger_test_db()))
+            .service(handlers::webfinger::webfinger),
+    )
+    .await;
+
+    // The subject is normalized whatever case or port the query used
+    for resource in [
+        "acct:alice@test.example.com",
+        "ACCT:alice@TEST.example.com:443",
+    ] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/.well-known/webfinger?resource={resource}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK, "{resource}");
+
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["subject"], "acct:alice@test.example.com");
+        assert_eq!(
+            body["aliases"],
+            json!([
+                "acct:alice@test.example.com",
+                "https://test.example.com/users/alice"
+            ])
+        );
+        assert_eq!(body["links"].as_array().unwrap().len(), 2);
+    }
+}
+
+#[actix_web::test]
+async fn test_webfinger_rel_filter() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_config()))
+            .app_data(web::Data::new(UrlBuilder::from_config(
+                &create_test_config(),
+            )))
+            .app_data(web::Data::new(create_webfinger_test_db()))
+            .service(handlers::webfinger::webfinger),
+    )
+    .await;
+
+    let profile_page = "http://webfinger.net/rel/profile-page";
+    for (query, expected) in [
+        ("rel=self", vec!["self"]),
+        (
+            "rel=http%3A%2F%2Fwebfinger.net%2Frel%2Fprofile-page",
+            vec![profile_page],
+        ),
+        (
+            "rel=self&rel=http%3A%2F%2Fwebfinger.net%2Frel%2Fprofile-page",
+            vec!["self", profile_page],
+        ),
+        (
+            "rel=http%3A%2F%2Fopenid.net%2Fspecs%2Fconnect%2F1.0%2Fissuer",
+            vec![],
+        ),
+    ] {
+        for resource in [
+            "acct:alice@test.example.com",
+            "https://test.example.com/users/alice",
+        ] {
+            let req = test::TestRequest::get()
+                .uri(&format!(
+                    "/.well-known/webfinger?resource={resource}&{query}"
+                ))
+                .to_request();
+            let resp = test::call_service(&app, req).await;
+            assert_eq!(resp.status(), StatusCode::OK, "{resource} {query}");
+
+            let body: Value = test::read_body_json(resp).await;
+            let rels: Vec<&str> = body["links"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|link| link["rel"].as_str().unwrap())
+                .collect();
+            assert_eq!(rels, expected, "{resource} {query}");
+            // Filtering leaves the subject and aliases alone
+            assert_eq!(body["subject"], "acct:alice@test.example.com");
+            assert_eq!(body["aliases"].as_array().unwrap().len(), 2);
+        }
+    }
+}
+
+#[actix_web::test]
+async fn test_webfinger_unknown_or_malformed_https_resource() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_config()))
+            .app_data(web::Data::new(UrlBuilder::from_config(
+                &create_test_config(),
+            )))
+            .app_data(web::Data::new(create_webfinger_test_db()))
+            .service(handlers::webfinger::webfinger),
+    )
+    .await;
+
+    for (resource, status) in [
+        ("https://test.example.com/users/bob", StatusCode::NOT_FOUND),
+        ("ftp://test.example.com/users/alice", StatusCode::NOT_FOUND),
+        ("https:///users/alice", StatusCode::BAD_REQUEST),
+        ("acct:alice", StatusCode::BAD_REQUEST),
+    ] {
+        let req = test::TestRequest::get()
+            .uri(&format!("/.well-known/webfinger?resource={resource}"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), status, "{resource}");
+    }
+}
+
+#[actix_web::test]
+async fn test_host_meta_xrd_and_json() {
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(create_test_config()))
+            .app_data(web::Data::new(UrlBuilder::from_config(
+                &create_test_config(),
+            )))
+            .service(handlers::host_meta::host_meta)
+            .service(handlers::host_meta::host_meta_json),
+    )
+    .await;
+    let template = "https://test.example.com/.well-known/webfinger?resource={uri}";
+
+    let req = test::TestRequest::get()
+        .uri("/.well-known/host-meta")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert!(resp
+        .headers()
+        .get("content-type")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .starts_with("application/xrd+xml"));
+    assert_eq!(resp.headers().get("vary").unwrap(), "Accept");
+    let body = test::read_body(resp).await;
+    let xrd = std::str::from_utf8(&body).unwrap();
+    assert!(xrd.contains(r#"<XRD xmlns="http://docs.oasis-open.org/ns/xri/xrd-1.0">"#));
+    assert!(xrd.contains(&format!(r#"<Link rel="lrdd" template="{template}"/>"#)));
+
+    for req in [
+        test::TestRequest::get().uri("/.well-known/host-meta.json"),
+        test::TestRequest::get()
+            .uri("/.well-known/host-meta")
+            .insert_header(("Accept", "application/json")),
+    ] {
+        let resp = test::call_service(&app, req.to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(
+            resp.headers().get("content-type").unwrap(),
+            "application/jrd+json"
+        );
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(body["links"][0]["rel"], "lrdd");
+        assert_eq!(body["links"][0]["template"], template);
+    }
+
+    // The negotiated JSON form varies by Accept as much as the XRD one does
+    let req = test::TestRequest::get()
+        .uri("/.well-known/host-meta")
+        .insert_header(("Accept", "application/json"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.headers().get("vary").unwrap(), "Accept");
+}
+
 #[actix_web::test]
 async fn test_webfinger_missing_resource() {
     let config = create_test_config();
+    let db: DatabaseRef = Arc::new(create_configured_mock_database());
     let app = test::init_service(
         App::new()
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
             .app_data(web::Data::new(config))
+            .app_data(web::Data::new(db))
             .service(handlers::webfinger::webfinger),
     )
     .await;
@@ -112,7 +479,12 @@ async fn test_get_actor() {
     let app = test::init_service(
         App::new()
             .app_data(web::Data::new(config.clone()))
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
             .app_data(web::Data::new(db))
+            .app_data(create_test_http_client())
+            .app_data(web::Data::new(DocumentCache::from_config(
+                &Config::default(),
+            )))
             .service(handlers::actor::get_actor),
     )
     .await;
@@ -139,8 +511,13 @@ async fn test_get_actor_different_username() {
     let db: DatabaseRef = Arc::new(create_configured_mock_database());
     let app = test::init_service(
         App::new()
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
             .app_data(web::Data::new(config))
             .app_data(web::Data::new(db))
+            .app_data(create_test_http_client())
+            .app_data(web::Data::new(DocumentCache::from_config(
+                &Config::default(),
+            )))
             .service(handlers::actor::get_actor),
     )
     .await;
@@ -166,14 +543,14 @@ async fn test_inbox_create_activity() {
     // Set up expectations for inbox processing
     mock.expect_get_actor_by_username().returning(|username| {
         Ok(Some(feder8::database::DbActor {
-            id: format!("https://test.example.com/users/{username}"),
-            username: username.to_string(),
-            name: format!("Test User {username}"),
             summary: Some("A test user".to_string()),
-            public_key_pem: "test_key".to_string(),
-            private_key_pem: None,
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
+            ..common::actor(
+                &format!("https://test.example.com/users/{username}"),
+                username,
+                &format!("Test User {username}"),
+            )
         }))
     });
 
@@ -183,11 +560,26 @@ async fn test_inbox_create_activity() {
 
     mock.expect_create_activity().returning(|_| Ok(()));
 
+    mock.expect_is_blocked().returning(|_, _| Ok(false)); // Sender isn't blocked
+
+    mock.expect_count_pending_deliveries().returning(|| Ok(0)); // Nothing queued
+
+    mock.expect_get_activity_by_id().returning(|_| Ok(None)); // Not a redelivery
+
     let db: DatabaseRef = Arc::new(mock);
     let app = test::init_service(
         App::new()
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
             .app_data(web::Data::new(config))
             .app_data(web::Data::new(db))
+            .app_data(create_test_http_client())
+            .app_data(web::Data::new(SeenActivities::new(1000)))
+            .app_data(web::Data::new(InboxBackpressure::from_config(
+                &Config::default(),
+                Arc::default(),
+            )))
+            .app_data(web::Data::new(LogDedup::from_config(&Config::default())))
+            .app_data(create_test_delivery())
             .service(handlers::inbox::inbox),
     )
     .await;
@@ -222,8 +614,17 @@ async fn test_inbox_follow_activity() {
     let db: DatabaseRef = Arc::new(create_configured_mock_database());
     let app = test::init_service(
         App::new()
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
             .app_data(web::Data::new(config))
             .app_data(web::Data::new(db))
+            .app_data(create_test_http_client())
+            .app_data(web::Data::new(SeenActivities::new(1000)))
+            .app_data(web::Data::new(InboxBackpressure::from_config(
+                &Config::default(),
+                Arc::default(),
+            )))
+            .app_data(web::Data::new(LogDedup::from_config(&Config::default())))
+            .app_data(create_test_delivery())
             .service(handlers::inbox::inbox),
     )
     .await;
@@ -254,8 +655,17 @@ async fn test_inbox_unknown_activity() {
     let db: DatabaseRef = Arc::new(create_configured_mock_database());
     let app = test::init_service(
         App::new()
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
             .app_data(web::Data::new(config))
             .app_data(web::Data::new(db))
+            .app_data(create_test_http_client())
+            .app_data(web::Data::new(SeenActivities::new(1000)))
+            .app_data(web::Data::new(InboxBackpressure::from_config(
+                &Config::default(),
+                Arc::default(),
+            )))
+            .app_data(web::Data::new(LogDedup::from_config(&Config::default())))
+            .app_data(create_test_delivery())
             .service(handlers::inbox::inbox),
     )
     .await;
@@ -286,6 +696,7 @@ async fn test_get_outbox() {
     let db: DatabaseRef = Arc::new(create_configured_mock_database());
     let app = test::init_service(
         App::new()
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
             .app_data(web::Data::new(config))
             .app_data(web::Data::new(db))
             .service(handlers::outbox::get_outbox),
@@ -303,26 +714,36 @@ async fn test_get_outbox() {
     let body: Value = test::read_body_json(resp).await;
     assert_eq!(body["type"], "OrderedCollection");
     assert_eq!(body["totalItems"], 5); // Mock returns 5 items
-    assert!(body["orderedItems"].is_array());
-    assert!(body["orderedItems"].as_array().unwrap().is_empty()); // But activities list is empty
+    assert!(body["first"]["orderedItems"].is_array());
+    assert!(body["first"]["orderedItems"].as_array().unwrap().is_empty()); // But activities list is empty
 }
 
 #[actix_web::test]
 async fn test_post_outbox_create_activity() {
     let config = create_test_config();
     let mut mock = feder8::database::MockDatabase::new();
+    mock.expect_validate_token().returning(|_| {
+        Ok(Some(DbToken {
+            id: "test-token".to_string(),
+            token_hash: String::new(),
+            actor_id: "https://test.example.com/users/alice".to_string(),
+            scopes: vec!["write".to_string()],
+            created_at: Utc::now(),
+            expires_at: None,
+        }))
+    });
 
     // Set up expectations for outbox processing
     mock.expect_get_actor_by_username().returning(|username| {
         Ok(Some(feder8::database::DbActor {
-            id: format!("https://test.example.com/users/{username}"),
-            username: username.to_string(),
-            name: format!("Test User {username}"),
             summary: Some("A test user".to_string()),
-            public_key_pem: "test_key".to_string(),
-            private_key_pem: None,
             created_at: chrono::Utc::now(),
             updated_at: chrono::Utc::now(),
+            ..common::actor(
+                &format!("https://test.example.com/users/{username}"),
+                username,
+                &format!("Test User {username}"),
+            )
         }))
     });
 
@@ -333,8 +754,11 @@ async fn test_post_outbox_create_activity() {
     let db: DatabaseRef = Arc::new(mock);
     let app = test::init_service(
         App::new()
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
             .app_data(web::Data::new(config))
             .app_data(web::Data::new(db))
+            .app_data(create_test_http_client())
+            .app_data(create_test_delivery())
             .service(handlers::outbox::post_outbox),
     )
     .await;
@@ -352,8 +776,7 @@ async fn test_post_outbox_create_activity() {
         "cc": ["https://test.example.com/users/alice/followers"]
     });
 
-    let req = test::TestRequest::post()
-        .uri("/users/alice/outbox")
+    let req = outbox_post()
         .insert_header(("Content-Type", "application/activity+json"))
         .set_json(&create_activity)
         .to_request();
@@ -365,32 +788,156 @@ async fn test_post_outbox_create_activity() {
 #[actix_web::test]
 async fn test_post_outbox_unsupported_activity() {
     let config = create_test_config();
-    let db: DatabaseRef = Arc::new(create_configured_mock_database());
+    let db: DatabaseRef = Arc::new(create_configured_mock_database_with_token(
+        ALICE_TOKEN,
+        ALICE,
+    ));
     let app = test::init_service(
         App::new()
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
             .app_data(web::Data::new(config))
             .app_data(web::Data::new(db))
+            .app_data(create_test_http_client())
+            .app_data(create_test_delivery())
             .service(handlers::outbox::post_outbox),
     )
     .await;
 
-    let follow_activity = json!({
+    let arrive_activity = json!({
         "@context": ["https://www.w3.org/ns/activitystreams"],
-        "type": "Follow",
+        "type": "Arrive",
         "actor": "https://test.example.com/users/alice",
-        "object": "https://example.com/users/bob",
+        "object": "https://example.com/places/home",
         "to": ["https://example.com/users/bob"],
         "cc": []
     });
 
-    let req = test::TestRequest::post()
-        .uri("/users/alice/outbox")
+    let req = outbox_post()
         .insert_header(("Content-Type", "application/activity+json"))
-        .set_json(&follow_activity)
+        .set_json(&arrive_activity)
         .to_request();
 
     let resp = test::call_service(&app, req).await;
-    assert_eq!(resp.status(), StatusCode::CREATED); // Still accepts but logs as unsupported
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Unsupported activity type: Arrive");
+}
+
+#[actix_web::test]
+async fn test_post_outbox_create_non_note_object() {
+    let config = create_test_config();
+    let db: DatabaseRef = Arc::new(create_configured_mock_database_with_token(
+        ALICE_TOKEN,
+        ALICE,
+    ));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(db))
+            .app_data(create_test_http_client())
+            .app_data(create_test_delivery())
+            .service(handlers::outbox::post_outbox),
+    )
+    .await;
+
+    let create_activity = json!({
+        "@context": ["https://www.w3.org/ns/activitystreams"],
+        "type": "Create",
+        "actor": "https://test.example.com/users/alice",
+        "object": {
+            "type": "Question",
+            "content": "Tea or coffee?"
+        }
+    });
+
+    let req = outbox_post()
+        .insert_header(("Content-Type", "application/activity+json"))
+        .set_json(&create_activity)
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Unsupported object type: Question");
+}
+
+#[actix_web::test]
+async fn test_post_outbox_create_missing_object() {
+    let config = create_test_config();
+    let db: DatabaseRef = Arc::new(create_configured_mock_database_with_token(
+        ALICE_TOKEN,
+        ALICE,
+    ));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(db))
+            .app_data(create_test_http_client())
+            .app_data(create_test_delivery())
+            .service(handlers::outbox::post_outbox),
+    )
+    .await;
+
+    let create_activity = json!({
+        "@context": ["https://www.w3.org/ns/activitystreams"],
+        "type": "Create",
+        "actor": "https://test.example.com/users/alice",
+        "to": ["https://www.w3.org/ns/activitystreams#Public"]
+    });
+
+    let req = outbox_post()
+        .insert_header(("Content-Type", "application/activity+json"))
+        .set_json(&create_activity)
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Missing activity object");
+}
+
+#[actix_web::test]
+async fn test_post_outbox_missing_type() {
+    let config = create_test_config();
+    let db: DatabaseRef = Arc::new(create_configured_mock_database_with_token(
+        ALICE_TOKEN,
+        ALICE,
+    ));
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(db))
+            .app_data(create_test_http_client())
+            .app_data(create_test_delivery())
+            .service(handlers::outbox::post_outbox),
+    )
+    .await;
+
+    let activity = json!({
+        "@context": ["https://www.w3.org/ns/activitystreams"],
+        "actor": "https://test.example.com/users/alice",
+        "object": {
+            "type": "Note",
+            "content": "No type on the wrapper"
+        }
+    });
+
+    let req = outbox_post()
+        .insert_header(("Content-Type", "application/activity+json"))
+        .set_json(&activity)
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Missing activity type");
 }
 
 #[actix_web::test]
@@ -399,15 +946,23 @@ async fn test_content_type_headers() {
     let db: DatabaseRef = Arc::new(create_configured_mock_database());
     let app = test::init_service(
         App::new()
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
             .app_data(web::Data::new(config))
             .app_data(web::Data::new(db))
+            .app_data(create_test_http_client())
+            .app_data(web::Data::new(DocumentCache::from_config(
+                &Config::default(),
+            )))
             .service(handlers::actor::get_actor)
             .service(handlers::outbox::get_outbox),
     )
     .await;
 
     // Test actor endpoint content type
-    let req = test::TestRequest::get().uri("/users/alice").to_request();
+    let req = test::TestRequest::get()
+        .uri("/users/alice")
+        .insert_header(("Accept", "application/activity+json"))
+        .to_request();
 
     let resp = test::call_service(&app, req).await;
     assert_eq!(resp.status(), StatusCode::OK);
@@ -433,12 +988,75 @@ async fn test_content_type_headers() {
         .contains("application/activity+json"));
 }
 
+#[actix_web::test]
+async fn test_ld_json_content_negotiation() {
+    const LD_JSON: &str = "application/ld+json; profile=\"https://www.w3.org/ns/activitystreams\"";
+
+    let config = create_test_config();
+    let db: DatabaseRef = Arc::new(create_configured_mock_database());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(db))
+            .app_data(create_test_http_client())
+            .app_data(web::Data::new(DocumentCache::from_config(
+                &Config::default(),
+            )))
+            .service(handlers::actor::get_actor)
+            .service(handlers::outbox::get_outbox),
+    )
+    .await;
+
+    for (uri, vary) in [
+        ("/users/alice", "Accept"),
+        // The owner sees more of the outbox than anyone else
+        ("/users/alice/outbox", "Accept, Authorization"),
+    ] {
+        let req = test::TestRequest::get()
+            .uri(uri)
+            .insert_header(("Accept", LD_JSON))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get("content-type").unwrap(), LD_JSON);
+        assert_eq!(resp.headers().get("vary").unwrap(), vary);
+
+        // Plain ld+json gets the ActivityStreams profile too
+        let req = test::TestRequest::get()
+            .uri(uri)
+            .insert_header(("Accept", "application/ld+json"))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.headers().get("content-type").unwrap(), LD_JSON);
+
+        let req = test::TestRequest::get()
+            .uri(uri)
+            .insert_header((
+                "Accept",
+                "application/ld+json;q=0.5, application/activity+json",
+            ))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(
+            resp.headers().get("content-type").unwrap(),
+            "application/activity+json"
+        );
+    }
+}
+
 #[actix_web::test]
 async fn test_webfinger_content_type() {
     let config = create_test_config();
+    let db: DatabaseRef = Arc::new(create_configured_mock_database());
     let app = test::init_service(
         App::new()
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
             .app_data(web::Data::new(config))
+            .app_data(web::Data::new(db))
             .service(handlers::webfinger::webfinger),
     )
     .await;
@@ -457,48 +1075,229 @@ async fn test_webfinger_content_type() {
         .contains("application/jrd+json"));
 }
 
-#[actix_web::test]
-async fn test_inbox_malformed_json() {
+/// Post a raw body to the inbox of an app using the production JSON config
+async fn post_raw_to_inbox(content_type: &str, body: Vec<u8>) -> (StatusCode, Value) {
     let config = create_test_config();
     let db: DatabaseRef = Arc::new(create_configured_mock_database());
     let app = test::init_service(
         App::new()
+            .app_data(json_config())
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
             .app_data(web::Data::new(config))
             .app_data(web::Data::new(db))
+            .app_data(create_test_http_client())
+            .app_data(web::Data::new(SeenActivities::new(1000)))
+            .app_data(web::Data::new(InboxBackpressure::from_config(
+                &Config::default(),
+                Arc::default(),
+            )))
+            .app_data(web::Data::new(LogDedup::from_config(&Config::default())))
+            .app_data(create_test_delivery())
             .service(handlers::inbox::inbox),
     )
     .await;
 
     let req = test::TestRequest::post()
         .uri("/users/bob/inbox")
-        .insert_header(("Content-Type", "application/activity+json"))
-        .set_payload("{invalid json}")
+        .insert_header(("Content-Type", content_type))
+        .set_payload(body)
         .to_request();
 
     let resp = test::call_service(&app, req).await;
-    // Should handle malformed JSON gracefully
-    assert_ne!(resp.status(), StatusCode::ACCEPTED);
+    let status = resp.status();
+    let body = test::read_body(resp).await;
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+/// A form body with the activity as JSON in its `body` parameter, the way
+/// some older clients post
+fn form_encoded(activity: &Value) -> Vec<u8> {
+    url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("body", &activity.to_string())
+        .finish()
+        .into_bytes()
+}
+
+#[actix_web::test]
+async fn test_inbox_malformed_json() {
+    let (status, body) =
+        post_raw_to_inbox("application/activity+json", b"{invalid json}".to_vec()).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "Invalid JSON");
+    // The parser's position is passed on so senders can find the problem
+    assert_eq!(body["detail"], "key must be a string at line 1 column 2");
+}
+
+#[actix_web::test]
+async fn test_inbox_wrong_content_type() {
+    let (status, body) = post_raw_to_inbox("text/plain", br#"{"type": "Follow"}"#.to_vec()).await;
+
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(body["error"], "Unsupported content type");
+    assert_eq!(
+        body["detail"],
+        "Expected application/activity+json or application/json"
+    );
+}
+
+#[actix_web::test]
+async fn test_inbox_oversized_payload() {
+    let oversized = format!(
+        r#"{{"type": "Create", "content": "{}"}}"#,
+        "x".repeat(MAX_JSON_PAYLOAD)
+    );
+    let (status, body) = post_raw_to_inbox("application/activity+json", oversized.into()).await;
+
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(body["error"], "Payload too large");
+    assert!(body["detail"]
+        .as_str()
+        .unwrap()
+        .ends_with(&format!("exceeds the limit of {MAX_JSON_PAYLOAD} bytes")));
+}
+
+#[actix_web::test]
+async fn test_inbox_form_encoded_body() {
+    let follow = json!({
+        "@context": ["https://www.w3.org/ns/activitystreams"],
+        "id": "https://example.com/activities/form-follow",
+        "type": "Follow",
+        "actor": "https://example.com/users/alice",
+        "object": "https://test.example.com/users/bob"
+    });
+    let form = "application/x-www-form-urlencoded";
+
+    let (status, _) = post_raw_to_inbox(form, form_encoded(&follow)).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+
+    // JSON mislabelled as a form is taken as it is
+    let (status, _) = post_raw_to_inbox(form, follow.to_string().into_bytes()).await;
+    assert_eq!(status, StatusCode::ACCEPTED);
+}
+
+#[actix_web::test]
+async fn test_inbox_form_encoded_errors() {
+    let form = "application/x-www-form-urlencoded";
+
+    let (status, body) = post_raw_to_inbox(form, b"activity=%7B%7D".to_vec()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "Invalid request body");
+    assert_eq!(body["detail"], "Form has no `body` parameter");
+
+    let (status, body) = post_raw_to_inbox(form, b"body=%7Binvalid+json%7D".to_vec()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "Invalid JSON");
+    assert_eq!(body["detail"], "key must be a string at line 1 column 2");
+
+    let oversized = format!("body={}", "x".repeat(MAX_JSON_PAYLOAD));
+    let (status, body) = post_raw_to_inbox(form, oversized.into_bytes()).await;
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(body["error"], "Payload too large");
+}
+
+#[actix_web::test]
+async fn test_post_outbox_form_encoded_body() {
+    let config = create_test_config();
+    let db: DatabaseRef = Arc::new(create_configured_mock_database_with_token(
+        ALICE_TOKEN,
+        ALICE,
+    ));
+    let app = test::init_service(
+        App::new()
+            .app_data(json_config())
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
+            .app_data(web::Data::new(config))
+            .app_data(web::Data::new(db))
+            .app_data(create_test_http_client())
+            .app_data(create_test_delivery())
+            .service(handlers::outbox::post_outbox),
+    )
+    .await;
+
+    let create_activity = json!({
+        "@context": ["https://www.w3.org/ns/activitystreams"],
+        "type": "Create",
+        "actor": ALICE,
+        "object": {
+            "type": "Note",
+            "content": "Posted from a form",
+            "attributedTo": ALICE
+        },
+        "to": ["https://www.w3.org/ns/activitystreams#Public"]
+    });
+
+    let req = outbox_post()
+        .insert_header(("Content-Type", "application/x-www-form-urlencoded"))
+        .set_payload(form_encoded(&create_activity))
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["type"], "Create");
+    assert_eq!(body["object"]["content"], "Posted from a form");
 }
 
 #[actix_web::test]
 async fn test_outbox_malformed_json() {
     let config = create_test_config();
-    let db: DatabaseRef = Arc::new(create_configured_mock_database());
+    let db: DatabaseRef = Arc::new(create_configured_mock_database_with_token(
+        ALICE_TOKEN,
+        ALICE,
+    ));
     let app = test::init_service(
         App::new()
+            .app_data(json_config())
+            .app_data(web::Data::new(UrlBuilder::from_config(&config)))
             .app_data(web::Data::new(config))
             .app_data(web::Data::new(db))
+            .app_data(create_test_http_client())
+            .app_data(create_test_delivery())
             .service(handlers::outbox::post_outbox),
     )
     .await;
 
-    let req = test::TestRequest::post()
-        .uri("/users/alice/outbox")
+    let req = outbox_post()
         .insert_header(("Content-Type", "application/activity+json"))
         .set_payload("{invalid json}")
         .to_request();
 
     let resp = test::call_service(&app, req).await;
-    // Should handle malformed JSON gracefully
-    assert_ne!(resp.status(), StatusCode::CREATED);
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["error"], "Invalid JSON");
+    assert!(body["detail"].as_str().unwrap().contains("line 1 column 2"));
+}
+
+#[actix_web::test]
+async fn test_capabilities_endpoint() {
+    let config = Config {
+        markdown_enabled: true,
+        max_note_length: 1000,
+        ..create_test_config()
+    };
+    let capabilities = Capabilities::from_config(&config);
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(capabilities))
+            .service(handlers::capabilities::get_capabilities),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/capabilities")
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["api_version"], 1);
+    assert_eq!(body["software"]["name"], "feder8");
+    assert_eq!(body["features"]["markdown"], true);
+    assert_eq!(body["features"]["polls"], false);
+    assert_eq!(body["limits"]["max_note_length"], 1000);
 }
@@ -0,0 +1,193 @@
+mod common;
+
+use actix_web::{test, App};
+use common::{OfflineHttpClient, ALICE_TOKEN};
+use feder8::database::DatabaseRef;
+use feder8::handlers;
+use feder8::models::addressing::PUBLIC;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ALICE: &str = "https://example.com/users/alice";
+const BOB: &str = "https://remote.example/users/bob";
+
+fn create_test_app(
+    db: &DatabaseRef,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    common::test_app(db, common::test_config(), Arc::new(OfflineHttpClient))
+        .service(handlers::outbox::post_outbox)
+        .service(handlers::outbox::get_outbox)
+        .service(handlers::inbox::inbox)
+        .service(handlers::api::statuses::get_status)
+}
+
+/// Deliver Bob's note to Alice's inbox
+async fn receive(db: &DatabaseRef, id: &str, note: Value) {
+    let app = test::init_service(create_test_app(db)).await;
+    let mut object = json!({"id": id, "type": "Note", "attributedTo": BOB, "to": [PUBLIC]});
+    object
+        .as_object_mut()
+        .unwrap()
+        .extend(note.as_object().unwrap().clone());
+    let req = test::TestRequest::post()
+        .uri("/users/alice/inbox")
+        .set_json(json!({
+            "id": format!("{id}/activity"),
+            "type": "Create",
+            "actor": BOB,
+            "to": [PUBLIC],
+            "object": object
+        }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 202);
+}
+
+/// Post a note as Alice and return the Create activity
+async fn post(db: &DatabaseRef, note: Value) -> Value {
+    let app = test::init_service(create_test_app(db)).await;
+    let mut object = json!({"type": "Note", "to": [PUBLIC]});
+    object
+        .as_object_mut()
+        .unwrap()
+        .extend(note.as_object().unwrap().clone());
+    let req = test::TestRequest::post()
+        .uri("/users/alice/outbox")
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+        .set_json(json!({"type": "Create", "actor": ALICE, "to": [PUBLIC], "object": object}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    test::read_body_json(resp).await
+}
+
+#[tokio::test]
+async fn test_inbox_stores_bilingual_notes() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+
+    receive(
+        &db,
+        "https://remote.example/notes/1",
+        json!({"content": "Bonjour", "contentMap": {"en": "Hello", "fr": "Bonjour"}}),
+    )
+    .await;
+    // Some servers only send the map
+    receive(
+        &db,
+        "https://remote.example/notes/2",
+        json!({"contentMap": {"de": "Hallo"}}),
+    )
+    .await;
+    receive(
+        &db,
+        "https://remote.example/notes/3",
+        json!({"content": "Hi"}),
+    )
+    .await;
+
+    let note = db
+        .get_note_by_id("https://remote.example/notes/1")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(note.content, "Bonjour");
+    assert_eq!(note.language.as_deref(), Some("fr"));
+    assert_eq!(
+        note.content_map,
+        Some(json!({"en": "Hello", "fr": "Bonjour"}))
+    );
+
+    let note = db
+        .get_note_by_id("https://remote.example/notes/2")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(note.content, "Hallo");
+    assert_eq!(note.language.as_deref(), Some("de"));
+
+    let note = db
+        .get_note_by_id("https://remote.example/notes/3")
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(note.language, None);
+    assert_eq!(note.content_map, None);
+
+    // Translations count when filtering by language, whatever the case
+    for (language, expected) in [("en", 1), ("FR", 1), ("de", 1), ("es", 0)] {
+        let notes = db
+            .get_notes_by_language(BOB, language, 10, 0)
+            .await
+            .unwrap();
+        assert_eq!(notes.len(), expected, "{language}");
+    }
+}
+
+#[tokio::test]
+async fn test_outbox_serializes_content_map_for_several_languages() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+
+    let create = post(
+        &db,
+        json!({"content": "Hello", "contentMap": {"en": "Hello", "fr": "Bonjour"}}),
+    )
+    .await;
+    assert_eq!(create["object"]["content"], "Hello");
+    assert_eq!(
+        create["object"]["contentMap"],
+        json!({"en": "Hello", "fr": "Bonjour"})
+    );
+    let bilingual = create["object"]["id"].as_str().unwrap().to_string();
+
+    // A single language has nothing to add to `content`
+    let create = post(&db, json!({"contentMap": {"en": "Just English"}})).await;
+    assert_eq!(create["object"]["content"], "Just English");
+    assert!(create["object"].get("contentMap").is_none());
+    let english = create["object"]["id"].as_str().unwrap().to_string();
+
+    let stored = db.get_note_by_id(&english).await.unwrap().unwrap();
+    assert_eq!(stored.language.as_deref(), Some("en"));
+
+    let app = test::init_service(create_test_app(&db)).await;
+    let req = test::TestRequest::get()
+        .uri("/users/alice/outbox?page=true")
+        .to_request();
+    let page: Value = test::call_and_read_body_json(&app, req).await;
+    let objects: Vec<&Value> = page["orderedItems"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|activity| &activity["object"])
+        .collect();
+    assert_eq!(objects.len(), 2);
+    for object in objects {
+        if object["id"] == bilingual.as_str() {
+            assert_eq!(object["contentMap"]["fr"], "Bonjour");
+        } else {
+            assert!(object.get("contentMap").is_none());
+        }
+    }
+
+    let status_id = bilingual.rsplit('/').next().unwrap();
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/statuses/{status_id}"))
+        .to_request();
+    let status: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(status["language"], "en");
+
+    let french = db.get_notes_by_language(ALICE, "fr", 10, 0).await.unwrap();
+    assert_eq!(french.len(), 1);
+    assert_eq!(french[0].id, bilingual);
+    let english = db.get_notes_by_language(ALICE, "en", 10, 0).await.unwrap();
+    assert_eq!(english.len(), 2);
+}
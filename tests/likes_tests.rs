@@ -0,0 +1,137 @@
+mod common;
+
+use actix_web::test;
+use chrono::Utc;
+use common::OfflineHttpClient;
+use feder8::database::{Database, DatabaseRef, DbNote, PublishState};
+use feder8::handlers;
+use feder8::models::Visibility;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ACTOR_ID: &str = "https://example.com/users/alice";
+const NOTE_ID: &str = "https://example.com/notes/1";
+const BOB: &str = "https://remote.example/users/bob";
+const MALLORY: &str = "https://remote.example/users/mallory";
+
+async fn create_test_database() -> (TempDir, DatabaseRef) {
+    let dir = TempDir::new().unwrap();
+    let db = common::sqlite_database(&dir).await;
+
+    db.create_actor(&common::actor(ACTOR_ID, "alice", "Alice"))
+        .await
+        .unwrap();
+
+    db.create_note(&DbNote {
+        id: NOTE_ID.to_string(),
+        attributed_to: ACTOR_ID.to_string(),
+        content: "Like me".to_string(),
+        to_recipients: vec!["https://www.w3.org/ns/activitystreams#Public".to_string()],
+        cc_recipients: vec![],
+        published: Utc::now(),
+        in_reply_to: None,
+        tags: vec![],
+        attachments: vec![],
+        visibility: Visibility::Public,
+        state: PublishState::Published,
+        pinned: false,
+        created_at: Utc::now(),
+        in_reply_to_actor: None,
+        thread_depth: Some(0),
+        thread_truncated: false,
+        sensitive: false,
+        summary: None,
+        language: None,
+        content_map: None,
+    })
+    .await
+    .unwrap();
+
+    (dir, Arc::new(db))
+}
+
+async fn post_to_inbox(db: &DatabaseRef, activity: Value) {
+    let app = test::init_service(
+        common::test_app(db, common::test_config(), Arc::new(OfflineHttpClient))
+            .service(handlers::inbox::inbox),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/users/alice/inbox")
+        .set_json(activity)
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 202);
+}
+
+fn like(actor: &str) -> Value {
+    json!({
+        "id": format!("{actor}/likes/1"),
+        "type": "Like",
+        "actor": actor,
+        "object": NOTE_ID
+    })
+}
+
+fn undo(actor: &str, like: Value) -> Value {
+    json!({
+        "id": format!("{actor}/undos/1"),
+        "type": "Undo",
+        "actor": actor,
+        "object": like
+    })
+}
+
+#[tokio::test]
+async fn test_like_then_undo_leaves_no_likes() {
+    let (_dir, db) = create_test_database().await;
+
+    post_to_inbox(&db, like(BOB)).await;
+    // A repeated Like doesn't count twice
+    post_to_inbox(&db, like(BOB)).await;
+    assert_eq!(db.count_likes(NOTE_ID).await.unwrap(), 1);
+
+    post_to_inbox(&db, undo(BOB, like(BOB))).await;
+    assert_eq!(db.count_likes(NOTE_ID).await.unwrap(), 0);
+    assert!(db.get_like(BOB, NOTE_ID).await.unwrap().is_none());
+
+    // Undoing again is harmless
+    post_to_inbox(&db, undo(BOB, like(BOB))).await;
+    assert_eq!(db.count_likes(NOTE_ID).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn test_undo_like_requires_original_liker() {
+    let (_dir, db) = create_test_database().await;
+
+    post_to_inbox(&db, like(BOB)).await;
+
+    // Mallory can't take back Bob's like, whether it names Bob or not
+    post_to_inbox(&db, undo(MALLORY, like(BOB))).await;
+    post_to_inbox(&db, undo(MALLORY, like(MALLORY))).await;
+
+    assert_eq!(db.count_likes(NOTE_ID).await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn test_like_of_unknown_object_is_ignored() {
+    let (_dir, db) = create_test_database().await;
+
+    post_to_inbox(
+        &db,
+        json!({
+            "type": "Like",
+            "actor": BOB,
+            "object": "https://example.com/notes/missing"
+        }),
+    )
+    .await;
+
+    assert_eq!(
+        db.count_likes("https://example.com/notes/missing")
+            .await
+            .unwrap(),
+        0
+    );
+}
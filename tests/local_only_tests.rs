@@ -0,0 +1,255 @@
+mod common;
+
+use actix_web::{http::StatusCode, test};
+use chrono::Utc;
+use common::ALICE_TOKEN;
+use feder8::auth::hash_token;
+use feder8::database::{DatabaseRef, DbActor, DbFollowRelation, DbRemoteActor, DbToken};
+use feder8::handlers;
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode as ClientStatus};
+use feder8::models::addressing::PUBLIC;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+const ALICE: &str = "https://example.com/users/alice";
+const CAROL_TOKEN: &str = "carol-token";
+const BOB: &str = "https://remote.example/users/bob";
+const BOB_INBOX: &str = "https://remote.example/users/bob/inbox";
+
+/// Records every delivery attempt
+#[derive(Default)]
+struct RecordingClient {
+    deliveries: Mutex<Vec<(String, Value)>>,
+}
+
+impl RecordingClient {
+    fn deliveries(&self) -> Vec<(String, Value)> {
+        self.deliveries.lock().unwrap().clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpClient for RecordingClient {
+    async fn send(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        let activity = serde_json::from_slice(request.body.as_deref().unwrap_or(b"{}"))?;
+        self.deliveries
+            .lock()
+            .unwrap()
+            .push((request.url.clone(), activity));
+        Ok(HttpResponse {
+            status: ClientStatus(202),
+            headers: HashMap::new(),
+            body: Vec::new(),
+        })
+    }
+}
+
+fn local_actor(username: &str) -> DbActor {
+    common::actor(
+        &format!("https://example.com/users/{username}"),
+        username,
+        username,
+    )
+}
+
+async fn create_test_database(dir: &TempDir) -> DatabaseRef {
+    let db = common::seeded_database(dir).await;
+    let carol = local_actor("carol");
+    db.create_actor(&carol).await.unwrap();
+    db.create_token(&DbToken {
+        id: "token-carol".to_string(),
+        token_hash: hash_token(CAROL_TOKEN),
+        actor_id: carol.id,
+        scopes: vec!["read".to_string(), "write".to_string()],
+        created_at: Utc::now(),
+        expires_at: None,
+    })
+    .await
+    .unwrap();
+
+    // Bob follows Alice from another server
+    db.upsert_remote_actor(&DbRemoteActor {
+        id: BOB.to_string(),
+        username: "bob".to_string(),
+        name: None,
+        avatar_url: None,
+        inbox: Some(BOB_INBOX.to_string()),
+        shared_inbox: None,
+        public_key_pem: None,
+        fetched_at: Utc::now(),
+    })
+    .await
+    .unwrap();
+    db.create_follow(&DbFollowRelation {
+        id: "https://remote.example/follows/1".to_string(),
+        follower_id: BOB.to_string(),
+        following_id: ALICE.to_string(),
+        status: "accepted".to_string(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    })
+    .await
+    .unwrap();
+    db
+}
+
+macro_rules! local_app {
+    ($db:expr, $client:expr) => {{
+        test::init_service(
+            common::test_app(&$db, common::test_config(), $client.clone())
+                .service(handlers::outbox::get_outbox)
+                .service(handlers::outbox::post_outbox)
+                .service(handlers::note::get_note)
+                .service(handlers::note::get_statuses)
+                .service(handlers::api::timelines::get_public_timeline),
+        )
+        .await
+    }};
+}
+
+fn create(content: &str, extension: Value) -> test::TestRequest {
+    let mut activity = json!({
+        "type": "Create",
+        "actor": ALICE,
+        "to": [PUBLIC],
+        "cc": [format!("{ALICE}/followers")],
+        "object": {"type": "Note", "content": content}
+    });
+    for (key, value) in extension.as_object().unwrap() {
+        activity[key] = value.clone();
+    }
+    test::TestRequest::post()
+        .uri("/users/alice/outbox")
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+        .set_json(activity)
+}
+
+fn get(uri: &str, token: Option<&str>) -> test::TestRequest {
+    let req = test::TestRequest::get()
+        .uri(uri)
+        .insert_header(("Accept", "application/activity+json"));
+    match token {
+        Some(token) => req.insert_header(("Authorization", format!("Bearer {token}"))),
+        None => req,
+    }
+}
+
+/// Contents of the notes or Create activities in a JSON list or collection
+fn contents(body: &Value) -> Vec<String> {
+    let items = body
+        .get("orderedItems")
+        .or_else(|| body.get("items"))
+        .unwrap_or(body);
+    items
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| {
+            let note = item.get("object").filter(|o| o.is_object()).unwrap_or(item);
+            note["content"].as_str().unwrap_or_default().to_string()
+        })
+        .collect()
+}
+
+#[actix_web::test]
+async fn test_local_only_notes_are_never_delivered() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let client = Arc::new(RecordingClient::default());
+    let app = local_app!(db, client);
+
+    let resp = test::call_service(
+        &app,
+        create("just us", json!({"local_only": true})).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let created: Value = test::read_body_json(resp).await;
+    // Public was asked for, but nothing on the wire says so
+    assert_eq!(created["to"], json!([]));
+    assert_eq!(created["cc"], json!([format!("{ALICE}/followers")]));
+    assert_eq!(created["object"]["to"], json!([]));
+    assert!(created["object"].get("local_only").is_none());
+
+    let resp = test::call_service(
+        &app,
+        create("just us too", json!({"visibility": "local"})).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    // A public note afterwards does reach Bob, so the fan-out was running
+    let resp = test::call_service(&app, create("everyone", json!({})).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    for _ in 0..100 {
+        if !client.deliveries().is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+    let deliveries = client.deliveries();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].0, BOB_INBOX);
+    assert_eq!(deliveries[0].1["object"]["content"], "everyone");
+    assert_eq!(db.count_pending_deliveries().await.unwrap(), 0);
+}
+
+#[actix_web::test]
+async fn test_local_only_notes_are_shown_only_to_local_readers() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let client = Arc::new(RecordingClient::default());
+    let app = local_app!(db, client);
+
+    let resp = test::call_service(
+        &app,
+        create("just us", json!({"local_only": true})).to_request(),
+    )
+    .await;
+    let created: Value = test::read_body_json(resp).await;
+    let note_id = created["object"]["id"].as_str().unwrap();
+    let note_path = note_id.strip_prefix("https://example.com").unwrap();
+    let resp = test::call_service(&app, create("everyone", json!({})).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    // Remote servers and anonymous readers never see it
+    let resp = test::call_service(&app, get(note_path, None).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    for uri in [
+        "/users/alice/outbox?page=true",
+        "/users/alice/statuses",
+        "/api/timelines/public?local=true",
+    ] {
+        let resp = test::call_service(&app, get(uri, None).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK, "{uri}");
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(contents(&body), vec!["everyone"], "{uri}");
+    }
+
+    // Anyone signed in here does, without it being cached for others
+    let resp = test::call_service(&app, get(note_path, Some(CAROL_TOKEN)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers().get("cache-control").unwrap(), "private");
+    let note: Value = test::read_body_json(resp).await;
+    assert_eq!(note["content"], "just us");
+    let resp = test::call_service(&app, get(note_path, None).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    for uri in ["/users/alice/statuses", "/api/timelines/public?local=true"] {
+        let resp = test::call_service(&app, get(uri, Some(CAROL_TOKEN)).to_request()).await;
+        let body: Value = test::read_body_json(resp).await;
+        assert_eq!(contents(&body), vec!["everyone", "just us"], "{uri}");
+    }
+    // The federated timeline stays free of them
+    let resp = test::call_service(
+        &app,
+        get("/api/timelines/public", Some(CAROL_TOKEN)).to_request(),
+    )
+    .await;
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(contents(&body), vec!["everyone"]);
+}
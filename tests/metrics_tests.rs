@@ -0,0 +1,139 @@
+mod common;
+
+use actix_web::{test, web, App};
+use common::OfflineHttpClient;
+use feder8::config::Config;
+use feder8::database::{create_configured_mock_database, DatabaseRef};
+use feder8::handlers;
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode};
+use feder8::metrics::{Metrics, RequestMetrics};
+use feder8::services::delivery::DeliveryService;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Accepts deliveries to one inbox and refuses the rest
+struct OneInboxClient;
+
+#[async_trait::async_trait]
+impl HttpClient for OneInboxClient {
+    async fn send(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        let status = if request.url == "https://remote.example/inbox" {
+            202
+        } else {
+            500
+        };
+        Ok(HttpResponse {
+            status: StatusCode(status),
+            headers: HashMap::new(),
+            body: Vec::new(),
+        })
+    }
+}
+
+fn create_test_app(
+    metrics: Arc<Metrics>,
+    config: Config,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse<impl actix_web::body::MessageBody>,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    let db: DatabaseRef = Arc::new(create_configured_mock_database());
+    common::test_app(&db, config, Arc::new(OfflineHttpClient))
+        .wrap(RequestMetrics::new(metrics.clone()))
+        .app_data(web::Data::from(metrics))
+        .service(handlers::health::healthz)
+        .service(handlers::metrics::metrics)
+        .service(handlers::outbox::get_outbox)
+}
+
+/// The body of a `/metrics` response, after checking it is the text format
+async fn exposition(
+    resp: actix_web::dev::ServiceResponse<impl actix_web::body::MessageBody>,
+) -> String {
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers().get("content-type").unwrap(),
+        "text/plain; version=0.0.4; charset=utf-8"
+    );
+    String::from_utf8(test::read_body(resp).await.to_vec()).unwrap()
+}
+
+fn scrape() -> test::TestRequest {
+    test::TestRequest::get().uri("/metrics")
+}
+
+#[actix_web::test]
+async fn test_requests_are_counted_per_route() {
+    let metrics = Arc::new(Metrics::new());
+    let app = test::init_service(create_test_app(metrics, Config::default())).await;
+
+    for uri in [
+        "/users/alice/outbox",
+        "/users/bob/outbox",
+        "/healthz",
+        "/nowhere",
+    ] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        test::call_service(&app, req).await;
+    }
+
+    let text = exposition(test::call_service(&app, scrape().to_request()).await).await;
+    assert!(text.contains("# TYPE feder8_http_requests_total counter"));
+    assert!(text.contains(
+        r#"feder8_http_requests_total{handler="/users/{username}/outbox",method="GET",status="200"} 2"#
+    ));
+    assert!(text
+        .contains(r#"feder8_http_requests_total{handler="/healthz",method="GET",status="200"} 1"#));
+    assert!(text.contains(
+        r#"feder8_http_requests_total{handler="unmatched",method="GET",status="404"} 1"#
+    ));
+    assert!(text.contains(
+        r#"feder8_http_request_duration_seconds_count{handler="/users/{username}/outbox",method="GET"} 2"#
+    ));
+}
+
+#[actix_web::test]
+async fn test_delivery_outcomes_are_exposed() {
+    let metrics = Arc::new(Metrics::new());
+    let delivery = DeliveryService::new(
+        Config::default(),
+        Arc::new(OneInboxClient),
+        Arc::new(create_configured_mock_database()),
+    )
+    .with_metrics(metrics.clone());
+    let activity = json!({ "id": "https://example.com/activities/1", "type": "Create" });
+    for inbox in [
+        "https://remote.example/inbox",
+        "https://remote.example/inbox",
+        "https://down.example/inbox",
+    ] {
+        let _ = delivery.deliver_activity(inbox, activity.clone()).await;
+    }
+
+    let app = test::init_service(create_test_app(metrics, Config::default())).await;
+    let text = exposition(test::call_service(&app, scrape().to_request()).await).await;
+    assert!(text.contains(r#"feder8_deliveries_total{outcome="success"} 2"#));
+    assert!(text.contains(r#"feder8_deliveries_total{outcome="failure"} 1"#));
+}
+
+#[actix_web::test]
+async fn test_metrics_endpoint_is_hidden_when_disabled() {
+    let config = Config {
+        metrics_enabled: false,
+        ..Config::default()
+    };
+    let app = test::init_service(create_test_app(Arc::new(Metrics::new()), config)).await;
+
+    assert_eq!(
+        test::call_service(&app, scrape().to_request())
+            .await
+            .status(),
+        404
+    );
+}
@@ -0,0 +1,364 @@
+mod common;
+
+use actix_web::{http::StatusCode, test, web};
+use chrono::Utc;
+use common::ALICE_TOKEN;
+use feder8::auth::hash_token;
+use feder8::database::{DatabaseRef, DbActor, DbFollowRelation, DbRemoteActor, DbToken};
+use feder8::handlers;
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode as ClientStatus};
+use feder8::services::document_cache::DocumentCache;
+use feder8::services::events::EventBus;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+const ALICE: &str = "https://example.com/users/alice";
+const ALICE2: &str = "https://example.com/users/alice2";
+const ALICE2_TOKEN: &str = "alice2-token";
+const NEW_ALICE: &str = "https://new.example/users/alice";
+const BOB: &str = "https://remote.example/users/bob";
+const BOB_INBOX: &str = "https://remote.example/users/bob/inbox";
+
+/// Serves remote actor documents and records deliveries
+#[derive(Default)]
+struct FederationClient {
+    actors: HashMap<String, Value>,
+    deliveries: Mutex<Vec<(String, Value)>>,
+}
+
+impl FederationClient {
+    fn with_actor(mut self, id: &str, also_known_as: &[&str]) -> Self {
+        self.actors.insert(
+            id.to_string(),
+            json!({
+                "id": id,
+                "type": "Person",
+                "preferredUsername": "alice",
+                "inbox": format!("{id}/inbox"),
+                "alsoKnownAs": also_known_as
+            }),
+        );
+        self
+    }
+
+    fn deliveries(&self) -> Vec<(String, Value)> {
+        self.deliveries.lock().unwrap().clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpClient for FederationClient {
+    async fn send(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        let (status, body) = match request.method.as_str() {
+            "GET" => match self.actors.get(&request.url) {
+                Some(actor) => (200, serde_json::to_vec(actor)?),
+                None => (404, Vec::new()),
+            },
+            _ => {
+                let activity = serde_json::from_slice(request.body.as_deref().unwrap_or(b"{}"))?;
+                self.deliveries
+                    .lock()
+                    .unwrap()
+                    .push((request.url.clone(), activity));
+                (202, Vec::new())
+            }
+        };
+        Ok(HttpResponse {
+            status: ClientStatus(status),
+            headers: HashMap::new(),
+            body,
+        })
+    }
+}
+
+fn local_actor(username: &str, also_known_as: Vec<String>) -> DbActor {
+    DbActor {
+        also_known_as,
+        ..common::actor(
+            &format!("https://example.com/users/{username}"),
+            username,
+            username,
+        )
+    }
+}
+
+async fn create_test_database(dir: &TempDir) -> DatabaseRef {
+    let db = common::seeded_database(dir).await;
+
+    // Bob follows Alice, and his inbox is already known
+    db.upsert_remote_actor(&DbRemoteActor {
+        id: BOB.to_string(),
+        username: "bob".to_string(),
+        name: None,
+        avatar_url: None,
+        inbox: Some(BOB_INBOX.to_string()),
+        shared_inbox: None,
+        public_key_pem: None,
+        fetched_at: Utc::now(),
+    })
+    .await
+    .unwrap();
+    db.create_follow(&DbFollowRelation {
+        id: "https://remote.example/follows/1".to_string(),
+        follower_id: BOB.to_string(),
+        following_id: ALICE.to_string(),
+        status: "accepted".to_string(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    })
+    .await
+    .unwrap();
+    db
+}
+
+macro_rules! move_app {
+    ($db:expr, $client:expr) => {{
+        let config = common::test_config();
+        let cache = Arc::new(DocumentCache::from_config(&config));
+        let events = Arc::new(EventBus::new());
+        events.subscribe(cache.clone());
+        test::init_service(
+            common::test_app(&$db, config, $client.clone())
+                .app_data(web::Data::from(cache))
+                .app_data(web::Data::from(events))
+                .service(handlers::actor::get_actor)
+                .service(handlers::outbox::post_outbox)
+                .service(handlers::api::accounts::set_aliases),
+        )
+        .await
+    }};
+}
+
+fn post_move(target: &str) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/users/alice/outbox")
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+        .set_json(json!({
+            "type": "Move",
+            "actor": ALICE,
+            "object": ALICE,
+            "target": target
+        }))
+}
+
+fn get_alice() -> test::TestRequest {
+    test::TestRequest::get()
+        .uri("/users/alice")
+        .insert_header(("Accept", "application/activity+json"))
+}
+
+#[actix_web::test]
+async fn test_move_to_account_that_lists_the_actor() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let client = Arc::new(FederationClient::default().with_actor(NEW_ALICE, &[ALICE]));
+    let app = move_app!(db, client);
+
+    // Cache the actor document before the move
+    let resp = test::call_service(&app, get_alice().to_request()).await;
+    let before: Value = test::read_body_json(resp).await;
+    assert!(before.get("movedTo").is_none());
+
+    let resp = test::call_service(&app, post_move(NEW_ALICE).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let moved: Value = test::read_body_json(resp).await;
+    assert_eq!(moved["type"], "Move");
+    assert_eq!(moved["object"], ALICE);
+    assert_eq!(moved["target"], NEW_ALICE);
+    assert_eq!(moved["to"], json!([format!("{ALICE}/followers")]));
+
+    let alice = db.get_actor_by_id(ALICE).await.unwrap().unwrap();
+    assert_eq!(alice.moved_to.as_deref(), Some(NEW_ALICE));
+
+    // The cached document is replaced by one pointing at the new account
+    let resp = test::call_service(&app, get_alice().to_request()).await;
+    let after: Value = test::read_body_json(resp).await;
+    assert_eq!(after["movedTo"], NEW_ALICE);
+
+    // Followers hear about the move in the background
+    for _ in 0..100 {
+        if !client.deliveries().is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    let deliveries = client.deliveries();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].0, BOB_INBOX);
+    assert_eq!(deliveries[0].1["type"], "Move");
+    assert_eq!(deliveries[0].1["target"], NEW_ALICE);
+
+    // The stored activity keeps where the account went
+    let stored = db.get_activities_by_actor(ALICE, 10, 0).await.unwrap();
+    assert_eq!(stored[0].activity_type, "Move");
+    assert_eq!(stored[0].target.as_deref(), Some(NEW_ALICE));
+}
+
+#[actix_web::test]
+async fn test_move_only_once() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let client = Arc::new(
+        FederationClient::default()
+            .with_actor(NEW_ALICE, &[ALICE])
+            .with_actor("https://newer.example/users/alice", &[ALICE]),
+    );
+    let app = move_app!(db, client);
+
+    let resp = test::call_service(&app, post_move(NEW_ALICE).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let resp = test::call_service(
+        &app,
+        post_move("https://newer.example/users/alice").to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+
+    let alice = db.get_actor_by_id(ALICE).await.unwrap().unwrap();
+    assert_eq!(alice.moved_to.as_deref(), Some(NEW_ALICE));
+}
+
+fn put_aliases(token: &str, aliases: Value) -> test::TestRequest {
+    test::TestRequest::put()
+        .uri("/api/v1/accounts/aliases")
+        .insert_header(("Authorization", format!("Bearer {token}")))
+        .set_json(json!({ "also_known_as": aliases }))
+}
+
+async fn create_alice2(db: &DatabaseRef) {
+    db.create_actor(&local_actor("alice2", Vec::new()))
+        .await
+        .unwrap();
+    db.create_token(&DbToken {
+        id: "token-2".to_string(),
+        token_hash: hash_token(ALICE2_TOKEN),
+        actor_id: ALICE2.to_string(),
+        scopes: vec!["write:accounts".to_string()],
+        created_at: Utc::now(),
+        expires_at: None,
+    })
+    .await
+    .unwrap();
+}
+
+#[actix_web::test]
+async fn test_move_to_local_account() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    create_alice2(&db).await;
+    let client = Arc::new(FederationClient::default());
+    let app = move_app!(db, client);
+
+    // The new account has to list the old one first
+    let resp = test::call_service(&app, post_move(ALICE2).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    let resp =
+        test::call_service(&app, put_aliases(ALICE2_TOKEN, json!([ALICE])).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["also_known_as"], json!([ALICE]));
+
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::get()
+            .uri("/users/alice2")
+            .insert_header(("Accept", "application/activity+json"))
+            .to_request(),
+    )
+    .await;
+    let alice2: Value = test::read_body_json(resp).await;
+    assert_eq!(alice2["alsoKnownAs"], json!([ALICE]));
+
+    let resp = test::call_service(&app, post_move(ALICE2).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+
+    let alice = db.get_actor_by_id(ALICE).await.unwrap().unwrap();
+    assert_eq!(alice.moved_to.as_deref(), Some(ALICE2));
+}
+
+#[actix_web::test]
+async fn test_aliases_must_be_other_https_accounts() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    create_alice2(&db).await;
+    let client = Arc::new(FederationClient::default());
+    let app = move_app!(db, client);
+
+    for aliases in [
+        json!(["http://old.example/users/alice"]),
+        json!(["not a url"]),
+        json!([ALICE2]),
+    ] {
+        let resp = test::call_service(
+            &app,
+            put_aliases(ALICE2_TOKEN, aliases.clone()).to_request(),
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST, "{aliases}");
+    }
+
+    // Nor can anyone set them without a token
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::put()
+            .uri("/api/v1/accounts/aliases")
+            .set_json(json!({ "also_known_as": [ALICE] }))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let alice2 = db.get_actor_by_id(ALICE2).await.unwrap().unwrap();
+    assert!(alice2.also_known_as.is_empty());
+}
+
+#[actix_web::test]
+async fn test_move_requires_alias_on_the_target() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let client = Arc::new(
+        FederationClient::default().with_actor(NEW_ALICE, &["https://other.example/users/alice"]),
+    );
+    let app = move_app!(db, client);
+
+    for (target, status) in [
+        (NEW_ALICE, StatusCode::UNPROCESSABLE_ENTITY),
+        ("https://new.example/users/nobody", StatusCode::NOT_FOUND),
+        ("http://new.example/users/alice", StatusCode::BAD_REQUEST),
+        (ALICE, StatusCode::BAD_REQUEST),
+    ] {
+        let resp = test::call_service(&app, post_move(target).to_request()).await;
+        assert_eq!(resp.status(), status, "{target}");
+    }
+
+    let alice = db.get_actor_by_id(ALICE).await.unwrap().unwrap();
+    assert_eq!(alice.moved_to, None);
+    assert!(client.deliveries().is_empty());
+}
+
+#[actix_web::test]
+async fn test_move_only_of_the_posting_actor() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let client = Arc::new(FederationClient::default().with_actor(NEW_ALICE, &[BOB]));
+    let app = move_app!(db, client);
+
+    let req = test::TestRequest::post()
+        .uri("/users/alice/outbox")
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+        .set_json(json!({
+            "type": "Move",
+            "actor": ALICE,
+            "object": BOB,
+            "target": NEW_ALICE
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let alice = db.get_actor_by_id(ALICE).await.unwrap().unwrap();
+    assert_eq!(alice.moved_to, None);
+}
@@ -1,12 +1,18 @@
 use actix_web::{middleware::Logger, web, App, HttpServer};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use feder8::{
     config::Config,
-    database::{create_configured_mock_database, DatabaseRef},
+    container::ContainerBuilder,
+    database::create_configured_mock_database,
     handlers,
+    http::RecordingHttpClient,
+    services::{delivery::DeliveryService, document_cache::DocumentCache},
 };
 use rand::Rng;
 use reqwest::Client;
 use serde_json::json;
+use sha2::{Digest, Sha256};
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::Once;
@@ -47,7 +53,6 @@ mod test_harness {
 
         pub async fn wait_for_nodes(&self) {
             println!("Waiting for nodes to start...");
-            sleep(Duration::from_secs(5)).await;
             let mut attempts = 0;
             while attempts < 60 {
                 let mut all_ready = true;
@@ -68,41 +73,82 @@ mod test_harness {
         }
 
         async fn is_node_ready(&self, url: &str) -> bool {
-            (self
-                .client
-                .get(url)
+            self.client
+                .get(format!("{url}/readyz"))
                 .timeout(Duration::from_secs(3))
                 .send()
-                .await)
-                .is_ok()
+                .await
+                .is_ok_and(|response| response.status().is_success())
         }
     }
 
-    async fn start_node(port: u16, actor_name: &str) -> JoinHandle<()> {
+    /// A running node, with a record of every request it has sent
+    pub struct TestNode {
+        pub url: String,
+        pub http: Arc<RecordingHttpClient>,
+        pub delivery: Arc<DeliveryService>,
+        handle: JoinHandle<()>,
+    }
+
+    impl TestNode {
+        pub fn abort(&self) {
+            self.handle.abort();
+        }
+    }
+
+    /// Boot a node, mounting its routes under `base_path` like `main` does
+    pub async fn start_node(port: u16, actor_name: &str, base_path: Option<&str>) -> TestNode {
         let config = Config {
             server_name: format!("Test Node {actor_name}"),
             server_url: format!("http://localhost:{port}"),
+            base_path: base_path.map(String::from),
             port,
             actor_name: actor_name.to_string(),
             private_key_path: None,
             public_key_path: None,
+            ..Config::default()
         };
 
-        let config_clone = config.clone();
-        let server_handle = tokio::spawn(async move {
-            // Initialize database (using mock for tests)
-            let db: DatabaseRef = Arc::new(create_configured_mock_database());
-
+        // Initialize database (using mock for tests)
+        let container = ContainerBuilder::new()
+            .with_config(config.clone())
+            .with_database(Arc::new(create_configured_mock_database()))
+            .with_test_mode()
+            .build()
+            .expect("Failed to build container");
+        let http = container
+            .recording_http_client()
+            .expect("Test mode records requests")
+            .clone();
+        let delivery = container.delivery_service().clone();
+
+        let handle = tokio::spawn(async move {
             let _ = HttpServer::new(move || {
+                let urls = container.urls().clone();
                 App::new()
                     .wrap(Logger::default())
-                    .app_data(web::Data::new(config_clone.clone()))
-                    .app_data(web::Data::new(db.clone()))
+                    .app_data(web::Data::new(container.config().clone()))
+                    .app_data(web::Data::new(urls.clone()))
+                    .app_data(web::Data::new(container.database().clone()))
+                    .app_data(web::Data::from(container.seen_activities().clone()))
+                    .app_data(web::Data::from(container.inbox_backpressure().clone()))
+                    .app_data(web::Data::from(container.log_dedup().clone()))
+                    .app_data(web::Data::from(container.http_client().clone()))
+                    .app_data(web::Data::from(container.delivery_service().clone()))
+                    .app_data(web::Data::from(container.health().clone()))
+                    .service(handlers::health::healthz)
+                    .service(handlers::health::readyz)
                     .service(handlers::webfinger::webfinger)
-                    .service(handlers::actor::get_actor)
-                    .service(handlers::inbox::inbox)
-                    .service(handlers::outbox::get_outbox)
-                    .service(handlers::outbox::post_outbox)
+                    .service(
+                        web::scope(urls.base_path())
+                            .app_data(web::Data::new(DocumentCache::from_config(
+                                &Config::default(),
+                            )))
+                            .service(handlers::actor::get_actor)
+                            .service(handlers::inbox::inbox)
+                            .service(handlers::outbox::get_outbox)
+                            .service(handlers::outbox::post_outbox),
+                    )
             })
             .bind(("127.0.0.1", port))
             .unwrap_or_else(|e| {
@@ -113,7 +159,12 @@ mod test_harness {
             .await;
         });
 
-        server_handle
+        TestNode {
+            url: format!("http://localhost:{port}"),
+            http,
+            delivery,
+            handle,
+        }
     }
 
     pub async fn setup_nodes(node_count: usize, base_port: u16) {
@@ -124,7 +175,7 @@ mod test_harness {
         for i in 0..node_count {
             let port = base_port + i as u16;
             let actor_name = format!("actor{}", i + 1);
-            handles.push(start_node(port, &actor_name).await);
+            handles.push(start_node(port, &actor_name, None).await.handle);
             // Add a small delay between starting nodes
             sleep(Duration::from_millis(100)).await;
         }
@@ -143,7 +194,7 @@ mod test_harness {
     }
 }
 
-use test_harness::{setup_nodes, teardown_nodes, TestContext};
+use test_harness::{setup_nodes, start_node, teardown_nodes, TestContext};
 
 #[tokio::test]
 async fn test_node_setup_and_teardown() {
@@ -285,44 +336,68 @@ async fn test_webfinger_discovery() {
 
 #[tokio::test]
 async fn test_message_delivery_between_nodes() {
-    let node_count = NODE_COUNT;
     let base_port = rand::thread_rng().gen_range(20000..60000);
-    setup_nodes(node_count, base_port).await;
-    let context = TestContext::new(node_count, base_port);
-    context.wait_for_nodes().await;
+    let alice = start_node(base_port, "alice", None).await;
+    let bob = start_node(base_port + 1, "bob", None).await;
+    TestContext::new(2, base_port).wait_for_nodes().await;
+
+    let alice_id = format!("{}/users/alice", alice.url);
+    let bob_id = format!("{}/users/bob", bob.url);
+    let bob_inbox = format!("{bob_id}/inbox");
     let note = json!({
-        "@context": ["https://www.w3.org/ns/activitystreams"],
-        "id": "https://example.com/notes/789",
+        "id": format!("{}/notes/789", alice.url),
         "type": "Note",
-        "attributedTo": format!("{}/users/{}", context.node_urls[0], context.actor_names[0]),
+        "attributedTo": alice_id,
         "content": "Hello! This is a test message.",
-        "to": [format!("{}/users/{}", context.node_urls[1], context.actor_names[1])],
+        "to": [bob_id],
         "cc": ["https://www.w3.org/ns/activitystreams#Public"],
         "published": "2024-01-01T12:00:00Z"
     });
     let create_activity = json!({
         "@context": ["https://www.w3.org/ns/activitystreams"],
-        "id": "https://example.com/activities/101",
+        "id": format!("{}/activities/101", alice.url),
         "type": "Create",
-        "actor": format!("{}/users/{}", context.node_urls[0], context.actor_names[0]),
+        "actor": alice_id,
         "object": note,
-        "to": [format!("{}/users/{}", context.node_urls[1], context.actor_names[1])],
+        "to": [bob_id],
         "cc": ["https://www.w3.org/ns/activitystreams#Public"],
         "published": "2024-01-01T12:00:00Z"
     });
-    let response = context
-        .client
-        .post(format!(
-            "{}/users/{}/inbox",
-            context.node_urls[1], context.actor_names[1]
-        ))
-        .header("Content-Type", "application/activity+json")
-        .json(&create_activity)
-        .send()
+
+    alice
+        .delivery
+        .deliver_activity(&bob_inbox, create_activity.clone())
         .await
-        .expect("Failed to send message to inbox");
-    assert!(response.status().is_success() || response.status().as_u16() == 202);
-    teardown_nodes();
+        .expect("Bob's node refused the delivery");
+
+    // What alice's node put on the wire is what bob's node accepted
+    let request = alice
+        .http
+        .wait_for_delivery(|r| r.url == bob_inbox, Duration::from_secs(5))
+        .await
+        .expect("No delivery to bob's inbox was recorded");
+    assert_eq!(request.method, "POST");
+    assert_eq!(
+        request.headers.get("Content-Type").map(String::as_str),
+        Some("application/activity+json")
+    );
+    assert!(request.headers.contains_key("User-Agent"));
+
+    let body = request.body.expect("Delivery without a body");
+    let sent: serde_json::Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(sent, create_activity);
+    let digest = format!("SHA-256={}", BASE64.encode(Sha256::digest(&body)));
+    assert_eq!(request.headers.get("Digest"), Some(&digest));
+    assert_eq!(alice.http.deliveries_to(&bob_inbox).len(), 1);
+
+    // Receiving isn't sending: bob's node made no requests of its own
+    assert!(bob
+        .http
+        .deliveries_to(&format!("{alice_id}/inbox"))
+        .is_empty());
+
+    alice.abort();
+    bob.abort();
 }
 
 #[tokio::test]
@@ -425,3 +500,62 @@ async fn test_outbox_endpoint_returns_collection() {
     );
     teardown_nodes();
 }
+
+#[tokio::test]
+async fn test_discovery_chain_under_base_path() {
+    let port = rand::thread_rng().gen_range(20000..60000);
+    let node = start_node(port, "alice", Some("/fedi")).await;
+    let context = TestContext::new(1, port);
+    context.wait_for_nodes().await;
+    let origin = &context.node_urls[0];
+
+    // Webfinger lives at the root and links into the base path
+    let webfinger: serde_json::Value = context
+        .client
+        .get(format!(
+            "{origin}/.well-known/webfinger?resource=acct:alice@localhost:{port}"
+        ))
+        .send()
+        .await
+        .expect("Failed to get WebFinger response")
+        .json()
+        .await
+        .expect("Failed to parse WebFinger JSON");
+    let actor_url = webfinger["links"][0]["href"].as_str().unwrap().to_string();
+    assert_eq!(actor_url, format!("{origin}/fedi/users/alice"));
+
+    // The ids handed out are the URLs the node serves
+    let response = context
+        .client
+        .get(&actor_url)
+        .header("Accept", "application/activity+json")
+        .send()
+        .await
+        .expect("Failed to get actor profile");
+    assert!(response.status().is_success());
+    let actor: serde_json::Value = response.json().await.expect("Failed to parse actor JSON");
+    assert_eq!(actor["id"], actor_url);
+
+    let outbox_url = actor["outbox"].as_str().unwrap();
+    assert_eq!(outbox_url, format!("{actor_url}/outbox"));
+    let response = context
+        .client
+        .get(outbox_url)
+        .header("Accept", "application/activity+json")
+        .send()
+        .await
+        .expect("Failed to get outbox");
+    assert!(response.status().is_success());
+    let outbox: serde_json::Value = response.json().await.expect("Failed to parse outbox JSON");
+    assert_eq!(outbox["id"], outbox_url);
+
+    let response = context
+        .client
+        .get(format!("{origin}/users/alice"))
+        .send()
+        .await
+        .expect("Failed to reach node");
+    assert_eq!(response.status().as_u16(), 404);
+
+    node.abort();
+}
@@ -0,0 +1,242 @@
+mod common;
+
+use actix_web::test;
+use chrono::Utc;
+use common::{OfflineHttpClient, ALICE_TOKEN};
+use feder8::config::Config;
+use feder8::database::{DatabaseRef, DbFollowRelation};
+use feder8::handlers;
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const LOCAL_ACTOR: &str = "https://example.com/users/alice";
+const REMOTE_ACTOR: &str = "https://remote.example/users/bob";
+
+// Each test gets its own on-disk database so parked rows don't leak between tests
+async fn create_test_database() -> (TempDir, DatabaseRef) {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    db.create_actor(&common::actor(REMOTE_ACTOR, "bob", "bob"))
+        .await
+        .unwrap();
+    (dir, db)
+}
+
+fn create_test_config(pending_activity_ttl_secs: u64) -> Config {
+    Config {
+        pending_activity_ttl_secs,
+        ..common::test_config()
+    }
+}
+
+async fn post_to_inbox(db: &DatabaseRef, config: &Config, activity: serde_json::Value) {
+    let app = test::init_service(
+        common::test_app(db, config.clone(), Arc::new(OfflineHttpClient))
+            .service(handlers::inbox::inbox),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/users/alice/inbox")
+        .insert_header(("Content-Type", "application/activity+json"))
+        .set_json(&activity)
+        .to_request();
+
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 202);
+}
+
+fn follow_activity() -> serde_json::Value {
+    json!({
+        "id": "https://remote.example/activities/follow/1",
+        "type": "Follow",
+        "actor": REMOTE_ACTOR,
+        "object": LOCAL_ACTOR
+    })
+}
+
+fn accept_activity(follow_id: &str) -> serde_json::Value {
+    json!({
+        "id": "https://remote.example/activities/accept/1",
+        "type": "Accept",
+        "actor": REMOTE_ACTOR,
+        "object": {
+            "id": follow_id,
+            "type": "Follow",
+            "actor": LOCAL_ACTOR,
+            "object": REMOTE_ACTOR
+        }
+    })
+}
+
+// Serves the remote actor's document, so Alice can follow him
+struct RemoteActorClient;
+
+#[async_trait::async_trait]
+impl HttpClient for RemoteActorClient {
+    async fn send(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        anyhow::ensure!(
+            request.url == REMOTE_ACTOR,
+            "unexpected request to {}",
+            request.url
+        );
+        Ok(HttpResponse {
+            status: StatusCode(200),
+            headers: HashMap::new(),
+            body: serde_json::to_vec(&json!({
+                "id": REMOTE_ACTOR,
+                "type": "Person",
+                "preferredUsername": "bob",
+                "inbox": format!("{REMOTE_ACTOR}/inbox")
+            }))?,
+        })
+    }
+}
+
+/// Alice follows the remote actor through her outbox, returning the follow
+async fn follow_from_outbox(db: &DatabaseRef, config: &Config) -> DbFollowRelation {
+    let app = test::init_service(
+        common::test_app(db, config.clone(), Arc::new(RemoteActorClient))
+            .service(handlers::outbox::post_outbox),
+    )
+    .await;
+
+    let req = test::TestRequest::post()
+        .uri("/users/alice/outbox")
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+        .set_json(json!({
+            "type": "Follow",
+            "actor": LOCAL_ACTOR,
+            "object": REMOTE_ACTOR
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+
+    db.get_follow_by_actors(LOCAL_ACTOR, REMOTE_ACTOR)
+        .await
+        .unwrap()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn test_accept_in_order() {
+    let (_dir, db) = create_test_database().await;
+    let config = create_test_config(600);
+
+    let follow = follow_from_outbox(&db, &config).await;
+    assert_eq!(follow.status, "pending");
+    post_to_inbox(&db, &config, accept_activity(&follow.id)).await;
+
+    let follow = db.get_follow_by_id(&follow.id).await.unwrap().unwrap();
+    assert_eq!(follow.status, "accepted");
+}
+
+#[tokio::test]
+async fn test_accept_before_follow_is_applied() {
+    let (_dir, db) = create_test_database().await;
+    let config = create_test_config(600);
+
+    // The remote side answers a Follow we haven't committed yet
+    post_to_inbox(
+        &db,
+        &config,
+        accept_activity("https://example.com/activities/unknown"),
+    )
+    .await;
+    assert!(db
+        .get_follow_by_actors(LOCAL_ACTOR, REMOTE_ACTOR)
+        .await
+        .unwrap()
+        .is_none());
+
+    let follow = follow_from_outbox(&db, &config).await;
+    assert_eq!(follow.status, "accepted");
+
+    // The parked Accept is consumed once applied
+    let parked = db
+        .get_pending_accepts(LOCAL_ACTOR, REMOTE_ACTOR, Utc::now())
+        .await
+        .unwrap();
+    assert!(parked.is_empty());
+}
+
+#[tokio::test]
+async fn test_expired_accept_is_not_applied() {
+    let (_dir, db) = create_test_database().await;
+    let config = create_test_config(0);
+
+    post_to_inbox(
+        &db,
+        &config,
+        accept_activity("https://example.com/activities/unknown"),
+    )
+    .await;
+
+    let follow = follow_from_outbox(&db, &config).await;
+    assert_eq!(follow.status, "pending");
+}
+
+#[tokio::test]
+async fn test_undo_in_order() {
+    let (_dir, db) = create_test_database().await;
+    let config = create_test_config(600);
+
+    post_to_inbox(&db, &config, follow_activity()).await;
+    assert!(db
+        .get_follow_by_actors(REMOTE_ACTOR, LOCAL_ACTOR)
+        .await
+        .unwrap()
+        .is_some());
+
+    post_to_inbox(
+        &db,
+        &config,
+        json!({
+            "id": "https://remote.example/activities/undo/1",
+            "type": "Undo",
+            "actor": REMOTE_ACTOR,
+            "object": follow_activity()
+        }),
+    )
+    .await;
+
+    assert!(db
+        .get_follow_by_actors(REMOTE_ACTOR, LOCAL_ACTOR)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn test_undo_before_follow_is_applied() {
+    let (_dir, db) = create_test_database().await;
+    let config = create_test_config(600);
+
+    post_to_inbox(
+        &db,
+        &config,
+        json!({
+            "id": "https://remote.example/activities/undo/1",
+            "type": "Undo",
+            "actor": REMOTE_ACTOR,
+            "object": follow_activity()
+        }),
+    )
+    .await;
+    post_to_inbox(&db, &config, follow_activity()).await;
+
+    assert!(db
+        .get_follow_by_actors(REMOTE_ACTOR, LOCAL_ACTOR)
+        .await
+        .unwrap()
+        .is_none());
+    assert!(db
+        .get_followers(LOCAL_ACTOR, 10, 0)
+        .await
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,147 @@
+mod common;
+
+use actix_web::test;
+use chrono::{Duration, Utc};
+use common::OfflineHttpClient;
+use feder8::database::{DatabaseRef, DbActivity, PublishState};
+use feder8::handlers;
+use feder8::models::addressing::PUBLIC;
+use feder8::models::Visibility;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ACTOR_ID: &str = "https://example.com/users/alice";
+const FOLLOWERS: &str = "https://example.com/users/alice/followers";
+
+/// Store `count` activities of `activity_type`, each a minute older than the last
+async fn store_activities(
+    db: &DatabaseRef,
+    activity_type: &str,
+    count: usize,
+    visibility: Visibility,
+) {
+    for i in 0..count {
+        let published = Utc::now() - Duration::minutes(i as i64);
+        let to = match visibility {
+            Visibility::Followers => vec![FOLLOWERS.to_string()],
+            _ => vec![PUBLIC.to_string()],
+        };
+        db.create_activity(&DbActivity {
+            id: format!("https://example.com/activities/{activity_type}-{visibility:?}-{i}"),
+            actor_id: ACTOR_ID.to_string(),
+            activity_type: activity_type.to_string(),
+            object: json!(format!("https://remote.example/objects/{i}")),
+            target: None,
+            to_recipients: to,
+            cc_recipients: vec![],
+            published,
+            visibility,
+            state: PublishState::Published,
+            created_at: published,
+        })
+        .await
+        .unwrap();
+    }
+}
+
+async fn get_outbox(db: &DatabaseRef, uri: &str) -> (u16, Value) {
+    let app = test::init_service(
+        common::test_app(db, common::test_config(), Arc::new(OfflineHttpClient))
+            .service(handlers::outbox::get_outbox),
+    )
+    .await;
+    let req = test::TestRequest::get().uri(uri).to_request();
+    let resp = test::call_service(&app, req).await;
+    let status = resp.status().as_u16();
+    (status, test::read_body_json(resp).await)
+}
+
+fn types(page: &Value) -> Vec<String> {
+    let mut types: Vec<String> = page["orderedItems"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["type"].as_str().unwrap().to_string())
+        .collect();
+    types.sort();
+    types.dedup();
+    types
+}
+
+#[tokio::test]
+async fn test_outbox_type_filter_returns_only_requested_types() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    store_activities(&db, "Create", 2, Visibility::Public).await;
+    store_activities(&db, "Announce", 1, Visibility::Public).await;
+    store_activities(&db, "Like", 3, Visibility::Public).await;
+    store_activities(&db, "Follow", 1, Visibility::Public).await;
+
+    let (status, outbox) = get_outbox(&db, "/users/alice/outbox?type=Create,Announce").await;
+
+    assert_eq!(status, 200);
+    assert_eq!(outbox["totalItems"], 3);
+    assert_eq!(types(&outbox["first"]), vec!["Announce", "Create"]);
+
+    let (_, outbox) = get_outbox(&db, "/users/alice/outbox?type=Like").await;
+    assert_eq!(outbox["totalItems"], 3);
+    assert_eq!(types(&outbox["first"]), vec!["Like"]);
+
+    let (_, outbox) = get_outbox(&db, "/users/alice/outbox").await;
+    assert_eq!(outbox["totalItems"], 7);
+}
+
+#[tokio::test]
+async fn test_outbox_type_filter_paginates_filtered_items() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    store_activities(&db, "Create", 25, Visibility::Public).await;
+    store_activities(&db, "Like", 30, Visibility::Public).await;
+
+    let (_, outbox) = get_outbox(&db, "/users/alice/outbox?type=Create").await;
+
+    let outbox_id = "https://example.com/users/alice/outbox?type=Create";
+    assert_eq!(outbox["id"], outbox_id);
+    assert_eq!(outbox["totalItems"], 25);
+    assert_eq!(outbox["last"], format!("{outbox_id}&page=true&offset=20"));
+    assert_eq!(
+        outbox["first"]["orderedItems"].as_array().unwrap().len(),
+        20
+    );
+    assert_eq!(
+        outbox["first"]["next"],
+        format!("{outbox_id}&page=true&offset=20")
+    );
+
+    let (status, page) =
+        get_outbox(&db, "/users/alice/outbox?type=Create&page=true&offset=20").await;
+    assert_eq!(status, 200);
+    assert_eq!(page["orderedItems"].as_array().unwrap().len(), 5);
+    assert_eq!(types(&page), vec!["Create"]);
+    assert!(page.get("next").is_none());
+}
+
+#[tokio::test]
+async fn test_outbox_type_filter_hides_private_items_from_anonymous_readers() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    store_activities(&db, "Create", 2, Visibility::Public).await;
+    store_activities(&db, "Create", 3, Visibility::Followers).await;
+
+    let (_, outbox) = get_outbox(&db, "/users/alice/outbox?type=Create").await;
+
+    assert_eq!(outbox["totalItems"], 2);
+    assert_eq!(outbox["first"]["orderedItems"].as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_outbox_type_filter_rejects_invalid_types() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+
+    let (status, body) = get_outbox(&db, "/users/alice/outbox?type=Create,Not%20A%20Type").await;
+
+    assert_eq!(status, 400);
+    assert_eq!(body["error"], "Invalid activity type: Not A Type");
+}
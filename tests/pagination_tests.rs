@@ -0,0 +1,518 @@
+mod common;
+
+use actix_web::test;
+use chrono::{Duration, TimeZone, Utc};
+use common::{OfflineHttpClient, ALICE_TOKEN};
+use feder8::database::{DatabaseRef, DbActivity, DbFollowRelation, DbNote, PublishState};
+use feder8::handlers;
+use feder8::http::client::HttpClient;
+use feder8::models::addressing::PUBLIC;
+use feder8::models::Visibility;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ALICE: &str = "https://example.com/users/alice";
+const CAROL: &str = "https://remote.example/users/carol";
+
+fn at(n: i64) -> chrono::DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap() + Duration::minutes(n)
+}
+
+/// Alice's public note `n`, published `n` minutes after a fixed start
+fn note(n: i64) -> DbNote {
+    DbNote {
+        id: format!("https://example.com/notes/{n}"),
+        attributed_to: ALICE.to_string(),
+        content: format!("note {n}"),
+        to_recipients: vec![PUBLIC.to_string()],
+        cc_recipients: vec![],
+        published: at(n),
+        in_reply_to: None,
+        tags: vec![],
+        attachments: vec![],
+        visibility: Visibility::Public,
+        state: PublishState::Published,
+        pinned: false,
+        created_at: at(n),
+        in_reply_to_actor: None,
+        thread_depth: Some(0),
+        thread_truncated: false,
+        sensitive: false,
+        summary: None,
+        language: None,
+        content_map: None,
+    }
+}
+
+fn activity(n: i64, actor_id: &str, to: &str) -> DbActivity {
+    DbActivity {
+        id: format!("{actor_id}/activities/{n}"),
+        actor_id: actor_id.to_string(),
+        activity_type: "Create".to_string(),
+        object: json!({"type": "Note", "content": format!("activity {n}")}),
+        target: None,
+        to_recipients: vec![to.to_string()],
+        cc_recipients: vec![],
+        published: at(n),
+        visibility: Visibility::Public,
+        state: PublishState::Published,
+        created_at: at(n),
+    }
+}
+
+/// The `Link` header and body of a GET of `uri`, as Alice
+async fn get_as_alice(db: &DatabaseRef, uri: &str) -> (Option<String>, Value) {
+    let http_client: Arc<dyn HttpClient> = Arc::new(OfflineHttpClient);
+    let app = test::init_service(
+        common::test_app(db, common::test_config(), http_client)
+            .service(handlers::note::get_statuses)
+            .service(handlers::outbox::get_outbox)
+            .service(handlers::inbox::get_inbox)
+            .service(handlers::api::accounts::get_followers),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri(uri)
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 200, "{uri}");
+    let link = resp
+        .headers()
+        .get("link")
+        .map(|value| value.to_str().unwrap().to_string());
+    (link, test::read_body_json(resp).await)
+}
+
+/// The `Link` header of a GET of `uri`, as Alice
+async fn link_header(db: &DatabaseRef, uri: &str) -> Option<String> {
+    get_as_alice(db, uri).await.0
+}
+
+fn ids(items: &Value) -> Vec<String> {
+    items
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["id"].as_str().unwrap().to_string())
+        .collect()
+}
+
+fn activity_ids(actor_id: &str, ns: impl IntoIterator<Item = i64>) -> Vec<String> {
+    ns.into_iter()
+        .map(|n| format!("{actor_id}/activities/{n}"))
+        .collect()
+}
+
+/// Activity `id` as a `max_id`/`min_id` value
+fn cursor(id: &str) -> String {
+    id.replace(':', "%3A").replace('/', "%2F")
+}
+
+fn next_and_prev(next: &str, prev: &str) -> Option<String> {
+    Some(format!(r#"<{next}>; rel="next", <{prev}>; rel="prev""#))
+}
+
+fn next_only(next: &str) -> Option<String> {
+    Some(format!(r#"<{next}>; rel="next""#))
+}
+
+fn prev_only(prev: &str) -> Option<String> {
+    Some(format!(r#"<{prev}>; rel="prev""#))
+}
+
+#[actix_web::test]
+async fn test_statuses_link_by_max_id_and_min_id() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    for n in 1..=5 {
+        db.create_note(&note(n)).await.unwrap();
+    }
+    let statuses = "https://example.com/users/alice/statuses";
+
+    // Everything fits on the first page, which has nothing before it
+    assert_eq!(link_header(&db, "/users/alice/statuses").await, None);
+
+    assert_eq!(
+        link_header(&db, "/users/alice/statuses?limit=2").await,
+        next_only(&format!("{statuses}?limit=2&max_id=4"))
+    );
+    assert_eq!(
+        link_header(&db, "/users/alice/statuses?limit=2&max_id=4").await,
+        next_and_prev(
+            &format!("{statuses}?limit=2&max_id=2"),
+            &format!("{statuses}?limit=2&min_id=3")
+        )
+    );
+    // The oldest page has nothing after it
+    assert_eq!(
+        link_header(&db, "/users/alice/statuses?limit=2&max_id=2").await,
+        prev_only(&format!("{statuses}?limit=2&min_id=1"))
+    );
+    // Paging back towards the newest keeps a way forward
+    assert_eq!(
+        link_header(&db, "/users/alice/statuses?limit=2&min_id=3").await,
+        next_and_prev(
+            &format!("{statuses}?limit=2&max_id=4"),
+            &format!("{statuses}?limit=2&min_id=5")
+        )
+    );
+}
+
+#[actix_web::test]
+async fn test_outbox_links_keep_its_filter() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    for n in 1..=25 {
+        db.create_activity(&activity(n, ALICE, PUBLIC))
+            .await
+            .unwrap();
+    }
+    let outbox = "https://example.com/users/alice/outbox";
+
+    // The unfiltered outbox pages by cursor, from the oldest item on the page
+    assert_eq!(
+        link_header(&db, "/users/alice/outbox").await,
+        next_only(&format!(
+            "{outbox}?page=true&max_id={}",
+            cursor(&format!("{ALICE}/activities/6"))
+        ))
+    );
+    assert_eq!(
+        link_header(&db, "/users/alice/outbox?type=Create").await,
+        next_only(&format!("{outbox}?type=Create&page=true&offset=20"))
+    );
+    assert_eq!(
+        link_header(&db, "/users/alice/outbox?page=true&offset=20").await,
+        prev_only(&format!("{outbox}?page=true&offset=0"))
+    );
+}
+
+#[actix_web::test]
+async fn test_inbox_and_followers_link_by_offset() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    for n in 1..=3 {
+        db.create_activity(&activity(n, ALICE, ALICE))
+            .await
+            .unwrap();
+        db.create_follow(&DbFollowRelation {
+            id: format!("follow-{n}"),
+            follower_id: format!("https://remote.example/users/{n}"),
+            following_id: ALICE.to_string(),
+            status: "accepted".to_string(),
+            created_at: at(n),
+            updated_at: at(n),
+        })
+        .await
+        .unwrap();
+    }
+    let followers = "https://example.com/api/v1/accounts/alice/followers";
+    assert_eq!(
+        link_header(&db, "/api/v1/accounts/alice/followers?limit=2").await,
+        next_only(&format!("{followers}?limit=2&offset=2"))
+    );
+
+    // The inbox pages by cursor, but links with an offset still work
+    for (path, base) in [
+        (
+            "/users/alice/inbox",
+            "https://example.com/users/alice/inbox",
+        ),
+        ("/api/v1/accounts/alice/followers", followers),
+    ] {
+        assert_eq!(link_header(&db, path).await, None, "{path}");
+        assert_eq!(
+            link_header(&db, &format!("{path}?limit=1&offset=1")).await,
+            next_and_prev(
+                &format!("{base}?limit=1&offset=2"),
+                &format!("{base}?limit=1&offset=0")
+            ),
+            "{path}"
+        );
+    }
+}
+
+#[actix_web::test]
+async fn test_inbox_links_by_max_id_and_min_id() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    for n in 1..=5 {
+        db.create_activity(&activity(n, CAROL, ALICE))
+            .await
+            .unwrap();
+    }
+    let inbox = "https://example.com/users/alice/inbox";
+    let id = |n: u32| cursor(&format!("{CAROL}/activities/{n}"));
+
+    assert_eq!(
+        link_header(&db, "/users/alice/inbox?limit=2").await,
+        next_only(&format!("{inbox}?limit=2&max_id={}", id(4)))
+    );
+    assert_eq!(
+        link_header(&db, &format!("/users/alice/inbox?limit=2&max_id={}", id(4))).await,
+        next_and_prev(
+            &format!("{inbox}?limit=2&max_id={}", id(2)),
+            &format!("{inbox}?limit=2&min_id={}", id(3))
+        )
+    );
+    assert_eq!(
+        link_header(&db, &format!("/users/alice/inbox?limit=2&min_id={}", id(3))).await,
+        next_and_prev(
+            &format!("{inbox}?limit=2&max_id={}", id(4)),
+            &format!("{inbox}?limit=2&min_id={}", id(5))
+        )
+    );
+}
+
+#[actix_web::test]
+async fn test_cursor_pages_stay_put_as_rows_arrive() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    for n in 1..=5 {
+        db.create_activity(&activity(n, ALICE, PUBLIC))
+            .await
+            .unwrap();
+        db.create_activity(&activity(n, CAROL, ALICE))
+            .await
+            .unwrap();
+        db.create_note(&note(n)).await.unwrap();
+    }
+    let activity_ids_of = |activities: &[DbActivity]| -> Vec<String> {
+        activities.iter().map(|a| a.id.clone()).collect()
+    };
+
+    let first = db.get_activities_by_actor(ALICE, 2, 0).await.unwrap();
+    assert_eq!(activity_ids_of(&first), activity_ids(ALICE, [5, 4]));
+    let first_inbox = db.get_inbox_activities(ALICE, 2, 0).await.unwrap();
+    let first_notes = db.get_notes_by_actor(ALICE, 2, 0).await.unwrap();
+
+    // Newer rows arrive between page fetches
+    for n in 6..=7 {
+        db.create_activity(&activity(n, ALICE, PUBLIC))
+            .await
+            .unwrap();
+        db.create_activity(&activity(n, CAROL, ALICE))
+            .await
+            .unwrap();
+        db.create_note(&note(n)).await.unwrap();
+    }
+
+    // An offset now serves the first page again; a cursor carries on
+    let by_offset = db.get_activities_by_actor(ALICE, 2, 2).await.unwrap();
+    assert_eq!(activity_ids_of(&by_offset), activity_ids(ALICE, [5, 4]));
+
+    let last = first.last().unwrap();
+    let second = db
+        .get_activities_by_actor_before(ALICE, last.published, &last.id, 2)
+        .await
+        .unwrap();
+    assert_eq!(activity_ids_of(&second), activity_ids(ALICE, [3, 2]));
+    let last = second.last().unwrap();
+    let third = db
+        .get_activities_by_actor_before(ALICE, last.published, &last.id, 2)
+        .await
+        .unwrap();
+    assert_eq!(activity_ids_of(&third), activity_ids(ALICE, [1]));
+
+    // Paging forward from the second page finds the first one again
+    let newest = second.first().unwrap();
+    let back = db
+        .get_activities_by_actor_after(ALICE, newest.published, &newest.id, 2)
+        .await
+        .unwrap();
+    assert_eq!(activity_ids_of(&back), activity_ids(ALICE, [5, 4]));
+
+    let last = first_inbox.last().unwrap();
+    let inbox = db
+        .get_inbox_activities_before(ALICE, last.published, &last.id, 2)
+        .await
+        .unwrap();
+    assert_eq!(activity_ids_of(&inbox), activity_ids(CAROL, [3, 2]));
+    let newest = inbox.first().unwrap();
+    let inbox_back = db
+        .get_inbox_activities_after(ALICE, newest.published, &newest.id, 2)
+        .await
+        .unwrap();
+    assert_eq!(activity_ids_of(&inbox_back), activity_ids(CAROL, [5, 4]));
+
+    let last = first_notes.last().unwrap();
+    let notes = db
+        .get_notes_by_actor_before(ALICE, last.published, &last.id, 2)
+        .await
+        .unwrap();
+    assert_eq!(
+        notes.iter().map(|n| n.id.as_str()).collect::<Vec<_>>(),
+        vec!["https://example.com/notes/3", "https://example.com/notes/2"]
+    );
+}
+
+#[actix_web::test]
+async fn test_cursor_breaks_ties_by_id_and_skips_hidden_activities() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    // Four activities published at the same instant, one followers-only
+    for n in 1..=4 {
+        let mut activity = activity(n, ALICE, PUBLIC);
+        activity.published = at(0);
+        if n == 3 {
+            activity.visibility = Visibility::Followers;
+        }
+        db.create_activity(&activity).await.unwrap();
+    }
+
+    let mut seen = Vec::new();
+    let mut page = db.get_activities_by_actor(ALICE, 1, 0).await.unwrap();
+    while let Some(last) = page.last().cloned() {
+        seen.push(last.id.clone());
+        page = db
+            .get_activities_by_actor_before(ALICE, last.published, &last.id, 1)
+            .await
+            .unwrap();
+    }
+    assert_eq!(seen, activity_ids(ALICE, [4, 3, 2, 1]));
+
+    let first = db
+        .get_public_activities_by_actor(ALICE, 1, 0)
+        .await
+        .unwrap();
+    let older = db
+        .get_public_activities_by_actor_before(ALICE, first[0].published, &first[0].id, 5)
+        .await
+        .unwrap();
+    assert_eq!(
+        older.iter().map(|a| a.id.clone()).collect::<Vec<_>>(),
+        activity_ids(ALICE, [2, 1])
+    );
+    let newer = db
+        .get_public_activities_by_actor_after(ALICE, older[1].published, &older[1].id, 5)
+        .await
+        .unwrap();
+    assert_eq!(
+        newer.iter().map(|a| a.id.clone()).collect::<Vec<_>>(),
+        activity_ids(ALICE, [4, 2])
+    );
+}
+
+#[actix_web::test]
+async fn test_outbox_and_inbox_pages_stay_put_as_activities_arrive() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    for n in 1..=25 {
+        db.create_activity(&activity(n, ALICE, PUBLIC))
+            .await
+            .unwrap();
+        db.create_activity(&activity(n, CAROL, ALICE))
+            .await
+            .unwrap();
+    }
+
+    let (_, outbox) = get_as_alice(&db, "/users/alice/outbox").await;
+    assert_eq!(
+        ids(&outbox["first"]["orderedItems"]),
+        activity_ids(ALICE, (6..=25).rev())
+    );
+    let next = outbox["first"]["next"].as_str().unwrap().to_string();
+    let (inbox_link, inbox) = get_as_alice(&db, "/users/alice/inbox?limit=20").await;
+    assert_eq!(
+        ids(&inbox["orderedItems"]),
+        activity_ids(CAROL, (6..=25).rev())
+    );
+    assert_eq!(inbox["totalItems"], 25);
+
+    for n in 26..=30 {
+        db.create_activity(&activity(n, ALICE, PUBLIC))
+            .await
+            .unwrap();
+        db.create_activity(&activity(n, CAROL, ALICE))
+            .await
+            .unwrap();
+    }
+
+    // The next pages hold exactly what was left, and lead back to the first
+    let path = next.strip_prefix("https://example.com").unwrap();
+    let (_, page) = get_as_alice(&db, path).await;
+    assert_eq!(page["type"], "OrderedCollectionPage");
+    assert_eq!(page["id"], next);
+    assert_eq!(
+        ids(&page["orderedItems"]),
+        activity_ids(ALICE, (1..=5).rev())
+    );
+    assert!(page.get("next").is_none());
+    let prev = page["prev"].as_str().unwrap();
+    let (_, back) = get_as_alice(&db, prev.strip_prefix("https://example.com").unwrap()).await;
+    assert_eq!(
+        ids(&back["orderedItems"]),
+        activity_ids(ALICE, (6..=25).rev())
+    );
+
+    let inbox_next = inbox_link.unwrap();
+    let inbox_next = inbox_next
+        .strip_prefix("<https://example.com")
+        .and_then(|link| link.strip_suffix(r#">; rel="next""#))
+        .unwrap();
+    let (_, page) = get_as_alice(&db, inbox_next).await;
+    assert_eq!(
+        ids(&page["orderedItems"]),
+        activity_ids(CAROL, (1..=5).rev())
+    );
+}
+
+#[actix_web::test]
+async fn test_cursors_must_name_a_visible_activity() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let mut hidden = activity(1, ALICE, ALICE);
+    hidden.visibility = Visibility::Direct;
+    db.create_activity(&hidden).await.unwrap();
+    let app = test::init_service(
+        common::test_app(&db, common::test_config(), Arc::new(OfflineHttpClient))
+            .service(handlers::outbox::get_outbox),
+    )
+    .await;
+
+    for uri in [
+        format!("/users/alice/outbox?max_id={}", cursor(&hidden.id)),
+        "/users/alice/outbox?min_id=unknown".to_string(),
+        format!(
+            "/users/alice/outbox?type=Create&max_id={}",
+            cursor(&hidden.id)
+        ),
+    ] {
+        let req = test::TestRequest::get().uri(&uri).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400, "{uri}");
+    }
+}
+
+#[actix_web::test]
+async fn test_inbox_cursors_must_name_an_activity_in_the_inbox() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let mut direct = activity(1, CAROL, "https://example.com/users/bob");
+    direct.visibility = Visibility::Direct;
+    db.create_activity(&direct).await.unwrap();
+    let mut scheduled = activity(2, CAROL, ALICE);
+    scheduled.state = PublishState::Scheduled;
+    db.create_activity(&scheduled).await.unwrap();
+    let app = test::init_service(
+        common::test_app(&db, common::test_config(), Arc::new(OfflineHttpClient))
+            .service(handlers::inbox::get_inbox),
+    )
+    .await;
+
+    // Someone else's direct message answers the same as no activity at all
+    for uri in [
+        format!("/users/alice/inbox?max_id={}", cursor(&direct.id)),
+        format!("/users/alice/inbox?min_id={}", cursor(&direct.id)),
+        format!("/users/alice/inbox?max_id={}", cursor(&scheduled.id)),
+        "/users/alice/inbox?max_id=unknown".to_string(),
+    ] {
+        let req = test::TestRequest::get()
+            .uri(&uri)
+            .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+            .to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 400, "{uri}");
+    }
+}
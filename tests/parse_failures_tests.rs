@@ -0,0 +1,177 @@
+mod common;
+
+use actix_web::{http::StatusCode, test};
+use common::OfflineHttpClient;
+use feder8::config::Config;
+use feder8::database::DatabaseRef;
+use feder8::handlers;
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode as ClientStatus};
+use feder8::metrics::Metrics;
+use feder8::services::actor_profiles::fetch_remote_actor;
+use feder8::services::parse_failures::{ParseFailureRecorder, MAX_STORED_DOCUMENT_BYTES};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ADMIN_TOKEN: &str = "test-admin-token";
+const CAROL: &str = "https://remote.example/users/carol";
+
+// Serves one document for every request
+struct DocumentClient(Value);
+
+#[async_trait::async_trait]
+impl HttpClient for DocumentClient {
+    async fn send(&self, _request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        Ok(HttpResponse {
+            status: ClientStatus(200),
+            headers: HashMap::new(),
+            body: serde_json::to_vec(&self.0)?,
+        })
+    }
+}
+
+/// Carol's actor document as a server that forgot `preferredUsername` sends it
+fn carol_without_username() -> Value {
+    json!({
+        "id": CAROL,
+        "type": "Person",
+        "name": "Carol",
+        "inbox": format!("{CAROL}/inbox")
+    })
+}
+
+fn counted(metrics: &Metrics, kind: &str, path: &str) -> bool {
+    metrics.render().lines().any(|line| {
+        line.starts_with("feder8_remote_parse_failures_total")
+            && line.contains(&format!("kind=\"{kind}\""))
+            && line.contains(&format!("path=\"{path}\""))
+            && line.ends_with(" 1")
+    })
+}
+
+#[tokio::test]
+async fn test_missing_field_is_recorded_with_its_path() {
+    let dir = TempDir::new().unwrap();
+    let db: DatabaseRef = Arc::new(common::sqlite_database(&dir).await);
+    let metrics = Arc::new(Metrics::new());
+    let recorder = ParseFailureRecorder::new(db.clone(), Some(metrics.clone()));
+    let client = DocumentClient(carol_without_username());
+
+    let error = fetch_remote_actor(&client, &recorder, CAROL)
+        .await
+        .unwrap_err();
+    assert!(error.to_string().contains("/preferredUsername"), "{error}");
+
+    let failures = db.get_parse_failures(10, 0).await.unwrap();
+    assert_eq!(failures.len(), 1);
+    let failure = &failures[0];
+    assert_eq!(failure.kind, "actor");
+    assert_eq!(failure.url, CAROL);
+    assert_eq!(failure.error_path, "/preferredUsername");
+    assert_eq!(failure.error, "missing field `preferredUsername`");
+    assert!(!failure.truncated);
+    let stored: Value = serde_json::from_str(&failure.document).unwrap();
+    assert_eq!(stored, carol_without_username());
+
+    assert!(counted(&metrics, "actor", "/preferredUsername"));
+}
+
+#[tokio::test]
+async fn test_large_documents_are_cut_short() {
+    let dir = TempDir::new().unwrap();
+    let db: DatabaseRef = Arc::new(common::sqlite_database(&dir).await);
+    let recorder = ParseFailureRecorder::new(db.clone(), None);
+    let mut document = carol_without_username();
+    document["summary"] = Value::String("x".repeat(MAX_STORED_DOCUMENT_BYTES));
+
+    fetch_remote_actor(&DocumentClient(document), &recorder, CAROL)
+        .await
+        .unwrap_err();
+
+    let failure = db.get_parse_failures(1, 0).await.unwrap().remove(0);
+    assert!(failure.truncated);
+    assert_eq!(failure.document.len(), MAX_STORED_DOCUMENT_BYTES);
+}
+
+#[tokio::test]
+async fn test_well_formed_documents_are_not_recorded() {
+    let dir = TempDir::new().unwrap();
+    let db: DatabaseRef = Arc::new(common::sqlite_database(&dir).await);
+    let recorder = ParseFailureRecorder::new(db.clone(), None);
+    let mut document = carol_without_username();
+    document["preferredUsername"] = json!("carol");
+
+    let actor = fetch_remote_actor(&DocumentClient(document), &recorder, CAROL)
+        .await
+        .unwrap();
+    assert_eq!(actor.username, "carol");
+    assert!(db.get_parse_failures(10, 0).await.unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn test_admin_lists_and_downloads_failures() {
+    let dir = TempDir::new().unwrap();
+    let db: DatabaseRef = Arc::new(common::sqlite_database(&dir).await);
+    let recorder = ParseFailureRecorder::new(db.clone(), None);
+    fetch_remote_actor(&DocumentClient(carol_without_username()), &recorder, CAROL)
+        .await
+        .unwrap_err();
+
+    let config = Config {
+        admin_token: Some(ADMIN_TOKEN.to_string()),
+        ..common::test_config()
+    };
+    let app = test::init_service(
+        common::test_app(&db, config, Arc::new(OfflineHttpClient))
+            .service(handlers::admin::parse_failures::list_parse_failures)
+            .service(handlers::admin::parse_failures::download_parse_failure),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/parse-failures")
+        .insert_header(("Authorization", format!("Bearer {ADMIN_TOKEN}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: Value = test::read_body_json(resp).await;
+    let listed = &body["parse_failures"][0];
+    assert_eq!(listed["kind"], "actor");
+    assert_eq!(listed["url"], CAROL);
+    assert_eq!(listed["error_path"], "/preferredUsername");
+    assert!(listed.get("document").is_none());
+
+    let id = listed["id"].as_str().unwrap();
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/admin/parse-failures/{id}"))
+        .insert_header(("Authorization", format!("Bearer {ADMIN_TOKEN}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let disposition = resp.headers().get("content-disposition").unwrap();
+    assert!(disposition
+        .to_str()
+        .unwrap()
+        .contains(&format!("actor-{id}.json")));
+    let document: Value = test::read_body_json(resp).await;
+    assert_eq!(document, carol_without_username());
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/parse-failures/unknown")
+        .insert_header(("Authorization", format!("Bearer {ADMIN_TOKEN}")))
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::NOT_FOUND
+    );
+
+    // Admin only
+    let req = test::TestRequest::get()
+        .uri("/api/admin/parse-failures")
+        .to_request();
+    assert_eq!(
+        test::call_service(&app, req).await.status(),
+        StatusCode::UNAUTHORIZED
+    );
+}
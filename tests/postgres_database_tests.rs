@@ -0,0 +1,492 @@
+//! Queries of the Postgres backend the handler scenarios in
+//! `backend_matrix_tests.rs` don't reach. Runs against the server at
+//! `PG_TEST_URL`, and is skipped when it isn't set.
+#![cfg(feature = "postgres")]
+
+mod common;
+
+use chrono::{Duration, Utc};
+use feder8::database::{
+    Database, DatabaseError, DbActivity, DbDelivery, DbFollowRelation, DbNote, DbRemoteActor,
+    DeliveryPriority, PublishState,
+};
+use feder8::models::Visibility;
+use feder8::postgres_database::PostgresDatabase;
+use serde_json::json;
+
+const ALICE: &str = "https://example.com/users/alice";
+const CAROL: &str = "https://remote.example/users/carol";
+
+/// A migrated database in a schema of its own, dropped by [`TestDb::close`]
+struct TestDb {
+    db: PostgresDatabase,
+    url: String,
+    schema: String,
+}
+
+impl TestDb {
+    async fn open() -> Option<Self> {
+        let Some(url) = std::env::var("PG_TEST_URL").ok().filter(|u| !u.is_empty()) else {
+            eprintln!("PG_TEST_URL is not set; skipping");
+            return None;
+        };
+        let schema = format!("feder8_test_{}", uuid::Uuid::new_v4().simple());
+        let admin = sqlx::PgPool::connect(&url).await.unwrap();
+        sqlx::query(&format!("CREATE SCHEMA {schema}"))
+            .execute(&admin)
+            .await
+            .unwrap();
+        admin.close().await;
+
+        let separator = if url.contains('?') { '&' } else { '?' };
+        let db = PostgresDatabase::new(&format!("{url}{separator}options[search_path]={schema}"))
+            .await
+            .unwrap();
+        db.run_migrations().await.unwrap();
+        Some(Self { db, url, schema })
+    }
+
+    async fn close(self) {
+        let admin = sqlx::PgPool::connect(&self.url).await.unwrap();
+        sqlx::query(&format!("DROP SCHEMA {} CASCADE", self.schema))
+            .execute(&admin)
+            .await
+            .unwrap();
+        admin.close().await;
+    }
+}
+
+fn note(n: u32, content: &str, minutes_ago: i64) -> DbNote {
+    DbNote {
+        id: format!("{ALICE}/notes/{n}"),
+        attributed_to: ALICE.to_string(),
+        content: content.to_string(),
+        to_recipients: vec!["https://www.w3.org/ns/activitystreams#Public".to_string()],
+        cc_recipients: Vec::new(),
+        published: Utc::now() - Duration::minutes(minutes_ago),
+        in_reply_to: None,
+        tags: Vec::new(),
+        attachments: Vec::new(),
+        visibility: Visibility::Public,
+        state: PublishState::Published,
+        pinned: false,
+        created_at: Utc::now(),
+        in_reply_to_actor: None,
+        thread_depth: Some(0),
+        thread_truncated: false,
+        sensitive: false,
+        summary: None,
+        language: None,
+        content_map: None,
+    }
+}
+
+fn delivery(n: u32, inbox_url: &str, priority: DeliveryPriority, minutes_ago: i64) -> DbDelivery {
+    DbDelivery {
+        id: format!("delivery-{n}"),
+        inbox_url: inbox_url.to_string(),
+        activity: json!({"type": "Create", "n": n}),
+        priority,
+        attempts: 0,
+        next_attempt_at: Utc::now() - Duration::minutes(minutes_ago),
+        last_error: None,
+        created_at: Utc::now() - Duration::minutes(minutes_ago),
+    }
+}
+
+#[tokio::test]
+async fn test_notes_round_trip_and_are_searchable() {
+    let Some(test_db) = TestDb::open().await else {
+        return;
+    };
+    let db = &test_db.db;
+    db.create_actor(&common::actor(ALICE, "alice", "Alice"))
+        .await
+        .unwrap();
+
+    let mut tagged = note(1, "<p>Rust on a rainy afternoon</p>", 3);
+    tagged.tags = vec!["rust".to_string()];
+    tagged.attachments = vec![json!({"type": "Image", "url": "https://example.com/a.png"})];
+    tagged.content_map = Some(json!({"en": "Rust on a rainy afternoon", "de": "Rost"}));
+    tagged.language = Some("en".to_string());
+    db.create_note(&tagged).await.unwrap();
+    let mut warned = note(2, "<p>More rust</p>", 2);
+    warned.summary = Some("rainy thoughts".to_string());
+    warned.sensitive = true;
+    warned.thread_depth = None;
+    db.create_note(&warned).await.unwrap();
+    let mut direct = note(3, "<p>rust, but only for Carol</p>", 1);
+    direct.visibility = Visibility::Direct;
+    db.create_note(&direct).await.unwrap();
+
+    let stored = db.get_note_by_id(&tagged.id).await.unwrap().unwrap();
+    assert_eq!(stored.tags, tagged.tags);
+    assert_eq!(stored.attachments, tagged.attachments);
+    assert_eq!(stored.content_map, tagged.content_map);
+    assert_eq!(stored.thread_depth, Some(0));
+    let stored = db.get_note_by_id(&warned.id).await.unwrap().unwrap();
+    assert_eq!(stored.thread_depth, None);
+    assert!(stored.sensitive);
+
+    // Every word has to match, the content warning counts, and direct
+    // notes never show up
+    let ids = |notes: Vec<DbNote>| notes.into_iter().map(|n| n.id).collect::<Vec<_>>();
+    let found = db.search_notes("rust", 10, 0).await.unwrap();
+    assert_eq!(found.len(), 2);
+    assert!(!ids(found).contains(&direct.id));
+    assert_eq!(
+        ids(db.search_notes("rainy thoughts", 10, 0).await.unwrap()),
+        vec![warned.id.clone()]
+    );
+    assert!(db.search_notes("   ", 10, 0).await.unwrap().is_empty());
+    assert!(db.search_notes("rust & !", 10, 0).await.is_ok());
+
+    assert_eq!(
+        ids(db.get_notes_by_language(ALICE, "DE", 10, 0).await.unwrap()),
+        vec![tagged.id.clone()]
+    );
+    assert_eq!(
+        ids(db
+            .search_notes_by_hashtag(ALICE, "rust", false, 10, 0)
+            .await
+            .unwrap()),
+        vec![tagged.id.clone()]
+    );
+    assert_eq!(
+        db.count_notes_by_hashtag(ALICE, "rust", false)
+            .await
+            .unwrap(),
+        1
+    );
+    assert_eq!(
+        db.count_notes_by_actor(ALICE, false, false).await.unwrap(),
+        2
+    );
+    // Feeds only carry public notes, newest first
+    assert_eq!(
+        ids(db.get_public_notes_by_actor(ALICE, 10).await.unwrap()),
+        vec![warned.id.clone(), tagged.id.clone()]
+    );
+    assert_eq!(
+        ids(db.get_public_notes_by_actor(ALICE, 1).await.unwrap()),
+        vec![warned.id.clone()]
+    );
+    assert_eq!(
+        db.count_notes_by_actor(ALICE, true, false).await.unwrap(),
+        3
+    );
+    assert_eq!(
+        db.get_notes_by_ids(&[tagged.id.clone(), direct.id.clone(), "missing".to_string()])
+            .await
+            .unwrap()
+            .len(),
+        2
+    );
+
+    // Paging the public timeline by id
+    let page = db
+        .get_public_notes(10, None, false, false, false)
+        .await
+        .unwrap();
+    assert_eq!(ids(page), vec![warned.id.clone(), tagged.id.clone()]);
+    let page = db
+        .get_public_notes(10, Some(warned.id.clone()), false, false, false)
+        .await
+        .unwrap();
+    assert_eq!(ids(page), vec![tagged.id.clone()]);
+    let page = db
+        .get_public_notes(10, None, false, true, false)
+        .await
+        .unwrap();
+    assert_eq!(ids(page), vec![tagged.id.clone()]);
+
+    test_db.close().await;
+}
+
+#[tokio::test]
+async fn test_threads_are_walked_both_ways() {
+    let Some(test_db) = TestDb::open().await else {
+        return;
+    };
+    let db = &test_db.db;
+    let root = note(1, "root", 4);
+    let mut reply = note(2, "reply", 3);
+    reply.in_reply_to = Some(root.id.clone());
+    let mut nested = note(3, "nested", 2);
+    nested.in_reply_to = Some(reply.id.clone());
+    let unrelated = note(4, "unrelated", 1);
+    for n in [&root, &reply, &nested, &unrelated] {
+        db.create_note(n).await.unwrap();
+    }
+
+    let thread: Vec<_> = db
+        .get_thread(&reply.id, 10)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|n| n.id)
+        .collect();
+    assert_eq!(
+        thread,
+        vec![root.id.clone(), reply.id.clone(), nested.id.clone()]
+    );
+    assert_eq!(db.get_thread(&root.id, 1).await.unwrap().len(), 2);
+    assert_eq!(db.count_replies(&root.id).await.unwrap(), 1);
+    assert_eq!(
+        db.get_replies(&reply.id, 10, 0).await.unwrap()[0].id,
+        nested.id
+    );
+
+    test_db.close().await;
+}
+
+#[tokio::test]
+async fn test_delivery_queue_is_ordered_and_summarised() {
+    let Some(test_db) = TestDb::open().await else {
+        return;
+    };
+    let db = &test_db.db;
+    let remote = "https://remote.example/inbox";
+    let other = "https://other.example/users/x/inbox";
+    db.enqueue_delivery(&delivery(1, remote, DeliveryPriority::Broadcast, 1))
+        .await
+        .unwrap();
+    db.enqueue_delivery(&delivery(2, remote, DeliveryPriority::Broadcast, 5))
+        .await
+        .unwrap();
+    db.enqueue_delivery(&delivery(3, other, DeliveryPriority::Interactive, 3))
+        .await
+        .unwrap();
+
+    let due = db
+        .get_due_deliveries(Utc::now(), DeliveryPriority::Broadcast, 10)
+        .await
+        .unwrap();
+    let due_ids: Vec<_> = due.iter().map(|d| d.id.as_str()).collect();
+    assert_eq!(due_ids, vec!["delivery-2", "delivery-1"]);
+    assert_eq!(due[0].activity, json!({"type": "Create", "n": 2}));
+    assert_eq!(due[0].priority, DeliveryPriority::Broadcast);
+
+    assert_eq!(
+        db.count_pending_by_priority().await.unwrap(),
+        vec![
+            (DeliveryPriority::Interactive, 1),
+            (DeliveryPriority::Broadcast, 2)
+        ]
+    );
+    let hosts: Vec<_> = db
+        .pending_by_host(10)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|h| (h.host, h.pending))
+        .collect();
+    assert_eq!(
+        hosts,
+        vec![
+            ("remote.example".to_string(), 2),
+            ("other.example".to_string(), 1)
+        ]
+    );
+    assert_eq!(
+        db.oldest_pending_age(Utc::now())
+            .await
+            .unwrap()
+            .unwrap()
+            .inbox_url,
+        remote
+    );
+
+    db.reschedule_delivery("delivery-2", 1, Utc::now() + Duration::hours(1), "timeout")
+        .await
+        .unwrap();
+    let due = db
+        .get_due_deliveries(Utc::now(), DeliveryPriority::Broadcast, 10)
+        .await
+        .unwrap();
+    assert_eq!(due.len(), 1);
+    assert!(matches!(
+        db.reschedule_delivery("missing", 1, Utc::now(), "timeout")
+            .await,
+        Err(DatabaseError::NotFound)
+    ));
+    db.delete_delivery("delivery-1").await.unwrap();
+    assert_eq!(db.count_pending_deliveries().await.unwrap(), 2);
+
+    test_db.close().await;
+}
+
+#[tokio::test]
+async fn test_actors_hosts_and_stats() {
+    let Some(test_db) = TestDb::open().await else {
+        return;
+    };
+    let db = &test_db.db;
+    db.create_actor(&common::actor(ALICE, "alice", "Alice"))
+        .await
+        .unwrap();
+    db.create_actor(&common::actor(
+        "https://example.com/users/al_ex",
+        "al_ex",
+        "Alex",
+    ))
+    .await
+    .unwrap();
+    db.create_actor(&common::actor(
+        "https://example.com/users/malice",
+        "malice",
+        "Mal",
+    ))
+    .await
+    .unwrap();
+    assert!(matches!(
+        db.create_actor(&common::actor(ALICE, "alice", "Alice"))
+            .await,
+        Err(DatabaseError::AlreadyExists)
+    ));
+
+    // An exact username comes first, and `_` only matches itself
+    let found: Vec<_> = db
+        .search_actors("ALICE", 10)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|a| a.username.unwrap())
+        .collect();
+    assert_eq!(found, vec!["alice", "malice"]);
+    let found = db.search_actors("l_e", 10).await.unwrap();
+    assert_eq!(found.len(), 1);
+    assert_eq!(found[0].username.as_deref(), Some("al_ex"));
+
+    db.upsert_remote_actor(&DbRemoteActor {
+        id: CAROL.to_string(),
+        username: "carol".to_string(),
+        name: None,
+        avatar_url: None,
+        inbox: None,
+        shared_inbox: None,
+        public_key_pem: None,
+        fetched_at: Utc::now(),
+    })
+    .await
+    .unwrap();
+    db.create_follow(&DbFollowRelation {
+        id: format!("{ALICE}/follows/1"),
+        follower_id: ALICE.to_string(),
+        following_id: CAROL.to_string(),
+        status: "accepted".to_string(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    })
+    .await
+    .unwrap();
+    assert!(db.is_following_host("REMOTE.example").await.unwrap());
+    assert!(!db.is_following_host("remote").await.unwrap());
+    assert!(!db.is_following_host("remote_example").await.unwrap());
+
+    let stats = db.get_instance_stats().await.unwrap();
+    assert_eq!(stats.user_count, 3);
+    assert_eq!(stats.domain_count, 1);
+
+    test_db.close().await;
+}
+
+#[tokio::test]
+async fn test_actor_updates_are_stamped_by_the_database() {
+    let Some(test_db) = TestDb::open().await else {
+        return;
+    };
+    let db = &test_db.db;
+    db.create_actor(&common::actor(ALICE, "alice", "Alice"))
+        .await
+        .unwrap();
+
+    let mut stale = common::actor(ALICE, "alice", "Alice Liddell");
+    stale.updated_at = Utc::now() - Duration::days(30);
+    db.update_actor(&stale).await.unwrap();
+    let updated = db.get_actor_by_id(ALICE).await.unwrap().unwrap();
+    assert_eq!(updated.name, "Alice Liddell");
+    assert!(updated.updated_at > stale.updated_at);
+
+    db.touch_actor(ALICE).await.unwrap();
+    let touched = db.get_actor_by_id(ALICE).await.unwrap().unwrap();
+    assert!(touched.updated_at > updated.updated_at);
+    assert!(matches!(
+        db.touch_actor(CAROL).await,
+        Err(DatabaseError::NotFound)
+    ));
+
+    test_db.close().await;
+}
+
+#[tokio::test]
+async fn test_gc_keeps_local_and_followed_activities() {
+    let Some(test_db) = TestDb::open().await else {
+        return;
+    };
+    let db = &test_db.db;
+    db.create_actor(&common::actor(ALICE, "alice", "Alice"))
+        .await
+        .unwrap();
+    let old = Utc::now() - Duration::days(30);
+    let activity = |id: &str, actor_id: &str, activity_type: &str, object| DbActivity {
+        id: id.to_string(),
+        actor_id: actor_id.to_string(),
+        activity_type: activity_type.to_string(),
+        object,
+        target: None,
+        to_recipients: vec![ALICE.to_string()],
+        cc_recipients: Vec::new(),
+        published: old,
+        visibility: Visibility::Public,
+        state: PublishState::Published,
+        created_at: old,
+    };
+    let follow_id = format!("{CAROL}/follows/1");
+    for a in [
+        activity("remote-create", CAROL, "Create", json!({"id": "n"})),
+        activity(&follow_id, CAROL, "Follow", json!(ALICE)),
+        activity("local-create", ALICE, "Create", json!({"id": "m"})),
+        DbActivity {
+            target: Some(CAROL.to_string()),
+            ..activity("local-move", ALICE, "Move", json!(ALICE))
+        },
+    ] {
+        db.create_activity(&a).await.unwrap();
+    }
+    let moved = db.get_activity_by_id("local-move").await.unwrap().unwrap();
+    assert_eq!(moved.target.as_deref(), Some(CAROL));
+    db.create_follow(&DbFollowRelation {
+        id: follow_id.clone(),
+        follower_id: CAROL.to_string(),
+        following_id: ALICE.to_string(),
+        status: "accepted".to_string(),
+        created_at: old,
+        updated_at: old,
+    })
+    .await
+    .unwrap();
+
+    let deleted = db
+        .delete_activities_older_than(Utc::now() - Duration::days(7))
+        .await
+        .unwrap();
+    assert_eq!(deleted, 1);
+    assert!(db
+        .get_activity_by_id("remote-create")
+        .await
+        .unwrap()
+        .is_none());
+    assert!(db.get_activity_by_id(&follow_id).await.unwrap().is_some());
+    assert_eq!(db.get_actor_inbox_count(ALICE).await.unwrap(), 3);
+
+    // Local activities go with their actor
+    db.delete_actor(ALICE).await.unwrap();
+    assert!(db
+        .get_activity_by_id("local-create")
+        .await
+        .unwrap()
+        .is_none());
+
+    test_db.close().await;
+}
@@ -0,0 +1,204 @@
+mod common;
+
+use actix_web::{http::StatusCode, test};
+use chrono::Utc;
+use feder8::config::Config;
+use feder8::database::DbBlock;
+use feder8::handlers;
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode as ClientStatus};
+use feder8::models::addressing::PUBLIC;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ALICE: &str = "https://example.com/users/alice";
+const BOB: &str = "https://remote.example/users/bob";
+const ADMIN_TOKEN: &str = "admin-secret";
+
+/// Nothing remote is reachable
+struct Offline;
+
+#[async_trait::async_trait]
+impl HttpClient for Offline {
+    async fn send(&self, _request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        Ok(HttpResponse {
+            status: ClientStatus(404),
+            headers: HashMap::new(),
+            body: Vec::new(),
+        })
+    }
+}
+
+macro_rules! trace_app {
+    ($db:expr, $sample_rate:expr) => {{
+        let config = Config {
+            admin_token: Some(ADMIN_TOKEN.to_string()),
+            trace_sample_rate: $sample_rate,
+            ..common::test_config()
+        };
+        test::init_service(
+            common::test_app(&$db, config, Arc::new(Offline))
+                .service(handlers::inbox::inbox)
+                .service(handlers::admin::traces::get_trace),
+        )
+        .await
+    }};
+}
+
+fn create(n: u32) -> Value {
+    json!({
+        "id": format!("{BOB}/activities/{n}"),
+        "type": "Create",
+        "actor": BOB,
+        "to": [ALICE],
+        "cc": [PUBLIC],
+        "object": {
+            "id": format!("{BOB}/notes/{n}"),
+            "type": "Note",
+            "attributedTo": BOB,
+            "content": "Hello Alice",
+            "to": [ALICE],
+            "cc": [PUBLIC]
+        }
+    })
+}
+
+fn post_inbox(activity: &Value) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/users/alice/inbox")
+        .set_json(activity)
+}
+
+fn get_trace(activity_id: &str) -> test::TestRequest {
+    test::TestRequest::get()
+        .uri(&format!(
+            "/api/admin/trace/{}",
+            utf8_percent_encode(activity_id, NON_ALPHANUMERIC)
+        ))
+        .insert_header(("Authorization", format!("Bearer {ADMIN_TOKEN}")))
+}
+
+fn stages(trace: &Value) -> Vec<(String, String)> {
+    trace["stages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|stage| {
+            (
+                stage["stage"].as_str().unwrap().to_string(),
+                stage["outcome"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect()
+}
+
+fn expected(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(stage, outcome)| (stage.to_string(), outcome.to_string()))
+        .collect()
+}
+
+#[actix_web::test]
+async fn test_create_is_traced_through_every_stage() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = trace_app!(db, 1.0);
+    let activity = create(1);
+
+    let resp = test::call_service(&app, post_inbox(&activity).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+
+    let resp =
+        test::call_service(&app, get_trace(&format!("{BOB}/activities/1")).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    let trace: Value = test::read_body_json(resp).await;
+    assert_eq!(trace["activity_id"], format!("{BOB}/activities/1"));
+    assert_eq!(trace["activity_type"], "Create");
+    assert_eq!(
+        stages(&trace),
+        expected(&[
+            ("parsed", "ok"),
+            ("signature", "skipped"),
+            ("audience", "ok"),
+            ("dedupe", "ok"),
+            ("stored_note", "ok"),
+            ("stored_activity", "ok"),
+            ("notifications", "skipped"),
+            ("forwarded", "skipped"),
+        ])
+    );
+    let total: u64 = trace["stages"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|stage| stage["duration_us"].as_u64().unwrap())
+        .sum();
+    assert_eq!(trace["total_duration_us"], total);
+
+    // A redelivery stops at dedupe, and the first trace is kept
+    let resp = test::call_service(&app, post_inbox(&activity).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let resp =
+        test::call_service(&app, get_trace(&format!("{BOB}/activities/1")).to_request()).await;
+    let redelivered: Value = test::read_body_json(resp).await;
+    assert_eq!(redelivered["stages"], trace["stages"]);
+}
+
+#[actix_web::test]
+async fn test_blocked_sender_is_traced_as_rejected() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    db.create_block(&DbBlock {
+        id: format!("{ALICE}/blocks/1"),
+        blocker_id: ALICE.to_string(),
+        blocked_id: BOB.to_string(),
+        created_at: Utc::now(),
+    })
+    .await
+    .unwrap();
+    let app = trace_app!(db, 1.0);
+
+    let resp = test::call_service(&app, post_inbox(&create(2)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+    let resp =
+        test::call_service(&app, get_trace(&format!("{BOB}/activities/2")).to_request()).await;
+    let trace: Value = test::read_body_json(resp).await;
+    let stages = trace["stages"].as_array().unwrap();
+    assert_eq!(stages.last().unwrap()["stage"], "audience");
+    assert_eq!(stages.last().unwrap()["outcome"], "rejected");
+    assert_eq!(
+        stages.last().unwrap()["detail"],
+        format!("{BOB} is blocked")
+    );
+}
+
+#[actix_web::test]
+async fn test_unsampled_activities_leave_no_trace() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = trace_app!(db, 0.0);
+
+    let resp = test::call_service(&app, post_inbox(&create(3)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+
+    let resp =
+        test::call_service(&app, get_trace(&format!("{BOB}/activities/3")).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+#[actix_web::test]
+async fn test_trace_requires_admin() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = trace_app!(db, 1.0);
+
+    let req = test::TestRequest::get()
+        .uri("/api/admin/trace/anything")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+}
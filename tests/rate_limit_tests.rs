@@ -0,0 +1,170 @@
+use actix_web::{http::StatusCode, test, web, App};
+use feder8::config::Config;
+use feder8::database::{create_configured_mock_database_with_token, DatabaseRef};
+use feder8::handlers;
+use feder8::http::ApiRateLimit;
+use feder8::services::rate_limit::{ApiRateLimiter, RateLimiter};
+use feder8::services::scheduler::SystemClock;
+use serde_json::json;
+use std::sync::Arc;
+
+const TOKEN: &str = "client-token";
+const ACTOR: &str = "https://example.com/users/alice";
+/// Reverse proxy trusted to say who it forwards for
+const PROXY: &str = "10.0.0.1";
+
+macro_rules! limited_app {
+    ($reads:expr, $writes:expr) => {{
+        let mut mock = create_configured_mock_database_with_token(TOKEN, ACTOR);
+        mock.expect_set_preferences().returning(|_, _| Ok(()));
+        let db: DatabaseRef = Arc::new(mock);
+        let clock = Arc::new(SystemClock);
+        let limiter = Arc::new(ApiRateLimiter::new(
+            RateLimiter::new(clock.clone(), $reads),
+            RateLimiter::new(clock, $writes),
+        ));
+        test::init_service(
+            App::new()
+                .wrap(ApiRateLimit::new(limiter, ""))
+                .app_data(web::Data::new(db))
+                .app_data(web::Data::new(Config {
+                    trusted_proxies: vec![PROXY.parse().unwrap()],
+                    ..Config::default()
+                }))
+                .service(handlers::api::preferences::get_preferences)
+                .service(handlers::api::preferences::update_preferences),
+        )
+        .await
+    }};
+}
+
+fn read() -> test::TestRequest {
+    test::TestRequest::get()
+        .uri("/api/v1/preferences")
+        .insert_header(("Authorization", format!("Bearer {TOKEN}")))
+}
+
+fn write() -> test::TestRequest {
+    test::TestRequest::patch()
+        .uri("/api/v1/preferences")
+        .insert_header(("Authorization", format!("Bearer {TOKEN}")))
+        .set_json(json!({"expand_sensitive": true}))
+}
+
+fn header<'a, B>(resp: &'a actix_web::dev::ServiceResponse<B>, name: &str) -> &'a str {
+    resp.headers().get(name).unwrap().to_str().unwrap()
+}
+
+#[actix_web::test]
+async fn test_exhausted_write_budget_leaves_reads_alone() {
+    let app = limited_app!(100, 2);
+
+    for remaining in ["1", "0"] {
+        let resp = test::call_service(&app, write().to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(header(&resp, "x-ratelimit-limit"), "2");
+        assert_eq!(header(&resp, "x-ratelimit-remaining"), remaining);
+        assert!(chrono::DateTime::parse_from_rfc3339(header(&resp, "x-ratelimit-reset")).is_ok());
+    }
+
+    let resp = test::call_service(&app, write().to_request()).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    // Two writes a minute come back one every 30 seconds
+    assert_eq!(header(&resp, "retry-after"), "30");
+    assert_eq!(header(&resp, "x-ratelimit-remaining"), "0");
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["code"], "quota_exceeded");
+
+    let resp = test::call_service(&app, read().to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(header(&resp, "x-ratelimit-limit"), "100");
+    assert_eq!(header(&resp, "x-ratelimit-remaining"), "99");
+}
+
+#[actix_web::test]
+async fn test_requests_without_a_token_are_limited_by_address() {
+    let app = limited_app!(1, 1);
+    let anonymous = |ip: &str| {
+        test::TestRequest::get()
+            .uri("/api/v1/preferences")
+            .peer_addr(format!("{ip}:40000").parse().unwrap())
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, anonymous("192.0.2.1")).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app, anonymous("192.0.2.1")).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // Neither another address nor the token shares that budget
+    let resp = test::call_service(&app, anonymous("192.0.2.2")).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app, read().to_request()).await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_clients_behind_a_trusted_proxy_are_limited_by_their_own_address() {
+    let app = limited_app!(1, 1);
+    let forwarded = |peer: &str, client: &str| {
+        test::TestRequest::get()
+            .uri("/api/v1/preferences")
+            .peer_addr(format!("{peer}:40000").parse().unwrap())
+            .insert_header(("X-Forwarded-For", client))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, forwarded(PROXY, "192.0.2.1")).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app, forwarded(PROXY, "192.0.2.1")).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // The proxy's other clients have their own budgets
+    let resp = test::call_service(&app, forwarded(PROXY, "192.0.2.2")).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    // Anyone else naming a fresh address is still counted by where they are
+    let resp = test::call_service(&app, forwarded("198.51.100.7", "192.0.2.3")).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    let resp = test::call_service(&app, forwarded("198.51.100.7", "192.0.2.4")).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+}
+
+#[actix_web::test]
+async fn test_made_up_tokens_share_their_address_budget() {
+    let app = limited_app!(1, 1);
+    let forged = |n: u32| {
+        test::TestRequest::get()
+            .uri("/api/v1/preferences")
+            .peer_addr("192.0.2.1:40000".parse().unwrap())
+            .insert_header(("Authorization", format!("Bearer made-up-{n}")))
+            .to_request()
+    };
+
+    let resp = test::call_service(&app, forged(1)).await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    // A new token every time is still the same client
+    let resp = test::call_service(&app, forged(2)).await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+
+    // A real token from that address has its own budget
+    let resp = test::call_service(
+        &app,
+        read()
+            .peer_addr("192.0.2.1:40000".parse().unwrap())
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+#[actix_web::test]
+async fn test_zero_budgets_turn_limiting_off() {
+    let app = limited_app!(0, 0);
+
+    for _ in 0..5 {
+        let resp = test::call_service(&app, write().to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp.headers().get("x-ratelimit-limit").is_none());
+    }
+}
@@ -0,0 +1,427 @@
+mod common;
+
+use actix_web::{http::StatusCode, test};
+use feder8::config::Config;
+use feder8::handlers;
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode as ClientStatus};
+use feder8::models::addressing::PUBLIC;
+use feder8::models::Visibility;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+const ADMIN_TOKEN: &str = "test-admin-token";
+const ALICE: &str = "https://example.com/users/alice";
+const RELAY_INBOX: &str = "https://relay.example/inbox";
+const RELAY_ACTOR: &str = "https://relay.example/actor";
+const CAROL: &str = "https://remote.example/users/carol";
+const FETCHED_NOTE: &str = "https://remote.example/notes/fetched";
+
+/// Records what is POSTed and serves one remote note plus any documents
+/// handed to [`RelayClient::serve`]
+#[derive(Default)]
+struct RelayClient {
+    deliveries: Mutex<Vec<(String, Value)>>,
+    documents: Mutex<HashMap<String, Value>>,
+    fetched: Mutex<Vec<String>>,
+}
+
+impl RelayClient {
+    fn deliveries(&self) -> Vec<(String, Value)> {
+        self.deliveries.lock().unwrap().clone()
+    }
+
+    fn serve(&self, document: Value) {
+        let id = document["id"].as_str().unwrap().to_string();
+        self.serve_at(&id, document);
+    }
+
+    fn serve_at(&self, url: &str, document: Value) {
+        self.documents
+            .lock()
+            .unwrap()
+            .insert(url.to_string(), document);
+    }
+
+    fn fetched(&self) -> Vec<String> {
+        self.fetched.lock().unwrap().clone()
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpClient for RelayClient {
+    async fn send(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        let (status, body) = match request.method.as_str() {
+            "POST" => {
+                let activity = serde_json::from_slice(request.body.as_deref().unwrap_or(b"{}"))?;
+                self.deliveries
+                    .lock()
+                    .unwrap()
+                    .push((request.url.clone(), activity));
+                (202, Vec::new())
+            }
+            _ => {
+                self.fetched.lock().unwrap().push(request.url.clone());
+                self.get(&request.url)?
+            }
+        };
+        Ok(HttpResponse {
+            status: ClientStatus(status),
+            headers: HashMap::new(),
+            body,
+        })
+    }
+}
+
+impl RelayClient {
+    fn get(&self, url: &str) -> anyhow::Result<(u16, Vec<u8>)> {
+        Ok(match url {
+            FETCHED_NOTE => {
+                let note = json!({
+                    "id": FETCHED_NOTE,
+                    "type": "Note",
+                    "attributedTo": CAROL,
+                    "content": "<p>Fetched by id</p>",
+                    "to": [PUBLIC]
+                });
+                (200, serde_json::to_vec(&note)?)
+            }
+            _ => match self.documents.lock().unwrap().get(url) {
+                Some(document) => (200, serde_json::to_vec(document)?),
+                None => (404, Vec::new()),
+            },
+        })
+    }
+}
+
+macro_rules! relay_app {
+    ($db:expr, $client:expr) => {{
+        let config = Config {
+            actor_name: "alice".to_string(),
+            admin_token: Some(ADMIN_TOKEN.to_string()),
+            ..common::test_config()
+        };
+        test::init_service(
+            common::test_app(&$db, config, $client.clone())
+                .service(handlers::inbox::inbox)
+                .service(handlers::admin::relays::list_relays)
+                .service(handlers::admin::relays::subscribe_relay)
+                .service(handlers::admin::relays::unsubscribe_relay),
+        )
+        .await
+    }};
+}
+
+fn admin(req: test::TestRequest) -> test::TestRequest {
+    req.insert_header(("Authorization", format!("Bearer {ADMIN_TOKEN}")))
+}
+
+fn subscribe(inbox_url: &str) -> test::TestRequest {
+    admin(test::TestRequest::post().uri("/api/admin/relays"))
+        .set_json(json!({"inbox_url": inbox_url}))
+}
+
+fn post_inbox(activity: Value) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/users/alice/inbox")
+        .set_json(activity)
+}
+
+fn announce(actor: &str, object: Value) -> Value {
+    json!({
+        "id": format!("{actor}/announces/{}", uuid::Uuid::new_v4()),
+        "type": "Announce",
+        "actor": actor,
+        "object": object,
+        "to": [PUBLIC]
+    })
+}
+
+/// The relay's Accept of the subscription `relay` as listed by the admin API
+fn accept(relay: &Value) -> Value {
+    json!({
+        "id": format!("{RELAY_ACTOR}/accepts/1"),
+        "type": "Accept",
+        "actor": RELAY_ACTOR,
+        "object": {
+            "id": relay["follow_id"],
+            "type": "Follow",
+            "actor": ALICE,
+            "object": PUBLIC
+        }
+    })
+}
+
+fn create_note(id: &str, content: &str) -> Value {
+    json!({
+        "id": format!("{id}/activity"),
+        "type": "Create",
+        "actor": CAROL,
+        "to": [PUBLIC],
+        "cc": [format!("{CAROL}/followers")],
+        "object": {
+            "id": id,
+            "type": "Note",
+            "attributedTo": CAROL,
+            "content": content,
+            "to": [PUBLIC],
+            "cc": [format!("{CAROL}/followers")]
+        }
+    })
+}
+
+#[actix_web::test]
+async fn test_subscribing_sends_the_relay_a_follow_of_public() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let client = Arc::new(RelayClient::default());
+    let app = relay_app!(db, client);
+
+    let resp = test::call_service(&app, subscribe(RELAY_INBOX).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let relay: Value = test::read_body_json(resp).await;
+    assert_eq!(relay["inbox_url"], RELAY_INBOX);
+    assert_eq!(relay["actor_id"], ALICE);
+    assert_eq!(relay["status"], "pending");
+
+    let deliveries = client.deliveries();
+    assert_eq!(deliveries.len(), 1);
+    let (inbox, follow) = &deliveries[0];
+    assert_eq!(inbox, RELAY_INBOX);
+    assert_eq!(follow["type"], "Follow");
+    assert_eq!(follow["actor"], ALICE);
+    assert_eq!(follow["object"], PUBLIC);
+    assert_eq!(follow["id"], relay["follow_id"]);
+
+    // One subscription per relay, and only to something that looks like one
+    let resp = test::call_service(&app, subscribe(RELAY_INBOX).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::CONFLICT);
+    let resp = test::call_service(&app, subscribe("relay.example").to_request()).await;
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let resp = test::call_service(
+        &app,
+        test::TestRequest::post()
+            .uri("/api/admin/relays")
+            .set_json(json!({"inbox_url": "https://other.example/inbox"}))
+            .to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(db.list_relays().await.unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn test_relay_accept_confirms_the_subscription() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let client = Arc::new(RelayClient::default());
+    let app = relay_app!(db, client);
+
+    let resp = test::call_service(&app, subscribe(RELAY_INBOX).to_request()).await;
+    let relay: Value = test::read_body_json(resp).await;
+
+    // Only the relay itself can accept
+    let mut forged = accept(&relay);
+    forged["id"] = json!("https://other.example/accepts/1");
+    forged["actor"] = json!("https://other.example/actor");
+    let resp = test::call_service(&app, post_inbox(forged).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let resp = test::call_service(
+        &app,
+        admin(test::TestRequest::get().uri("/api/admin/relays")).to_request(),
+    )
+    .await;
+    let listed: Value = test::read_body_json(resp).await;
+    assert_eq!(listed["relays"][0]["status"], "pending");
+
+    let resp = test::call_service(&app, post_inbox(accept(&relay)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+
+    let resp = test::call_service(
+        &app,
+        admin(test::TestRequest::get().uri("/api/admin/relays")).to_request(),
+    )
+    .await;
+    let listed: Value = test::read_body_json(resp).await;
+    assert_eq!(listed["relays"][0]["id"], relay["id"]);
+    assert_eq!(listed["relays"][0]["status"], "accepted");
+}
+
+#[actix_web::test]
+async fn test_announces_from_relays_are_stored() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let client = Arc::new(RelayClient::default());
+    let app = relay_app!(db, client);
+    let resp = test::call_service(&app, subscribe(RELAY_INBOX).to_request()).await;
+    let relay: Value = test::read_body_json(resp).await;
+    test::call_service(&app, post_inbox(accept(&relay)).to_request()).await;
+
+    // What the relay embeds is only a pointer; the note comes from its server
+    let embedded = "https://remote.example/notes/embedded";
+    client.serve(create_note(embedded, "<p>Passed on by a relay</p>"));
+    let activity = announce(RELAY_ACTOR, create_note(embedded, "<p>Forged</p>"));
+    let resp = test::call_service(&app, post_inbox(activity).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+
+    let note = db.get_note_by_id(embedded).await.unwrap().unwrap();
+    assert_eq!(note.attributed_to, CAROL);
+    assert_eq!(note.content, "<p>Passed on by a relay</p>");
+    assert_eq!(note.visibility, Visibility::Public);
+    let create = db
+        .get_activity_by_id(&format!("{embedded}/activity"))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(create.actor_id, CAROL);
+    assert_eq!(create.activity_type, "Create");
+
+    // Relays may announce by id only
+    let activity = announce(RELAY_ACTOR, json!(FETCHED_NOTE));
+    let resp = test::call_service(&app, post_inbox(activity).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    let note = db.get_note_by_id(FETCHED_NOTE).await.unwrap().unwrap();
+    assert_eq!(note.content, "<p>Fetched by id</p>");
+
+    // Announces from anyone else are not relayed posts
+    let stray = "https://remote.example/notes/stray";
+    let activity = announce(CAROL, create_note(stray, "<p>Boosted</p>"));
+    let resp = test::call_service(&app, post_inbox(activity).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    assert!(db.get_note_by_id(stray).await.unwrap().is_none());
+}
+
+#[actix_web::test]
+async fn test_reannounces_of_stored_objects_are_not_fetched() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let client = Arc::new(RelayClient::default());
+    let app = relay_app!(db, client);
+    let resp = test::call_service(&app, subscribe(RELAY_INBOX).to_request()).await;
+    let relay: Value = test::read_body_json(resp).await;
+    test::call_service(&app, post_inbox(accept(&relay)).to_request()).await;
+
+    let embedded = "https://remote.example/notes/embedded";
+    let create_id = format!("{embedded}/activity");
+    client.serve_at(&create_id, create_note(embedded, "<p>Once</p>"));
+    for object in [json!(FETCHED_NOTE), json!(create_id)] {
+        for _ in 0..3 {
+            let activity = announce(RELAY_ACTOR, object.clone());
+            let resp = test::call_service(&app, post_inbox(activity).to_request()).await;
+            assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        }
+    }
+
+    // Each was fetched the first time only
+    assert_eq!(client.fetched(), vec![FETCHED_NOTE.to_string(), create_id]);
+    assert!(db.get_note_by_id(embedded).await.unwrap().is_some());
+}
+
+#[actix_web::test]
+async fn test_relays_must_accept_before_their_announces_count() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let client = Arc::new(RelayClient::default());
+    let app = relay_app!(db, client);
+    test::call_service(&app, subscribe(RELAY_INBOX).to_request()).await;
+
+    let activity = announce(RELAY_ACTOR, json!(FETCHED_NOTE));
+    let resp = test::call_service(&app, post_inbox(activity).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::ACCEPTED);
+    assert!(db.get_note_by_id(FETCHED_NOTE).await.unwrap().is_none());
+    assert!(client.fetched().is_empty());
+}
+
+#[actix_web::test]
+async fn test_relayed_notes_must_come_from_their_authors_server() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let client = Arc::new(RelayClient::default());
+    let app = relay_app!(db, client);
+    let resp = test::call_service(&app, subscribe(RELAY_INBOX).to_request()).await;
+    let relay: Value = test::read_body_json(resp).await;
+    test::call_service(&app, post_inbox(accept(&relay)).to_request()).await;
+
+    // Our own ids are never taken from a relay, nor even fetched
+    let local = "https://example.com/notes/1";
+    let activity = announce(RELAY_ACTOR, create_note(local, "<p>Not Alice</p>"));
+    test::call_service(&app, post_inbox(activity).to_request()).await;
+    assert!(db.get_note_by_id(local).await.unwrap().is_none());
+    assert!(client.fetched().is_empty());
+
+    // A server can't pass off a note as written by someone elsewhere
+    let impostor = "https://evil.example/notes/1";
+    let mut note = create_note(impostor, "<p>Carol says hi</p>")["object"].clone();
+    note["attributedTo"] = json!(CAROL);
+    client.serve(note);
+    let activity = announce(RELAY_ACTOR, json!(impostor));
+    test::call_service(&app, post_inbox(activity).to_request()).await;
+    assert!(db.get_note_by_id(impostor).await.unwrap().is_none());
+
+    // Nor answer for an id it was not asked about
+    let elsewhere = "https://evil.example/notes/2";
+    client.serve_at(
+        elsewhere,
+        json!({
+            "id": "https://evil.example/notes/3",
+            "type": "Note",
+            "attributedTo": "https://evil.example/users/mallory",
+            "content": "<p>Swapped</p>"
+        }),
+    );
+    let activity = announce(RELAY_ACTOR, json!(elsewhere));
+    test::call_service(&app, post_inbox(activity).to_request()).await;
+    assert!(db
+        .get_note_by_id("https://evil.example/notes/3")
+        .await
+        .unwrap()
+        .is_none());
+    assert_eq!(
+        client.fetched(),
+        vec![impostor.to_string(), elsewhere.to_string()]
+    );
+}
+
+#[actix_web::test]
+async fn test_unsubscribing_sends_an_undo() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let client = Arc::new(RelayClient::default());
+    let app = relay_app!(db, client);
+
+    let resp = test::call_service(&app, subscribe(RELAY_INBOX).to_request()).await;
+    let relay: Value = test::read_body_json(resp).await;
+    let uri = format!("/api/admin/relays/{}", relay["id"].as_str().unwrap());
+
+    let resp = test::call_service(
+        &app,
+        admin(test::TestRequest::delete().uri(&uri)).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+    assert!(db.list_relays().await.unwrap().is_empty());
+
+    let deliveries = client.deliveries();
+    assert_eq!(deliveries.len(), 2);
+    let (inbox, undo) = &deliveries[1];
+    assert_eq!(inbox, RELAY_INBOX);
+    assert_eq!(undo["type"], "Undo");
+    assert_eq!(undo["actor"], ALICE);
+    assert_eq!(undo["object"]["type"], "Follow");
+    assert_eq!(undo["object"]["id"], relay["follow_id"]);
+    assert_eq!(undo["object"]["object"], PUBLIC);
+
+    let resp = test::call_service(
+        &app,
+        admin(test::TestRequest::delete().uri(&uri)).to_request(),
+    )
+    .await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    // The relay's posts are no longer taken in
+    let late = "https://remote.example/notes/late";
+    let activity = announce(RELAY_ACTOR, create_note(late, "<p>Too late</p>"));
+    test::call_service(&app, post_inbox(activity).to_request()).await;
+    assert!(db.get_note_by_id(late).await.unwrap().is_none());
+}
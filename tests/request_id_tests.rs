@@ -0,0 +1,125 @@
+use actix_web::{get, test, web, App, HttpResponse};
+use feder8::config::Config;
+use feder8::database::{create_configured_mock_database, DatabaseRef};
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse as ClientResponse, StatusCode};
+use feder8::http::request_id::{self, REQUEST_ID_HEADER};
+use feder8::http::RequestId;
+use feder8::services::delivery::DeliveryService;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// Accepts everything, remembering each request's headers
+#[derive(Default)]
+struct RecordingHttpClient {
+    headers: Mutex<Vec<HashMap<String, String>>>,
+}
+
+#[async_trait::async_trait]
+impl HttpClient for RecordingHttpClient {
+    async fn send(&self, request: HttpRequest) -> anyhow::Result<ClientResponse> {
+        self.headers.lock().unwrap().push(request.headers);
+        Ok(ClientResponse {
+            status: StatusCode(202),
+            headers: HashMap::new(),
+            body: Vec::new(),
+        })
+    }
+}
+
+/// Answers with the request ID handlers see
+#[get("/whoami")]
+async fn whoami() -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "request_id": request_id::current() }))
+}
+
+/// Delivers an activity, as handlers do on behalf of a request
+#[get("/deliver")]
+async fn deliver(delivery: web::Data<DeliveryService>) -> HttpResponse {
+    delivery
+        .deliver_activity("https://remote.example/inbox", json!({"type": "Create"}))
+        .await
+        .unwrap();
+    HttpResponse::Accepted().finish()
+}
+
+fn response_id(resp: &actix_web::dev::ServiceResponse) -> String {
+    resp.headers()
+        .get(REQUEST_ID_HEADER)
+        .expect("response carries a request id")
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+#[actix_web::test]
+async fn test_request_id_is_echoed_when_sent() {
+    let app = test::init_service(App::new().wrap(RequestId).service(whoami)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/whoami")
+        .insert_header(("X-Request-ID", "trace-abc-123"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+
+    assert_eq!(resp.status(), 200);
+    assert_eq!(response_id(&resp), "trace-abc-123");
+    let body: serde_json::Value = test::read_body_json(resp).await;
+    assert_eq!(body["request_id"], "trace-abc-123");
+}
+
+#[actix_web::test]
+async fn test_request_id_is_generated_when_absent_or_unusable() {
+    let app = test::init_service(App::new().wrap(RequestId).service(whoami)).await;
+
+    let first =
+        test::call_service(&app, test::TestRequest::get().uri("/whoami").to_request()).await;
+    let second =
+        test::call_service(&app, test::TestRequest::get().uri("/whoami").to_request()).await;
+    let generated = response_id(&first);
+    assert!(uuid::Uuid::parse_str(&generated).is_ok(), "{generated}");
+    assert_ne!(generated, response_id(&second));
+    let body: serde_json::Value = test::read_body_json(first).await;
+    assert_eq!(body["request_id"], generated);
+
+    let req = test::TestRequest::get()
+        .uri("/whoami")
+        .insert_header(("X-Request-ID", "x".repeat(500)))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(uuid::Uuid::parse_str(&response_id(&resp)).is_ok());
+
+    // Unrouted requests get one too
+    let resp =
+        test::call_service(&app, test::TestRequest::get().uri("/missing").to_request()).await;
+    assert_eq!(resp.status(), 404);
+    assert!(uuid::Uuid::parse_str(&response_id(&resp)).is_ok());
+}
+
+#[actix_web::test]
+async fn test_request_id_is_sent_on_deliveries() {
+    let client = Arc::new(RecordingHttpClient::default());
+    let db: DatabaseRef = Arc::new(create_configured_mock_database());
+    let delivery = DeliveryService::new(Config::default(), client.clone(), db);
+    let app = test::init_service(
+        App::new()
+            .wrap(RequestId)
+            .app_data(web::Data::new(delivery))
+            .service(deliver),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/deliver")
+        .insert_header(("X-Request-ID", "trace-xyz"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 202);
+
+    let headers = client.headers.lock().unwrap();
+    assert_eq!(headers.len(), 1);
+    assert_eq!(
+        headers[0].get(REQUEST_ID_HEADER).map(String::as_str),
+        Some("trace-xyz")
+    );
+}
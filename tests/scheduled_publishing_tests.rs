@@ -0,0 +1,233 @@
+mod common;
+
+use actix_web::{test, App};
+use chrono::{Duration, Utc};
+use common::ALICE_TOKEN;
+use feder8::database::{DatabaseRef, DbFollowRelation, DbRemoteActor, DeliveryPriority};
+use feder8::handlers;
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode};
+use feder8::models::addressing::PUBLIC;
+use feder8::services::delivery::DeliveryService;
+use feder8::services::scheduled_publishing;
+use feder8::urls::UrlBuilder;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ACTOR_ID: &str = "https://example.com/users/alice";
+const BOB: &str = "https://remote.example/users/bob";
+const CAROL: &str = "https://remote.example/users/carol";
+
+// Serves Bob's actor document and refuses everything else
+struct RemoteActorClient;
+
+#[async_trait::async_trait]
+impl HttpClient for RemoteActorClient {
+    async fn send(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        anyhow::ensure!(request.url == BOB, "unexpected request to {}", request.url);
+        Ok(HttpResponse {
+            status: StatusCode(200),
+            headers: HashMap::new(),
+            body: serde_json::to_vec(&json!({
+                "id": BOB,
+                "type": "Person",
+                "preferredUsername": "bob",
+                "inbox": format!("{BOB}/inbox")
+            }))?,
+        })
+    }
+}
+
+fn create_test_app(
+    db: &DatabaseRef,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    common::test_app(db, common::test_config(), Arc::new(RemoteActorClient))
+        .service(handlers::outbox::get_outbox)
+        .service(handlers::outbox::post_outbox)
+}
+
+fn delivery(db: &DatabaseRef) -> DeliveryService {
+    DeliveryService::new(
+        common::test_config(),
+        Arc::new(RemoteActorClient),
+        db.clone(),
+    )
+}
+
+async fn outbox_total(db: &DatabaseRef) -> u64 {
+    let app = test::init_service(create_test_app(db)).await;
+    let req = test::TestRequest::get()
+        .uri("/users/alice/outbox")
+        .to_request();
+    let body: Value = test::call_and_read_body_json(&app, req).await;
+    body["totalItems"].as_u64().unwrap()
+}
+
+#[tokio::test]
+async fn test_future_create_is_published_when_due() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let publish_at = Utc::now() + Duration::hours(1);
+
+    let app = test::init_service(create_test_app(&db)).await;
+    let req = test::TestRequest::post()
+        .uri("/users/alice/outbox")
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+        .set_json(json!({
+            "type": "Create",
+            "actor": ACTOR_ID,
+            "object": {
+                "type": "Note",
+                "content": "Later, Bob",
+                "published": publish_at.to_rfc3339(),
+                "tag": [{"type": "Mention", "name": "@bob@remote.example", "href": BOB}]
+            },
+            "to": [PUBLIC]
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let created: Value = test::read_body_json(resp).await;
+
+    // Held back: not in the outbox and nothing queued for Bob
+    assert_eq!(outbox_total(&db).await, 0);
+    assert!(db
+        .get_due_deliveries(publish_at + Duration::days(1), DeliveryPriority::Direct, 10,)
+        .await
+        .unwrap()
+        .is_empty());
+
+    let early = scheduled_publishing::publish_due(
+        &db,
+        &delivery(&db).actor_fetch(),
+        &UrlBuilder::new("https://example.com", None),
+        &delivery(&db),
+        Utc::now(),
+    )
+    .await
+    .unwrap();
+    assert_eq!(early, 0);
+
+    let published = scheduled_publishing::publish_due(
+        &db,
+        &delivery(&db).actor_fetch(),
+        &UrlBuilder::new("https://example.com", None),
+        &delivery(&db),
+        publish_at + Duration::seconds(1),
+    )
+    .await
+    .unwrap();
+    assert_eq!(published, 1);
+
+    assert_eq!(outbox_total(&db).await, 1);
+    let deliveries = db
+        .get_due_deliveries(publish_at + Duration::days(1), DeliveryPriority::Direct, 10)
+        .await
+        .unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].inbox_url, format!("{BOB}/inbox"));
+    assert_eq!(deliveries[0].activity["id"], created["id"]);
+
+    // Already published activities aren't picked up again
+    let again = scheduled_publishing::publish_due(
+        &db,
+        &delivery(&db).actor_fetch(),
+        &UrlBuilder::new("https://example.com", None),
+        &delivery(&db),
+        publish_at + Duration::hours(1),
+    )
+    .await
+    .unwrap();
+    assert_eq!(again, 0);
+}
+
+#[tokio::test]
+async fn test_published_activity_reaches_followers_once() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let publish_at = Utc::now() + Duration::hours(1);
+    for follower in [BOB, CAROL] {
+        db.upsert_remote_actor(&DbRemoteActor {
+            id: follower.to_string(),
+            username: follower.rsplit('/').next().unwrap().to_string(),
+            name: None,
+            avatar_url: None,
+            inbox: Some(format!("{follower}/inbox")),
+            shared_inbox: None,
+            public_key_pem: None,
+            fetched_at: Utc::now(),
+        })
+        .await
+        .unwrap();
+        db.create_follow(&DbFollowRelation {
+            id: format!("{follower}/follows/alice"),
+            follower_id: follower.to_string(),
+            following_id: ACTOR_ID.to_string(),
+            status: "accepted".to_string(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        })
+        .await
+        .unwrap();
+    }
+
+    let app = test::init_service(create_test_app(&db)).await;
+    let req = test::TestRequest::post()
+        .uri("/users/alice/outbox")
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+        .set_json(json!({
+            "type": "Create",
+            "actor": ACTOR_ID,
+            "object": {
+                "type": "Note",
+                "content": "Later, everyone",
+                "published": publish_at.to_rfc3339(),
+                "tag": [{"type": "Mention", "name": "@bob@remote.example", "href": BOB}]
+            },
+            "to": [PUBLIC]
+        }))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+
+    let published = scheduled_publishing::publish_due(
+        &db,
+        &delivery(&db).actor_fetch(),
+        &UrlBuilder::new("https://example.com", None),
+        &delivery(&db),
+        publish_at + Duration::seconds(1),
+    )
+    .await
+    .unwrap();
+    assert_eq!(published, 1);
+
+    // This client refuses deliveries, so the fan-out queues them for retry:
+    // one per follower, and none for Bob as a mentioned actor
+    let later = publish_at + Duration::days(1);
+    assert!(db
+        .get_due_deliveries(later, DeliveryPriority::Direct, 10)
+        .await
+        .unwrap()
+        .is_empty());
+    let mut inboxes: Vec<String> = db
+        .get_due_deliveries(later, DeliveryPriority::Broadcast, 10)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|delivery| delivery.inbox_url)
+        .collect();
+    inboxes.sort();
+    assert_eq!(
+        inboxes,
+        vec![format!("{BOB}/inbox"), format!("{CAROL}/inbox")]
+    );
+}
@@ -0,0 +1,491 @@
+mod common;
+
+use actix_web::test;
+use chrono::Utc;
+use common::ALICE_TOKEN;
+use feder8::database::{Database, DatabaseRef, DbActor, DbNote, PublishState};
+use feder8::handlers;
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode};
+use feder8::models::addressing::PUBLIC;
+use feder8::models::Visibility;
+use feder8::services::search::SearchService;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+const CAROL: &str = "https://remote.example/users/carol";
+const CAROL_JRD: &str =
+    "https://remote.example/.well-known/webfinger?resource=acct:carol@remote.example";
+// A bridge without working WebFinger, whose actors live at `/@user`
+const DAVE: &str = "https://bridge.example/@dave";
+
+// Serves Carol's WebFinger JRD and actor document, Dave's actor document
+// (also, falsely, as Eve's), and records every request
+#[derive(Default)]
+struct RemoteClient {
+    requested: Mutex<Vec<String>>,
+}
+
+#[async_trait::async_trait]
+impl HttpClient for RemoteClient {
+    async fn send(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        self.requested.lock().unwrap().push(request.url.clone());
+        let (status, body) = match request.url.as_str() {
+            CAROL_JRD => (
+                200,
+                json!({
+                    "subject": "acct:carol@remote.example",
+                    "links": [
+                        {"rel": "self", "type": "application/activity+json", "href": CAROL}
+                    ]
+                }),
+            ),
+            CAROL => (
+                200,
+                json!({
+                    "id": CAROL,
+                    "type": "Person",
+                    "preferredUsername": "carol",
+                    "name": "Carol",
+                    "inbox": format!("{CAROL}/inbox"),
+                    "icon": {"type": "Image", "url": "https://remote.example/carol.png"}
+                }),
+            ),
+            DAVE | "https://bridge.example/@eve" => (
+                200,
+                json!({
+                    "id": DAVE,
+                    "type": "Person",
+                    "preferredUsername": "dave",
+                    "inbox": format!("{DAVE}/inbox")
+                }),
+            ),
+            _ => (404, json!({})),
+        };
+        Ok(HttpResponse {
+            status: StatusCode(status),
+            headers: HashMap::new(),
+            body: serde_json::to_vec(&body)?,
+        })
+    }
+}
+
+fn local_actor(username: &str, name: &str) -> DbActor {
+    common::actor(
+        &format!("https://example.com/users/{username}"),
+        username,
+        name,
+    )
+}
+
+async fn create_test_database(dir: &TempDir) -> DatabaseRef {
+    let db = common::sqlite_database(dir).await;
+    for (username, name) in [
+        ("alice", "Alice Liddell"),
+        ("malice", "Mal"),
+        ("bob", "Bob the Builder"),
+        ("under_score", "Under Score"),
+    ] {
+        db.create_actor(&local_actor(username, name)).await.unwrap();
+    }
+    db.create_token(&common::alice_token()).await.unwrap();
+    Arc::new(db)
+}
+
+async fn search(db: &DatabaseRef, client: Arc<RemoteClient>, query: &str) -> (u16, Value) {
+    get(db, client, &format!("/api/search?{query}")).await
+}
+
+/// Search as Alice, asking for remote handles and URLs to be looked up
+async fn resolve(db: &DatabaseRef, client: Arc<RemoteClient>, query: &str) -> (u16, Value) {
+    let uri = format!("/api/search?{query}&resolve=true");
+    get_as(db, client, &uri, Some(ALICE_TOKEN)).await
+}
+
+async fn get(db: &DatabaseRef, client: Arc<RemoteClient>, uri: &str) -> (u16, Value) {
+    get_as(db, client, uri, None).await
+}
+
+async fn get_as(
+    db: &DatabaseRef,
+    client: Arc<RemoteClient>,
+    uri: &str,
+    token: Option<&str>,
+) -> (u16, Value) {
+    let app = test::init_service(
+        common::test_app(db, common::test_config(), client)
+            .service(handlers::api::search::search)
+            .service(handlers::api::search::search_notes)
+            .service(handlers::api::search::search_v1),
+    )
+    .await;
+
+    let mut req = test::TestRequest::get().uri(uri);
+    if let Some(token) = token {
+        req = req.insert_header(("Authorization", format!("Bearer {token}")));
+    }
+    let resp = test::call_service(&app, req.to_request()).await;
+    let status = resp.status().as_u16();
+    let body = if status == 200 {
+        test::read_body_json(resp).await
+    } else {
+        Value::Null
+    };
+    (status, body)
+}
+
+fn accts(results: &Value) -> Vec<&str> {
+    results
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|account| account["acct"].as_str().unwrap())
+        .collect()
+}
+
+#[actix_web::test]
+async fn test_local_actors_match_by_username_or_name() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let client = Arc::new(RemoteClient::default());
+
+    let (status, results) = search(&db, client.clone(), "q=alice").await;
+    assert_eq!(status, 200);
+    // The exact username match comes first
+    assert_eq!(accts(&results), vec!["alice", "malice"]);
+    assert_eq!(results[0]["display_name"], "Alice Liddell");
+    assert_eq!(results[0]["url"], "https://example.com/users/alice");
+
+    let (_, results) = search(&db, client.clone(), "q=BUILDER").await;
+    assert_eq!(accts(&results), vec!["bob"]);
+
+    // Wildcards in the query are matched literally
+    let (_, results) = search(&db, client.clone(), "q=b_b").await;
+    assert!(accts(&results).is_empty());
+    let (_, results) = search(&db, client.clone(), "q=a%25e").await;
+    assert!(accts(&results).is_empty());
+    let (_, results) = search(&db, client.clone(), "q=r_s").await;
+    assert_eq!(accts(&results), vec!["under_score"]);
+
+    let (_, results) = search(&db, client.clone(), "q=ali&limit=1").await;
+    assert_eq!(accts(&results), vec!["alice"]);
+
+    assert!(client.requested.lock().unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn test_remote_handles_are_resolved_through_webfinger() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let client = Arc::new(RemoteClient::default());
+
+    let (status, results) = resolve(&db, client.clone(), "q=@carol@remote.example").await;
+    assert_eq!(status, 200);
+    assert_eq!(accts(&results), vec!["carol@remote.example"]);
+    assert_eq!(results[0]["id"], CAROL);
+    assert_eq!(results[0]["display_name"], "Carol");
+    assert_eq!(results[0]["avatar"], "https://remote.example/carol.png");
+    assert_eq!(*client.requested.lock().unwrap(), vec![CAROL_JRD, CAROL]);
+
+    // The fetched profile is cached for later lookups
+    let cached = db.get_remote_actor(CAROL).await.unwrap().unwrap();
+    assert_eq!(
+        cached.inbox.as_deref(),
+        Some("https://remote.example/users/carol/inbox")
+    );
+}
+
+#[actix_web::test]
+async fn test_handles_fall_back_to_conventional_actor_urls() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let client = Arc::new(RemoteClient::default());
+
+    let (status, results) = resolve(&db, client.clone(), "q=@dave@bridge.example").await;
+    assert_eq!(status, 200);
+    assert_eq!(accts(&results), vec!["dave@bridge.example"]);
+    assert_eq!(
+        *client.requested.lock().unwrap(),
+        vec![
+            "https://bridge.example/.well-known/webfinger?resource=acct:dave@bridge.example",
+            "https://bridge.example/users/dave",
+            DAVE,
+            DAVE,
+        ]
+    );
+
+    // A document that answers for someone else is not taken as theirs
+    let (status, results) = resolve(&db, client, "q=@eve@bridge.example").await;
+    assert_eq!(status, 200);
+    assert!(accts(&results).is_empty());
+}
+
+#[actix_web::test]
+async fn test_actor_urls_are_fetched_directly() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let client = Arc::new(RemoteClient::default());
+
+    let (_, results) = resolve(&db, client.clone(), &format!("q={CAROL}")).await;
+    assert_eq!(accts(&results), vec!["carol@remote.example"]);
+    assert_eq!(*client.requested.lock().unwrap(), vec![CAROL]);
+}
+
+#[actix_web::test]
+async fn test_remote_lookups_need_a_signed_in_resolve() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let client = Arc::new(RemoteClient::default());
+
+    for uri in [
+        "/api/search?q=@carol@remote.example".to_string(),
+        "/api/search?q=@carol@remote.example&resolve=true".to_string(),
+        format!("/api/search?q={CAROL}&resolve=true"),
+    ] {
+        let (status, results) = get(&db, client.clone(), &uri).await;
+        assert_eq!(status, 200, "{uri}");
+        assert!(accts(&results).is_empty(), "{uri}");
+    }
+    let uri = "/api/search?q=@carol@remote.example";
+    let (_, results) = get_as(&db, client.clone(), uri, Some(ALICE_TOKEN)).await;
+    assert!(accts(&results).is_empty());
+    assert!(client.requested.lock().unwrap().is_empty());
+
+    // A made-up token is refused rather than searched anonymously
+    let uri = "/api/search?q=alice&resolve=true";
+    let (status, _) = get_as(&db, client.clone(), uri, Some("bogus")).await;
+    assert_eq!(status, 401);
+}
+
+#[actix_web::test]
+async fn test_private_and_plain_http_targets_are_not_fetched() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let client = Arc::new(RemoteClient::default());
+
+    for query in [
+        "q=http://remote.example/users/carol",
+        "q=https://127.0.0.1/users/carol",
+        "q=https://169.254.169.254/latest/meta-data",
+        "q=https://[::1]/users/carol",
+        "q=https://localhost/users/carol",
+        "q=@carol@10.0.0.5",
+        "q=@carol@localhost",
+    ] {
+        let (status, results) = resolve(&db, client.clone(), query).await;
+        assert_eq!(status, 200, "{query}");
+        assert!(accts(&results).is_empty(), "{query}");
+    }
+    assert!(client.requested.lock().unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn test_local_handles_and_urls_are_not_fetched() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let client = Arc::new(RemoteClient::default());
+
+    for query in ["q=@bob@example.com", "q=https://example.com/users/bob"] {
+        let (_, results) = search(&db, client.clone(), query).await;
+        assert_eq!(accts(&results), vec!["bob"], "{query}");
+    }
+    assert!(client.requested.lock().unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn test_failed_lookups_are_not_errors() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let client = Arc::new(RemoteClient::default());
+
+    let (status, results) = resolve(&db, client.clone(), "q=@nobody@remote.example").await;
+    assert_eq!(status, 200);
+    assert!(accts(&results).is_empty());
+
+    let (status, _) = search(&db, client, "q=%20").await;
+    assert_eq!(status, 400);
+}
+
+fn note(n: u32, content: &str, visibility: Visibility) -> DbNote {
+    DbNote {
+        id: format!("https://example.com/notes/{n}"),
+        attributed_to: "https://example.com/users/alice".to_string(),
+        content: content.to_string(),
+        to_recipients: vec![PUBLIC.to_string()],
+        cc_recipients: vec![],
+        published: Utc::now(),
+        in_reply_to: None,
+        tags: vec![],
+        attachments: vec![],
+        visibility,
+        state: PublishState::Published,
+        pinned: false,
+        created_at: Utc::now(),
+        in_reply_to_actor: None,
+        thread_depth: Some(0),
+        thread_truncated: false,
+        sensitive: false,
+        summary: None,
+        language: None,
+        content_map: None,
+    }
+}
+
+async fn note_ids(db: &DatabaseRef, query: &str) -> Vec<String> {
+    let uri = format!("/api/search/notes?{query}");
+    let (status, results) = get(db, Arc::new(RemoteClient::default()), &uri).await;
+    assert_eq!(status, 200, "{query}");
+    results
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|note| {
+            let id = note["id"].as_str().unwrap();
+            id.rsplit('/').next().unwrap().to_string()
+        })
+        .collect()
+}
+
+#[actix_web::test]
+async fn test_notes_are_ranked_by_relevance() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let mut warned = note(4, "<p>Dinner</p>", Visibility::Public);
+    warned.summary = Some("rust spoilers".to_string());
+    for note in [
+        note(1, "<p>Rust is a language. I use it at work, on weekends and at night, whenever there is time to spare for it.</p>", Visibility::Public),
+        note(2, "<p>Rust, rust, rust!</p>", Visibility::Public),
+        note(3, "<p>Nothing to see here</p>", Visibility::Public),
+        warned,
+        note(5, "<p>Secret rust plans</p>", Visibility::Direct),
+        note(6, "<p>Unlisted rust thoughts</p>", Visibility::Unlisted),
+    ] {
+        db.create_note(&note).await.unwrap();
+    }
+
+    assert_eq!(note_ids(&db, "q=rust").await, vec!["2", "4", "1"]);
+    // Every word has to match
+    assert_eq!(note_ids(&db, "q=rust%20language").await, vec!["1"]);
+    assert_eq!(note_ids(&db, "q=rust&limit=1&offset=1").await, vec!["4"]);
+}
+
+#[actix_web::test]
+async fn test_deleted_notes_drop_out_of_results() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    db.create_note(&note(1, "<p>Fleeting thought</p>", Visibility::Public))
+        .await
+        .unwrap();
+    db.create_note(&note(2, "<p>Lasting thought</p>", Visibility::Public))
+        .await
+        .unwrap();
+    assert_eq!(note_ids(&db, "q=thought").await.len(), 2);
+
+    db.delete_note("https://example.com/notes/1").await.unwrap();
+    assert_eq!(note_ids(&db, "q=thought").await, vec!["2"]);
+    assert!(note_ids(&db, "q=fleeting").await.is_empty());
+}
+
+#[actix_web::test]
+async fn test_search_syntax_is_taken_literally() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    db.create_note(&note(1, "<p>Cats OR dogs</p>", Visibility::Public))
+        .await
+        .unwrap();
+    db.create_note(&note(2, "<p>Only cats</p>", Visibility::Public))
+        .await
+        .unwrap();
+
+    // Operators, column filters and prefixes are just words, and stray quotes
+    // are ignored like other punctuation
+    for (query, expected) in [
+        ("q=cats%20OR%20dogs", vec!["1"]),
+        ("q=dogs%20OR%20nothing", vec![]),
+        ("q=note_id:1", vec![]),
+        ("q=cat*", vec![]),
+        ("q=%22only", vec!["2"]),
+        ("q=NEAR(cats", vec![]),
+    ] {
+        assert_eq!(note_ids(&db, query).await, expected, "{query}");
+    }
+
+    let (status, _) = get(
+        &db,
+        Arc::new(RemoteClient::default()),
+        "/api/search/notes?q=",
+    )
+    .await;
+    assert_eq!(status, 400);
+}
+
+#[actix_web::test]
+async fn test_search_service_matches_note_content() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    for note in [
+        note(1, "<p>Baking sourdough bread</p>", Visibility::Public),
+        note(2, "<p>Bread and butter</p>", Visibility::Public),
+        note(
+            3,
+            "<p>Sourdough starter, private</p>",
+            Visibility::Followers,
+        ),
+    ] {
+        db.create_note(&note).await.unwrap();
+    }
+    let search = SearchService::new(db.clone());
+
+    let contents = |notes: Vec<DbNote>| -> Vec<String> {
+        notes.into_iter().map(|note| note.content).collect()
+    };
+    assert_eq!(
+        contents(search.search_notes("sourdough", 10, 0).await.unwrap()),
+        vec!["<p>Baking sourdough bread</p>"]
+    );
+    assert_eq!(search.search_notes("bread", 10, 0).await.unwrap().len(), 2);
+    assert_eq!(search.search_notes("bread", 10, 1).await.unwrap().len(), 1);
+    assert!(search.search_notes("  ", 10, 0).await.unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn test_v1_search_returns_a_mastodon_search_result() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    db.create_note(&note(1, "<p>Alice in Wonderland</p>", Visibility::Public))
+        .await
+        .unwrap();
+    let client = Arc::new(RemoteClient::default());
+
+    let (status, results) = get(&db, client.clone(), "/api/v1/search?q=alice").await;
+    assert_eq!(status, 200);
+    assert_eq!(accts(&results["accounts"]), vec!["alice", "malice"]);
+    assert_eq!(results["statuses"].as_array().unwrap().len(), 1);
+    assert_eq!(results["statuses"][0]["id"], "1");
+    assert_eq!(
+        results["statuses"][0]["content"],
+        "<p>Alice in Wonderland</p>"
+    );
+    assert_eq!(results["hashtags"], json!([]));
+
+    let (_, results) = get(&db, client.clone(), "/api/v1/search?q=alice&type=statuses").await;
+    assert_eq!(results["accounts"], json!([]));
+    assert_eq!(results["statuses"].as_array().unwrap().len(), 1);
+
+    let (_, results) = get(
+        &db,
+        client.clone(),
+        "/api/v1/search?q=alice&type=accounts&limit=1&offset=1",
+    )
+    .await;
+    assert_eq!(accts(&results["accounts"]), vec!["malice"]);
+    assert_eq!(results["statuses"], json!([]));
+
+    let (status, _) = get(&db, client.clone(), "/api/v1/search?q=alice&type=emoji").await;
+    assert_eq!(status, 400);
+    let (status, _) = get(&db, client.clone(), "/api/v1/search?q=").await;
+    assert_eq!(status, 400);
+    // Local search never reaches out to other servers
+    assert!(client.requested.lock().unwrap().is_empty());
+}
@@ -0,0 +1,94 @@
+mod common;
+
+use feder8::database::{Database, DbActor, SqliteDatabase, SqliteDatabaseConfig};
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+
+fn actor(n: u32) -> DbActor {
+    common::actor(
+        &format!("https://example.com/users/user{n}"),
+        &format!("user{n}"),
+        &format!("User {n}"),
+    )
+}
+
+async fn create_test_database(dir: &TempDir, max_connections: u32) -> Arc<SqliteDatabase> {
+    let db = SqliteDatabase::with_config(SqliteDatabaseConfig {
+        database_url: common::sqlite_url(dir),
+        max_connections,
+        min_connections: 2,
+        connect_timeout: Duration::from_secs(5),
+        idle_timeout: None,
+    })
+    .await
+    .unwrap();
+    db.run_migrations().await.unwrap();
+    db.create_actor(&actor(0)).await.unwrap();
+    Arc::new(db)
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_reads_do_not_deadlock() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir, 10).await;
+
+    // Ten readers at once, alongside a writer
+    let readers: Vec<_> = (0..10)
+        .map(|_| {
+            let db = db.clone();
+            tokio::spawn(async move {
+                for _ in 0..20 {
+                    let found = db.get_actor_by_username("user0").await.unwrap();
+                    assert!(found.is_some());
+                }
+            })
+        })
+        .collect();
+    let writer = {
+        let db = db.clone();
+        tokio::spawn(async move {
+            for n in 1..=20 {
+                db.create_actor(&actor(n)).await.unwrap();
+            }
+        })
+    };
+
+    tokio::time::timeout(Duration::from_secs(10), async {
+        for reader in readers {
+            reader.await.unwrap();
+        }
+        writer.await.unwrap();
+    })
+    .await
+    .expect("readers and writer finished");
+
+    assert!(db.get_actor_by_username("user20").await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_file_databases_use_write_ahead_logging() {
+    let dir = TempDir::new().unwrap();
+    let _db = create_test_database(&dir, 4).await;
+
+    assert!(dir.path().join("test.db-wal").exists());
+}
+
+#[tokio::test]
+async fn test_in_memory_databases_keep_their_data() {
+    // Asking for a big pool still leaves one connection, so the data stays
+    let db = SqliteDatabase::with_config(SqliteDatabaseConfig {
+        database_url: "sqlite::memory:".to_string(),
+        max_connections: 10,
+        min_connections: 0,
+        connect_timeout: Duration::from_secs(5),
+        idle_timeout: Some(Duration::from_millis(1)),
+    })
+    .await
+    .unwrap();
+    db.run_migrations().await.unwrap();
+    db.create_actor(&actor(1)).await.unwrap();
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(db.get_actor_by_username("user1").await.unwrap().is_some());
+}
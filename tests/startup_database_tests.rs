@@ -0,0 +1,88 @@
+mod common;
+
+use actix_web::{test, web, App};
+use feder8::config::Config;
+use feder8::database::{open_database, DbActor};
+use feder8::{handlers, Container};
+use serde_json::Value;
+use tempfile::TempDir;
+
+fn config_for(dir: &TempDir) -> Config {
+    Config {
+        server_url: "https://example.com".to_string(),
+        database_url: format!(
+            "sqlite://{}?mode=rwc",
+            dir.path().join("feder8.db").display()
+        ),
+        mock_database: false,
+        ..Config::default()
+    }
+}
+
+fn alice() -> DbActor {
+    DbActor {
+        summary: Some("Stored for real".to_string()),
+        ..common::actor("https://example.com/users/alice", "alice", "Alice")
+    }
+}
+
+#[actix_web::test]
+async fn test_actor_survives_a_restart() {
+    let dir = TempDir::new().unwrap();
+    let config = config_for(&dir);
+
+    // First start: a fresh file, migrated on open
+    let db = open_database(&config).await.unwrap();
+    db.create_actor(&alice()).await.unwrap();
+    drop(db);
+
+    // Second start against the same file, serving the stored actor
+    let container = Container::new(config.clone(), open_database(&config).await.unwrap());
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(container.urls().clone()))
+            .app_data(web::Data::new(container.database().clone()))
+            .app_data(web::Data::from(container.http_client().clone()))
+            .app_data(web::Data::from(container.document_cache().clone()))
+            .service(handlers::actor::get_actor),
+    )
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/users/alice")
+        .insert_header(("Accept", "application/activity+json"))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.status().is_success());
+    let body: Value = test::read_body_json(resp).await;
+    assert_eq!(body["id"], "https://example.com/users/alice");
+    assert_eq!(body["name"], "Alice");
+}
+
+#[actix_web::test]
+async fn test_unreachable_database_fails_to_open() {
+    let dir = TempDir::new().unwrap();
+    let config = Config {
+        // Read-write without create, so a missing file can't be opened
+        database_url: format!(
+            "sqlite://{}?mode=rw",
+            dir.path().join("missing.db").display()
+        ),
+        mock_database: false,
+        ..Config::default()
+    };
+
+    assert!(open_database(&config).await.is_err());
+}
+
+#[actix_web::test]
+async fn test_mock_database_needs_no_file() {
+    let config = Config {
+        database_url: "sqlite:///nonexistent/dir/feder8.db?mode=rw".to_string(),
+        mock_database: true,
+        ..Config::default()
+    };
+
+    let db = open_database(&config).await.unwrap();
+    assert!(db.get_actor_by_username("alice").await.unwrap().is_some());
+}
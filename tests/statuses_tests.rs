@@ -0,0 +1,207 @@
+mod common;
+
+use actix_web::test;
+use chrono::{Duration, TimeZone, Utc};
+use common::{OfflineHttpClient, ALICE_TOKEN};
+use feder8::auth::hash_token;
+use feder8::database::{DatabaseRef, DbActor, DbNote, DbToken, PublishState};
+use feder8::handlers;
+use feder8::models::addressing::PUBLIC;
+use feder8::models::Visibility;
+use serde_json::Value;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ALICE: &str = "https://example.com/users/alice";
+const BOB: &str = "https://example.com/users/bob";
+const BOB_TOKEN: &str = "bob-token";
+
+fn actor(id: &str, username: &str) -> DbActor {
+    common::actor(id, username, username)
+}
+
+fn token(id: &str, plaintext: &str, actor_id: &str, scopes: &[&str]) -> DbToken {
+    DbToken {
+        id: id.to_string(),
+        token_hash: hash_token(plaintext),
+        actor_id: actor_id.to_string(),
+        scopes: scopes.iter().map(|s| s.to_string()).collect(),
+        created_at: Utc::now(),
+        expires_at: None,
+    }
+}
+
+async fn create_test_database(dir: &TempDir) -> DatabaseRef {
+    let db = common::seeded_database(dir).await;
+    db.create_actor(&actor(BOB, "bob")).await.unwrap();
+    db.create_token(&token("token-2", BOB_TOKEN, BOB, &["read"]))
+        .await
+        .unwrap();
+    db
+}
+
+/// Alice's note `n`, published `n` minutes after a fixed start
+fn note(n: i64, visibility: Visibility) -> DbNote {
+    let (to, cc) = match visibility {
+        Visibility::Public => (vec![PUBLIC.to_string()], vec![]),
+        Visibility::Unlisted => (vec![], vec![PUBLIC.to_string()]),
+        Visibility::Followers | Visibility::Local => (vec![format!("{ALICE}/followers")], vec![]),
+        Visibility::Direct => (vec![BOB.to_string()], vec![]),
+    };
+    let published = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap() + Duration::minutes(n);
+    DbNote {
+        id: format!("https://example.com/notes/{n}"),
+        attributed_to: ALICE.to_string(),
+        content: format!("note {n}"),
+        to_recipients: to,
+        cc_recipients: cc,
+        published,
+        in_reply_to: None,
+        tags: vec![],
+        attachments: vec![],
+        visibility,
+        state: PublishState::Published,
+        pinned: false,
+        created_at: published,
+        in_reply_to_actor: None,
+        thread_depth: Some(0),
+        thread_truncated: false,
+        sensitive: false,
+        summary: None,
+        language: None,
+        content_map: None,
+    }
+}
+
+async fn get_statuses(db: &DatabaseRef, uri: &str, token: Option<&str>) -> (u16, Value) {
+    let app = test::init_service(
+        common::test_app(db, common::test_config(), Arc::new(OfflineHttpClient))
+            .service(handlers::note::get_statuses),
+    )
+    .await;
+
+    let mut req = test::TestRequest::get().uri(uri);
+    if let Some(token) = token {
+        req = req.insert_header(("Authorization", format!("Bearer {token}")));
+    }
+    let resp = test::call_service(&app, req.to_request()).await;
+    let status = resp.status().as_u16();
+    let body = test::read_body(resp).await;
+    (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+}
+
+fn contents(collection: &Value) -> Vec<String> {
+    collection["orderedItems"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|note| note["content"].as_str().unwrap().to_string())
+        .collect()
+}
+
+async fn store_mixed_notes(db: &DatabaseRef) {
+    let mut sensitive = note(2, Visibility::Public);
+    sensitive.sensitive = true;
+    sensitive.summary = Some("spoilers".to_string());
+    let mut scheduled = note(6, Visibility::Public);
+    scheduled.state = PublishState::Scheduled;
+    for note in [
+        note(1, Visibility::Public),
+        sensitive,
+        note(3, Visibility::Unlisted),
+        note(4, Visibility::Followers),
+        note(5, Visibility::Direct),
+        scheduled,
+    ] {
+        db.create_note(&note).await.unwrap();
+    }
+}
+
+#[tokio::test]
+async fn test_unauthenticated_requests_see_only_public_statuses() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    store_mixed_notes(&db).await;
+
+    let (status, collection) = get_statuses(&db, "/users/alice/statuses", None).await;
+
+    assert_eq!(status, 200);
+    assert_eq!(collection["type"], "OrderedCollection");
+    assert_eq!(collection["id"], "https://example.com/users/alice/statuses");
+    assert_eq!(contents(&collection), vec!["note 3", "note 2", "note 1"]);
+    assert_eq!(collection["totalItems"], 3);
+
+    // Sensitive notes are listed with their flag and content warning
+    let sensitive = &collection["orderedItems"][1];
+    assert_eq!(sensitive["sensitive"], true);
+    assert_eq!(sensitive["summary"], "spoilers");
+    assert!(collection["orderedItems"][0].get("sensitive").is_none());
+}
+
+#[tokio::test]
+async fn test_the_actors_own_token_sees_every_status() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    store_mixed_notes(&db).await;
+
+    let (status, collection) = get_statuses(&db, "/users/alice/statuses", Some(ALICE_TOKEN)).await;
+
+    assert_eq!(status, 200);
+    assert_eq!(
+        contents(&collection),
+        vec!["note 5", "note 4", "note 3", "note 2", "note 1"]
+    );
+    assert_eq!(collection["totalItems"], 5);
+}
+
+#[tokio::test]
+async fn test_other_tokens_get_the_public_view_and_bad_ones_are_refused() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    store_mixed_notes(&db).await;
+
+    let (status, collection) = get_statuses(&db, "/users/alice/statuses", Some(BOB_TOKEN)).await;
+    assert_eq!(status, 200);
+    assert_eq!(contents(&collection), vec!["note 3", "note 2", "note 1"]);
+
+    let (status, _) = get_statuses(&db, "/users/alice/statuses", Some("not-a-token")).await;
+    assert_eq!(status, 401);
+
+    let (status, _) = get_statuses(&db, "/users/nobody/statuses", None).await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn test_statuses_page_with_max_id_and_min_id() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    for n in 1..=150 {
+        db.create_note(&note(n, Visibility::Public)).await.unwrap();
+    }
+    // The newest note is private, and skipped without shortening the page
+    db.create_note(&note(200, Visibility::Direct))
+        .await
+        .unwrap();
+
+    let (_, first) = get_statuses(&db, "/users/alice/statuses?limit=3", None).await;
+    assert_eq!(contents(&first), vec!["note 150", "note 149", "note 148"]);
+    // The total counts every visible status, not just this page
+    assert_eq!(first["totalItems"], 150);
+
+    let (_, older) = get_statuses(&db, "/users/alice/statuses?limit=3&max_id=148", None).await;
+    assert_eq!(contents(&older), vec!["note 147", "note 146", "note 145"]);
+
+    // Paging back past the first database page of notes
+    let (_, oldest) = get_statuses(&db, "/users/alice/statuses?max_id=3", None).await;
+    assert_eq!(contents(&oldest), vec!["note 2", "note 1"]);
+
+    // min_id gives the page just after it, newest first
+    let (_, newer) = get_statuses(&db, "/users/alice/statuses?limit=3&min_id=10", None).await;
+    assert_eq!(contents(&newer), vec!["note 13", "note 12", "note 11"]);
+
+    let (_, between) = get_statuses(&db, "/users/alice/statuses?max_id=14&min_id=10", None).await;
+    assert_eq!(contents(&between), vec!["note 13", "note 12", "note 11"]);
+
+    let (status, _) = get_statuses(&db, "/users/alice/statuses?max_id=999", None).await;
+    assert_eq!(status, 400);
+}
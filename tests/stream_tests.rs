@@ -0,0 +1,177 @@
+mod common;
+
+use actix_web::{web, HttpServer};
+use common::{OfflineHttpClient, ALICE_TOKEN};
+use feder8::database::{DatabaseRef, DbActor};
+use feder8::handlers;
+use feder8::models::addressing::PUBLIC;
+use feder8::services::inbox_stream::InboxStream;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tempfile::TempDir;
+
+const CAROL: &str = "https://example.com/users/carol";
+const BOB: &str = "https://remote.example/users/bob";
+
+fn actor(id: &str, username: &str) -> DbActor {
+    common::actor(id, username, username)
+}
+
+/// Alice, who has a token, and Carol
+async fn create_test_database(dir: &TempDir) -> DatabaseRef {
+    let db = common::seeded_database(dir).await;
+    db.create_actor(&actor(CAROL, "carol")).await.unwrap();
+    db
+}
+
+/// A server on a free local port with the inbox and the stream
+fn start_server(db: DatabaseRef, stream: Arc<InboxStream>) -> SocketAddr {
+    let server = HttpServer::new(move || {
+        common::test_app(&db, common::test_config(), Arc::new(OfflineHttpClient))
+            .app_data(web::Data::from(stream.clone()))
+            .service(handlers::inbox::inbox)
+            .service(handlers::api::stream::stream)
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let addr = server.addrs()[0];
+    tokio::spawn(server.run());
+    addr
+}
+
+fn create_note(n: u32) -> Value {
+    json!({
+        "id": format!("https://remote.example/activities/{n}"),
+        "type": "Create",
+        "actor": BOB,
+        "to": [PUBLIC],
+        "object": {
+            "id": format!("https://remote.example/notes/{n}"),
+            "type": "Note",
+            "attributedTo": BOB,
+            "content": format!("Note {n}"),
+            "to": [PUBLIC]
+        }
+    })
+}
+
+async fn post_inbox(client: &reqwest::Client, addr: SocketAddr, username: &str, activity: Value) {
+    let resp = client
+        .post(format!("http://{addr}/users/{username}/inbox"))
+        .json(&activity)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 202);
+}
+
+/// Read the stream until `count` events have arrived, returning their data
+async fn read_events(resp: &mut reqwest::Response, count: usize) -> Vec<Value> {
+    let mut buffer = String::new();
+    let mut events = Vec::new();
+    while events.len() < count {
+        let chunk = resp.chunk().await.unwrap().expect("stream still open");
+        buffer.push_str(std::str::from_utf8(&chunk).unwrap());
+        while let Some(end) = buffer.find("\n\n") {
+            let frame: String = buffer.drain(..end + 2).collect();
+            if let Some(data) = frame
+                .strip_prefix("event: activity\n")
+                .and_then(|rest| rest.strip_prefix("data: "))
+            {
+                events.push(serde_json::from_str(data.trim_end()).unwrap());
+            }
+        }
+    }
+    events
+}
+
+#[tokio::test]
+async fn test_stream_pushes_the_callers_inbox_activities() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let stream = Arc::new(InboxStream::new(16));
+    let addr = start_server(db, stream.clone());
+    let client = reqwest::Client::new();
+
+    let mut resp = client
+        .get(format!("http://{addr}/api/stream"))
+        .bearer_auth(ALICE_TOKEN)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    assert_eq!(
+        resp.headers()["content-type"].to_str().unwrap(),
+        "text/event-stream"
+    );
+    assert_eq!(stream.subscribers(), 1);
+
+    // Carol's inbox activity isn't Alice's business
+    post_inbox(&client, addr, "carol", create_note(1)).await;
+    post_inbox(&client, addr, "alice", create_note(2)).await;
+    post_inbox(&client, addr, "alice", create_note(3)).await;
+
+    let events = tokio::time::timeout(Duration::from_secs(10), read_events(&mut resp, 2))
+        .await
+        .expect("two events arrive");
+    assert_eq!(events[0]["id"], "https://remote.example/activities/2");
+    assert_eq!(events[1]["id"], "https://remote.example/activities/3");
+    assert_eq!(events[1]["object"]["content"], "Note 3");
+
+    // A client going away takes its subscription with it, once the server
+    // next writes to it
+    drop(resp);
+    tokio::time::timeout(Duration::from_secs(10), async {
+        let mut n = 4;
+        while stream.subscribers() > 0 {
+            post_inbox(&client, addr, "alice", create_note(n)).await;
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            n += 1;
+        }
+    })
+    .await
+    .expect("the server notices the disconnect");
+}
+
+#[tokio::test]
+async fn test_stream_requires_a_token() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let stream = Arc::new(InboxStream::new(16));
+    let addr = start_server(db, stream.clone());
+
+    let resp = reqwest::get(format!("http://{addr}/api/stream"))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 401);
+    assert_eq!(stream.subscribers(), 0);
+}
+
+#[tokio::test]
+async fn test_closing_the_stream_ends_open_clients() {
+    let dir = TempDir::new().unwrap();
+    let db = create_test_database(&dir).await;
+    let stream = Arc::new(InboxStream::new(16));
+    let addr = start_server(db, stream.clone());
+
+    let mut resp = reqwest::Client::new()
+        .get(format!("http://{addr}/api/stream"))
+        .bearer_auth(ALICE_TOKEN)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), 200);
+    let connected = resp.chunk().await.unwrap().unwrap();
+    assert_eq!(&connected[..], b": connected\n\n");
+
+    // As on shutdown: the response ends rather than waiting for a heartbeat
+    stream.close();
+    let end = tokio::time::timeout(Duration::from_secs(5), resp.chunk())
+        .await
+        .expect("the stream ends");
+    assert!(end.unwrap().is_none());
+    assert_eq!(stream.subscribers(), 0);
+}
@@ -0,0 +1,322 @@
+mod common;
+
+use actix_web::{test, App};
+use chrono::Utc;
+use feder8::config::Config;
+use feder8::database::{DatabaseRef, DbNote, PublishState};
+use feder8::handlers;
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode};
+use feder8::models::addressing::PUBLIC;
+use feder8::models::Visibility;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tempfile::TempDir;
+
+const ALICE: &str = "https://example.com/users/alice";
+const BOB: &str = "https://remote.example/users/bob";
+
+/// Serves canned remote documents and records what was asked for
+#[derive(Default)]
+struct RemoteThreadClient {
+    documents: HashMap<String, Value>,
+    requests: Mutex<Vec<(String, Option<String>)>>,
+}
+
+impl RemoteThreadClient {
+    /// A chain of Bob's notes 1..=n, each replying to the one before
+    fn chain(n: u32) -> Self {
+        let mut client = Self::default();
+        for i in 1..=n {
+            let mut note = remote_note(i);
+            if i > 1 {
+                note["inReplyTo"] = json!(remote_id(i - 1));
+            }
+            client.documents.insert(remote_id(i), note);
+        }
+        client
+    }
+
+    fn requested(&self) -> Vec<String> {
+        let requests = self.requests.lock().unwrap();
+        requests.iter().map(|(url, _)| url.clone()).collect()
+    }
+}
+
+#[async_trait::async_trait]
+impl HttpClient for RemoteThreadClient {
+    async fn send(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        self.requests
+            .lock()
+            .unwrap()
+            .push((request.url.clone(), request.headers.get("Accept").cloned()));
+        let document = self
+            .documents
+            .get(&request.url)
+            .ok_or_else(|| anyhow::anyhow!("unexpected request to {}", request.url))?;
+        Ok(HttpResponse {
+            status: StatusCode(200),
+            headers: HashMap::new(),
+            body: serde_json::to_vec(document)?,
+        })
+    }
+}
+
+fn remote_id(i: u32) -> String {
+    format!("https://remote.example/notes/{i}")
+}
+
+fn remote_note(i: u32) -> Value {
+    json!({
+        "id": remote_id(i),
+        "type": "Note",
+        "attributedTo": BOB,
+        "content": format!("note {i}"),
+        "published": format!("2024-01-01T00:00:0{i}Z"),
+        "to": [PUBLIC]
+    })
+}
+
+fn create_test_app(
+    db: &DatabaseRef,
+    http_client: Arc<RemoteThreadClient>,
+    reply_fetch_depth: u32,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    let config = Config {
+        reply_fetch_depth,
+        ..common::test_config()
+    };
+    common::test_app(db, config, http_client)
+        .service(handlers::inbox::inbox)
+        .service(handlers::api::statuses::get_status_context)
+}
+
+/// Deliver Bob's note `note` to Alice's inbox
+fn receive(note: Value) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/users/alice/inbox")
+        .set_json(json!({
+            "id": format!("{}/activity", note["id"].as_str().unwrap()),
+            "type": "Create",
+            "actor": BOB,
+            "to": [PUBLIC],
+            "object": note
+        }))
+}
+
+fn local_note(id: &str, in_reply_to: Option<&str>, visibility: Visibility) -> DbNote {
+    DbNote {
+        id: format!("https://example.com/notes/{id}"),
+        attributed_to: ALICE.to_string(),
+        content: id.to_string(),
+        to_recipients: vec![PUBLIC.to_string()],
+        cc_recipients: vec![],
+        published: Utc::now(),
+        in_reply_to: in_reply_to.map(|parent| format!("https://example.com/notes/{parent}")),
+        tags: vec![],
+        attachments: vec![],
+        visibility,
+        state: PublishState::Published,
+        pinned: false,
+        created_at: Utc::now(),
+        in_reply_to_actor: None,
+        thread_depth: None,
+        thread_truncated: false,
+        sensitive: false,
+        summary: None,
+        language: None,
+        content_map: None,
+    }
+}
+
+#[tokio::test]
+async fn test_reply_fetches_missing_ancestors_root_first() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let client = Arc::new(RemoteThreadClient::chain(3));
+    let app = test::init_service(create_test_app(&db, client.clone(), 5)).await;
+
+    let mut reply = remote_note(4);
+    reply["inReplyTo"] = json!(remote_id(3));
+    let resp = test::call_service(&app, receive(reply).to_request()).await;
+    assert_eq!(resp.status(), 202);
+
+    assert_eq!(
+        client.requested(),
+        vec![remote_id(3), remote_id(2), remote_id(1)]
+    );
+    assert!(client
+        .requests
+        .lock()
+        .unwrap()
+        .iter()
+        .all(|(_, accept)| accept.as_deref() == Some("application/activity+json")));
+
+    for i in 1..=4 {
+        let note = db.get_note_by_id(&remote_id(i)).await.unwrap().unwrap();
+        assert_eq!(note.thread_depth, Some(i - 1), "note {i}");
+        assert_eq!(note.attributed_to, BOB);
+    }
+}
+
+#[tokio::test]
+async fn test_fetching_stops_at_the_configured_depth() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let client = Arc::new(RemoteThreadClient::chain(5));
+    let app = test::init_service(create_test_app(&db, client.clone(), 2)).await;
+
+    let mut reply = remote_note(6);
+    reply["inReplyTo"] = json!(remote_id(5));
+    let resp = test::call_service(&app, receive(reply).to_request()).await;
+    assert_eq!(resp.status(), 202);
+
+    assert_eq!(client.requested(), vec![remote_id(5), remote_id(4)]);
+    assert!(db.get_note_by_id(&remote_id(3)).await.unwrap().is_none());
+    // The top of what we have replies to a note we never saw
+    let top = db.get_note_by_id(&remote_id(4)).await.unwrap().unwrap();
+    assert_eq!(top.thread_depth, None);
+}
+
+#[tokio::test]
+async fn test_stored_parents_and_disabled_fetching_make_no_requests() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let client = Arc::new(RemoteThreadClient::chain(2));
+
+    let app = test::init_service(create_test_app(&db, client.clone(), 0)).await;
+    let mut reply = remote_note(3);
+    reply["inReplyTo"] = json!(remote_id(2));
+    test::call_service(&app, receive(reply).to_request()).await;
+    assert!(client.requested().is_empty());
+
+    // With the parent already stored there is nothing to fetch
+    let app = test::init_service(create_test_app(&db, client.clone(), 5)).await;
+    let mut reply = remote_note(4);
+    reply["inReplyTo"] = json!(remote_id(3));
+    test::call_service(&app, receive(reply).to_request()).await;
+    assert!(client.requested().is_empty());
+}
+
+#[tokio::test]
+async fn test_documents_served_under_another_id_are_refused() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let mut client = RemoteThreadClient::default();
+    let mut forged = remote_note(1);
+    forged["id"] = json!("https://remote.example/notes/other");
+    client.documents.insert(remote_id(1), forged);
+    let app = test::init_service(create_test_app(&db, Arc::new(client), 5)).await;
+
+    let mut reply = remote_note(2);
+    reply["inReplyTo"] = json!(remote_id(1));
+    let resp = test::call_service(&app, receive(reply).to_request()).await;
+    assert_eq!(resp.status(), 202);
+
+    assert!(db.get_note_by_id(&remote_id(1)).await.unwrap().is_none());
+    assert!(db
+        .get_note_by_id("https://remote.example/notes/other")
+        .await
+        .unwrap()
+        .is_none());
+    assert!(db.get_note_by_id(&remote_id(2)).await.unwrap().is_some());
+}
+
+#[tokio::test]
+async fn test_get_thread_walks_both_directions() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    for note in [
+        local_note("root", None, Visibility::Public),
+        local_note("a", Some("root"), Visibility::Public),
+        local_note("b", Some("a"), Visibility::Public),
+        local_note("c", Some("b"), Visibility::Public),
+        local_note("unrelated", None, Visibility::Public),
+    ] {
+        db.create_note(&note).await.unwrap();
+    }
+
+    let ids = |thread: Vec<DbNote>| -> Vec<String> {
+        let mut ids: Vec<_> = thread
+            .into_iter()
+            .map(|note| note.id.rsplit('/').next().unwrap().to_string())
+            .collect();
+        ids.sort();
+        ids
+    };
+
+    let thread = db
+        .get_thread("https://example.com/notes/a", 10)
+        .await
+        .unwrap();
+    assert_eq!(ids(thread), vec!["a", "b", "c", "root"]);
+
+    // One hop each way
+    let thread = db
+        .get_thread("https://example.com/notes/a", 1)
+        .await
+        .unwrap();
+    assert_eq!(ids(thread), vec!["a", "b", "root"]);
+
+    let thread = db
+        .get_thread("https://example.com/notes/missing", 10)
+        .await
+        .unwrap();
+    assert!(thread.is_empty());
+}
+
+#[tokio::test]
+async fn test_context_lists_visible_ancestors_and_descendants() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    for note in [
+        local_note("root", None, Visibility::Public),
+        local_note("a", Some("root"), Visibility::Public),
+        local_note("b", Some("a"), Visibility::Public),
+        local_note("secret", Some("a"), Visibility::Direct),
+        local_note("c", Some("b"), Visibility::Unlisted),
+    ] {
+        db.create_note(&note).await.unwrap();
+    }
+    let app = test::init_service(create_test_app(
+        &db,
+        Arc::new(RemoteThreadClient::default()),
+        5,
+    ))
+    .await;
+
+    let req = test::TestRequest::get()
+        .uri("/users/alice/statuses/a/context")
+        .to_request();
+    let context: Value = test::call_and_read_body_json(&app, req).await;
+    let uris = |key: &str| -> Vec<String> {
+        context[key]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|status| status["uri"].as_str().unwrap().to_string())
+            .collect()
+    };
+    assert_eq!(uris("ancestors"), vec!["https://example.com/notes/root"]);
+    assert_eq!(
+        uris("descendants"),
+        vec!["https://example.com/notes/b", "https://example.com/notes/c"]
+    );
+
+    for uri in [
+        "/users/alice/statuses/secret/context",
+        "/users/alice/statuses/missing/context",
+        "/users/nobody/statuses/a/context",
+    ] {
+        let req = test::TestRequest::get().uri(uri).to_request();
+        assert_eq!(test::call_service(&app, req).await.status(), 404, "{uri}");
+    }
+}
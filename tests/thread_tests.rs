@@ -0,0 +1,198 @@
+mod common;
+
+use actix_web::{test, App};
+use common::ALICE_TOKEN;
+use feder8::config::Config;
+use feder8::database::{DatabaseRef, DbNote};
+use feder8::handlers;
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode};
+use feder8::models::addressing::PUBLIC;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ALICE: &str = "https://example.com/users/alice";
+const BOB: &str = "https://remote.example/users/bob";
+
+// Serves Bob's notes, which is all the outbox needs to address a reply
+struct RemoteNoteClient;
+
+#[async_trait::async_trait]
+impl HttpClient for RemoteNoteClient {
+    async fn send(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        anyhow::ensure!(
+            request.url.starts_with("https://remote.example/notes/"),
+            "unexpected request to {}",
+            request.url
+        );
+        Ok(HttpResponse {
+            status: StatusCode(200),
+            headers: HashMap::new(),
+            body: serde_json::to_vec(&json!({
+                "id": request.url,
+                "type": "Note",
+                "attributedTo": BOB
+            }))?,
+        })
+    }
+}
+
+fn create_test_app(
+    db: &DatabaseRef,
+    max_thread_depth: u32,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    let config = Config {
+        max_thread_depth,
+        ..common::test_config()
+    };
+    common::test_app(db, config, Arc::new(RemoteNoteClient))
+        .service(handlers::outbox::post_outbox)
+        .service(handlers::inbox::inbox)
+        .service(handlers::api::statuses::get_status)
+}
+
+/// Post a note as Alice, optionally replying, and return its id
+async fn post_note(db: &DatabaseRef, max_thread_depth: u32, in_reply_to: Option<&str>) -> String {
+    let app = test::init_service(create_test_app(db, max_thread_depth)).await;
+    let mut note = json!({"type": "Note", "content": "hello", "to": [PUBLIC]});
+    if let Some(parent) = in_reply_to {
+        note["inReplyTo"] = json!(parent);
+    }
+    let req = test::TestRequest::post()
+        .uri("/users/alice/outbox")
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+        .set_json(json!({"type": "Create", "actor": ALICE, "object": note, "to": [PUBLIC]}))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.status(), 201);
+    let activity: Value = test::read_body_json(resp).await;
+    activity["object"]["id"].as_str().unwrap().to_string()
+}
+
+/// Deliver Bob's reply to `in_reply_to` to Alice's inbox
+async fn receive_reply(db: &DatabaseRef, note_id: &str, in_reply_to: &str) {
+    let app = test::init_service(create_test_app(db, 100)).await;
+    let req = test::TestRequest::post()
+        .uri("/users/alice/inbox")
+        .set_json(json!({
+            "id": format!("{note_id}/activity"),
+            "type": "Create",
+            "actor": BOB,
+            "to": [PUBLIC],
+            "object": {
+                "id": note_id,
+                "type": "Note",
+                "attributedTo": BOB,
+                "content": "reply",
+                "inReplyTo": in_reply_to,
+                "to": [PUBLIC]
+            }
+        }))
+        .to_request();
+    assert_eq!(test::call_service(&app, req).await.status(), 202);
+}
+
+async fn stored(db: &DatabaseRef, id: &str) -> DbNote {
+    db.get_note_by_id(id).await.unwrap().unwrap()
+}
+
+#[tokio::test]
+async fn test_five_deep_thread_across_outbox_and_inbox() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+
+    let mut ids = vec![post_note(&db, 100, None).await];
+    for _ in 0..3 {
+        let parent = ids.last().unwrap().clone();
+        ids.push(post_note(&db, 100, Some(&parent)).await);
+    }
+    let bob_reply = "https://remote.example/notes/5";
+    receive_reply(&db, bob_reply, ids.last().unwrap()).await;
+    ids.push(bob_reply.to_string());
+
+    for (depth, id) in ids.iter().enumerate() {
+        let note = stored(&db, id).await;
+        assert_eq!(note.thread_depth, Some(depth as u32), "{id}");
+        assert!(!note.thread_truncated);
+        let expected_actor = (depth > 0).then_some(ALICE);
+        assert_eq!(note.in_reply_to_actor.as_deref(), expected_actor);
+    }
+
+    // Alice answering Bob records Bob as the parent's author
+    let answer = post_note(&db, 100, Some(bob_reply)).await;
+    let note = stored(&db, &answer).await;
+    assert_eq!(note.thread_depth, Some(5));
+    assert_eq!(note.in_reply_to_actor.as_deref(), Some(BOB));
+
+    let app = test::init_service(create_test_app(&db, 100)).await;
+    let status_id = answer.rsplit('/').next().unwrap();
+    let req = test::TestRequest::get()
+        .uri(&format!("/api/v1/statuses/{status_id}"))
+        .to_request();
+    let status: Value = test::call_and_read_body_json(&app, req).await;
+    assert_eq!(status["uri"], answer);
+    assert_eq!(status["in_reply_to_id"], bob_reply);
+    assert_eq!(status["in_reply_to_account_id"], BOB);
+    assert_eq!(status["feder8"]["thread_depth"], 5);
+    assert_eq!(status["feder8"]["thread_truncated"], false);
+}
+
+#[tokio::test]
+async fn test_depth_is_capped_and_marked_truncated() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+
+    let mut ids = vec![post_note(&db, 2, None).await];
+    for _ in 0..3 {
+        let parent = ids.last().unwrap().clone();
+        ids.push(post_note(&db, 2, Some(&parent)).await);
+    }
+
+    let placements: Vec<(Option<u32>, bool)> = {
+        let mut placements = vec![];
+        for id in &ids {
+            let note = stored(&db, id).await;
+            placements.push((note.thread_depth, note.thread_truncated));
+        }
+        placements
+    };
+    assert_eq!(
+        placements,
+        vec![
+            (Some(0), false),
+            (Some(1), false),
+            (Some(2), false),
+            (Some(2), true)
+        ]
+    );
+}
+
+#[tokio::test]
+async fn test_reply_to_missing_parent_has_unknown_depth() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+
+    let orphan = "https://remote.example/notes/orphan";
+    receive_reply(&db, orphan, "https://elsewhere.example/notes/never-seen").await;
+
+    let note = stored(&db, orphan).await;
+    assert_eq!(note.thread_depth, None);
+    assert_eq!(note.in_reply_to_actor, None);
+    assert!(!note.thread_truncated);
+
+    // Nor can anything below it be placed
+    let below = "https://remote.example/notes/below";
+    receive_reply(&db, below, orphan).await;
+    let note = stored(&db, below).await;
+    assert_eq!(note.thread_depth, None);
+    assert_eq!(note.in_reply_to_actor.as_deref(), Some(BOB));
+}
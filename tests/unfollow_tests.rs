@@ -0,0 +1,145 @@
+mod common;
+
+use actix_web::{http::StatusCode, test};
+use chrono::Utc;
+use common::ALICE_TOKEN;
+use feder8::database::{DatabaseRef, DbFollowRelation, DeliveryPriority};
+use feder8::handlers;
+use feder8::http::client::{HttpClient, HttpRequest, HttpResponse, StatusCode as ClientStatus};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ALICE: &str = "https://example.com/users/alice";
+const BOB: &str = "https://remote.example/users/bob";
+const BOB_INBOX: &str = "https://remote.example/users/bob/inbox";
+
+/// Serves Bob's actor document so his inbox can be found
+struct RemoteActors;
+
+#[async_trait::async_trait]
+impl HttpClient for RemoteActors {
+    async fn send(&self, request: HttpRequest) -> anyhow::Result<HttpResponse> {
+        let (status, body) = if request.url == BOB {
+            let actor = json!({
+                "id": BOB,
+                "type": "Person",
+                "preferredUsername": "bob",
+                "inbox": BOB_INBOX
+            });
+            (200, serde_json::to_vec(&actor)?)
+        } else {
+            (404, Vec::new())
+        };
+        Ok(HttpResponse {
+            status: ClientStatus(status),
+            headers: HashMap::new(),
+            body,
+        })
+    }
+}
+
+macro_rules! outbox_app {
+    ($db:expr) => {{
+        test::init_service(
+            common::test_app(&$db, common::test_config(), Arc::new(RemoteActors))
+                .service(handlers::outbox::post_outbox),
+        )
+        .await
+    }};
+}
+
+fn post_outbox(activity: Value) -> test::TestRequest {
+    test::TestRequest::post()
+        .uri("/users/alice/outbox")
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+        .set_json(activity)
+}
+
+fn undo_follow(target: &str) -> Value {
+    json!({
+        "type": "Undo",
+        "actor": ALICE,
+        "object": {"type": "Follow", "actor": ALICE, "object": target}
+    })
+}
+
+async fn queued_deliveries(db: &DatabaseRef) -> Vec<(String, Value)> {
+    db.get_due_deliveries(Utc::now(), DeliveryPriority::Interactive, 100)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|d| (d.inbox_url, d.activity))
+        .collect()
+}
+
+#[actix_web::test]
+async fn test_unfollow_cancels_a_pending_follow() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = outbox_app!(db);
+
+    let follow = json!({"type": "Follow", "actor": ALICE, "object": BOB});
+    let resp = test::call_service(&app, post_outbox(follow).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let pending = db.get_follow_by_actors(ALICE, BOB).await.unwrap().unwrap();
+    assert_eq!(pending.status, "pending");
+
+    let resp = test::call_service(&app, post_outbox(undo_follow(BOB)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let undo: Value = test::read_body_json(resp).await;
+    assert_eq!(undo["type"], "Undo");
+    assert_eq!(undo["object"]["id"], pending.id);
+    assert_eq!(undo["object"]["object"], BOB);
+
+    assert!(db.get_follow_by_actors(ALICE, BOB).await.unwrap().is_none());
+    assert!(db
+        .get_activity_by_id(undo["id"].as_str().unwrap())
+        .await
+        .unwrap()
+        .is_some());
+
+    let deliveries = queued_deliveries(&db).await;
+    let types: Vec<_> = deliveries
+        .iter()
+        .map(|(inbox, activity)| (inbox.as_str(), activity["type"].as_str().unwrap()))
+        .collect();
+    assert_eq!(types, vec![(BOB_INBOX, "Follow"), (BOB_INBOX, "Undo")]);
+}
+
+#[actix_web::test]
+async fn test_unfollow_ends_an_accepted_follow() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    db.create_follow(&DbFollowRelation {
+        id: format!("{ALICE}/follows/1"),
+        follower_id: ALICE.to_string(),
+        following_id: BOB.to_string(),
+        status: "accepted".to_string(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    })
+    .await
+    .unwrap();
+    assert_eq!(db.get_actor_following_count(ALICE).await.unwrap(), 1);
+    let app = outbox_app!(db);
+
+    let resp = test::call_service(&app, post_outbox(undo_follow(BOB)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::CREATED);
+    let undo: Value = test::read_body_json(resp).await;
+    assert_eq!(undo["object"]["id"], format!("{ALICE}/follows/1"));
+
+    assert_eq!(db.get_actor_following_count(ALICE).await.unwrap(), 0);
+    assert_eq!(db.get_actor_followers_count(BOB).await.unwrap(), 0);
+    assert!(db.get_following(ALICE, 20, 0).await.unwrap().is_empty());
+
+    let deliveries = queued_deliveries(&db).await;
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].0, BOB_INBOX);
+    assert_eq!(deliveries[0].1["id"], undo["id"]);
+
+    // There is nothing left to undo
+    let resp = test::call_service(&app, post_outbox(undo_follow(BOB)).to_request()).await;
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,250 @@
+mod common;
+
+use actix_web::{test, App};
+use chrono::Utc;
+use common::{OfflineHttpClient, ALICE_TOKEN};
+use feder8::database::DatabaseRef;
+use feder8::handlers;
+use feder8::models::addressing::PUBLIC;
+use feder8::models::Visibility;
+use feder8::services::threads::{self, ThreadPosition};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use tempfile::TempDir;
+
+const ACTOR_ID: &str = "https://example.com/users/alice";
+const FOLLOWERS: &str = "https://example.com/users/alice/followers";
+const BOB: &str = "https://remote.example/users/bob";
+
+fn create_test_app(
+    db: &DatabaseRef,
+) -> App<
+    impl actix_web::dev::ServiceFactory<
+        actix_web::dev::ServiceRequest,
+        Config = (),
+        Response = actix_web::dev::ServiceResponse,
+        Error = actix_web::Error,
+        InitError = (),
+    >,
+> {
+    common::test_app(db, common::test_config(), Arc::new(OfflineHttpClient))
+        .service(handlers::outbox::get_outbox)
+        .service(handlers::outbox::post_outbox)
+}
+
+/// Post one note for each visibility class, returning the created activities
+async fn post_notes(db: &DatabaseRef) -> Vec<Value> {
+    let app = test::init_service(create_test_app(db)).await;
+    let mut created = Vec::new();
+
+    for (content, to, cc) in [
+        ("public", json!([PUBLIC]), json!([FOLLOWERS])),
+        ("unlisted", json!([FOLLOWERS]), json!([PUBLIC])),
+        ("followers", json!([FOLLOWERS]), json!([])),
+        ("direct", json!([BOB]), json!([])),
+    ] {
+        let req = test::TestRequest::post()
+            .uri("/users/alice/outbox")
+            .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+            .set_json(json!({
+                "type": "Create",
+                "actor": ACTOR_ID,
+                "object": {"type": "Note", "content": content, "to": to, "cc": cc},
+                "to": to,
+                "cc": cc
+            }))
+            .to_request();
+
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 201);
+        created.push(test::read_body_json(resp).await);
+    }
+
+    created
+}
+
+async fn get_outbox(db: &DatabaseRef, token: Option<&str>) -> Value {
+    let app = test::init_service(create_test_app(db)).await;
+
+    let mut req = test::TestRequest::get().uri("/users/alice/outbox");
+    if let Some(token) = token {
+        req = req.insert_header(("Authorization", format!("Bearer {token}")));
+    }
+
+    let resp = test::call_service(&app, req.to_request()).await;
+    assert_eq!(resp.status(), 200);
+    test::read_body_json(resp).await
+}
+
+fn contents(outbox: &Value) -> Vec<String> {
+    let mut contents: Vec<String> = outbox["first"]["orderedItems"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| item["object"]["content"].as_str().unwrap().to_string())
+        .collect();
+    contents.sort();
+    contents
+}
+
+#[tokio::test]
+async fn test_visibility_is_stored_for_each_class() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let created = post_notes(&db).await;
+
+    let expected = [
+        Visibility::Public,
+        Visibility::Unlisted,
+        Visibility::Followers,
+        Visibility::Direct,
+    ];
+    for (activity, visibility) in created.iter().zip(expected) {
+        let id = activity["id"].as_str().unwrap();
+        let stored = db.get_activity_by_id(id).await.unwrap().unwrap();
+        assert_eq!(stored.visibility, visibility);
+
+        let note_id = activity["object"]["id"].as_str().unwrap();
+        let note = db.get_note_by_id(note_id).await.unwrap().unwrap();
+        assert_eq!(note.visibility, visibility);
+    }
+}
+
+#[tokio::test]
+async fn test_anonymous_outbox_hides_followers_and_direct() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    post_notes(&db).await;
+
+    let outbox = get_outbox(&db, None).await;
+
+    assert_eq!(outbox["totalItems"], 2);
+    assert_eq!(contents(&outbox), vec!["public", "unlisted"]);
+}
+
+#[tokio::test]
+async fn test_owner_outbox_includes_all_visibilities() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    post_notes(&db).await;
+
+    let outbox = get_outbox(&db, Some(ALICE_TOKEN)).await;
+
+    assert_eq!(outbox["totalItems"], 4);
+    assert_eq!(
+        contents(&outbox),
+        vec!["direct", "followers", "public", "unlisted"]
+    );
+}
+
+#[tokio::test]
+async fn test_owner_outbox_is_kept_out_of_shared_caches() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    post_notes(&db).await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    let req = test::TestRequest::get()
+        .uri("/users/alice/outbox")
+        .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert_eq!(resp.headers().get("cache-control").unwrap(), "private");
+    assert_eq!(resp.headers().get("vary").unwrap(), "Accept, Authorization");
+
+    // The public view may be cached, but only for requests without a token
+    let req = test::TestRequest::get()
+        .uri("/users/alice/outbox")
+        .to_request();
+    let resp = test::call_service(&app, req).await;
+    assert!(resp.headers().get("cache-control").is_none());
+    assert_eq!(resp.headers().get("vary").unwrap(), "Accept, Authorization");
+}
+
+#[tokio::test]
+async fn test_invalid_token_is_treated_as_anonymous() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    post_notes(&db).await;
+
+    let outbox = get_outbox(&db, Some("not-a-token")).await;
+
+    assert_eq!(outbox["totalItems"], 2);
+}
+
+/// Every way servers spell the Public address
+const PUBLIC_SPELLINGS: [&str; 3] = [PUBLIC, "as:Public", "Public"];
+
+#[tokio::test]
+async fn test_outbox_treats_every_public_spelling_as_public() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+    let app = test::init_service(create_test_app(&db)).await;
+
+    for (n, public) in PUBLIC_SPELLINGS.into_iter().enumerate() {
+        let req = test::TestRequest::post()
+            .uri("/users/alice/outbox")
+            .insert_header(("Authorization", format!("Bearer {ALICE_TOKEN}")))
+            .set_json(json!({
+                "type": "Create",
+                "actor": ACTOR_ID,
+                "object": {"type": "Note", "content": format!("public {n}")},
+                "to": [public],
+                "cc": [FOLLOWERS]
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), 201, "{public}");
+        let created: Value = test::read_body_json(resp).await;
+
+        let note_id = created["object"]["id"].as_str().unwrap();
+        let note = db.get_note_by_id(note_id).await.unwrap().unwrap();
+        assert_eq!(note.visibility, Visibility::Public, "{public}");
+        // Stored and sent in the canonical spelling
+        assert_eq!(note.to_recipients, vec![PUBLIC], "{public}");
+        assert_eq!(created["to"], json!([PUBLIC]), "{public}");
+    }
+
+    let outbox = get_outbox(&db, None).await;
+    assert_eq!(outbox["totalItems"], 3);
+}
+
+#[tokio::test]
+async fn test_remote_notes_with_any_public_spelling_reach_the_timeline() {
+    let dir = TempDir::new().unwrap();
+    let db = common::seeded_database(&dir).await;
+
+    for (n, public) in PUBLIC_SPELLINGS.into_iter().enumerate() {
+        // A bare string as well as an array, as some servers send
+        let to = if n == 0 {
+            json!([public])
+        } else {
+            json!(public)
+        };
+        let object = json!({
+            "id": format!("{BOB}/notes/{n}"),
+            "type": "Note",
+            "attributedTo": BOB,
+            "content": format!("remote {n}"),
+            "to": to,
+            "cc": [public, format!("{BOB}/followers")]
+        });
+        let note = threads::note_from_object(&object, Utc::now(), ThreadPosition::default());
+        assert_eq!(note.visibility, Visibility::Public, "{public}");
+        assert_eq!(note.to_recipients, vec![PUBLIC], "{public}");
+        assert_eq!(
+            note.cc_recipients,
+            vec![PUBLIC.to_string(), format!("{BOB}/followers")],
+            "{public}"
+        );
+        db.create_note(&note).await.unwrap();
+    }
+
+    let timeline = db
+        .get_public_notes(10, None, false, false, false)
+        .await
+        .unwrap();
+    let mut contents: Vec<String> = timeline.into_iter().map(|note| note.content).collect();
+    contents.sort();
+    assert_eq!(contents, vec!["remote 0", "remote 1", "remote 2"]);
+}